@@ -30,6 +30,7 @@ pub enum ADS1299RegisterError {
     InvalidLeadOffCurrent(u8),
     InvalidLeadOffFrequency(u8),
     AdsNotDetected,
+    InvalidFrameHeader(u8),
 }
 impl core::fmt::Display for ADS1299RegisterError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -55,6 +56,9 @@ impl core::fmt::Display for ADS1299RegisterError {
             ADS1299RegisterError::AdsNotDetected => {
                 write!(f, "Ads not detected!")
             }
+            ADS1299RegisterError::InvalidFrameHeader(value) => {
+                write!(f, "Invalid data frame header: {:#x}", value)
+            }
         }
     }
 }
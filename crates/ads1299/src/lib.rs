@@ -6,6 +6,7 @@ use embedded_hal_async::digital::Wait;
 use embedded_hal_async::spi::SpiDevice;
 use heapless::Vec;
 
+use crate::errors::ADS1299RegisterError;
 pub use crate::errors::Error;
 pub use crate::registers::*;
 use core::result::Result;
@@ -20,6 +21,13 @@ pub const MIN_T_POR: u32 = MAX_ADS_CLK_PER_NS << 18;
 pub const MIN_T_RST: u32 = MAX_ADS_CLK_PER_NS << 1;
 pub const MIN_RST_WAIT: u32 = 18 * MAX_ADS_CLK_PER_NS;
 
+/// Length of an RDATAC frame with 8 channels: 24 status bits, then 24 bits
+/// per channel.
+pub const FRAME_LEN: usize = 27;
+
+/// Undecoded RDATAC frames of every device on a frontend.
+pub type RawFrames<const N: usize> = [[u8; FRAME_LEN]; N];
+
 pub struct Ads1299<SPI> {
     spi: SPI,
     pub num_chs: Option<u8>,
@@ -156,6 +164,17 @@ where
         Ok(AdsData::new(sample, *self.num_chs.get_or_insert(8)))
     }
 
+    /// Reads one RDATAC frame into `frame` without decoding it, so the
+    /// transfer can run while an earlier frame is decoded; see
+    /// [`AdsData::from_frame`].
+    pub async fn rdatac_raw(
+        &mut self,
+        frame: &mut [u8; FRAME_LEN],
+    ) -> Result<(), Error<E>> {
+        let len = 3 + 3 * self.num_chs.unwrap_or(8) as usize;
+        self.spi.read(&mut frame[..len]).await.map_err(Error::SpiError)
+    }
+
     pub async fn get_num_ch(&mut self) -> Result<u8, Error<E>> {
         let reg_value: u8 = self.read_register(Register::ID).await?;
         let id = Id::from_bits_retain(reg_value);
@@ -278,6 +297,18 @@ impl AdsData {
         }
     }
 
+    /// Decodes a frame read by [`Ads1299::rdatac_raw`], checking its
+    /// status header.
+    pub fn from_frame(
+        frame: &[u8; FRAME_LEN],
+        num_chs: u8,
+    ) -> Result<Self, ADS1299RegisterError> {
+        if (frame[0] & 0xF0) != 0xC0 {
+            return Err(ADS1299RegisterError::InvalidFrameHeader(frame[0]));
+        }
+        Ok(Self::new(*frame, num_chs))
+    }
+
     fn read_statusp(buffer: [u8; 3]) -> LoffStatP {
         LoffStatP::from_bits_retain(buffer[0] << 4 | buffer[1] >> 4)
     }
@@ -369,8 +400,32 @@ where
     }
 
     pub async fn poll(&mut self) -> Result<Vec<AdsData, N>, Error<E>> {
+        self.wait_ready().await;
+        self.read().await
+    }
+
+    /// Waits for DRDY, i.e. for a new conversion to be ready.
+    pub async fn wait_ready(&mut self) {
         self.drdy.wait_for_falling_edge().await.unwrap();
+    }
+
+    /// Reads the conversion that DRDY announced into `frames`, leaving the
+    /// decoding to the caller.
+    ///
+    /// With two sets of frames, one can be decoded while the next is being
+    /// transferred.
+    pub async fn read_raw(
+        &mut self,
+        frames: &mut RawFrames<N>,
+    ) -> Result<(), Error<E>> {
+        for (dev, frame) in self.ads.iter_mut().zip(frames.iter_mut()) {
+            dev.rdatac_raw(frame).await?;
+        }
+        Ok(())
+    }
 
+    /// Reads the conversion that DRDY announced.
+    pub async fn read(&mut self) -> Result<Vec<AdsData, N>, Error<E>> {
         let mut data: Vec<AdsData, N> = Vec::new();
         for dev in self.ads.iter_mut() {
             let _ = data.push(dev.rdatac().await?);
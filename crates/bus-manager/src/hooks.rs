@@ -0,0 +1,30 @@
+/// Side effects tied to the lifetime of a managed bus.
+///
+/// [`on_create`](Self::on_create) runs before the factory builds the bus and
+/// [`on_release`](Self::on_release) runs after the bus has been torn down (or
+/// after a failed create), while the manager's state mutex is held. This is
+/// the place to switch a supply rail in lockstep with the bus.
+///
+/// Both hooks default to no-ops; `()` is the hook type of managers created
+/// with [`BusManager::new`](crate::BusManager::new).
+#[allow(async_fn_in_trait)]
+pub trait BusHooks {
+    /// Called before [`BusFactory::create`](crate::BusFactory::create).
+    async fn on_create(&self) {}
+
+    /// Called after [`BusFactory::recover`](crate::BusFactory::recover), or
+    /// after a failed create to undo [`on_create`](Self::on_create).
+    async fn on_release(&self) {}
+}
+
+impl BusHooks for () {}
+
+impl<T: BusHooks + ?Sized> BusHooks for &T {
+    async fn on_create(&self) {
+        T::on_create(self).await
+    }
+
+    async fn on_release(&self) {
+        T::on_release(self).await
+    }
+}
@@ -57,6 +57,18 @@
 //! manager.try_release().await?; // recovers resources, bus powers down
 //! ```
 //!
+//! # Hooks
+//!
+//! [`BusManager::with_hooks`] attaches [`BusHooks`] that run before the bus
+//! is created and after it is released, e.g. to switch the supply rail of the
+//! devices on the bus.
+//!
+//! # Registries
+//!
+//! Boards with several buses can group their managers in a registry keyed by
+//! role (see [`BusId`], [`ProvidesBus`] and [`bus_registry!`]). Drivers then
+//! request e.g. `buses.get::<ImuBus>()` without naming a manager static.
+//!
 //! # Safety invariants
 //!
 //! - The bus is written to `GroundedCell` only while the mutex is held (Idle -> Active)
@@ -68,9 +80,13 @@
 mod error;
 mod factory;
 mod handle;
+mod hooks;
 mod manager;
+mod registry;
 
 pub use error::BusError;
 pub use factory::BusFactory;
 pub use handle::BusHandle;
+pub use hooks::BusHooks;
 pub use manager::BusManager;
+pub use registry::{BusId, ProvidesBus};
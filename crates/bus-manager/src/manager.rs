@@ -6,6 +6,7 @@ use portable_atomic::{AtomicUsize, Ordering};
 use crate::error::BusError;
 use crate::factory::BusFactory;
 use crate::handle::BusHandle;
+use crate::hooks::BusHooks;
 
 /// Phase state machine for the bus lifecycle.
 enum Phase<F: BusFactory> {
@@ -22,25 +23,36 @@ enum Phase<F: BusFactory> {
 /// Manages the creation, sharing, and teardown of a bus peripheral.
 /// The bus is lazily created on first `acquire()` and can be explicitly
 /// released with `try_release()` when all handles have been dropped.
-pub struct BusManager<M: RawMutex, F: BusFactory> {
+/// Optional [`BusHooks`] run around creation and teardown.
+pub struct BusManager<M: RawMutex, F: BusFactory, H: BusHooks = ()> {
     bus_cell: GroundedCell<F::Bus>,
     state: Mutex<M, Phase<F>>,
     users: AtomicUsize,
+    hooks: H,
 }
 
 impl<M: RawMutex, F: BusFactory> BusManager<M, F> {
     /// Create a new bus manager with the given resources.
     pub const fn new(resources: F::Resources) -> Self {
+        Self::with_hooks(resources, ())
+    }
+}
+
+impl<M: RawMutex, F: BusFactory, H: BusHooks> BusManager<M, F, H> {
+    /// Create a new bus manager whose bus lifetime drives `hooks`.
+    pub const fn with_hooks(resources: F::Resources, hooks: H) -> Self {
         Self {
             bus_cell: GroundedCell::uninit(),
             state: Mutex::new(Phase::Idle(resources)),
             users: AtomicUsize::new(0),
+            hooks,
         }
     }
 
     /// Acquire a handle to the bus.
     ///
-    /// If the bus is not yet configured, it will be created via the factory.
+    /// If the bus is not yet configured, `on_create` runs and the bus is
+    /// created via the factory.
     /// The bus will remain configured as long as at least one handle exists
     /// (and until `try_release()` is called after all handles are dropped).
     pub async fn acquire(
@@ -50,6 +62,10 @@ impl<M: RawMutex, F: BusFactory> BusManager<M, F> {
 
         match &*state {
             Phase::Idle(_) => {
+                // Run the hook while still Idle: if this future is dropped
+                // mid-hook the manager stays usable instead of Poisoned.
+                self.hooks.on_create().await;
+
                 // Take resources out, replacing with Poisoned temporarily.
                 let resources =
                     match core::mem::replace(&mut *state, Phase::Poisoned) {
@@ -77,6 +93,7 @@ impl<M: RawMutex, F: BusFactory> BusManager<M, F> {
                     Err((err, resources)) => {
                         // Restore resources so the manager can try again later.
                         *state = Phase::Idle(resources);
+                        self.hooks.on_release().await;
                         Err(BusError::FactoryError(err))
                     }
                 }
@@ -92,7 +109,8 @@ impl<M: RawMutex, F: BusFactory> BusManager<M, F> {
 
     /// Attempt to release (deconfigure) the bus and recover resources.
     ///
-    /// Returns `Ok(())` if the bus was successfully torn down or was already idle.
+    /// Returns `Ok(())` if the bus was successfully torn down (after running
+    /// `on_release`) or was already idle.
     /// Returns `Err(InUse(n))` if there are still `n` active handles.
     pub async fn try_release(&self) -> Result<(), BusError<F::Error>> {
         let mut state = self.state.lock().await;
@@ -122,6 +140,7 @@ impl<M: RawMutex, F: BusFactory> BusManager<M, F> {
 
                 let resources = F::recover(destructor);
                 *state = Phase::Idle(resources);
+                self.hooks.on_release().await;
 
                 Ok(())
             }
@@ -0,0 +1,119 @@
+use embassy_sync::blocking_mutex::raw::RawMutex;
+
+use crate::factory::BusFactory;
+use crate::hooks::BusHooks;
+use crate::manager::BusManager;
+
+/// Names a bus by the role it plays (e.g. "the IMU bus").
+///
+/// A key only carries the factory type of the bus it resolves to; several
+/// keys may resolve to the same physical bus.
+pub trait BusId {
+    /// Factory of the bus this key resolves to.
+    type Factory: BusFactory;
+}
+
+/// Provides the [`BusManager`] registered under the key `K`.
+///
+/// Implement this once per key on a board-level registry type (usually via
+/// [`bus_registry!`](crate::bus_registry)). Drivers can then be generic over
+/// `R: ProvidesBus<K>` instead of being compiled against a specific manager
+/// static.
+pub trait ProvidesBus<K: BusId> {
+    /// Raw mutex type used by the registered manager.
+    type Mutex: RawMutex;
+    /// Hooks attached to the registered manager.
+    type Hooks: BusHooks;
+
+    /// Returns the manager registered under `K`.
+    fn bus_manager(&self)
+        -> &BusManager<Self::Mutex, K::Factory, Self::Hooks>;
+}
+
+/// Declares a registry struct holding `'static` bus manager references,
+/// implements [`ProvidesBus`] for each listed key and adds an inherent
+/// `get::<Key>()` accessor. A bus may name its [`BusHooks`] type after the
+/// factory; it defaults to `()`.
+///
+/// ```rust,ignore
+/// bus_registry! {
+///     pub struct Buses<CriticalSectionRawMutex> {
+///         twim1: Twim1Factory, &'static SensorRail => [ImuBus, ApdsBus],
+///         spi3: Spi3Factory => [AdsBus],
+///     }
+/// }
+///
+/// let handle = buses.get::<ImuBus>().acquire().await?;
+/// ```
+#[macro_export]
+macro_rules! bus_registry {
+    (@hooks) => { () };
+    (@hooks $hooks:ty) => { $hooks };
+    (
+        @impl $name:ident<$mutex:ty>,
+        $field:ident: $factory:ty,
+        $hooks:ty,
+        [$($key:ty),*]
+    ) => {
+        $(
+            impl $crate::ProvidesBus<$key> for $name {
+                type Mutex = $mutex;
+                type Hooks = $hooks;
+
+                #[inline]
+                fn bus_manager(
+                    &self,
+                ) -> &$crate::BusManager<$mutex, $factory, $hooks> {
+                    self.$field
+                }
+            }
+        )*
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident<$mutex:ty> {
+            $(
+                $field:ident: $factory:ty $(, $hooks:ty)?
+                    => [$($key:ty),* $(,)?]
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $(
+                pub $field: &'static $crate::BusManager<
+                    $mutex,
+                    $factory,
+                    $crate::bus_registry!(@hooks $($hooks)?),
+                >,
+            )*
+        }
+
+        impl $name {
+            /// Returns the manager registered under `K`.
+            #[inline]
+            #[allow(dead_code)]
+            pub fn get<K: $crate::BusId>(
+                &self,
+            ) -> &$crate::BusManager<
+                $mutex,
+                K::Factory,
+                <Self as $crate::ProvidesBus<K>>::Hooks,
+            >
+            where
+                Self: $crate::ProvidesBus<K, Mutex = $mutex>,
+            {
+                <Self as $crate::ProvidesBus<K>>::bus_manager(self)
+            }
+        }
+
+        $(
+            $crate::bus_registry!(
+                @impl $name<$mutex>,
+                $field: $factory,
+                $crate::bus_registry!(@hooks $($hooks)?),
+                [$($key),*]
+            );
+        )*
+    };
+}
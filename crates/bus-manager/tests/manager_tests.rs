@@ -1,7 +1,9 @@
+use std::future::Future;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::task::Poll;
 
-use bus_manager::{BusError, BusFactory, BusHandle, BusManager};
+use bus_manager::{BusError, BusFactory, BusHandle, BusHooks, BusManager};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 
 // ---------------------------------------------------------------------------
@@ -109,6 +111,26 @@ impl BusFactory for MockFactory {
     }
 }
 
+/// Hooks recording how often they ran, standing in for a supply rail.
+#[derive(Default)]
+struct RailHooks {
+    enabled: AtomicBool,
+    on_count: AtomicUsize,
+    off_count: AtomicUsize,
+}
+
+impl BusHooks for RailHooks {
+    async fn on_create(&self) {
+        self.enabled.store(true, Ordering::SeqCst);
+        self.on_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    async fn on_release(&self) {
+        self.enabled.store(false, Ordering::SeqCst);
+        self.off_count.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Helper
 // ---------------------------------------------------------------------------
@@ -253,3 +275,89 @@ async fn handle_deref_returns_correct_value() {
     let bus: &MockBus = &*handle;
     assert_eq!(bus.value, 99);
 }
+
+#[futures_test::test]
+async fn hooks_follow_bus_lifetime() {
+    let fail = Arc::new(AtomicBool::new(false));
+    let hooks = RailHooks::default();
+    let mgr: BusManager<NoopRawMutex, MockFactory, &RailHooks> =
+        BusManager::with_hooks(
+            MockResources { value: 1, fail_next: fail },
+            &hooks,
+        );
+
+    let h1 = mgr.acquire().await.unwrap();
+    let h2 = mgr.acquire().await.unwrap();
+    assert!(hooks.enabled.load(Ordering::SeqCst));
+    assert_eq!(hooks.on_count.load(Ordering::SeqCst), 1);
+
+    drop(h1);
+    drop(h2);
+    assert!(hooks.enabled.load(Ordering::SeqCst));
+
+    mgr.try_release().await.unwrap();
+    assert!(!hooks.enabled.load(Ordering::SeqCst));
+    assert_eq!(hooks.off_count.load(Ordering::SeqCst), 1);
+
+    // Releasing an idle bus does not run the hook again.
+    mgr.try_release().await.unwrap();
+    assert_eq!(hooks.off_count.load(Ordering::SeqCst), 1);
+}
+
+#[futures_test::test]
+async fn hooks_undo_failed_create() {
+    let fail = Arc::new(AtomicBool::new(true));
+    let hooks = RailHooks::default();
+    let mgr: BusManager<NoopRawMutex, MockFactory, &RailHooks> =
+        BusManager::with_hooks(
+            MockResources { value: 1, fail_next: fail },
+            &hooks,
+        );
+
+    assert!(mgr.acquire().await.is_err());
+    assert!(!hooks.enabled.load(Ordering::SeqCst));
+    assert_eq!(hooks.on_count.load(Ordering::SeqCst), 1);
+    assert_eq!(hooks.off_count.load(Ordering::SeqCst), 1);
+
+    let _handle = mgr.acquire().await.unwrap();
+    assert!(hooks.enabled.load(Ordering::SeqCst));
+    assert_eq!(hooks.on_count.load(Ordering::SeqCst), 2);
+}
+
+/// Hooks whose `on_create` stays pending until the gate is opened.
+#[derive(Default)]
+struct GatedHooks {
+    open: AtomicBool,
+}
+
+impl BusHooks for GatedHooks {
+    async fn on_create(&self) {
+        std::future::poll_fn(|_| {
+            if self.open.load(Ordering::SeqCst) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[futures_test::test]
+async fn cancelled_acquire_does_not_poison() {
+    let hooks = GatedHooks::default();
+    let mgr: BusManager<NoopRawMutex, MockFactory, &GatedHooks> =
+        BusManager::with_hooks(
+            MockResources { value: 5, fail_next: Arc::default() },
+            &hooks,
+        );
+
+    let mut acquire = Box::pin(mgr.acquire());
+    let mut cx = futures_test::task::noop_context();
+    assert!(acquire.as_mut().poll(&mut cx).is_pending());
+    drop(acquire);
+
+    hooks.open.store(true, Ordering::SeqCst);
+    let handle = mgr.acquire().await.unwrap();
+    assert_eq!(handle.value, 5);
+}
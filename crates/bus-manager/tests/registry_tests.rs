@@ -0,0 +1,164 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use bus_manager::{
+    bus_registry, BusFactory, BusHooks, BusId, BusManager, ProvidesBus,
+};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+
+// ---------------------------------------------------------------------------
+// Mock factories
+// ---------------------------------------------------------------------------
+
+/// A bus that remembers which factory built it.
+#[derive(Debug, PartialEq, Eq)]
+struct NamedBus(&'static str);
+
+struct I2cFactory;
+struct SpiFactory;
+
+impl BusFactory for I2cFactory {
+    type Bus = NamedBus;
+    type Resources = ();
+    type Destructor = ();
+    type Error = ();
+
+    fn create(
+        _resources: Self::Resources,
+    ) -> Result<(Self::Bus, Self::Destructor), (Self::Error, Self::Resources)>
+    {
+        Ok((NamedBus("i2c"), ()))
+    }
+
+    fn recover(_destructor: Self::Destructor) -> Self::Resources {}
+}
+
+impl BusFactory for SpiFactory {
+    type Bus = NamedBus;
+    type Resources = ();
+    type Destructor = ();
+    type Error = ();
+
+    fn create(
+        _resources: Self::Resources,
+    ) -> Result<(Self::Bus, Self::Destructor), (Self::Error, Self::Resources)>
+    {
+        Ok((NamedBus("spi"), ()))
+    }
+
+    fn recover(_destructor: Self::Destructor) -> Self::Resources {}
+}
+
+// ---------------------------------------------------------------------------
+// Keys and registry
+// ---------------------------------------------------------------------------
+
+struct ImuBus;
+struct LightBus;
+struct FrontendBus;
+
+impl BusId for ImuBus {
+    type Factory = I2cFactory;
+}
+impl BusId for LightBus {
+    type Factory = I2cFactory;
+}
+impl BusId for FrontendBus {
+    type Factory = SpiFactory;
+}
+
+bus_registry! {
+    struct Buses<CriticalSectionRawMutex> {
+        i2c: I2cFactory => [ImuBus, LightBus],
+        spi: SpiFactory => [FrontendBus],
+    }
+}
+
+/// Rail switched by the I2C bus lifetime.
+#[derive(Default)]
+struct SensorRail {
+    enabled: AtomicBool,
+}
+
+impl BusHooks for SensorRail {
+    async fn on_create(&self) {
+        self.enabled.store(true, Ordering::SeqCst);
+    }
+
+    async fn on_release(&self) {
+        self.enabled.store(false, Ordering::SeqCst);
+    }
+}
+
+bus_registry! {
+    struct RailBuses<CriticalSectionRawMutex> {
+        i2c: I2cFactory, &'static SensorRail => [ImuBus, LightBus],
+        spi: SpiFactory => [FrontendBus],
+    }
+}
+
+/// Registries hold `'static` references; tests simply leak their managers.
+fn leak<T>(value: T) -> &'static T {
+    Box::leak(Box::new(value))
+}
+
+fn make_registry() -> Buses {
+    Buses { i2c: leak(BusManager::new(())), spi: leak(BusManager::new(())) }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[futures_test::test]
+async fn get_resolves_each_key() {
+    let buses = make_registry();
+
+    let imu = buses.get::<ImuBus>().acquire().await.unwrap();
+    let ads = buses.get::<FrontendBus>().acquire().await.unwrap();
+
+    assert_eq!(*imu, NamedBus("i2c"));
+    assert_eq!(*ads, NamedBus("spi"));
+}
+
+#[futures_test::test]
+async fn keys_share_the_same_manager() {
+    let buses = make_registry();
+
+    let _imu = buses.get::<ImuBus>().acquire().await.unwrap();
+    let _light = buses.get::<LightBus>().acquire().await.unwrap();
+
+    assert!(core::ptr::eq(buses.get::<ImuBus>(), buses.get::<LightBus>()));
+    assert_eq!(buses.get::<ImuBus>().user_count(), 2);
+    assert_eq!(buses.get::<FrontendBus>().user_count(), 0);
+}
+
+/// Drivers can be written against a key without naming the registry type.
+async fn driver_user_count<R>(registry: &R) -> usize
+where
+    R: ProvidesBus<ImuBus>,
+{
+    let _handle = registry.bus_manager().acquire().await.unwrap();
+    registry.bus_manager().user_count()
+}
+
+#[futures_test::test]
+async fn generic_driver_access() {
+    let buses = make_registry();
+    assert_eq!(driver_user_count(&buses).await, 1);
+}
+
+#[futures_test::test]
+async fn registry_bus_with_hooks() {
+    let rail: &'static SensorRail = leak(SensorRail::default());
+    let buses = RailBuses {
+        i2c: leak(BusManager::with_hooks((), rail)),
+        spi: leak(BusManager::new(())),
+    };
+
+    let imu = buses.get::<ImuBus>().acquire().await.unwrap();
+    assert!(rail.enabled.load(Ordering::SeqCst));
+
+    drop(imu);
+    buses.get::<LightBus>().try_release().await.unwrap();
+    assert!(!rail.enabled.load(Ordering::SeqCst));
+}
@@ -1,24 +1,43 @@
 MEMORY
 {
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
-  BOOTLOADER                        : ORIGIN = 0x00000000, LENGTH = 24K
-  BOOTLOADER_STATE                  : ORIGIN = 0x00006000, LENGTH = 4K
-  FLASH                             : ORIGIN = 0x00007000, LENGTH = 988K
+  /* Keep in sync with dc-mini-boot's memory.x */
+  BOOTLOADER                        : ORIGIN = 0x00000000, LENGTH = 96K
+  BOOTLOADER_STATE                  : ORIGIN = 0x00018000, LENGTH = 4K
+  FLASH                             : ORIGIN = 0x00019000, LENGTH = 908K
+  /* Just past the bootloader's active partition: inside it, a swap would
+     move the report and a rollback would discard it. Keep in sync between
+     both images. */
+  CRASH                             : ORIGIN = 0x000fc000, LENGTH = 4K
+  /* Written by the bootloader, see `dc_mini_bsp::BootMetrics`. Keep in
+     sync between both images. */
+  BOOT_METRICS                      : ORIGIN = 0x000fd000, LENGTH = 4K
   STORAGE                           : ORIGIN = 0x000fe000, LENGTH = 8K
   RAM                         (rwx) : ORIGIN = 0x20000000, LENGTH = 256K
 
-  /* DFU is stored in external flash */
+  /* DFU is stored in external flash, followed by the data partition.
+     Keep in sync with the layout in `dc_mini_bsp::ExternalFlashResources`. */
   DFU                               : ORIGIN = 0x00000000, LENGTH = 992K
   EXTERNAL_STORAGE                  : ORIGIN = 0x000f8000, LENGTH = 1056K
 }
  
 __storage_start = ORIGIN(STORAGE);
 __storage_end = ORIGIN(STORAGE) + LENGTH(STORAGE);
-__external_storage = ORIGIN(EXTERNAL_STORAGE);
+
+__crash_start = ORIGIN(CRASH);
+
+__boot_metrics_start = ORIGIN(BOOT_METRICS);
+
+/* The application runs from the bootloader's active partition */
+__bootloader_active_start = ORIGIN(FLASH);
+__bootloader_active_end = ORIGIN(FLASH) + LENGTH(FLASH);
 
 __bootloader_state_start = ORIGIN(BOOTLOADER_STATE);
 __bootloader_state_end = ORIGIN(BOOTLOADER_STATE) + LENGTH(BOOTLOADER_STATE);
 
+/* DFU signing key, stored in the last 256 bytes of the bootloader */
+__bootloader_dfu_key = ORIGIN(BOOTLOADER) + LENGTH(BOOTLOADER) - 256;
+
 __bootloader_dfu_start = ORIGIN(DFU);
 __bootloader_dfu_end = ORIGIN(DFU) + LENGTH(DFU);
 
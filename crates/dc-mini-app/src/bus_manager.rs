@@ -1,15 +1,98 @@
 //! I2C Bus Manager for power-efficient shared bus access
 //!
 //! Thin type aliases over the generic `bus_manager` crate, specialized for
-//! the TWIM1 peripheral on nRF52840.
+//! the TWIM1 peripheral on nRF52840, plus the board bus registry that lets
+//! drivers request a bus by role and the sensor rail switched with the bus.
 
-use bus_manager::{BusHandle, BusManager};
+use bus_manager::{bus_registry, BusHandle, BusHooks, BusId, BusManager};
 use dc_mini_bsp::Twim1Factory;
+#[cfg(not(feature = "sr6"))]
+use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+#[cfg(not(feature = "sr6"))]
+use embassy_sync::mutex::Mutex;
+
+#[cfg(not(feature = "sr6"))]
+use crate::tasks::power_control::rails::{self, RailUser};
+
+/// The dedicated PMIC bus, shared by the PMIC driver and [`PmicUsbCSense`].
+#[cfg(not(feature = "sr6"))]
+pub type PmicBus =
+    Mutex<CriticalSectionRawMutex, embassy_nrf::twim::Twim<'static>>;
+
+/// Device on the dedicated PMIC bus.
+#[cfg(not(feature = "sr6"))]
+pub type PmicBusDevice = I2cDevice<
+    'static,
+    CriticalSectionRawMutex,
+    embassy_nrf::twim::Twim<'static>,
+>;
+
+/// nPM1300 on its dedicated PMIC bus.
+#[cfg(not(feature = "sr6"))]
+pub type Pmic = npm1300::NPM1300<PmicBusDevice, embassy_time::Delay>;
+
+/// USB-C sense of the nPM1300 on its dedicated PMIC bus.
+#[cfg(not(feature = "sr6"))]
+pub type PmicUsbCSense = dc_mini_bsp::UsbCSense<PmicBusDevice>;
+
+/// Claims the 3V3 sensor rail from the power manager in lockstep with
+/// TWIM1.
+///
+/// On SR6 the PMIC sits on TWIM1 itself, so the rail cannot follow the
+/// bus and is claimed once in `main` instead.
+#[derive(Default)]
+pub struct SensorRail;
+
+impl SensorRail {
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl BusHooks for SensorRail {
+    #[cfg(not(feature = "sr6"))]
+    async fn on_create(&self) {
+        rails::acquire(RailUser::SensorBus).await;
+    }
+
+    #[cfg(not(feature = "sr6"))]
+    async fn on_release(&self) {
+        rails::release(RailUser::SensorBus).await;
+    }
+}
 
 /// I2C bus manager for the TWIM1 peripheral.
-pub type I2cBusManager = BusManager<CriticalSectionRawMutex, Twim1Factory>;
+pub type I2cBusManager =
+    BusManager<CriticalSectionRawMutex, Twim1Factory, &'static SensorRail>;
 
 /// RAII handle for accessing the shared I2C bus.
 pub type I2cBusHandle<'a> =
     BusHandle<'a, CriticalSectionRawMutex, Twim1Factory>;
+
+/// Bus used by the ICM-45605 IMU.
+pub struct ImuBus;
+/// Bus used by the APDS-9253 light sensor.
+pub struct ApdsBus;
+/// Bus used by the DRV2605L haptic driver.
+pub struct HapticBus;
+
+impl BusId for ImuBus {
+    type Factory = Twim1Factory;
+}
+
+impl BusId for ApdsBus {
+    type Factory = Twim1Factory;
+}
+
+impl BusId for HapticBus {
+    type Factory = Twim1Factory;
+}
+
+bus_registry! {
+    /// Shared buses of the board, addressable by role (e.g. `get::<ImuBus>()`).
+    pub struct AppBuses<CriticalSectionRawMutex> {
+        twim1: Twim1Factory, &'static SensorRail
+            => [ImuBus, ApdsBus, HapticBus],
+    }
+}
@@ -1,28 +1,265 @@
-use core::cell::RefCell;
-use core::ops::Add;
+use core::cell::Cell;
+use core::ops::Deref;
 
-use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use dc_mini_icd::TimeSample;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Instant};
 use portable_atomic::{AtomicBool, Ordering};
 
 pub static CLOCK_SET: AtomicBool = AtomicBool::new(false);
 
-pub struct Clock {
-    time: Mutex<ThreadModeRawMutex, RefCell<time::PrimitiveDateTime>>,
+/// Exchanges closer together than this re-anchor the clock without
+/// updating the drift estimate, which link jitter would dominate.
+const DRIFT_MIN_INTERVAL_US: u64 = 60_000_000;
+/// Largest drift believed; the crystals are good to tens of ppm.
+const DRIFT_MAX_PPB: i64 = 200_000;
+
+/// How uptime maps to Unix time, as of the last time the clock was set.
+#[derive(Clone, Copy)]
+struct ClockSync {
+    /// Unix time in microseconds at boot, as measured at `uptime_us`.
+    boot_epoch_us: i64,
+    uptime_us: u64,
+    /// See [`dc_mini_icd::TimeStatus::drift_ppb`].
+    drift_ppb: i32,
+    /// Set by a two-way exchange, precise enough to estimate drift from.
+    exchanged: bool,
+}
+
+impl ClockSync {
+    const UNSET: Self = Self {
+        boot_epoch_us: 0,
+        uptime_us: 0,
+        drift_ppb: 0,
+        exchanged: false,
+    };
+
+    fn epoch_us(&self, uptime_us: u64) -> i64 {
+        let since_sync = uptime_us as i64 - self.uptime_us as i64;
+        self.boot_epoch_us
+            + uptime_us as i64
+            + since_sync * self.drift_ppb as i64 / 1_000_000_000
+    }
+}
+
+static SYNC: Mutex<CriticalSectionRawMutex, Cell<ClockSync>> =
+    Mutex::new(Cell::new(ClockSync::UNSET));
+
+/// Device timestamp for `uptime_us`: Unix time in microseconds once the
+/// clock has been set, microseconds since boot before that.
+pub fn timestamp_micros(uptime_us: u64) -> u64 {
+    if !CLOCK_SET.load(Ordering::SeqCst) {
+        return uptime_us;
+    }
+    SYNC.lock(|sync| sync.get().epoch_us(uptime_us)).max(0) as u64
+}
+
+/// Current device timestamp, see [`timestamp_micros`].
+pub fn now_micros() -> u64 {
+    timestamp_micros(Instant::now().as_micros())
+}
+
+/// A reading tagged with its capture time, so that records from different
+/// sensors line up. Each pipeline stamps as close to the hardware as it
+/// can: ADS samples at DRDY, IMU samples from the sensor's own FIFO clock
+/// (see [`SensorClock`]) or at data-ready, mic buffers at DMA completion.
+#[derive(Debug, Clone, Copy)]
+pub struct Captured<T> {
+    /// Device timestamp of the capture, see [`timestamp_micros`].
+    pub ts: u64,
+    pub data: T,
 }
 
+impl<T> Captured<T> {
+    pub fn at(instant: Instant, data: T) -> Self {
+        Self { ts: timestamp_micros(instant.as_micros()), data }
+    }
+}
+
+impl<T> Deref for Captured<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data
+    }
+}
+
+/// Largest step, per batch, by which [`SensorClock`] lets its offset grow.
+const SENSOR_OFFSET_RELAX_US: i64 = 10;
+
+/// Maps the free-running 16-bit timestamps of a sensor FIFO to the device
+/// clock.
+///
+/// The sensor time is unwrapped across batches and related to the device
+/// clock by an offset: the smallest seen difference between the time a
+/// batch was read and the sensor time of its newest sample. Only the read
+/// latency is ever added to that difference, so its minimum is the best
+/// estimate; it may creep up slowly to follow drift between the clocks.
+pub struct SensorClock {
+    tick_us: u32,
+    last: Option<(u16, Instant)>,
+    sensor_us: u64,
+    offset_us: Option<i64>,
+}
+
+impl SensorClock {
+    pub const fn new(tick_us: u32) -> Self {
+        Self { tick_us, last: None, sensor_us: 0, offset_us: None }
+    }
+
+    /// Forgets the sensor time, e.g. after the sensor was reconfigured.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.offset_us = None;
+    }
+
+    fn wrap(&self) -> Duration {
+        Duration::from_micros(self.tick_us as u64 * (u16::MAX as u64 + 1))
+    }
+
+    /// Unwrapped sensor time of `tick`, which must not be older than the
+    /// previous one by more than a counter wrap.
+    fn advance(&mut self, tick: u16, now: Instant) -> u64 {
+        match self.last {
+            Some((last, at)) if now - at < self.wrap() => {
+                let ticks = tick.wrapping_sub(last) as u64;
+                self.sensor_us += ticks * self.tick_us as u64;
+            }
+            // Too long since the last reading to unwrap; start over.
+            _ => self.offset_us = None,
+        }
+        self.last = Some((tick, now));
+        self.sensor_us
+    }
+
+    /// Capture times of a batch of samples with sensor timestamps `ticks`,
+    /// oldest first, read at `read_at`.
+    pub fn map_batch<const N: usize>(
+        &mut self,
+        ticks: &[u16],
+        read_at: Instant,
+    ) -> heapless::Vec<Instant, N> {
+        let mut sensor_us = heapless::Vec::<u64, N>::new();
+        for tick in ticks {
+            let _ = sensor_us.push(self.advance(*tick, read_at));
+        }
+        let Some(newest) = sensor_us.last() else {
+            return heapless::Vec::new();
+        };
+        let candidate = read_at.as_micros() as i64 - *newest as i64;
+        let offset = match self.offset_us {
+            Some(offset) => candidate.min(offset + SENSOR_OFFSET_RELAX_US),
+            None => candidate,
+        };
+        self.offset_us = Some(offset);
+        sensor_us
+            .iter()
+            .map(
+                |us| Instant::from_micros((*us as i64 + offset).max(0) as u64),
+            )
+            .collect()
+    }
+}
+
+/// The device's wall clock, kept as an offset from uptime plus an estimate
+/// of how fast uptime drifts against the host.
+pub struct Clock;
+
 impl Clock {
     pub const fn new() -> Self {
-        Self { time: Mutex::new(RefCell::new(time::PrimitiveDateTime::MIN)) }
+        Self
     }
 
-    pub fn set(&self, time: time::PrimitiveDateTime) {
-        self.time.lock(|f| *f.borrow_mut() = time);
+    fn store(&self, sync: ClockSync) {
+        SYNC.lock(|cell| cell.set(sync));
         CLOCK_SET.store(true, Ordering::SeqCst);
     }
 
-    pub fn get(&self, duration: time::Duration) -> time::PrimitiveDateTime {
-        let time = self.time.lock(|f| f.borrow().clone());
-        time.add(duration)
+    /// Sets the clock in one step from the Unix time (in microseconds) of
+    /// boot. The drift estimate is kept, but not refined.
+    pub fn set_boot_epoch_micros(&self, micros: i64) {
+        let drift_ppb = SYNC.lock(|cell| cell.get().drift_ppb);
+        self.store(ClockSync {
+            boot_epoch_us: micros,
+            uptime_us: Instant::now().as_micros(),
+            drift_ppb,
+            exchanged: false,
+        });
+    }
+
+    /// Sets the clock in one step from the calendar time of boot.
+    pub fn set(&self, time: time::PrimitiveDateTime) {
+        let boot_epoch_us = time.assume_utc().unix_timestamp_nanos() / 1000;
+        self.set_boot_epoch_micros(boot_epoch_us as i64);
+    }
+
+    /// Calendar time `since_boot` after boot. Counts from
+    /// [`time::PrimitiveDateTime::MIN`] until the clock is set.
+    pub fn get(&self, since_boot: time::Duration) -> time::PrimitiveDateTime {
+        let uptime_us = since_boot.whole_microseconds().max(0) as u64;
+        self.epoch_micros(uptime_us)
+            .and_then(|us| {
+                time::OffsetDateTime::from_unix_timestamp_nanos(
+                    us as i128 * 1000,
+                )
+                .ok()
+            })
+            .map(|time| time::PrimitiveDateTime::new(time.date(), time.time()))
+            .unwrap_or(time::PrimitiveDateTime::MIN + since_boot)
+    }
+
+    /// Sets the clock from a two-way exchange with the host. How far the
+    /// clock had strayed since the previous exchange refines the drift
+    /// estimate. Returns that error in microseconds, 0 if there was no
+    /// previous exchange.
+    pub fn apply_exchange(&self, sample: &TimeSample) -> i64 {
+        let uptime_us = sample.device_us();
+        let boot_epoch_us = sample.boot_epoch_us();
+        let previous = SYNC.lock(|cell| cell.get());
+        let exchanged = self.exchanged();
+
+        let mut drift_ppb = previous.drift_ppb;
+        let error_us = if exchanged {
+            boot_epoch_us + uptime_us as i64 - previous.epoch_us(uptime_us)
+        } else {
+            0
+        };
+        let since_us = uptime_us.saturating_sub(previous.uptime_us);
+        if exchanged && since_us >= DRIFT_MIN_INTERVAL_US {
+            let correction =
+                error_us as i128 * 1_000_000_000 / since_us as i128;
+            drift_ppb = (drift_ppb as i64 + correction as i64)
+                .clamp(-DRIFT_MAX_PPB, DRIFT_MAX_PPB)
+                as i32;
+        }
+
+        self.store(ClockSync {
+            boot_epoch_us,
+            uptime_us,
+            drift_ppb,
+            exchanged: true,
+        });
+        error_us
+    }
+
+    /// Whether the clock was last set by a two-way exchange, rather than in
+    /// one step.
+    pub fn exchanged(&self) -> bool {
+        CLOCK_SET.load(Ordering::SeqCst)
+            && SYNC.lock(|cell| cell.get().exchanged)
+    }
+
+    /// See [`dc_mini_icd::TimeStatus::drift_ppb`].
+    pub fn drift_ppb(&self) -> i32 {
+        SYNC.lock(|cell| cell.get().drift_ppb)
+    }
+
+    /// Unix time in microseconds at `uptime_us`, if the clock is set.
+    pub fn epoch_micros(&self, uptime_us: u64) -> Option<i64> {
+        if !CLOCK_SET.load(Ordering::SeqCst) {
+            return None;
+        }
+        Some(SYNC.lock(|cell| cell.get().epoch_us(uptime_us)))
     }
 }
@@ -0,0 +1,22 @@
+//! Sample encoding of the live ADS streams.
+//!
+//! Like [decimation](crate::decimation) this only affects data sent to a
+//! host; SD recordings always store raw readings.
+
+use dc_mini_icd::AdsCodec;
+use portable_atomic::{AtomicBool, Ordering};
+
+static DELTA: AtomicBool = AtomicBool::new(false);
+
+/// Codec applied to outgoing ADS frames.
+pub fn ads_codec() -> AdsCodec {
+    if DELTA.load(Ordering::Relaxed) {
+        AdsCodec::Delta
+    } else {
+        AdsCodec::Raw
+    }
+}
+
+pub fn set_ads_codec(codec: AdsCodec) {
+    DELTA.store(codec == AdsCodec::Delta, Ordering::Relaxed);
+}
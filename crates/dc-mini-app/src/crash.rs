@@ -0,0 +1,207 @@
+//! Crash reports that survive the reset after a panic or hard fault.
+//!
+//! The handlers in `main.rs` call [`record_panic`] and [`record_hard_fault`],
+//! which write a [`CrashReport`] to a reserved internal flash page and reset.
+//! The watchdog task calls [`record_stall`] before it stops feeding the
+//! watchdog.
+//! The page sits just past the bootloader's active partition, so a report
+//! outlives a firmware update and, in particular, the rollback of one that
+//! crashed.
+
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use cortex_m_rt::ExceptionFrame;
+use dc_mini_icd::{
+    CrashKind, CrashReport, MonitoredTask, MAX_CRASH_MESSAGE_LEN,
+};
+use embassy_nrf::nvmc::{Nvmc, PAGE_SIZE};
+use embedded_storage::nor_flash::NorFlash;
+use portable_atomic::{AtomicBool, Ordering};
+
+const MAGIC: u32 = 0xC8A5_4E02;
+const HEADER_WORDS: usize = 8;
+/// Task word of reports not about a stalled task.
+const NO_TASK: u32 = u32::MAX;
+const RECORD_LEN: usize = HEADER_WORDS * 4 + MAX_CRASH_MESSAGE_LEN + 4;
+
+static CRASHING: AtomicBool = AtomicBool::new(false);
+
+fn page_start() -> u32 {
+    extern "C" {
+        static __crash_start: u32;
+    }
+    unsafe { &__crash_start as *const u32 as u32 }
+}
+
+/// Collects a message into a fixed buffer, dropping what does not fit.
+struct Truncating {
+    buf: [u8; MAX_CRASH_MESSAGE_LEN],
+    len: usize,
+}
+
+impl Write for Truncating {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    // FNV-1a
+    bytes.iter().fold(0x811c_9dc5, |hash, b| {
+        (hash ^ *b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn active_exception() -> u16 {
+    let icsr = unsafe { (*cortex_m::peripheral::SCB::PTR).icsr.read() };
+    (icsr & 0x1FF) as u16
+}
+
+fn uptime_ms() -> u32 {
+    embassy_time::Instant::now().as_millis() as u32
+}
+
+/// Records a panic and resets.
+pub fn record_panic(info: &PanicInfo) -> ! {
+    let mut message = Truncating { buf: [0; MAX_CRASH_MESSAGE_LEN], len: 0 };
+    // A panic while formatting the message resets without a report.
+    if !CRASHING.swap(true, Ordering::SeqCst) {
+        let _ = write!(message, "{}", info);
+        store(
+            CrashKind::Panic,
+            cortex_m::register::pc::read(),
+            cortex_m::register::lr::read(),
+            active_exception(),
+            &message.buf[..message.len],
+            None,
+        );
+    }
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Records a hard fault and resets.
+pub fn record_hard_fault(ef: &ExceptionFrame) -> ! {
+    if !CRASHING.swap(true, Ordering::SeqCst) {
+        store(
+            CrashKind::HardFault,
+            ef.pc(),
+            ef.lr(),
+            (ef.xpsr() & 0x1FF) as u16,
+            &[],
+            None,
+        );
+    }
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Records that `task` stalled. Unlike the other handlers this does not
+/// reset; the watchdog does once it is no longer fed.
+pub fn record_stall(task: MonitoredTask) {
+    if !CRASHING.swap(true, Ordering::SeqCst) {
+        store(
+            CrashKind::TaskStalled,
+            cortex_m::register::pc::read(),
+            cortex_m::register::lr::read(),
+            active_exception(),
+            &[],
+            Some(task),
+        );
+    }
+}
+
+fn store(
+    kind: CrashKind,
+    pc: u32,
+    lr: u32,
+    exception: u16,
+    message: &[u8],
+    task: Option<MonitoredTask>,
+) {
+    let header = [
+        MAGIC,
+        kind as u32,
+        pc,
+        lr,
+        exception as u32,
+        uptime_ms(),
+        message.len() as u32,
+        task.map_or(NO_TASK, |task| task as u32),
+    ];
+    let mut record = [0xFFu8; RECORD_LEN];
+    for (chunk, word) in record.chunks_exact_mut(4).zip(header) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    let body = HEADER_WORDS * 4;
+    record[body..body + message.len()].copy_from_slice(message);
+    let sum = checksum(&record[..RECORD_LEN - 4]);
+    record[RECORD_LEN - 4..].copy_from_slice(&sum.to_le_bytes());
+
+    // Safety: nothing else runs once a crash is being recorded; a stall is
+    // recorded from a task, and the NVMC serializes it with the profile
+    // storage writes.
+    let mut nvmc =
+        Nvmc::new(unsafe { embassy_nrf::peripherals::NVMC::steal() });
+    let start = page_start();
+    if nvmc.erase(start, start + PAGE_SIZE as u32).is_ok() {
+        let _ = nvmc.write(start, &record);
+    }
+}
+
+/// The report left by the last crash, if any.
+pub fn last_report() -> Option<CrashReport> {
+    let mut record = [0u8; RECORD_LEN];
+    let start = page_start() as *const u8;
+    for (i, byte) in record.iter_mut().enumerate() {
+        *byte = unsafe { core::ptr::read_volatile(start.add(i)) };
+    }
+
+    let word = |i: usize| {
+        u32::from_le_bytes(record[i * 4..i * 4 + 4].try_into().unwrap())
+    };
+    let sum = u32::from_le_bytes(record[RECORD_LEN - 4..].try_into().unwrap());
+    if word(0) != MAGIC || sum != checksum(&record[..RECORD_LEN - 4]) {
+        return None;
+    }
+
+    let kind = match word(1) {
+        0 => CrashKind::Panic,
+        1 => CrashKind::HardFault,
+        _ => CrashKind::TaskStalled,
+    };
+    let body = HEADER_WORDS * 4;
+    let len = (word(6) as usize).min(MAX_CRASH_MESSAGE_LEN);
+    let mut message = heapless::String::new();
+    // The message was cut at a byte limit; keep its valid UTF-8 prefix.
+    let text = match core::str::from_utf8(&record[body..body + len]) {
+        Ok(text) => text,
+        Err(e) => core::str::from_utf8(&record[body..body + e.valid_up_to()])
+            .unwrap_or_default(),
+    };
+    let _ = message.push_str(text);
+
+    Some(CrashReport {
+        kind,
+        pc: word(2),
+        lr: word(3),
+        exception: word(4) as u16,
+        uptime_ms: word(5),
+        message,
+        task: crate::stats::monitored_task(word(7) as usize),
+    })
+}
+
+/// Erases the stored report. Returns false if the erase failed.
+pub fn clear() -> bool {
+    if last_report().is_none() {
+        return true;
+    }
+    // Safety: the crash page is only written here and by the crash
+    // handlers; the NVMC serializes it with the profile storage writes.
+    let mut nvmc =
+        Nvmc::new(unsafe { embassy_nrf::peripherals::NVMC::steal() });
+    let start = page_start();
+    nvmc.erase(start, start + PAGE_SIZE as u32).is_ok()
+}
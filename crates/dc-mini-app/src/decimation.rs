@@ -0,0 +1,227 @@
+//! Rate reduction of the live data streams.
+//!
+//! The stream tasks (USB and BLE) run every sample through a [`Decimator`];
+//! the SD recording reads the measurement channel directly and always keeps
+//! the full acquisition rate.
+//!
+//! On top of the configured factor, each ADS stream consumer decimates
+//! further on its own while its link cannot keep up, see
+//! [`CongestionControl`], and reports the total factor in its frames.
+
+use crate::prelude::{info, warn};
+use dc_mini_icd::{
+    DecimationMode, StreamConfig, StreamKind, ADS_MAX_CHANNELS,
+};
+use portable_atomic::{AtomicU8, Ordering};
+
+const STREAMS: usize = 2;
+
+/// Largest factor congestion control adds to the configured one.
+const MAX_CONGESTION_FACTOR: u8 = 8;
+/// Frames in a row with a saturated backlog before the rate is halved.
+const SATURATED_FRAMES: u8 = 5;
+/// Frames in a row with a short backlog before the rate is doubled again.
+const CLEAR_FRAMES: u8 = 50;
+
+static FACTORS: [AtomicU8; STREAMS] = [AtomicU8::new(1), AtomicU8::new(1)];
+static MODES: [AtomicU8; STREAMS] = [
+    AtomicU8::new(DecimationMode::Decimate as u8),
+    AtomicU8::new(DecimationMode::Decimate as u8),
+];
+
+fn mode_from_u8(value: u8) -> DecimationMode {
+    if value == DecimationMode::Average as u8 {
+        DecimationMode::Average
+    } else {
+        DecimationMode::Decimate
+    }
+}
+
+/// Current configuration of `stream`.
+pub fn config(stream: StreamKind) -> StreamConfig {
+    StreamConfig {
+        stream,
+        factor: FACTORS[stream as usize].load(Ordering::Relaxed),
+        mode: mode_from_u8(MODES[stream as usize].load(Ordering::Relaxed)),
+    }
+}
+
+/// Applies `config`. Returns `false` if it is invalid.
+pub fn set_config(config: &StreamConfig) -> bool {
+    if config.factor == 0
+        || (config.stream == StreamKind::Imu
+            && config.mode == DecimationMode::Average)
+    {
+        return false;
+    }
+    MODES[config.stream as usize].store(config.mode as u8, Ordering::Relaxed);
+    FACTORS[config.stream as usize].store(config.factor, Ordering::Relaxed);
+    true
+}
+
+/// Per-consumer decimation state of one stream.
+pub struct Decimator {
+    stream: StreamKind,
+    /// Multiplies the configured factor.
+    extra: u8,
+    factor: u8,
+    mode: DecimationMode,
+    count: u8,
+    sum: [i64; ADS_MAX_CHANNELS],
+}
+
+impl Decimator {
+    pub const fn new(stream: StreamKind) -> Self {
+        Self {
+            stream,
+            extra: 1,
+            factor: 1,
+            mode: DecimationMode::Decimate,
+            count: 0,
+            sum: [0; ADS_MAX_CHANNELS],
+        }
+    }
+
+    /// Feeds one sample and returns whether it should be sent.
+    ///
+    /// In [`DecimationMode::Average`] the emitted sample's `data` is replaced
+    /// by the mean over the window. Streams without channel data (IMU) pass
+    /// an empty slice.
+    pub fn push(&mut self, data: &mut [i32]) -> bool {
+        let StreamConfig { factor, mode, .. } = config(self.stream);
+        let factor = factor.saturating_mul(self.extra);
+        if factor != self.factor || mode != self.mode {
+            // Start a fresh window on configuration changes.
+            self.factor = factor;
+            self.mode = mode;
+            self.count = 0;
+            self.sum = [0; ADS_MAX_CHANNELS];
+        }
+        if self.factor <= 1 {
+            return true;
+        }
+
+        if self.mode == DecimationMode::Average {
+            for (acc, x) in self.sum.iter_mut().zip(data.iter()) {
+                *acc += *x as i64;
+            }
+        }
+        self.count += 1;
+        if self.count < self.factor {
+            return false;
+        }
+
+        if self.mode == DecimationMode::Average {
+            for (x, acc) in data.iter_mut().zip(self.sum.iter_mut()) {
+                *x = (*acc / self.factor as i64) as i32;
+                *acc = 0;
+            }
+        }
+        self.count = 0;
+        true
+    }
+}
+
+/// Extra decimation of one stream consumer while its link is congested.
+///
+/// The consumer reports its backlog on the measurement channel after each
+/// frame it sends. A backlog that stays above three quarters of the
+/// channel doubles the factor; one that stays below a quarter halves it.
+pub struct CongestionControl {
+    factor: u8,
+    saturated: u8,
+    clear: u8,
+}
+
+impl CongestionControl {
+    pub const fn new() -> Self {
+        Self { factor: 1, saturated: 0, clear: 0 }
+    }
+
+    pub fn factor(&self) -> u8 {
+        self.factor
+    }
+
+    /// Takes the backlog after a frame. Returns whether the factor changed.
+    pub fn update(&mut self, backlog: usize, capacity: usize) -> bool {
+        if backlog * 4 >= capacity * 3 {
+            self.clear = 0;
+            self.saturated = self.saturated.saturating_add(1);
+            if self.saturated >= SATURATED_FRAMES
+                && self.factor < MAX_CONGESTION_FACTOR
+            {
+                self.factor *= 2;
+                self.saturated = 0;
+                return true;
+            }
+        } else if backlog * 4 <= capacity {
+            self.saturated = 0;
+            self.clear = self.clear.saturating_add(1);
+            if self.clear >= CLEAR_FRAMES && self.factor > 1 {
+                self.factor /= 2;
+                self.clear = 0;
+                return true;
+            }
+        } else {
+            self.saturated = 0;
+            self.clear = 0;
+        }
+        false
+    }
+}
+
+impl Default for CongestionControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decimators of the ADS stream and the IMU readings attached to it.
+pub struct AdsStreamDecimator {
+    ads: Decimator,
+    imu: Decimator,
+    congestion: CongestionControl,
+}
+
+impl AdsStreamDecimator {
+    pub const fn new() -> Self {
+        Self {
+            ads: Decimator::new(StreamKind::Ads),
+            imu: Decimator::new(StreamKind::Imu),
+            congestion: CongestionControl::new(),
+        }
+    }
+
+    /// Total factor the emitted ADS samples are decimated by.
+    pub fn factor(&self) -> u8 {
+        config(StreamKind::Ads).factor.saturating_mul(self.congestion.factor())
+    }
+
+    /// Takes the consumer's backlog of `capacity` after it sent a frame,
+    /// adapting the rate to the link.
+    pub fn report_backlog(&mut self, backlog: usize, capacity: usize) {
+        if self.congestion.update(backlog, capacity) {
+            self.ads.extra = self.congestion.factor();
+            if self.ads.extra > 1 {
+                warn!("ADS stream congested, decimating by {}", self.factor());
+            } else {
+                info!("ADS stream link recovered, full rate restored");
+            }
+        }
+    }
+
+    /// Feeds one ADS sample. Returns `None` if it is dropped, otherwise
+    /// whether its IMU reading should be kept.
+    pub fn push(&mut self, data: &mut [i32]) -> Option<bool> {
+        if !self.ads.push(data) {
+            return None;
+        }
+        Some(self.imu.push(&mut []))
+    }
+}
+
+impl Default for AdsStreamDecimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
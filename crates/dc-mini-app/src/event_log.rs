@@ -0,0 +1,172 @@
+//! Persistent log of system events, so the history of a unit that comes
+//! back misbehaving can be reconstructed.
+//!
+//! Anything may call [`record`]; [`event_log_task`] appends the events to
+//! a queue at the start of the external flash's data partition,
+//! overwriting the oldest ones once it is full. The partition sits behind
+//! the DFU partition, so firmware updates keep the log.
+
+use crate::clock::now_micros;
+use crate::prelude::warn;
+use crate::tasks::dfu::DfuResources;
+use dc_mini_bsp::{DataFlash, ExternalFlashResources};
+use dc_mini_icd::{EventLogDump, SystemEvent, SystemEventKind};
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::{
+    CriticalSectionRawMutex, NoopRawMutex,
+};
+use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{with_timeout, Duration, Timer};
+use portable_atomic::{AtomicBool, Ordering};
+use sequential_storage::cache::NoCache;
+use sequential_storage::queue::{QueueConfig, QueueStorage};
+
+/// Offset of the log in the data partition.
+const LOG_OFFSET: u32 = 0;
+/// Flash given to the log; about 600 events.
+const LOG_SIZE: u32 = 16 * 1024;
+/// Largest encoded event.
+const ENTRY_LEN: usize = 32;
+/// How long [`flush`] waits for pending events to reach flash.
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long [`dump`] waits for the log task.
+const DUMP_TIMEOUT: Duration = Duration::from_secs(2);
+
+type LogFlash = DataFlash<'static, NoopRawMutex>;
+
+static PENDING: Channel<CriticalSectionRawMutex, (u64, SystemEventKind), 8> =
+    Channel::new();
+/// Set while the task writes an event taken from [`PENDING`].
+static WRITING: AtomicBool = AtomicBool::new(false);
+static DUMP_REQ: Signal<CriticalSectionRawMutex, u32> = Signal::new();
+static DUMP_SIG: Signal<CriticalSectionRawMutex, EventLogDump> = Signal::new();
+/// One dump at a time, so requests and replies pair up.
+static DUMP_LOCK: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+
+/// Queues an event for the log. Never blocks; the event is dropped if the
+/// log task has fallen behind.
+pub fn record(kind: SystemEventKind) {
+    if PENDING.try_send((now_micros(), kind)).is_err() {
+        warn!("Event log queue full, dropped {:?}", kind);
+    }
+}
+
+/// Waits, for a bounded time, until the queued events are in flash. Call
+/// before powering down.
+pub async fn flush() {
+    let written = with_timeout(FLUSH_TIMEOUT, async {
+        while !PENDING.is_empty() || WRITING.load(Ordering::SeqCst) {
+            Timer::after_millis(10).await;
+        }
+    })
+    .await;
+    if written.is_err() {
+        warn!("Event log not flushed");
+    }
+}
+
+/// Reads up to [`dc_mini_icd::EVENT_LOG_DUMP_MAX`] events, starting at
+/// index `from`.
+pub async fn dump(from: u32) -> EventLogDump {
+    let _guard = DUMP_LOCK.lock().await;
+    DUMP_SIG.reset();
+    DUMP_REQ.signal(from);
+    match with_timeout(DUMP_TIMEOUT, DUMP_SIG.wait()).await {
+        Ok(dump) => dump,
+        Err(_) => {
+            DUMP_REQ.reset();
+            EventLogDump {
+                first: from,
+                events: heapless::Vec::new(),
+                end: from,
+            }
+        }
+    }
+}
+
+struct EventLog {
+    queue: QueueStorage<LogFlash, NoCache>,
+    buf: [u8; ENTRY_LEN],
+}
+
+impl EventLog {
+    fn new(flash: LogFlash) -> Self {
+        let config = QueueConfig::new(0..LOG_SIZE);
+        Self {
+            queue: QueueStorage::new(flash, config, NoCache::new()),
+            buf: [0; ENTRY_LEN],
+        }
+    }
+
+    /// The last event stored, if any.
+    async fn last(&mut self) -> Option<SystemEvent> {
+        let mut last = None;
+        let mut iter = self.queue.iter().await.ok()?;
+        while let Ok(Some(entry)) = iter.next(&mut self.buf).await {
+            if let Ok(event) = postcard::from_bytes::<SystemEvent>(&entry) {
+                last = Some(event);
+            }
+        }
+        last
+    }
+
+    async fn append(&mut self, event: &SystemEvent) {
+        let mut bytes = [0u8; ENTRY_LEN];
+        let Ok(encoded) = postcard::to_slice(event, &mut bytes) else {
+            return;
+        };
+        // Overwrites the oldest events once the log is full.
+        if self.queue.push(encoded, true).await.is_err() {
+            warn!("Failed to write event log");
+        }
+    }
+
+    async fn dump(&mut self, from: u32, end: u32) -> EventLogDump {
+        let mut events = heapless::Vec::new();
+        if let Ok(mut iter) = self.queue.iter().await {
+            while let Ok(Some(entry)) = iter.next(&mut self.buf).await {
+                let Ok(event) = postcard::from_bytes::<SystemEvent>(&entry)
+                else {
+                    continue;
+                };
+                if event.index >= from && events.push(event).is_err() {
+                    break;
+                }
+            }
+        }
+        let first = events.first().map_or(end, |e| e.index);
+        EventLogDump { first, events, end }
+    }
+}
+
+/// Writes recorded events to flash and serves [`dump`]. Must run on the
+/// thread-mode executor, which shares the QSPI flash with DFU.
+#[embassy_executor::task]
+pub async fn event_log_task(dfu: &'static DfuResources) {
+    let mut log = EventLog::new(ExternalFlashResources::data_region(
+        &dfu.dfu_flash,
+        LOG_OFFSET,
+        LOG_SIZE,
+    ));
+
+    let (mut index, boot) = match log.last().await {
+        Some(last) => (last.index + 1, last.boot + 1),
+        None => (0, 0),
+    };
+    loop {
+        match select(PENDING.receive(), DUMP_REQ.wait()).await {
+            Either::First((ts, kind)) => {
+                WRITING.store(true, Ordering::SeqCst);
+                let event = SystemEvent { index, boot, ts, kind };
+                log.append(&event).await;
+                index += 1;
+                WRITING.store(false, Ordering::SeqCst);
+            }
+            Either::Second(from) => {
+                DUMP_SIG.signal(log.dump(from, index).await);
+            }
+        }
+    }
+}
@@ -1,10 +1,20 @@
+use crate::event_log;
 use crate::tasks::ads::events::AdsEvent;
 use crate::tasks::apds::events::ApdsEvent;
 use crate::tasks::haptic::events::HapticEvent;
 use crate::tasks::mic::events::MicEvent;
+use crate::tasks::power_control::rails::{self, RailUser};
+use crate::tasks::power_control::sleep::{self, WakePin};
 use crate::tasks::session::events::SessionEvent;
 use crate::{prelude::*, todo};
+use core::future::Future;
 use derive_more::From;
+use embassy_futures::select::{select, Either};
+use embassy_time::with_timeout;
+
+/// Longest an optional sensor's manager may take over one event. Past
+/// it, the sensor is marked degraded and the loop moves on.
+const SENSOR_EVENT_TIMEOUT: Duration = Duration::from_secs(3);
 
 #[derive(Debug, From)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -37,31 +47,69 @@ pub enum Event {
     HapticEvent(HapticEvent),
     PowerEvent(PowerEvent),
     DfuEvent(DfuEvent),
+    /// Runs every subsystem check and signals the report on
+    /// [`SELF_TEST_SIG`](crate::selftest::SELF_TEST_SIG).
+    SelfTest,
 }
 
 #[embassy_executor::task]
 pub async fn orchestrate(
     receiver: EventReceiver,
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
     ads_manager: AdsManager,
     apds_manager: ApdsManager,
     mut session_manager: SessionManager,
     imu_manager: ImuManager,
     mic_manager: MicManager,
     haptic_manager: HapticManager,
-    mut power_manager: PowerManager,
+    button_wake: WakePin,
 ) {
-    power_manager.handle_event(PowerEvent::Enable).await;
+    sleep::record_activity();
+    // Idle, the loop still wakes every poll; leave room for slow events.
+    let _watch = watch_heartbeat(
+        MonitoredTask::Orchestrator,
+        sleep::AUTO_SLEEP_POLL * 3,
+    );
 
     loop {
-        match receiver.receive().await {
+        let event = match select(
+            receiver.receive(),
+            Timer::after(sleep::AUTO_SLEEP_POLL),
+        )
+        .await
+        {
+            Either::First(event) => event,
+            Either::Second(_) => {
+                heartbeat(MonitoredTask::Orchestrator);
+                if sleep::idle_expired() {
+                    auto_sleep(&imu_manager, button_wake).await;
+                }
+                continue;
+            }
+        };
+        sleep::record_activity();
+        crate::stats::record_event_queue_depth(receiver.len() + 1);
+        heartbeat(MonitoredTask::Orchestrator);
+        match event {
             Event::AdsEvent(e) => ads_manager.handle_event(e).await,
-            Event::ApdsEvent(e) => apds_manager.handle_event(e).await,
+            Event::ApdsEvent(e) => {
+                isolate(MonitoredTask::Apds, apds_manager.handle_event(e))
+                    .await
+            }
             Event::SessionEvent(e) => session_manager.handle_event(e).await,
             Event::ButtonPress(e) => match e {
-                ButtonPress::Single => {} // Do nothing
-                ButtonPress::Double => {
+                ButtonPress::Single => {
                     ads_manager.handle_event(AdsEvent::ManualRecord).await;
                 }
+                ButtonPress::Double => {
+                    let profile = app_context
+                        .lock()
+                        .await
+                        .apply_profile_command(ProfileCommand::Next)
+                        .await;
+                    info!("Switched to profile {}", profile);
+                    show_profile(profile).await;
+                }
                 ButtonPress::Hold => {
                     info!("Powering down");
                     unwrap!(NEOPIX_CHAN.try_send(NeopixEvent::PowerOff));
@@ -69,15 +117,159 @@ pub async fn orchestrate(
                 }
             },
             Event::TimerElapsed => todo!(),
-            Event::ImuEvent(e) => imu_manager.handle_event(e).await,
+            Event::ImuEvent(e) => {
+                isolate(MonitoredTask::Imu, imu_manager.handle_event(e)).await
+            }
             Event::MicEvent(e) => mic_manager.handle_event(e).await,
             Event::HapticEvent(e) => haptic_manager.handle_event(e).await,
             Event::PowerEvent(e) => {
-                power_manager.handle_event(e).await;
+                let reason = if matches!(e, PowerEvent::Overheat) {
+                    PowerOffReason::Overheat
+                } else {
+                    PowerOffReason::LowBattery
+                };
+                protective_shutdown(
+                    reason,
+                    &ads_manager,
+                    &mut session_manager,
+                    &imu_manager,
+                    &mic_manager,
+                    &haptic_manager,
+                    button_wake,
+                )
+                .await;
             }
             Event::DfuEvent(e) => {
                 info!("DFU event: {:?}", e);
+                let kind = match e {
+                    DfuEvent::Started => Some(SystemEventKind::DfuStarted),
+                    DfuEvent::Progress(_) => None,
+                    DfuEvent::Complete => Some(SystemEventKind::DfuCompleted),
+                    DfuEvent::Failed => Some(SystemEventKind::DfuFailed),
+                    DfuEvent::Aborted => Some(SystemEventKind::DfuAborted),
+                };
+                if let Some(kind) = kind {
+                    event_log::record(kind);
+                }
+            }
+            Event::SelfTest => {
+                info!("Running self-test");
+                let report = SelfTestReport {
+                    ads: ads_manager.self_test().await,
+                    imu: with_timeout(
+                        SENSOR_EVENT_TIMEOUT,
+                        imu_manager.self_test(),
+                    )
+                    .await
+                    .unwrap_or_else(|_| {
+                        crate::selftest::fail("IMU self-test timed out")
+                    }),
+                    sd: session_manager.self_test().await,
+                    pmic: pmic_self_test().await,
+                    mic: mic_manager.self_test().await,
+                };
+                info!("Self-test report: {:?}", report);
+                // The status LED shows the error state until a self-test
+                // passes.
+                let health = crate::selftest::record_health(&report);
+                if !health.is_healthy() {
+                    warn!("Board health check failed: {:?}", health);
+                }
+                crate::selftest::SELF_TEST_SIG.signal(report);
+            }
+        }
+    }
+}
+
+/// Runs an optional sensor's event `handling`, marking the sensor behind
+/// `task` degraded if it does not finish in time, e.g. on a stuck bus.
+/// Dropping the handler releases any lock it held.
+async fn isolate(task: MonitoredTask, handling: impl Future<Output = ()>) {
+    if with_timeout(SENSOR_EVENT_TIMEOUT, handling).await.is_err() {
+        error!("{:?} did not handle its event in time", task);
+        faults::degrade(FaultKind::SensorBusError, task);
+    }
+}
+
+/// Powers down the rails and enters System OFF with the button and, if
+/// available, IMU wake-on-motion as wake sources.
+async fn auto_sleep(imu_manager: &ImuManager, button_wake: WakePin) -> ! {
+    info!("Idle for {} s, powering down", sleep::AUTO_SLEEP_TIMEOUT.as_secs());
+    let mut wake = heapless::Vec::<WakePin, 2>::new();
+    let _ = wake.push(button_wake);
+    imu_manager.handle_event(ImuEvent::StopStream).await;
+    // A wedged IMU must not keep the device awake.
+    if let Ok(Some(pin)) =
+        with_timeout(SENSOR_EVENT_TIMEOUT, imu_manager.arm_wake_on_motion())
+            .await
+    {
+        let _ = wake.push(pin);
+    }
+    event_log::record(SystemEventKind::PowerOff(PowerOffReason::Idle));
+    event_log::flush().await;
+    // The sensor rail follows TWIM1, which wake-on-motion keeps up.
+    rails::release(RailUser::Ads).await;
+    sleep::enter_system_off(&wake)
+}
+
+/// Stops streaming, closes the session file, reports the fault and powers
+/// down before the battery browns out mid-write or the hardware cuts out.
+async fn protective_shutdown(
+    reason: PowerOffReason,
+    ads_manager: &AdsManager,
+    session_manager: &mut SessionManager,
+    imu_manager: &ImuManager,
+    mic_manager: &MicManager,
+    haptic_manager: &HapticManager,
+    button_wake: WakePin,
+) -> ! {
+    warn!("Shutting down: {:?}", reason);
+    if matches!(reason, PowerOffReason::Overheat) {
+        faults::report(FaultKind::Overheat, None);
+    } else {
+        faults::report(FaultKind::BatteryLow, None);
+        haptic_manager
+            .handle_event(HapticEvent::Trigger(HapticTrigger::LowBattery))
+            .await;
+    }
+    if session_active() {
+        session_manager.handle_event(SessionEvent::StopRecording).await;
+        // The recording task flushes and closes the file on its own.
+        let closed = with_timeout(Duration::from_secs(5), async {
+            while session_active() {
+                Timer::after_millis(50).await;
             }
+        })
+        .await;
+        if closed.is_err() {
+            error!("Session file did not close before shutdown");
         }
     }
+    ads_manager.handle_event(AdsEvent::StopAll).await;
+    imu_manager.handle_event(ImuEvent::StopStream).await;
+    mic_manager.handle_event(MicEvent::StopStream).await;
+    // Let the fault reach the host, the streams wind down and the haptic
+    // cue play out.
+    Timer::after_millis(500).await;
+    event_log::record(SystemEventKind::PowerOff(reason));
+    event_log::flush().await;
+    // The sensor rail follows TWIM1, which wake-on-motion keeps up.
+    rails::release(RailUser::Ads).await;
+    sleep::enter_system_off(&[button_wake])
+}
+
+/// Blink interval of the profile indication.
+const PROFILE_FLASH_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Blinks the LED once per profile index, counting from one, so profile 0
+/// is a single blink.
+async fn show_profile(profile: u8) {
+    NEOPIX_CHAN
+        .send(NeopixEvent::FlashFor(
+            smart_leds::colors::CYAN,
+            PROFILE_FLASH_INTERVAL,
+            u32::from(profile) + 1,
+            None,
+        ))
+        .await;
 }
@@ -0,0 +1,54 @@
+//! Structured reporting of runtime faults to the host.
+//!
+//! Tasks call [`report`] where they would otherwise only log an error; the
+//! USB server forwards every [`Fault`] on `FaultTopic`.
+
+use crate::clock::now_micros;
+use dc_mini_icd::{Fault, FaultKind, MonitoredTask, SystemEventKind};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pubsub::PubSubChannel;
+use portable_atomic::{AtomicU32, AtomicU8, Ordering};
+
+pub const FAULT_CAP: usize = 8;
+pub const FAULT_SUBS: usize = 2;
+
+pub static FAULT_CH: PubSubChannel<
+    CriticalSectionRawMutex,
+    Fault,
+    FAULT_CAP,
+    FAULT_SUBS,
+    0,
+> = PubSubChannel::new();
+
+const KINDS: usize = 8;
+
+static COUNTS: [AtomicU32; KINDS] = [const { AtomicU32::new(0) }; KINDS];
+
+/// Publishes a fault. Never blocks; the oldest fault is dropped when
+/// subscribers fall behind.
+pub fn report(kind: FaultKind, source: Option<MonitoredTask>) {
+    let count = COUNTS[kind as usize].fetch_add(1, Ordering::Relaxed) + 1;
+    crate::event_log::record(SystemEventKind::Fault(kind));
+    FAULT_CH.immediate_publisher().publish_immediate(Fault {
+        ts: now_micros(),
+        kind,
+        source,
+        count,
+    });
+}
+
+/// Subsystems marked degraded since boot, one bit per [`MonitoredTask`].
+static DEGRADED: AtomicU8 = AtomicU8::new(0);
+
+/// Reports `kind` for `task` and marks its subsystem degraded until the
+/// next boot. Its manager then ignores requests, so a failing optional
+/// sensor cannot hold up the rest of the device.
+pub fn degrade(kind: FaultKind, task: MonitoredTask) {
+    DEGRADED.fetch_or(1 << task as u8, Ordering::Relaxed);
+    report(kind, Some(task));
+}
+
+/// Whether the subsystem run by `task` has been marked degraded.
+pub fn is_degraded(task: MonitoredTask) -> bool {
+    DEGRADED.load(Ordering::Relaxed) & (1 << task as u8) != 0
+}
@@ -0,0 +1,26 @@
+//! Unit identity: the factory serial number and the user-assigned nickname.
+
+use core::fmt::Write;
+use dc_mini_icd::{Nickname, MAX_NICKNAME_LEN};
+use heapless::String;
+
+/// Name used in advertising and USB descriptors when no nickname is set.
+pub const DEFAULT_NAME: &str = "dc-mini";
+
+/// Factory serial number, the 64-bit FICR DEVICEID as hex.
+pub fn serial_number() -> String<16> {
+    let ficr = embassy_nrf::pac::FICR;
+    let id = (ficr.deviceid(1).read() as u64) << 32
+        | ficr.deviceid(0).read() as u64;
+    let mut serial = String::new();
+    let _ = write!(serial, "{:016X}", id);
+    serial
+}
+
+/// Name the device presents itself under.
+pub fn display_name(nickname: Option<&Nickname>) -> String<MAX_NICKNAME_LEN> {
+    match nickname {
+        Some(name) if !name.is_empty() => name.clone(),
+        _ => String::try_from(DEFAULT_NAME).unwrap(),
+    }
+}
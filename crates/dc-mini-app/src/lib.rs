@@ -6,7 +6,18 @@ extern crate alloc;
 
 mod bus_manager;
 mod clock;
+pub mod codec;
+pub mod crash;
+pub mod decimation;
+pub mod event_log;
 pub mod events;
+pub mod faults;
+pub mod identity;
+pub mod logging;
+pub mod memory;
+pub mod selftest;
+pub mod stats;
+pub mod status;
 pub mod storage;
 pub mod tasks;
 mod util;
@@ -15,14 +26,15 @@ mod util;
 compile_error!("You must enable exactly one of the following features: `trouble`, `critical-section`");
 
 use core::ptr::addr_of_mut;
-use dc_mini_icd::{DeviceCapabilities, DeviceInfo};
+use dc_mini_icd::{
+    DeviceCapabilities, DeviceInfo, ProfileCommand, MAX_PROFILES,
+};
 use embassy_executor::{InterruptExecutor, SendSpawner};
 use embassy_nrf::interrupt;
 use embassy_nrf::interrupt::{InterruptExt, Priority};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::{Channel, Receiver, Sender};
 use embassy_sync::mutex::Mutex;
-use embedded_alloc::LlffHeap;
 use static_cell::StaticCell;
 use storage::profile_manager::ProfileManager;
 
@@ -32,17 +44,14 @@ pub const MANUFACTURER: &str = "Johns Hopkins APL";
 
 // Heap helpers
 #[global_allocator]
-pub static ALLOCATOR: trallocator::Trallocator<LlffHeap> =
-    trallocator::Trallocator::new(LlffHeap::empty());
+pub static ALLOCATOR: memory::TrackedHeap = memory::TrackedHeap::new();
 // static HEAP: LlffHeap = LlffHeap::empty();
+pub const HEAP_SIZE: usize = 32 * 1024;
 pub fn init_heap() {
     use core::mem::MaybeUninit;
-    const HEAP_SIZE: usize = 32 * 1024;
     static mut HEAP_MEM: [MaybeUninit<u8>; HEAP_SIZE] =
         [MaybeUninit::uninit(); HEAP_SIZE];
-    unsafe {
-        ALLOCATOR.borrow().init(addr_of_mut!(HEAP_MEM) as usize, HEAP_SIZE)
-    }
+    unsafe { ALLOCATOR.init(addr_of_mut!(HEAP_MEM) as usize, HEAP_SIZE) }
 }
 
 const PROFILE_BUF_SZ: usize = 256;
@@ -93,6 +102,18 @@ impl AppContext {
             }
         }
     }
+    pub async fn save_montage(&mut self, montage: prelude::Montage) {
+        match self.profile_manager.set_montage(montage).await {
+            Ok(_) => {
+                self.event_sender
+                    .send(prelude::AdsEvent::MontageChanged.into())
+                    .await;
+            }
+            Err(e) => {
+                prelude::warn!("Failed to save montage: {:?}", e);
+            }
+        }
+    }
     pub async fn save_imu_config(&mut self, config: prelude::ImuConfig) {
         match self.profile_manager.set_imu_config(config).await {
             Ok(_) => {
@@ -133,6 +154,65 @@ impl AppContext {
             }
         }
     }
+
+    /// Makes `profile` current and has the running sensors and the LED pick
+    /// up its configs. Returns `false` if the switch could not be stored.
+    pub async fn switch_profile(&mut self, profile: u8) -> bool {
+        if let Err(e) = self.profile_manager.set_current_profile(profile).await
+        {
+            prelude::warn!("Failed to switch to profile {}: {:?}", profile, e);
+            return false;
+        }
+        let capabilities = self.capabilities();
+        self.event_sender.send(prelude::AdsEvent::ConfigChanged.into()).await;
+        if capabilities.imu_present {
+            self.event_sender
+                .send(prelude::ImuEvent::ConfigChanged.into())
+                .await;
+        }
+        if capabilities.apds_present {
+            self.event_sender
+                .send(prelude::ApdsEvent::ConfigChanged.into())
+                .await;
+        }
+        self.event_sender.send(prelude::MicEvent::ConfigChanged.into()).await;
+        // The LED also rereads its config on the next battery reading, so a
+        // full channel only delays the change.
+        let _ =
+            prelude::NEOPIX_CHAN.try_send(prelude::NeopixEvent::ConfigChanged);
+        true
+    }
+
+    /// Applies a profile command, wrapping past the last profile. Returns
+    /// the profile current afterwards.
+    pub async fn apply_profile_command(
+        &mut self,
+        command: ProfileCommand,
+    ) -> u8 {
+        let current = self.profile_manager.get_current_profile().await;
+        let profile = match command {
+            ProfileCommand::Reset => 0,
+            ProfileCommand::Next => {
+                if current >= MAX_PROFILES {
+                    0
+                } else {
+                    current + 1
+                }
+            }
+            ProfileCommand::Previous => {
+                if current == 0 {
+                    MAX_PROFILES
+                } else {
+                    current - 1
+                }
+            }
+        };
+        if self.switch_profile(profile).await {
+            profile
+        } else {
+            current
+        }
+    }
 }
 
 // Statics
@@ -142,16 +222,14 @@ pub static APP_CONTEXT: StaticCell<
     Mutex<CriticalSectionRawMutex, AppContext>,
 > = StaticCell::new();
 
-const EVENT_CAPACITY: usize = 10;
+pub const EVENT_CAPACITY: usize = 10;
 pub type EventMutexType = CriticalSectionRawMutex;
 pub type EventChannel = Channel<EventMutexType, events::Event, EVENT_CAPACITY>;
 pub type EventSender =
     Sender<'static, EventMutexType, events::Event, EVENT_CAPACITY>;
 pub type EventReceiver =
     Receiver<'static, EventMutexType, events::Event, EVENT_CAPACITY>;
-static EVENT_CHANNEL: StaticCell<
-    Channel<CriticalSectionRawMutex, events::Event, 10>,
-> = StaticCell::new();
+static EVENT_CHANNEL: StaticCell<EventChannel> = StaticCell::new();
 pub fn init_event_channel() -> (EventSender, EventReceiver) {
     let channel = EVENT_CHANNEL.init(Channel::new());
     (channel.sender(), channel.receiver())
@@ -181,10 +259,10 @@ pub fn init_executors() -> (SendSpawner, SendSpawner) {
 
 pub mod prelude {
     pub use super::{
-        bus_manager::*, error, events::*, info, init_executors, init_heap,
-        storage::*, tasks::*, unwrap, warn, AppContext, AppProfileManager,
-        EventReceiver, EventSender, State, CLOCK, FW_VERSION, HW_VERSION,
-        MANUFACTURER,
+        bus_manager::*, error, events::*, faults, info, init_executors,
+        init_heap, stats::heartbeat, stats::watch_heartbeat, storage::*,
+        tasks::*, unwrap, warn, AppContext, AppProfileManager, EventReceiver,
+        EventSender, State, CLOCK, FW_VERSION, HW_VERSION, MANUFACTURER,
     };
     pub use embassy_executor::Spawner;
     pub use embassy_nrf::bind_interrupts;
@@ -198,8 +276,10 @@ pub mod prelude {
     pub use embassy_time::{Duration, Timer};
 
     pub use dc_mini_bsp::{
-        AdsResources, DCMini, HapticResources, ImuResources, MicResources,
-        SdCardResources, Spi3BusResources, Twim1BusResources,
+        AdsResources, BoardRev, DCMini, HapticResources, ImuResources,
+        MicResources, SdCardResources, Spi3BusResources, StatusLed,
+        StatusLedResources, Twim1BusResources, UsbCSense, UsbCurrent,
+        VsysSenseResources,
     };
     pub use dc_mini_icd::{
         self as icd,
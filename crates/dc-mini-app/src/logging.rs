@@ -0,0 +1,110 @@
+//! Forwarding of firmware log records to the host.
+//!
+//! The logging macros in `util.rs` hand every call to [`record`], which
+//! publishes it on [`LOG_CH`] if it passes the level filter. The last
+//! [`LOG_HISTORY_LEN`] records are also kept in RAM, so the lead-up to a
+//! problem can be read back with [`dump`] after the fact.
+
+use core::cell::RefCell;
+pub use dc_mini_icd::LogLevel;
+use dc_mini_icd::{LogDump, LogRecord, MAX_LOG_LEN};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::pubsub::PubSubChannel;
+use embassy_time::Instant;
+use heapless::Deque;
+use portable_atomic::{AtomicU8, Ordering};
+
+pub const LOG_CAP: usize = 16;
+pub const LOG_SUBS: usize = 2;
+/// Records kept for [`dump`], about 7 KiB of RAM.
+pub const LOG_HISTORY_LEN: usize = 64;
+
+struct History {
+    records: Deque<LogRecord, LOG_HISTORY_LEN>,
+    /// Index the next record gets.
+    end: u32,
+}
+
+static HISTORY: Mutex<CriticalSectionRawMutex, RefCell<History>> =
+    Mutex::new(RefCell::new(History { records: Deque::new(), end: 0 }));
+
+pub static LOG_CH: PubSubChannel<
+    CriticalSectionRawMutex,
+    LogRecord,
+    LOG_CAP,
+    LOG_SUBS,
+    0,
+> = PubSubChannel::new();
+
+static LOG_FILTER: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+const LEVELS: [LogLevel; 6] = [
+    LogLevel::Trace,
+    LogLevel::Debug,
+    LogLevel::Info,
+    LogLevel::Warn,
+    LogLevel::Error,
+    LogLevel::Off,
+];
+
+/// Minimum level forwarded to the host.
+pub fn level() -> LogLevel {
+    LEVELS[LOG_FILTER.load(Ordering::Relaxed) as usize]
+}
+
+pub fn set_level(level: LogLevel) {
+    LOG_FILTER.store(level as u8, Ordering::Relaxed);
+}
+
+/// Publishes a log record if `level` passes the filter. Never blocks; the
+/// oldest record is dropped when subscribers fall behind.
+///
+/// The history keeps everything the filter passes, and `Info` and above
+/// even while the filter is stricter or off.
+pub fn record(level: LogLevel, message: &str) {
+    let forward = level >= self::level();
+    if level == LogLevel::Off || (!forward && level < LogLevel::Info) {
+        return;
+    }
+
+    let mut end = message.len().min(MAX_LOG_LEN);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut text = heapless::String::new();
+    let _ = text.push_str(&message[..end]);
+
+    let record =
+        LogRecord { ts: Instant::now().as_micros(), level, message: text };
+
+    HISTORY.lock(|history| {
+        let mut history = history.borrow_mut();
+        if history.records.is_full() {
+            history.records.pop_front();
+        }
+        let _ = history.records.push_back(record.clone());
+        history.end += 1;
+    });
+
+    if forward {
+        LOG_CH.immediate_publisher().publish_immediate(record);
+    }
+}
+
+/// Up to [`dc_mini_icd::LOG_DUMP_MAX`] kept records, starting at index
+/// `from` or the oldest one still kept.
+pub fn dump(from: u32) -> LogDump {
+    HISTORY.lock(|history| {
+        let history = history.borrow();
+        let oldest = history.end - history.records.len() as u32;
+        let first = from.clamp(oldest, history.end);
+        let mut records = heapless::Vec::new();
+        for record in history.records.iter().skip((first - oldest) as usize) {
+            if records.push(record.clone()).is_err() {
+                break;
+            }
+        }
+        LogDump { first, records, end: history.end }
+    })
+}
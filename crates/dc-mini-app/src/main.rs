@@ -13,11 +13,12 @@ use static_cell::StaticCell;
 use defmt_rtt as _;
 #[cfg(feature = "defmt")]
 use panic_probe as _;
-#[cfg(not(feature = "defmt"))]
-use panic_reset as _;
 
+use dc_mini_app::event_log::event_log_task;
 use dc_mini_app::tasks::dfu::DfuResources;
+use dc_mini_app::tasks::power_control::{rails, usb_c};
 use dc_mini_app::{init_event_channel, prelude::*, FW_VERSION};
+use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
 use embassy_nrf::nvmc::Nvmc;
 
 static ADS_RESOURCES: StaticCell<
@@ -30,12 +31,25 @@ static SPI3_BUS_RESOURCES: StaticCell<
     Mutex<CriticalSectionRawMutex, Spi3BusResources>,
 > = StaticCell::new();
 static I2C_BUS_MANAGER: StaticCell<I2cBusManager> = StaticCell::new();
+static SENSOR_RAIL: StaticCell<SensorRail> = StaticCell::new();
+#[cfg(not(feature = "sr6"))]
+static PMIC_BUS_RESOURCES: StaticCell<dc_mini_bsp::PmicBusResources> =
+    StaticCell::new();
+#[cfg(not(feature = "sr6"))]
+static PMIC_BUS: StaticCell<PmicBus> = StaticCell::new();
+#[cfg(not(feature = "sr6"))]
+static PMIC: StaticCell<Mutex<CriticalSectionRawMutex, Pmic>> =
+    StaticCell::new();
+static BUSES: StaticCell<AppBuses> = StaticCell::new();
 static IMU_RESOURCES: StaticCell<
     Mutex<CriticalSectionRawMutex, ImuResources>,
 > = StaticCell::new();
 static MIC_RESOURCES: StaticCell<
     Mutex<CriticalSectionRawMutex, MicResources>,
 > = StaticCell::new();
+static VSYS_SENSE_RESOURCES: StaticCell<
+    Mutex<CriticalSectionRawMutex, VsysSenseResources>,
+> = StaticCell::new();
 static APP_CONTEXT: StaticCell<Mutex<CriticalSectionRawMutex, AppContext>> =
     StaticCell::new();
 static DFU_RESOURCES: StaticCell<DfuResources> = StaticCell::new();
@@ -46,8 +60,27 @@ static EXT_FLASH_RES: StaticCell<dc_mini_bsp::ExternalFlashResources> =
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     info!("In main!");
+    dc_mini_app::memory::paint_stack();
+    dc_mini_app::stats::capture_reset_reason();
+    dc_mini_app::event_log::record(SystemEventKind::PowerOn(
+        dc_mini_app::stats::reset_reason(),
+    ));
+    dc_mini_app::tasks::dfu::record_boot_status();
+    if let Some(crash) = dc_mini_app::crash::last_report() {
+        warn!("Reset after a crash: {:?}", crash);
+    }
     // First we initialize our board.
     let mut board = DCMini::default();
+    info!("Board revision: {:?}", board.rev);
+    // Pin assignments still follow the hardware feature, so flag a board
+    // this firmware was not built for.
+    if board.rev != BoardRev::COMPILED {
+        warn!(
+            "Firmware built for {:?} running on {:?}",
+            BoardRev::COMPILED,
+            board.rev
+        );
+    }
 
     // Phase 0: Confirm boot to prevent rollback on next reset.
     // Temporarily init QSPI + NVMC to set boot state, then drop them
@@ -70,7 +103,14 @@ async fn main(spawner: Spawner) {
         let mut aligned = [0u8; 4];
         let mut updater = BlockingFirmwareUpdater::new(config, &mut aligned);
         match updater.mark_booted() {
-            Ok(()) => info!("Firmware boot confirmed (mark_booted ok)"),
+            Ok(()) => {
+                info!("Firmware boot confirmed (mark_booted ok)");
+                nvmc.lock(|nvmc| {
+                    dc_mini_app::tasks::dfu::acknowledge_boot_status(
+                        &mut *nvmc.borrow_mut(),
+                    )
+                });
+            }
             Err(_e) => warn!("mark_booted failed"),
         }
         // ext_flash and nvmc dropped here, QSPI/NVMC peripherals freed.
@@ -80,24 +120,25 @@ async fn main(spawner: Spawner) {
     // ExternalFlashResources moved to StaticCell so QSPI gets 'static lifetime.
     let ext_flash_res = EXT_FLASH_RES.init(board.external_flash);
     let dfu_qspi = ext_flash_res.configure();
-    // Safety: This NVMC instance only writes to BOOTLOADER_STATE (0x6000..0x7000).
+    // Safety: This NVMC instance only writes to BOOTLOADER_STATE
+    // (0x18000..0x19000).
     // The ProfileManager's NVMC writes to STORAGE (0xFE000..0x100000).
     // Non-overlapping regions, serialized by hardware.
     let dfu_nvmc = unsafe { embassy_nrf::peripherals::NVMC::steal() };
     let dfu_nvmc = Nvmc::new(dfu_nvmc);
     let dfu_resources =
         DFU_RESOURCES.init(DfuResources::new(dfu_qspi, dfu_nvmc));
-
-    let mut power_manager = PowerManager::new(board.en5v.into());
+    // Shares the QSPI flash with DFU, so it runs on this executor too.
+    spawner.must_spawn(event_log_task(dfu_resources));
 
     #[cfg(feature = "trouble")]
-    let sdc = {
-        let (sdc, mpsl) = board
+    let (sdc, ble_seed) = {
+        let (sdc, mpsl, seed) = board
             .ble
             .init(board.timer0, board.rng)
             .expect("BLE stack failed to initialize");
         spawner.must_spawn(mpsl_task(mpsl));
-        sdc
+        (sdc, seed)
     };
 
     // Initialize the allocator BEFORE you use it
@@ -118,7 +159,8 @@ async fn main(spawner: Spawner) {
 
     let app_context = APP_CONTEXT.init(Mutex::new(AppContext {
         device_info: DeviceInfo {
-            hardware_revision: heapless::String::try_from(HW_VERSION).unwrap(),
+            hardware_revision: heapless::String::try_from(board.rev.as_str())
+                .unwrap(),
             software_revision: heapless::String::try_from(FW_VERSION).unwrap(),
             manufacturer_name: heapless::String::try_from(MANUFACTURER)
                 .unwrap(),
@@ -145,87 +187,70 @@ async fn main(spawner: Spawner) {
     let ads_resources = ADS_RESOURCES.init(Mutex::new(board.ads_resources));
     let sd_card_resources =
         SD_CARD_RESOURCES.init(Mutex::new(board.sd_card_resources));
-    let i2c_bus_manager =
-        I2C_BUS_MANAGER.init(I2cBusManager::new(board.twim1_bus_resources));
+
+    use npm1300::{
+        charger::ChargerTerminationVoltage, sysreg::VbusInCurrentLimit,
+        NtcThermistorType, NPM1300,
+    };
+
+    // On SR7 the PMIC has a dedicated bus, so TWIM1 switches the sensor rail
+    // through its bus hooks.
+    #[cfg(not(feature = "sr6"))]
+    let pmic_bus = {
+        let resources = PMIC_BUS_RESOURCES.init(board.pmic_bus_resources);
+        PMIC_BUS.init(Mutex::new(resources.get_bus()))
+    };
+    #[cfg(not(feature = "sr6"))]
+    let pmic = PMIC.init(Mutex::new(NPM1300::new(
+        I2cDevice::new(pmic_bus),
+        embassy_time::Delay,
+    )));
+    #[cfg(not(feature = "sr6"))]
+    let mut usb_c_sense = UsbCSense::new(I2cDevice::new(pmic_bus));
+    #[cfg(not(feature = "sr6"))]
+    let _ = SHARED_PMIC.init(pmic);
+    let sensor_rail = SENSOR_RAIL.init(SensorRail::new());
+
+    let i2c_bus_manager = I2C_BUS_MANAGER.init(I2cBusManager::with_hooks(
+        board.twim1_bus_resources,
+        sensor_rail,
+    ));
+    let buses = BUSES.init(AppBuses { twim1: i2c_bus_manager });
+    #[cfg(not(feature = "sr6"))]
+    let power_manager = PowerManager::new(board.en5v.into());
+    #[cfg(feature = "sr6")]
+    let power_manager = PowerManager::new(board.en5v.into(), i2c_bus_manager);
+    let _ = rails::POWER_MANAGER.init(Mutex::new(power_manager));
     let imu_resources = IMU_RESOURCES.init(Mutex::new(board.imu_resources));
     let mic_resources = MIC_RESOURCES.init(Mutex::new(board.mic));
+    let vsys_sense = VSYS_SENSE_RESOURCES.init(Mutex::new(board.vsys_sense));
+    let _ = VSYS_SENSE.init(vsys_sense);
 
     spawner.must_spawn(watchdog_task(board.wdt));
 
     Timer::after_millis(50).await;
 
-    use npm1300::{
-        charger::ChargerTerminationVoltage,
-        gpios::{Gpio, GpioConfigBuilder, GpioMode, GpioPolarity},
-        ldsw::LdoVoltage,
-        sysreg::VbusInCurrentLimit,
-        Ldsw1Ldosel, Ldsw1Softstartdisable, Ldsw1Softstartsel,
-        NtcThermistorType, VsysThreshold, NPM1300,
-    };
-
     #[cfg(feature = "sr6")]
     // Acquire shared bus handle - configures the bus if needed.
     let handle = i2c_bus_manager.acquire().await.unwrap();
     #[cfg(feature = "sr6")]
-    let mut npm1300 = NPM1300::new(
-        embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice::new(
-            handle.bus(),
-        ),
-        embassy_time::Delay,
-    );
-    #[cfg(not(feature = "sr6"))]
     let mut npm1300 =
-        NPM1300::new(board.pmic_bus_resources.get_bus(), embassy_time::Delay);
+        NPM1300::new(I2cDevice::new(handle.bus()), embassy_time::Delay);
+    #[cfg(feature = "sr6")]
+    let mut usb_c_sense = UsbCSense::new(I2cDevice::new(handle.bus()));
+    #[cfg(not(feature = "sr6"))]
+    let mut npm1300 = pmic.lock().await;
 
     info!("Created nPM1300 driver!");
     Timer::after_millis(200).await;
 
-    power_manager.handle_event(PowerEvent::Enable).await;
+    // The ADS is probed below, before it is put in power-down.
+    rails::acquire(RailUser::Ads).await;
 
-    npm1300
-        .set_ldsw1_gpio_control(Gpio::None, GpioPolarity::NotInverted)
-        .await
-        .unwrap();
-    Timer::after_millis(200).await;
-    npm1300
-        .set_ldsw2_gpio_control(Gpio::None, GpioPolarity::NotInverted)
-        .await
-        .unwrap();
-    Timer::after_millis(200).await;
-
-    info!("Check Status...");
-    let status = npm1300.get_ldsw_status().await.unwrap();
-    info!("LDSW status: {:?}", status);
-
-    info!("Waiting 2s...");
-    Timer::after_millis(200).await;
-
-    info!("Configuring LDSW1 as Load Switch");
-    let _ = npm1300.set_ldsw1_mode(Ldsw1Ldosel::Ldsw).await;
-    let _ = npm1300
-        .configure_ldsw1_soft_start(
-            Ldsw1Softstartdisable::Noeffect,
-            Ldsw1Softstartsel::Ma50,
-        )
-        .await;
-
-    // Enable LDSW1
-    info!("Pre-charging analog frontend...");
-    let _ = npm1300.enable_ldsw1().await;
-
-    Timer::after_millis(500).await;
-
-    info!("Switching LDSW1 to LDO with 3.3V output...");
-    // Set LDO1 output voltage to 3.3V
-    let _ = npm1300.set_ldsw1_ldo_voltage(LdoVoltage::V3_3).await;
-    info!("After set_ldsw1_ldo_voltage...");
-    // Configure LDSW1 as LDO mode
-    let _ = npm1300.set_ldsw1_mode(Ldsw1Ldosel::Ldo).await;
-    info!("After set_ldsw1_mode...");
-
-    info!("Check Status...");
-    let status = npm1300.get_ldsw_status().await.unwrap();
-    info!("LDSW status: {:?}", status);
+    // On SR6 the PMIC sits on TWIM1, so the sensor rail cannot follow the
+    // bus and is claimed for good.
+    #[cfg(feature = "sr6")]
+    rails::acquire(RailUser::SensorBus).await;
 
     // Clear Charger Errors
     npm1300.clear_charger_errors().await.unwrap();
@@ -235,7 +260,11 @@ async fn main(spawner: Spawner) {
         .set_vbus_in_current_limit(VbusInCurrentLimit::MA100)
         .await
         .unwrap();
-    npm1300.set_charger_current(32).await.unwrap(); // mA
+    #[cfg(feature = "sr6")]
+    usb_c::apply_current_limit(&mut npm1300, &mut usb_c_sense).await;
+    #[cfg(not(feature = "sr6"))]
+    usb_c::apply_current_limit(&mut *npm1300, &mut usb_c_sense).await;
+    npm1300.set_charger_current(CHARGE_CURRENT_MA).await.unwrap();
     npm1300
         .configure_ntc_resistance(NtcThermistorType::Ntc10K, Some(4250.0))
         .await
@@ -267,17 +296,30 @@ async fn main(spawner: Spawner) {
         npm1300.is_power_failure_detection_enabled().await.unwrap();
     info!("Power failure detection enabled: {:?}", pofena);
 
-    let plw_config =
-        GpioConfigBuilder::new().mode(GpioMode::GpoPowerLossWarning).build();
-    npm1300.configure_gpio(1, plw_config).await.unwrap();
-    npm1300.set_vsys_threshold(VsysThreshold::V32).await.unwrap();
-    npm1300.enable_power_failure_detection(true).await.unwrap();
+    #[cfg(feature = "sr6")]
+    {
+        use npm1300::{
+            gpios::{GpioConfigBuilder, GpioMode},
+            VsysThreshold,
+        };
+        let plw_config = GpioConfigBuilder::new()
+            .mode(GpioMode::GpoPowerLossWarning)
+            .build();
+        npm1300.configure_gpio(1, plw_config).await.unwrap();
+        npm1300.set_vsys_threshold(VsysThreshold::V32).await.unwrap();
+        npm1300.enable_power_failure_detection(true).await.unwrap();
+    }
+    #[cfg(not(feature = "sr6"))]
+    pmic_irq::configure(&mut npm1300).await;
 
     pofena = npm1300.is_power_failure_detection_enabled().await.unwrap();
     info!("Power failure detection enabled?: {:?}", pofena);
+    // Release the PMIC before the first TWIM1 acquire switches the rail on.
+    drop(npm1300);
 
-    let imu_present = probe_imu_presence(i2c_bus_manager, imu_resources).await;
-    let apds_present = probe_apds_presence(i2c_bus_manager).await;
+    let imu_present =
+        probe_imu_presence(buses.get::<ImuBus>(), imu_resources).await;
+    let apds_present = probe_apds_presence(buses.get::<ApdsBus>()).await;
     let capabilities = DeviceCapabilities {
         imu_present,
         apds_present,
@@ -292,15 +334,10 @@ async fn main(spawner: Spawner) {
 
     let ads_manager =
         AdsManager::new(spi3_bus_resources, ads_resources, app_context);
-    let imu_manager = ImuManager::new(
-        imu_present,
-        i2c_bus_manager,
-        imu_resources,
-        app_context,
-    );
-    let apds_manager =
-        ApdsManager::new(apds_present, i2c_bus_manager, app_context);
-    let haptic_manager = HapticManager::new(i2c_bus_manager, app_context);
+    let imu_manager =
+        ImuManager::new(imu_present, buses, imu_resources, app_context);
+    let apds_manager = ApdsManager::new(apds_present, buses, app_context);
+    let haptic_manager = HapticManager::new(buses, app_context);
     let mic_manager = MicManager::new(mic_resources, app_context);
     let session_manager = SessionManager::new(app_context, sd_card_resources);
 
@@ -308,15 +345,18 @@ async fn main(spawner: Spawner) {
         use embassy_nrf::gpio::{Level, Output, OutputDrive};
         Output::new(board.usbsel, Level::High, OutputDrive::Standard)
     };
+    // The button is active low with a pull-up.
+    let button_wake = sleep::WakePin::new(&*board.pwrbtn, false);
     spawner.must_spawn(orchestrate(
         receiver,
+        app_context,
         ads_manager.clone(),
         apds_manager,
         session_manager,
-        imu_manager,
+        imu_manager.clone(),
         mic_manager,
         haptic_manager,
-        power_manager,
+        button_wake,
     ));
 
     {
@@ -326,7 +366,32 @@ async fn main(spawner: Spawner) {
             .must_spawn(button_task(board.pwrbtn.into(), sender));
         context
             .low_prio_spawner
-            .must_spawn(neopix_task(board.pwm0, board.neopix.into()));
+            .must_spawn(neopix_task(board.status_led, app_context));
+        context.low_prio_spawner.must_spawn(battery_monitor_task(app_context));
+        #[cfg(not(feature = "sr6"))]
+        context.low_prio_spawner.must_spawn(pmic_irq_task(
+            board.npm_gpio,
+            usb_c_sense,
+            app_context,
+        ));
+        context.low_prio_spawner.must_spawn(low_battery_task(app_context));
+        context.low_prio_spawner.must_spawn(sd_detect_task(sd_card_resources));
+        context
+            .low_prio_spawner
+            .must_spawn(file_reader_task(sd_card_resources));
+        context.low_prio_spawner.must_spawn(thermal_task(app_context));
+        context.low_prio_spawner.must_spawn(power_profile_task());
+        context
+            .low_prio_spawner
+            .must_spawn(lead_off_monitor_task(app_context));
+        if apds_present {
+            context.low_prio_spawner.must_spawn(gesture_task(app_context));
+        }
+        if imu_present {
+            context
+                .low_prio_spawner
+                .must_spawn(motion_trigger_task(app_context, imu_manager));
+        }
 
         // Check for ADS config.
         // create a default config.
@@ -351,16 +416,25 @@ async fn main(spawner: Spawner) {
         board.usb,
         app_context,
         dfu_resources,
+        sd_card_resources,
     ));
 
     #[cfg(feature = "trouble")]
-    spawner.must_spawn(ble_run_task(sdc, app_context, dfu_resources));
+    spawner.must_spawn(ble_run_task(
+        sdc,
+        ble_seed,
+        app_context,
+        dfu_resources,
+    ));
 
     #[cfg(feature = "demo")]
     spawner.must_spawn(demo_task(sender));
 
     {
         let app_ctx = app_context.lock().await;
+        // Surface broken hardware now rather than as obscure failures once
+        // a stream is started.
+        app_ctx.event_sender.send(Event::SelfTest).await;
         if app_ctx.capabilities().imu_present {
             app_ctx.event_sender.send(ImuEvent::StartStream.into()).await;
         }
@@ -378,3 +452,15 @@ async fn main(spawner: Spawner) {
         // }
     }
 }
+
+#[cfg(not(feature = "defmt"))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    dc_mini_app::crash::record_panic(info)
+}
+
+#[cfg(not(feature = "defmt"))]
+#[cortex_m_rt::exception]
+unsafe fn HardFault(ef: &cortex_m_rt::ExceptionFrame) -> ! {
+    dc_mini_app::crash::record_hard_fault(ef)
+}
@@ -0,0 +1,114 @@
+//! Heap and stack high-water marks for the device stats.
+//!
+//! Every executor, interrupt executors included, runs on the one main
+//! stack, so a single painted region covers all tasks.
+
+use core::alloc::{GlobalAlloc, Layout};
+use embedded_alloc::LlffHeap;
+use portable_atomic::{AtomicUsize, Ordering};
+use trallocator::Trallocator;
+
+const PAINT: u32 = 0xDC5A_C0DE;
+
+/// Room left below the stack pointer while painting, for the painting
+/// code itself and any interrupt that fires meanwhile.
+const PAINT_GUARD: usize = 256;
+
+/// Allocator that also remembers the most heap ever in use.
+pub struct TrackedHeap {
+    inner: Trallocator<LlffHeap>,
+    peak: AtomicUsize,
+}
+
+impl TrackedHeap {
+    pub const fn new() -> Self {
+        Self {
+            inner: Trallocator::new(LlffHeap::empty()),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Same contract as [`LlffHeap::init`]: call once, before the first
+    /// allocation, with memory used for nothing else.
+    pub unsafe fn init(&self, start: usize, size: usize) {
+        self.inner.borrow().init(start, size)
+    }
+
+    /// Bytes currently allocated.
+    pub fn usage(&self) -> usize {
+        self.inner.usage()
+    }
+
+    /// Most bytes allocated at once since boot.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+unsafe impl GlobalAlloc for TrackedHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        self.peak.fetch_max(self.inner.usage(), Ordering::Relaxed);
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(
+        &self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        let ptr = self.inner.realloc(ptr, layout, new_size);
+        self.peak.fetch_max(self.inner.usage(), Ordering::Relaxed);
+        ptr
+    }
+}
+
+/// Lowest address the stack may grow to and the address it starts at.
+fn stack_bounds() -> (usize, usize) {
+    extern "C" {
+        static __sheap: u32;
+        static _stack_start: u32;
+    }
+    unsafe {
+        (&__sheap as *const u32 as usize, &_stack_start as *const u32 as usize)
+    }
+}
+
+/// Bytes set aside for the stack.
+pub fn stack_size() -> usize {
+    let (bottom, top) = stack_bounds();
+    top - bottom
+}
+
+/// Fills the unused part of the stack with a known pattern. Call once,
+/// early in `main`.
+pub fn paint_stack() {
+    let (bottom, _) = stack_bounds();
+    let limit = cortex_m::register::msp::read() as usize - PAINT_GUARD;
+    let mut word = bottom as *mut u32;
+    while (word as usize) < limit {
+        // Safety: the region below the live stack is not in use.
+        unsafe {
+            word.write_volatile(PAINT);
+            word = word.add(1);
+        }
+    }
+}
+
+/// Bytes of stack that have never been used since [`paint_stack`].
+pub fn stack_margin() -> usize {
+    let (bottom, top) = stack_bounds();
+    let mut word = bottom as *const u32;
+    // Safety: reads stay within the stack region.
+    while (word as usize) < top && unsafe { word.read_volatile() } == PAINT {
+        word = unsafe { word.add(1) };
+    }
+    word as usize - bottom
+}
@@ -0,0 +1,53 @@
+//! Self-test of the board peripherals, run once at boot and on request.
+//!
+//! The orchestrator runs each manager's check in turn on
+//! [`Event::SelfTest`](crate::events::Event::SelfTest), records the
+//! resulting [`BoardHealth`] and signals the assembled report on
+//! [`SELF_TEST_SIG`].
+
+use dc_mini_icd::{
+    BoardHealth, SelfTestOutcome, SelfTestReport, SelfTestResult,
+};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use portable_atomic::{AtomicU16, Ordering};
+
+pub static SELF_TEST_SIG: Signal<CriticalSectionRawMutex, SelfTestReport> =
+    Signal::new();
+
+/// Sentinel for a self-test that has not finished yet.
+const NOT_RUN: u16 = u16::MAX;
+
+static BOARD_HEALTH: AtomicU16 = AtomicU16::new(NOT_RUN);
+
+/// Stores and returns the health from `report`.
+pub fn record_health(report: &SelfTestReport) -> BoardHealth {
+    let health = BoardHealth::from_report(report);
+    BOARD_HEALTH.store(health.0 as u16, Ordering::Relaxed);
+    health
+}
+
+/// Health from the last self-test, if one has finished.
+pub fn board_health() -> Option<BoardHealth> {
+    let bits = BOARD_HEALTH.load(Ordering::Relaxed);
+    (bits != NOT_RUN).then(|| BoardHealth(bits as u8))
+}
+
+pub fn pass(message: &str) -> SelfTestResult {
+    result(SelfTestOutcome::Pass, message)
+}
+
+pub fn fail(message: &str) -> SelfTestResult {
+    result(SelfTestOutcome::Fail, message)
+}
+
+pub fn skipped(message: &str) -> SelfTestResult {
+    result(SelfTestOutcome::Skipped, message)
+}
+
+fn result(outcome: SelfTestOutcome, message: &str) -> SelfTestResult {
+    SelfTestResult {
+        outcome,
+        message: heapless::String::try_from(message).unwrap_or_default(),
+    }
+}
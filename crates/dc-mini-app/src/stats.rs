@@ -0,0 +1,170 @@
+use dc_mini_icd::{
+    AdsConsumer, AdsDropCount, DeviceStats, FaultKind, MonitoredTask,
+    ResetReason, TaskHeartbeat, MAX_ADS_CONSUMERS, MAX_MONITORED_TASKS,
+};
+use embassy_time::{Duration, Instant};
+use portable_atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+const TASKS: [MonitoredTask; 6] = [
+    MonitoredTask::Orchestrator,
+    MonitoredTask::Ads,
+    MonitoredTask::Imu,
+    MonitoredTask::Apds,
+    MonitoredTask::Mic,
+    MonitoredTask::Session,
+];
+
+const ADS_CONSUMERS: [AdsConsumer; 3] =
+    [AdsConsumer::Usb, AdsConsumer::Ble, AdsConsumer::Session];
+
+/// Dropped ADS samples (100 ms at 250 SPS) that raise a fault.
+const ADS_DROP_FAULT_THRESHOLD: u32 = 25;
+
+/// Sentinel for tasks that have not reported yet.
+const NEVER: u64 = u64::MAX;
+/// Heartbeat timeout of tasks the watchdog ignores.
+const UNWATCHED: u32 = 0;
+
+static HEARTBEATS: [AtomicU64; TASKS.len()] =
+    [const { AtomicU64::new(NEVER) }; TASKS.len()];
+/// Longest gap between heartbeats, in ms, before a watched task counts as
+/// stalled.
+static TIMEOUTS: [AtomicU32; TASKS.len()] =
+    [const { AtomicU32::new(UNWATCHED) }; TASKS.len()];
+static EVENT_QUEUE_HIGH_WATER: AtomicUsize = AtomicUsize::new(0);
+static RESET_REASON: AtomicU32 = AtomicU32::new(0);
+static ADS_DROPS: [AtomicU32; ADS_CONSUMERS.len()] =
+    [const { AtomicU32::new(0) }; ADS_CONSUMERS.len()];
+/// Value of `ADS_DROPS` when the last fault was raised.
+static ADS_DROPS_REPORTED: [AtomicU32; ADS_CONSUMERS.len()] =
+    [const { AtomicU32::new(0) }; ADS_CONSUMERS.len()];
+
+/// Records that `task` is alive.
+pub fn heartbeat(task: MonitoredTask) {
+    HEARTBEATS[task as usize]
+        .store(Instant::now().as_millis(), Ordering::Relaxed);
+}
+
+/// Watch on a task's heartbeats, ended when dropped.
+pub struct HeartbeatWatch(MonitoredTask);
+
+impl Drop for HeartbeatWatch {
+    fn drop(&mut self) {
+        TIMEOUTS[self.0 as usize].store(UNWATCHED, Ordering::Relaxed);
+    }
+}
+
+/// Stops the watchdog being fed whenever `task` goes longer than
+/// `timeout` without a [`heartbeat`]. Counts as a heartbeat itself.
+pub fn watch_heartbeat(
+    task: MonitoredTask,
+    timeout: Duration,
+) -> HeartbeatWatch {
+    heartbeat(task);
+    let timeout_ms = timeout.as_millis().clamp(1, u32::MAX as u64) as u32;
+    TIMEOUTS[task as usize].store(timeout_ms, Ordering::Relaxed);
+    HeartbeatWatch(task)
+}
+
+/// The first watched task overdue for a heartbeat, if any.
+pub fn stalled_task() -> Option<MonitoredTask> {
+    let now = Instant::now().as_millis();
+    TASKS
+        .iter()
+        .zip(HEARTBEATS.iter().zip(TIMEOUTS.iter()))
+        .find(|(_, (last, timeout))| {
+            let timeout = timeout.load(Ordering::Relaxed);
+            let last = last.load(Ordering::Relaxed);
+            timeout != UNWATCHED
+                && last != NEVER
+                && now.saturating_sub(last) > timeout as u64
+        })
+        .map(|(task, _)| *task)
+}
+
+/// Inverse of `task as usize`.
+pub(crate) fn monitored_task(index: usize) -> Option<MonitoredTask> {
+    TASKS.get(index).copied()
+}
+
+/// Tracks the deepest event queue backlog.
+pub fn record_event_queue_depth(depth: usize) {
+    EVENT_QUEUE_HIGH_WATER.fetch_max(depth, Ordering::Relaxed);
+}
+
+/// Counts ADS samples that `consumer` missed because it fell behind, and
+/// reports a fault for every [`ADS_DROP_FAULT_THRESHOLD`] of them.
+pub fn record_ads_drops(consumer: AdsConsumer, dropped: u64) {
+    let idx = consumer as usize;
+    let dropped = dropped.min(u32::MAX as u64) as u32;
+    let total = ADS_DROPS[idx]
+        .fetch_add(dropped, Ordering::Relaxed)
+        .saturating_add(dropped);
+    let reported = ADS_DROPS_REPORTED[idx].load(Ordering::Relaxed);
+    if total.wrapping_sub(reported) >= ADS_DROP_FAULT_THRESHOLD {
+        ADS_DROPS_REPORTED[idx].store(total, Ordering::Relaxed);
+        let source = match consumer {
+            AdsConsumer::Session => Some(MonitoredTask::Session),
+            AdsConsumer::Usb | AdsConsumer::Ble => None,
+        };
+        crate::faults::report(FaultKind::FifoOverflow, source);
+    }
+}
+
+/// Latches and clears the `RESETREAS` register. Call once at boot.
+pub fn capture_reset_reason() {
+    let power = embassy_nrf::pac::POWER;
+    let bits = power.resetreas().read().0;
+    // Bits are cleared by writing 1 to them.
+    power.resetreas().write(|w| w.0 = bits);
+    RESET_REASON.store(bits, Ordering::Relaxed);
+}
+
+pub fn reset_reason() -> ResetReason {
+    match RESET_REASON.load(Ordering::Relaxed) {
+        0 => ResetReason::PowerOn,
+        0x0000_0001 => ResetReason::Pin,
+        0x0000_0002 => ResetReason::Watchdog,
+        0x0000_0004 => ResetReason::SoftReset,
+        0x0000_0008 => ResetReason::Lockup,
+        0x0001_0000 => ResetReason::WakeFromOff,
+        0x0004_0000 => ResetReason::Debug,
+        bits => ResetReason::Other(bits),
+    }
+}
+
+/// Snapshot of the runtime statistics.
+pub fn device_stats() -> DeviceStats {
+    let now = Instant::now().as_millis();
+    let mut heartbeats = heapless::Vec::<_, MAX_MONITORED_TASKS>::new();
+    for (task, last) in TASKS.iter().zip(HEARTBEATS.iter()) {
+        let last = last.load(Ordering::Relaxed);
+        let age_ms = (last != NEVER)
+            .then(|| now.saturating_sub(last).min(u32::MAX as u64) as u32);
+        let _ = heartbeats.push(TaskHeartbeat { task: *task, age_ms });
+    }
+    let mut ads_drops = heapless::Vec::<_, MAX_ADS_CONSUMERS>::new();
+    for (consumer, dropped) in ADS_CONSUMERS.iter().zip(ADS_DROPS.iter()) {
+        let _ = ads_drops.push(AdsDropCount {
+            consumer: *consumer,
+            dropped: dropped.load(Ordering::Relaxed),
+        });
+    }
+
+    DeviceStats {
+        uptime_ms: now,
+        heap_used: crate::ALLOCATOR.usage() as u32,
+        heap_size: crate::HEAP_SIZE as u32,
+        heap_peak: crate::ALLOCATOR.peak() as u32,
+        stack_size: crate::memory::stack_size() as u32,
+        stack_margin: crate::memory::stack_margin() as u32,
+        board_health: crate::selftest::board_health(),
+        event_queue_high_water: EVENT_QUEUE_HIGH_WATER.load(Ordering::Relaxed)
+            as u8,
+        event_queue_capacity: crate::EVENT_CAPACITY as u8,
+        reset_reason: reset_reason(),
+        heartbeats,
+        ads_drops,
+        boot_metrics: crate::tasks::dfu::boot_metrics(),
+    }
+}
@@ -0,0 +1,56 @@
+//! Overall device state, as shown on the status LED.
+
+use crate::tasks::ads::ADS_WATCH;
+use crate::tasks::mic::MIC_WATCH;
+use crate::tasks::power_control::sleep::ble_connected;
+use crate::tasks::session::session_active;
+use portable_atomic::{AtomicBool, Ordering};
+
+static BLE_ADVERTISING: AtomicBool = AtomicBool::new(false);
+
+/// What the device is doing, most important first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SystemState {
+    /// The last self-test found a failed subsystem.
+    Error,
+    Recording,
+    /// ADS or mic data is streaming to a host.
+    Streaming,
+    /// A BLE central is connected.
+    Connected,
+    Advertising,
+    Idle,
+}
+
+impl SystemState {
+    pub fn current() -> Self {
+        if crate::selftest::board_health().is_some_and(|h| !h.is_healthy()) {
+            SystemState::Error
+        } else {
+            Self::activity()
+        }
+    }
+
+    /// What the device is doing, regardless of the self-test result.
+    pub fn activity() -> Self {
+        if session_active() {
+            SystemState::Recording
+        } else if ADS_WATCH.try_get().unwrap_or(false)
+            || MIC_WATCH.try_get().unwrap_or(false)
+        {
+            SystemState::Streaming
+        } else if ble_connected() {
+            SystemState::Connected
+        } else if BLE_ADVERTISING.load(Ordering::Relaxed) {
+            SystemState::Advertising
+        } else {
+            SystemState::Idle
+        }
+    }
+}
+
+/// Called by the BLE task around each advertising run.
+pub fn set_ble_advertising(advertising: bool) {
+    BLE_ADVERTISING.store(advertising, Ordering::Relaxed);
+}
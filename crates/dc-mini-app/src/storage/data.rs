@@ -1,5 +1,10 @@
 use super::{Setting, StorageKey};
-use dc_mini_icd::{AdsConfig, ApdsConfig, ImuConfig, MicConfig, SessionId};
+use dc_mini_icd::{
+    AdsConfig, ApdsConfig, GestureConfig, HapticConfig, ImuConfig,
+    LowBatteryConfig, MicConfig, Montage, MotionTriggerConfig, NeopixelConfig,
+    Nickname, ProfileInfo, SessionId, SessionMetadata,
+};
+use embedded_sdmmc::{BlockDevice, File, TimeSource};
 use postcard_schema::Schema;
 use sequential_storage::map::SerializationError;
 use serde::{Deserialize, Serialize};
@@ -15,23 +20,36 @@ pub enum StorageData {
     HapticConfig(HapticConfig),
     NeopixelConfig(NeopixelConfig),
     ApdsConfig(ApdsConfig),
+    GestureConfig(GestureConfig),
+    MotionTrigger(MotionTriggerConfig),
     MicConfig(MicConfig),
+    SessionMetadata(SessionMetadata),
+    Nickname(Nickname),
+    LowBatteryConfig(LowBatteryConfig),
+    BleBonds(BleBonds),
+    ProfileInfo(ProfileInfo),
+    Montage(Montage),
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct HapticConfig {
-    pub pattern: u32,
-    pub intensity: u8,
-    pub duration: u16,
-}
+/// Most BLE centrals bonded at once; one per connection slot.
+pub const BLE_BONDS_MAX: usize = 2;
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+/// Bonded BLE centrals, oldest first.
+pub type BleBonds = heapless::Vec<BleBond, BLE_BONDS_MAX>;
+
+/// Keys of a bonded BLE central, so an encrypted link can be resumed
+/// after a reset without pairing again.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Schema)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct NeopixelConfig {
-    pub r: u32,
-    pub g: u32,
-    pub b: u32,
+pub struct BleBond {
+    /// Identity address of the central.
+    pub address: [u8; 6],
+    /// Identity resolving key, if the central uses a private address.
+    pub irk: Option<[u8; 16]>,
+    /// Long term key, little-endian.
+    pub ltk: [u8; 16],
+    /// Whether pairing was protected against man-in-the-middle.
+    pub authenticated: bool,
 }
 
 /// Abstraction for storage keys based on profiles or global keys.
@@ -49,6 +67,11 @@ impl KeyedEnum for StorageData {
             StorageData::CurrentProfile(_) => {
                 StorageKey::CurrentProfile.into()
             }
+            StorageData::Nickname(_) => StorageKey::Nickname.into(),
+            StorageData::LowBatteryConfig(_) => {
+                StorageKey::LowBatteryConfig.into()
+            }
+            StorageData::BleBonds(_) => StorageKey::BleBonds.into(),
             StorageData::AdsConfig(_) => StorageKey::UserProfile {
                 profile_id: active_profile,
                 setting: Setting::AdsConfig,
@@ -74,6 +97,16 @@ impl KeyedEnum for StorageData {
                 setting: Setting::ApdsConfig,
             }
             .into(),
+            StorageData::GestureConfig(_) => StorageKey::UserProfile {
+                profile_id: active_profile,
+                setting: Setting::GestureConfig,
+            }
+            .into(),
+            StorageData::MotionTrigger(_) => StorageKey::UserProfile {
+                profile_id: active_profile,
+                setting: Setting::MotionTrigger,
+            }
+            .into(),
             StorageData::SessionId(_) => StorageKey::UserProfile {
                 profile_id: active_profile,
                 setting: Setting::SessionId,
@@ -84,6 +117,21 @@ impl KeyedEnum for StorageData {
                 setting: Setting::MicConfig,
             }
             .into(),
+            StorageData::SessionMetadata(_) => StorageKey::UserProfile {
+                profile_id: active_profile,
+                setting: Setting::SessionMetadata,
+            }
+            .into(),
+            StorageData::ProfileInfo(_) => StorageKey::UserProfile {
+                profile_id: active_profile,
+                setting: Setting::ProfileInfo,
+            }
+            .into(),
+            StorageData::Montage(_) => StorageKey::UserProfile {
+                profile_id: active_profile,
+                setting: Setting::Montage,
+            }
+            .into(),
         }
     }
 }
@@ -107,3 +155,37 @@ impl<'a> sequential_storage::map::Value<'a> for StorageData {
             .map_err(|_| SerializationError::BufferTooSmall)
     }
 }
+
+/// Destination for the container bytes of a recording session.
+///
+/// Sessions go to a FAT file by default; the `raw-log` feature writes them
+/// to a power-fail-safe block log instead.
+#[allow(async_fn_in_trait)]
+pub trait SessionSink {
+    type Error;
+
+    /// Appends `bytes` to the session.
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+
+    /// Hands everything written so far to the card.
+    async fn flush(&mut self) -> Result<(), Self::Error>;
+}
+
+impl<
+        D: BlockDevice,
+        T: TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > SessionSink for File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    type Error = embedded_sdmmc::Error<D::Error>;
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        File::write(self, bytes)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        File::flush(self)
+    }
+}
@@ -3,6 +3,9 @@
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum StorageKey {
     CurrentProfile,
+    Nickname,
+    LowBatteryConfig,
+    BleBonds,
     UserProfile { profile_id: u8, setting: Setting },
 }
 
@@ -17,6 +20,11 @@ pub enum Setting {
     ApdsConfig,
     SessionId,
     MicConfig,
+    SessionMetadata,
+    GestureConfig,
+    MotionTrigger,
+    ProfileInfo,
+    Montage,
 }
 
 impl Setting {
@@ -29,6 +37,11 @@ impl Setting {
             Setting::ApdsConfig => 0x04,
             Setting::SessionId => 0x05,
             Setting::MicConfig => 0x06,
+            Setting::SessionMetadata => 0x07,
+            Setting::GestureConfig => 0x08,
+            Setting::MotionTrigger => 0x09,
+            Setting::ProfileInfo => 0x0A,
+            Setting::Montage => 0x0B,
         }
     }
 }
@@ -37,6 +50,10 @@ impl Into<u16> for StorageKey {
     fn into(self) -> u16 {
         match self {
             StorageKey::CurrentProfile => 0x00,
+            StorageKey::Nickname => 0x01,
+            StorageKey::LowBatteryConfig => 0x02,
+            // 0x03 held a single bond before.
+            StorageKey::BleBonds => 0x04,
             StorageKey::UserProfile { profile_id, setting } => {
                 const BASE: u16 = 0x0100;
                 let profile_offset = profile_id as u16 * 0x10;
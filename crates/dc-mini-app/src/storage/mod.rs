@@ -3,6 +3,6 @@ pub mod keys;
 pub mod profile_manager;
 
 // Re-export commonly used items for convenience
-pub use data::{HapticConfig, NeopixelConfig, StorageData};
+pub use data::{BleBond, BleBonds, SessionSink, StorageData, BLE_BONDS_MAX};
 pub use keys::{Setting, StorageKey};
 pub use profile_manager::ProfileManager;
@@ -1,6 +1,10 @@
 use super::data::*;
 use super::keys::{Setting, StorageKey};
-use dc_mini_icd::{AdsConfig, ApdsConfig, ImuConfig, MicConfig, SessionId};
+use dc_mini_icd::{
+    AdsConfig, ApdsConfig, GestureConfig, HapticConfig, ImuConfig,
+    LowBatteryConfig, MicConfig, Montage, MotionTriggerConfig, NeopixelConfig,
+    Nickname, ProfileInfo, SessionId, SessionMetadata,
+};
 use embedded_storage_async::nor_flash::NorFlash;
 use sequential_storage::cache::NoCache;
 use sequential_storage::map::{MapConfig, MapStorage};
@@ -47,13 +51,20 @@ pub struct ProfileManager<Flash: NorFlash, const N: usize> {
     map: MapStorage<u16, Flash, NoCache>,
     buffer: [u8; N],
     current_profile: u8,
+    nickname: Option<Nickname>,
+    low_battery_config: Option<LowBatteryConfig>,
+    ble_bonds: Option<BleBonds>,
     session_id: Option<SessionId>,
+    session_metadata: Option<SessionMetadata>,
     ads_config: Option<AdsConfig>,
     imu_config: Option<ImuConfig>,
     haptic_config: Option<HapticConfig>,
     neopixel_config: Option<NeopixelConfig>,
     apds_config: Option<ApdsConfig>,
+    gesture_config: Option<GestureConfig>,
+    motion_trigger: Option<MotionTriggerConfig>,
     mic_config: Option<MicConfig>,
+    montage: Option<Montage>,
 }
 
 impl<Flash: NorFlash, const N: usize> ProfileManager<Flash, N> {
@@ -76,13 +87,20 @@ impl<Flash: NorFlash, const N: usize> ProfileManager<Flash, N> {
             map,
             buffer: [0; N],
             current_profile: 0,
+            nickname: None,
+            low_battery_config: None,
+            ble_bonds: None,
             session_id: None,
+            session_metadata: None,
             ads_config: None,
             imu_config: None,
             haptic_config: None,
             neopixel_config: None,
             apds_config: None,
+            gesture_config: None,
+            motion_trigger: None,
             mic_config: None,
+            montage: None,
         };
 
         manager.current_profile = match embassy_futures::block_on(
@@ -104,11 +122,20 @@ impl<Flash: NorFlash, const N: usize> ProfileManager<Flash, N> {
     }
 
     /// Saves data to persistent storage.
+    ///
+    /// The map appends every store and erases a page once it fills up, so
+    /// a value that is already stored is not written again; hosts tend to
+    /// send the whole config back when only part of it changed.
     async fn save(
         &mut self,
         key: u16,
         value: &StorageData,
     ) -> Result<(), Error<Flash::Error>> {
+        if let Ok(Some(stored)) = self.load(key).await {
+            if &stored == value {
+                return Ok(());
+            }
+        }
         self.map.store_item(&mut self.buffer, &key, value).await
     }
 
@@ -142,6 +169,123 @@ impl<Flash: NorFlash, const N: usize> ProfileManager<Flash, N> {
         }
     }
 
+    /// Device nickname; shared by all profiles.
+    pub async fn get_nickname(&mut self) -> Option<&Nickname> {
+        if self.nickname.is_none() {
+            if let Some(StorageData::Nickname(nickname)) =
+                self.load(StorageKey::Nickname.into()).await.ok()?
+            {
+                self.nickname = Some(nickname);
+            }
+        }
+        self.nickname.as_ref()
+    }
+
+    pub async fn set_nickname(
+        &mut self,
+        nickname: Nickname,
+    ) -> Result<(), Error<Flash::Error>> {
+        let data = StorageData::Nickname(nickname);
+        self.save(StorageKey::Nickname.into(), &data).await?;
+        if let StorageData::Nickname(nickname) = data {
+            self.nickname = Some(nickname);
+        }
+        Ok(())
+    }
+
+    /// Name and description of `profile`, which need not be the current
+    /// one. Not cached, since only the host asks for it.
+    pub async fn get_profile_info(&mut self, profile: u8) -> ProfileInfo {
+        let key = StorageKey::UserProfile {
+            profile_id: profile,
+            setting: Setting::ProfileInfo,
+        }
+        .into();
+        match self.load(key).await {
+            Ok(Some(StorageData::ProfileInfo(info))) => info,
+            _ => ProfileInfo::default(),
+        }
+    }
+
+    pub async fn set_profile_info(
+        &mut self,
+        profile: u8,
+        info: ProfileInfo,
+    ) -> Result<(), Error<Flash::Error>> {
+        let key = StorageKey::UserProfile {
+            profile_id: profile,
+            setting: Setting::ProfileInfo,
+        }
+        .into();
+        self.save(key, &StorageData::ProfileInfo(info)).await
+    }
+
+    /// Low-battery shutdown thresholds; shared by all profiles.
+    pub async fn get_low_battery_config(&mut self) -> LowBatteryConfig {
+        if self.low_battery_config.is_none() {
+            if let Ok(Some(StorageData::LowBatteryConfig(config))) =
+                self.load(StorageKey::LowBatteryConfig.into()).await
+            {
+                self.low_battery_config = Some(config);
+            }
+        }
+        self.low_battery_config.unwrap_or_default()
+    }
+
+    pub async fn set_low_battery_config(
+        &mut self,
+        config: LowBatteryConfig,
+    ) -> Result<(), Error<Flash::Error>> {
+        let data = StorageData::LowBatteryConfig(config);
+        self.save(StorageKey::LowBatteryConfig.into(), &data).await?;
+        self.low_battery_config = Some(config);
+        Ok(())
+    }
+
+    /// Bonds with the paired BLE centrals; shared by all profiles.
+    pub async fn get_ble_bonds(&mut self) -> &[BleBond] {
+        if self.ble_bonds.is_none() {
+            let bonds = match self.load(StorageKey::BleBonds.into()).await {
+                Ok(Some(StorageData::BleBonds(bonds))) => bonds,
+                _ => BleBonds::new(),
+            };
+            self.ble_bonds = Some(bonds);
+        }
+        self.ble_bonds.as_deref().unwrap_or_default()
+    }
+
+    /// Stores `bond`, replacing an earlier bond with the same central.
+    /// Returns `false` without storing if every bond slot is taken.
+    pub async fn add_ble_bond(
+        &mut self,
+        bond: BleBond,
+    ) -> Result<bool, Error<Flash::Error>> {
+        let mut bonds = BleBonds::from_slice(self.get_ble_bonds().await)
+            .unwrap_or_default();
+        if let Some(old) = bonds.iter_mut().find(|b| b.address == bond.address)
+        {
+            *old = bond;
+        } else if bonds.push(bond).is_err() {
+            return Ok(false);
+        }
+        let data = StorageData::BleBonds(bonds);
+        self.save(StorageKey::BleBonds.into(), &data).await?;
+        if let StorageData::BleBonds(bonds) = data {
+            self.ble_bonds = Some(bonds);
+        }
+        Ok(true)
+    }
+
+    /// Forgets every bonded central, so new ones can pair.
+    pub async fn clear_ble_bonds(
+        &mut self,
+    ) -> Result<(), Error<Flash::Error>> {
+        let data = StorageData::BleBonds(BleBonds::new());
+        self.save(StorageKey::BleBonds.into(), &data).await?;
+        self.ble_bonds = Some(BleBonds::new());
+        Ok(())
+    }
+
     /// Switch the active profile and reload any previously loaded settings.
     pub async fn switch_profile(
         &mut self,
@@ -158,6 +302,10 @@ impl<Flash: NorFlash, const N: usize> ProfileManager<Flash, N> {
             self.session_id = None;
             self.get_session_id().await;
         }
+        if self.session_metadata.is_some() {
+            self.session_metadata = None;
+            self.get_session_metadata().await;
+        }
         if self.ads_config.is_some() {
             self.ads_config = None;
             self.get_ads_config().await;
@@ -178,18 +326,34 @@ impl<Flash: NorFlash, const N: usize> ProfileManager<Flash, N> {
             self.apds_config = None;
             self.get_apds_config().await;
         }
+        if self.gesture_config.is_some() {
+            self.gesture_config = None;
+            self.get_gesture_config().await;
+        }
+        if self.motion_trigger.is_some() {
+            self.motion_trigger = None;
+            self.get_motion_trigger().await;
+        }
         if self.mic_config.is_some() {
             self.mic_config = None;
             self.get_mic_config().await;
         }
+        if self.montage.is_some() {
+            self.montage = None;
+            self.get_montage().await;
+        }
         Ok(())
     }
 
     config_accessors!(session_id, SessionId, SessionId);
+    config_accessors!(session_metadata, SessionMetadata, SessionMetadata);
     config_accessors!(ads_config, AdsConfig, AdsConfig);
     config_accessors!(imu_config, ImuConfig, ImuConfig);
     config_accessors!(haptic_config, HapticConfig, HapticConfig);
     config_accessors!(neopixel_config, NeopixelConfig, NeopixelConfig);
     config_accessors!(apds_config, ApdsConfig, ApdsConfig);
+    config_accessors!(gesture_config, GestureConfig, GestureConfig);
+    config_accessors!(motion_trigger, MotionTrigger, MotionTriggerConfig);
     config_accessors!(mic_config, MicConfig, MicConfig);
+    config_accessors!(montage, Montage, Montage);
 }
@@ -1,20 +1,61 @@
 use super::*;
 use crate::prelude::*;
+use crate::selftest;
+use crate::tasks::power_control::rails::{self, RailUser};
+use dc_mini_icd::{ImpedanceReport, SelfTestResult};
 use derive_more::From;
 use embassy_executor::SendSpawner;
 use embassy_sync::mutex::Mutex;
-use portable_atomic::Ordering;
-use tasks::ads_pwdn_task;
+use embassy_time::with_timeout;
+use portable_atomic::{AtomicU8, Ordering};
+use tasks::{ads_impedance_check, ads_pwdn_task, ads_self_test};
+
+/// Users of the ADS acquisition. It runs while any of them holds a claim,
+/// so each can start and stop without cutting off the others.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AdsClaim {
+    Usb,
+    Ble,
+    Session,
+    /// Started on the device itself, by a gesture or the demo.
+    Local,
+}
+
+impl AdsClaim {
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Claims currently held, one bit per [`AdsClaim`].
+static ADS_CLAIMS: AtomicU8 = AtomicU8::new(0);
+
+/// Whether `claim` holds the acquisition.
+pub fn ads_claimed(claim: AdsClaim) -> bool {
+    ADS_CLAIMS.load(Ordering::SeqCst) & claim.bit() != 0
+}
 
 #[derive(Debug, From)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum AdsEvent {
-    StartStream,
-    StopStream,
+    /// Claims the acquisition, starting it if no one else holds it.
+    #[from(skip)]
+    StartStream(AdsClaim),
+    /// Releases a claim, stopping the acquisition with the last one.
+    #[from(skip)]
+    StopStream(AdsClaim),
+    /// Stops the acquisition whoever holds it, e.g. before powering off.
+    StopAll,
     ResetConfig,
     PrintConfig,
     ConfigChanged,
+    /// Reloads the montage of the current profile.
+    MontageChanged,
     ManualRecord,
+    /// Measures electrode impedance and signals the report on
+    /// [`IMPEDANCE_SIG`].
+    ImpedanceCheck,
 }
 
 #[derive(Debug)]
@@ -23,12 +64,13 @@ pub enum AdsEventError {
     InvalidConversion(u8),
 }
 
+/// Decodes a command written to the BLE ADS service.
 impl TryFrom<u8> for AdsEvent {
     type Error = AdsEventError;
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            0 => Ok(AdsEvent::StartStream),
-            1 => Ok(AdsEvent::StopStream),
+            0 => Ok(AdsEvent::StartStream(AdsClaim::Ble)),
+            1 => Ok(AdsEvent::StopStream(AdsClaim::Ble)),
             2 => Ok(AdsEvent::ResetConfig),
             3 => Ok(AdsEvent::PrintConfig),
             _ => Err(AdsEventError::InvalidConversion(value)),
@@ -72,14 +114,121 @@ impl AdsManager {
         total_channels
     }
 
+    /// Runs the ADS self-test, waking the ADS from power-down if needed.
+    pub async fn self_test(&self) -> SelfTestResult {
+        if ADS_MEAS.load(Ordering::SeqCst) {
+            return selftest::skipped("ADS streaming");
+        }
+
+        let was_ads_pwdn = ADS_PWDN.load(Ordering::SeqCst);
+        if was_ads_pwdn {
+            self.wake().await;
+        }
+        let result = ads_self_test(self.bus, self.ads).await;
+        if was_ads_pwdn {
+            let app_ctx = self.app.lock().await;
+            self.power_down(app_ctx.low_prio_spawner);
+        }
+        result
+    }
+
+    /// Measures electrode impedance with the current profile's config.
+    ///
+    /// A running stream is paused for the measurement and restarted with
+    /// the same config afterwards; otherwise the ADS is returned to
+    /// power-down if it was there.
+    pub async fn impedance_check(&self) -> ImpedanceReport {
+        let config = {
+            let mut app_ctx = self.app.lock().await;
+            app_ctx.profile_manager.get_ads_config().await.cloned()
+        };
+        let Some(config) = config else {
+            return impedance_error("No ADS config");
+        };
+
+        let was_streaming = ADS_MEAS.load(Ordering::SeqCst);
+        if was_streaming {
+            ADS_MEAS_SIG.signal(None);
+            let stopped = with_timeout(Duration::from_secs(1), async {
+                while ADS_MEAS.load(Ordering::SeqCst) {
+                    Timer::after_millis(10).await;
+                }
+            })
+            .await;
+            if stopped.is_err() {
+                return impedance_error("ADS stream did not stop");
+            }
+        }
+        let was_ads_pwdn = ADS_PWDN.load(Ordering::SeqCst);
+        if was_ads_pwdn {
+            self.wake().await;
+        }
+
+        let report = ads_impedance_check(self.bus, self.ads, &config).await;
+
+        let app_ctx = self.app.lock().await;
+        if was_streaming {
+            app_ctx
+                .high_prio_spawner
+                .must_spawn(ads_measure_task(self.bus, self.ads, config));
+        } else if was_ads_pwdn {
+            self.power_down(app_ctx.low_prio_spawner);
+        }
+        report
+    }
+
+    /// Claims the AFE rail and brings the ADS out of power-down.
+    async fn wake(&self) {
+        rails::acquire(RailUser::Ads).await;
+        if ADS_PWDN.load(Ordering::SeqCst) {
+            ADS_PWDN_SIG.signal(());
+        }
+    }
+
+    /// Hands the current profile's montage to the measure task.
+    async fn update_montage(&self) {
+        let montage = {
+            let mut app_ctx = self.app.lock().await;
+            app_ctx.profile_manager.get_montage().await.cloned()
+        };
+        MONTAGE_WATCH.sender().send(montage.unwrap_or_default());
+    }
+
+    async fn start_stream(&self) {
+        self.wake().await;
+        self.update_montage().await;
+        let mut app_ctx = self.app.lock().await;
+        let ads_config =
+            app_ctx.profile_manager.get_ads_config().await.unwrap().clone();
+        app_ctx
+            .high_prio_spawner
+            .must_spawn(ads_measure_task(self.bus, self.ads, ads_config));
+        app_ctx.event_sender.send(ImuEvent::StartStream.into()).await;
+        ADS_WATCH.sender().send(true);
+    }
+
+    async fn stop_stream(&self) {
+        if ADS_PWDN.load(Ordering::SeqCst) {
+            info!("Tried to power down ADS when it was already powered down.");
+            return;
+        }
+        ADS_MEAS_SIG.signal(None);
+        let app_ctx = self.app.lock().await;
+        app_ctx.event_sender.send(ImuEvent::StopStream.into()).await;
+        self.power_down(app_ctx.low_prio_spawner);
+        ADS_WATCH.sender().send(false);
+    }
+
+    /// Puts the ADS in power-down and hands back its AFE rail.
     pub fn power_down(&self, spawner: SendSpawner) {
-        // Power down the ADS on startup
         spawner.must_spawn(ads_pwdn_task(self.ads));
     }
 
     pub async fn handle_event(&self, event: AdsEvent) {
         match event {
             AdsEvent::ConfigChanged => {
+                // A profile switch brings its own montage.
+                self.update_montage().await;
                 // Handle configuration changes
                 if ADS_MEAS.load(Ordering::SeqCst) {
                     // We are streaming and need to update the active ADS config.
@@ -91,53 +240,41 @@ impl AdsManager {
                     }
                 }
             }
-            AdsEvent::StopStream => {
-                if ADS_PWDN.load(Ordering::SeqCst) {
-                    info!("Tried to power down ADS when it was already powered down.")
+            AdsEvent::MontageChanged => self.update_montage().await,
+            AdsEvent::StartStream(claim) => {
+                ADS_CLAIMS.fetch_or(claim.bit(), Ordering::SeqCst);
+                // The watch, unlike `ADS_MEAS`, stays set while the stream
+                // restarts, e.g. around an impedance check.
+                if ADS_WATCH.try_get() == Some(true) {
+                    info!("{:?} joined the running ADS stream.", claim);
                 } else {
-                    ADS_MEAS_SIG.signal(None);
-                    let app_ctx = self.app.lock().await;
-                    app_ctx
-                        .event_sender
-                        .send(ImuEvent::StopStream.into())
-                        .await;
-                    self.power_down(app_ctx.low_prio_spawner);
-                    ADS_WATCH.sender().send(false);
+                    self.start_stream().await;
                 }
             }
-            AdsEvent::StartStream => {
-                if ADS_MEAS.load(Ordering::SeqCst) {
-                    info!("Tried to start ADS stream while already running.");
+            AdsEvent::StopStream(claim) => {
+                let held =
+                    ADS_CLAIMS.fetch_and(!claim.bit(), Ordering::SeqCst);
+                if held & !claim.bit() != 0 {
+                    info!(
+                        "{:?} left, ADS stream kept for other sinks.",
+                        claim
+                    );
                 } else {
-                    if ADS_PWDN.load(Ordering::SeqCst) {
-                        ADS_PWDN_SIG.signal(());
-                    }
-                    let mut app_ctx = self.app.lock().await;
-                    let ads_config = app_ctx
-                        .profile_manager
-                        .get_ads_config()
-                        .await
-                        .unwrap()
-                        .clone();
-                    app_ctx.high_prio_spawner.must_spawn(ads_measure_task(
-                        self.bus, self.ads, ads_config,
-                    ));
-                    app_ctx
-                        .event_sender
-                        .send(ImuEvent::StartStream.into())
-                        .await;
-                    ADS_WATCH.sender().send(true);
-                };
+                    self.stop_stream().await;
+                }
+            }
+            AdsEvent::StopAll => {
+                ADS_CLAIMS.store(0, Ordering::SeqCst);
+                self.stop_stream().await;
             }
             AdsEvent::ResetConfig => {
                 if ADS_MEAS.load(Ordering::SeqCst) {
                     warn!("Not allowed to reset config while ADS streaming.");
                 }
 
-                let mut was_ads_pwdn = false;
-                if ADS_PWDN.load(Ordering::SeqCst) {
-                    ADS_PWDN_SIG.signal(());
-                    was_ads_pwdn = true;
+                let was_ads_pwdn = ADS_PWDN.load(Ordering::SeqCst);
+                if was_ads_pwdn {
+                    self.wake().await;
                 }
 
                 // Overwrite the current AdsConfig with the default.
@@ -164,32 +301,20 @@ impl AdsManager {
                 info!("PrintConfig Requested: {:?}", config);
             }
             AdsEvent::ManualRecord => {
-                let context = self.app.lock().await;
-                if ADS_MEAS.load(Ordering::SeqCst) {
-                    // Stop Recording.
-                    context
-                        .event_sender
-                        .send(AdsEvent::StopStream.into())
-                        .await;
-                    Timer::after_millis(500).await;
-                    context
-                        .event_sender
-                        .send(SessionEvent::StopRecording.into())
-                        .await;
-                    NEOPIX_CHAN.send(NeopixEvent::PowerOn).await;
+                // The session claims the ADS itself, so a running live
+                // stream is left alone.
+                let event = if session_active() {
+                    SessionEvent::StopRecording
                 } else {
-                    // Start Recording.
-                    context
-                        .event_sender
-                        .send(SessionEvent::StartRecording.into())
-                        .await;
-                    Timer::after_millis(500).await;
-                    context
-                        .event_sender
-                        .send(AdsEvent::StartStream.into())
-                        .await;
-                    NEOPIX_CHAN.send(NeopixEvent::Recording).await;
-                }
+                    SessionEvent::StartRecording
+                };
+                let context = self.app.lock().await;
+                context.event_sender.send(event.into()).await;
+            }
+            AdsEvent::ImpedanceCheck => {
+                let report = self.impedance_check().await;
+                info!("Impedance check: {:?}", report);
+                IMPEDANCE_SIG.signal(report);
             }
         }
     }
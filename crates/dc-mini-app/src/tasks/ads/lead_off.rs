@@ -0,0 +1,57 @@
+use super::LEAD_OFF_WATCH;
+use crate::prelude::*;
+use dc_mini_icd::LeadOffStatus;
+use smart_leds::colors;
+
+const ALERT_FLASHES: u32 = 6;
+const ALERT_FLASH_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Bitmask of channels that are off in `status` but were connected in
+/// `prev`.
+fn newly_off(prev: Option<&LeadOffStatus>, status: &LeadOffStatus) -> u16 {
+    let mut mask = 0;
+    for (i, ch) in status.channels.iter().enumerate() {
+        let was_off = prev
+            .and_then(|prev| prev.channels.get(i))
+            .is_some_and(|p| p.positive || p.negative);
+        if (ch.positive || ch.negative) && !was_off {
+            mask |= 1 << i;
+        }
+    }
+    mask
+}
+
+/// Warns with the haptic motor and the LED when an electrode comes off
+/// while a session is recording. Changes are written to the session by the
+/// recording itself.
+#[embassy_executor::task]
+pub async fn lead_off_monitor_task(
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) {
+    let mut receiver = unwrap!(LEAD_OFF_WATCH.receiver());
+    let mut last: Option<LeadOffStatus> = None;
+    loop {
+        let status = receiver.changed().await;
+        let off = newly_off(last.as_ref(), &status);
+        last = Some(status);
+        if off == 0 || !session_active() {
+            continue;
+        }
+
+        warn!("Electrode disconnected while recording, channels {:#x}", off);
+        let sender = app_context.lock().await.event_sender;
+        sender
+            .send(HapticEvent::Trigger(HapticTrigger::ElectrodeOff).into())
+            .await;
+        NEOPIX_CHAN
+            .send(NeopixEvent::FlashFor(
+                colors::ORANGE,
+                ALERT_FLASH_INTERVAL,
+                ALERT_FLASHES,
+                None,
+            ))
+            .await;
+        // The LED returns to the recording indication once the flash ends.
+        Timer::after(ALERT_FLASH_INTERVAL * ALERT_FLASHES).await;
+    }
+}
@@ -1,16 +1,19 @@
 pub(crate) mod config;
 pub(crate) mod events;
+mod lead_off;
 
 mod tasks; // Tasks module is private
 
 pub use config::*;
 pub use events::*;
+pub use lead_off::*;
 use tasks::*;
 
+use crate::clock::Captured;
 use crate::prelude::*;
 use ads1299::{self, AdsData};
 use alloc::sync::Arc;
-use embassy_sync::pubsub::PubSubChannel;
+use embassy_sync::pubsub::{DynSubscriber, PubSubChannel, WaitResult};
 use embassy_sync::signal::Signal;
 use embassy_sync::watch::Watch;
 use heapless::Vec;
@@ -25,18 +28,65 @@ pub(self) static ADS_MEAS_SIG: Signal<
 > = Signal::new();
 pub(self) static ADS_PWDN_SIG: Signal<CriticalSectionRawMutex, ()> =
     Signal::new();
+/// Result of the last [`AdsEvent::ImpedanceCheck`].
+pub static IMPEDANCE_SIG: Signal<CriticalSectionRawMutex, ImpedanceReport> =
+    Signal::new();
 
 pub const ADS_CAP: usize = 100;
-pub const ADS_SUBS: usize = 3;
+pub const ADS_SUBS: usize = 4; // USB, recording and one per BLE link
 pub type MutexType = CriticalSectionRawMutex;
 pub type AdsCh<T> =
     PubSubChannel<CriticalSectionRawMutex, T, ADS_CAP, ADS_SUBS, 1>;
-pub static ADS_MEAS_CH: AdsCh<Arc<Vec<ads1299::AdsData, 2>>> = AdsCh::new();
+/// One sample of every ADS device, stamped at DRDY.
+pub type AdsMeasurement = Arc<Captured<Vec<AdsData, 2>>>;
+/// Samples are published without waiting for subscribers; one that falls
+/// more than [`ADS_CAP`] samples behind loses the oldest ones instead of
+/// stalling the others. Receive with [`next_ads_sample`] so the loss is
+/// counted.
+pub static ADS_MEAS_CH: AdsCh<AdsMeasurement> = AdsCh::new();
 pub static ADS_WATCH: Watch<CriticalSectionRawMutex, bool, ADS_SUBS> =
     Watch::new();
+/// Latest lead-off state; updated on change while the ADS is measuring and
+/// cleared when it stops. When the active config leaves the comparators
+/// off, the state comes from periodic checks instead of every sample.
+pub static LEAD_OFF_WATCH: Watch<
+    CriticalSectionRawMutex,
+    LeadOffStatus,
+    ADS_SUBS,
+> = Watch::new();
+
+/// Montage of the current profile, applied to samples before they are
+/// published. Kept across stream restarts; empty passes the enabled
+/// physical channels through.
+pub static MONTAGE_WATCH: Watch<CriticalSectionRawMutex, Montage, 1> =
+    Watch::new();
+
+/// Per-channel lead-off state of a raw (unfiltered) ADS sample.
+pub(crate) fn lead_off_status(samples: &[AdsData]) -> LeadOffStatus {
+    let mut channels = heapless::Vec::new();
+    for sample in samples {
+        let pos = sample.lead_off_status_pos.bits();
+        let neg = sample.lead_off_status_neg.bits();
+        for ch in 0..sample.data.len() {
+            let _ = channels.push(LeadOffChannel {
+                positive: pos & (1 << ch) != 0,
+                negative: neg & (1 << ch) != 0,
+            });
+        }
+    }
+    LeadOffStatus { ts: crate::clock::now_micros(), channels }
+}
+
+/// An [`ImpedanceReport`] for a check that could not run.
+pub(crate) fn impedance_error(message: &str) -> ImpedanceReport {
+    ImpedanceReport {
+        channels: heapless::Vec::new(),
+        error: heapless::String::try_from(message).ok(),
+    }
+}
 
 pub(crate) fn convert_to_proto(
-    samples: alloc::sync::Arc<Vec<AdsData, 2>>,
+    samples: AdsMeasurement,
 ) -> icd::proto::AdsSample {
     // Calculate the total number of channels across all ADS devices
     let total_channels: usize =
@@ -73,34 +123,41 @@ pub(crate) fn convert_to_proto(
         gpio_shift += 4; // Shift by 4 bits (1 nibble per GPIO)
     }
 
-    // Return the constructed AdsSample
-    let sample = if let Some(current_imu) = IMU_DATA_WATCH.try_get() {
-        icd::proto::AdsSample {
-            lead_off_positive,
-            lead_off_negative,
-            gpio,
-            data,
-            accel_x: Some(current_imu.accel_x),
-            accel_y: Some(current_imu.accel_y),
-            accel_z: Some(current_imu.accel_z),
-            gyro_x: Some(current_imu.gyro_x),
-            gyro_y: Some(current_imu.gyro_y),
-            gyro_z: Some(current_imu.gyro_z),
-        }
-    } else {
-        icd::proto::AdsSample {
-            lead_off_positive,
-            lead_off_negative,
-            gpio,
-            data,
-            accel_x: None,
-            accel_y: None,
-            accel_z: None,
-            gyro_x: None,
-            gyro_y: None,
-            gyro_z: None,
-        }
+    // Return the constructed AdsSample with the latest IMU data, if any.
+    let imu = ads_imu_data();
+    let sample = icd::proto::AdsSample {
+        lead_off_positive,
+        lead_off_negative,
+        gpio,
+        data,
+        accel_x: imu.accel.map(|a| a[0]),
+        accel_y: imu.accel.map(|a| a[1]),
+        accel_z: imu.accel.map(|a| a[2]),
+        gyro_x: imu.gyro.map(|g| g[0]),
+        gyro_y: imu.gyro.map(|g| g[1]),
+        gyro_z: imu.gyro.map(|g| g[2]),
+        quat_w: imu.orientation.map(|q| q[0]),
+        quat_x: imu.orientation.map(|q| q[1]),
+        quat_y: imu.orientation.map(|q| q[2]),
+        quat_z: imu.orientation.map(|q| q[3]),
     };
     info!("Converted sample = {}", sample);
     sample
 }
+
+/// Waits for the next sample on `sub`, counting samples it missed against
+/// `consumer`.
+pub async fn next_ads_sample(
+    sub: &mut DynSubscriber<'_, AdsMeasurement>,
+    consumer: AdsConsumer,
+) -> AdsMeasurement {
+    loop {
+        match sub.next_message().await {
+            WaitResult::Message(data) => return data,
+            WaitResult::Lagged(dropped) => {
+                warn!("ADS subscriber lagged, {} samples dropped", dropped);
+                crate::stats::record_ads_drops(consumer, dropped);
+            }
+        }
+    }
+}
@@ -1,12 +1,27 @@
 use super::*;
 use crate::prelude::*;
-use dc_mini_icd::AdsConfig;
-use embassy_futures::select::{select, Either};
+use crate::selftest;
+use crate::tasks::power_control::rails::{self, RailUser};
+use ads1299::{LoffStatN, LoffStatP, RawFrames, FRAME_LEN};
+use core::f32::consts::{FRAC_1_SQRT_2, PI};
+use dc_mini_bsp::PoweredAdsFrontend;
+use dc_mini_icd::{
+    AdsConfig, ImpedanceReport, LeadOffStatus, Montage, MontageChannel,
+    SelfTestResult, ADS_MAX_CHANNELS,
+};
+use embassy_futures::join::join;
+use embassy_futures::select::{select3, Either3};
 use embassy_nrf::gpio::{Level, Output, OutputDrive};
+use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_time::Delay;
+use embassy_sync::pubsub::Publisher;
+use embassy_sync::watch::{Receiver as WatchReceiver, Sender as WatchSender};
+use embassy_time::{Delay, Instant, Ticker};
 use portable_atomic::Ordering;
 
+/// Longest the stream may go without a sample, reconfiguration included.
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[embassy_executor::task]
 pub async fn ads_pwdn_task(
     ads_resources: &'static Mutex<MutexType, AdsResources>,
@@ -19,6 +34,7 @@ pub async fn ads_pwdn_task(
         Level::Low,
         OutputDrive::Standard,
     );
+    rails::release(RailUser::Ads).await;
 
     ADS_PWDN_SIG.wait().await;
     ADS_PWDN_SIG.reset();
@@ -26,11 +42,222 @@ pub async fn ads_pwdn_task(
     ADS_PWDN.store(false, Ordering::SeqCst);
 }
 
+/// Filters inactive channels out of decoded frames, or applies the
+/// montage, and publishes them, tracking the lead-off state on the way.
+struct FramePublisher {
+    publisher: Publisher<
+        'static,
+        CriticalSectionRawMutex,
+        AdsMeasurement,
+        ADS_CAP,
+        ADS_SUBS,
+        1,
+    >,
+    lead_off:
+        WatchSender<'static, CriticalSectionRawMutex, LeadOffStatus, ADS_SUBS>,
+    last_lead_off: Option<heapless::Vec<(u8, u8), 2>>,
+    /// Channel counts of the devices, in frame order.
+    num_chs: heapless::Vec<u8, 2>,
+    /// Power state of every channel across the devices.
+    channel_active: [bool; 16],
+    pd_loff_comp: bool,
+    montage_rx: WatchReceiver<'static, CriticalSectionRawMutex, Montage, 1>,
+    montage: Montage,
+}
+
+impl FramePublisher {
+    fn new<M: RawMutex>(frontend: &PoweredAdsFrontend<'_, '_, M>) -> Self {
+        Self {
+            publisher: ADS_MEAS_CH
+                .publisher()
+                .expect("This is the only expected publisher of ADS data."),
+            lead_off: LEAD_OFF_WATCH.sender(),
+            last_lead_off: None,
+            num_chs: frontend
+                .ads
+                .iter()
+                .map(|dev| dev.num_chs.unwrap())
+                .collect(),
+            channel_active: [false; 16],
+            pd_loff_comp: false,
+            montage_rx: MONTAGE_WATCH
+                .receiver()
+                .expect("This is the only expected montage receiver."),
+            montage: Montage::default(),
+        }
+    }
+
+    fn set_config(&mut self, config: &AdsConfig) {
+        let total: usize = self.num_chs.iter().map(|&n| n as usize).sum();
+        self.channel_active = [false; 16];
+        for (active, ch) in
+            self.channel_active[..total].iter_mut().zip(config.channels.iter())
+        {
+            *active = !ch.power_down;
+        }
+        self.pd_loff_comp = config.pd_loff_comp;
+        info!("Channel active: {:?}", self.channel_active);
+    }
+
+    /// Takes up a new montage, dropping logical channels that refer to
+    /// physical channels the devices do not have.
+    fn set_montage(&mut self, mut montage: Montage) {
+        let total: u8 = self.num_chs.iter().sum();
+        montage.channels.retain(|ch| {
+            let valid = match *ch {
+                MontageChannel::Physical(a)
+                | MontageChannel::AverageReference(a) => a < total,
+                MontageChannel::Bipolar(a, b) => a < total && b < total,
+            };
+            if !valid {
+                warn!("Ignoring montage channel {:?}", ch);
+            }
+            valid
+        });
+        info!("Montage: {:?}", montage);
+        self.montage = montage;
+    }
+
+    /// The logical channels of the montage, eight per device entry, built
+    /// from the physical ones in `ads_data`. Each entry keeps the GPIO of
+    /// the device it replaces, and a logical channel is flagged lead-off
+    /// when any of its sources is.
+    fn apply_montage(
+        &self,
+        ads_data: &[AdsData],
+    ) -> heapless::Vec<AdsData, 2> {
+        let mut physical = heapless::Vec::<i32, ADS_MAX_CHANNELS>::new();
+        let mut loff_pos: u16 = 0;
+        let mut loff_neg: u16 = 0;
+        for sample in ads_data {
+            let shift = physical.len();
+            loff_pos |= (sample.lead_off_status_pos.bits() as u16) << shift;
+            loff_neg |= (sample.lead_off_status_neg.bits() as u16) << shift;
+            let _ = physical.extend_from_slice(&sample.data);
+        }
+
+        let (sum, count) = physical
+            .iter()
+            .zip(self.channel_active.iter())
+            .filter(|(_, active)| **active)
+            .fold((0i64, 0i64), |(sum, count), (&v, _)| {
+                (sum + v as i64, count + 1)
+            });
+        let average = if count > 0 { sum / count } else { 0 };
+
+        let mut logical = heapless::Vec::<AdsData, 2>::new();
+        for (i, ch) in self.montage.channels.iter().enumerate() {
+            let (value, sources) = match *ch {
+                MontageChannel::Physical(a) => {
+                    (physical[a as usize], 1u16 << a)
+                }
+                MontageChannel::Bipolar(a, b) => (
+                    physical[a as usize].saturating_sub(physical[b as usize]),
+                    (1u16 << a) | (1u16 << b),
+                ),
+                MontageChannel::AverageReference(a) => {
+                    ((physical[a as usize] as i64 - average) as i32, 1u16 << a)
+                }
+            };
+            let bit = 1 << (i % 8);
+            if i % 8 == 0 {
+                let mut entry =
+                    ads_data.get(i / 8).unwrap_or(&ads_data[0]).clone();
+                entry.data.clear();
+                entry.lead_off_status_pos = LoffStatP::empty();
+                entry.lead_off_status_neg = LoffStatN::empty();
+                let _ = logical.push(entry);
+            }
+            let Some(entry) = logical.last_mut() else {
+                break;
+            };
+            let _ = entry.data.push(value);
+            if loff_pos & sources != 0 {
+                entry.lead_off_status_pos |= LoffStatP::from_bits_retain(bit);
+            }
+            if loff_neg & sources != 0 {
+                entry.lead_off_status_neg |= LoffStatN::from_bits_retain(bit);
+            }
+        }
+        logical
+    }
+
+    fn update_lead_off(&mut self, ads_data: &[AdsData]) {
+        let loff_bits = lead_off_bits(ads_data);
+        if self.last_lead_off.as_ref() != Some(&loff_bits) {
+            self.lead_off.send(lead_off_status(ads_data));
+            self.last_lead_off = Some(loff_bits);
+        }
+    }
+
+    fn publish(&mut self, frames: &RawFrames<2>, drdy_at: Instant) {
+        let decoded = frames
+            .iter()
+            .zip(self.num_chs.iter())
+            .map(|(frame, &num_chs)| AdsData::from_frame(frame, num_chs))
+            .collect::<Result<heapless::Vec<AdsData, 2>, _>>();
+        let mut ads_data = match decoded {
+            Ok(ads_data) => ads_data,
+            Err(e) => {
+                warn!("Dropped ADS sample: {:?}", e);
+                return;
+            }
+        };
+
+        // Without the comparators the status bits are meaningless and
+        // lead-off is only known from the periodic checks.
+        if self.pd_loff_comp {
+            self.update_lead_off(&ads_data);
+        }
+
+        if let Some(montage) = self.montage_rx.try_changed() {
+            self.set_montage(montage);
+        }
+        if !self.montage.channels.is_empty() {
+            ads_data = self.apply_montage(&ads_data);
+        } else {
+            self.filter_active(&mut ads_data);
+        }
+
+        heartbeat(MonitoredTask::Ads);
+        // Slow subscribers account for their own drops.
+        self.publisher
+            .publish_immediate(Arc::new(Captured::at(drdy_at, ads_data)));
+    }
+
+    /// Drops powered-down channels, and devices left without any.
+    fn filter_active(&self, ads_data: &mut heapless::Vec<AdsData, 2>) {
+        let mut config_idx = 0;
+        let mut i = 0;
+        while i < ads_data.len() {
+            let num_channels = ads_data[i].data.len();
+            let start_idx = config_idx;
+
+            ads_data[i].data = ads_data[i]
+                .data
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| self.channel_active[start_idx + i])
+                .map(|(_, &v)| v)
+                .collect();
+
+            // Remove the ADS device if it has no active channels
+            if ads_data[i].data.is_empty() {
+                let _ = ads_data.remove(i);
+            } else {
+                i += 1;
+            }
+
+            config_idx += num_channels;
+        }
+    }
+}
+
 #[embassy_executor::task]
 pub async fn ads_measure_task(
     bus: &'static Mutex<CriticalSectionRawMutex, Spi3BusResources>,
     ads: &'static Mutex<CriticalSectionRawMutex, AdsResources>,
-    config: AdsConfig,
+    mut config: AdsConfig,
 ) {
     ADS_MEAS.store(true, Ordering::SeqCst);
 
@@ -43,47 +270,53 @@ pub async fn ads_measure_task(
     frontend.reset(&mut Delay).await.unwrap();
 
     apply_ads_config(&mut frontend, &config).await;
-
-    // Create array mapping channel indices to their power state
-    let mut config_idx = 0;
-    let mut channel_active = [false; 16]; // Max possible channels across all ADSs
-    for ads_dev in frontend.ads.iter() {
-        let num_channels = ads_dev.num_chs.unwrap() as usize;
-        for i in 0..num_channels {
-            channel_active[config_idx + i] =
-                !config.channels[config_idx + i].power_down;
-        }
-        config_idx += num_channels;
-    }
-    info!("Channel active: {:?}", channel_active);
+    let mut publisher = FramePublisher::new(&frontend);
+    publisher.set_config(&config);
 
     frontend.start_stream().await.unwrap();
-    let publisher = ADS_MEAS_CH
-        .publisher()
-        .expect("This is the only expected publisher of ADS data.");
+    let _watch = watch_heartbeat(MonitoredTask::Ads, STALL_TIMEOUT);
+    let mut lead_off_check = Ticker::every(LEAD_OFF_CHECK_INTERVAL);
+
+    // Each sample is read into one buffer while the previous one, in the
+    // other, is decoded and published, so the SPI transfer overlaps with
+    // the processing and the DRDY wait is armed before it starts.
+    let mut buffers: [RawFrames<2>; 2] = [[[0; FRAME_LEN]; 2]; 2];
+    let mut fill_first = true;
+    // DRDY time of the frames waiting to be published.
+    let mut ready_at: Option<Instant> = None;
 
     loop {
-        match select(ADS_MEAS_SIG.wait(), frontend.poll()).await {
-            Either::First(config) => {
-                if let Some(config) = config {
+        let [first, second] = &mut buffers;
+        let (fill, ready) =
+            if fill_first { (first, second) } else { (second, first) };
+        let sample = async {
+            let transfer = async {
+                frontend.wait_ready().await;
+                // Stamped at DRDY rather than once the SPI reads are done.
+                let drdy_at = Instant::now();
+                frontend.read_raw(fill).await.map(|()| drdy_at)
+            };
+            let publish = async {
+                if let Some(drdy_at) = ready_at.take() {
+                    publisher.publish(ready, drdy_at);
+                }
+            };
+            join(transfer, publish).await.0
+        };
+        match select3(ADS_MEAS_SIG.wait(), sample, lead_off_check.next()).await
+        {
+            Either3::First(new_config) => {
+                if let Some(drdy_at) = ready_at.take() {
+                    publisher.publish(ready, drdy_at);
+                }
+                if let Some(new_config) = new_config {
+                    config = new_config;
                     frontend
                         .stop_stream()
                         .await
                         .expect("Failed to stop ads stream.");
                     apply_ads_config(&mut frontend, &config).await;
-
-                    // Create array mapping channel indices to their power state
-                    let mut config_idx = 0;
-                    let mut channel_active = [false; 16]; // Max possible channels across all ADSs
-                    for ads_dev in frontend.ads.iter() {
-                        let num_channels = ads_dev.num_chs.unwrap() as usize;
-                        for i in 0..num_channels {
-                            channel_active[config_idx + i] =
-                                !config.channels[config_idx + i].power_down;
-                        }
-                        config_idx += num_channels;
-                    }
-                    info!("Channel active: {:?}", channel_active);
+                    publisher.set_config(&config);
                     frontend
                         .start_stream()
                         .await
@@ -92,42 +325,296 @@ pub async fn ads_measure_task(
                     break;
                 }
             }
-            Either::Second(ads_data) => {
-                let mut ads_data =
-                    ads_data.expect("ADS poll resulted in error.");
-
-                let mut config_idx = 0;
-                let mut i = 0;
-                while i < ads_data.len() {
-                    let num_channels = ads_data[i].data.len();
-                    let start_idx = config_idx;
-
-                    ads_data[i].data = ads_data[i]
-                        .data
-                        .iter()
-                        .enumerate()
-                        .filter(|(i, _)| channel_active[start_idx + i])
-                        .map(|(_, &v)| v)
-                        .collect();
-
-                    // Remove the ADS device if it has no active channels
-                    if ads_data[i].data.is_empty() {
-                        let _ = ads_data.remove(i);
-                    } else {
-                        i += 1;
-                    }
-
-                    config_idx += num_channels;
+            Either3::Second(drdy_at) => {
+                ready_at = Some(drdy_at.expect("ADS poll resulted in error."));
+                fill_first = !fill_first;
+            }
+            Either3::Third(()) => {
+                if config.pd_loff_comp {
+                    continue;
                 }
-
-                if let Err(_) = publisher.try_publish(ads_data.into()) {
-                    warn!("Failed to publish ads data! Subscriber back pressure!");
+                if let Some(drdy_at) = ready_at.take() {
+                    publisher.publish(ready, drdy_at);
+                }
+                match check_lead_off(&mut frontend, &config).await {
+                    Some(ads_data) => publisher.update_lead_off(&ads_data),
+                    None => warn!("Lead-off check failed"),
                 }
             }
         }
     }
     frontend.stop_stream().await.unwrap();
+    publisher.lead_off.clear();
     ADS_MEAS_SIG.reset();
 
     ADS_MEAS.store(false, Ordering::SeqCst);
 }
+
+/// How often the lead-off comparators are switched on when the active
+/// config leaves them off.
+const LEAD_OFF_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Samples taken per check; the status of the last one is used so the
+/// comparators have settled.
+const LEAD_OFF_CHECK_SAMPLES: usize = 4;
+
+fn lead_off_bits(ads_data: &[AdsData]) -> heapless::Vec<(u8, u8), 2> {
+    ads_data
+        .iter()
+        .map(|d| (d.lead_off_status_pos.bits(), d.lead_off_status_neg.bits()))
+        .collect()
+}
+
+/// `config` with DC lead-off detection on every active channel.
+fn lead_off_check_config(config: &AdsConfig) -> AdsConfig {
+    let mut check = config.clone();
+    check.pd_loff_comp = true;
+    check.lead_off_frequency = dc_mini_icd::FLeadOff::Dc;
+    for ch in check.channels.iter_mut().filter(|ch| !ch.power_down) {
+        ch.lead_off_sensp = true;
+        ch.lead_off_sensn = true;
+    }
+    check
+}
+
+/// Briefly enables lead-off detection and returns the last sample taken
+/// with it, then restores `config`. The samples taken during the check are
+/// not published, leaving a gap of a few samples in the stream.
+async fn check_lead_off<M: RawMutex>(
+    frontend: &mut PoweredAdsFrontend<'_, '_, M>,
+    config: &AdsConfig,
+) -> Option<heapless::Vec<AdsData, 2>> {
+    frontend.stop_stream().await.ok()?;
+    apply_ads_config(frontend, &lead_off_check_config(config)).await;
+    let mut last = None;
+    if frontend.start_stream().await.is_ok() {
+        for _ in 0..LEAD_OFF_CHECK_SAMPLES {
+            match frontend.poll().await {
+                Ok(data) => last = Some(data),
+                Err(_) => break,
+            }
+        }
+        let _ = frontend.stop_stream().await;
+    }
+    apply_ads_config(frontend, config).await;
+    frontend.start_stream().await.ok()?;
+    last
+}
+
+/// Samples needed to span a full period of the FclkBy20 (~2 Hz) test
+/// signal at 250 SPS.
+const SELF_TEST_SAMPLES: usize = 150;
+/// Minimum peak-to-peak (codes) of the ±1.875 mV test signal at gain 24.
+const SELF_TEST_MIN_P2P: i32 = 10_000;
+
+/// Checks the ADS IDs and that every channel sees the internal test signal.
+///
+/// The ADS must not be streaming or held in power-down.
+pub async fn ads_self_test(
+    bus: &'static Mutex<CriticalSectionRawMutex, Spi3BusResources>,
+    ads: &'static Mutex<CriticalSectionRawMutex, AdsResources>,
+) -> SelfTestResult {
+    let mut bus_resources = bus.lock().await;
+    let bus = bus_resources.get_bus::<CriticalSectionRawMutex>();
+
+    let mut ads_resources = ads.lock().await;
+    // Devices that fail the ID check are dropped from the frontend.
+    let mut frontend = ads_resources.configure(&bus).await;
+    if frontend.ads.is_empty() {
+        return selftest::fail("ADS ID check failed");
+    }
+    if frontend.init().await.is_err() {
+        return selftest::fail("ADS init failed");
+    }
+
+    let num_chs: u8 = frontend.ads.iter().filter_map(|dev| dev.num_chs).sum();
+    let mut config = default_ads_settings(num_chs);
+    config.internal_calibration = true;
+    config.calibration_frequency = dc_mini_icd::CalFreq::FclkBy20;
+    for ch in config.channels.iter_mut() {
+        ch.mux = dc_mini_icd::Mux::TestSignal;
+    }
+    apply_ads_config(&mut frontend, &config).await;
+
+    if frontend.start_stream().await.is_err() {
+        return selftest::fail("ADS failed to start");
+    }
+    let mut min = [i32::MAX; 16];
+    let mut max = [i32::MIN; 16];
+    let mut timed_out = false;
+    for _ in 0..SELF_TEST_SAMPLES {
+        let Ok(Ok(data)) = embassy_time::with_timeout(
+            Duration::from_secs(1),
+            frontend.poll(),
+        )
+        .await
+        else {
+            timed_out = true;
+            break;
+        };
+        for (i, v) in data.iter().flat_map(|d| d.data.iter()).enumerate() {
+            min[i] = min[i].min(*v);
+            max[i] = max[i].max(*v);
+        }
+    }
+    let _ = frontend.stop_stream().await;
+
+    if timed_out {
+        return selftest::fail("ADS data timeout");
+    }
+    let flat = (0..num_chs as usize)
+        .filter(|&i| max[i].saturating_sub(min[i]) < SELF_TEST_MIN_P2P)
+        .count();
+    if flat > 0 {
+        let mut msg: heapless::String<64> = heapless::String::new();
+        let _ = core::fmt::write(
+            &mut msg,
+            format_args!("{} of {} channels miss test signal", flat, num_chs),
+        );
+        return selftest::fail(&msg);
+    }
+    selftest::pass("")
+}
+
+/// Amplitude of the square-wave lead-off current.
+const IMPEDANCE_CURRENT_A: f32 = 6e-9;
+/// Samples per period of the 31.25 Hz excitation at 250 SPS.
+const IMPEDANCE_PERIOD: usize = 8;
+/// Samples dropped while the digital filter settles.
+const IMPEDANCE_SETTLE_SAMPLES: usize = 64;
+/// Samples demodulated per channel, a whole number of periods.
+const IMPEDANCE_SAMPLES: usize = 256;
+/// `cos(2πk / IMPEDANCE_PERIOD)`; the sine is the same table shifted by a
+/// quarter period.
+const IMPEDANCE_COS: [f32; IMPEDANCE_PERIOD] = [
+    1.0,
+    FRAC_1_SQRT_2,
+    0.0,
+    -FRAC_1_SQRT_2,
+    -1.0,
+    -FRAC_1_SQRT_2,
+    0.0,
+    FRAC_1_SQRT_2,
+];
+
+/// `config` with 6 nA AC excitation on the positive input of every active
+/// channel, sampled at 250 SPS so one period is [`IMPEDANCE_PERIOD`]
+/// samples.
+fn impedance_check_config(config: &AdsConfig) -> AdsConfig {
+    let mut check = config.clone();
+    check.sample_rate = dc_mini_icd::SampleRate::Sps250;
+    check.internal_calibration = false;
+    check.pd_loff_comp = true;
+    check.lead_off_current = dc_mini_icd::ILeadOff::_6nA;
+    check.lead_off_frequency = dc_mini_icd::FLeadOff::Ac31_2;
+    for ch in check.channels.iter_mut() {
+        ch.mux = dc_mini_icd::Mux::NormalElectrodeInput;
+        ch.lead_off_sensp = !ch.power_down;
+        ch.lead_off_sensn = false;
+        ch.lead_off_flip = false;
+    }
+    check
+}
+
+/// Volts per ADC code with the internal 4.5 V reference.
+fn volts_per_code(gain: dc_mini_icd::Gain) -> f32 {
+    use dc_mini_icd::Gain;
+    let gain = match gain {
+        Gain::X1 => 1.0,
+        Gain::X2 => 2.0,
+        Gain::X4 => 4.0,
+        Gain::X6 => 6.0,
+        Gain::X8 => 8.0,
+        Gain::X12 => 12.0,
+        Gain::X24 => 24.0,
+    };
+    2.0 * 4.5 / (gain * (1u32 << 24) as f32)
+}
+
+/// Square root by Newton's method, as `core` has no float `sqrt`.
+fn sqrt(x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let mut y = f32::from_bits((x.to_bits() >> 1) + 0x1FBD_1DF5);
+    for _ in 0..4 {
+        y = 0.5 * (y + x / y);
+    }
+    y
+}
+
+/// Injects the lead-off current, measures the resulting voltage on every
+/// active channel and returns the impedances, then writes `config` back.
+///
+/// The excitation is a square wave, so the amplitude is recovered from its
+/// fundamental by I/Q demodulation, which rejects DC offset and most
+/// out-of-band noise. The result includes any series resistance on the
+/// board. The ADS must not be streaming or held in power-down.
+pub async fn ads_impedance_check(
+    bus: &'static Mutex<CriticalSectionRawMutex, Spi3BusResources>,
+    ads: &'static Mutex<CriticalSectionRawMutex, AdsResources>,
+    config: &AdsConfig,
+) -> ImpedanceReport {
+    let mut bus_resources = bus.lock().await;
+    let bus = bus_resources.get_bus::<CriticalSectionRawMutex>();
+
+    let mut ads_resources = ads.lock().await;
+    let mut frontend = ads_resources.configure(&bus).await;
+    if frontend.init().await.is_err() {
+        return impedance_error("ADS init failed");
+    }
+    apply_ads_config(&mut frontend, &impedance_check_config(config)).await;
+
+    if frontend.start_stream().await.is_err() {
+        apply_ads_config(&mut frontend, config).await;
+        return impedance_error("ADS failed to start");
+    }
+    let mut in_phase = [0f32; ADS_MAX_CHANNELS];
+    let mut quadrature = [0f32; ADS_MAX_CHANNELS];
+    let mut timed_out = false;
+    for n in 0..IMPEDANCE_SETTLE_SAMPLES + IMPEDANCE_SAMPLES {
+        let Ok(Ok(data)) = embassy_time::with_timeout(
+            Duration::from_secs(1),
+            frontend.poll(),
+        )
+        .await
+        else {
+            timed_out = true;
+            break;
+        };
+        let Some(k) = n.checked_sub(IMPEDANCE_SETTLE_SAMPLES) else {
+            continue;
+        };
+        let cos = IMPEDANCE_COS[k % IMPEDANCE_PERIOD];
+        let sin =
+            IMPEDANCE_COS[(k + 3 * IMPEDANCE_PERIOD / 4) % IMPEDANCE_PERIOD];
+        let values = data.iter().flat_map(|d| d.data.iter());
+        for (ch, v) in values.take(ADS_MAX_CHANNELS).enumerate() {
+            in_phase[ch] += *v as f32 * cos;
+            quadrature[ch] += *v as f32 * sin;
+        }
+    }
+    let _ = frontend.stop_stream().await;
+    apply_ads_config(&mut frontend, config).await;
+
+    if timed_out {
+        return impedance_error("ADS data timeout");
+    }
+    let channels = config
+        .channels
+        .iter()
+        .enumerate()
+        .map(|(ch, ch_config)| {
+            (!ch_config.power_down).then(|| {
+                let (i, q) = (in_phase[ch], quadrature[ch]);
+                // Peak of the fundamental, which is 4/π of the square
+                // wave's amplitude.
+                let fundamental =
+                    2.0 / IMPEDANCE_SAMPLES as f32 * sqrt(i * i + q * q);
+                let volts =
+                    fundamental * (PI / 4.0) * volts_per_code(ch_config.gain);
+                (volts / IMPEDANCE_CURRENT_A) as u32
+            })
+        })
+        .collect();
+    ImpedanceReport { channels, error: None }
+}
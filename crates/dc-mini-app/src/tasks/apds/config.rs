@@ -8,17 +8,18 @@ pub type I2cDev<'a> = embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice<
     twim::Twim<'static>,
 >;
 
+/// Programs `config` into the sensor and enables it; false if any write
+/// failed.
 pub async fn apply_apds_config(
     sensor: &mut Apds9253<I2cDev<'_>>,
     config: &ApdsConfig,
-) {
-    unwrap!(sensor.set_gain_async(config.gain.into()).await);
-    unwrap!(sensor.set_resolution_async(config.resolution.into()).await);
-    unwrap!(
-        sensor
+) -> bool {
+    sensor.set_gain_async(config.gain.into()).await.is_ok()
+        && sensor.set_resolution_async(config.resolution.into()).await.is_ok()
+        && sensor
             .set_measurement_rate_async(config.measurement_rate.into())
             .await
-    );
-    unwrap!(sensor.enable_rgb_mode_async(config.rgb_mode).await);
-    unwrap!(sensor.enable_async(true).await);
+            .is_ok()
+        && sensor.enable_rgb_mode_async(config.rgb_mode).await.is_ok()
+        && sensor.enable_async(true).await.is_ok()
 }
@@ -17,24 +17,29 @@ pub enum ApdsEvent {
 #[derive(Clone)]
 pub struct ApdsManager {
     available: bool,
-    bus_manager: &'static I2cBusManager,
+    buses: &'static AppBuses,
     app: &'static Mutex<CriticalSectionRawMutex, AppContext>,
 }
 
 impl ApdsManager {
     pub fn new(
         available: bool,
-        bus_manager: &'static I2cBusManager,
+        buses: &'static AppBuses,
         app: &'static Mutex<CriticalSectionRawMutex, AppContext>,
     ) -> Self {
-        Self { available, bus_manager, app }
+        Self { available, buses, app }
+    }
+
+    /// Whether the APDS is fitted and has not been marked degraded.
+    fn usable(&self) -> bool {
+        self.available && !faults::is_degraded(MonitoredTask::Apds)
     }
 
     pub async fn handle_event(&self, event: ApdsEvent) {
         info!("Received event {:?}", event);
         match event {
             ApdsEvent::ConfigChanged => {
-                if !self.available {
+                if !self.usable() {
                     return;
                 }
                 if APDS_MEAS.load(Ordering::SeqCst) {
@@ -50,7 +55,7 @@ impl ApdsManager {
                 }
             }
             ApdsEvent::StopStream => {
-                if !self.available {
+                if !self.usable() {
                     return;
                 }
                 if !APDS_MEAS.load(Ordering::SeqCst) {
@@ -61,9 +66,9 @@ impl ApdsManager {
                 }
             }
             ApdsEvent::StartStream => {
-                if !self.available {
+                if !self.usable() {
                     warn!(
-                        "Ignoring APDS start request because no APDS sensor is present"
+                        "Ignoring APDS start request, APDS missing or degraded"
                     );
                     return;
                 }
@@ -83,7 +88,7 @@ impl ApdsManager {
                             .await;
                     }
                     app_ctx.low_prio_spawner.must_spawn(apds_task(
-                        self.bus_manager,
+                        self.buses.get::<ApdsBus>(),
                         apds_config.unwrap(),
                     ));
                     APDS_WATCH.sender().send(true);
@@ -0,0 +1,155 @@
+//! Gestures read from shadows over the ambient light sensor.
+//!
+//! The APDS9253 has no gesture engine, so a gesture is a dip in lux below
+//! the tracked ambient level: a short one is a [`Gesture::Wave`], a held
+//! one a [`Gesture::Cover`]. The direction of a swipe cannot be told.
+
+use super::APDS_DATA_WATCH;
+use crate::clock::now_micros;
+use crate::prelude::*;
+use dc_mini_icd::{Gesture, GestureAction, GestureConfig, MarkerRecord};
+use embassy_time::Instant;
+
+/// Fraction of the ambient level below which the sensor counts as shaded.
+const SHADE_RATIO: f32 = 0.5;
+/// Fraction of the ambient level that ends a shadow.
+const CLEAR_RATIO: f32 = 0.8;
+/// Ambient level below which shadows are not looked for.
+const MIN_AMBIENT_LUX: f32 = 5.0;
+/// Weight of each new reading in the ambient level.
+const AMBIENT_SMOOTHING: f32 = 0.1;
+const WAVE_MAX: Duration = Duration::from_millis(800);
+const COVER_MIN: Duration = Duration::from_millis(1500);
+/// A shadow this long is taken as a new ambient level, e.g. the lights
+/// going off.
+const SHADE_MAX: Duration = Duration::from_secs(5);
+/// Readings further apart than this restart the detector.
+const MAX_GAP: Duration = Duration::from_secs(2);
+
+const MARKER_LABEL: &str = "gesture";
+
+#[derive(Default)]
+struct GestureDetector {
+    ambient: Option<f32>,
+    shaded_since: Option<Instant>,
+    cover_reported: bool,
+    last: Option<Instant>,
+}
+
+impl GestureDetector {
+    fn update(&mut self, now: Instant, lux: f32) -> Option<Gesture> {
+        if self.last.is_some_and(|last| now - last > MAX_GAP) {
+            *self = Self::default();
+        }
+        self.last = Some(now);
+
+        let Some(ambient) = self.ambient else {
+            self.ambient = Some(lux);
+            return None;
+        };
+
+        let Some(since) = self.shaded_since else {
+            if ambient >= MIN_AMBIENT_LUX && lux < ambient * SHADE_RATIO {
+                self.shaded_since = Some(now);
+            } else {
+                self.ambient =
+                    Some(ambient + AMBIENT_SMOOTHING * (lux - ambient));
+            }
+            return None;
+        };
+
+        let shaded = now - since;
+        if lux >= ambient * CLEAR_RATIO {
+            let wave = !self.cover_reported && shaded <= WAVE_MAX;
+            self.shaded_since = None;
+            self.cover_reported = false;
+            wave.then_some(Gesture::Wave)
+        } else if shaded >= SHADE_MAX {
+            self.ambient = Some(lux);
+            self.shaded_since = None;
+            self.cover_reported = false;
+            None
+        } else if !self.cover_reported && shaded >= COVER_MIN {
+            self.cover_reported = true;
+            Some(Gesture::Cover)
+        } else {
+            None
+        }
+    }
+}
+
+async fn load_config(
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) -> GestureConfig {
+    let mut ctx = app_context.lock().await;
+    ctx.profile_manager.get_gesture_config().await.copied().unwrap_or_default()
+}
+
+/// Starts the light sensor if the active profile binds any gesture.
+pub async fn start_for_gestures(
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) {
+    if load_config(app_context).await.is_enabled() {
+        info!("[apds] starting light sensor for gestures");
+        let ctx = app_context.lock().await;
+        ctx.event_sender.send(ApdsEvent::StartStream.into()).await;
+    }
+}
+
+async fn perform(
+    action: GestureAction,
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) {
+    match action {
+        GestureAction::None => {}
+        GestureAction::EventMarker => {
+            let mut marker = MarkerRecord {
+                ts: now_micros(),
+                // Not known on the device.
+                host_epoch_us: 0,
+                label: heapless::String::try_from(MARKER_LABEL)
+                    .unwrap_or_default(),
+                recorded: false,
+            };
+            marker.recorded = record_marker(&marker);
+            if !marker.recorded {
+                info!("[apds] no recording, gesture marker dropped");
+            }
+        }
+        GestureAction::ToggleAdsStream => {
+            let event = if ads_claimed(AdsClaim::Local) {
+                AdsEvent::StopStream(AdsClaim::Local)
+            } else {
+                AdsEvent::StartStream(AdsClaim::Local)
+            };
+            let ctx = app_context.lock().await;
+            ctx.event_sender.send(event.into()).await;
+        }
+        GestureAction::ToggleRecording => {
+            let ctx = app_context.lock().await;
+            ctx.event_sender.send(AdsEvent::ManualRecord.into()).await;
+        }
+    }
+}
+
+/// Watches the light sensor readings for gestures and performs the
+/// actions the active profile binds to them. Readings only arrive while
+/// the sensor runs; see [`start_for_gestures`].
+#[embassy_executor::task]
+pub async fn gesture_task(
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) {
+    let mut frames = unwrap!(APDS_DATA_WATCH.receiver());
+    start_for_gestures(app_context).await;
+
+    let mut detector = GestureDetector::default();
+    loop {
+        let frame = frames.changed().await;
+        let Some(gesture) = detector.update(Instant::now(), frame.lux) else {
+            continue;
+        };
+        let action = load_config(app_context).await.action(gesture);
+        info!("[apds] {:?} gesture: {:?}", gesture, action);
+        perform(action, app_context).await;
+    }
+}
@@ -1,10 +1,12 @@
 pub(crate) mod config;
 pub(crate) mod events;
+mod gesture;
 
 mod tasks; // Tasks module is private
 
 pub use config::*;
 pub use events::*;
+pub use gesture::*;
 pub use tasks::*;
 
 use crate::prelude::*;
@@ -19,7 +21,7 @@ pub(self) static APDS_MEAS_SIG: Signal<
     Option<ApdsConfig>,
 > = Signal::new();
 
-pub const APDS_SUBS: usize = 3;
+pub const APDS_SUBS: usize = 4; // USB, recording, gestures and one spare
 pub static APDS_WATCH: Watch<CriticalSectionRawMutex, bool, APDS_SUBS> =
     Watch::new();
 pub static APDS_DATA_WATCH: Watch<
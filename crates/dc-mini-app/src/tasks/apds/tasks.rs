@@ -37,7 +37,11 @@ pub async fn apds_task(
     APDS_MEAS.store(true, Ordering::SeqCst);
 
     // Acquire bus handle - configures bus if needed
-    let handle = bus_manager.acquire().await.unwrap();
+    let Ok(handle) = bus_manager.acquire().await else {
+        error!("Failed to acquire I2C bus for APDS");
+        stop_degraded();
+        return;
+    };
     let mut sensor = Apds9253::new(I2cDevice::new(handle.bus()));
 
     // Initialize sensor with retry loop
@@ -54,13 +58,16 @@ pub async fn apds_task(
 
     if !initialized {
         warn!("APDS init failed, stopping APDS task");
-        APDS_MEAS_SIG.reset();
-        APDS_MEAS.store(false, Ordering::SeqCst);
+        stop_degraded();
         return;
     }
 
     // Apply all configuration settings
-    apply_apds_config(&mut sensor, &config).await;
+    if !apply_apds_config(&mut sensor, &config).await {
+        error!("Failed to configure APDS");
+        stop_degraded();
+        return;
+    }
 
     let sender = APDS_DATA_WATCH.sender();
     let poll_delay_ms = sensor.get_measurement_delay_ms() as u64 + 5;
@@ -81,6 +88,7 @@ pub async fn apds_task(
                 .unwrap_or(apds9253::ColorData { cct: 0, x: 0.0, y: 0.0 });
 
             Ok(Some(ApdsDataFrame {
+                ts: crate::clock::now_micros(),
                 red: rgb_data.red,
                 green: rgb_data.green,
                 blue: rgb_data.blue,
@@ -97,18 +105,32 @@ pub async fn apds_task(
                 if let Some(config) = config {
                     // Disable sensor before reconfiguring
                     let _ = sensor.enable_async(false).await;
-                    apply_apds_config(&mut sensor, &config).await;
+                    if !apply_apds_config(&mut sensor, &config).await {
+                        error!("Failed to reconfigure APDS");
+                        faults::degrade(
+                            FaultKind::SensorBusError,
+                            MonitoredTask::Apds,
+                        );
+                        APDS_WATCH.sender().send(false);
+                        break;
+                    }
                 } else {
                     break;
                 }
             }
             Either::Second(Ok(data)) => {
+                heartbeat(MonitoredTask::Apds);
                 if let Some(data) = data {
                     sender.send(data);
                 }
             }
             Either::Second(Err(e)) => {
                 error!("Error reading APDS data: {:?}", e);
+                faults::degrade(
+                    FaultKind::SensorBusError,
+                    MonitoredTask::Apds,
+                );
+                APDS_WATCH.sender().send(false);
                 break;
             }
         }
@@ -120,3 +142,12 @@ pub async fn apds_task(
     APDS_MEAS_SIG.reset();
     APDS_MEAS.store(false, Ordering::SeqCst);
 }
+
+/// Ends an APDS task that never got streaming, marking the sensor
+/// degraded so later requests are ignored.
+fn stop_degraded() {
+    faults::degrade(FaultKind::SensorBusError, MonitoredTask::Apds);
+    APDS_WATCH.sender().send(false);
+    APDS_MEAS_SIG.reset();
+    APDS_MEAS.store(false, Ordering::SeqCst);
+}
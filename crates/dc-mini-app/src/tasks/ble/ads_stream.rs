@@ -1,40 +1,102 @@
 extern crate alloc;
 
+use crate::clock::Captured;
+use crate::codec::ads_codec;
+use crate::decimation::AdsStreamDecimator;
 use crate::prelude::*;
-use crate::tasks::ads::ADS_MEAS_CH;
-use ads1299::AdsData;
+use crate::tasks::ads::{
+    next_ads_sample, AdsMeasurement, ADS_CAP, ADS_MEAS_CH,
+};
+use dc_mini_icd::AdsCodec;
 use embassy_futures::select::{select, Either};
 use embassy_sync::pubsub::DynSubscriber;
 use embassy_sync::watch::DynReceiver;
-use embassy_time::Instant;
 use heapless::Vec;
 use prost::Message;
 
+/// A streamed sample with the capture time of its reading.
+type StampedSample = Captured<icd::proto::AdsSample>;
+
+/// Converts a sample for streaming, applying the configured decimation.
+fn decimate(
+    decimator: &mut AdsStreamDecimator,
+    data: AdsMeasurement,
+) -> Option<StampedSample> {
+    let ts = data.ts;
+    let mut sample = convert_to_proto(data);
+    let keep_imu = decimator.push(&mut sample.data)?;
+    if !keep_imu {
+        sample.accel_x = None;
+        sample.accel_y = None;
+        sample.accel_z = None;
+        sample.gyro_x = None;
+        sample.gyro_y = None;
+        sample.gyro_z = None;
+        sample.quat_w = None;
+        sample.quat_x = None;
+        sample.quat_y = None;
+        sample.quat_z = None;
+    }
+    Some(Captured { ts, data: sample })
+}
+
+/// Encodes `message` into `out`, applying `codec` to a copy so that samples
+/// can still be moved between frames as raw readings.
+fn encode_frame(
+    message: &icd::proto::AdsDataFrame,
+    codec: AdsCodec,
+    out: &mut alloc::vec::Vec<u8>,
+) {
+    match codec {
+        AdsCodec::Raw => message.encode(out).unwrap(),
+        AdsCodec::Delta => {
+            let mut message = message.clone();
+            message.delta_encode();
+            message.encode(out).unwrap();
+        }
+    }
+}
+
 /// Find the initial maximum number of samples that can fit in the agreed upon mtu.
 pub(crate) async fn find_initial_max_samples(
     att_mtu: usize,
-    sub: &mut DynSubscriber<'_, alloc::sync::Arc<Vec<AdsData, 2>>>,
-) -> (usize, alloc::vec::Vec<u8>, Option<alloc::vec::Vec<icd::proto::AdsSample>>)
-{
+    sub: &mut DynSubscriber<'_, AdsMeasurement>,
+    decimator: &mut AdsStreamDecimator,
+    packet_counter: u64,
+    codec: AdsCodec,
+) -> (usize, alloc::vec::Vec<u8>, Option<alloc::vec::Vec<StampedSample>>) {
     let mut max_samples = 0;
     let mut out_buffer = alloc::vec::Vec::new();
+    let mut last_ts = 0;
 
     let mut message = icd::proto::AdsDataFrame {
-        packet_counter: 0,
-        ts: Instant::now().as_micros(),
+        packet_counter,
+        // Capture time of the first sample, set below.
+        ts: 0,
         samples: alloc::vec::Vec::with_capacity(16),
+        markers: alloc::vec::Vec::new(),
+        seq: packet_counter as u32,
+        imu: alloc::vec::Vec::new(),
+        codec: icd::proto::AdsCodec::Raw as i32,
+        decimation: decimator.factor() as u32,
     };
 
     loop {
         out_buffer.clear();
 
-        let data = sub.next_message_pure().await;
-        let ads_sample = convert_to_proto(data);
+        let data = next_ads_sample(sub, AdsConsumer::Ble).await;
+        let Some(ads_sample) = decimate(decimator, data) else {
+            continue;
+        };
 
-        message.samples.push(ads_sample);
+        if message.samples.is_empty() {
+            message.ts = ads_sample.ts;
+        }
+        last_ts = ads_sample.ts;
+        message.samples.push(ads_sample.data);
         max_samples += 1;
 
-        message.encode(&mut out_buffer).unwrap();
+        encode_frame(&message, codec, &mut out_buffer);
 
         // Check if the encoded frame fits within att_mtu
         if out_buffer.len() > att_mtu {
@@ -44,13 +106,10 @@ pub(crate) async fn find_initial_max_samples(
                 return (max_samples, out_buffer, None);
             }
             out_buffer.clear();
-            let carry_over_samples = if let Some(carry) = message.samples.pop()
-            {
-                Some(alloc::vec![carry])
-            } else {
-                None
-            };
-            message.encode(&mut out_buffer).unwrap();
+            let carry_over_samples = message.samples.pop().map(|carry| {
+                alloc::vec![Captured { ts: last_ts, data: carry }]
+            });
+            encode_frame(&message, codec, &mut out_buffer);
             return (max_samples - 1, out_buffer, carry_over_samples);
         }
     }
@@ -67,11 +126,12 @@ pub(crate) trait AdsStreamNotifier {
 /// Encodes and sends a message frame
 async fn encode_and_send<T: AdsStreamNotifier>(
     message: icd::proto::AdsDataFrame,
+    codec: AdsCodec,
     att_payload: &mut Vec<u8, ATT_MTU>,
     notifier: &T,
 ) -> Result<(), super::Error> {
     let mut out_buffer = alloc::vec::Vec::new();
-    message.encode(&mut out_buffer).unwrap();
+    encode_frame(&message, codec, &mut out_buffer);
     att_payload
         .extend_from_slice(&out_buffer)
         .map_err(|_| super::Error::HeaplessExtendFromSlice)?;
@@ -80,11 +140,12 @@ async fn encode_and_send<T: AdsStreamNotifier>(
 
 /// Collects samples up to max_samples, handling watcher interruptions
 async fn collect_samples(
-    sub: &mut DynSubscriber<'_, alloc::sync::Arc<Vec<AdsData, 2>>>,
+    sub: &mut DynSubscriber<'_, AdsMeasurement>,
     ads_watcher: &mut DynReceiver<'_, bool>,
+    decimator: &mut AdsStreamDecimator,
     max_samples: usize,
-    carry_over_samples: Option<alloc::vec::Vec<icd::proto::AdsSample>>,
-) -> (alloc::vec::Vec<icd::proto::AdsSample>, bool) {
+    carry_over_samples: Option<alloc::vec::Vec<StampedSample>>,
+) -> (alloc::vec::Vec<StampedSample>, bool) {
     let mut samples = alloc::vec::Vec::with_capacity(max_samples.max(1));
 
     // Add carry-over samples first
@@ -93,9 +154,16 @@ async fn collect_samples(
     }
 
     while samples.len() < max_samples.max(1) {
-        match select(sub.next_message_pure(), ads_watcher.changed()).await {
+        match select(
+            next_ads_sample(sub, AdsConsumer::Ble),
+            ads_watcher.changed(),
+        )
+        .await
+        {
             Either::First(data) => {
-                samples.push(convert_to_proto(data));
+                if let Some(sample) = decimate(decimator, data) {
+                    samples.push(sample);
+                }
             }
             Either::Second(streaming) => {
                 if !streaming {
@@ -113,18 +181,19 @@ fn ensure_mtu_fit(
     message: &mut icd::proto::AdsDataFrame,
     mtu: usize,
     max_samples: usize,
+    codec: AdsCodec,
 ) -> (usize, Option<alloc::vec::Vec<icd::proto::AdsSample>>) {
     let mut out_buffer = alloc::vec::Vec::new();
     let mut current_max_samples = max_samples;
     let mut carry_over_samples = alloc::vec::Vec::new();
 
-    message.encode(&mut out_buffer).unwrap();
+    encode_frame(message, codec, &mut out_buffer);
 
     while out_buffer.len() > mtu {
         out_buffer.clear();
         current_max_samples = current_max_samples.saturating_sub(1);
         carry_over_samples.push(message.samples.pop().unwrap());
-        message.encode(&mut out_buffer).unwrap();
+        encode_frame(message, codec, &mut out_buffer);
         warn!("Reduced max_samples to {}", current_max_samples);
     }
 
@@ -146,17 +215,32 @@ pub(crate) async fn ads_stream_notify<T: AdsStreamNotifier>(
     let mut sub =
         ADS_MEAS_CH.dyn_subscriber().expect("Failed to create subscriber.");
 
-    let mut packet_counter = 0;
+    let mut decimator = AdsStreamDecimator::new();
+    let mut packet_counter: u64 = 0;
     let mut max_samples = 0;
     let mut needs_recalc = true;
     let mut carry_over_samples = None;
     let mut att_payload: heapless::Vec<u8, ATT_MTU> = heapless::Vec::new();
+    let mut codec = ads_codec();
 
     loop {
+        // More samples fit a notification when they are delta-encoded, so
+        // the frame size is worked out again when the codec changes.
+        if ads_codec() != codec {
+            codec = ads_codec();
+            needs_recalc = true;
+        }
+
         // Initialize or reinitialize max_samples if needed
         if needs_recalc {
             match select(
-                find_initial_max_samples(mtu, &mut sub),
+                find_initial_max_samples(
+                    mtu,
+                    &mut sub,
+                    &mut decimator,
+                    packet_counter,
+                    codec,
+                ),
                 ads_watcher.changed(),
             )
             .await
@@ -190,6 +274,7 @@ pub(crate) async fn ads_stream_notify<T: AdsStreamNotifier>(
         let (samples, should_recalc) = collect_samples(
             &mut sub,
             &mut ads_watcher,
+            &mut decimator,
             max_samples,
             carry_over_samples.take(),
         )
@@ -199,26 +284,45 @@ pub(crate) async fn ads_stream_notify<T: AdsStreamNotifier>(
 
         // Only proceed with encoding and sending if we have samples
         if !samples.is_empty() {
+            // Stamped with the capture time of the first sample
+            let ts = samples[0].ts;
+            let (stamps, samples): (alloc::vec::Vec<u64>, alloc::vec::Vec<_>) =
+                samples.into_iter().map(|s| (s.ts, s.data)).unzip();
+
             // Prepare and encode message
             let mut message = icd::proto::AdsDataFrame {
-                ts: Instant::now().as_micros(),
+                ts,
                 packet_counter,
                 samples,
+                markers: alloc::vec::Vec::new(),
+                seq: packet_counter as u32,
+                imu: alloc::vec::Vec::new(),
+                codec: icd::proto::AdsCodec::Raw as i32,
+                decimation: decimator.factor() as u32,
             };
 
             // Ensure message fits within MTU and update state
             let (new_max_samples, new_carry_over) =
-                ensure_mtu_fit(&mut message, mtu, max_samples);
+                ensure_mtu_fit(&mut message, mtu, max_samples, codec);
             max_samples = new_max_samples;
-            carry_over_samples = new_carry_over;
+            // The carried samples are the newest ones of the frame.
+            carry_over_samples = new_carry_over.map(|carry| {
+                stamps[stamps.len() - carry.len()..]
+                    .iter()
+                    .zip(carry)
+                    .map(|(ts, data)| Captured { ts: *ts, data })
+                    .collect()
+            });
 
             if let Err(_) =
-                encode_and_send(message, &mut att_payload, notifier).await
+                encode_and_send(message, codec, &mut att_payload, notifier)
+                    .await
             {
                 error!("Failed to encode and send message");
             }
             packet_counter += 1;
             att_payload.clear();
+            decimator.report_backlog(sub.len(), ADS_CAP);
         }
     }
 }
@@ -0,0 +1,42 @@
+extern crate alloc;
+
+use crate::prelude::*;
+use crate::tasks::imu::QUATERNION_WATCH;
+use heapless::Vec;
+use prost::Message;
+
+pub(crate) trait ImuStreamNotifier {
+    async fn notify_quaternion(
+        &self,
+        data: &Vec<u8, ATT_MTU>,
+    ) -> Result<(), super::Error>;
+}
+
+/// Notifies every orientation the IMU task publishes, one
+/// [`icd::imu_proto::ImuQuaternion`] per notification.
+pub(crate) async fn quaternion_stream_notify<T: ImuStreamNotifier>(
+    notifier: &T,
+) {
+    let mut receiver = QUATERNION_WATCH
+        .dyn_receiver()
+        .expect("Failed to create quaternion receiver");
+    let mut att_payload: Vec<u8, ATT_MTU> = Vec::new();
+
+    loop {
+        let quat = receiver.changed().await;
+        let frame = icd::imu_proto::ImuQuaternion::from(&quat);
+
+        let mut out_buffer = alloc::vec::Vec::new();
+        frame.encode(&mut out_buffer).unwrap();
+
+        att_payload.clear();
+        if att_payload.extend_from_slice(&out_buffer).is_err() {
+            warn!("Quaternion frame too large for ATT payload");
+            continue;
+        }
+
+        if let Err(_) = notifier.notify_quaternion(&att_payload).await {
+            warn!("Failed to notify quaternion");
+        }
+    }
+}
@@ -2,9 +2,10 @@ extern crate alloc;
 
 use crate::prelude::*;
 use crate::tasks::mic::adpcm::AdpcmEncoder;
-use crate::tasks::mic::{MIC_BUF_SAMPLES, MIC_STREAM_CH, MIC_WATCH};
+use crate::tasks::mic::{
+    MIC_BUF_SAMPLES, MIC_CONFIG_WATCH, MIC_STREAM_CH, MIC_WATCH,
+};
 use embassy_futures::select::{select, Either};
-use embassy_time::Instant;
 use heapless::Vec;
 use prost::Message;
 
@@ -34,15 +35,25 @@ pub(crate) async fn mic_stream_notify<T: MicStreamNotifier>(
         match select(sub.next_message_pure(), mic_watcher.changed()).await {
             Either::First(pcm_buf) => {
                 let (predictor, step_index) = encoder.decoder_state();
-                encoder.encode_block(&pcm_buf, &mut adpcm_buf);
+                encoder.encode_block(&pcm_buf.data, &mut adpcm_buf);
 
+                let sample_rate = MIC_CONFIG_WATCH
+                    .try_get()
+                    .unwrap_or_default()
+                    .sample_rate
+                    .as_hz();
+                // A PCM buffer does not fit one notification, so BLE always
+                // streams ADPCM whatever the configured stream codec.
                 let frame = icd::mic_proto::MicDataFrame {
-                    ts: Instant::now().as_micros(),
+                    ts: pcm_buf.ts,
                     packet_counter,
-                    sample_rate: 16000, // TODO: read from config
+                    sample_rate,
                     predictor,
                     step_index,
                     adpcm_data: adpcm_buf.to_vec(),
+                    seq: packet_counter as u32,
+                    pcm_data: alloc::vec::Vec::new(),
+                    codec: icd::mic_proto::MicCodec::Adpcm as i32,
                 };
 
                 let mut out_buffer = alloc::vec::Vec::new();
@@ -1,6 +1,7 @@
 use derive_more::From;
 
 pub mod ads_stream;
+pub mod imu_stream;
 pub mod mic_stream;
 // pub use ads_stream::*;
 
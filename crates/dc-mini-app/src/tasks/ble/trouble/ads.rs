@@ -1,7 +1,8 @@
-use super::{gatt::Server, ATT_MTU};
+use super::{gatt::Server, DataStream, Link, ATT_MTU};
 use crate::prelude::{info, unwrap};
 use crate::tasks::ble::ads_stream::{self, AdsStreamNotifier};
-use dc_mini_icd::{AdsConfig, ADS_MAX_CHANNELS};
+use dc_mini_icd::{AdsConfig, StreamKind, ADS_MAX_CHANNELS};
+use embassy_futures::select::select;
 use heapless::Vec;
 use trouble_host::prelude::*;
 
@@ -183,6 +184,20 @@ pub struct AdsService {
     pub data_stream: Vec<u8, ATT_MTU>,
     #[characteristic(uuid = "32000300-af46-43af-a0ba-4dbeb457f51c", write)]
     pub command: u8,
+    /// Decimation factor of the ADS stream (1 sends every sample).
+    #[characteristic(
+        uuid = "32000301-af46-43af-a0ba-4dbeb457f51c",
+        read,
+        write
+    )]
+    pub stream_decimation: u8,
+    /// Codec of the ADS stream, a [`dc_mini_icd::AdsCodec`] discriminant.
+    #[characteristic(
+        uuid = "32000302-af46-43af-a0ba-4dbeb457f51c",
+        read,
+        write
+    )]
+    pub stream_codec: u8,
 }
 
 /// Notifier that holds only the characteristic handle (Copy) and a borrow
@@ -202,9 +217,12 @@ impl<P: PacketPool> AdsStreamNotifier for TroubleNotifier<'_, '_, '_, P> {
     }
 }
 
+/// Streams to the central while it is subscribed and, for shared streams,
+/// while this link holds the stream.
 pub async fn ads_stream_notify<P: PacketPool>(
     server: &Server<'_>,
     conn: &GattConnection<'_, '_, P>,
+    link: &Link,
 ) {
     let notifier =
         TroubleNotifier { handle: server.ads.data_stream.clone(), conn };
@@ -217,7 +235,11 @@ pub async fn ads_stream_notify<P: PacketPool>(
     let mtu = att_mtu - 3;
     info!("ADS ATT mtu = {}, max notify value = {}", att_mtu, mtu);
 
-    ads_stream::ads_stream_notify(&notifier, mtu).await
+    loop {
+        let grant = link.acquire(DataStream::Ads).await;
+        select(ads_stream::ads_stream_notify(&notifier, mtu), grant.revoked())
+            .await;
+    }
 }
 
 pub async fn update_ads_characteristics(
@@ -235,6 +257,12 @@ pub async fn update_ads_characteristics(
         values
     }
 
+    unwrap!(server.set(
+        &server.ads.stream_decimation,
+        &crate::decimation::config(StreamKind::Ads).factor,
+    ));
+    unwrap!(server
+        .set(&server.ads.stream_codec, &(crate::codec::ads_codec() as u8)));
     unwrap!(server.set(&server.ads.daisy_en, &config.daisy_en));
     unwrap!(server.set(&server.ads.clk_en, &config.clk_en));
     unwrap!(server.set(&server.ads.sample_rate, &(config.sample_rate as u8),));
@@ -4,7 +4,7 @@ use trouble_host::prelude::*;
 
 /// Create an advertiser, attach the GATT server, and wait for a connection.
 pub async fn advertise<'values, 'server, C: Controller>(
-    name: &'values str,
+    name: &str,
     peripheral: &mut Peripheral<'values, C, DefaultPacketPool>,
     server: &'server Server<'values>,
 ) -> Result<
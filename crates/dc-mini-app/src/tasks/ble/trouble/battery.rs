@@ -1,5 +1,6 @@
 use super::Server;
 use crate::prelude::*;
+use crate::tasks::power_control::BATTERY_WATCH;
 use trouble_host::prelude::*;
 
 /// Battery Service (UUID: 0x180F)
@@ -19,7 +20,11 @@ impl<'d> Server<'d> {
         _app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
     ) {
         if handle == self.battery.battery_level.handle {
-            update_battery_characteristics(self, 100).await;
+            // Keeps the last level while the PMIC cannot be read.
+            if let Some(status) = latest_battery_status().await {
+                update_battery_characteristics(self, status.state_of_charge)
+                    .await;
+            }
         }
     }
 }
@@ -33,3 +38,25 @@ pub async fn update_battery_characteristics(
     let level = battery_level.min(100);
     unwrap!(server.set(&server.battery.battery_level, &level));
 }
+
+/// Notifies the connected central whenever the reported level changes.
+pub async fn battery_level_notify<P: PacketPool>(
+    server: &Server<'_>,
+    conn: &GattConnection<'_, '_, P>,
+) {
+    let mut receiver = unwrap!(BATTERY_WATCH.receiver());
+    let mut last_level = None;
+    loop {
+        let Some(status) = receiver.changed().await else {
+            continue;
+        };
+        let level = status.state_of_charge.min(100);
+        if last_level == Some(level) {
+            continue;
+        }
+        last_level = Some(level);
+        if server.battery.battery_level.notify(conn, &level).await.is_err() {
+            warn!("Failed to notify battery level");
+        }
+    }
+}
@@ -1,17 +1,113 @@
+//! Device clock over BLE.
+//!
+//! A central running the dc-mini host sets the clock with two-way
+//! exchanges on the [`TimeService`], see [`dc_mini_icd::TimeSample`]:
+//! it writes its Unix time in microseconds (8 bytes, little endian) to
+//! `exchange`, reads the [`TimeExchange`] back and writes the completed
+//! sample to `sample`. Any other central is asked for its Current Time
+//! Service once, which sets the clock less precisely.
+
+use super::gatt::Server;
 use crate::prelude::*;
+use dc_mini_icd::{TimeExchange, TimeSample};
 use embassy_futures::select::select;
 use embassy_futures::select::Either;
 use embassy_time::Instant;
+use heapless::Vec;
 use trouble_host::prelude::*;
 
 use super::BleController;
 
+const EXCHANGE_LEN: usize = TimeExchange::SIZE;
+const SAMPLE_LEN: usize = TimeSample::SIZE;
+
+#[gatt_service(uuid = "32400000-af46-43af-a0ba-4dbeb457f51c")]
+pub struct TimeService {
+    #[characteristic(
+        uuid = "32400001-af46-43af-a0ba-4dbeb457f51c",
+        read,
+        write
+    )]
+    pub exchange: Vec<u8, EXCHANGE_LEN>,
+    #[characteristic(uuid = "32400002-af46-43af-a0ba-4dbeb457f51c", write)]
+    pub sample: Vec<u8, SAMPLE_LEN>,
+}
+
+impl Server<'_> {
+    /// Stamps the exchange as read at `received_at`.
+    pub fn handle_time_read(&self, handle: u16, received_at: Instant) {
+        if handle != self.time.exchange.handle {
+            return;
+        }
+        let Some(mut exchange) = self
+            .get(&self.time.exchange)
+            .ok()
+            .and_then(|value| TimeExchange::from_bytes(&value))
+        else {
+            return;
+        };
+        exchange.device_tx_us = received_at.as_micros();
+        let value = unwrap!(Vec::from_slice(&exchange.to_bytes()));
+        unwrap!(self.set(&self.time.exchange, &value));
+    }
+
+    /// Starts an exchange written at `received_at`, or applies a completed
+    /// one.
+    pub fn handle_time_write(&self, handle: u16, received_at: Instant) {
+        if handle == self.time.exchange.handle {
+            let Ok(value) = self.get(&self.time.exchange) else {
+                return;
+            };
+            let Some(host_tx_us) =
+                value.get(..8).and_then(|bytes| bytes.try_into().ok())
+            else {
+                warn!("[ble] malformed clock exchange");
+                return;
+            };
+            let exchange = TimeExchange {
+                host_tx_us: u64::from_le_bytes(host_tx_us),
+                device_rx_us: received_at.as_micros(),
+                device_tx_us: 0,
+            };
+            let value = unwrap!(Vec::from_slice(&exchange.to_bytes()));
+            unwrap!(self.set(&self.time.exchange, &value));
+        } else if handle == self.time.sample.handle {
+            let Some(sample) = self
+                .get(&self.time.sample)
+                .ok()
+                .and_then(|value| TimeSample::from_bytes(&value))
+            else {
+                warn!("[ble] malformed clock sample");
+                return;
+            };
+            let error_us = crate::CLOCK.apply_exchange(&sample);
+            info!(
+                "[ble] clock synced, delay {}us, error {}us, drift {}ppb",
+                sample.delay_us(),
+                error_us,
+                crate::CLOCK.drift_ppb()
+            );
+        }
+    }
+}
+
+/// Sets the clock from the central's Current Time Service, unless it has
+/// been set more precisely by an exchange.
 pub async fn sync_time<'a>(
     stack: &'a Stack<'a, BleController, DefaultPacketPool>,
     conn: &Connection<'a, DefaultPacketPool>,
 ) {
+    if crate::CLOCK.exchanged() {
+        return;
+    }
     info!("[ble] synchronizing time");
-    let client = unwrap!(GattClient::<_, _, 10>::new(stack, conn).await);
+    let client = match GattClient::<_, _, 10>::new(stack, conn).await {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("[ble] failed to create gatt client: {:?}", e);
+            return;
+        }
+    };
     match select(client.task(), async {
         let services =
             client.services_by_uuid(&Uuid::new_short(0x1805)).await?;
@@ -23,7 +119,10 @@ pub async fn sync_time<'a>(
             let mut data = [0; 10];
             client.read_characteristic(&c, &mut data[..]).await?;
 
-            if let Some(time) = parse_time(data) {
+            // An exchange may have completed while reading.
+            if let Some(time) =
+                parse_time(data).filter(|_| !crate::CLOCK.exchanged())
+            {
                 let time_of_boot = time
                     - time::Duration::microseconds(
                         Instant::now().as_micros() as i64
@@ -39,7 +138,7 @@ pub async fn sync_time<'a>(
     })
     .await
     {
-        Either::First(_) => panic!("[ble] gatt client exited prematurely"),
+        Either::First(_) => warn!("[ble] gatt client exited prematurely"),
         Either::Second(Ok(_)) => {
             info!("[ble] time sync completed");
         }
@@ -0,0 +1,90 @@
+use crate::prelude::*;
+use crate::tasks::ads::ADS_WATCH;
+use crate::tasks::mic::MIC_WATCH;
+use crate::tasks::power_control::sleep::ble_links;
+use embassy_time::Ticker;
+use trouble_host::prelude::*;
+
+use super::BleController;
+
+/// How often the streaming load is re-evaluated.
+const LOAD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Streaming load the connection parameters are chosen for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Load {
+    Idle,
+    /// ADS (and IMU) frames only.
+    Ads,
+    /// Microphone audio, with or without ADS frames.
+    Audio,
+}
+
+impl Load {
+    fn current() -> Self {
+        if MIC_WATCH.try_get().unwrap_or(false) {
+            Load::Audio
+        } else if ADS_WATCH.try_get().unwrap_or(false) {
+            Load::Ads
+        } else {
+            Load::Idle
+        }
+    }
+
+    /// Short intervals with long connection events while streaming, and a
+    /// slow interval with peripheral latency when idle so the radio can
+    /// sleep. With several `links` the connection events are shortened so
+    /// every link gets a share of each interval.
+    fn params(self, links: u8) -> ConnectParams {
+        // Intervals in microseconds; 7.5 ms is the shortest the spec allows.
+        let (min_interval, max_interval, max_latency) = match self {
+            Load::Idle => (100_000, 200_000, 4),
+            Load::Ads => (15_000, 30_000, 0),
+            Load::Audio => (7_500, 15_000, 0),
+        };
+        let max_event = match self {
+            Load::Idle => 0,
+            _ => max_interval / links.max(1) as u64,
+        };
+        ConnectParams {
+            min_connection_interval: Duration::from_micros(min_interval),
+            max_connection_interval: Duration::from_micros(max_interval),
+            max_latency,
+            min_event_length: Duration::from_micros(0),
+            max_event_length: Duration::from_micros(max_event),
+            supervision_timeout: Duration::from_secs(4),
+        }
+    }
+}
+
+/// Switches the link to the 2M PHY, then keeps the connection parameters
+/// matched to the streaming load for as long as the connection lasts.
+///
+/// The central may reject or adjust a request; a rejected update is retried
+/// on the next load change only.
+pub async fn manage_connection_params<'a>(
+    stack: &'a Stack<'a, BleController, DefaultPacketPool>,
+    conn: &Connection<'a, DefaultPacketPool>,
+) {
+    if let Err(e) = conn.set_phy(stack, PhyKind::Le2M).await {
+        warn!("[ble] 2M PHY request failed: {:?}", e);
+    }
+
+    let mut applied = None;
+    let mut ticker = Ticker::every(LOAD_POLL_INTERVAL);
+    loop {
+        let load = Load::current();
+        let links = ble_links();
+        if applied != Some((load, links)) {
+            info!("[ble] connection load {:?} over {} links", load, links);
+            let params = load.params(links);
+            if let Err(e) = conn.update_connection_params(stack, &params).await
+            {
+                warn!("[ble] connection parameter update failed: {:?}", e);
+            }
+            applied = Some((load, links));
+        }
+        ticker.next().await;
+    }
+}
@@ -0,0 +1,129 @@
+//! SD card file downloads over BLE.
+//!
+//! The same transfers as the USB `fs/read` endpoints, see
+//! [`crate::tasks::open_transfer`]. A central writes requests to
+//! `control` and gets the answer as a `control` notification, integers
+//! little endian:
+//!
+//! - open: `[0x01, name…]`, answered `[0x01, ok, size (u32), message…]`
+//! - read: `[0x02, offset (u32), length (u16)]`, answered with a `data`
+//!   notification `[offset (u32), eof, bytes…]`, or on `control` with
+//!   `[0x02, 0, 0 (u32), message…]` if the read failed
+//! - close: `[0x03]`, answered `[0x03, ok, size (u32), message…]`
+//!
+//! A read returns at most what fits one notification on the link, and never
+//! more than [`FILE_CHUNK_MAX`] bytes; the central moves on by the length
+//! it got.
+
+use super::gatt::Server;
+use super::ATT_MTU;
+use crate::prelude::*;
+use heapless::{String, Vec};
+use trouble_host::prelude::*;
+
+const OP_OPEN: u8 = 0x01;
+const OP_READ: u8 = 0x02;
+const OP_CLOSE: u8 = 0x03;
+
+/// Offset and end-of-file flag ahead of the bytes in a `data` notification.
+const DATA_HEADER_LEN: usize = 5;
+/// Most file bytes carried by one `data` notification.
+pub const FILE_CHUNK_MAX: usize = ATT_MTU - 3 - DATA_HEADER_LEN;
+
+#[gatt_service(uuid = "32500000-af46-43af-a0ba-4dbeb457f51c")]
+pub struct FileService {
+    #[characteristic(
+        uuid = "32500001-af46-43af-a0ba-4dbeb457f51c",
+        write,
+        notify
+    )]
+    pub control: Vec<u8, ATT_MTU>,
+    #[characteristic(uuid = "32500002-af46-43af-a0ba-4dbeb457f51c", notify)]
+    pub data: Vec<u8, ATT_MTU>,
+}
+
+/// Transfer owner for the central on `conn`.
+pub fn transfer_owner<P: PacketPool>(
+    conn: &GattConnection<'_, '_, P>,
+) -> TransferOwner {
+    TransferOwner::Ble(conn.raw().handle().raw())
+}
+
+impl Server<'_> {
+    /// Answers a request written to the file `control` characteristic.
+    pub async fn handle_file_write<P: PacketPool>(
+        &self,
+        handle: u16,
+        conn: &GattConnection<'_, '_, P>,
+    ) {
+        if handle != self.files.control.handle {
+            return;
+        }
+        let Ok(request) = self.get(&self.files.control) else {
+            return;
+        };
+        let owner = transfer_owner(conn);
+        match request.split_first() {
+            Some((&OP_OPEN, name)) => {
+                let name = core::str::from_utf8(name)
+                    .ok()
+                    .and_then(|name| String::try_from(name).ok());
+                let result = match name {
+                    Some(name) => open_transfer(owner, name).await,
+                    None => Err("Invalid file name"),
+                };
+                self.notify_file_result(conn, OP_OPEN, result).await;
+            }
+            Some((&OP_READ, &[o0, o1, o2, o3, l0, l1])) => {
+                let offset = u32::from_le_bytes([o0, o1, o2, o3]);
+                let fits = (conn.raw().att_mtu() as usize)
+                    .saturating_sub(3 + DATA_HEADER_LEN)
+                    .min(FILE_CHUNK_MAX);
+                let length = (u16::from_le_bytes([l0, l1]) as usize).min(fits);
+                match read_transfer(owner, offset, length).await {
+                    Ok((data, eof)) => {
+                        let mut value = Vec::<u8, ATT_MTU>::new();
+                        unwrap!(value.extend_from_slice(&offset.to_le_bytes()));
+                        unwrap!(value.push(eof as u8));
+                        unwrap!(value.extend_from_slice(&data));
+                        if let Err(e) =
+                            self.files.data.notify(conn, &value).await
+                        {
+                            warn!("[ble] Error notifying file data: {:?}", e);
+                        }
+                    }
+                    Err(msg) => {
+                        self.notify_file_result(conn, OP_READ, Err(msg)).await
+                    }
+                }
+            }
+            Some((&OP_CLOSE, &[])) => {
+                let result = close_transfer(owner).await;
+                self.notify_file_result(conn, OP_CLOSE, result).await;
+            }
+            _ => warn!("[ble] malformed file request"),
+        }
+    }
+
+    async fn notify_file_result<P: PacketPool>(
+        &self,
+        conn: &GattConnection<'_, '_, P>,
+        op: u8,
+        result: Result<u32, &'static str>,
+    ) {
+        let (ok, size, message) = match result {
+            Ok(size) => (true, size, "OK"),
+            Err(msg) => (false, 0, msg),
+        };
+        let mut value = Vec::<u8, ATT_MTU>::new();
+        unwrap!(value.push(op));
+        unwrap!(value.push(ok as u8));
+        unwrap!(value.extend_from_slice(&size.to_le_bytes()));
+        let room = value.capacity() - value.len();
+        let message = &message.as_bytes()[..message.len().min(room)];
+        unwrap!(value.extend_from_slice(message));
+        if let Err(e) = self.files.control.notify(conn, &value).await {
+            warn!("[ble] Error notifying file control: {:?}", e);
+        }
+    }
+}
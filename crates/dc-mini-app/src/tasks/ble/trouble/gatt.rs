@@ -1,4 +1,7 @@
-use super::{ads::*, dfu::*, mic::*, session::*};
+use super::{
+    ads::*, clock::*, dfu::*, file_transfer::*, imu::*, link::*, mic::*,
+    security::*, session::*,
+};
 use crate::events::DfuEvent;
 use crate::prelude::*;
 use crate::tasks::dfu::{DfuPartition, DfuResources};
@@ -84,9 +87,12 @@ pub struct Server {
     pub device_info: DeviceInfoService,
     pub profile: ProfileService,
     pub ads: AdsService,
+    pub imu: ImuService,
     pub mic: MicService,
     pub session: SessionService,
+    pub time: TimeService,
     pub dfu: NrfDfuService,
+    pub files: FileService,
 }
 
 impl<'d> Server<'d> {
@@ -307,6 +313,25 @@ impl<'d> Server<'d> {
             handle_vector_field_write!(self, lead_off_sensn, ads_config);
         } else if handle == self.ads.lead_off_flip.handle {
             handle_vector_field_write!(self, lead_off_flip, ads_config);
+        } else if handle == self.ads.stream_decimation.handle {
+            if let Ok(factor) = self.get(&self.ads.stream_decimation) {
+                let config = dc_mini_icd::StreamConfig {
+                    stream: dc_mini_icd::StreamKind::Ads,
+                    factor,
+                    mode: dc_mini_icd::DecimationMode::Decimate,
+                };
+                if !crate::decimation::set_config(&config) {
+                    warn!("Invalid ADS stream decimation {}", factor);
+                }
+            }
+        } else if handle == self.ads.stream_codec.handle {
+            if let Ok(value) = self.get(&self.ads.stream_codec) {
+                match value {
+                    0 => crate::codec::set_ads_codec(AdsCodec::Raw),
+                    1 => crate::codec::set_ads_codec(AdsCodec::Delta),
+                    _ => warn!("Invalid ADS stream codec {}", value),
+                }
+            }
         } else if handle == self.ads.command.handle {
             if let Ok(value) = self.get(&self.ads.command) {
                 let evt = AdsEvent::try_from(value);
@@ -387,6 +412,82 @@ impl<'d> Server<'d> {
         app_ctx.save_mic_config(mic_config).await;
     }
 
+    pub async fn handle_imu_read_event(
+        &self,
+        handle: u16,
+        app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+    ) {
+        let mut app_ctx = app_context.lock().await;
+        let imu_config = app_ctx
+            .profile_manager
+            .get_imu_config()
+            .await
+            .cloned()
+            .unwrap_or_default();
+
+        if handle == self.imu.quaternion_rate.handle {
+            unwrap!(self
+                .set(&self.imu.quaternion_rate, &imu_config.quaternion_rate));
+        }
+    }
+
+    /// Starting and stopping mirror the USB quaternion endpoints: the
+    /// orientation stream is enabled in the IMU config, and the IMU keeps
+    /// running on stop while the ADS stream carries its readings.
+    pub async fn handle_imu_write_event(
+        &self,
+        handle: u16,
+        app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+    ) {
+        let mut app_ctx = app_context.lock().await;
+        if !app_ctx.capabilities().imu_present {
+            warn!("[ble] no IMU on this board");
+            return;
+        }
+        let mut imu_config = app_ctx
+            .profile_manager
+            .get_imu_config()
+            .await
+            .cloned()
+            .unwrap_or_default();
+
+        if handle == self.imu.quaternion_rate.handle {
+            if let Ok(value) = self.get(&self.imu.quaternion_rate) {
+                if value != 0 {
+                    imu_config.quaternion_rate = value;
+                }
+            }
+        } else if handle == self.imu.command.handle {
+            if let Ok(value) = self.get(&self.imu.command) {
+                match value {
+                    0 => {
+                        imu_config.quaternion_enabled = true;
+                        app_ctx.save_imu_config(imu_config).await;
+                        app_ctx
+                            .event_sender
+                            .send(ImuEvent::StartStream.into())
+                            .await;
+                        return;
+                    }
+                    1 => {
+                        imu_config.quaternion_enabled = false;
+                        app_ctx.save_imu_config(imu_config).await;
+                        if ADS_WATCH.try_get() != Some(true) {
+                            app_ctx
+                                .event_sender
+                                .send(ImuEvent::StopStream.into())
+                                .await;
+                        }
+                        return;
+                    }
+                    _ => warn!("Unknown IMU command: {}", value),
+                };
+            }
+        }
+
+        app_ctx.save_imu_config(imu_config).await;
+    }
+
     /// Handle a DFU write (control or packet characteristic).
     ///
     /// On the first DFU write per connection, acquires the DFU lock and checks
@@ -439,6 +540,7 @@ impl<'d> Server<'d> {
 pub async fn gatt_server_task<P: PacketPool>(
     server: &Server<'_>,
     conn: &GattConnection<'_, '_, P>,
+    link: &Link,
     app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
     dfu_resources: &'static DfuResources,
 ) {
@@ -447,6 +549,8 @@ pub async fn gatt_server_task<P: PacketPool>(
     let mut dfu_target: Target = Target::new(dfu_size, fw_info(), hw_info());
     let mut dfu_partition = dfu_resources.dfu_partition();
     let mut dfu_started = false;
+    let mut pairing = Pairing::None;
+    let mut trusted = false;
 
     loop {
         match conn.next().await {
@@ -454,7 +558,51 @@ pub async fn gatt_server_task<P: PacketPool>(
                 info!("[gatt] Disconnected: {:?}", reason);
                 break;
             }
+            GattConnectionEvent::PairingComplete { security_level, bond } => {
+                info!("[gatt] Paired: {:?}", security_level);
+                pairing = match bond {
+                    Some(bond) if store_bond(&bond, app_context).await => {
+                        Pairing::Bonded
+                    }
+                    _ => Pairing::Unbonded,
+                };
+            }
+            GattConnectionEvent::PairingFailed(e) => {
+                warn!("[gatt] Pairing failed: {:?}", e);
+            }
             GattConnectionEvent::Gatt { event } => {
+                // Clock exchanges are stamped on arrival.
+                let received_at = embassy_time::Instant::now();
+                let handle = match &event {
+                    GattEvent::Read(event) => Some(event.handle()),
+                    GattEvent::Write(event) => Some(event.handle()),
+                    _ => None,
+                };
+                let protected =
+                    handle.is_some_and(|h| server.requires_encryption(h));
+                if protected && !trusted {
+                    trusted =
+                        is_trusted(conn.raw(), pairing, app_context).await;
+                }
+                if protected && !trusted {
+                    // Centrals react to missing encryption by pairing and
+                    // retrying. An encrypted but unbonded central has no
+                    // way in until the bond is cleared.
+                    let code = match conn.raw().security_level() {
+                        Ok(SecurityLevel::NoEncryption) | Err(_) => {
+                            AttErrorCode::INSUFFICIENT_ENCRYPTION
+                        }
+                        Ok(_) => AttErrorCode::INSUFFICIENT_AUTHENTICATION,
+                    };
+                    match event.reject(code) {
+                        Ok(reply) => reply.send().await,
+                        Err(e) => {
+                            warn!("[gatt] error sending response: {:?}", e)
+                        }
+                    }
+                    continue;
+                }
+
                 let mut dfu_status = None;
 
                 // For reads: populate attribute values BEFORE accept() so
@@ -506,10 +654,32 @@ pub async fn gatt_server_task<P: PacketPool>(
                             server
                                 .handle_mic_read_event(handle, app_context)
                                 .await;
+                        } else if handle >= server.imu.quaternion_rate.handle
+                            && handle <= server.imu.command.handle
+                        {
+                            server
+                                .handle_imu_read_event(handle, app_context)
+                                .await;
+                        } else {
+                            server.handle_time_read(handle, received_at);
                         }
                         None
                     }
-                    GattEvent::Write(event) => Some(event.handle()),
+                    GattEvent::Write(event) => {
+                        let handle = event.handle();
+                        if Some(handle) == server.ads.data_stream.cccd_handle {
+                            link.set_cccd(DataStream::Ads, event.data());
+                        } else if Some(handle)
+                            == server.mic.data_stream.cccd_handle
+                        {
+                            link.set_cccd(DataStream::Mic, event.data());
+                        } else if Some(handle)
+                            == server.imu.quaternion_stream.cccd_handle
+                        {
+                            link.set_cccd(DataStream::Imu, event.data());
+                        }
+                        Some(handle)
+                    }
                     _ => None,
                 };
 
@@ -556,27 +726,46 @@ pub async fn gatt_server_task<P: PacketPool>(
                         server
                             .handle_mic_write_event(handle, app_context)
                             .await;
+                    } else if handle >= server.imu.quaternion_rate.handle
+                        && handle <= server.imu.command.handle
+                    {
+                        server
+                            .handle_imu_write_event(handle, app_context)
+                            .await;
+                    } else if handle >= server.files.control.handle
+                        && handle <= server.files.data.handle
+                    {
+                        server.handle_file_write(handle, conn).await;
+                    } else {
+                        server.handle_time_write(handle, received_at);
                     }
                 }
 
                 // Handle DFU completion after sending the GATT response
                 if let Some(DfuStatus::DoneReset) = dfu_status {
-                    info!("[dfu] Transfer complete, marking updated");
-                    {
-                        let app_ctx = app_context.lock().await;
-                        app_ctx
-                            .event_sender
-                            .send(DfuEvent::Complete.into())
-                            .await;
-                    }
-                    match dfu_resources.mark_updated() {
+                    info!("[dfu] Transfer complete, verifying signature");
+                    match dfu_resources.verify_and_mark_updated().await {
                         Ok(()) => {
+                            {
+                                let app_ctx = app_context.lock().await;
+                                app_ctx
+                                    .event_sender
+                                    .send(DfuEvent::Complete.into())
+                                    .await;
+                            }
                             info!("[dfu] Marked updated, resetting in 4s");
                             embassy_time::Timer::after_secs(4).await;
                             cortex_m::peripheral::SCB::sys_reset();
                         }
-                        Err(_e) => {
-                            warn!("[dfu] Failed to mark updated");
+                        Err(e) => {
+                            warn!("[dfu] Update rejected: {:?}", e);
+                            dfu_resources.finish();
+                            dfu_started = false;
+                            let app_ctx = app_context.lock().await;
+                            app_ctx
+                                .event_sender
+                                .send(DfuEvent::Failed.into())
+                                .await;
                         }
                     }
                 }
@@ -584,7 +773,11 @@ pub async fn gatt_server_task<P: PacketPool>(
             _ => {}
         }
     }
+    // Release the card if the link dropped mid-download.
+    let _ = close_transfer(transfer_owner(conn)).await;
     if dfu_started {
+        // Release the DFU lock if the link dropped mid-transfer.
+        dfu_resources.finish();
         let app_ctx = app_context.lock().await;
         app_ctx.event_sender.send(DfuEvent::Aborted.into()).await;
     }
@@ -0,0 +1,69 @@
+use super::{gatt::Server, DataStream, Link, ATT_MTU};
+use crate::prelude::{info, unwrap};
+use crate::tasks::ble::imu_stream::{self, ImuStreamNotifier};
+use dc_mini_icd::ImuConfig;
+use embassy_futures::select::select;
+use heapless::Vec;
+use trouble_host::prelude::*;
+
+#[gatt_service(uuid = "34100000-af46-43af-a0ba-4dbeb457f51c")]
+pub struct ImuService {
+    #[characteristic(
+        uuid = "34000200-af46-43af-a0ba-4dbeb457f51c",
+        read,
+        notify
+    )]
+    pub quaternion_stream: Vec<u8, ATT_MTU>,
+    /// Orientation rate in Hz.
+    #[characteristic(
+        uuid = "34000000-af46-43af-a0ba-4dbeb457f51c",
+        read,
+        write
+    )]
+    pub quaternion_rate: u8,
+    /// 0 starts the orientation stream, 1 stops it.
+    #[characteristic(uuid = "34000300-af46-43af-a0ba-4dbeb457f51c", write)]
+    pub command: u8,
+}
+
+struct TroubleNotifier<'a, 'b, 'c, P: PacketPool> {
+    handle: Characteristic<Vec<u8, ATT_MTU>>,
+    conn: &'a GattConnection<'b, 'c, P>,
+}
+
+impl<P: PacketPool> ImuStreamNotifier for TroubleNotifier<'_, '_, '_, P> {
+    async fn notify_quaternion(
+        &self,
+        data: &Vec<u8, ATT_MTU>,
+    ) -> Result<(), super::Error> {
+        self.handle.notify(self.conn, data).await?;
+        Ok(())
+    }
+}
+
+/// Streams to the central while it is subscribed.
+pub async fn imu_stream_notify<P: PacketPool>(
+    server: &Server<'_>,
+    conn: &GattConnection<'_, '_, P>,
+    link: &Link,
+) {
+    let notifier =
+        TroubleNotifier { handle: server.imu.quaternion_stream.clone(), conn };
+
+    loop {
+        let grant = link.acquire(DataStream::Imu).await;
+        info!("[ble] streaming orientation");
+        select(
+            imu_stream::quaternion_stream_notify(&notifier),
+            grant.revoked(),
+        )
+        .await;
+    }
+}
+
+pub async fn update_imu_characteristics(
+    server: &Server<'_>,
+    config: &ImuConfig,
+) {
+    unwrap!(server.set(&server.imu.quaternion_rate, &config.quaternion_rate));
+}
@@ -0,0 +1,122 @@
+//! Per-connection state shared between the GATT server and the stream
+//! notifiers of one link.
+//!
+//! Up to [`CONNECTIONS_MAX`](super::CONNECTIONS_MAX) centrals are served at
+//! once. Each link only encodes the data streams its central subscribed
+//! to. ADS and orientation frames are small enough to go to every
+//! subscriber; the mic stream is handed to one link at a time, the first
+//! to subscribe.
+
+use crate::prelude::*;
+use crate::tasks::power_control::sleep::set_ble_connected;
+use portable_atomic::{AtomicBool, Ordering};
+
+/// How often a notifier checks whether its central subscribed.
+const SUBSCRIPTION_POLL: Duration = Duration::from_millis(100);
+
+/// CCCD value bit enabling notifications.
+const CCCD_NOTIFY: u16 = 0x0001;
+
+static MIC_OWNER: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataStream {
+    Ads,
+    Imu,
+    Mic,
+}
+
+pub struct Link {
+    ads_subscribed: AtomicBool,
+    imu_subscribed: AtomicBool,
+    mic_subscribed: AtomicBool,
+}
+
+impl Link {
+    /// Registers a new connection with the auto-sleep tracker; dropping
+    /// the `Link` unregisters it.
+    pub fn new() -> Self {
+        set_ble_connected(true);
+        Self {
+            ads_subscribed: AtomicBool::new(false),
+            imu_subscribed: AtomicBool::new(false),
+            mic_subscribed: AtomicBool::new(false),
+        }
+    }
+
+    fn flag(&self, stream: DataStream) -> &AtomicBool {
+        match stream {
+            DataStream::Ads => &self.ads_subscribed,
+            DataStream::Imu => &self.imu_subscribed,
+            DataStream::Mic => &self.mic_subscribed,
+        }
+    }
+
+    /// Records a CCCD write for `stream`.
+    pub fn set_cccd(&self, stream: DataStream, value: &[u8]) {
+        let bits = match value {
+            [lo, hi, ..] => u16::from_le_bytes([*lo, *hi]),
+            [lo] => *lo as u16,
+            [] => 0,
+        };
+        let subscribed = bits & CCCD_NOTIFY != 0;
+        info!("[ble] {:?} stream subscribed: {}", stream, subscribed);
+        self.flag(stream).store(subscribed, Ordering::SeqCst);
+    }
+
+    pub fn subscribed(&self, stream: DataStream) -> bool {
+        self.flag(stream).load(Ordering::SeqCst)
+    }
+
+    /// Waits until the central subscribes to `stream` and, for the mic,
+    /// until no other link owns it.
+    pub async fn acquire(&self, stream: DataStream) -> StreamGrant<'_> {
+        loop {
+            if self.subscribed(stream)
+                && (stream != DataStream::Mic
+                    || MIC_OWNER
+                        .compare_exchange(
+                            false,
+                            true,
+                            Ordering::SeqCst,
+                            Ordering::SeqCst,
+                        )
+                        .is_ok())
+            {
+                return StreamGrant { link: self, stream };
+            }
+            Timer::after(SUBSCRIPTION_POLL).await;
+        }
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        set_ble_connected(false);
+    }
+}
+
+/// Permission for one link to send a data stream; dropping it hands the
+/// stream on.
+pub struct StreamGrant<'a> {
+    link: &'a Link,
+    stream: DataStream,
+}
+
+impl StreamGrant<'_> {
+    /// Resolves once the central unsubscribes.
+    pub async fn revoked(&self) {
+        while self.link.subscribed(self.stream) {
+            Timer::after(SUBSCRIPTION_POLL).await;
+        }
+    }
+}
+
+impl Drop for StreamGrant<'_> {
+    fn drop(&mut self) {
+        if self.stream == DataStream::Mic {
+            MIC_OWNER.store(false, Ordering::SeqCst);
+        }
+    }
+}
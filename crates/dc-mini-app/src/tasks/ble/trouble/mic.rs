@@ -1,7 +1,8 @@
-use super::{gatt::Server, ATT_MTU};
+use super::{gatt::Server, DataStream, Link, ATT_MTU};
 use crate::prelude::{info, unwrap};
 use crate::tasks::ble::mic_stream::{self, MicStreamNotifier};
 use dc_mini_icd::MicConfig;
+use embassy_futures::select::select;
 use heapless::Vec;
 use trouble_host::prelude::*;
 
@@ -44,9 +45,12 @@ impl<P: PacketPool> MicStreamNotifier for TroubleNotifier<'_, '_, '_, P> {
     }
 }
 
+/// Streams to the central while it is subscribed and, for shared streams,
+/// while this link holds the stream.
 pub async fn mic_stream_notify<P: PacketPool>(
     server: &Server<'_>,
     conn: &GattConnection<'_, '_, P>,
+    link: &Link,
 ) {
     let notifier =
         TroubleNotifier { handle: server.mic.data_stream.clone(), conn };
@@ -59,7 +63,11 @@ pub async fn mic_stream_notify<P: PacketPool>(
     let mtu = att_mtu - 3;
     info!("Mic ATT mtu = {}, max notify value = {}", att_mtu, mtu);
 
-    mic_stream::mic_stream_notify(&notifier, mtu).await
+    loop {
+        let grant = link.acquire(DataStream::Mic).await;
+        select(mic_stream::mic_stream_notify(&notifier, mtu), grant.revoked())
+            .await;
+    }
 }
 
 pub async fn update_mic_characteristics(
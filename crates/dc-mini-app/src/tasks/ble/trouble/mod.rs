@@ -2,43 +2,63 @@ pub mod ads;
 pub mod advertiser;
 pub mod battery;
 pub mod clock;
+pub mod conn_params;
 pub mod device_info;
 pub mod dfu;
+pub mod file_transfer;
 pub mod gatt;
+pub mod imu;
+pub mod link;
 pub mod mic;
 pub mod profile;
+pub mod security;
 pub mod session;
 
-use dc_mini_bsp::ble::{MultiprotocolServiceLayer, SoftdeviceController};
+use dc_mini_bsp::ble::{
+    MultiprotocolServiceLayer, SoftdeviceController, PERIPHERAL_COUNT,
+};
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha12Rng;
 use trouble_host::prelude::*;
 
 pub use ads::*;
 pub use advertiser::*;
 pub use battery::*;
 pub use clock::*;
+pub use conn_params::*;
 pub use device_info::*;
+pub use file_transfer::*;
 pub use gatt::*;
+pub use imu::*;
+pub use link::*;
 pub use mic::*;
 pub use profile::*;
+pub use security::*;
 pub use session::*;
 
 use super::Error;
 
 use crate::prelude::{
-    error, info, AppContext, CriticalSectionRawMutex, Mutex,
+    error, info, unwrap, warn, AppContext, CriticalSectionRawMutex, Mutex,
+    NoopRawMutex,
 };
 use crate::tasks::dfu::DfuResources;
+use embassy_sync::channel::Channel;
 
 /// Maximum ATT MTU supported by this device.
 /// Derived from TROUBLE_HOST_DEFAULT_PACKET_POOL_MTU (251) - 4 byte L2CAP header.
 /// This ensures every notification fits in a single DLE ACL packet (251 bytes).
 pub const ATT_MTU: usize = 247;
 
-/// Max number of connections
-const CONNECTIONS_MAX: usize = 1;
+/// Max number of connections, one per peripheral role the controller is
+/// built with.
+pub const CONNECTIONS_MAX: usize = PERIPHERAL_COUNT as usize;
+
+// Every connection slot can hold a bonded central.
+const _: () = assert!(crate::storage::BLE_BONDS_MAX == CONNECTIONS_MAX);
 
 /// Max number of L2CAP channels.
-const L2CAP_CHANNELS_MAX: usize = 2; // Signal + att
+const L2CAP_CHANNELS_MAX: usize = 2 * CONNECTIONS_MAX; // Signal + att per link
 
 pub type BleController = SoftdeviceController<'static>;
 
@@ -66,6 +86,7 @@ async fn ble_runner(mut runner: Runner<'_, BleController, DefaultPacketPool>) {
 /// all BLE resources are cleaned up.
 async fn run(
     controller: BleController,
+    seed: [u8; 32],
     app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
     dfu_resources: &'static DfuResources,
 ) {
@@ -73,8 +94,12 @@ async fn run(
     info!("Our address = {:?}", address);
 
     let mut resources: BleResources = HostResources::new();
+    // Keys for LE Secure Connections pairing come from this generator.
+    let mut rng = ChaCha12Rng::from_seed(seed);
     let stack = trouble_host::new(controller, &mut resources)
-        .set_random_address(address);
+        .set_random_address(address)
+        .set_random_generator_seed(&mut rng);
+    restore_bonds(&stack, app_context).await;
     let Host { mut peripheral, runner, .. } = stack.build();
 
     let server =
@@ -90,38 +115,124 @@ async fn run(
     // The join runs forever (app_loop is infinite), so in practice
     // this drop ordering only matters for compiler verification.
     let app_loop =
-        app_task(&server, &mut peripheral, app_context, dfu_resources);
+        app_task(&stack, &server, &mut peripheral, app_context, dfu_resources);
     let _ = embassy_futures::join::join(ble_runner(runner), app_loop).await;
 }
 
-async fn app_task<'values>(
-    server: &Server<'values>,
+/// Channel handing accepted connections to a free [`connection_slot`].
+type ConnectionQueue<'values, 'server> = Channel<
+    NoopRawMutex,
+    GattConnection<'values, 'server, DefaultPacketPool>,
+    CONNECTIONS_MAX,
+>;
+
+async fn app_task<'values, 'server>(
+    stack: &'values Stack<'values, BleController, DefaultPacketPool>,
+    server: &'server Server<'values>,
     peripheral: &mut Peripheral<'values, BleController, DefaultPacketPool>,
     app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
     dfu_resources: &'static DfuResources,
+) {
+    let connections: ConnectionQueue<'values, 'server> = Channel::new();
+    // One token per slot; advertising pauses while every slot is busy.
+    let free_slots: Channel<NoopRawMutex, (), CONNECTIONS_MAX> =
+        Channel::new();
+    for _ in 0..CONNECTIONS_MAX {
+        unwrap!(free_slots.try_send(()));
+    }
+
+    let advertiser = async {
+        loop {
+            free_slots.receive().await;
+            loop {
+                // Re-read every cycle so a new nickname is advertised
+                // without a reset.
+                let name = {
+                    let mut app_ctx = app_context.lock().await;
+                    crate::identity::display_name(
+                        app_ctx.profile_manager.get_nickname().await,
+                    )
+                };
+                crate::status::set_ble_advertising(true);
+                let advertised = advertise(&name, peripheral, server).await;
+                crate::status::set_ble_advertising(false);
+                match advertised {
+                    Ok(conn) => {
+                        connections.send(conn).await;
+                        break;
+                    }
+                    Err(e) => {
+                        error!("Advertisement error: {:?}", e);
+                        embassy_time::Timer::after_secs(1).await;
+                    }
+                }
+            }
+        }
+    };
+    let slots: [_; CONNECTIONS_MAX] = core::array::from_fn(|_| {
+        connection_slot(
+            stack,
+            server,
+            &connections,
+            &free_slots,
+            app_context,
+            dfu_resources,
+        )
+    });
+    let slots = embassy_futures::join::join_array(slots);
+    embassy_futures::join::join(advertiser, slots).await;
+}
+
+/// Serves connections from `connections` one after another, returning a
+/// token to `free_slots` after each disconnect.
+async fn connection_slot<'values, 'server>(
+    stack: &'values Stack<'values, BleController, DefaultPacketPool>,
+    server: &'server Server<'values>,
+    connections: &ConnectionQueue<'values, 'server>,
+    free_slots: &Channel<NoopRawMutex, (), CONNECTIONS_MAX>,
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+    dfu_resources: &'static DfuResources,
 ) {
     loop {
-        match advertise("dc-mini", peripheral, server).await {
-            Ok(conn) => {
-                sync_characteristics(server, app_context).await;
-                let gatt = gatt_server_task(
+        let conn = connections.receive().await;
+        let link = Link::new();
+        // Encrypt with the stored bond, or ask a new central to pair; the
+        // protected characteristics stay closed until the central is
+        // bonded. Only offer bonding while a bond slot is free.
+        let bondable = bond_slot_free(app_context).await;
+        if let Err(e) = conn.raw().set_bondable(bondable) {
+            warn!("[ble] failed to set bondable: {:?}", e);
+        }
+        if let Err(e) = conn.raw().request_security() {
+            warn!("[ble] security request failed: {:?}", e);
+        }
+        sync_characteristics(server, app_context).await;
+        // Pick up the central's clock while serving, so data frames carry
+        // wall-clock timestamps, and keep the connection parameters
+        // matched to what is being streamed.
+        let gatt = embassy_futures::select::select(
+            embassy_futures::join::join(
+                gatt_server_task(
                     server,
                     &conn,
+                    &link,
                     app_context,
                     dfu_resources,
-                );
-                let ads = ads_stream_notify(server, &conn);
-                let mic = mic_stream_notify(server, &conn);
-                futures::pin_mut!(gatt, ads, mic);
-                embassy_futures::select::select3(gatt, ads, mic).await;
-                // Release DFU lock if connection drops mid-transfer
-                dfu_resources.finish();
-            }
-            Err(e) => {
-                error!("Advertisement error: {:?}", e);
-                embassy_time::Timer::after_secs(1).await;
-            }
-        }
+                ),
+                sync_time(stack, conn.raw()),
+            ),
+            manage_connection_params(stack, conn.raw()),
+        );
+        let ads = embassy_futures::select::select(
+            ads_stream_notify(server, &conn, &link),
+            imu_stream_notify(server, &conn, &link),
+        );
+        let mic = mic_stream_notify(server, &conn, &link);
+        let battery = battery_level_notify(server, &conn);
+        futures::pin_mut!(gatt, ads, mic, battery);
+        embassy_futures::select::select4(gatt, ads, mic, battery).await;
+        drop(link);
+        free_slots.send(()).await;
     }
 }
 
@@ -133,6 +244,7 @@ async fn sync_characteristics(
         device_info,
         current_profile,
         ads_config,
+        imu_config,
         mic_config,
         recording_status,
     ) = {
@@ -146,6 +258,12 @@ async fn sync_characteristics(
                 .await
                 .cloned()
                 .unwrap_or_default(),
+            app_ctx
+                .profile_manager
+                .get_imu_config()
+                .await
+                .cloned()
+                .unwrap_or_default(),
             app_ctx
                 .profile_manager
                 .get_mic_config()
@@ -165,16 +283,20 @@ async fn sync_characteristics(
     .await;
     update_profile_characteristics(server, current_profile).await;
     update_session_characteristics(server, &[], recording_status).await;
-    update_battery_characteristics(server, 100).await;
+    if let Some(status) = latest_battery_status().await {
+        update_battery_characteristics(server, status.state_of_charge).await;
+    }
     update_ads_characteristics(server, &ads_config).await;
+    update_imu_characteristics(server, &imu_config).await;
     update_mic_characteristics(server, &mic_config).await;
 }
 
 #[embassy_executor::task]
 pub async fn ble_run_task(
     controller: BleController,
+    seed: [u8; 32],
     app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
     dfu_resources: &'static DfuResources,
 ) {
-    run(controller, app_context, dfu_resources).await;
+    run(controller, seed, app_context, dfu_resources).await;
 }
@@ -1,29 +1,8 @@
 use super::Server;
 use crate::prelude::*;
+use dc_mini_icd::ProfileCommand;
 use trouble_host::prelude::*;
 
-/// Profile Command types
-#[repr(u8)]
-#[derive(Debug, Clone, Copy)]
-pub enum ProfileCommand {
-    Reset = 0,
-    Next = 1,
-    Previous = 2,
-}
-
-impl TryFrom<u8> for ProfileCommand {
-    type Error = &'static str;
-
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(ProfileCommand::Reset),
-            1 => Ok(ProfileCommand::Next),
-            2 => Ok(ProfileCommand::Previous),
-            _ => Err("Invalid profile command"),
-        }
-    }
-}
-
 /// Custom Profile Service (UUID: 0x32300000-af46-43af-a0ba-4dbeb457f51c)
 #[gatt_service(uuid = "32300000-af46-43af-a0ba-4dbeb457f51c")]
 pub struct ProfileService {
@@ -59,52 +38,12 @@ impl<'d> Server<'d> {
         handle: u16,
         app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
     ) {
-        let mut app_ctx = app_context.lock().await;
-        let _evt_sender = app_ctx.event_sender;
-
         if handle == self.profile.command.handle {
             if let Ok(value) = self.get(&self.profile.command) {
                 if let Ok(cmd) = ProfileCommand::try_from(value) {
-                    match cmd {
-                        ProfileCommand::Reset => {
-                            unwrap!(
-                                app_ctx
-                                    .profile_manager
-                                    .set_current_profile(0)
-                                    .await
-                            );
-                        }
-                        ProfileCommand::Next => {
-                            let current = app_ctx
-                                .profile_manager
-                                .get_current_profile()
-                                .await;
-                            unwrap!(
-                                app_ctx
-                                    .profile_manager
-                                    .set_current_profile(
-                                        current.wrapping_add(1)
-                                    )
-                                    .await
-                            );
-                        }
-                        ProfileCommand::Previous => {
-                            let current = app_ctx
-                                .profile_manager
-                                .get_current_profile()
-                                .await;
-                            unwrap!(
-                                app_ctx
-                                    .profile_manager
-                                    .set_current_profile(
-                                        current.wrapping_sub(1)
-                                    )
-                                    .await
-                            );
-                        }
-                    }
+                    let mut app_ctx = app_context.lock().await;
                     let current_profile =
-                        app_ctx.profile_manager.get_current_profile().await;
+                        app_ctx.apply_profile_command(cmd).await;
                     update_profile_characteristics(self, current_profile)
                         .await;
                 }
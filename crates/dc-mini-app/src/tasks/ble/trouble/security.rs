@@ -0,0 +1,139 @@
+use super::gatt::Server;
+use super::BleController;
+use crate::prelude::*;
+use crate::storage::{BleBond, BLE_BONDS_MAX};
+use trouble_host::prelude::*;
+
+impl Server<'_> {
+    /// Whether `handle` belongs to a service that only a bonded central
+    /// may use: ADS, IMU, mic, session, profile, time, DFU and file
+    /// transfer. Battery and device information stay open.
+    pub fn requires_encryption(&self, handle: u16) -> bool {
+        let protected = [
+            (self.ads.daisy_en.handle, self.ads.stream_codec.handle),
+            (self.imu.quaternion_stream.handle, self.imu.command.handle),
+            (self.mic.data_stream.handle, self.mic.command.handle),
+            (self.session.recording_id.handle, self.session.command.handle),
+            (self.profile.current_profile.handle, self.profile.command.handle),
+            (self.time.exchange.handle, self.time.sample.handle),
+            (self.dfu.control.handle, self.dfu.packet.handle),
+            (self.files.control.handle, self.files.data.handle),
+        ];
+        protected.iter().any(|&(first, last)| (first..=last).contains(&handle))
+    }
+}
+
+/// What pairing did on the current link.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Pairing {
+    /// No pairing ran; an encrypted link resumed a bond the host holds.
+    None,
+    /// The central paired and its bond was stored.
+    Bonded,
+    /// The central paired, but no bond was stored for it.
+    Unbonded,
+}
+
+/// Whether the central on `conn` may use the protected characteristics.
+///
+/// Just Works pairing encrypts a link without proving who the central is,
+/// so an encrypted link only counts once the central holds a stored bond:
+/// it resumed one, or bonded on this link while a bond slot was free.
+pub async fn is_trusted<P: PacketPool>(
+    conn: &Connection<'_, P>,
+    pairing: Pairing,
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) -> bool {
+    match conn.security_level() {
+        Ok(SecurityLevel::EncryptedAuthenticated) => true,
+        Ok(SecurityLevel::Encrypted) => match pairing {
+            Pairing::None => {
+                let peer = conn.peer_identity();
+                let mut app_ctx = app_context.lock().await;
+                app_ctx
+                    .profile_manager
+                    .get_ble_bonds()
+                    .await
+                    .iter()
+                    .any(|bond| identity(bond).match_identity(&peer))
+            }
+            Pairing::Bonded => true,
+            Pairing::Unbonded => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether a new central may bond. Once every slot is taken, further
+/// centrals can still encrypt but stay locked out until the bonds are
+/// cleared over USB.
+pub async fn bond_slot_free(
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) -> bool {
+    let mut app_ctx = app_context.lock().await;
+    app_ctx.profile_manager.get_ble_bonds().await.len() < BLE_BONDS_MAX
+}
+
+fn identity(bond: &BleBond) -> Identity {
+    Identity {
+        bd_addr: BdAddr::new(bond.address),
+        irk: bond.irk.map(|irk| IdentityResolvingKey::from_le_bytes(irk)),
+    }
+}
+
+/// Hands the stored bonds to the host so bonded centrals can resume
+/// encryption without pairing again.
+pub async fn restore_bonds(
+    stack: &Stack<'_, BleController, DefaultPacketPool>,
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) {
+    let mut app_ctx = app_context.lock().await;
+    for bond in app_ctx.profile_manager.get_ble_bonds().await {
+        let info = BondInformation {
+            identity: identity(bond),
+            ltk: LongTermKey::from_le_bytes(bond.ltk),
+            security_level: if bond.authenticated {
+                SecurityLevel::EncryptedAuthenticated
+            } else {
+                SecurityLevel::Encrypted
+            },
+            is_bonded: true,
+        };
+        match stack.add_bond_information(info) {
+            Ok(()) => info!("[ble] restored bond"),
+            Err(e) => warn!("[ble] failed to restore bond: {:?}", e),
+        }
+    }
+}
+
+/// Persists the bond from a completed pairing if a bond slot is still
+/// free. Returns whether it was stored.
+pub async fn store_bond(
+    bond: &BondInformation,
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) -> bool {
+    let bond = BleBond {
+        address: bond.identity.bd_addr.into_inner(),
+        irk: bond.identity.irk.map(|irk| irk.to_le_bytes()),
+        ltk: bond.ltk.to_le_bytes(),
+        authenticated: bond.security_level
+            == SecurityLevel::EncryptedAuthenticated,
+    };
+    let mut app_ctx = app_context.lock().await;
+    match app_ctx.profile_manager.add_ble_bond(bond).await {
+        Ok(true) => {
+            info!("[ble] bond stored");
+            true
+        }
+        Ok(false) => {
+            // Other centrals bonded while this one was pairing.
+            warn!("[ble] bond slots taken, bond not stored");
+            false
+        }
+        Err(_) => {
+            error!("[ble] failed to store bond");
+            false
+        }
+    }
+}
@@ -139,9 +139,9 @@ pub async fn demo_task(sender: EventSender) {
 
     // 2. ADS stream
     info!("[Demo] ADS stream — 5s");
-    sender.send(AdsEvent::StartStream.into()).await;
+    sender.send(AdsEvent::StartStream(AdsClaim::Local).into()).await;
     log_ads_for_seconds(5).await;
-    sender.send(AdsEvent::StopStream.into()).await;
+    sender.send(AdsEvent::StopStream(AdsClaim::Local).into()).await;
     Timer::after_secs(1).await;
 
     // 3. IMU stream
@@ -1,6 +1,13 @@
+extern crate alloc;
+
 use core::cell::RefCell;
 use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
+use dc_mini_bsp::{BootStatus, UpdateRejection};
+use dc_mini_icd::{
+    BootMetrics, DfuRejectReason, DfuSignature, SwapResult, SystemEventKind,
+    DFU_SIGNATURE_LEN, DFU_SIGNATURE_MAGIC,
+};
 use embassy_boot::{BlockingFirmwareState, FirmwareUpdaterConfig};
 use embassy_embedded_hal::flash::partition::Partition;
 use embassy_nrf::nvmc::Nvmc;
@@ -8,13 +15,116 @@ use embassy_nrf::qspi::Qspi;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
 use embassy_sync::mutex::Mutex;
+use embedded_storage_async::nor_flash::{NorFlash, ReadNorFlash};
 
 /// The DFU partition size (992K, from linkerfile).
 pub const DFU_PARTITION_SIZE: u32 = 992 * 1024;
 
+/// Bytes of the staged image read at a time.
+const CHUNK: usize = 256;
+/// Overlap between scanned chunks, so a magic in a chunk's last word is
+/// seen along with the length after it.
+const SCAN_OVERLAP: usize = 8;
+
 /// Async partition over external QSPI flash for DFU firmware writes.
 pub type DfuPartition<'a> = Partition<'a, NoopRawMutex, Qspi<'static>>;
 
+/// Why a staged image was not marked updated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DfuError {
+    /// The bootloader carries no signing key.
+    NoKey,
+    /// The image has no signature trailer.
+    Unsigned,
+    /// The signature does not match the image.
+    BadSignature,
+    /// Reading or erasing the staged image failed.
+    Flash,
+    /// The image was linked for another flash layout.
+    Layout,
+    /// Writing the bootloader state failed.
+    State,
+}
+
+impl DfuError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DfuError::NoKey => "No signing key in bootloader",
+            DfuError::Unsigned => "Image not signed",
+            DfuError::BadSignature => "Bad image signature",
+            DfuError::Flash => "Flash access failed",
+            DfuError::Layout => "Image built for another flash layout",
+            DfuError::State => "mark_updated failed",
+        }
+    }
+}
+
+/// Logs what the bootloader did before this boot, recording a refused
+/// update in the event log. Call once at boot; the status stays pending
+/// until [`acknowledge_boot_status`] after the boot is confirmed.
+pub fn record_boot_status() {
+    let status = BootStatus::pending();
+    crate::info!("Boot status: {:?}", status);
+    if let Some(BootStatus::UpdateRejected(reason)) = status {
+        crate::event_log::record(SystemEventKind::DfuRejected(reject_reason(
+            reason,
+        )));
+    }
+}
+
+/// Clears the status the bootloader left. Call only once `mark_booted`
+/// succeeded, so a crash before that leaves it for the next boot.
+pub fn acknowledge_boot_status<F: embedded_storage::nor_flash::NorFlash>(
+    flash: &mut F,
+) {
+    if BootStatus::acknowledge(flash).is_err() {
+        crate::warn!("Failed to acknowledge the boot status");
+    }
+}
+
+/// The counters the bootloader keeps, `None` if it keeps none.
+pub fn boot_metrics() -> Option<BootMetrics> {
+    let metrics = dc_mini_bsp::BootMetrics::read()?;
+    let last_swap = metrics.last_swap.and_then(|status| match status {
+        BootStatus::Booted => None,
+        BootStatus::Updated => Some(SwapResult::Updated),
+        BootStatus::UpdateRejected(reason) => {
+            Some(SwapResult::Rejected(reject_reason(reason)))
+        }
+        BootStatus::RolledBack => Some(SwapResult::RolledBack),
+    });
+    Some(BootMetrics {
+        boot_count: metrics.boot_count,
+        watchdog_resets: metrics.watchdog_resets,
+        rollbacks: metrics.rollbacks,
+        last_swap,
+    })
+}
+
+fn reject_reason(reason: UpdateRejection) -> DfuRejectReason {
+    match reason {
+        UpdateRejection::NoKey => DfuRejectReason::NoKey,
+        UpdateRejection::Unsigned => DfuRejectReason::Unsigned,
+        UpdateRejection::BadSignature => DfuRejectReason::BadSignature,
+        UpdateRejection::Flash => DfuRejectReason::Flash,
+        UpdateRejection::TrailingData => DfuRejectReason::TrailingData,
+    }
+}
+
+/// Public key updates must be signed with, stored by dc-mini-boot in its
+/// own flash region so a firmware update cannot replace it. `None` if the
+/// bootloader was built without one.
+fn bootloader_public_key() -> Option<[u8; 32]> {
+    extern "C" {
+        static __bootloader_dfu_key: [u8; 32];
+    }
+    let key = unsafe {
+        core::ptr::read_volatile(&__bootloader_dfu_key as *const [u8; 32])
+    };
+    (key != [0xFF; 32]).then_some(key)
+}
+
 /// Shared DFU resources used by both BLE and USB firmware update paths.
 ///
 /// Holds the external QSPI flash (DFU partition) and internal NVMC (state partition)
@@ -38,7 +148,7 @@ impl DfuResources {
     ///
     /// # Safety
     /// The NVMC instance provided here must only write to the BOOTLOADER_STATE region
-    /// (0x6000..0x7000). The ProfileManager's NVMC writes to the STORAGE region
+    /// (0x18000..0x19000). The ProfileManager's NVMC writes to the STORAGE region
     /// (0xFE000..0x100000). These are non-overlapping regions serialized by hardware.
     pub fn new(qspi: Qspi<'static>, nvmc: Nvmc<'static>) -> Self {
         Self {
@@ -65,11 +175,28 @@ impl DfuResources {
         Partition::new(&self.dfu_flash, start, size)
     }
 
+    /// Checks the signature of the staged image against the bootloader's
+    /// key, then marks it updated.
+    ///
+    /// The swap installs the whole partition, so everything after the
+    /// trailer is erased first; the bootloader refuses an image followed
+    /// by anything else. Transports leave padding there, and the Nordic
+    /// DFU target fills the rest of the partition with a pattern.
+    pub async fn verify_and_mark_updated(&self) -> Result<(), DfuError> {
+        let key = bootloader_public_key().ok_or(DfuError::NoKey)?;
+        let mut partition = self.dfu_partition();
+        let trailer = find_signature(&mut partition).await?;
+        verify_image(&mut partition, &trailer, &key).await?;
+        check_layout(&mut partition).await?;
+        let image_end = DfuSignature::offset(trailer.firmware_len)
+            + DFU_SIGNATURE_LEN as u32;
+        erase_after(&mut partition, image_end).await?;
+        self.mark_updated().map_err(|_| DfuError::State)
+    }
+
     /// Mark the DFU partition as updated (triggers bootloader swap on next reset).
     /// This is a blocking operation on the NVMC state partition.
-    pub fn mark_updated(
-        &self,
-    ) -> Result<(), embassy_boot::FirmwareUpdaterError> {
+    fn mark_updated(&self) -> Result<(), embassy_boot::FirmwareUpdaterError> {
         let dfu_stub = self.dfu_flash_blocking_stub();
         let config = FirmwareUpdaterConfig::from_linkerfile_blocking(
             &dfu_stub,
@@ -130,6 +257,168 @@ impl DfuResources {
     }
 }
 
+/// Verifies the firmware before `trailer` against `public_key`.
+async fn verify_image(
+    partition: &mut DfuPartition<'_>,
+    trailer: &DfuSignature,
+    public_key: &[u8; 32],
+) -> Result<(), DfuError> {
+    let public_key =
+        salty::PublicKey::try_from(public_key).map_err(|_| DfuError::NoKey)?;
+    let mut hasher = salty::Sha512::new();
+    let mut buf = [0u8; CHUNK];
+    let mut offset = 0;
+    while offset < trailer.firmware_len {
+        let len = (trailer.firmware_len - offset).min(CHUNK as u32);
+        // Reads stay word aligned; the padding is not hashed.
+        let aligned = (len as usize + 3) & !3;
+        partition
+            .read(offset, &mut buf[..aligned])
+            .await
+            .map_err(|_| DfuError::Flash)?;
+        hasher.update(&buf[..len as usize]);
+        offset += len;
+    }
+    let digest = hasher.finalize();
+
+    let signature = salty::Signature::from(&trailer.signature);
+    public_key.verify(&digest, &signature).map_err(|_| DfuError::BadSignature)
+}
+
+/// Refuses an image whose reset vector lies outside the bootloader's
+/// active partition. A bootloader with another layout would swap it in
+/// where it cannot run, leaving a unit that only a probe can recover.
+async fn check_layout(
+    partition: &mut DfuPartition<'_>,
+) -> Result<(), DfuError> {
+    extern "C" {
+        static __bootloader_active_start: u32;
+        static __bootloader_active_end: u32;
+    }
+    let (start, end) = unsafe {
+        (
+            &__bootloader_active_start as *const u32 as u32,
+            &__bootloader_active_end as *const u32 as u32,
+        )
+    };
+    // The vector table opens the image: initial stack pointer, then reset.
+    let mut vectors = [0u8; 8];
+    partition.read(0, &mut vectors).await.map_err(|_| DfuError::Flash)?;
+    let reset =
+        u32::from_le_bytes([vectors[4], vectors[5], vectors[6], vectors[7]]);
+    // Thumb entry points have the low bit set.
+    if (start..end).contains(&(reset & !1)) {
+        Ok(())
+    } else {
+        Err(DfuError::Layout)
+    }
+}
+
+/// Returns the first trailer in the partition whose firmware length
+/// places it where it was found, as the bootloader does.
+async fn find_signature(
+    partition: &mut DfuPartition<'_>,
+) -> Result<DfuSignature, DfuError> {
+    let capacity = partition.capacity() as u32;
+    let mut buf = [0u8; CHUNK];
+    let mut base = 0;
+    while base + DFU_SIGNATURE_LEN as u32 <= capacity {
+        let len = (capacity - base).min(CHUNK as u32) as usize;
+        partition
+            .read(base, &mut buf[..len])
+            .await
+            .map_err(|_| DfuError::Flash)?;
+        for i in (0..len.saturating_sub(SCAN_OVERLAP - 1)).step_by(4) {
+            if buf[i..i + 4] != DFU_SIGNATURE_MAGIC {
+                continue;
+            }
+            let firmware_len = u32::from_le_bytes([
+                buf[i + 4],
+                buf[i + 5],
+                buf[i + 6],
+                buf[i + 7],
+            ]);
+            let pos = base + i as u32;
+            if DfuSignature::offset(firmware_len) != pos
+                || pos + DFU_SIGNATURE_LEN as u32 > capacity
+            {
+                continue;
+            }
+            let mut bytes = [0u8; DFU_SIGNATURE_LEN];
+            partition
+                .read(pos, &mut bytes)
+                .await
+                .map_err(|_| DfuError::Flash)?;
+            if let Some(trailer) = DfuSignature::from_bytes(&bytes) {
+                return Ok(trailer);
+            }
+        }
+        base += (CHUNK - SCAN_OVERLAP) as u32;
+    }
+    Err(DfuError::Unsigned)
+}
+
+/// Erases the partition from `end` on, rewriting the start of the sector
+/// `end` falls in. Sectors already erased are left alone.
+async fn erase_after(
+    partition: &mut DfuPartition<'_>,
+    end: u32,
+) -> Result<(), DfuError> {
+    const SECTOR: u32 = <DfuPartition<'static> as NorFlash>::ERASE_SIZE as u32;
+    let capacity = partition.capacity() as u32;
+    let mut sector = end - end % SECTOR;
+    if sector < end {
+        if !is_erased(partition, end, sector + SECTOR).await? {
+            let mut head = alloc::vec![0u8; (end - sector) as usize];
+            partition
+                .read(sector, &mut head)
+                .await
+                .map_err(|_| DfuError::Flash)?;
+            partition
+                .erase(sector, sector + SECTOR)
+                .await
+                .map_err(|_| DfuError::Flash)?;
+            partition
+                .write(sector, &head)
+                .await
+                .map_err(|_| DfuError::Flash)?;
+        }
+        sector += SECTOR;
+    }
+    while sector < capacity {
+        if !is_erased(partition, sector, sector + SECTOR).await? {
+            partition
+                .erase(sector, sector + SECTOR)
+                .await
+                .map_err(|_| DfuError::Flash)?;
+        }
+        sector += SECTOR;
+    }
+    Ok(())
+}
+
+/// Whether the partition reads `0xFF` from `from` up to `to`.
+async fn is_erased(
+    partition: &mut DfuPartition<'_>,
+    from: u32,
+    to: u32,
+) -> Result<bool, DfuError> {
+    let mut buf = [0u8; CHUNK];
+    let mut offset = from;
+    while offset < to {
+        let len = (to - offset).min(CHUNK as u32) as usize;
+        partition
+            .read(offset, &mut buf[..len])
+            .await
+            .map_err(|_| DfuError::Flash)?;
+        if buf[..len].iter().any(|&b| b != 0xFF) {
+            return Ok(false);
+        }
+        offset += len as u32;
+    }
+    Ok(true)
+}
+
 /// Stub flash that satisfies `from_linkerfile_blocking`'s DFU flash type requirement.
 /// Only the state partition is actually used for mark_updated/mark_booted.
 struct StubFlash;
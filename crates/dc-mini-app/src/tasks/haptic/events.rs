@@ -1,5 +1,6 @@
 use super::*;
 use crate::prelude::*;
+use dc_mini_icd::{HapticConfig, HapticCue, HapticPattern, HapticSequence};
 use derive_more::From;
 use drv260x::{Effect, WaveformEntry};
 use embassy_sync::mutex::Mutex;
@@ -10,28 +11,67 @@ use portable_atomic::Ordering;
 pub enum HapticCommand {
     PlayEffect(Effect),
     PlaySequence(heapless::Vec<WaveformEntry, 8>),
+    /// Host cue; interrupted by the next command.
+    PlayPattern(HapticPattern),
+    /// Timed steps; interrupted by the next command.
+    PlaySteps(HapticSequence),
+}
+
+/// Device events a profile can bind a [`HapticCue`] to.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HapticTrigger {
+    SessionStart,
+    SessionStop,
+    LowBattery,
+    ElectrodeOff,
 }
 
 #[derive(Debug, From)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum HapticEvent {
     Play(HapticCommand),
+    /// Plays a named pattern from the active profile.
+    Cue(HapticCue),
+    /// Plays the cue the active profile binds to a device event, if any.
+    Trigger(HapticTrigger),
     Stop,
     Init,
 }
 
 #[derive(Clone)]
 pub struct HapticManager {
-    bus_manager: &'static I2cBusManager,
+    buses: &'static AppBuses,
     app: &'static Mutex<CriticalSectionRawMutex, AppContext>,
 }
 
 impl HapticManager {
     pub fn new(
-        bus_manager: &'static I2cBusManager,
+        buses: &'static AppBuses,
         app: &'static Mutex<CriticalSectionRawMutex, AppContext>,
     ) -> Self {
-        Self { bus_manager, app }
+        Self { buses, app }
+    }
+
+    async fn config(&self) -> HapticConfig {
+        let mut app_ctx = self.app.lock().await;
+        app_ctx
+            .profile_manager
+            .get_haptic_config()
+            .await
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    async fn play(&self, cmd: HapticCommand) {
+        if !HAPTIC_ACTIVE.load(Ordering::SeqCst) {
+            // Auto-init: spawn the task first, then send command
+            let app_ctx = self.app.lock().await;
+            app_ctx
+                .low_prio_spawner
+                .must_spawn(haptic_task(self.buses.get::<HapticBus>()));
+        }
+        HAPTIC_CMD_SIG.signal(Some(cmd));
     }
 
     pub async fn handle_event(&self, event: HapticEvent) {
@@ -42,20 +82,28 @@ impl HapticManager {
                     info!("Haptic task already running.");
                 } else {
                     let app_ctx = self.app.lock().await;
-                    app_ctx
-                        .low_prio_spawner
-                        .must_spawn(haptic_task(self.bus_manager));
+                    app_ctx.low_prio_spawner.must_spawn(haptic_task(
+                        self.buses.get::<HapticBus>(),
+                    ));
                 }
             }
-            HapticEvent::Play(cmd) => {
-                if !HAPTIC_ACTIVE.load(Ordering::SeqCst) {
-                    // Auto-init: spawn the task first, then send command
-                    let app_ctx = self.app.lock().await;
-                    app_ctx
-                        .low_prio_spawner
-                        .must_spawn(haptic_task(self.bus_manager));
+            HapticEvent::Play(cmd) => self.play(cmd).await,
+            HapticEvent::Cue(cue) => {
+                let sequence = self.config().await.sequence(cue).clone();
+                self.play(HapticCommand::PlaySteps(sequence)).await;
+            }
+            HapticEvent::Trigger(trigger) => {
+                let config = self.config().await;
+                let cue = match trigger {
+                    HapticTrigger::SessionStart => config.on_session_start,
+                    HapticTrigger::SessionStop => config.on_session_stop,
+                    HapticTrigger::LowBattery => config.on_low_battery,
+                    HapticTrigger::ElectrodeOff => config.on_electrode_off,
+                };
+                if let Some(cue) = cue {
+                    let sequence = config.sequence(cue).clone();
+                    self.play(HapticCommand::PlaySteps(sequence)).await;
                 }
-                HAPTIC_CMD_SIG.signal(Some(cmd));
             }
             HapticEvent::Stop => {
                 if !HAPTIC_ACTIVE.load(Ordering::SeqCst) {
@@ -1,9 +1,66 @@
 use super::*;
 use crate::prelude::*;
-use drv260x::Drv260x;
+use dc_mini_icd::{HapticSequence, HapticStep};
+use drv260x::{Drv260x, Mode};
 use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
+use embassy_futures::select::{select, Either};
+use embassy_nrf::twim::Twim;
 use portable_atomic::Ordering;
 
+type Haptic<'a> =
+    Drv260x<I2cDevice<'a, CriticalSectionRawMutex, Twim<'static>>>;
+
+async fn play_step(
+    haptic: &mut Haptic<'_>,
+    step: &HapticStep,
+) -> Result<(), ()> {
+    let duration = Duration::from_millis(step.duration_ms.into());
+    if step.effect == 0 {
+        haptic.set_mode_async(Mode::RealTimePlayback).await.map_err(drop)?;
+        haptic.set_rtp_input_async(step.strength).await.map_err(drop)?;
+        Timer::after(duration).await;
+        haptic.set_rtp_input_async(0).await.map_err(drop)?;
+        haptic.set_mode_async(Mode::InternalTrigger).await.map_err(drop)?;
+    } else {
+        haptic.set_single_effect_async(step.effect).await.map_err(drop)?;
+        haptic.go_async().await.map_err(drop)?;
+        Timer::after(duration).await;
+    }
+    Timer::after_millis(step.pause_ms.into()).await;
+    Ok(())
+}
+
+/// Plays `sequence` until it ends or the next command arrives, which is
+/// returned so the caller can handle it.
+async fn play_sequence(
+    haptic: &mut Haptic<'_>,
+    sequence: &HapticSequence,
+) -> Option<Option<HapticCommand>> {
+    let play = async {
+        for _ in 0..=sequence.repeat {
+            for step in &sequence.steps {
+                play_step(haptic, step).await?;
+            }
+        }
+        Ok::<(), ()>(())
+    };
+
+    match select(play, HAPTIC_CMD_SIG.wait()).await {
+        Either::First(Err(())) => {
+            error!("Failed to play haptic sequence");
+            None
+        }
+        Either::First(Ok(())) => None,
+        Either::Second(next) => {
+            // Interrupted: silence the motor before handling the next
+            // command.
+            let _ = haptic.stop_async().await;
+            let _ = haptic.set_mode_async(Mode::InternalTrigger).await;
+            Some(next)
+        }
+    }
+}
+
 #[embassy_executor::task]
 pub async fn haptic_task(bus_manager: &'static I2cBusManager) {
     HAPTIC_ACTIVE.store(true, Ordering::SeqCst);
@@ -24,8 +81,12 @@ pub async fn haptic_task(bus_manager: &'static I2cBusManager) {
 
     info!("DRV2605L haptic driver initialized.");
 
+    let mut pending = None;
     loop {
-        let cmd = HAPTIC_CMD_SIG.wait().await;
+        let cmd = match pending.take() {
+            Some(cmd) => cmd,
+            None => HAPTIC_CMD_SIG.wait().await,
+        };
 
         match cmd {
             Some(HapticCommand::PlayEffect(effect)) => {
@@ -50,6 +111,16 @@ pub async fn haptic_task(bus_manager: &'static I2cBusManager) {
                     error!("Failed to trigger haptic sequence: {:?}", e);
                 }
             }
+            Some(HapticCommand::PlayPattern(pattern)) => {
+                pending = play_sequence(
+                    &mut haptic,
+                    &HapticSequence::from(&pattern),
+                )
+                .await;
+            }
+            Some(HapticCommand::PlaySteps(sequence)) => {
+                pending = play_sequence(&mut haptic, &sequence).await;
+            }
             None => {
                 // Stop signal received
                 let _ = haptic.stop_async().await;
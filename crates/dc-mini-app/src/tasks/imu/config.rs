@@ -2,53 +2,75 @@ use super::*;
 use crate::prelude::*;
 use dc_mini_bsp::Imu;
 use dc_mini_icd::ImuConfig;
+use embassy_embedded_hal::shared_bus::I2cDeviceError;
+use embassy_nrf::twim;
 use embassy_sync::blocking_mutex::raw::RawMutex;
-use icm_45605::FifoConfig;
+use icm_45605::{AccelMode, ApexFeature, FifoConfig, GyroMode};
 
+/// Error from the IMU driver on the shared I2C bus.
+pub type ImuError = icm_45605::Error<I2cDeviceError<twim::Error>>;
+
+/// Programs the whole of `config` into the IMU. Safe to call on a running
+/// device: features the config turns off are stopped, so a new config
+/// fully replaces the previous one. Stops at the first failed write.
 pub async fn apply_imu_config<MutexType: RawMutex>(
     imu: &mut Imu<'_, '_, MutexType>,
     config: &ImuConfig,
-) {
-    // Configure gyroscope
-    unwrap!(
-        imu.start_gyro(config.gyro_odr.into(), config.gyro_fsr.into()).await
-    );
-    // Configure accelerometer
-    unwrap!(
-        imu.start_accel(config.accel_odr.into(), config.accel_fsr.into())
-            .await
-    );
-
-    // Configure FIFO if enabled
-    if config.fifo_enabled {
-        let fifo_config = FifoConfig {
-            accel_en: true,
-            gyro_en: true,
-            temp_en: config.fifo_temp_en,
-            hires_en: config.fifo_hires_en,
-            watermark: config.fifo_watermark,
-            mode: config.fifo_mode.into(),
-        };
-        unwrap!(imu.configure_fifo(fifo_config).await);
-        unwrap!(imu.configure_fifo_interrupt(true).await);
-    }
-
-    // Configure motion detection features
-    if config.wake_on_motion_enabled {
-        unwrap!(
-            imu.start_wake_on_motion(config.wake_on_motion_threshold).await
-        );
+) -> Result<(), ImuError> {
+    // Motion detection features. Starting one reprograms the accelerometer
+    // for its own rate, so they go before the configured ODR/FSR below.
+    let features = [
+        (ApexFeature::WakeOnMotion, config.wake_on_motion_enabled),
+        (ApexFeature::Tap, config.tap_detection_enabled),
+        (ApexFeature::Pedometer, config.pedometer_enabled),
+        (ApexFeature::Tilt, config.tilt_detection_enabled),
+        (ApexFeature::RaiseToWake, false),
+    ];
+    for (feature, enabled) in features {
+        if !enabled {
+            imu.stop_apex_feature(feature).await?;
+            continue;
+        }
+        match feature {
+            ApexFeature::WakeOnMotion => {
+                imu.start_wake_on_motion(config.wake_on_motion_threshold)
+                    .await?
+            }
+            ApexFeature::Tap => imu.start_tap_detection().await?,
+            ApexFeature::Pedometer => imu.start_pedometer().await?,
+            ApexFeature::Tilt => imu.start_tilt_detection().await?,
+            ApexFeature::RaiseToWake => imu.start_raise_to_wake().await?,
+        }
     }
 
-    if config.tap_detection_enabled {
-        unwrap!(imu.start_tap_detection().await);
+    // Configure gyroscope
+    imu.start_gyro(config.gyro_odr.into(), config.gyro_fsr.into()).await?;
+    imu.set_gyro_lpf(config.gyro_lpf_enabled).await?;
+    if !config.gyro_power_mode {
+        imu.set_gyro_mode(GyroMode::LowPower).await?;
     }
-
-    if config.pedometer_enabled {
-        unwrap!(imu.start_pedometer().await);
+    // Configure accelerometer
+    imu.start_accel(config.accel_odr.into(), config.accel_fsr.into()).await?;
+    imu.set_accel_lpf(config.accel_lpf_enabled).await?;
+    if !config.accel_power_mode {
+        imu.set_accel_mode(AccelMode::LowPower).await?;
     }
 
-    if config.tilt_detection_enabled {
-        unwrap!(imu.start_tilt_detection().await);
-    }
+    // Configure the FIFO, or put it back in bypass so samples are read
+    // from the data registers on data ready.
+    let fifo_config = FifoConfig {
+        accel_en: true,
+        gyro_en: true,
+        temp_en: config.fifo_temp_en,
+        hires_en: config.fifo_hires_en,
+        tmst_en: true,
+        watermark: config.fifo_watermark,
+        mode: if config.fifo_enabled {
+            config.fifo_mode.into()
+        } else {
+            icm_45605::FifoMode::Bypass
+        },
+    };
+    imu.configure_fifo(fifo_config).await?;
+    imu.configure_fifo_interrupt(config.fifo_enabled).await
 }
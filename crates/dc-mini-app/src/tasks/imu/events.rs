@@ -1,10 +1,17 @@
 use super::*;
 use crate::prelude::*;
+use crate::selftest;
+use crate::tasks::power_control::sleep::WakePin;
 use dc_mini_bsp::ImuResources;
+use dc_mini_icd::SelfTestResult;
 use derive_more::From;
 use embassy_sync::mutex::Mutex;
+use embassy_time::Instant;
 use portable_atomic::Ordering;
 
+/// How often motion is looked for in the IMU samples while streaming.
+const MOTION_POLL: Duration = Duration::from_millis(500);
+
 #[derive(Debug, From)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ImuEvent {
@@ -37,7 +44,7 @@ impl TryFrom<u8> for ImuEvent {
 #[derive(Clone)]
 pub struct ImuManager {
     available: bool,
-    bus_manager: &'static I2cBusManager,
+    buses: &'static AppBuses,
     imu: &'static Mutex<CriticalSectionRawMutex, ImuResources>,
     app: &'static Mutex<CriticalSectionRawMutex, AppContext>,
 }
@@ -45,18 +52,98 @@ pub struct ImuManager {
 impl ImuManager {
     pub fn new(
         available: bool,
-        bus_manager: &'static I2cBusManager,
+        buses: &'static AppBuses,
         imu: &'static Mutex<CriticalSectionRawMutex, ImuResources>,
         app: &'static Mutex<CriticalSectionRawMutex, AppContext>,
     ) -> Self {
-        Self { available, bus_manager, imu, app }
+        Self { available, buses, imu, app }
+    }
+
+    /// Whether the IMU is fitted and has not been marked degraded.
+    fn usable(&self) -> bool {
+        self.available && !faults::is_degraded(MonitoredTask::Imu)
+    }
+
+    /// Runs the IMU self-test. Skipped while streaming, since the built-in
+    /// test takes over the sensor.
+    pub async fn self_test(&self) -> SelfTestResult {
+        if !self.available {
+            return selftest::skipped("IMU not present");
+        }
+        if !self.usable() {
+            return selftest::fail("IMU degraded after a bus error");
+        }
+        if IMU_MEAS.load(Ordering::SeqCst) {
+            return selftest::skipped("IMU streaming");
+        }
+        imu_self_test(self.buses.get::<ImuBus>(), self.imu).await
+    }
+
+    /// Configures the IMU to raise INT1 on motion while the device is in
+    /// System OFF. The IMU stream must be stopped first. Returns `None` if
+    /// there is no IMU or it could not be configured.
+    pub async fn arm_wake_on_motion(&self) -> Option<WakePin> {
+        if !self.usable() {
+            return None;
+        }
+        WOM_RELEASE.signal(());
+        let threshold = {
+            let mut app_ctx = self.app.lock().await;
+            app_ctx
+                .profile_manager
+                .get_imu_config()
+                .await
+                .cloned()
+                .unwrap_or_else(default_imu_settings)
+                .wake_on_motion_threshold
+        };
+        imu_arm_wake_on_motion(self.buses.get::<ImuBus>(), self.imu, threshold)
+            .await
+    }
+
+    /// Waits until the IMU senses motion or `until` passes. While the IMU
+    /// streams, motion is read from its samples, otherwise from the
+    /// wake-on-motion interrupt. May return false early if the IMU
+    /// changes hands; callers wait again.
+    pub async fn wait_for_motion(&self, until: Instant) -> bool {
+        if !self.usable() {
+            Timer::at(until).await;
+            return false;
+        }
+        if IMU_MEAS.load(Ordering::SeqCst) {
+            let since = last_motion();
+            while IMU_MEAS.load(Ordering::SeqCst) && Instant::now() < until {
+                if last_motion() > since {
+                    return true;
+                }
+                Timer::after(MOTION_POLL).await;
+            }
+            return false;
+        }
+        let threshold = {
+            let mut app_ctx = self.app.lock().await;
+            app_ctx
+                .profile_manager
+                .get_imu_config()
+                .await
+                .cloned()
+                .unwrap_or_else(default_imu_settings)
+                .wake_on_motion_threshold
+        };
+        imu_wait_for_motion(
+            self.buses.get::<ImuBus>(),
+            self.imu,
+            threshold,
+            until,
+        )
+        .await
     }
 
     pub async fn handle_event(&self, event: ImuEvent) {
         info!("Received event {:?}", event);
         match event {
             ImuEvent::ConfigChanged => {
-                if !self.available {
+                if !self.usable() {
                     return;
                 }
                 // Handle configuration changes
@@ -71,7 +158,7 @@ impl ImuManager {
                 }
             }
             ImuEvent::StopStream => {
-                if !self.available {
+                if !self.usable() {
                     return;
                 }
                 if !IMU_MEAS.load(Ordering::SeqCst) {
@@ -82,9 +169,9 @@ impl ImuManager {
                 }
             }
             ImuEvent::StartStream => {
-                if !self.available {
+                if !self.usable() {
                     warn!(
-                        "Ignoring IMU start request because no IMU is present"
+                        "Ignoring IMU start request, IMU missing or degraded"
                     );
                     return;
                 }
@@ -103,8 +190,9 @@ impl ImuManager {
                             .save_imu_config(imu_config.clone().unwrap())
                             .await;
                     }
+                    WOM_RELEASE.signal(());
                     app_ctx.low_prio_spawner.must_spawn(imu_task(
-                        self.bus_manager,
+                        self.buses.get::<ImuBus>(),
                         self.imu,
                         imu_config.unwrap(),
                     ));
@@ -1,17 +1,23 @@
 pub(crate) mod config;
 pub(crate) mod events;
+pub(crate) mod motion;
 
 mod tasks; // Tasks module is private
 
 pub use config::*;
 pub use events::*;
+pub use motion::*;
 pub use tasks::*;
 
+use crate::clock::Captured;
 use crate::prelude::*;
+use crate::tasks::power_control::sleep;
+use dc_mini_icd::ImuQuaternion;
 use embassy_sync::signal::Signal;
 use embassy_sync::watch::Watch;
+use embassy_time::Instant;
 use icm_45605::{self, CalibSensorData};
-use portable_atomic::AtomicBool;
+use portable_atomic::{AtomicBool, AtomicU64, Ordering};
 
 pub(self) static IMU_MEAS: AtomicBool = AtomicBool::new(false);
 
@@ -20,12 +26,69 @@ pub(self) static IMU_MEAS_SIG: Signal<
     Option<ImuConfig>,
 > = Signal::new();
 
+/// Ends a wake-on-motion wait so the IMU can be used elsewhere.
+pub(self) static WOM_RELEASE: Signal<CriticalSectionRawMutex, ()> =
+    Signal::new();
+
+/// Set while the IMU task runs with `ImuConfig::ads_orientation`.
+pub(self) static ADS_ORIENTATION: AtomicBool = AtomicBool::new(false);
+
+static LAST_MOTION_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Notes motion seen by the IMU, which also counts as activity.
+pub(self) fn record_motion() {
+    LAST_MOTION_MS.store(Instant::now().as_millis(), Ordering::Relaxed);
+    sleep::record_activity();
+}
+
+/// When the IMU last saw motion.
+pub fn last_motion() -> Instant {
+    Instant::from_millis(LAST_MOTION_MS.load(Ordering::Relaxed))
+}
+
 pub const IMU_CAP: usize = 100;
+/// USB plus one per BLE link for the quaternion stream.
 pub const IMU_SUBS: usize = 3;
 pub static IMU_WATCH: Watch<CriticalSectionRawMutex, bool, IMU_SUBS> =
     Watch::new();
+/// Latest IMU sample, stamped at data-ready or, with the FIFO enabled,
+/// from the sensor's own timestamps.
 pub static IMU_DATA_WATCH: Watch<
     CriticalSectionRawMutex,
-    CalibSensorData,
+    Captured<CalibSensorData>,
     IMU_SUBS,
 > = Watch::new();
+/// Fused orientation, published at `ImuConfig::quaternion_rate` while
+/// `ImuConfig::quaternion_enabled` is set.
+pub static QUATERNION_WATCH: Watch<
+    CriticalSectionRawMutex,
+    ImuQuaternion,
+    IMU_SUBS,
+> = Watch::new();
+
+/// IMU data attached to an ADS sample.
+#[derive(Default)]
+pub struct AdsImuData {
+    pub accel: Option<[f32; 3]>,
+    pub gyro: Option<[f32; 3]>,
+    /// Fused orientation as `[w, x, y, z]`.
+    pub orientation: Option<[f32; 4]>,
+}
+
+/// The latest IMU data for an ADS sample: the fused orientation while
+/// `ImuConfig::ads_orientation` is in effect, the raw reading otherwise.
+pub fn ads_imu_data() -> AdsImuData {
+    if ADS_ORIENTATION.load(Ordering::Relaxed) {
+        let orientation =
+            QUATERNION_WATCH.try_get().map(|q| [q.w, q.x, q.y, q.z]);
+        return AdsImuData { orientation, ..Default::default() };
+    }
+    match IMU_DATA_WATCH.try_get() {
+        Some(imu) => AdsImuData {
+            accel: Some([imu.accel_x, imu.accel_y, imu.accel_z]),
+            gyro: Some([imu.gyro_x, imu.gyro_y, imu.gyro_z]),
+            orientation: None,
+        },
+        None => AdsImuData::default(),
+    }
+}
@@ -0,0 +1,121 @@
+//! Hands-free recording started and stopped by motion.
+//!
+//! While the active profile enables it, motion sensed by the IMU starts a
+//! session and the device lying still for
+//! [`MotionTriggerConfig::still_minutes`] stops it again. Sessions started
+//! any other way are left alone.
+
+use super::{last_motion, ImuManager};
+use crate::prelude::*;
+use dc_mini_icd::MotionTriggerConfig;
+use embassy_futures::select::{select, Either};
+use embassy_sync::signal::Signal;
+use embassy_time::Instant;
+
+/// How long a requested session may take to start recording.
+const SESSION_START_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often a session started by other means is checked for its end.
+const SESSION_POLL: Duration = Duration::from_secs(5);
+/// How often a disabled trigger re-reads its configuration, which picks
+/// up profile switches.
+const CONFIG_POLL: Duration = Duration::from_secs(30);
+
+static CONFIG_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Makes the motion trigger pick up a new configuration.
+pub fn motion_trigger_changed() {
+    CONFIG_CHANGED.signal(());
+}
+
+async fn load_config(
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) -> MotionTriggerConfig {
+    let mut ctx = app_context.lock().await;
+    ctx.profile_manager.get_motion_trigger().await.copied().unwrap_or_default()
+}
+
+/// A session this task started.
+struct AutoSession;
+
+impl AutoSession {
+    async fn start(
+        app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+    ) -> Option<Self> {
+        info!("[imu] motion detected, starting session");
+        {
+            // The session starts the ADS itself.
+            let ctx = app_context.lock().await;
+            ctx.event_sender.send(SessionEvent::StartRecording.into()).await;
+        }
+        let deadline = Instant::now() + SESSION_START_TIMEOUT;
+        while !session_active() {
+            if Instant::now() >= deadline {
+                warn!("[imu] motion-triggered session did not start");
+                return None;
+            }
+            Timer::after_millis(100).await;
+        }
+        Some(Self)
+    }
+
+    async fn stop(
+        self,
+        app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+    ) {
+        info!("[imu] device still, stopping session");
+        let ctx = app_context.lock().await;
+        ctx.event_sender.send(SessionEvent::StopRecording.into()).await;
+    }
+}
+
+/// Starts and stops sessions on motion as configured in the active
+/// profile.
+#[embassy_executor::task]
+pub async fn motion_trigger_task(
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+    imu_manager: ImuManager,
+) {
+    let mut session: Option<AutoSession> = None;
+    loop {
+        let config = load_config(app_context).await;
+        if !session_active() {
+            // Stopped by other means.
+            session = None;
+        }
+
+        if !config.enabled {
+            select(CONFIG_CHANGED.wait(), Timer::after(CONFIG_POLL)).await;
+            continue;
+        }
+
+        if !session_active() {
+            let motion = select(
+                imu_manager.wait_for_motion(Instant::MAX),
+                CONFIG_CHANGED.wait(),
+            )
+            .await;
+            // The profile may have changed while waiting.
+            if matches!(motion, Either::First(true))
+                && load_config(app_context).await.enabled
+            {
+                session = AutoSession::start(app_context).await;
+            }
+            continue;
+        }
+
+        let Some(auto) = session.take() else {
+            Timer::after(SESSION_POLL).await;
+            continue;
+        };
+        let still =
+            Duration::from_secs(config.still_minutes.max(1) as u64 * 60);
+        let deadline = last_motion() + still;
+        if Instant::now() >= deadline {
+            auto.stop(app_context).await;
+            continue;
+        }
+        session = Some(auto);
+        select(imu_manager.wait_for_motion(deadline), CONFIG_CHANGED.wait())
+            .await;
+    }
+}
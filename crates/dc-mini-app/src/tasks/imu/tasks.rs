@@ -1,10 +1,16 @@
 use super::*;
+use crate::clock::{Captured, SensorClock};
 use crate::prelude::*;
+use crate::selftest;
+use crate::tasks::power_control::sleep::WakePin;
 use dc_mini_bsp::ImuResources;
-use dc_mini_icd::ImuConfig;
+use dc_mini_icd::{ImuConfig, SelfTestResult};
 use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_nrf::gpio::{Input, Pull};
 use embassy_sync::mutex::Mutex;
+use embassy_time::{with_timeout, Instant};
+use icm_45605::Madgwick;
 use portable_atomic::Ordering;
 
 pub async fn probe_imu_presence(
@@ -39,12 +45,16 @@ pub async fn probe_imu_presence(
 pub async fn imu_task(
     bus_manager: &'static I2cBusManager,
     imu: &'static Mutex<CriticalSectionRawMutex, ImuResources>,
-    config: ImuConfig,
+    mut config: ImuConfig,
 ) {
     IMU_MEAS.store(true, Ordering::SeqCst);
 
     // Acquire bus handle - configures bus if needed
-    let handle = bus_manager.acquire().await.unwrap();
+    let Ok(handle) = bus_manager.acquire().await else {
+        error!("Failed to acquire I2C bus for IMU");
+        stop_degraded();
+        return;
+    };
 
     let mut imu_resources = imu.lock().await;
     let device = I2cDevice::new(handle.bus());
@@ -64,67 +74,350 @@ pub async fn imu_task(
 
     if !initialized {
         warn!("IMU init failed, stopping IMU task");
-        IMU_MEAS_SIG.reset();
-        IMU_MEAS.store(false, Ordering::SeqCst);
+        stop_degraded();
         return;
     }
 
     // Apply all configuration settings
-    apply_imu_config(&mut imu, &config).await;
+    if let Err(e) = apply_imu_config(&mut imu, &config).await {
+        error!("Failed to configure IMU: {:?}", e);
+        stop_degraded();
+        return;
+    }
 
     let sender = IMU_DATA_WATCH.sender();
+    let mut fusion = Fusion::new(&config);
+    ADS_ORIENTATION.store(config.ads_orientation, Ordering::Relaxed);
+    let mut motion_ref = None;
+    let mut sensor_clock =
+        SensorClock::new(icm_45605::FIFO_TMST_RESOLUTION_US);
+    // INT1 pulses at the FIFO watermark or, without the FIFO, on data
+    // ready; in between the task sleeps.
+    let mut int1 = Input::new(imu_resources.irq.reborrow(), Pull::Down);
+    // Set while a full batch suggests more frames are waiting.
+    let mut draining = false;
 
     loop {
         match select(IMU_MEAS_SIG.wait(), async {
-            match imu.new_data_ready().await {
-                Ok(ready) => {
-                    if !ready {
-                        return Ok(None);
-                    }
+            if !draining {
+                // The timeout catches a pulse missed while reading.
+                let _ = with_timeout(
+                    interrupt_timeout(&config),
+                    int1.wait_for_rising_edge(),
+                )
+                .await;
+                if !imu.new_data_ready().await? {
+                    return Ok(heapless::Vec::<_, 32>::new());
                 }
-                Err(e) => return Err(e),
             }
-            let raw = imu.read_6dof().await?;
-            Ok(Some(raw))
+            let ready_at = Instant::now();
+            if !config.fifo_enabled {
+                let raw = imu.read_6dof().await?;
+                return Ok(heapless::Vec::from_iter([(ready_at, raw)]));
+            }
+            // FIFO frames carry the sensor's own timestamps. The batch is
+            // only complete once read, so that is the time it is mapped
+            // against.
+            let batch = imu.read_fifo_data_calibrated().await?;
+            let ticks: heapless::Vec<u16, 32> =
+                batch.iter().map(|sample| sample.timestamp).collect();
+            let stamps: heapless::Vec<Instant, 32> =
+                sensor_clock.map_batch(&ticks, Instant::now());
+            Ok(stamps
+                .into_iter()
+                .zip(batch.into_iter().map(|sample| sample.data))
+                .collect())
         })
         .await
         {
-            Either::First(config) => {
-                if let Some(config) = config {
-                    // Stop all features before reconfiguring
-                    imu.stop_accel().await.unwrap();
-                    imu.stop_gyro().await.unwrap();
-
-                    // Flush FIFO if it was enabled
-                    if config.fifo_enabled {
-                        imu.flush_fifo().await.unwrap();
-                    }
+            Either::First(new_config) => {
+                if let Some(new_config) = new_config {
+                    let reconfigured = async {
+                        // Stop the sensors before reconfiguring
+                        imu.stop_accel().await?;
+                        imu.stop_gyro().await?;
+
+                        // Frames queued under the old config would be read
+                        // with the new scale and timing.
+                        if config.fifo_enabled || new_config.fifo_enabled {
+                            imu.flush_fifo().await?;
+                        }
 
-                    // Apply new configuration
-                    apply_imu_config(&mut imu, &config).await;
+                        apply_imu_config(&mut imu, &new_config).await
+                    }
+                    .await;
+                    if let Err(e) = reconfigured {
+                        error!("Failed to reconfigure IMU: {:?}", e);
+                        faults::degrade(
+                            FaultKind::SensorBusError,
+                            MonitoredTask::Imu,
+                        );
+                        IMU_WATCH.sender().send(false);
+                        break;
+                    }
+                    info!("IMU reconfigured");
+                    fusion = Fusion::new(&new_config);
+                    ADS_ORIENTATION
+                        .store(new_config.ads_orientation, Ordering::Relaxed);
+                    sensor_clock.reset();
+                    motion_ref = None;
+                    draining = false;
+                    config = new_config;
                 } else {
                     break;
                 }
             }
-            Either::Second(Ok(data)) => {
-                if let Some(data) = data {
-                    sender.send(data);
+            Either::Second(Ok(samples)) => {
+                heartbeat(MonitoredTask::Imu);
+                draining = config.fifo_enabled && samples.is_full();
+                for (at, data) in samples {
+                    if moved(
+                        &mut motion_ref,
+                        &data,
+                        config.wake_on_motion_threshold,
+                    ) {
+                        record_motion();
+                    }
+                    fusion.update(at, &data);
+                    sender.send(Captured::at(at, data));
                 }
-                Timer::after_nanos(config.accel_odr.sleep_duration_ns()).await;
             }
             Either::Second(Err(e)) => {
                 error!("Error reading IMU data: {:?}", e);
+                faults::degrade(FaultKind::SensorBusError, MonitoredTask::Imu);
+                IMU_WATCH.sender().send(false);
                 break;
             }
         }
     }
 
-    // Clean up - stop all features
-    imu.stop_accel().await.unwrap();
-    imu.stop_gyro().await.unwrap();
+    // Clean up - stop all features. After a bus error this may fail too.
+    let _ = imu.stop_accel().await;
+    let _ = imu.stop_gyro().await;
 
+    ADS_ORIENTATION.store(false, Ordering::Relaxed);
     IMU_MEAS_SIG.reset();
     IMU_MEAS.store(false, Ordering::SeqCst);
 
     // Handle and resources drop automatically, managing bus cleanup
 }
+
+/// Ends an IMU task that never got streaming, marking the IMU degraded so
+/// later requests are ignored.
+fn stop_degraded() {
+    faults::degrade(FaultKind::SensorBusError, MonitoredTask::Imu);
+    IMU_WATCH.sender().send(false);
+    IMU_MEAS_SIG.reset();
+    IMU_MEAS.store(false, Ordering::SeqCst);
+}
+
+/// Longest wait for INT1 before the status is read anyway: two interrupt
+/// periods.
+fn interrupt_timeout(config: &ImuConfig) -> Duration {
+    let frames = if config.fifo_enabled {
+        config.fifo_watermark.max(1) as u64
+    } else {
+        1
+    };
+    Duration::from_nanos(2 * frames * config.accel_odr.sleep_duration_ns())
+}
+
+/// Whether any acceleration axis moved more than `threshold_mg` from
+/// `reference`, which is then reset to `data`.
+fn moved(
+    reference: &mut Option<[f32; 3]>,
+    data: &CalibSensorData,
+    threshold_mg: u8,
+) -> bool {
+    let accel = [data.accel_x, data.accel_y, data.accel_z];
+    let threshold = threshold_mg as f32 / 1000.0;
+    let moved = match reference {
+        Some(reference) => reference
+            .iter()
+            .zip(accel.iter())
+            .any(|(r, a)| a - r > threshold || r - a > threshold),
+        None => false,
+    };
+    if moved || reference.is_none() {
+        *reference = Some(accel);
+    }
+    moved
+}
+
+/// Orientation fusion state of the IMU task.
+struct Fusion {
+    filter: Option<Madgwick>,
+    period: Duration,
+    last_sample: Option<Instant>,
+    next_publish: Instant,
+    seq: u32,
+}
+
+impl Fusion {
+    fn new(config: &ImuConfig) -> Self {
+        let rate = config.quaternion_rate.max(1) as u64;
+        Self {
+            filter: (config.quaternion_enabled || config.ads_orientation)
+                .then(Madgwick::default),
+            period: Duration::from_micros(1_000_000 / rate),
+            last_sample: None,
+            next_publish: Instant::now(),
+            seq: 0,
+        }
+    }
+
+    /// Feeds one sample captured at `now` and publishes the orientation
+    /// when it is due.
+    fn update(&mut self, now: Instant, data: &CalibSensorData) {
+        let Some(filter) = self.filter.as_mut() else {
+            return;
+        };
+        if let Some(last) = self.last_sample {
+            let dt = (now - last).as_micros() as f32 / 1_000_000.0;
+            filter.update(data, dt);
+        }
+        self.last_sample = Some(now);
+
+        if now < self.next_publish {
+            return;
+        }
+        self.next_publish = now + self.period;
+        let [w, x, y, z] = filter.quaternion();
+        QUATERNION_WATCH.sender().send(ImuQuaternion {
+            ts: crate::clock::timestamp_micros(now.as_micros()),
+            seq: self.seq,
+            w,
+            x,
+            y,
+            z,
+        });
+        self.seq = self.seq.wrapping_add(1);
+    }
+}
+
+/// Checks WHO_AM_I (via `init`) and runs the sensor's built-in self-test
+/// of every accelerometer and gyroscope axis.
+pub async fn imu_self_test(
+    bus_manager: &'static I2cBusManager,
+    imu: &'static Mutex<CriticalSectionRawMutex, ImuResources>,
+) -> SelfTestResult {
+    let Ok(handle) = bus_manager.acquire().await else {
+        return selftest::fail("I2C bus unavailable");
+    };
+
+    let mut imu_resources = imu.lock().await;
+    let device = I2cDevice::new(handle.bus());
+    let mut imu = imu_resources.configure_with_device(device).await;
+
+    if imu.init().await.is_err() {
+        return selftest::fail("WHO_AM_I check failed");
+    }
+    let axes = match imu.self_test().await {
+        Ok(axes) => axes,
+        Err(icm_45605::Error::SelfTestTimeout) => {
+            return selftest::fail("Built-in self-test did not finish")
+        }
+        Err(_) => return selftest::fail("Built-in self-test failed to run"),
+    };
+    if axes.passed() {
+        return selftest::pass("");
+    }
+    let mut message = heapless::String::<64>::new();
+    let _ = message.push_str("Failed axes:");
+    let failed = axes
+        .accel
+        .iter()
+        .zip(["ax", "ay", "az"])
+        .chain(axes.gyro.iter().zip(["gx", "gy", "gz"]))
+        .filter(|(pass, _)| !**pass);
+    for (_, axis) in failed {
+        let _ = message.push(' ');
+        let _ = message.push_str(axis);
+    }
+    selftest::fail(&message)
+}
+
+/// Enables wake-on-motion on INT1 and returns the pin to arm for System OFF.
+pub async fn imu_arm_wake_on_motion(
+    bus_manager: &'static I2cBusManager,
+    imu: &'static Mutex<CriticalSectionRawMutex, ImuResources>,
+    threshold_mg: u8,
+) -> Option<WakePin> {
+    let handle = bus_manager.acquire().await.ok()?;
+
+    let mut imu_resources = imu.lock().await;
+    // INT1 is configured active high in `init`.
+    let wake = WakePin::new(&*imu_resources.irq, true);
+    let device = I2cDevice::new(handle.bus());
+    let mut imu = imu_resources.configure_with_device(device).await;
+
+    if imu.init().await.is_err()
+        || imu.start_wake_on_motion(threshold_mg).await.is_err()
+    {
+        warn!("Failed to enable IMU wake-on-motion");
+        return None;
+    }
+    drop(imu);
+    drop(imu_resources);
+    // Keep the sensor rail up; the IMU must stay powered to wake us.
+    core::mem::forget(handle);
+    Some(wake)
+}
+
+/// Retry delay after wake-on-motion could not be enabled.
+const WOM_RETRY: Duration = Duration::from_secs(10);
+
+/// Enables wake-on-motion and waits for INT1 while the IMU is not
+/// streaming. Returns false if `until` passes first or the IMU is wanted
+/// elsewhere, see [`WOM_RELEASE`].
+pub async fn imu_wait_for_motion(
+    bus_manager: &'static I2cBusManager,
+    imu: &'static Mutex<CriticalSectionRawMutex, ImuResources>,
+    threshold_mg: u8,
+    until: Instant,
+) -> bool {
+    let retry = until.min(Instant::now() + WOM_RETRY);
+    let Ok(handle) = bus_manager.acquire().await else {
+        Timer::at(retry).await;
+        return false;
+    };
+
+    let mut imu_resources = imu.lock().await;
+    if IMU_MEAS.load(Ordering::SeqCst) {
+        return false;
+    }
+    let armed = {
+        let device = I2cDevice::new(handle.bus());
+        let mut imu = imu_resources.configure_with_device(device).await;
+        imu.init().await.is_ok()
+            && imu.start_wake_on_motion(threshold_mg).await.is_ok()
+    };
+    if !armed {
+        warn!("Failed to enable IMU wake-on-motion");
+        drop(imu_resources);
+        Timer::at(retry).await;
+        return false;
+    }
+
+    let motion = {
+        // INT1 is configured active high in `init`.
+        let mut int1 = Input::new(imu_resources.irq.reborrow(), Pull::Down);
+        matches!(
+            select3(
+                int1.wait_for_high(),
+                WOM_RELEASE.wait(),
+                Timer::at(until)
+            )
+            .await,
+            Either3::First(())
+        )
+    };
+
+    let device = I2cDevice::new(handle.bus());
+    let mut imu = imu_resources.configure_with_device(device).await;
+    let _ = imu.stop_accel().await;
+    if motion {
+        record_motion();
+    }
+    motion
+}
@@ -1,5 +1,7 @@
 use super::*;
 use crate::prelude::*;
+use crate::selftest;
+use dc_mini_icd::SelfTestResult;
 use derive_more::From;
 use embassy_sync::mutex::Mutex;
 use portable_atomic::Ordering;
@@ -27,6 +29,22 @@ impl MicManager {
         Self { mic, app }
     }
 
+    pub async fn self_test(&self) -> SelfTestResult {
+        if MIC_STREAMING.load(Ordering::SeqCst) {
+            return selftest::skipped("Mic streaming");
+        }
+        let config = {
+            let mut app_ctx = self.app.lock().await;
+            app_ctx
+                .profile_manager
+                .get_mic_config()
+                .await
+                .cloned()
+                .unwrap_or_else(default_mic_settings)
+        };
+        mic_self_test(self.mic, config).await
+    }
+
     pub async fn handle_event(&self, event: MicEvent) {
         info!("Received event {:?}", event);
         match event {
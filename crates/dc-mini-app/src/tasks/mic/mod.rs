@@ -1,6 +1,7 @@
 pub(crate) mod adpcm;
 pub(crate) mod config;
 pub(crate) mod events;
+pub(crate) mod vad;
 
 mod tasks; // Tasks module is private
 
@@ -8,6 +9,7 @@ pub use config::*;
 pub use events::*;
 use tasks::*;
 
+use crate::clock::Captured;
 use crate::prelude::*;
 use embassy_sync::pubsub::PubSubChannel;
 use embassy_sync::signal::Signal;
@@ -22,11 +24,20 @@ pub(self) static MIC_STREAM_SIG: Signal<
 > = Signal::new();
 
 pub const MIC_CAP: usize = 10;
-pub const MIC_SUBS: usize = 3;
+pub const MIC_SUBS: usize = 4;
 pub const MIC_BUF_SAMPLES: usize = 256;
 
 pub type MicCh<T> =
     PubSubChannel<CriticalSectionRawMutex, T, MIC_CAP, MIC_SUBS, 1>;
-pub static MIC_STREAM_CH: MicCh<[i16; MIC_BUF_SAMPLES]> = MicCh::new();
+/// One buffer of PCM samples, stamped with the capture of its first sample.
+pub type MicBuffer = Captured<[i16; MIC_BUF_SAMPLES]>;
+pub static MIC_STREAM_CH: MicCh<MicBuffer> = MicCh::new();
 pub static MIC_WATCH: Watch<CriticalSectionRawMutex, bool, MIC_SUBS> =
     Watch::new();
+/// Config the mic stream currently samples with, so streams can tag their
+/// frames with its rate and codec.
+pub static MIC_CONFIG_WATCH: Watch<
+    CriticalSectionRawMutex,
+    MicConfig,
+    MIC_SUBS,
+> = Watch::new();
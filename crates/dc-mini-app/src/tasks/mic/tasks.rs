@@ -1,12 +1,72 @@
 use super::*;
+use crate::clock::Captured;
 use crate::prelude::*;
-use dc_mini_icd::MicConfig;
+use crate::selftest;
+use dc_mini_icd::{MicConfig, SelfTestResult};
+use embassy_futures::select::{select, Either};
 use embassy_nrf::pdm::SamplerState;
 use embassy_sync::mutex::Mutex;
+use embassy_time::Instant;
 use portable_atomic::Ordering;
 
 const MIC_STARTUP_SETTLE_MS: u64 = 10;
 
+/// Stamps a buffer the DMA has just completed with the capture time of its
+/// first sample.
+fn captured(config: &MicConfig, buf: [i16; MIC_BUF_SAMPLES]) -> MicBuffer {
+    let rate = config.sample_rate.as_hz().max(1) as u64;
+    let span =
+        Duration::from_micros(MIC_BUF_SAMPLES as u64 * 1_000_000 / rate);
+    Captured::at(Instant::now() - span, buf)
+}
+
+/// Outcome of listening for speech between bursts.
+enum Listen {
+    Speech(MicBuffer),
+    /// A stop (`None`) or reconfigure request arrived.
+    Request(Option<MicConfig>),
+    Failed,
+}
+
+/// Wakes the mic for one buffer at a time until a buffer holds speech,
+/// leaving the PDM clock off in between.
+async fn listen_for_speech(
+    mic_resources: &mut MicResources,
+    config: &MicConfig,
+) -> Listen {
+    let interval = Duration::from_millis(config.vad.burst_interval_ms as u64);
+    loop {
+        heartbeat(MonitoredTask::Mic);
+        let mut buf = [0i16; MIC_BUF_SAMPLES];
+        let result = {
+            let mut spk = mic_resources.configure(
+                to_driver_config_with_channel(config, DEFAULT_MIC_CHANNEL),
+            );
+            spk.start().await;
+            Timer::after_millis(MIC_STARTUP_SETTLE_MS).await;
+            let result =
+                spk.sample(&mut buf).await.map(|()| captured(config, buf));
+            spk.stop().await;
+            result
+        };
+        let buf = match result {
+            Ok(buf) => buf,
+            Err(e) => {
+                error!("Error sampling microphone: {:?}", e);
+                return Listen::Failed;
+            }
+        };
+        if vad::is_speech(&config.vad, &buf.data) {
+            return Listen::Speech(buf);
+        }
+        if let Either::Second(request) =
+            select(Timer::after(interval), MIC_STREAM_SIG.wait()).await
+        {
+            return Listen::Request(request);
+        }
+    }
+}
+
 #[embassy_executor::task]
 pub async fn mic_stream_task(
     mic: &'static Mutex<CriticalSectionRawMutex, MicResources>,
@@ -22,19 +82,50 @@ pub async fn mic_stream_task(
     let mut active_config = config;
 
     'stream: loop {
+        MIC_CONFIG_WATCH.sender().send(active_config.clone());
+
+        // With VAD on, nothing is published until speech is heard.
+        let mut segment = None;
+        if active_config.vad.enabled {
+            match listen_for_speech(&mut mic_resources, &active_config).await {
+                Listen::Speech(buf) => {
+                    segment =
+                        Some(vad::SpeechSegment::start(&active_config.vad));
+                    if publisher.try_publish(buf).is_err() {
+                        warn!("Failed to publish mic data! Subscriber back pressure!");
+                    }
+                }
+                Listen::Request(Some(new_config)) => {
+                    active_config = new_config;
+                    continue 'stream;
+                }
+                Listen::Request(None) => break,
+                Listen::Failed => {
+                    faults::report(
+                        FaultKind::SensorBusError,
+                        Some(MonitoredTask::Mic),
+                    );
+                    break;
+                }
+            }
+        }
+
         let mut spk = mic_resources.configure(to_driver_config_with_channel(
             &active_config,
             DEFAULT_MIC_CHANNEL,
         ));
         let mut stop_requested = false;
         let mut next_config: Option<MicConfig> = None;
+        let mut speech_ended = false;
         let mut bufs = [[0i16; MIC_BUF_SAMPLES]; 2];
 
         info!("Mic streaming using {:?} edge", DEFAULT_MIC_CHANNEL);
 
         let run_result = spk
             .run_sampler(&mut bufs, |buf| {
-                if publisher.try_publish(*buf).is_err() {
+                heartbeat(MonitoredTask::Mic);
+                if publisher.try_publish(captured(&active_config, *buf)).is_err()
+                {
                     warn!("Failed to publish mic data! Subscriber back pressure!");
                 }
 
@@ -47,12 +138,24 @@ pub async fn mic_stream_task(
                     return SamplerState::Stopped;
                 }
 
+                if let Some(segment) = segment.as_mut() {
+                    if !segment.update(&active_config.vad, buf) {
+                        speech_ended = true;
+                        return SamplerState::Stopped;
+                    }
+                }
+
                 SamplerState::Sampled
             })
             .await;
+        drop(segment);
 
         if let Err(e) = run_result {
             error!("Error sampling microphone: {:?}", e);
+            faults::report(
+                FaultKind::SensorBusError,
+                Some(MonitoredTask::Mic),
+            );
             break;
         }
 
@@ -67,6 +170,10 @@ pub async fn mic_stream_task(
             break;
         }
 
+        if speech_ended {
+            continue 'stream;
+        }
+
         // Should not happen in normal operation; avoid spinning forever.
         break;
     }
@@ -99,7 +206,7 @@ pub async fn mic_single_sample_task(
             let publisher = MIC_STREAM_CH
                 .publisher()
                 .expect("This is the only expected publisher of MIC data.");
-            if let Err(_) = publisher.try_publish(buf) {
+            if let Err(_) = publisher.try_publish(captured(&config, buf)) {
                 warn!("Failed to publish single mic sample!");
             }
         }
@@ -111,3 +218,35 @@ pub async fn mic_single_sample_task(
     spk.stop().await;
     // Spk0838 drops, PDM disabled
 }
+
+/// Captures one buffer and fails if the microphone output is stuck.
+pub async fn mic_self_test(
+    mic: &'static Mutex<CriticalSectionRawMutex, MicResources>,
+    config: MicConfig,
+) -> SelfTestResult {
+    let mut mic_resources = mic.lock().await;
+    let mut spk = mic_resources.configure(to_driver_config_with_channel(
+        &config,
+        DEFAULT_MIC_CHANNEL,
+    ));
+
+    spk.start().await;
+    Timer::after_millis(MIC_STARTUP_SETTLE_MS).await;
+
+    let mut buf = [0i16; MIC_BUF_SAMPLES];
+    let result = spk.sample(&mut buf).await;
+    spk.stop().await;
+
+    match result {
+        Ok(()) => {
+            let min = buf.iter().min().copied().unwrap_or_default();
+            let max = buf.iter().max().copied().unwrap_or_default();
+            if min == max {
+                selftest::fail("Mic output stuck")
+            } else {
+                selftest::pass("")
+            }
+        }
+        Err(_) => selftest::fail("Mic capture failed"),
+    }
+}
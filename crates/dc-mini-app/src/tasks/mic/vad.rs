@@ -0,0 +1,82 @@
+//! Voice activity detection on microphone buffers.
+//!
+//! A buffer counts as speech when it is loud enough and crosses zero
+//! rarely enough to rule out hiss and other broadband noise. Both are
+//! measured around the buffer mean, since the PDM output carries a DC
+//! offset.
+
+use crate::clock::now_micros;
+use crate::prelude::*;
+use dc_mini_icd::{MarkerRecord, VadConfig};
+use embassy_time::Instant;
+
+const SPEECH_LABEL: &str = "vad_on";
+const SILENCE_LABEL: &str = "vad_off";
+
+pub(super) fn is_speech(config: &VadConfig, buf: &[i16]) -> bool {
+    if buf.is_empty() {
+        return false;
+    }
+    let len = buf.len() as i32;
+    let mean = buf.iter().map(|s| *s as i32).sum::<i32>() / len;
+    let energy =
+        buf.iter().map(|s| (*s as i32 - mean).unsigned_abs()).sum::<u32>()
+            / len as u32;
+    let crossings = buf
+        .windows(2)
+        .filter(|w| (w[0] as i32 >= mean) != (w[1] as i32 >= mean))
+        .count();
+    energy >= config.energy_threshold as u32
+        && crossings <= config.max_zero_crossings as usize
+}
+
+/// Follows a speech segment and ends it after the configured silence.
+pub(super) struct SpeechSegment {
+    hangover: Duration,
+    last_speech: Instant,
+}
+
+impl SpeechSegment {
+    /// Starts a segment on a buffer already found to hold speech.
+    pub(super) fn start(config: &VadConfig) -> Self {
+        info!("[mic] speech detected");
+        mark(SPEECH_LABEL);
+        Self {
+            hangover: Duration::from_millis(config.hangover_ms as u64),
+            last_speech: Instant::now(),
+        }
+    }
+
+    /// Takes the next buffer; returns false once the segment has ended.
+    pub(super) fn update(&mut self, config: &VadConfig, buf: &[i16]) -> bool {
+        let now = Instant::now();
+        if is_speech(config, buf) {
+            self.last_speech = now;
+        }
+        now - self.last_speech < self.hangover
+    }
+}
+
+impl Drop for SpeechSegment {
+    fn drop(&mut self) {
+        info!("[mic] speech ended");
+        mark(SILENCE_LABEL);
+    }
+}
+
+/// Notes a VAD transition in the active recording, if any.
+fn mark(label: &str) {
+    let marker = MarkerRecord {
+        ts: now_micros(),
+        // Not known on the device.
+        host_epoch_us: 0,
+        label: heapless::String::try_from(label).unwrap_or_default(),
+        recorded: false,
+    };
+    record_marker(&marker);
+}
+
+/// Whether `label` is the marker closing a speech segment.
+pub fn is_silence_marker(label: &str) -> bool {
+    label == SILENCE_LABEL
+}
@@ -42,10 +42,17 @@ pub use session::*;
 #[cfg(feature = "usb")]
 pub use usb::*;
 
-// Keeps our system alive
+/// Keeps our system alive for as long as every task watched with
+/// [`watch_heartbeat`] keeps reporting. A stalled task is recorded for the
+/// crash report and the watchdog then left to reset the device.
 #[embassy_executor::task]
 pub async fn watchdog_task(wdt: Peri<'static, WDT>) {
     let wdt_config = wdt::Config::try_new(&wdt).unwrap();
+    // Feeding later than half the timeout means something starved this
+    // task and nearly reset the device.
+    let near_miss = Duration::from_millis(
+        wdt_config.timeout_ticks as u64 * 1000 / 32768 / 2,
+    );
     let (_wdt, [mut handle]) = match Watchdog::try_new(wdt, wdt_config) {
         Ok(x) => x,
         Err(_) => {
@@ -55,8 +62,23 @@ pub async fn watchdog_task(wdt: Peri<'static, WDT>) {
             }
         }
     };
+    let mut last_pet = Instant::now();
     loop {
+        if let Some(task) = crate::stats::stalled_task() {
+            error!("{:?} task stalled, waiting for the watchdog", task);
+            crate::crash::record_stall(task);
+            core::future::pending::<()>().await;
+        }
         handle.pet();
+        let now = Instant::now();
+        if now - last_pet > near_miss {
+            warn!(
+                "Watchdog fed late after {} ms",
+                (now - last_pet).as_millis()
+            );
+            faults::report(FaultKind::WatchdogNearMiss, None);
+        }
+        last_pet = now;
         Timer::after(Duration::from_secs(2)).await;
     }
 }
@@ -111,7 +133,12 @@ pub async fn timer_task(duration: u64, sender: EventSender) {
 pub async fn heap_usage() {
     loop {
         Timer::after_secs(1).await;
-        info!("Heap Usage = {:?}", crate::ALLOCATOR.usage());
+        info!(
+            "Heap usage = {:?}, peak = {:?}, stack margin = {:?}",
+            crate::ALLOCATOR.usage(),
+            crate::ALLOCATOR.peak(),
+            crate::memory::stack_margin()
+        );
     }
 }
 
@@ -1,34 +1,42 @@
+//! Status LED effect engine.
+//!
+//! The LED shows, from highest precedence down: a host override, an alert
+//! sent on [`NEOPIX_CHAN`], the charger and battery indication, and the
+//! pattern the active profile maps to the current [`SystemState`].
+
 use crate::prelude::*;
-use embassy_nrf::gpio::AnyPin;
-use embassy_nrf::peripherals;
+use crate::status::SystemState;
+use crate::tasks::power_control::BATTERY_WATCH;
+use dc_mini_icd::{
+    BatteryStatus, ChargingState, LedEffect, LedOverride, LedPattern,
+    NeopixelConfig,
+};
+use embassy_futures::select::{select3, Either3};
 use embassy_nrf::pwm::Error as PwmError;
-use embassy_nrf::Peri;
 use embassy_sync::channel::Channel;
 use embassy_time::{Duration, Instant, Timer};
 use smart_leds::{brightness, colors, SmartLedsWriteAsync, RGB8};
-use ws2812_nrf_pwm::Ws2812;
 
 pub static NEOPIX_CHAN: Channel<CriticalSectionRawMutex, NeopixEvent, 4> =
     Channel::new();
 
 #[derive(Debug)]
 pub enum NeopixEvent {
-    PowerOn,
     PowerOff,
-    Recording,
     Color(RGB8),
     Flash(RGB8, Duration, Option<u8>), // Color, blink interval, duty cycle (0-100)
     FlashFor(RGB8, Duration, u32, Option<u8>), // Color, blink interval, number of cycles, duty cycle
     OnFor(RGB8, Duration),                     // Color and duration to stay on
+    Breathe(RGB8, Duration),                   // Color and fade period
+    Override(Option<LedOverride>), // Host override, `None` restores the status display
+    ConfigChanged,                 // Reload `NeopixelConfig` from the profile
 }
 
 #[cfg(feature = "defmt")]
 impl defmt::Format for NeopixEvent {
     fn format(&self, f: defmt::Formatter) {
         match self {
-            NeopixEvent::PowerOn => defmt::write!(f, "PowerOn"),
             NeopixEvent::PowerOff => defmt::write!(f, "PowerOff"),
-            NeopixEvent::Recording => defmt::write!(f, "Recording"),
             NeopixEvent::Color(c) => {
                 defmt::write!(f, "Color({},{},{})", c.r, c.g, c.b)
             }
@@ -54,18 +62,30 @@ impl defmt::Format for NeopixEvent {
             NeopixEvent::OnFor(c, d) => {
                 defmt::write!(f, "OnFor({},{},{}, {:?})", c.r, c.g, c.b, d)
             }
+            NeopixEvent::Breathe(c, d) => {
+                defmt::write!(f, "Breathe({},{},{}, {:?})", c.r, c.g, c.b, d)
+            }
+            NeopixEvent::Override(o) => defmt::write!(f, "Override({:?})", o),
+            NeopixEvent::ConfigChanged => defmt::write!(f, "ConfigChanged"),
         }
     }
 }
 
 const BRIGHTNESS: u8 = 10;
 const DEFAULT_DUTY_CYCLE: u8 = 50;
+/// Refresh interval while breathing.
+const BREATHE_STEP: Duration = Duration::from_millis(20);
+/// How often the system state is re-read while the LED is not animating.
+const STATE_POLL: Duration = Duration::from_millis(250);
 
 struct NeopixState {
     current_color: RGB8,
+    brightness: u8,
     mode: NeopixMode,
     end_time: Option<Instant>,
     remaining_cycles: Option<u32>,
+    /// A timed or counted effect has run out.
+    expired: bool,
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -73,18 +93,58 @@ enum NeopixMode {
     Off,
     Solid,
     Flashing { on_time: Duration, off_time: Duration },
+    Breathing { period: Duration },
 }
 
 impl NeopixState {
     fn new() -> Self {
         Self {
             current_color: colors::BLACK,
+            brightness: BRIGHTNESS,
             mode: NeopixMode::Off,
             end_time: None,
             remaining_cycles: None,
+            expired: false,
         }
     }
 
+    fn from_event(evt: NeopixEvent) -> Self {
+        let mut state = Self::new();
+        state.handle_event(evt);
+        state
+    }
+
+    fn from_override(cfg: &LedOverride) -> Self {
+        let color = RGB8::new(cfg.r, cfg.g, cfg.b);
+        Self::from_effect(color, &cfg.effect, cfg.brightness)
+    }
+
+    fn from_pattern(pattern: &LedPattern, brightness: u8) -> Self {
+        let color = RGB8::new(pattern.r, pattern.g, pattern.b);
+        Self::from_effect(color, &pattern.effect, brightness)
+    }
+
+    fn from_effect(color: RGB8, effect: &LedEffect, brightness: u8) -> Self {
+        let mut state = Self::new();
+        state.handle_event(match *effect {
+            LedEffect::Off => NeopixEvent::PowerOff,
+            LedEffect::Solid => NeopixEvent::Color(color),
+            LedEffect::Flash { interval_ms, duty_cycle } => {
+                NeopixEvent::Flash(
+                    color,
+                    Duration::from_millis(interval_ms.into()),
+                    Some(duty_cycle),
+                )
+            }
+            LedEffect::Breathe { period_ms } => NeopixEvent::Breathe(
+                color,
+                Duration::from_millis(period_ms.into()),
+            ),
+        });
+        state.brightness = brightness;
+        state
+    }
+
     fn calculate_flash_times(
         interval: Duration,
         duty_cycle: u8,
@@ -99,9 +159,9 @@ impl NeopixState {
         (on_time, off_time)
     }
 
-    async fn update<'a>(
+    async fn update(
         &mut self,
-        ws: &mut Ws2812<'a, 25>,
+        ws: &mut StatusLed<'_>,
     ) -> Result<(), PwmError> {
         // Check if we've reached the end time for timed operations
         if let Some(end_time) = self.end_time {
@@ -109,6 +169,7 @@ impl NeopixState {
                 self.mode = NeopixMode::Off;
                 self.current_color = colors::BLACK;
                 self.end_time = None;
+                self.expired = true;
             }
         }
 
@@ -118,13 +179,13 @@ impl NeopixState {
             }
             NeopixMode::Solid => {
                 let color = [self.current_color; 1];
-                let dimmed = brightness(color.into_iter(), BRIGHTNESS);
+                let dimmed = brightness(color.into_iter(), self.brightness);
                 ws.write(dimmed).await?;
             }
             NeopixMode::Flashing { on_time, off_time } => {
                 // Write current color
                 let color = [self.current_color; 1];
-                let dimmed = brightness(color.into_iter(), BRIGHTNESS);
+                let dimmed = brightness(color.into_iter(), self.brightness);
                 ws.write(dimmed).await?;
 
                 Timer::after(on_time).await;
@@ -141,37 +202,35 @@ impl NeopixState {
                         self.mode = NeopixMode::Off;
                         self.current_color = colors::BLACK;
                         self.remaining_cycles = None;
+                        self.expired = true;
                     }
                 }
             }
+            NeopixMode::Breathing { period } => {
+                // Triangle wave from off to full brightness and back.
+                let period_us = period.as_micros().max(2);
+                let half = period_us / 2;
+                let phase = Instant::now().as_micros() % period_us;
+                let level =
+                    if phase < half { phase } else { period_us - phase };
+                let scaled = (self.brightness as u64 * level / half) as u8;
+                let color = [self.current_color; 1];
+                ws.write(brightness(color.into_iter(), scaled)).await?;
+
+                Timer::after(BREATHE_STEP).await;
+            }
         }
         Ok(())
     }
 
     fn handle_event(&mut self, evt: NeopixEvent) {
         match evt {
-            NeopixEvent::PowerOn => {
-                let (on_time, off_time) =
-                    Self::calculate_flash_times(Duration::from_secs(3), 5);
-                self.mode = NeopixMode::Flashing { on_time, off_time };
-                self.current_color = colors::ALICE_BLUE;
-                self.end_time = None;
-                self.remaining_cycles = None;
-            }
             NeopixEvent::PowerOff => {
                 self.mode = NeopixMode::Off;
                 self.current_color = colors::BLACK;
                 self.end_time = None;
                 self.remaining_cycles = None;
             }
-            NeopixEvent::Recording => {
-                let (on_time, off_time) =
-                    Self::calculate_flash_times(Duration::from_secs(2), 25);
-                self.mode = NeopixMode::Flashing { on_time, off_time };
-                self.current_color = colors::MEDIUM_VIOLET_RED;
-                self.end_time = None;
-                self.remaining_cycles = None;
-            }
             NeopixEvent::Color(color) => {
                 self.mode = NeopixMode::Solid;
                 self.current_color = color;
@@ -211,40 +270,175 @@ impl NeopixState {
                 self.end_time = Some(Instant::now() + duration);
                 self.remaining_cycles = None;
             }
+            NeopixEvent::Breathe(color, period) => {
+                self.mode = NeopixMode::Breathing { period };
+                self.current_color = color;
+                self.end_time = None;
+                self.remaining_cycles = None;
+            }
+            // Overrides are layered on top of this state by `neopix_task`.
+            NeopixEvent::Override(_) | NeopixEvent::ConfigChanged => {}
         }
     }
 
-    fn is_flashing(&self) -> bool {
-        matches!(self.mode, NeopixMode::Flashing { .. })
+    fn is_animated(&self) -> bool {
+        matches!(
+            self.mode,
+            NeopixMode::Flashing { .. } | NeopixMode::Breathing { .. }
+        )
     }
 }
 
+fn state_pattern(state: SystemState, config: &NeopixelConfig) -> &LedPattern {
+    match state {
+        SystemState::Error => &config.error,
+        SystemState::Recording => &config.recording,
+        SystemState::Streaming => &config.streaming,
+        SystemState::Connected => &config.connected,
+        SystemState::Advertising => &config.advertising,
+        SystemState::Idle => &config.idle,
+    }
+}
+
+/// Picks the configured pattern for the charger and battery state, if any.
+fn battery_pattern<'a>(
+    status: &BatteryStatus,
+    config: &'a NeopixelConfig,
+) -> Option<&'a LedPattern> {
+    let faults = &status.faults;
+    if faults.battery_missing || faults.die_temp_high {
+        Some(&config.charger_fault)
+    } else {
+        match status.charging {
+            ChargingState::Complete => Some(&config.charged),
+            ChargingState::NotCharging => (status.state_of_charge
+                <= config.low_battery_soc)
+                .then_some(&config.low_battery),
+            _ => Some(&config.charging),
+        }
+    }
+}
+
+async fn load_config(
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) -> NeopixelConfig {
+    let mut ctx = app_context.lock().await;
+    ctx.profile_manager
+        .get_neopixel_config()
+        .await
+        .cloned()
+        .unwrap_or_default()
+}
+
+enum Input {
+    Event(NeopixEvent),
+    Battery(Option<BatteryStatus>),
+}
+
 #[embassy_executor::task]
 pub async fn neopix_task(
-    pwm: Peri<'static, peripherals::PWM0>,
-    pin: Peri<'static, AnyPin>,
+    led: StatusLedResources,
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
 ) {
     let receiver = NEOPIX_CHAN.receiver();
-    let mut ws: Ws2812<'_, 25> = Ws2812::new(pwm, pin);
-    let mut state = NeopixState::new();
-    state.handle_event(NeopixEvent::PowerOn);
-    unwrap!(state.update(&mut ws).await);
+    let mut battery_rx = unwrap!(BATTERY_WATCH.receiver());
+    let mut ws = led.into_led();
+    let mut config = load_config(app_context).await;
+    let mut led_override: Option<NeopixState> = None;
+    let mut alert: Option<NeopixState> = None;
+    let mut battery: Option<BatteryStatus> = None;
+    let mut pattern: Option<LedPattern> = None;
+    let mut indication: Option<NeopixState> = None;
+    let mut system = SystemState::current();
+    let mut base = NeopixState::from_pattern(
+        state_pattern(system, &config),
+        config.brightness,
+    );
+    unwrap!(base.update(&mut ws).await);
 
     loop {
-        // Check for new events with timeout if we're flashing
-        let evt = if state.is_flashing() {
+        let shown = led_override
+            .as_ref()
+            .or(alert.as_ref())
+            .or(indication.as_ref())
+            .unwrap_or(&base);
+
+        // Check for new events with timeout if we're animating
+        let input = if shown.is_animated() {
             match receiver.try_receive() {
-                Ok(evt) => Some(evt),
-                Err(_) => None,
+                Ok(evt) => Some(Input::Event(evt)),
+                Err(_) => battery_rx.try_changed().map(Input::Battery),
             }
         } else {
-            Some(receiver.receive().await)
+            match select3(
+                receiver.receive(),
+                battery_rx.changed(),
+                Timer::after(STATE_POLL),
+            )
+            .await
+            {
+                Either3::First(evt) => Some(Input::Event(evt)),
+                Either3::Second(status) => Some(Input::Battery(status)),
+                Either3::Third(()) => None,
+            }
         };
 
-        if let Some(evt) = evt {
-            state.handle_event(evt);
+        let mut config_changed = false;
+        match input {
+            Some(Input::Event(NeopixEvent::Override(cfg))) => {
+                led_override = cfg.as_ref().map(NeopixState::from_override);
+            }
+            Some(Input::Event(NeopixEvent::ConfigChanged)) => {
+                config = load_config(app_context).await;
+                config_changed = true;
+            }
+            Some(Input::Event(evt)) => {
+                alert = Some(NeopixState::from_event(evt))
+            }
+            Some(Input::Battery(status)) => {
+                // Also picks up profile switches without a dedicated event.
+                let latest = load_config(app_context).await;
+                if latest != config {
+                    config = latest;
+                    config_changed = true;
+                }
+                battery = status;
+            }
+            None => {}
+        }
+        if config_changed {
+            pattern = None;
+            indication = None;
+        }
+
+        // Only rebuild an indication when its pattern changes, so a new
+        // reading does not restart the animation.
+        let next = battery
+            .as_ref()
+            .and_then(|status| battery_pattern(status, &config));
+        if next != pattern.as_ref() {
+            pattern = next.cloned();
+            indication = pattern
+                .as_ref()
+                .map(|p| NeopixState::from_pattern(p, config.brightness));
+        }
+        let current = SystemState::current();
+        if current != system || config_changed {
+            system = current;
+            base = NeopixState::from_pattern(
+                state_pattern(system, &config),
+                config.brightness,
+            );
+        }
+        if alert.as_ref().is_some_and(|a| a.expired) {
+            alert = None;
         }
 
-        unwrap!(state.update(&mut ws).await);
+        let shown = led_override
+            .as_mut()
+            .or(alert.as_mut())
+            .or(indication.as_mut())
+            .unwrap_or(&mut base);
+        unwrap!(shown.update(&mut ws).await);
     }
 }
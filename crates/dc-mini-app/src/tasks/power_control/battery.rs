@@ -0,0 +1,251 @@
+use crate::prelude::*;
+use crate::selftest;
+#[cfg(not(feature = "sr6"))]
+use dc_mini_icd::ChargerFaults;
+use dc_mini_icd::{BatteryStatus, ChargingState, SelfTestResult};
+use embassy_futures::select::select;
+use embassy_sync::once_lock::OnceLock;
+use embassy_sync::signal::Signal;
+use embassy_sync::watch::Watch;
+
+/// PMIC used by the battery handlers. Registered by `main` on boards where
+/// the nPM1300 has a dedicated bus.
+#[cfg(not(feature = "sr6"))]
+pub static SHARED_PMIC: OnceLock<
+    &'static Mutex<CriticalSectionRawMutex, Pmic>,
+> = OnceLock::new();
+
+/// SAADC path to VSYS, used where the PMIC cannot measure it. Registered
+/// by `main`.
+pub static VSYS_SENSE: OnceLock<
+    &'static Mutex<CriticalSectionRawMutex, VsysSenseResources>,
+> = OnceLock::new();
+
+/// Wakes [`battery_monitor_task`] for a reading ahead of its interval,
+/// e.g. when the PMIC reports a charger change.
+pub static BATTERY_REFRESH: Signal<CriticalSectionRawMutex, ()> =
+    Signal::new();
+
+/// Latest battery reading, published by [`battery_monitor_task`]; `None`
+/// while the PMIC cannot be read.
+pub static BATTERY_WATCH: Watch<
+    CriticalSectionRawMutex,
+    Option<BatteryStatus>,
+    4,
+> = Watch::new();
+
+/// Interval between fuel gauge readings.
+const MONITOR_INTERVAL: Duration = Duration::from_secs(10);
+/// Approximate cell and protection circuit resistance (mΩ), used to recover
+/// the resting voltage while current is flowing.
+const CELL_RESISTANCE_MOHM: i32 = 150;
+
+/// Single-cell LiPo resting voltage (mV) to state of charge (%).
+const SOC_CURVE: [(u16, u8); 11] = [
+    (3300, 0),
+    (3500, 5),
+    (3600, 10),
+    (3700, 25),
+    (3750, 40),
+    (3800, 50),
+    (3850, 60),
+    (3900, 70),
+    (4000, 80),
+    (4100, 92),
+    (4200, 100),
+];
+
+/// Estimates the state of charge from the battery voltage by interpolating
+/// along [`SOC_CURVE`].
+pub fn estimate_state_of_charge(voltage_mv: u16) -> u8 {
+    let (first_mv, first_soc) = SOC_CURVE[0];
+    if voltage_mv <= first_mv {
+        return first_soc;
+    }
+    for pair in SOC_CURVE.windows(2) {
+        let (lo_mv, lo_soc) = pair[0];
+        let (hi_mv, hi_soc) = pair[1];
+        if voltage_mv <= hi_mv {
+            let span = (hi_mv - lo_mv) as u32;
+            let offset = (voltage_mv - lo_mv) as u32;
+            let delta = (hi_soc - lo_soc) as u32;
+            return lo_soc + (offset * delta / span) as u8;
+        }
+    }
+    100
+}
+
+/// Tracks the state of charge across fuel gauge readings.
+///
+/// The measured voltage is corrected for the IR drop of the battery current
+/// and smoothed, so load steps from streaming or the radio do not make the
+/// reported level jump.
+struct SocEstimator {
+    filtered_mv: Option<i32>,
+}
+
+impl SocEstimator {
+    const fn new() -> Self {
+        Self { filtered_mv: None }
+    }
+
+    /// Folds a new reading into the estimate and returns the state of charge.
+    fn update(&mut self, status: &BatteryStatus) -> u8 {
+        // Current is positive while charging, so this lowers the voltage
+        // under charge and raises it under load.
+        let resting_mv = status.voltage_mv as i32
+            - status.current_ma as i32 * CELL_RESISTANCE_MOHM / 1000;
+        let filtered = match self.filtered_mv {
+            Some(prev) => prev + (resting_mv - prev) / 8,
+            None => resting_mv,
+        };
+        self.filtered_mv = Some(filtered);
+        estimate_state_of_charge(filtered.clamp(0, u16::MAX as i32) as u16)
+    }
+
+    /// Drops the filter state, e.g. after the charger was (dis)connected.
+    fn reset(&mut self) {
+        self.filtered_mv = None;
+    }
+}
+
+/// Returns the latest reading from [`battery_monitor_task`], falling back
+/// to reading the PMIC directly before the first one is available. `None`
+/// if the PMIC cannot be read.
+pub async fn latest_battery_status() -> Option<BatteryStatus> {
+    match BATTERY_WATCH.try_get() {
+        Some(status) => status,
+        None => read_battery_status().await,
+    }
+}
+
+/// Periodically reads the fuel gauge, updates [`State`] and publishes the
+/// result on [`BATTERY_WATCH`].
+#[embassy_executor::task]
+pub async fn battery_monitor_task(
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) {
+    let sender = BATTERY_WATCH.sender();
+    let mut estimator = SocEstimator::new();
+    let mut was_charging = None;
+    #[cfg(feature = "sr6")]
+    let mut was_usb_powered = None;
+    loop {
+        let mut status = read_battery_status().await;
+        if let Some(status) = status.as_mut() {
+            let charging = status.charging != ChargingState::NotCharging;
+            if was_charging != Some(charging) {
+                estimator.reset();
+                was_charging = Some(charging);
+            }
+            status.state_of_charge = estimator.update(status);
+        }
+
+        let usb_powered = super::sleep::usb_powered();
+        // Other boards hear of charger changes from the PMIC interrupt.
+        #[cfg(feature = "sr6")]
+        {
+            if was_usb_powered.is_some_and(|was| was != usb_powered) {
+                crate::event_log::record(if usb_powered {
+                    SystemEventKind::ChargerAttached
+                } else {
+                    SystemEventKind::ChargerDetached
+                });
+            }
+            was_usb_powered = Some(usb_powered);
+        }
+
+        let vsys = read_vsys().await;
+        {
+            let mut ctx = app_context.lock().await;
+            if let Some(vsys) = vsys {
+                ctx.state.vsys_voltage = vsys;
+            }
+            ctx.state.usb_powered = usb_powered;
+        }
+        sender.send(status);
+        select(Timer::after(MONITOR_INTERVAL), BATTERY_REFRESH.wait()).await;
+    }
+}
+
+async fn read_vsys() -> Option<f32> {
+    #[cfg(not(feature = "sr6"))]
+    if let Some(pmic) = SHARED_PMIC.try_get() {
+        if let Ok(vsys) = pmic.lock().await.measure_vsys().await {
+            return Some(vsys);
+        }
+    }
+    if let Some(sense) = VSYS_SENSE.try_get() {
+        return Some(sense.lock().await.read_voltage().await);
+    }
+    None
+}
+
+/// Reads the battery and charger state from the PMIC, or `None` if it is
+/// not reachable.
+pub async fn read_battery_status() -> Option<BatteryStatus> {
+    #[cfg(not(feature = "sr6"))]
+    if let Some(pmic) = SHARED_PMIC.try_get() {
+        let mut pmic = pmic.lock().await;
+        if let Some(status) = read_from_pmic(&mut pmic).await {
+            return Some(status);
+        }
+        warn!("Failed to read battery status from PMIC");
+    }
+    None
+}
+
+/// Checks that the PMIC responds and reports no charger faults.
+pub async fn pmic_self_test() -> SelfTestResult {
+    if cfg!(feature = "sr6") {
+        return selftest::skipped("PMIC not shared on SR6");
+    }
+    let Some(status) = read_battery_status().await else {
+        return selftest::fail("PMIC not responding");
+    };
+    if status.faults.battery_missing {
+        selftest::fail("Battery not detected")
+    } else if status.faults.die_temp_high {
+        selftest::fail("PMIC die temperature high")
+    } else {
+        let mut msg: heapless::String<64> = heapless::String::new();
+        let _ = core::fmt::write(
+            &mut msg,
+            format_args!("VBAT {} mV", status.voltage_mv),
+        );
+        selftest::pass(&msg)
+    }
+}
+
+#[cfg(not(feature = "sr6"))]
+async fn read_from_pmic(pmic: &mut Pmic) -> Option<BatteryStatus> {
+    let vbat = pmic.measure_vbat().await.ok()?;
+    let ibat = pmic.measure_ibat().await.unwrap_or(0.0);
+    let die_temp = pmic.measure_die_temp().await.ok();
+    let chg = pmic.get_charger_status().await.ok()?;
+
+    let charging = if chg.completed {
+        ChargingState::Complete
+    } else if chg.constant_voltage {
+        ChargingState::ConstantVoltage
+    } else if chg.constant_current {
+        ChargingState::ConstantCurrent
+    } else if chg.trickle_charge {
+        ChargingState::Trickle
+    } else {
+        ChargingState::NotCharging
+    };
+
+    let voltage_mv = (vbat * 1000.0) as u16;
+    Some(BatteryStatus {
+        voltage_mv,
+        current_ma: ibat as i16,
+        state_of_charge: estimate_state_of_charge(voltage_mv),
+        charging,
+        faults: ChargerFaults {
+            battery_missing: !chg.battery_detected,
+            die_temp_high: chg.die_temp_high,
+        },
+        die_temp_c: die_temp.map(|t| t as i8),
+    })
+}
@@ -1,59 +1,139 @@
+use super::rails::{self, Rail, RailUser};
+use crate::prelude::*;
+#[cfg(feature = "sr6")]
+use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
 use embassy_nrf::gpio::{AnyPin, Level, Output, OutputDrive};
 use embassy_nrf::Peri;
 
+/// Time for the 5V rail to come up and the ADS1299 to finish its
+/// power-on reset (2^18 master clock cycles) before it can be reset and
+/// configured.
+const AFE_SETTLE: Duration = Duration::from_millis(150);
+
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PowerEvent {
-    Enable,
-    Disable,
+    /// The battery reached the shutdown threshold; handled by the
+    /// orchestrator, which owns the subsystems to stop.
+    LowBattery,
+    /// The battery or board reached its critical temperature; handled by
+    /// the orchestrator like [`PowerEvent::LowBattery`].
+    Overheat,
 }
 
+/// Rail claims, see [`rails::acquire`] and [`rails::release`].
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum PowerEventError {
-    InvalidConversion(u8),
-}
-
-impl TryFrom<u8> for PowerEvent {
-    type Error = PowerEventError;
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(PowerEvent::Enable),
-            1 => Ok(PowerEvent::Disable),
-            _ => Err(PowerEventError::InvalidConversion(value)),
-        }
-    }
+pub enum RailEvent {
+    Acquire(RailUser),
+    Release(RailUser),
 }
 
+/// Owns the switched rails and sequences them for their users.
 pub struct PowerManager {
-    count: u8,
-    pwctl: Output<'static>,
+    /// Claims currently held, one bit per [`RailUser`].
+    held: u8,
+    en5v: Output<'static>,
+    /// On SR6 the PMIC sits on TWIM1 itself.
+    #[cfg(feature = "sr6")]
+    twim1: &'static I2cBusManager,
 }
 
 impl PowerManager {
-    pub fn new(pwctl_pin: Peri<'static, AnyPin>) -> Self {
-        // SR6: active-low en5v — start High (off)
-        let pwctl = Output::new(pwctl_pin, Level::High, OutputDrive::Standard);
-        Self { count: 0, pwctl }
+    #[cfg(not(feature = "sr6"))]
+    pub fn new(en5v: Peri<'static, AnyPin>) -> Self {
+        // Active-low en5v: start High (off)
+        let en5v = Output::new(en5v, Level::High, OutputDrive::Standard);
+        Self { held: 0, en5v }
+    }
+
+    #[cfg(feature = "sr6")]
+    pub fn new(
+        en5v: Peri<'static, AnyPin>,
+        twim1: &'static I2cBusManager,
+    ) -> Self {
+        // Active-low en5v: start High (off)
+        let en5v = Output::new(en5v, Level::High, OutputDrive::Standard);
+        Self { held: 0, en5v, twim1 }
     }
-    pub async fn handle_event(&mut self, event: PowerEvent) {
+
+    pub async fn handle_event(&mut self, event: RailEvent) {
         match event {
-            PowerEvent::Enable => {
-                if self.count == 0 {
-                    // SR6: pull low to enable 5V rail
-                    self.pwctl.set_low();
+            RailEvent::Acquire(user) => {
+                let rail = user.rail();
+                if !self.powered(rail) {
+                    info!("Powering up {:?} rail for {:?}", rail, user);
+                    self.power_up(rail).await;
+                }
+                self.held |= user.bit();
+            }
+            RailEvent::Release(user) => {
+                if self.held & user.bit() == 0 {
+                    return;
+                }
+                self.held &= !user.bit();
+                let rail = user.rail();
+                if !self.powered(rail) {
+                    info!("Powering down {:?} rail", rail);
+                    self.power_down(rail).await;
+                }
+            }
+        }
+    }
+
+    /// Whether any held claim needs `rail`.
+    fn powered(&self, rail: Rail) -> bool {
+        [RailUser::Ads, RailUser::SensorBus]
+            .into_iter()
+            .any(|user| user.rail() == rail && self.held & user.bit() != 0)
+    }
+
+    async fn power_up(&mut self, rail: Rail) {
+        match rail {
+            Rail::Afe => {
+                self.en5v.set_low();
+                Timer::after(AFE_SETTLE).await;
+            }
+            #[cfg(not(feature = "sr6"))]
+            Rail::Sensor => {
+                let pmic = super::SHARED_PMIC.get().await;
+                if let Err(step) =
+                    rails::enable_ldsw1_ldo(&mut *pmic.lock().await).await
+                {
+                    error!("Sensor rail power-up failed at {}", step);
+                }
+            }
+            #[cfg(feature = "sr6")]
+            Rail::Sensor => {
+                let Ok(handle) = self.twim1.acquire().await else {
+                    error!("Failed to acquire I2C bus for the PMIC");
+                    return;
+                };
+                let mut pmic = npm1300::NPM1300::new(
+                    I2cDevice::new(handle.bus()),
+                    embassy_time::Delay,
+                );
+                if let Err(step) = rails::enable_ldsw1_ldo(&mut pmic).await {
+                    error!("Sensor rail power-up failed at {}", step);
                 }
-                self.count = self.count.wrapping_add(1);
             }
-            PowerEvent::Disable => {
-                if self.count > 0 {
-                    self.count = self.count.wrapping_sub(1);
-                    if self.count <= 0 {
-                        // SR6: pull high to disable 5V rail
-                        self.pwctl.set_high();
-                    }
+        }
+    }
+
+    async fn power_down(&mut self, rail: Rail) {
+        match rail {
+            Rail::Afe => self.en5v.set_high(),
+            #[cfg(not(feature = "sr6"))]
+            Rail::Sensor => {
+                let pmic = super::SHARED_PMIC.get().await;
+                if pmic.lock().await.disable_ldsw1().await.is_err() {
+                    error!("Sensor rail power-down failed");
                 }
             }
+            // On SR6 the PMIC shares TWIM1 with the sensors; the rail stays
+            // up.
+            #[cfg(feature = "sr6")]
+            Rail::Sensor => {}
         }
     }
 }
@@ -0,0 +1,53 @@
+use super::BATTERY_WATCH;
+use crate::prelude::*;
+use dc_mini_icd::{BatteryStatus, ChargingState, LowBatteryConfig};
+
+/// Consecutive low readings required, so a short sag under load does not
+/// shut the device down.
+const LOW_READINGS: u8 = 3;
+
+fn below_threshold(status: &BatteryStatus, config: &LowBatteryConfig) -> bool {
+    let faults = &status.faults;
+    config.enabled
+        && !faults.battery_missing
+        && status.charging == ChargingState::NotCharging
+        && (status.voltage_mv <= config.shutdown_mv
+            || status.state_of_charge <= config.shutdown_soc)
+}
+
+/// Watches the battery and requests [`PowerEvent::LowBattery`] once it
+/// stays below the configured shutdown threshold.
+#[embassy_executor::task]
+pub async fn low_battery_task(
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) {
+    let mut receiver = unwrap!(BATTERY_WATCH.receiver());
+    let mut low_readings = 0;
+    loop {
+        let Some(status) = receiver.changed().await else {
+            // Nothing to judge while the PMIC cannot be read.
+            low_readings = 0;
+            continue;
+        };
+        let (config, sender) = {
+            let mut ctx = app_context.lock().await;
+            (
+                ctx.profile_manager.get_low_battery_config().await,
+                ctx.event_sender,
+            )
+        };
+        if !below_threshold(&status, &config) {
+            low_readings = 0;
+            continue;
+        }
+        low_readings += 1;
+        warn!(
+            "Battery low: {} mV, {}%",
+            status.voltage_mv, status.state_of_charge
+        );
+        if low_readings >= LOW_READINGS {
+            sender.send(PowerEvent::LowBattery.into()).await;
+            return;
+        }
+    }
+}
@@ -1,3 +1,19 @@
+pub mod battery;
 pub mod events;
+pub mod low_battery;
+#[cfg(not(feature = "sr6"))]
+pub mod pmic_irq;
+pub mod profiler;
+pub mod rails;
+pub mod sleep;
+pub mod thermal;
+pub mod usb_c;
 
+pub use battery::*;
 pub use events::*;
+pub use low_battery::*;
+#[cfg(not(feature = "sr6"))]
+pub use pmic_irq::pmic_irq_task;
+pub use profiler::*;
+pub use rails::{Rail, RailUser};
+pub use thermal::*;
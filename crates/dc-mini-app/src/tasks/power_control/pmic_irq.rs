@@ -0,0 +1,127 @@
+//! Reacts to nPM1300 events as they happen, through the PMIC's interrupt
+//! line, instead of catching them on the next battery poll.
+//!
+//! [`configure`] routes the events below to PMIC GPIO1, which drives
+//! `npm_gpio` high while any of them is pending; [`pmic_irq_task`] reads
+//! and clears them, re-reading the USB-C sense when VBUS comes or goes.
+
+use super::{usb_c, BATTERY_REFRESH, SHARED_PMIC};
+use crate::prelude::*;
+use embassy_nrf::gpio::{Input, Pull};
+use embassy_nrf::peripherals::P1_12;
+use embassy_nrf::Peri;
+use npm1300::gpios::{GpioConfigBuilder, GpioMode};
+use npm1300::main::EventGroup;
+use npm1300::VsysThreshold;
+
+/// PMIC GPIO wired to `npm_gpio`.
+const IRQ_GPIO: u8 = 1;
+
+// Event bits within their groups, per the nPM1300 MAIN register map.
+const VBUS_DETECTED: u8 = 1 << 0;
+const VBUS_REMOVED: u8 = 1 << 1;
+const DIE_TEMP_HIGH: u8 = 1 << 4;
+const DIE_TEMP_RESUME: u8 = 1 << 5;
+const CHARGE_COMPLETED: u8 = 1 << 4;
+const CHARGE_ERROR: u8 = 1 << 5;
+const POF_WARNING: u8 = 1 << 0;
+
+/// Events handled by [`pmic_irq_task`], by group.
+const EVENTS: [(EventGroup, u8); 4] = [
+    (EventGroup::VbusIn0, VBUS_DETECTED | VBUS_REMOVED),
+    (EventGroup::BCharger0, DIE_TEMP_HIGH | DIE_TEMP_RESUME),
+    (EventGroup::BCharger1, CHARGE_COMPLETED | CHARGE_ERROR),
+    (EventGroup::PofWarn, POF_WARNING),
+];
+
+/// Sets GPIO1 up as the interrupt output and enables the events
+/// [`pmic_irq_task`] handles, with the power-fail warning at 3.2 V.
+pub async fn configure(pmic: &mut Pmic) {
+    let irq_config =
+        GpioConfigBuilder::new().mode(GpioMode::GpoInterrupt).build();
+    let mut ok = pmic.configure_gpio(IRQ_GPIO, irq_config).await.is_ok();
+    ok &= pmic.set_vsys_threshold(VsysThreshold::V32).await.is_ok();
+    ok &= pmic.enable_power_failure_detection(true).await.is_ok();
+    for (group, mask) in EVENTS {
+        // Stale events from before boot would hold the line high.
+        ok &= pmic.clear_events(group, 0xff).await.is_ok();
+        ok &= pmic.enable_events_interrupt(group, mask).await.is_ok();
+    }
+    if !ok {
+        warn!("[pmic] failed to configure the interrupt line");
+    }
+}
+
+/// Waits for the PMIC interrupt and dispatches the pending events.
+#[embassy_executor::task]
+pub async fn pmic_irq_task(
+    irq: Peri<'static, P1_12>,
+    mut sense: PmicUsbCSense,
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) {
+    let Some(pmic) = SHARED_PMIC.try_get() else {
+        warn!("[pmic] not shared, interrupt task not started");
+        return;
+    };
+    let mut irq = Input::new(irq, Pull::Down);
+    loop {
+        irq.wait_for_high().await;
+        let mut pending = [0u8; EVENTS.len()];
+        {
+            let mut pmic = pmic.lock().await;
+            for (i, (group, mask)) in EVENTS.into_iter().enumerate() {
+                match pmic.read_events(group).await {
+                    Ok(events) => {
+                        pending[i] = events & mask;
+                        let _ = pmic.clear_events(group, events).await;
+                    }
+                    Err(_) => warn!("[pmic] failed to read events"),
+                }
+            }
+            // VBUS came or went; the source may advertise another current.
+            if pending[0] != 0 {
+                usb_c::apply_current_limit(&mut *pmic, &mut sense).await;
+            }
+        }
+        handle(pending, app_context).await;
+        // Nothing we can clear is left; avoid spinning on a stuck line.
+        if irq.is_high() {
+            Timer::after_millis(100).await;
+        }
+    }
+}
+
+async fn handle(
+    [vbus, temp, charger, pof]: [u8; EVENTS.len()],
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) {
+    if vbus & VBUS_DETECTED != 0 {
+        info!("[pmic] charger attached");
+        crate::event_log::record(SystemEventKind::ChargerAttached);
+    }
+    if vbus & VBUS_REMOVED != 0 {
+        info!("[pmic] charger detached");
+        crate::event_log::record(SystemEventKind::ChargerDetached);
+    }
+    if temp & DIE_TEMP_HIGH != 0 {
+        warn!("[pmic] die temperature high, charging paused");
+        faults::report(FaultKind::PmicOverheat, None);
+    }
+    if temp & DIE_TEMP_RESUME != 0 {
+        info!("[pmic] die temperature back to normal");
+    }
+    if charger & CHARGE_COMPLETED != 0 {
+        info!("[pmic] charge complete");
+    }
+    if charger & CHARGE_ERROR != 0 {
+        warn!("[pmic] charger error");
+    }
+    if vbus | temp | charger != 0 {
+        BATTERY_REFRESH.signal(());
+    }
+    if pof & POF_WARNING != 0 {
+        warn!("[pmic] VSYS below the power-fail threshold");
+        let sender = app_context.lock().await.event_sender;
+        sender.send(PowerEvent::LowBattery.into()).await;
+    }
+}
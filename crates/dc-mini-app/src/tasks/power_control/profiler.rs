@@ -0,0 +1,163 @@
+//! Battery energy per system state, to compare the power draw of firmware
+//! releases.
+//!
+//! Once started, [`power_profile_task`] samples VBAT and IBAT a few times a
+//! second and adds the energy drawn since the previous sample to the state
+//! the device is in. Time on USB power is not profiled, since the battery
+//! then does not supply the system.
+
+use super::sleep::usb_powered;
+#[cfg(not(feature = "sr6"))]
+use super::SHARED_PMIC;
+use crate::prelude::*;
+use crate::status::SystemState;
+use core::cell::RefCell;
+use dc_mini_icd::{
+    PowerProfile, PowerState, PowerStateEnergy, POWER_STATE_COUNT,
+};
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Instant, Ticker};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+const STATES: [PowerState; POWER_STATE_COUNT] = [
+    PowerState::Idle,
+    PowerState::Advertising,
+    PowerState::Connected,
+    PowerState::Streaming,
+    PowerState::Recording,
+];
+
+#[derive(Clone, Copy)]
+struct Totals {
+    duration_ms: u64,
+    energy_uj: u64,
+}
+
+struct Profile {
+    running: bool,
+    usb_powered_ms: u64,
+    totals: [Totals; POWER_STATE_COUNT],
+}
+
+impl Profile {
+    const fn new(running: bool) -> Self {
+        Self {
+            running,
+            usb_powered_ms: 0,
+            totals: [Totals { duration_ms: 0, energy_uj: 0 };
+                POWER_STATE_COUNT],
+        }
+    }
+}
+
+static PROFILE: BlockingMutex<CriticalSectionRawMutex, RefCell<Profile>> =
+    BlockingMutex::new(RefCell::new(Profile::new(false)));
+/// Wakes the task when profiling starts (`true`) or stops.
+static RUN_SIG: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+
+/// Clears the totals and starts profiling. Returns false on boards where
+/// the PMIC cannot be read.
+pub fn start_power_profile() -> bool {
+    if cfg!(feature = "sr6") {
+        return false;
+    }
+    PROFILE.lock(|profile| *profile.borrow_mut() = Profile::new(true));
+    RUN_SIG.signal(true);
+    true
+}
+
+/// Stops profiling, keeping the totals.
+pub fn stop_power_profile() {
+    PROFILE.lock(|profile| profile.borrow_mut().running = false);
+    RUN_SIG.signal(false);
+}
+
+/// Totals since profiling was last started.
+pub fn power_profile() -> PowerProfile {
+    PROFILE.lock(|profile| {
+        let profile = profile.borrow();
+        PowerProfile {
+            running: profile.running,
+            usb_powered_ms: profile.usb_powered_ms,
+            states: STATES
+                .iter()
+                .zip(profile.totals.iter())
+                .map(|(state, totals)| PowerStateEnergy {
+                    state: *state,
+                    duration_ms: totals.duration_ms,
+                    energy_uj: totals.energy_uj,
+                })
+                .collect(),
+        }
+    })
+}
+
+fn power_state() -> PowerState {
+    match SystemState::activity() {
+        SystemState::Recording => PowerState::Recording,
+        SystemState::Streaming => PowerState::Streaming,
+        SystemState::Connected => PowerState::Connected,
+        SystemState::Advertising => PowerState::Advertising,
+        SystemState::Idle | SystemState::Error => PowerState::Idle,
+    }
+}
+
+/// Power drawn from the battery in milliwatts.
+async fn read_battery_power_mw() -> Option<f32> {
+    #[cfg(not(feature = "sr6"))]
+    if let Some(pmic) = SHARED_PMIC.try_get() {
+        let mut pmic = pmic.lock().await;
+        let vbat = pmic.measure_vbat().await.ok()?;
+        let ibat = pmic.measure_ibat().await.ok()?;
+        // IBAT (mA) is positive while charging.
+        return Some(vbat * -ibat);
+    }
+    None
+}
+
+/// Samples the battery power while profiling runs; see
+/// [`start_power_profile`].
+#[embassy_executor::task]
+pub async fn power_profile_task() {
+    loop {
+        while !RUN_SIG.wait().await {}
+        info!("[power] profiling started");
+        let mut ticker = Ticker::every(SAMPLE_INTERVAL);
+        let mut last = Instant::now();
+        loop {
+            match select(RUN_SIG.wait(), ticker.next()).await {
+                Either::First(false) => break,
+                // Restarted; the totals were cleared.
+                Either::First(true) => {
+                    last = Instant::now();
+                    continue;
+                }
+                Either::Second(()) => {}
+            }
+            let now = Instant::now();
+            let elapsed_ms = (now - last).as_millis();
+            last = now;
+
+            let state = power_state();
+            let on_usb = usb_powered();
+            let power_mw =
+                if on_usb { None } else { read_battery_power_mw().await };
+            PROFILE.lock(|profile| {
+                let mut profile = profile.borrow_mut();
+                if on_usb {
+                    profile.usb_powered_ms += elapsed_ms;
+                } else if let Some(power_mw) = power_mw {
+                    let totals = &mut profile.totals[state as usize];
+                    totals.duration_ms += elapsed_ms;
+                    // mW × ms = µJ
+                    totals.energy_uj +=
+                        (power_mw.max(0.0) * elapsed_ms as f32) as u64;
+                }
+            });
+        }
+        info!("[power] profiling stopped");
+    }
+}
@@ -0,0 +1,109 @@
+//! Switched supply rails and the subsystems that need them.
+//!
+//! Subsystems claim their rail with [`acquire`] and hand it back with
+//! [`release`]; the [`PowerManager`] sequences a rail up for its first
+//! user and down after its last. [`RailUser::rail`] is the one place that
+//! says which subsystem needs which rail.
+
+use super::{PowerManager, RailEvent};
+use crate::prelude::*;
+use embassy_sync::once_lock::OnceLock;
+use npm1300::NPM1300;
+
+/// Power manager shared by the rail users. Registered by `main`.
+pub static POWER_MANAGER: OnceLock<
+    Mutex<CriticalSectionRawMutex, PowerManager>,
+> = OnceLock::new();
+
+/// Supply rails switched by the firmware.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Rail {
+    /// 5V analog frontend supply, switched through `en5v`.
+    Afe,
+    /// 3V3 sensor supply, nPM1300 LDSW1 run as an LDO.
+    Sensor,
+}
+
+/// Subsystems that hold a rail while they run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RailUser {
+    /// The ADS1299, whenever it is out of power-down.
+    Ads,
+    /// TWIM1 and the IMU, APDS and haptic driver on it, while the bus is
+    /// up.
+    SensorBus,
+}
+
+impl RailUser {
+    /// The rail this user needs.
+    pub const fn rail(self) -> Rail {
+        match self {
+            RailUser::Ads => Rail::Afe,
+            RailUser::SensorBus => Rail::Sensor,
+        }
+    }
+
+    pub(crate) fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Claims `user`'s rail, returning once it is powered.
+pub async fn acquire(user: RailUser) {
+    let manager = POWER_MANAGER.get().await;
+    manager.lock().await.handle_event(RailEvent::Acquire(user)).await;
+}
+
+/// Hands `user`'s rail back, switching it off if no one else needs it.
+pub async fn release(user: RailUser) {
+    let manager = POWER_MANAGER.get().await;
+    manager.lock().await.handle_event(RailEvent::Release(user)).await;
+}
+
+/// Brings LDSW1 up as a 3.3V LDO for the sensor rail. LDSW1 first closes
+/// as a soft-started load switch, so the rail's capacitance charges at a
+/// limited current, then switches over to LDO mode.
+///
+/// Returns the step that failed if the PMIC rejects one.
+pub(crate) async fn enable_ldsw1_ldo<I2c, D>(
+    pmic: &mut NPM1300<I2c, D>,
+) -> Result<(), &'static str>
+where
+    I2c: embedded_hal_async::i2c::I2c,
+    D: embedded_hal_async::delay::DelayNs,
+{
+    use npm1300::{
+        gpios::{Gpio, GpioPolarity},
+        ldsw::LdoVoltage,
+        Ldsw1Ldosel, Ldsw1Softstartdisable, Ldsw1Softstartsel,
+    };
+
+    pmic.set_ldsw1_gpio_control(Gpio::None, GpioPolarity::NotInverted)
+        .await
+        .map_err(|_| "LDSW1 GPIO control")?;
+    pmic.set_ldsw2_gpio_control(Gpio::None, GpioPolarity::NotInverted)
+        .await
+        .map_err(|_| "LDSW2 GPIO control")?;
+    pmic.set_ldsw1_mode(Ldsw1Ldosel::Ldsw)
+        .await
+        .map_err(|_| "load switch mode")?;
+    pmic.configure_ldsw1_soft_start(
+        Ldsw1Softstartdisable::Noeffect,
+        Ldsw1Softstartsel::Ma50,
+    )
+    .await
+    .map_err(|_| "soft start")?;
+    pmic.enable_ldsw1().await.map_err(|_| "enable")?;
+    Timer::after_millis(500).await;
+    pmic.set_ldsw1_ldo_voltage(LdoVoltage::V3_3)
+        .await
+        .map_err(|_| "LDO voltage")?;
+    pmic.set_ldsw1_mode(Ldsw1Ldosel::Ldo).await.map_err(|_| "LDO mode")?;
+    match pmic.get_ldsw_status().await {
+        Ok(status) => info!("LDSW status: {:?}", status),
+        Err(_) => warn!("Failed to read LDSW status"),
+    }
+    Ok(())
+}
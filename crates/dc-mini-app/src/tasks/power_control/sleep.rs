@@ -0,0 +1,109 @@
+//! Inactivity auto-sleep.
+//!
+//! The orchestrator polls [`idle_expired`] and, once nothing has kept the
+//! device busy for [`AUTO_SLEEP_TIMEOUT`], powers down and calls
+//! [`enter_system_off`]. A wake pin brings it back through a reset.
+
+use crate::prelude::*;
+use crate::tasks::session::session_active;
+use embassy_nrf::gpio::Pin;
+use embassy_nrf::pac;
+use embassy_nrf::pac::gpio::vals::{Dir, Input, Pull, Sense};
+use embassy_time::Instant;
+use portable_atomic::{AtomicU64, AtomicU8, Ordering};
+
+/// Idle time after which the device powers off.
+pub const AUTO_SLEEP_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// How often the orchestrator checks for inactivity.
+pub const AUTO_SLEEP_POLL: Duration = Duration::from_secs(30);
+
+static LAST_ACTIVITY_MS: AtomicU64 = AtomicU64::new(0);
+static BLE_LINKS: AtomicU8 = AtomicU8::new(0);
+
+/// Restarts the inactivity timeout.
+pub fn record_activity() {
+    LAST_ACTIVITY_MS.store(Instant::now().as_millis(), Ordering::Relaxed);
+}
+
+/// Tracks BLE links; the device stays awake while any central is
+/// connected. Call once with `true` and once with `false` per link.
+pub fn set_ble_connected(connected: bool) {
+    if connected {
+        BLE_LINKS.fetch_add(1, Ordering::Relaxed);
+    } else {
+        BLE_LINKS.fetch_sub(1, Ordering::Relaxed);
+    }
+    record_activity();
+}
+
+/// Number of BLE centrals currently connected.
+pub fn ble_links() -> u8 {
+    BLE_LINKS.load(Ordering::Relaxed)
+}
+
+/// Whether any BLE central is connected.
+pub fn ble_connected() -> bool {
+    ble_links() > 0
+}
+
+/// VBUS is present while a USB host or charger is attached.
+pub fn usb_powered() -> bool {
+    pac::POWER.usbregstatus().read().vbusdetect()
+}
+
+/// Whether the device has been idle for [`AUTO_SLEEP_TIMEOUT`]: no session,
+/// no host connection and no activity reported.
+pub fn idle_expired() -> bool {
+    if session_active() || ble_connected() || usb_powered() {
+        record_activity();
+        return false;
+    }
+    let idle_ms = Instant::now()
+        .as_millis()
+        .saturating_sub(LAST_ACTIVITY_MS.load(Ordering::Relaxed));
+    idle_ms >= AUTO_SLEEP_TIMEOUT.as_millis()
+}
+
+/// A GPIO that wakes the chip from System OFF.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WakePin {
+    pin_port: u8,
+    active_high: bool,
+}
+
+impl WakePin {
+    pub fn new<P: Pin>(pin: &P, active_high: bool) -> Self {
+        Self { pin_port: pin.port() as u8 * 32 + pin.pin(), active_high }
+    }
+
+    fn arm(&self) {
+        let port = if self.pin_port < 32 { pac::P0 } else { pac::P1 };
+        let (pull, sense) = if self.active_high {
+            (Pull::PULLDOWN, Sense::HIGH)
+        } else {
+            (Pull::PULLUP, Sense::LOW)
+        };
+        port.pin_cnf(self.pin_port as usize % 32).write(|w| {
+            w.set_dir(Dir::INPUT);
+            w.set_input(Input::CONNECT);
+            w.set_pull(pull);
+            w.set_sense(sense);
+        });
+    }
+}
+
+/// Parks the board's pins, arms `wake` and enters System OFF. The chip
+/// resets when a wake pin reaches its active level.
+pub fn enter_system_off(wake: &[WakePin]) -> ! {
+    info!("Entering System OFF, wake pins: {:?}", wake);
+    DCMini::park_pins();
+    for pin in wake {
+        pin.arm();
+    }
+    pac::POWER.systemoff().write(|w| w.set_systemoff(true));
+    // System OFF is only emulated while a debugger is attached.
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
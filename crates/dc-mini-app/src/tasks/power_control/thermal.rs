@@ -0,0 +1,143 @@
+//! Keeps the battery and board within safe temperatures in firmware, ahead
+//! of the PMIC's own hardware cutoff.
+//!
+//! The battery NTC throttles and then stops charging as the cell warms up,
+//! and either sensor past its critical limit requests
+//! [`PowerEvent::Overheat`].
+
+use crate::prelude::*;
+use crate::tasks::imu::{IMU_DATA_WATCH, IMU_WATCH};
+
+/// Interval between temperature readings.
+const THERMAL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Charge current set by `main`.
+pub const CHARGE_CURRENT_MA: u16 = 32;
+/// Charge current while the battery is warm.
+const REDUCED_CHARGE_MA: u16 = 16;
+
+/// Battery temperature (°C) above which charging is throttled.
+const BATTERY_WARM_C: f32 = 40.0;
+/// Battery temperature (°C) above which charging stops.
+const BATTERY_HOT_C: f32 = 45.0;
+/// Battery temperature (°C) at which the device shuts down.
+const BATTERY_CRITICAL_C: f32 = 60.0;
+/// Board temperature (°C), from the IMU, at which the device shuts down.
+const BOARD_CRITICAL_C: f32 = 70.0;
+/// How far a temperature must drop below a limit to undo its action.
+const HYSTERESIS_C: f32 = 3.0;
+/// Consecutive critical readings required, so one bad conversion does not
+/// shut the device down.
+const CRITICAL_READINGS: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum ChargeLimit {
+    Normal,
+    Reduced,
+    Stopped,
+}
+
+impl ChargeLimit {
+    /// The limit for `temp_c`, given the current one; a limit is only
+    /// lifted once the battery is [`HYSTERESIS_C`] below it.
+    fn for_temperature(self, temp_c: f32) -> Self {
+        let hot = if self == Self::Stopped {
+            BATTERY_HOT_C - HYSTERESIS_C
+        } else {
+            BATTERY_HOT_C
+        };
+        let warm = if self == Self::Normal {
+            BATTERY_WARM_C
+        } else {
+            BATTERY_WARM_C - HYSTERESIS_C
+        };
+        if temp_c >= hot {
+            Self::Stopped
+        } else if temp_c >= warm {
+            Self::Reduced
+        } else {
+            Self::Normal
+        }
+    }
+}
+
+/// Reads the battery and board temperatures, adjusts charging and
+/// requests a shutdown once either gets critical.
+#[embassy_executor::task]
+pub async fn thermal_task(
+    app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
+) {
+    let mut limit = ChargeLimit::Normal;
+    let mut critical_readings = 0;
+    loop {
+        Timer::after(THERMAL_INTERVAL).await;
+
+        let battery_c = read_battery_temp().await;
+        // The IMU sample is only current while it streams.
+        let board_c = if IMU_WATCH.try_get() == Some(true) {
+            IMU_DATA_WATCH.try_get().map(|imu| imu.temp)
+        } else {
+            None
+        };
+
+        if let Some(temp_c) = battery_c {
+            let next = limit.for_temperature(temp_c);
+            if next != limit && apply_charge_limit(next).await {
+                warn!("Battery at {} C, charging {:?}", temp_c, next);
+                limit = next;
+            }
+        }
+
+        let critical = battery_c.is_some_and(|t| t >= BATTERY_CRITICAL_C)
+            || board_c.is_some_and(|t| t >= BOARD_CRITICAL_C);
+        if !critical {
+            critical_readings = 0;
+            continue;
+        }
+        critical_readings += 1;
+        error!(
+            "Overheating: battery {:?} C, board {:?} C",
+            battery_c, board_c
+        );
+        if critical_readings >= CRITICAL_READINGS {
+            let sender = app_context.lock().await.event_sender;
+            sender.send(PowerEvent::Overheat.into()).await;
+            return;
+        }
+    }
+}
+
+async fn read_battery_temp() -> Option<f32> {
+    #[cfg(not(feature = "sr6"))]
+    if let Some(pmic) = super::SHARED_PMIC.try_get() {
+        return pmic.lock().await.measure_ntc().await.ok();
+    }
+    None
+}
+
+/// Programs the charger for `limit`; false if the PMIC could not be
+/// reached, so the change is retried on the next reading.
+async fn apply_charge_limit(limit: ChargeLimit) -> bool {
+    #[cfg(not(feature = "sr6"))]
+    if let Some(pmic) = super::SHARED_PMIC.try_get() {
+        let mut pmic = pmic.lock().await;
+        return match limit {
+            ChargeLimit::Normal | ChargeLimit::Reduced => {
+                let current_ma = if limit == ChargeLimit::Normal {
+                    CHARGE_CURRENT_MA
+                } else {
+                    REDUCED_CHARGE_MA
+                };
+                pmic.set_charger_current(current_ma).await.is_ok()
+                    && pmic.enable_battery_charging().await.is_ok()
+            }
+            ChargeLimit::Stopped => {
+                pmic.disable_battery_charging().await.is_ok()
+            }
+        };
+    }
+    #[cfg(feature = "sr6")]
+    let _ = limit;
+    false
+}
@@ -0,0 +1,32 @@
+//! VBUS input current limit from the USB-C source's advertisement.
+//!
+//! The nPM1300 starts at 100 mA, which USB allows without enumeration.
+//! [`apply_current_limit`] raises it to the PMIC's 1.5 A ceiling when the
+//! source advertises 1.5 A or more on CC, and drops it back otherwise.
+
+use crate::prelude::*;
+use npm1300::sysreg::VbusInCurrentLimit;
+use npm1300::NPM1300;
+
+/// Reads the USB-C sense and sets the VBUS input current limit to match.
+pub async fn apply_current_limit<I2c, D, S>(
+    pmic: &mut NPM1300<I2c, D>,
+    sense: &mut UsbCSense<S>,
+) where
+    I2c: embedded_hal_async::i2c::I2c,
+    D: embedded_hal_async::delay::DelayNs,
+    S: embedded_hal_async::i2c::I2c,
+{
+    let Ok(status) = sense.read().await else {
+        warn!("[usb-c] failed to read the CC status");
+        return;
+    };
+    info!("[usb-c] {:?}", status);
+    let limit = match status.current {
+        UsbCurrent::A1_5 | UsbCurrent::A3_0 => VbusInCurrentLimit::MA1500,
+        UsbCurrent::None | UsbCurrent::Default => VbusInCurrentLimit::MA100,
+    };
+    if pmic.set_vbus_in_current_limit(limit).await.is_err() {
+        warn!("[usb-c] failed to set the VBUS current limit");
+    }
+}
@@ -0,0 +1,85 @@
+use super::container::ContainerWriter;
+use crate::tasks::mic::adpcm::AdpcmEncoder;
+use dc_mini_icd::container::RecordKind;
+use dc_mini_icd::mic_proto::{MicCodec, MicDataFrame};
+use dc_mini_icd::MicConfig;
+
+/// Mic buffers combined into one record (128 ms at 16 kHz).
+const BUFFERS_PER_RECORD: usize = 8;
+
+/// Batches microphone buffers into `Mic` container records holding either
+/// ADPCM (`adpcm_data`) or raw little-endian PCM (`pcm_data`) audio.
+pub(super) struct MicRecorder {
+    /// `None` records raw PCM.
+    encoder: Option<AdpcmEncoder>,
+    frame: MicDataFrame,
+    buffers: usize,
+}
+
+impl MicRecorder {
+    pub(super) fn new(config: &MicConfig) -> Self {
+        Self {
+            encoder: config.record_adpcm.then(AdpcmEncoder::new),
+            frame: MicDataFrame {
+                sample_rate: config.sample_rate.as_hz(),
+                codec: if config.record_adpcm {
+                    MicCodec::Adpcm
+                } else {
+                    MicCodec::Pcm
+                } as i32,
+                ..Default::default()
+            },
+            buffers: 0,
+        }
+    }
+
+    /// Adds one buffer of samples captured at `ts`, emitting a record once
+    /// it is full.
+    pub(super) fn push(
+        &mut self,
+        ts: u64,
+        pcm: &[i16],
+        writer: &mut ContainerWriter,
+    ) {
+        if self.buffers == 0 {
+            self.frame.ts = ts;
+            if let Some(encoder) = &self.encoder {
+                let (predictor, step_index) = encoder.decoder_state();
+                self.frame.predictor = predictor;
+                self.frame.step_index = step_index;
+            }
+        }
+        match self.encoder.as_mut() {
+            Some(encoder) => {
+                let start = self.frame.adpcm_data.len();
+                self.frame.adpcm_data.resize(start + pcm.len() / 2, 0);
+                encoder.encode_block(pcm, &mut self.frame.adpcm_data[start..]);
+            }
+            None => {
+                for sample in pcm {
+                    self.frame
+                        .pcm_data
+                        .extend_from_slice(&sample.to_le_bytes());
+                }
+            }
+        }
+        self.buffers += 1;
+        if self.buffers >= BUFFERS_PER_RECORD {
+            self.finish(writer);
+        }
+    }
+
+    /// Emits the pending buffers, if any, as a record.
+    pub(super) fn finish(&mut self, writer: &mut ContainerWriter) {
+        if self.buffers == 0 {
+            return;
+        }
+        writer.push_proto(RecordKind::Mic, self.frame.ts, &self.frame);
+
+        self.frame.adpcm_data.clear();
+        self.frame.pcm_data.clear();
+        self.frame.packet_counter += 1;
+        self.frame.seq = self.frame.packet_counter as u32;
+        self.buffers = 0;
+    }
+}
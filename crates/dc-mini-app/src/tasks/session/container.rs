@@ -0,0 +1,73 @@
+use crate::storage::SessionSink;
+use dc_mini_icd::container::{FileHeader, RecordHeader, RecordKind};
+use prost::Message;
+use serde::Serialize;
+
+/// Buffered bytes are handed to the SD card once they reach this size.
+const FLUSH_SIZE: usize = 4096;
+
+/// Largest postcard-encoded record payload.
+const MAX_POSTCARD_LEN: usize = 256;
+
+/// Builds a session container (see [`dc_mini_icd::container`]) in RAM and
+/// hands it to the file in SD-friendly chunks.
+pub(super) struct ContainerWriter {
+    buf: alloc::vec::Vec<u8>,
+}
+
+impl ContainerWriter {
+    /// Starts a new container; the file header is written with the first
+    /// chunk.
+    pub(super) fn new() -> Self {
+        let mut buf = alloc::vec::Vec::with_capacity(FLUSH_SIZE);
+        buf.extend_from_slice(&FileHeader::current().to_bytes());
+        Self { buf }
+    }
+
+    pub(super) fn push_proto(
+        &mut self,
+        kind: RecordKind,
+        ts: u64,
+        message: &impl Message,
+    ) {
+        let header = RecordHeader::new(kind, message.encoded_len() as u32, ts);
+        self.buf.extend_from_slice(&header.to_bytes());
+        // Encoding into a `Vec` only fails if it cannot grow.
+        message.encode(&mut self.buf).unwrap();
+    }
+
+    /// Returns `false` if `value` does not fit a record.
+    pub(super) fn push_postcard(
+        &mut self,
+        kind: RecordKind,
+        ts: u64,
+        value: &impl Serialize,
+    ) -> bool {
+        let mut scratch = [0u8; MAX_POSTCARD_LEN];
+        let Ok(payload) = postcard::to_slice(value, &mut scratch) else {
+            return false;
+        };
+        let header = RecordHeader::new(kind, payload.len() as u32, ts);
+        self.buf.extend_from_slice(&header.to_bytes());
+        self.buf.extend_from_slice(payload);
+        true
+    }
+
+    /// Whether enough data is buffered to be written out.
+    pub(super) fn is_full(&self) -> bool {
+        self.buf.len() >= FLUSH_SIZE
+    }
+
+    /// Writes the buffered bytes to `sink` and returns how many were
+    /// written. The buffer is cleared even if the write fails.
+    pub(super) async fn drain<S: SessionSink>(
+        &mut self,
+        sink: &mut S,
+    ) -> Result<usize, S::Error> {
+        let len = self.buf.len();
+        let result =
+            if len > 0 { sink.write(&self.buf).await } else { Ok(()) };
+        self.buf.clear();
+        result.map(|_| len)
+    }
+}
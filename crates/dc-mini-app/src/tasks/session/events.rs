@@ -1,5 +1,8 @@
 use super::*;
 use crate::prelude::*;
+use crate::selftest;
+use crate::tasks::mic::MIC_WATCH;
+use dc_mini_icd::SelfTestResult;
 use portable_atomic::Ordering;
 use session::recording_task;
 
@@ -8,6 +11,9 @@ use session::recording_task;
 pub enum SessionEvent {
     StartRecording,
     StopRecording,
+    /// Sent by the recording task when it stopped on its own, e.g. because
+    /// the SD card filled up.
+    RecordingAborted,
 }
 
 #[derive(Debug)]
@@ -30,6 +36,8 @@ impl TryFrom<u8> for SessionEvent {
 pub struct SessionManager {
     app: &'static Mutex<CriticalSectionRawMutex, AppContext>,
     sd: &'static Mutex<CriticalSectionRawMutex, SdCardResources>,
+    /// The microphone was started for the active recording.
+    started_mic: bool,
 }
 
 impl SessionManager {
@@ -37,7 +45,19 @@ impl SessionManager {
         app: &'static Mutex<CriticalSectionRawMutex, AppContext>,
         sd: &'static Mutex<CriticalSectionRawMutex, SdCardResources>,
     ) -> Self {
-        Self { app, sd }
+        Self { app, sd, started_mic: false }
+    }
+
+    /// Checks the SD card with a scratch file; skipped while recording.
+    pub async fn self_test(&self) -> SelfTestResult {
+        if SESSION_ACTIVE.load(Ordering::SeqCst) {
+            return selftest::skipped("Recording");
+        }
+        let mut sd = self.sd.lock().await;
+        match write_test(&mut sd) {
+            Ok(()) => selftest::pass(""),
+            Err(e) => selftest::fail(e),
+        }
     }
 
     pub async fn handle_event(&mut self, event: SessionEvent) {
@@ -47,13 +67,56 @@ impl SessionManager {
                     warn!("Tried to StartRecording while recording already active!");
                     return;
                 }
+                if SD_PRESENT_WATCH.try_get() == Some(false) {
+                    warn!("No SD card, not recording");
+                    return;
+                }
                 SESSION_SIG.reset();
                 let mut app_ctx = self.app.lock().await;
                 let id =
                     app_ctx.profile_manager.get_session_id().await.cloned();
+                let metadata = app_ctx
+                    .profile_manager
+                    .get_session_metadata()
+                    .await
+                    .cloned();
+                let mic_present = app_ctx.capabilities().mic_present;
+                let mic = app_ctx
+                    .profile_manager
+                    .get_mic_config()
+                    .await
+                    .cloned()
+                    .filter(|config| config.record_to_sd)
+                    .filter(|_| mic_present);
+                // Start the microphone for the session unless it is
+                // already streaming, and stop it again afterwards.
+                self.started_mic =
+                    mic.is_some() && MIC_WATCH.try_get() != Some(true);
+                if self.started_mic {
+                    app_ctx
+                        .event_sender
+                        .send(MicEvent::StartStream.into())
+                        .await;
+                }
+                app_ctx.low_prio_spawner.must_spawn(recording_task(
+                    self.sd,
+                    app_ctx.event_sender,
+                    id,
+                    metadata,
+                    mic,
+                ));
+                // Shares the acquisition with any live stream.
                 app_ctx
-                    .low_prio_spawner
-                    .must_spawn(recording_task(self.sd, id));
+                    .event_sender
+                    .send(AdsEvent::StartStream(AdsClaim::Session).into())
+                    .await;
+                app_ctx
+                    .event_sender
+                    .send(
+                        HapticEvent::Trigger(HapticTrigger::SessionStart)
+                            .into(),
+                    )
+                    .await;
             }
             SessionEvent::StopRecording => {
                 if !SESSION_ACTIVE.load(Ordering::SeqCst) {
@@ -61,6 +124,40 @@ impl SessionManager {
                     return;
                 }
                 SESSION_SIG.signal(());
+                let app_ctx = self.app.lock().await;
+                app_ctx
+                    .event_sender
+                    .send(AdsEvent::StopStream(AdsClaim::Session).into())
+                    .await;
+                if self.started_mic {
+                    self.started_mic = false;
+                    app_ctx
+                        .event_sender
+                        .send(MicEvent::StopStream.into())
+                        .await;
+                }
+                app_ctx
+                    .event_sender
+                    .send(
+                        HapticEvent::Trigger(HapticTrigger::SessionStop)
+                            .into(),
+                    )
+                    .await;
+            }
+            SessionEvent::RecordingAborted => {
+                // The task has already reported why and finished the file.
+                let app_ctx = self.app.lock().await;
+                app_ctx
+                    .event_sender
+                    .send(AdsEvent::StopStream(AdsClaim::Session).into())
+                    .await;
+                if self.started_mic {
+                    self.started_mic = false;
+                    app_ctx
+                        .event_sender
+                        .send(MicEvent::StopStream.into())
+                        .await;
+                }
             }
         }
     }
@@ -0,0 +1,76 @@
+//! Free space on the card, counted in the FAT.
+//!
+//! embedded-sdmmc does not report free space and leaves the FSInfo free
+//! count stale, so the free clusters are counted in the first FAT of the
+//! volume the recordings go to. That reads the whole FAT, which takes a
+//! few seconds on a large card.
+
+use embedded_sdmmc::fat::Bpb;
+use embedded_sdmmc::{Block, BlockDevice, BlockIdx};
+
+/// First partition entry in the MBR.
+const PARTITION1_START: usize = 446;
+const PARTITION_LBA_START: usize = 8;
+/// Blocks read from the FAT at a time.
+const FAT_BLOCKS_PER_READ: usize = 4;
+/// Cluster numbers start at 2; the first two FAT entries are reserved.
+const FIRST_CLUSTER: u32 = 2;
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[at],
+        bytes[at + 1],
+        bytes[at + 2],
+        bytes[at + 3],
+    ])
+}
+
+/// Bytes in the free clusters of the first partition.
+pub fn free_bytes<D: BlockDevice>(card: &D) -> Result<u64, &'static str> {
+    let mut blocks: [Block; FAT_BLOCKS_PER_READ] = Default::default();
+
+    card.read(&mut blocks[..1], BlockIdx(0)).map_err(|_| "Read MBR failed")?;
+    let lba_start =
+        read_u32(&blocks[0], PARTITION1_START + PARTITION_LBA_START);
+
+    card.read(&mut blocks[..1], BlockIdx(lba_start))
+        .map_err(|_| "Read BPB failed")?;
+    let (entry_len, fat_start, fat_blocks, end, cluster_bytes) = {
+        let bpb = Bpb::create_from_bytes(&blocks[0].contents)?;
+        // Only FAT32 has an FSInfo block.
+        let entry_len = if bpb.fs_info_block().is_some() { 4 } else { 2 };
+        let end = FIRST_CLUSTER + bpb.total_clusters();
+        (
+            entry_len,
+            lba_start + u32::from(bpb.reserved_block_count()),
+            (end as usize * entry_len).div_ceil(Block::LEN),
+            end,
+            u64::from(bpb.blocks_per_cluster()) * Block::LEN as u64,
+        )
+    };
+
+    let mut free: u64 = 0;
+    let mut cluster: u32 = 0;
+    let mut read = 0;
+    while read < fat_blocks {
+        let count = (fat_blocks - read).min(FAT_BLOCKS_PER_READ);
+        card.read(&mut blocks[..count], BlockIdx(fat_start + read as u32))
+            .map_err(|_| "Read FAT failed")?;
+        for block in &blocks[..count] {
+            for entry in block.chunks_exact(entry_len) {
+                let value = if entry_len == 4 {
+                    // The top four bits of a FAT32 entry are reserved.
+                    read_u32(entry, 0) & 0x0FFF_FFFF
+                } else {
+                    u32::from(u16::from_le_bytes([entry[0], entry[1]]))
+                };
+                if (FIRST_CLUSTER..end).contains(&cluster) && value == 0 {
+                    free += 1;
+                }
+                cluster += 1;
+            }
+        }
+        read += count;
+    }
+    Ok(free * cluster_bytes)
+}
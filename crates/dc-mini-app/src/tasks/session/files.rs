@@ -0,0 +1,148 @@
+#[cfg(feature = "raw-log")]
+use super::raw_log::RawLog;
+use super::tasks::RealTimeSource;
+use super::STORAGE_LOW_THRESHOLD;
+use crate::prelude::*;
+use dc_mini_icd::StorageStatus;
+#[cfg(not(feature = "raw-log"))]
+use embedded_sdmmc::Mode;
+use embedded_sdmmc::{VolumeIdx, VolumeManager};
+
+/// Checks that the raw log opens. The log is append-only, so no scratch
+/// data is written.
+#[cfg(feature = "raw-log")]
+pub fn write_test(sd: &mut SdCardResources) -> Result<(), &'static str> {
+    RawLog::open(sd.get_card()).map(|_| ()).map_err(|_| "Open raw log failed")
+}
+
+#[cfg(not(feature = "raw-log"))]
+const SELF_TEST_FILE: &str = "SELFTEST.TMP";
+
+/// Writes a scratch file, reads it back and deletes it.
+#[cfg(not(feature = "raw-log"))]
+pub fn write_test(sd: &mut SdCardResources) -> Result<(), &'static str> {
+    let volume_mgr = VolumeManager::new(sd.get_card(), RealTimeSource);
+    let volume = volume_mgr
+        .open_volume(VolumeIdx(0))
+        .map_err(|_| "Open volume failed")?;
+    let root_dir = volume.open_root_dir().map_err(|_| "Open root failed")?;
+
+    let mut pattern = [0u8; 512];
+    for (i, b) in pattern.iter_mut().enumerate() {
+        *b = i as u8;
+    }
+
+    {
+        let file = root_dir
+            .open_file_in_dir(SELF_TEST_FILE, Mode::ReadWriteCreateOrTruncate)
+            .map_err(|_| "Create failed")?;
+        file.write(&pattern).map_err(|_| "Write failed")?;
+        file.flush().map_err(|_| "Flush failed")?;
+    }
+
+    let mut readback = [0u8; 512];
+    let read = {
+        let file = root_dir
+            .open_file_in_dir(SELF_TEST_FILE, Mode::ReadOnly)
+            .map_err(|_| "Reopen failed")?;
+        file.read(&mut readback).map_err(|_| "Read failed")?
+    };
+    root_dir
+        .delete_file_in_dir(SELF_TEST_FILE)
+        .map_err(|_| "Delete failed")?;
+
+    if read != pattern.len() || readback != pattern {
+        return Err("Read-back mismatch");
+    }
+    Ok(())
+}
+
+/// Capacity and free space of the raw session log.
+#[cfg(feature = "raw-log")]
+pub fn storage_status(
+    sd: &mut SdCardResources,
+) -> Result<StorageStatus, &'static str> {
+    let log =
+        RawLog::open(sd.get_card()).map_err(|_| "Open raw log failed")?;
+    let free_bytes = log.free_bytes();
+    Ok(StorageStatus {
+        total_bytes: log.total_bytes(),
+        free_bytes,
+        low: free_bytes < STORAGE_LOW_THRESHOLD,
+    })
+}
+
+/// Card capacity and the free space in its filesystem.
+#[cfg(not(feature = "raw-log"))]
+pub fn storage_status(
+    sd: &mut SdCardResources,
+) -> Result<StorageStatus, &'static str> {
+    let card = sd.get_card();
+    let total_bytes = card.num_bytes().map_err(|_| "Card not responding")?;
+    let free_bytes = super::fat::free_bytes(&card)?;
+    Ok(StorageStatus {
+        total_bytes,
+        free_bytes,
+        low: free_bytes < STORAGE_LOW_THRESHOLD,
+    })
+}
+
+/// Deletes `name` from the SD card root directory.
+pub fn delete_file(
+    sd: &mut SdCardResources,
+    name: &str,
+) -> Result<(), &'static str> {
+    let volume_mgr = VolumeManager::new(sd.get_card(), RealTimeSource);
+    let volume = volume_mgr
+        .open_volume(VolumeIdx(0))
+        .map_err(|_| "Open volume failed")?;
+    let root_dir = volume.open_root_dir().map_err(|_| "Open root failed")?;
+    root_dir.delete_file_in_dir(name).map_err(|_| "Delete failed")
+}
+
+/// Drops every session in the raw log. Returns the number of sessions
+/// dropped.
+#[cfg(feature = "raw-log")]
+pub fn clear_card(sd: &mut SdCardResources) -> Result<u32, &'static str> {
+    let mut log =
+        RawLog::open(sd.get_card()).map_err(|_| "Open raw log failed")?;
+    let sessions = log.session_count();
+    log.format().map_err(|_| "Format failed")?;
+    Ok(sessions)
+}
+
+/// Deletes every file in the SD card root directory, where the device
+/// writes its recordings. The filesystem and any directories are kept.
+/// Returns the number of files deleted.
+#[cfg(not(feature = "raw-log"))]
+pub fn clear_card(sd: &mut SdCardResources) -> Result<u32, &'static str> {
+    let volume_mgr = VolumeManager::new(sd.get_card(), RealTimeSource);
+    let volume = volume_mgr
+        .open_volume(VolumeIdx(0))
+        .map_err(|_| "Open volume failed")?;
+    let root_dir = volume.open_root_dir().map_err(|_| "Open root failed")?;
+
+    let mut deleted = 0;
+    loop {
+        // Directory entries cannot be removed while iterating, so collect
+        // names in batches.
+        let mut names: heapless::Vec<embedded_sdmmc::ShortFileName, 32> =
+            heapless::Vec::new();
+        root_dir
+            .iterate_dir(|entry| {
+                if !entry.attributes.is_directory()
+                    && !entry.attributes.is_volume()
+                {
+                    let _ = names.push(entry.name.clone());
+                }
+            })
+            .map_err(|_| "List failed")?;
+        if names.is_empty() {
+            return Ok(deleted);
+        }
+        for name in names.iter() {
+            root_dir.delete_file_in_dir(name).map_err(|_| "Delete failed")?;
+            deleted += 1;
+        }
+    }
+}
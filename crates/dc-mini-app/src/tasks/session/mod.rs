@@ -1,15 +1,66 @@
+mod audio;
+mod container;
 pub(crate) mod events;
+#[cfg(not(feature = "raw-log"))]
+mod fat;
+pub(crate) mod files;
+#[cfg(feature = "raw-log")]
+mod raw_log;
+mod reader;
 mod tasks;
 
 pub use events::*;
+pub use files::*;
+pub use reader::{
+    close_transfer, file_reader_task, open_transfer, read_transfer,
+    TransferOwner,
+};
+pub use tasks::sd_detect_task;
 use tasks::*;
 
 use crate::prelude::*;
+use dc_mini_icd::{MarkerRecord, StorageStatus};
+use embassy_sync::channel::Channel;
 use embassy_sync::signal::Signal;
-use portable_atomic::AtomicBool;
+use embassy_sync::watch::Watch;
+use portable_atomic::{AtomicBool, Ordering};
 
 pub(self) static SESSION_ACTIVE: AtomicBool = AtomicBool::new(false);
 pub(self) static SESSION_SIG: Signal<CriticalSectionRawMutex, ()> =
     Signal::new();
 
+pub(self) static MARKER_CH: Channel<CriticalSectionRawMutex, MarkerRecord, 8> =
+    Channel::new();
+
+#[cfg(not(feature = "raw-log"))]
 pub(self) const MAX_FILENAME_LEN: usize = 12; // For possible date in name
+
+/// Free space below which the SD card is reported as low.
+pub const STORAGE_LOW_THRESHOLD: u64 = 64 * 1024 * 1024;
+/// Free space below which a recording is stopped, keeping room to finish
+/// the file.
+pub const STORAGE_FULL_THRESHOLD: u64 = 4 * 1024 * 1024;
+
+/// Latest known SD card status, updated on queries and when a recording
+/// pushes free space below [`STORAGE_LOW_THRESHOLD`].
+pub static STORAGE_STATUS_WATCH: Watch<
+    CriticalSectionRawMutex,
+    StorageStatus,
+    2,
+> = Watch::new();
+
+/// Whether a card is in the SD socket, kept by [`sd_detect_task`].
+pub static SD_PRESENT_WATCH: Watch<CriticalSectionRawMutex, bool, 2> =
+    Watch::new();
+
+/// Whether a recording is in progress.
+pub fn session_active() -> bool {
+    SESSION_ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Queues an event marker for the active session file. Returns `false` if
+/// no session is recording or the queue is full.
+pub fn record_marker(marker: &MarkerRecord) -> bool {
+    SESSION_ACTIVE.load(Ordering::SeqCst)
+        && MARKER_CH.try_send(marker.clone()).is_ok()
+}
@@ -0,0 +1,357 @@
+//! Session storage on the raw block log described in
+//! [`dc_mini_icd::rawlog`].
+//!
+//! The log takes over the whole card, so a card used with this feature is
+//! not readable as a FAT volume; sessions are extracted on the host.
+//!
+//! A session owns every block from the end of the log to the end of the
+//! card, so its blocks are contiguous and written without any filesystem
+//! bookkeeping. Sealed blocks are collected into bursts of
+//! [`BURST_BLOCKS`] and each burst goes to the card as one multi-block
+//! write. Two bursts are used in turn: [`RawLogSession`] fills one while
+//! [`RawLogWriter`] writes the other.
+
+use crate::prelude::*;
+use dc_mini_icd::rawlog::{
+    BlockHeader, Superblock, BLOCK_HEADER_SIZE, BLOCK_PAYLOAD,
+    FIRST_DATA_BLOCK, LOG_VERSION,
+};
+use embassy_sync::channel::Channel;
+use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx};
+use portable_atomic::{AtomicBool, Ordering};
+
+/// Blocks sent to the card in one multi-block write.
+pub const BURST_BLOCKS: usize = 8;
+
+pub type Burst = [Block; BURST_BLOCKS];
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RawLogError<E> {
+    Device(E),
+    /// No free blocks are left on the card.
+    Full,
+    /// The session writer stopped after a device error.
+    Aborted,
+}
+
+pub struct RawLog<D: BlockDevice> {
+    device: D,
+    generation: u32,
+    num_blocks: u32,
+    /// Next block to write.
+    end: u32,
+    /// Session number of the last block in the log, if any.
+    last_session: Option<u32>,
+}
+
+impl<D: BlockDevice> RawLog<D> {
+    /// Opens the log on `device`, formatting the card if it holds no log.
+    pub fn open(device: D) -> Result<Self, RawLogError<D::Error>> {
+        let BlockCount(num_blocks) =
+            device.num_blocks().map_err(RawLogError::Device)?;
+        let mut block = [Block::new()];
+        device.read(&mut block, BlockIdx(0)).map_err(RawLogError::Device)?;
+        let generation = match Superblock::from_bytes(&block[0].contents) {
+            Some(sb) => sb.generation,
+            None => {
+                warn!("No raw log found, formatting SD card");
+                write_superblock(&device, 1).map_err(RawLogError::Device)?;
+                1
+            }
+        };
+
+        let mut log = Self {
+            device,
+            generation,
+            num_blocks,
+            end: FIRST_DATA_BLOCK,
+            last_session: None,
+        };
+        log.end = log.find_end()?;
+        if log.end > FIRST_DATA_BLOCK {
+            log.last_session =
+                log.read_header(log.end - 1)?.map(|header| header.session);
+        }
+        info!(
+            "Raw log generation {}, {} blocks used",
+            generation,
+            log.end - FIRST_DATA_BLOCK
+        );
+        Ok(log)
+    }
+
+    /// Drops every session by starting a new generation.
+    pub fn format(&mut self) -> Result<(), RawLogError<D::Error>> {
+        let generation = self.generation.wrapping_add(1);
+        write_superblock(&self.device, generation)
+            .map_err(RawLogError::Device)?;
+        self.generation = generation;
+        self.end = FIRST_DATA_BLOCK;
+        self.last_session = None;
+        Ok(())
+    }
+
+    /// Number of sessions in the log.
+    pub fn session_count(&self) -> u32 {
+        self.last_session.map_or(0, |s| s.wrapping_add(1))
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.num_blocks.saturating_sub(FIRST_DATA_BLOCK) as u64
+            * BLOCK_PAYLOAD as u64
+    }
+
+    pub fn free_bytes(&self) -> u64 {
+        self.num_blocks.saturating_sub(self.end) as u64 * BLOCK_PAYLOAD as u64
+    }
+
+    /// Starts a new session at the end of the log. The session fills
+    /// bursts from `queue` and the returned writer puts them on the card;
+    /// both have to be polled until the session is closed.
+    pub fn start_session<'q, 'b>(
+        &mut self,
+        queue: &'q BurstQueue<'b>,
+    ) -> (RawLogSession<'q, 'b>, RawLogWriter<'_, 'q, 'b, D>) {
+        let session = self.session_count();
+        let filler = RawLogSession {
+            queue,
+            burst: None,
+            generation: self.generation,
+            session,
+            next: self.end,
+            limit: self.num_blocks,
+            count: 0,
+            len: 0,
+        };
+        (filler, RawLogWriter { log: self, queue, session })
+    }
+
+    fn read_header(
+        &self,
+        idx: u32,
+    ) -> Result<Option<BlockHeader>, RawLogError<D::Error>> {
+        let mut block = [Block::new()];
+        self.device
+            .read(&mut block, BlockIdx(idx))
+            .map_err(RawLogError::Device)?;
+        Ok(BlockHeader::verify(&block[0].contents, self.generation))
+    }
+
+    /// Blocks are only ever appended, so the valid blocks of the current
+    /// generation form a prefix and the end can be found by bisection.
+    fn find_end(&self) -> Result<u32, RawLogError<D::Error>> {
+        let (mut lo, mut hi) = (FIRST_DATA_BLOCK, self.num_blocks);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.read_header(mid)?.is_some() {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        Ok(lo)
+    }
+
+    /// Writes sealed `blocks` starting at `start`, which must be the end
+    /// of the log, in a single multi-block write.
+    fn write_burst(
+        &mut self,
+        blocks: &[Block],
+        start: u32,
+        session: u32,
+    ) -> Result<(), RawLogError<D::Error>> {
+        self.device
+            .write(blocks, BlockIdx(start))
+            .map_err(RawLogError::Device)?;
+        self.end = start + blocks.len() as u32;
+        self.last_session = Some(session);
+        Ok(())
+    }
+}
+
+fn write_superblock<D: BlockDevice>(
+    device: &D,
+    generation: u32,
+) -> Result<(), D::Error> {
+    let mut block = Block::new();
+    let sb = Superblock { version: LOG_VERSION, generation };
+    block.contents[..Superblock::SIZE].copy_from_slice(&sb.to_bytes());
+    device.write(core::slice::from_ref(&block), BlockIdx(0))
+}
+
+/// The two bursts a session alternates between.
+pub struct BurstBuffers {
+    bursts: [Burst; 2],
+}
+
+impl BurstBuffers {
+    pub fn new() -> Self {
+        Self {
+            bursts: core::array::from_fn(|_| {
+                core::array::from_fn(|_| Block::new())
+            }),
+        }
+    }
+}
+
+/// A filled burst waiting to be written.
+struct Pending<'b> {
+    blocks: &'b mut Burst,
+    /// Block index of `blocks[0]`.
+    start: u32,
+    /// Sealed blocks in `blocks`.
+    count: usize,
+}
+
+/// Hands bursts between a [`RawLogSession`] and its [`RawLogWriter`].
+pub struct BurstQueue<'b> {
+    /// Filled bursts in log order; `None` closes the session.
+    full: Channel<CriticalSectionRawMutex, Option<Pending<'b>>, 2>,
+    free: Channel<CriticalSectionRawMutex, &'b mut Burst, 2>,
+    failed: AtomicBool,
+}
+
+impl<'b> BurstQueue<'b> {
+    pub fn new(buffers: &'b mut BurstBuffers) -> Self {
+        let queue = Self {
+            full: Channel::new(),
+            free: Channel::new(),
+            failed: AtomicBool::new(false),
+        };
+        for burst in buffers.bursts.iter_mut() {
+            // The channel holds both bursts.
+            let _ = queue.free.try_send(burst);
+        }
+        queue
+    }
+}
+
+/// Splits one session's container bytes into sealed blocks.
+pub struct RawLogSession<'q, 'b> {
+    queue: &'q BurstQueue<'b>,
+    /// Burst being filled, taken from the queue on the first write.
+    burst: Option<&'b mut Burst>,
+    generation: u32,
+    session: u32,
+    /// Block index of the first block in `burst`.
+    next: u32,
+    /// End of the session's extent.
+    limit: u32,
+    /// Sealed blocks in `burst`.
+    count: usize,
+    /// Payload bytes in the block being filled.
+    len: usize,
+}
+
+impl RawLogSession<'_, '_> {
+    /// Flushes the session and tells the writer to stop once everything
+    /// before it is on the card.
+    pub async fn close(mut self) -> Result<(), RawLogError<()>> {
+        let result = self.flush().await;
+        self.queue.full.send(None).await;
+        result
+    }
+
+    async fn burst(&mut self) -> Result<&mut Burst, RawLogError<()>> {
+        if self.queue.failed.load(Ordering::SeqCst) {
+            return Err(RawLogError::Aborted);
+        }
+        if self.burst.is_none() {
+            self.burst = Some(self.queue.free.receive().await);
+        }
+        Ok(self.burst.as_deref_mut().unwrap())
+    }
+
+    /// Seals the block being filled and submits the burst once it is full.
+    async fn seal(&mut self) -> Result<(), RawLogError<()>> {
+        if self.next + self.count as u32 >= self.limit {
+            return Err(RawLogError::Full);
+        }
+        let header = BlockHeader {
+            generation: self.generation,
+            session: self.session,
+            len: self.len as u16,
+        };
+        let count = self.count;
+        header.seal(&mut self.burst().await?[count].contents);
+        self.count += 1;
+        self.len = 0;
+        if self.count == BURST_BLOCKS {
+            self.submit().await;
+        }
+        Ok(())
+    }
+
+    async fn submit(&mut self) {
+        let Some(blocks) = self.burst.take() else {
+            return;
+        };
+        let pending = Pending { blocks, start: self.next, count: self.count };
+        self.queue.full.send(Some(pending)).await;
+        self.next += self.count as u32;
+        self.count = 0;
+    }
+}
+
+impl SessionSink for RawLogSession<'_, '_> {
+    /// A device error is reported by the writer, so this side only sees
+    /// [`RawLogError::Aborted`].
+    type Error = RawLogError<()>;
+
+    async fn write(&mut self, mut bytes: &[u8]) -> Result<(), Self::Error> {
+        while !bytes.is_empty() {
+            let n = bytes.len().min(BLOCK_PAYLOAD - self.len);
+            let (count, start) = (self.count, BLOCK_HEADER_SIZE + self.len);
+            self.burst().await?[count].contents[start..start + n]
+                .copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+            if self.len == BLOCK_PAYLOAD {
+                self.seal().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Seals a partially filled block and submits the burst; later bytes
+    /// start a new block.
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        if self.len > 0 {
+            self.seal().await?;
+        }
+        if self.count > 0 {
+            self.submit().await;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the bursts of a [`RawLogSession`] to the card in order.
+pub struct RawLogWriter<'l, 'q, 'b, D: BlockDevice> {
+    log: &'l mut RawLog<D>,
+    queue: &'q BurstQueue<'b>,
+    session: u32,
+}
+
+impl<D: BlockDevice> RawLogWriter<'_, '_, '_, D> {
+    /// Runs until the session is closed. After a device error the
+    /// remaining bursts are dropped so the session side never stalls.
+    pub async fn run(mut self) -> Result<(), RawLogError<D::Error>> {
+        let mut result = Ok(());
+        while let Some(pending) = self.queue.full.receive().await {
+            if result.is_ok() {
+                result = self.log.write_burst(
+                    &pending.blocks[..pending.count],
+                    pending.start,
+                    self.session,
+                );
+                if result.is_err() {
+                    self.queue.failed.store(true, Ordering::SeqCst);
+                }
+            }
+            self.queue.free.send(pending.blocks).await;
+        }
+        result
+    }
+}
@@ -0,0 +1,227 @@
+//! File downloads from the SD card.
+//!
+//! Mounting the card and walking the cluster chain again for every chunk
+//! makes a download quadratic in the file size, so [`file_reader_task`]
+//! keeps one file open between requests. USB and BLE share it: one
+//! transfer at a time, owned by the link that opened it. An open file holds
+//! the card, so it is closed after [`IDLE_TIMEOUT`] without requests and as
+//! soon as a recording starts.
+
+use super::session_active;
+use super::tasks::RealTimeSource;
+use crate::prelude::*;
+use dc_mini_icd::{FS_CHUNK_SIZE, MAX_FS_NAME_LEN};
+use embassy_futures::select::{select, Either};
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::Instant;
+use embedded_sdmmc::{Mode, VolumeIdx, VolumeManager};
+use heapless::{String, Vec};
+
+/// Time without requests after which an open file is closed.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Interval at which an open transfer checks for a recording starting.
+const SESSION_POLL: Duration = Duration::from_secs(1);
+
+/// Link that opened a transfer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TransferOwner {
+    Usb,
+    /// A BLE central, by connection handle.
+    Ble(u16),
+}
+
+pub type ChunkData = Vec<u8, FS_CHUNK_SIZE>;
+
+enum Request {
+    Open { owner: TransferOwner, name: String<MAX_FS_NAME_LEN> },
+    Read { owner: TransferOwner, offset: u32, length: usize },
+    Close { owner: TransferOwner },
+}
+
+enum Response {
+    Opened(Result<u32, &'static str>),
+    Read(Result<(ChunkData, bool), &'static str>),
+    Closed(Result<u32, &'static str>),
+}
+
+static REQUESTS: Channel<CriticalSectionRawMutex, Request, 1> = Channel::new();
+static RESPONSES: Signal<CriticalSectionRawMutex, Response> = Signal::new();
+/// Held for a whole request and its response.
+static CLIENT: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+
+async fn request(request: Request) -> Response {
+    let _client = CLIENT.lock().await;
+    // Drop a response left behind by a caller that gave up waiting.
+    RESPONSES.reset();
+    REQUESTS.send(request).await;
+    RESPONSES.wait().await
+}
+
+/// Opens `name` in the SD card root directory for `owner`, replacing any
+/// transfer `owner` already has open. Returns the file size.
+pub async fn open_transfer(
+    owner: TransferOwner,
+    name: String<MAX_FS_NAME_LEN>,
+) -> Result<u32, &'static str> {
+    match request(Request::Open { owner, name }).await {
+        Response::Opened(result) => result,
+        _ => Err("Unexpected response"),
+    }
+}
+
+/// Reads up to `length` bytes (at most [`FS_CHUNK_SIZE`]) of the file
+/// `owner` opened, starting at `offset`. Also returns whether the end of
+/// the file was reached.
+pub async fn read_transfer(
+    owner: TransferOwner,
+    offset: u32,
+    length: usize,
+) -> Result<(ChunkData, bool), &'static str> {
+    let length = length.min(FS_CHUNK_SIZE);
+    match request(Request::Read { owner, offset, length }).await {
+        Response::Read(result) => result,
+        _ => Err("Unexpected response"),
+    }
+}
+
+/// Closes the file `owner` opened. Returns its size.
+pub async fn close_transfer(
+    owner: TransferOwner,
+) -> Result<u32, &'static str> {
+    match request(Request::Close { owner }).await {
+        Response::Closed(result) => result,
+        _ => Err("Unexpected response"),
+    }
+}
+
+/// Serves [`open_transfer`], [`read_transfer`] and [`close_transfer`].
+#[embassy_executor::task]
+pub async fn file_reader_task(
+    sd: &'static Mutex<CriticalSectionRawMutex, SdCardResources>,
+) {
+    loop {
+        match REQUESTS.receive().await {
+            Request::Open { owner, name } => {
+                if session_active() {
+                    RESPONSES
+                        .signal(Response::Opened(Err("Recording active")));
+                    continue;
+                }
+                let mut sd_resources = sd.lock().await;
+                if let Err(msg) = serve(&mut sd_resources, owner, name).await {
+                    RESPONSES.signal(Response::Opened(Err(msg)));
+                }
+            }
+            Request::Read { .. } => {
+                RESPONSES.signal(Response::Read(Err("No file open")))
+            }
+            Request::Close { .. } => {
+                RESPONSES.signal(Response::Closed(Err("No file open")))
+            }
+        }
+    }
+}
+
+/// Opens `name` and answers requests until the transfer ends. Errors are
+/// returned for the open request being answered.
+async fn serve(
+    sd: &mut SdCardResources,
+    owner: TransferOwner,
+    mut name: String<MAX_FS_NAME_LEN>,
+) -> Result<(), &'static str> {
+    let volume_mgr = VolumeManager::new(sd.get_card(), RealTimeSource);
+    let volume = volume_mgr
+        .open_volume(VolumeIdx(0))
+        .map_err(|_| "Open volume failed")?;
+    let root_dir = volume.open_root_dir().map_err(|_| "Open root failed")?;
+
+    'files: loop {
+        let file = root_dir
+            .open_file_in_dir(name.as_str(), Mode::ReadOnly)
+            .map_err(|_| "File not found")?;
+        let size = file.length();
+        let mut position = 0;
+        info!(
+            "[file-reader] {:?} reading {} ({}B)",
+            owner,
+            name.as_str(),
+            size
+        );
+        RESPONSES.signal(Response::Opened(Ok(size)));
+
+        let mut last_request = Instant::now();
+        loop {
+            let request =
+                match select(REQUESTS.receive(), Timer::after(SESSION_POLL))
+                    .await
+                {
+                    Either::First(request) => request,
+                    Either::Second(()) => {
+                        if session_active() {
+                            info!("[file-reader] Recording started, closing");
+                            return Ok(());
+                        }
+                        if last_request.elapsed() >= IDLE_TIMEOUT {
+                            info!("[file-reader] Idle, closing");
+                            return Ok(());
+                        }
+                        continue;
+                    }
+                };
+            last_request = Instant::now();
+
+            match request {
+                Request::Open { owner: from, name: next } if from == owner => {
+                    name = next;
+                    continue 'files;
+                }
+                Request::Open { .. } => RESPONSES
+                    .signal(Response::Opened(Err("Transfer in progress"))),
+                Request::Read { owner: from, offset, length }
+                    if from == owner =>
+                {
+                    let mut data = ChunkData::new();
+                    unwrap!(data.resize_default(length));
+                    let result = (|| {
+                        if offset > size {
+                            return Err("Offset past end of file");
+                        }
+                        // Sequential reads carry on from the cluster the
+                        // last one ended in.
+                        if offset != position {
+                            file.seek_from_start(offset)
+                                .map_err(|_| "Seek failed")?;
+                        }
+                        let mut read = 0;
+                        while read < data.len() && !file.is_eof() {
+                            read += file
+                                .read(&mut data[read..])
+                                .map_err(|_| "Read failed")?;
+                        }
+                        position = offset + read as u32;
+                        data.truncate(read);
+                        Ok(file.is_eof())
+                    })();
+                    if result.is_err() {
+                        // Resync on the next read.
+                        position = u32::MAX;
+                    }
+                    RESPONSES
+                        .signal(Response::Read(result.map(|eof| (data, eof))));
+                }
+                Request::Read { .. } => {
+                    RESPONSES.signal(Response::Read(Err("No file open")))
+                }
+                Request::Close { owner: from } if from == owner => {
+                    RESPONSES.signal(Response::Closed(Ok(size)));
+                    return Ok(());
+                }
+                Request::Close { .. } => {
+                    RESPONSES.signal(Response::Closed(Err("No file open")))
+                }
+            }
+        }
+    }
+}
@@ -1,18 +1,34 @@
+use super::audio::MicRecorder;
+use super::container::ContainerWriter;
+#[cfg(feature = "raw-log")]
+use super::raw_log::{BurstBuffers, BurstQueue, RawLog};
 use super::*;
-use crate::clock::CLOCK_SET;
+use crate::clock::now_micros;
+use crate::event_log;
 use crate::prelude::*;
-use crate::tasks::ads::ADS_MEAS_CH;
-use crate::tasks::ads::ADS_WATCH;
-use core::fmt::Write;
+use crate::tasks::ads::{
+    next_ads_sample, ADS_MEAS_CH, ADS_WATCH, LEAD_OFF_WATCH,
+};
+use crate::tasks::apds::APDS_DATA_WATCH;
+use crate::tasks::imu::IMU_DATA_WATCH;
+use crate::tasks::mic::{vad, MIC_STREAM_CH};
 // use ads1299::AdsData;
-use dc_mini_bsp::SdCardResources;
+use dc_mini_bsp::{CardDetect, SdCardResources};
+use dc_mini_icd::container::RecordKind;
 // use dc_mini_icd::AdsConfig;
-use embassy_futures::select::{select3, Either3};
-use embassy_time::Instant;
-use embedded_sdmmc::{Mode, TimeSource, Timestamp, VolumeIdx, VolumeManager};
-use heapless::String;
+#[cfg(feature = "raw-log")]
+use embassy_futures::join::join;
+use embassy_futures::select::{
+    select, select3, select4, Either, Either3, Either4,
+};
+use embassy_time::{Instant, Ticker};
+use embedded_sdmmc::{TimeSource, Timestamp};
 use portable_atomic::Ordering;
-use prost::Message;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest a recording may go without a heartbeat; generous, since SD
+/// cards can pause for a long time mid-write.
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct RealTimeSource;
 
@@ -33,33 +49,226 @@ impl TimeSource for RealTimeSource {
     }
 }
 
+/// Why [`record`] returned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum SessionEnd {
+    /// Stopped on request.
+    Stopped,
+    /// Free space fell below [`STORAGE_FULL_THRESHOLD`].
+    SdFull,
+    /// The card could not be opened or written.
+    WriteFailed,
+    /// The card was missing or pulled out mid-recording.
+    CardRemoved,
+}
+
+/// Accounts for `written` bytes and publishes a warning once free space
+/// drops below [`STORAGE_LOW_THRESHOLD`]. Returns whether the card is full
+/// enough that the recording must stop.
+fn track_free_space(
+    storage: &mut Option<StorageStatus>,
+    written: usize,
+) -> bool {
+    let Some(status) = storage.as_mut() else {
+        return false;
+    };
+    status.free_bytes = status.free_bytes.saturating_sub(written as u64);
+    if !status.low && status.free_bytes < STORAGE_LOW_THRESHOLD {
+        status.low = true;
+        warn!("SD card space low: {} bytes free", status.free_bytes);
+        STORAGE_STATUS_WATCH.sender().send(status.clone());
+    }
+    status.free_bytes < STORAGE_FULL_THRESHOLD
+}
+
+/// Blink interval of the LED alert for a recording that stopped on its
+/// own.
+const ALERT_FLASH_INTERVAL: Duration = Duration::from_millis(200);
+const ALERT_FLASHES: u32 = 10;
+
+/// Tells the host, the orchestrator and the wearer that the recording
+/// stopped without being asked to.
+async fn report_early_end(
+    end: SessionEnd,
+    storage: &Option<StorageStatus>,
+    event_sender: EventSender,
+) {
+    let kind = match end {
+        SessionEnd::SdFull => FaultKind::SdFull,
+        SessionEnd::CardRemoved => FaultKind::SdRemoved,
+        _ => FaultKind::SdWriteFailed,
+    };
+    faults::report(kind, Some(MonitoredTask::Session));
+    if let (SessionEnd::SdFull, Some(status)) = (end, storage) {
+        STORAGE_STATUS_WATCH.sender().send(status.clone());
+    }
+    NEOPIX_CHAN
+        .send(NeopixEvent::FlashFor(
+            smart_leds::colors::RED,
+            ALERT_FLASH_INTERVAL,
+            ALERT_FLASHES,
+            None,
+        ))
+        .await;
+    event_sender.send(SessionEvent::RecordingAborted.into()).await;
+}
+
 #[embassy_executor::task]
 pub async fn recording_task(
     sd: &'static Mutex<CriticalSectionRawMutex, SdCardResources>,
+    event_sender: EventSender,
     id: Option<SessionId>,
+    metadata: Option<SessionMetadata>,
+    mic: Option<MicConfig>,
 ) {
     SESSION_ACTIVE.store(true, Ordering::SeqCst);
+    event_log::record(SystemEventKind::SessionStarted);
 
     let mut sd_resources = sd.lock().await;
+    if !sd_resources.card_present() {
+        warn!("No SD card, not recording");
+        drop(sd_resources);
+        SESSION_ACTIVE.store(false, Ordering::SeqCst);
+        event_log::record(SystemEventKind::SessionStopped);
+        report_early_end(SessionEnd::CardRemoved, &None, event_sender).await;
+        return;
+    }
 
-    let sd_card = sd_resources.get_card();
+    // Free space is tracked from the bytes written so the card does not have
+    // to be rescanned while recording.
+    let mut storage = storage_status(&mut sd_resources).ok();
+    if let Some(status) = storage.as_ref().filter(|s| s.low) {
+        warn!("SD card space low: {} bytes free", status.free_bytes);
+        STORAGE_STATUS_WATCH.sender().send(status.clone());
+    }
 
-    // Initialize SD card
-    info!("SD card initialized, size: {} bytes", sd_card.num_bytes().unwrap());
+    let end = if storage
+        .as_ref()
+        .is_some_and(|s| s.free_bytes < STORAGE_FULL_THRESHOLD)
+    {
+        warn!("SD card full, not recording");
+        SessionEnd::SdFull
+    } else {
+        let (sd_card, mut detect) = sd_resources.get_card_with_detect();
+        info!(
+            "SD card initialized, size: {} bytes",
+            sd_card.num_bytes().unwrap_or(0)
+        );
+        record_to_card(sd_card, &mut detect, id, &mut storage, metadata, mic)
+            .await
+    };
 
-    // Create volume manager
-    let volume_mgr = VolumeManager::new(sd_card, RealTimeSource);
+    drop(sd_resources);
+    SESSION_ACTIVE.store(false, Ordering::SeqCst);
+    event_log::record(SystemEventKind::SessionStopped);
 
-    let mut ads_watcher =
-        ADS_WATCH.receiver().expect("Failed to get ADS watch receiver");
-    let mut ads_subscriber = ADS_MEAS_CH
-        .subscriber()
-        .expect("Failed to get ADS measurement subscriber");
+    if end != SessionEnd::Stopped {
+        report_early_end(end, &storage, event_sender).await;
+    }
+}
+
+/// Interval between card-detect checks.
+const CARD_DETECT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Tracks the SD card in [`SD_PRESENT_WATCH`] and mounts it on insertion
+/// to refresh [`STORAGE_STATUS_WATCH`]. A recording holds the card and
+/// watches for removal itself, so checks pause until it ends.
+#[embassy_executor::task]
+pub async fn sd_detect_task(
+    sd: &'static Mutex<CriticalSectionRawMutex, SdCardResources>,
+) {
+    let present_sender = SD_PRESENT_WATCH.sender();
+    loop {
+        {
+            let mut sd_resources = sd.lock().await;
+            let present = sd_resources.card_present();
+            if present_sender.try_get() != Some(present) {
+                if present {
+                    match storage_status(&mut sd_resources) {
+                        Ok(status) => {
+                            info!("SD card inserted: {:?}", status);
+                            STORAGE_STATUS_WATCH.sender().send(status);
+                        }
+                        Err(e) => {
+                            warn!("SD card inserted, mount failed: {}", e)
+                        }
+                    }
+                } else {
+                    info!("No SD card");
+                    STORAGE_STATUS_WATCH.sender().clear();
+                }
+                present_sender.send(present);
+            }
+        }
+        Timer::after(CARD_DETECT_INTERVAL).await;
+    }
+}
+
+/// Records the session to the raw log.
+#[cfg(feature = "raw-log")]
+async fn record_to_card<D: embedded_sdmmc::BlockDevice>(
+    sd_card: D,
+    detect: &mut CardDetect<'_>,
+    id: Option<SessionId>,
+    storage: &mut Option<StorageStatus>,
+    metadata: Option<SessionMetadata>,
+    mic: Option<MicConfig>,
+) -> SessionEnd {
+    // Sessions in the raw log are numbered rather than named.
+    let _ = id;
+    let Ok(mut log) = RawLog::open(sd_card) else {
+        error!("Failed to open raw log");
+        return SessionEnd::WriteFailed;
+    };
+    let mut buffers = BurstBuffers::new();
+    let queue = BurstQueue::new(&mut buffers);
+    let (mut session, writer) = log.start_session(&queue);
+    let (end, written) = join(
+        async {
+            let end =
+                record(&mut session, detect, storage, metadata, mic).await;
+            if session.close().await.is_err() && end != SessionEnd::CardRemoved
+            {
+                return SessionEnd::WriteFailed;
+            }
+            end
+        },
+        writer.run(),
+    )
+    .await;
+    if written.is_err() && end != SessionEnd::CardRemoved {
+        error!("Raw log write failed");
+        return SessionEnd::WriteFailed;
+    }
+    end
+}
+
+/// Records the session to the next free `.dcs` file in the root directory.
+#[cfg(not(feature = "raw-log"))]
+async fn record_to_card<D: embedded_sdmmc::BlockDevice>(
+    sd_card: D,
+    detect: &mut CardDetect<'_>,
+    id: Option<SessionId>,
+    storage: &mut Option<StorageStatus>,
+    metadata: Option<SessionMetadata>,
+    mic: Option<MicConfig>,
+) -> SessionEnd {
+    use crate::clock::CLOCK_SET;
+    use core::fmt::Write;
+    use dc_mini_icd::container::CONTAINER_EXT;
+    use embedded_sdmmc::{Mode, VolumeIdx, VolumeManager};
+    use heapless::String;
 
-    // Initialize recording
-    let volume =
-        volume_mgr.open_volume(VolumeIdx(0)).expect("Open volume failed.");
-    let root_dir = volume.open_root_dir().expect("Failed to open root dir.");
+    let volume_mgr = VolumeManager::new(sd_card, RealTimeSource);
+    let Ok(volume) = volume_mgr.open_volume(VolumeIdx(0)) else {
+        error!("Failed to open SD volume");
+        return SessionEnd::WriteFailed;
+    };
+    let Ok(root_dir) = volume.open_root_dir() else {
+        error!("Failed to open SD root directory");
+        return SessionEnd::WriteFailed;
+    };
 
     let mut filename: String<MAX_FILENAME_LEN> = String::new();
     if CLOCK_SET.load(Ordering::SeqCst) {
@@ -84,8 +293,9 @@ pub async fn recording_task(
             if let Some(recording_id) = &id {
                 filename.push_str("_").unwrap();
                 filename.push_str(recording_id.0.as_str()).unwrap();
-                filename.push_str(".dat").unwrap();
             }
+            filename.push_str(".").unwrap();
+            filename.push_str(CONTAINER_EXT).unwrap();
 
             // Check if file exists
             if root_dir.find_directory_entry(filename.as_str()).is_err() {
@@ -105,7 +315,8 @@ pub async fn recording_task(
                 filename.push_str(recording_id.0.as_str()).unwrap();
             }
 
-            filename.push_str(".dat").unwrap();
+            filename.push_str(".").unwrap();
+            filename.push_str(CONTAINER_EXT).unwrap();
 
             if root_dir.find_directory_entry(filename.as_str()).is_err() {
                 break;
@@ -113,56 +324,236 @@ pub async fn recording_task(
             file_num += 1;
         }
     }
-    let file = root_dir
+    let Ok(mut file) = root_dir
         .open_file_in_dir(filename.as_str(), Mode::ReadWriteCreateOrAppend)
-        .expect("Failed to open file.");
+    else {
+        error!("Failed to create session file");
+        return SessionEnd::WriteFailed;
+    };
+
+    record(&mut file, detect, storage, metadata, mic).await
+}
+
+/// Streams the session into `sink` until it is stopped, the card fills up,
+/// a write fails or the card is pulled, then finishes the file with what
+/// it could write.
+async fn record<S: SessionSink>(
+    sink: &mut S,
+    detect: &mut CardDetect<'_>,
+    storage: &mut Option<StorageStatus>,
+    metadata: Option<SessionMetadata>,
+    mic: Option<MicConfig>,
+) -> SessionEnd {
+    let mut ads_watcher =
+        ADS_WATCH.receiver().expect("Failed to get ADS watch receiver");
+    let mut ads_subscriber = ADS_MEAS_CH
+        .dyn_subscriber()
+        .expect("Failed to get ADS measurement subscriber");
+    let mut imu_receiver =
+        IMU_DATA_WATCH.receiver().expect("Failed to get IMU data receiver");
+    // Skip readings left over from before the session started.
+    let _ = imu_receiver.try_changed();
+    let mut apds_receiver =
+        APDS_DATA_WATCH.receiver().expect("Failed to get APDS data receiver");
+    let _ = apds_receiver.try_changed();
+    let mut lead_off_receiver =
+        LEAD_OFF_WATCH.receiver().expect("Failed to get lead-off receiver");
+
+    // Beats even while no data arrives, so only a wedged write stalls it.
+    let mut heartbeat_tick = Ticker::every(HEARTBEAT_INTERVAL);
+    let _watch = watch_heartbeat(MonitoredTask::Session, STALL_TIMEOUT);
+
+    let mut writer = ContainerWriter::new();
+
+    let mut metadata = metadata.unwrap_or_default();
+    if let Some(start) = crate::CLOCK.epoch_micros(Instant::now().as_micros())
+    {
+        metadata.start_epoch_us = Some(start as u64);
+    }
+    if !writer.push_postcard(RecordKind::Metadata, now_micros(), &metadata) {
+        warn!("Failed to serialize session metadata");
+    }
+
+    let (mut mic_subscriber, mut mic_recorder) = match mic.as_ref() {
+        Some(config) => {
+            (MIC_STREAM_CH.subscriber().ok(), Some(MicRecorder::new(config)))
+        }
+        None => (None, None),
+    };
 
     let batch_sz: usize = 100;
     let mut packet_counter = 0;
     let mut message = icd::proto::AdsDataFrame {
         packet_counter,
-        ts: Instant::now().as_micros(),
+        ts: now_micros(),
         samples: alloc::vec::Vec::with_capacity(batch_sz),
+        markers: alloc::vec::Vec::new(),
+        seq: packet_counter as u32,
+        imu: alloc::vec::Vec::new(),
+        codec: icd::proto::AdsCodec::Raw as i32,
+        decimation: 0,
     };
-    let mut out_buffer = alloc::vec::Vec::new();
+    MARKER_CH.clear();
 
+    let mut end = SessionEnd::Stopped;
     loop {
-        match select3(
-            ads_subscriber.next_message_pure(),
+        match select4(
+            next_ads_sample(&mut ads_subscriber, AdsConsumer::Session),
             ads_watcher.changed(),
-            SESSION_SIG.wait(),
+            select(SESSION_SIG.wait(), detect.wait_for_remove()),
+            select4(
+                select3(
+                    MARKER_CH.receive(),
+                    lead_off_receiver.changed(),
+                    heartbeat_tick.next(),
+                ),
+                imu_receiver.changed(),
+                apds_receiver.changed(),
+                async {
+                    match mic_subscriber.as_mut() {
+                        Some(sub) => sub.next_message_pure().await,
+                        None => core::future::pending().await,
+                    }
+                },
+            ),
         )
         .await
         {
-            Either3::First(data) => {
+            Either4::First(data) => {
+                heartbeat(MonitoredTask::Session);
+                // Frames are stamped with the capture of their first sample.
+                if message.samples.is_empty() {
+                    message.ts = data.ts;
+                }
                 let ads_sample = convert_to_proto(data);
 
                 message.samples.push(ads_sample);
                 if message.samples.len() >= batch_sz {
-                    out_buffer.clear();
-                    message.encode(&mut out_buffer).unwrap();
-                    let size = out_buffer.len() as u32;
-                    file.write(&size.to_le_bytes()).unwrap();
-                    file.write(out_buffer.as_slice()).unwrap();
+                    writer.push_proto(RecordKind::Ads, message.ts, &message);
                     message.samples.clear();
                     packet_counter += 1;
                     message.packet_counter = packet_counter;
-                    message.ts = Instant::now().as_micros();
+                    message.seq = packet_counter as u32;
                 }
             }
-            Either3::Second(streaming) => {
+            Either4::Second(streaming) => {
                 // If we have data in the buffer, we should probably write out here with
                 // corresponding timestamp so that and gap in data has proper timestamping.
                 if !streaming {
                     info!("While recording, ADS streaming has stopped!")
                 }
             }
-            Either3::Third(_) => {
+            Either4::Third(Either::First(())) => {
                 break;
             }
+            Either4::Third(Either::Second(())) => {
+                error!("SD card removed, stopping recording");
+                end = SessionEnd::CardRemoved;
+                break;
+            }
+            Either4::Fourth(Either4::First(Either3::First(marker))) => {
+                // Close the audio record at the end of a speech segment so
+                // that no record spans the gap before the next one.
+                if vad::is_silence_marker(&marker.label) {
+                    if let (Some(sub), Some(recorder)) =
+                        (mic_subscriber.as_mut(), mic_recorder.as_mut())
+                    {
+                        while let Some(pcm) = sub.try_next_message_pure() {
+                            recorder.push(pcm.ts, &pcm.data, &mut writer);
+                        }
+                        recorder.finish(&mut writer);
+                    }
+                }
+                writer.push_proto(
+                    RecordKind::Marker,
+                    marker.ts,
+                    &icd::proto::EventMarker {
+                        ts: marker.ts,
+                        host_epoch_us: marker.host_epoch_us,
+                        label: marker.label.as_str().into(),
+                    },
+                );
+            }
+            Either4::Fourth(Either4::First(Either3::Second(status))) => {
+                if !writer.push_postcard(
+                    RecordKind::LeadOff,
+                    status.ts,
+                    &status,
+                ) {
+                    warn!("Failed to serialize lead-off status");
+                }
+            }
+            Either4::Fourth(Either4::First(Either3::Third(()))) => {
+                heartbeat(MonitoredTask::Session);
+            }
+            Either4::Fourth(Either4::Second(imu)) => {
+                let ts = imu.ts;
+                writer.push_proto(
+                    RecordKind::Imu,
+                    ts,
+                    &icd::proto::ImuRecord {
+                        ts,
+                        accel_x: imu.accel_x,
+                        accel_y: imu.accel_y,
+                        accel_z: imu.accel_z,
+                        gyro_x: imu.gyro_x,
+                        gyro_y: imu.gyro_y,
+                        gyro_z: imu.gyro_z,
+                        temp: imu.temp,
+                    },
+                );
+            }
+            Either4::Fourth(Either4::Third(frame)) => {
+                if !writer.push_postcard(RecordKind::Apds, frame.ts, &frame) {
+                    warn!("Failed to serialize APDS frame");
+                }
+            }
+            Either4::Fourth(Either4::Fourth(pcm)) => {
+                if let Some(recorder) = mic_recorder.as_mut() {
+                    recorder.push(pcm.ts, &pcm.data, &mut writer);
+                }
+            }
+        }
+
+        if writer.is_full() {
+            match writer.drain(sink).await {
+                Ok(written) => {
+                    if track_free_space(storage, written) {
+                        warn!("SD card full, stopping recording");
+                        end = SessionEnd::SdFull;
+                        break;
+                    }
+                }
+                Err(_) => {
+                    error!("SD write failed, stopping recording");
+                    end = SessionEnd::WriteFailed;
+                    break;
+                }
+            }
         }
     }
-    // Probably need to also write any data that is still in the buffer out here.
-    file.flush().unwrap();
-    SESSION_ACTIVE.store(false, Ordering::SeqCst);
+
+    // Keep the tail of the session even though the last frame is partial.
+    // After a failed write it is dropped, but the file is still flushed so
+    // everything written before the failure stays readable. Without the
+    // card there is nothing left to write to.
+    if end == SessionEnd::CardRemoved {
+        return end;
+    }
+    if end != SessionEnd::WriteFailed {
+        if !message.samples.is_empty() {
+            writer.push_proto(RecordKind::Ads, message.ts, &message);
+        }
+        if let Some(recorder) = mic_recorder.as_mut() {
+            recorder.finish(&mut writer);
+        }
+        if writer.drain(sink).await.is_err() {
+            end = SessionEnd::WriteFailed;
+        }
+    }
+    if sink.flush().await.is_err() {
+        error!("SD flush failed, recording may be truncated");
+        end = SessionEnd::WriteFailed;
+    }
+    end
 }
@@ -1,21 +1,31 @@
+use crate::codec::ads_codec;
+use crate::decimation::AdsStreamDecimator;
 use crate::prelude::*;
+use crate::tasks::ads::next_ads_sample;
+use crate::tasks::ads::AdsMeasurement;
+use crate::tasks::ads::ADS_CAP;
 use crate::tasks::ads::ADS_MEAS_CH;
 use crate::tasks::ads::ADS_WATCH;
-use crate::tasks::imu::IMU_DATA_WATCH;
-use ads1299::AdsData;
-use dc_mini_icd::AdsConfig;
-use dc_mini_icd::{AdsDataFrame, AdsSample};
+use crate::tasks::ads::LEAD_OFF_WATCH;
+use crate::tasks::ads::{impedance_error, IMPEDANCE_SIG};
+use crate::tasks::imu::ads_imu_data;
+use dc_mini_icd::{AdsCodec, AdsDataFrame, AdsSample, ImpedanceReport};
+use dc_mini_icd::{AdsConfig, Montage};
 use embassy_futures::select::{select, Either};
 use embassy_sync::pubsub::DynSubscriber;
 use embassy_sync::signal::Signal;
 use embassy_sync::watch::DynReceiver;
-use embassy_time::{Duration, Instant};
+use embassy_time::{with_timeout, Duration, Instant, Ticker};
 use heapless::Vec;
 use postcard_rpc::{header::VarHeader, server::Sender};
 
 const BATCH_INTERVAL: Duration = Duration::from_millis(33); // ~30Hz
+const LEAD_OFF_INTERVAL: Duration = Duration::from_secs(1);
+/// Upper bound for an impedance check, including queued events.
+const IMPEDANCE_TIMEOUT: Duration = Duration::from_secs(10);
 
 static USB_STREAM: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static LEAD_OFF_STREAM: Signal<CriticalSectionRawMutex, ()> = Signal::new();
 
 #[embassy_executor::task]
 pub async fn ads_start_handler(
@@ -26,7 +36,9 @@ pub async fn ads_start_handler(
 ) {
     let config = {
         let mut ctx = context.app.lock().await;
-        ctx.event_sender.send(AdsEvent::StartStream.into()).await;
+        ctx.event_sender
+            .send(AdsEvent::StartStream(AdsClaim::Usb).into())
+            .await;
         ctx.profile_manager
             .get_ads_config()
             .await
@@ -50,10 +62,40 @@ pub async fn ads_stop_handler(
     _rqst: (),
 ) -> () {
     let ctx = context.app.lock().await;
-    let _res = ctx.event_sender.send(AdsEvent::StopStream.into()).await;
+    let _res = ctx
+        .event_sender
+        .send(AdsEvent::StopStream(AdsClaim::Usb).into())
+        .await;
     USB_STREAM.signal(());
 }
 
+/// Publishes the lead-off state at ~1 Hz while the ADS is measuring,
+/// independent of the raw data stream.
+#[embassy_executor::task]
+pub async fn lead_off_start_handler(
+    _context: SpawnCtx,
+    header: VarHeader,
+    _rqst: (),
+    sender: Sender<super::AppTx>,
+) {
+    if sender.reply::<LeadOffStartEndpoint>(header.seq_no, &()).await.is_err()
+    {
+        error!("Failed to reply, stopping lead-off stream");
+        return;
+    }
+
+    select(lead_off_stream_usb(sender), LEAD_OFF_STREAM.wait()).await;
+    LEAD_OFF_STREAM.reset();
+}
+
+pub async fn lead_off_stop_handler(
+    _context: &mut Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> () {
+    LEAD_OFF_STREAM.signal(());
+}
+
 pub async fn ads_get_config(
     context: &mut Context,
     _header: VarHeader,
@@ -77,6 +119,25 @@ pub async fn ads_set_config(
     true
 }
 
+pub async fn ads_get_montage(
+    context: &mut Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> Montage {
+    let mut ctx = context.app.lock().await;
+    ctx.profile_manager.get_montage().await.cloned().unwrap_or_default()
+}
+
+pub async fn ads_set_montage(
+    context: &mut Context,
+    _header: VarHeader,
+    rqst: Montage,
+) -> bool {
+    let mut ctx = context.app.lock().await;
+    ctx.save_montage(rqst).await;
+    true
+}
+
 pub async fn ads_reset_config(
     context: &mut Context,
     _header: VarHeader,
@@ -87,7 +148,27 @@ pub async fn ads_reset_config(
     true
 }
 
-fn convert_sample(samples: alloc::sync::Arc<Vec<AdsData, 2>>) -> AdsSample {
+/// Runs an electrode impedance check, pausing the stream if one is running.
+pub async fn ads_impedance_check(
+    context: &mut Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> ImpedanceReport {
+    IMPEDANCE_SIG.reset();
+    {
+        let ctx = context.app.lock().await;
+        ctx.event_sender.send(AdsEvent::ImpedanceCheck.into()).await;
+    }
+    match with_timeout(IMPEDANCE_TIMEOUT, IMPEDANCE_SIG.wait()).await {
+        Ok(report) => report,
+        Err(_) => {
+            warn!("Impedance check timed out");
+            impedance_error("Timed out")
+        }
+    }
+}
+
+fn convert_sample(samples: AdsMeasurement) -> AdsSample {
     // Calculate the total number of channels across all ADS devices
     let total_channels: usize =
         samples.iter().map(|sample| sample.data.len()).sum();
@@ -123,59 +204,78 @@ fn convert_sample(samples: alloc::sync::Arc<Vec<AdsData, 2>>) -> AdsSample {
         gpio_shift += 4; // Shift by 4 bits (1 nibble per GPIO)
     }
 
-    // Return the constructed AdsSample, attaching the latest IMU sample if
-    // one has been published.
-    if let Some(current_imu) = IMU_DATA_WATCH.try_get() {
-        AdsSample {
-            lead_off_positive,
-            lead_off_negative,
-            gpio,
-            data,
-            accel_x: Some(current_imu.accel_x),
-            accel_y: Some(current_imu.accel_y),
-            accel_z: Some(current_imu.accel_z),
-            gyro_x: Some(current_imu.gyro_x),
-            gyro_y: Some(current_imu.gyro_y),
-            gyro_z: Some(current_imu.gyro_z),
-        }
-    } else {
-        AdsSample {
-            lead_off_positive,
-            lead_off_negative,
-            gpio,
-            data,
-            accel_x: None,
-            accel_y: None,
-            accel_z: None,
-            gyro_x: None,
-            gyro_y: None,
-            gyro_z: None,
-        }
+    // Return the constructed AdsSample with the latest IMU data, if any.
+    let imu = ads_imu_data();
+    AdsSample {
+        lead_off_positive,
+        lead_off_negative,
+        gpio,
+        data,
+        accel_x: imu.accel.map(|a| a[0]),
+        accel_y: imu.accel.map(|a| a[1]),
+        accel_z: imu.accel.map(|a| a[2]),
+        gyro_x: imu.gyro.map(|g| g[0]),
+        gyro_y: imu.gyro.map(|g| g[1]),
+        gyro_z: imu.gyro.map(|g| g[2]),
+        quat_w: imu.orientation.map(|q| q[0]),
+        quat_x: imu.orientation.map(|q| q[1]),
+        quat_y: imu.orientation.map(|q| q[2]),
+        quat_z: imu.orientation.map(|q| q[3]),
     }
 }
 
-/// Collects samples until the batch interval is reached or streaming is stopped
+/// Collects samples until the batch interval is reached or streaming is
+/// stopped. Also returns the capture time of the first sample kept.
 async fn collect_batch(
-    sub: &mut DynSubscriber<'_, alloc::sync::Arc<Vec<AdsData, 2>>>,
+    sub: &mut DynSubscriber<'_, AdsMeasurement>,
     ads_watcher: &mut DynReceiver<'_, bool>,
+    decimator: &mut AdsStreamDecimator,
     next_batch_time: Instant,
-) -> (alloc::vec::Vec<AdsSample>, bool) {
+) -> (alloc::vec::Vec<AdsSample>, u64, bool) {
     let mut samples = alloc::vec::Vec::new();
+    let mut first_ts = None;
 
     while Instant::now() < next_batch_time {
-        match select(sub.next_message_pure(), ads_watcher.changed()).await {
+        match select(
+            next_ads_sample(sub, AdsConsumer::Usb),
+            ads_watcher.changed(),
+        )
+        .await
+        {
             Either::First(data) => {
-                samples.push(convert_sample(data));
+                let ts = data.ts;
+                let mut sample = convert_sample(data);
+                let Some(keep_imu) = decimator.push(&mut sample.data) else {
+                    continue;
+                };
+                first_ts.get_or_insert(ts);
+                if keep_imu {
+                    samples.push(sample);
+                } else {
+                    samples.push(AdsSample {
+                        accel_x: None,
+                        accel_y: None,
+                        accel_z: None,
+                        gyro_x: None,
+                        gyro_y: None,
+                        gyro_z: None,
+                        quat_w: None,
+                        quat_x: None,
+                        quat_y: None,
+                        quat_z: None,
+                        ..sample
+                    });
+                }
             }
             Either::Second(streaming) => {
                 if !streaming {
-                    return (samples, true);
+                    return (samples, first_ts.unwrap_or_default(), true);
                 }
             }
         }
     }
 
-    (samples, false)
+    (samples, first_ts.unwrap_or_default(), false)
 }
 
 async fn ads_stream_usb(sender: Sender<super::AppTx>) {
@@ -184,7 +284,8 @@ async fn ads_stream_usb(sender: Sender<super::AppTx>) {
     let mut ads_watcher =
         ADS_WATCH.dyn_receiver().expect("Failed to create watcher");
 
-    let mut packet_counter = 0u8;
+    let mut decimator = AdsStreamDecimator::new();
+    let mut packet_counter = 0u32;
     let mut next_batch_time = Instant::now() + BATCH_INTERVAL;
     let mut needs_recalc = false;
 
@@ -200,18 +301,31 @@ async fn ads_stream_usb(sender: Sender<super::AppTx>) {
         }
 
         // Collect samples until batch interval or streaming stops
-        let (samples, should_recalc) =
-            collect_batch(&mut sub, &mut ads_watcher, next_batch_time).await;
+        let (samples, ts, should_recalc) = collect_batch(
+            &mut sub,
+            &mut ads_watcher,
+            &mut decimator,
+            next_batch_time,
+        )
+        .await;
         needs_recalc = should_recalc;
 
         // Send collected samples if any
         if !samples.is_empty() {
-            let frame =
-                AdsDataFrame { ts: Instant::now().as_micros(), samples };
+            let mut frame = AdsDataFrame {
+                ts,
+                seq: packet_counter,
+                codec: AdsCodec::Raw,
+                decimation: decimator.factor(),
+                samples,
+            };
+            if ads_codec() == AdsCodec::Delta {
+                frame.delta_encode();
+            }
 
             if let Err(_e) = sender
                 .publish::<dc_mini_icd::AdsTopic>(
-                    packet_counter.into(),
+                    (packet_counter as u8).into(),
                     &frame,
                 )
                 .await
@@ -224,6 +338,7 @@ async fn ads_stream_usb(sender: Sender<super::AppTx>) {
             }
 
             packet_counter = packet_counter.wrapping_add(1);
+            decimator.report_backlog(sub.len(), ADS_CAP);
         }
 
         // Update next batch time if still streaming
@@ -232,3 +347,21 @@ async fn ads_stream_usb(sender: Sender<super::AppTx>) {
         }
     }
 }
+
+async fn lead_off_stream_usb(sender: Sender<super::AppTx>) {
+    let mut ticker = Ticker::every(LEAD_OFF_INTERVAL);
+    let mut seq = 0u8;
+
+    loop {
+        ticker.next().await;
+        let Some(status) = LEAD_OFF_WATCH.try_get() else {
+            continue;
+        };
+        if let Err(_e) =
+            sender.publish::<LeadOffTopic>(seq.into(), &status).await
+        {
+            warn!("Failed to publish lead-off status");
+        }
+        seq = seq.wrapping_add(1);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::prelude::*;
+use crate::tasks::apds::{APDS_DATA_WATCH, APDS_WATCH};
+use dc_mini_icd::{ApdsConfig, GestureConfig};
+use embassy_futures::select::{select, Either};
+use embassy_sync::signal::Signal;
+use postcard_rpc::{header::VarHeader, server::Sender};
+
+static APDS_USB_STREAM: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+#[embassy_executor::task]
+pub async fn apds_start_handler(
+    context: SpawnCtx,
+    header: VarHeader,
+    _rqst: (),
+    sender: Sender<super::AppTx>,
+) {
+    let config = {
+        let mut ctx = context.app.lock().await;
+        ctx.event_sender.send(ApdsEvent::StartStream.into()).await;
+        ctx.profile_manager
+            .get_apds_config()
+            .await
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    if sender.reply::<ApdsStartEndpoint>(header.seq_no, &config).await.is_err()
+    {
+        error!("Failed to reply, stopping apds");
+        return;
+    }
+
+    select(apds_stream_usb(sender), APDS_USB_STREAM.wait()).await;
+    APDS_USB_STREAM.reset();
+}
+
+pub async fn apds_stop_handler(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> () {
+    let ctx = context.app.lock().await;
+    let _res = ctx.event_sender.send(ApdsEvent::StopStream.into()).await;
+    APDS_USB_STREAM.signal(());
+}
+
+pub async fn apds_get_config(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> ApdsConfig {
+    let mut ctx = context.app.lock().await;
+    ctx.profile_manager.get_apds_config().await.cloned().unwrap_or_default()
+}
+
+pub async fn apds_set_config(
+    context: &mut super::Context,
+    _header: VarHeader,
+    rqst: ApdsConfig,
+) -> bool {
+    let mut ctx = context.app.lock().await;
+    ctx.save_apds_config(rqst).await;
+    true
+}
+
+pub async fn apds_reset_config(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> bool {
+    let ctx = context.app.lock().await;
+    ctx.event_sender.send(ApdsEvent::ResetConfig.into()).await;
+    true
+}
+
+pub async fn apds_get_gestures(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> GestureConfig {
+    let mut ctx = context.app.lock().await;
+    ctx.profile_manager.get_gesture_config().await.copied().unwrap_or_default()
+}
+
+pub async fn apds_set_gestures(
+    context: &mut super::Context,
+    _header: VarHeader,
+    rqst: GestureConfig,
+) -> bool {
+    let saved = {
+        let mut ctx = context.app.lock().await;
+        ctx.profile_manager.set_gesture_config(rqst).await.is_ok()
+    };
+    if saved {
+        start_for_gestures(context.app).await;
+    }
+    saved
+}
+
+async fn apds_stream_usb(sender: Sender<super::AppTx>) {
+    let mut data_rx = APDS_DATA_WATCH
+        .dyn_receiver()
+        .expect("Failed to create apds data receiver");
+    let mut apds_watcher =
+        APDS_WATCH.dyn_receiver().expect("Failed to create apds watcher");
+
+    let mut packet_counter = 0u8;
+
+    loop {
+        match select(data_rx.changed(), apds_watcher.changed()).await {
+            Either::First(frame) => {
+                if let Err(_e) = sender
+                    .publish::<dc_mini_icd::ApdsTopic>(
+                        packet_counter.into(),
+                        &frame,
+                    )
+                    .await
+                {
+                    #[cfg(feature = "defmt")]
+                    warn!(
+                        "Failed to publish apds data: {:?}",
+                        defmt::Debug2Format(&_e)
+                    );
+                }
+                packet_counter = packet_counter.wrapping_add(1);
+            }
+            Either::Second(streaming) => {
+                if !streaming {
+                    // Streaming stopped — wait for restart
+                    while !apds_watcher.changed().await {}
+                    packet_counter = 0;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! The PDM mic as a USB Audio Class 1.0 microphone, so audio tools can
+//! record from the device directly.
+//!
+//! The mic runs while the host has the streaming interface open, unless
+//! something else had already started it. The sample rate in the
+//! descriptors is the configured one at enumeration; a later change takes
+//! a reset to show up.
+
+use super::{AppDriver, MutexType};
+use crate::prelude::*;
+use crate::tasks::mic::{MIC_STREAM_CH, MIC_WATCH};
+use dc_mini_icd::MicSampleRate;
+use embassy_futures::select::{select, Either};
+use embassy_sync::signal::Signal;
+use embassy_usb::descriptor::{SynchronizationType, UsageType};
+use embassy_usb::driver::{Driver, EndpointIn};
+use embassy_usb::types::InterfaceNumber;
+use embassy_usb::{Builder, Handler};
+use heapless::Deque;
+use static_cell::StaticCell;
+
+const CLASS_AUDIO: u8 = 0x01;
+const SUBCLASS_AUDIOCONTROL: u8 = 0x01;
+const SUBCLASS_AUDIOSTREAMING: u8 = 0x02;
+const CS_INTERFACE: u8 = 0x24;
+const CS_ENDPOINT: u8 = 0x25;
+
+const TERMINAL_MIC: u8 = 1;
+const TERMINAL_USB: u8 = 2;
+
+/// Highest rate the mic runs at.
+const MAX_RATE_HZ: u32 = 20_000;
+/// Samples queued for the host; about 50 ms at the highest rate.
+const FIFO_LEN: usize = 1024;
+
+/// Open (`true`) or close of the streaming interface by the host.
+static STREAMING: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+static HANDLER: StaticCell<StreamingHandler> = StaticCell::new();
+
+/// Follows the host selecting the streaming interface's alternate
+/// settings; setting 1 is the one with the endpoint.
+struct StreamingHandler {
+    iface: InterfaceNumber,
+}
+
+impl Handler for StreamingHandler {
+    fn reset(&mut self) {
+        STREAMING.signal(false);
+    }
+
+    fn configured(&mut self, configured: bool) {
+        if !configured {
+            STREAMING.signal(false);
+        }
+    }
+
+    fn set_alternate_setting(
+        &mut self,
+        iface: InterfaceNumber,
+        alternate_setting: u8,
+    ) {
+        if iface == self.iface {
+            STREAMING.signal(alternate_setting == 1);
+        }
+    }
+}
+
+pub struct UsbMicrophone {
+    ep: <AppDriver as Driver<'static>>::EndpointIn,
+    rate_hz: u32,
+    app: &'static Mutex<MutexType, AppContext>,
+}
+
+impl UsbMicrophone {
+    /// Adds the audio function to the USB device being built, advertising
+    /// `rate`.
+    pub fn new(
+        builder: &mut Builder<'static, AppDriver>,
+        rate: MicSampleRate,
+        app: &'static Mutex<MutexType, AppContext>,
+    ) -> Self {
+        let rate_hz = rate.as_hz();
+        let mut func = builder.function(CLASS_AUDIO, SUBCLASS_AUDIOCONTROL, 0);
+
+        let mut control = func.interface();
+        let streaming_iface =
+            InterfaceNumber(control.interface_number().0 + 1);
+        let mut alt =
+            control.alt_setting(CLASS_AUDIO, SUBCLASS_AUDIOCONTROL, 0, None);
+        // Header: UAC 1.0, 30 bytes of class descriptors, one streaming
+        // interface.
+        alt.descriptor(
+            CS_INTERFACE,
+            &[0x01, 0x00, 0x01, 30, 0, 1, streaming_iface.0],
+        );
+        // Input terminal: a mono microphone.
+        alt.descriptor(
+            CS_INTERFACE,
+            &[0x02, TERMINAL_MIC, 0x01, 0x02, 0, 1, 0, 0, 0, 0],
+        );
+        // Output terminal: USB streaming, fed by the microphone.
+        alt.descriptor(
+            CS_INTERFACE,
+            &[0x03, TERMINAL_USB, 0x01, 0x01, 0, TERMINAL_MIC, 0],
+        );
+
+        let mut streaming = func.interface();
+        let iface = streaming.interface_number();
+        // Zero bandwidth while the host is not recording.
+        streaming.alt_setting(CLASS_AUDIO, SUBCLASS_AUDIOSTREAMING, 0, None);
+        let mut alt = streaming.alt_setting(
+            CLASS_AUDIO,
+            SUBCLASS_AUDIOSTREAMING,
+            0,
+            None,
+        );
+        // General: linked to the USB terminal, one frame delay, PCM.
+        alt.descriptor(CS_INTERFACE, &[0x01, TERMINAL_USB, 1, 0x01, 0x00]);
+        // Format type I: mono, 16-bit, one discrete rate.
+        let rate = rate_hz.to_le_bytes();
+        alt.descriptor(
+            CS_INTERFACE,
+            &[0x02, 0x01, 1, 2, 16, 1, rate[0], rate[1], rate[2]],
+        );
+        let ep = alt.endpoint_isochronous_in(
+            None,
+            max_packet_size(rate_hz),
+            1,
+            SynchronizationType::Asynchronous,
+            UsageType::DataEndpoint,
+            // bRefresh and bSynchAddress of the audio endpoint descriptor.
+            &[0, 0],
+        );
+        // General endpoint: no controls, no lock delay.
+        alt.descriptor(CS_ENDPOINT, &[0x01, 0x00, 0x00, 0x00, 0x00]);
+        drop(func);
+
+        builder.handler(HANDLER.init(StreamingHandler { iface }));
+        Self { ep, rate_hz, app }
+    }
+
+    /// Streams the mic to the host whenever it records.
+    pub async fn run(&mut self) -> ! {
+        loop {
+            while !STREAMING.wait().await {}
+            info!("[usb] audio stream opened");
+            self.stream().await;
+            info!("[usb] audio stream closed");
+        }
+    }
+
+    async fn stream(&mut self) {
+        let started_mic = MIC_WATCH.try_get() != Some(true);
+        {
+            let mut ctx = self.app.lock().await;
+            let config = ctx.profile_manager.get_mic_config().await;
+            let rate_hz =
+                config.cloned().unwrap_or_default().sample_rate.as_hz();
+            if rate_hz != self.rate_hz {
+                warn!(
+                    "[usb] mic runs at {} Hz, audio interface at {} Hz; \
+                     reset to match",
+                    rate_hz, self.rate_hz
+                );
+            }
+            if started_mic {
+                ctx.event_sender.send(MicEvent::StartStream.into()).await;
+            }
+        }
+
+        match MIC_STREAM_CH.dyn_subscriber() {
+            Ok(mut sub) => {
+                let mut fifo = Deque::<i16, FIFO_LEN>::new();
+                let mut pacer = FramePacer::new(self.rate_hz);
+                let mut packet = [0u8; max_packet_size(MAX_RATE_HZ) as usize];
+                loop {
+                    while let Some(buf) = sub.try_next_message_pure() {
+                        for sample in buf.data.iter() {
+                            if fifo.is_full() {
+                                fifo.pop_front();
+                            }
+                            let _ = fifo.push_back(*sample);
+                        }
+                    }
+                    let len = pacer.next() * 2;
+                    for bytes in packet[..len].chunks_exact_mut(2) {
+                        // Silence on underrun.
+                        let sample = fifo.pop_front().unwrap_or(0);
+                        bytes.copy_from_slice(&sample.to_le_bytes());
+                    }
+                    match select(
+                        STREAMING.wait(),
+                        self.ep.write(&packet[..len]),
+                    )
+                    .await
+                    {
+                        Either::First(true) | Either::Second(Ok(())) => {}
+                        Either::First(false) | Either::Second(Err(_)) => break,
+                    }
+                }
+            }
+            Err(_) => {
+                warn!("[usb] no mic subscriber left for audio");
+                while STREAMING.wait().await {}
+            }
+        }
+
+        if started_mic {
+            let ctx = self.app.lock().await;
+            ctx.event_sender.send(MicEvent::StopStream.into()).await;
+        }
+    }
+}
+
+/// Room for one sample more than a 1 ms frame holds on average, since the
+/// rate need not divide evenly.
+const fn max_packet_size(rate_hz: u32) -> u16 {
+    ((rate_hz / 1000 + 1) * 2) as u16
+}
+
+/// Samples per 1 ms frame, spreading any remainder over the frames.
+struct FramePacer {
+    rate_hz: u32,
+    remainder: u32,
+}
+
+impl FramePacer {
+    fn new(rate_hz: u32) -> Self {
+        Self { rate_hz, remainder: 0 }
+    }
+
+    fn next(&mut self) -> usize {
+        self.remainder += self.rate_hz;
+        let samples = self.remainder / 1000;
+        self.remainder %= 1000;
+        samples as usize
+    }
+}
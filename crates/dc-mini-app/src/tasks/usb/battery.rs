@@ -1,12 +1,90 @@
-use dc_mini_icd::BatteryLevel;
+use crate::prelude::*;
+use crate::tasks::power_control::{
+    latest_battery_status, power_profile, start_power_profile,
+    stop_power_profile, BATTERY_WATCH,
+};
+use dc_mini_icd::{
+    BatteryLevel, BatteryStatus, LowBatteryConfig, PowerProfile,
+};
 use postcard_rpc::header::VarHeader;
+use postcard_rpc::server::Sender;
 
 pub async fn battery_get_level(
     _context: &mut super::Context,
     _header: VarHeader,
     _req: (),
 ) -> BatteryLevel {
-    // let app_ctx = context.app.lock().await;
-    // TODO: Implement actual battery level reading
-    BatteryLevel(100)
+    // Reads 0 while the PMIC cannot be read; `battery/status` answers
+    // `None` then.
+    BatteryLevel(
+        latest_battery_status()
+            .await
+            .map_or(0, |status| status.state_of_charge),
+    )
+}
+
+pub async fn battery_get_status(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> Option<BatteryStatus> {
+    latest_battery_status().await
+}
+
+pub async fn battery_get_shutdown(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> LowBatteryConfig {
+    let mut ctx = context.app.lock().await;
+    ctx.profile_manager.get_low_battery_config().await
+}
+
+pub async fn battery_set_shutdown(
+    context: &mut super::Context,
+    _header: VarHeader,
+    rqst: LowBatteryConfig,
+) -> bool {
+    let mut ctx = context.app.lock().await;
+    ctx.profile_manager.set_low_battery_config(rqst).await.is_ok()
+}
+
+pub async fn power_profile_start(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> bool {
+    start_power_profile()
+}
+
+pub async fn power_profile_stop(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) {
+    stop_power_profile()
+}
+
+pub async fn power_profile_get(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> PowerProfile {
+    power_profile()
+}
+
+/// Forwards each battery reading to the host for as long as USB is up.
+/// Failed PMIC reads are not forwarded.
+pub async fn battery_publisher(sender: Sender<super::AppTx>) {
+    let mut receiver = unwrap!(BATTERY_WATCH.receiver());
+    let mut seq: u8 = 0;
+    loop {
+        let Some(status) = receiver.changed().await else {
+            continue;
+        };
+        if sender.publish::<BatteryTopic>(seq.into(), &status).await.is_err() {
+            warn!("[usb] Failed to publish battery status");
+        }
+        seq = seq.wrapping_add(1);
+    }
 }
@@ -1,4 +1,8 @@
-use dc_mini_icd::DeviceInfo;
+use crate::prelude::*;
+use dc_mini_icd::{
+    CrashReport, DeviceIdentity, DeviceInfo, DeviceStats, FirmwareFeatures,
+    Nickname, ProtocolInfo, ADS_MAX_CHANNELS, FS_CHUNK_SIZE, PROTOCOL_VERSION,
+};
 use postcard_rpc::header::VarHeader;
 
 pub async fn device_info_get(
@@ -9,3 +13,106 @@ pub async fn device_info_get(
     let app_ctx = context.app.lock().await;
     app_ctx.device_info.clone()
 }
+
+pub async fn device_stats_get(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> DeviceStats {
+    crate::stats::device_stats()
+}
+
+pub async fn crash_report_get(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> Option<CrashReport> {
+    crate::crash::last_report()
+}
+
+pub async fn crash_clear(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> bool {
+    // Hold the app context so the erase does not interleave with profile
+    // storage writes on the NVMC.
+    let _app_ctx = context.app.lock().await;
+    crate::crash::clear()
+}
+
+pub async fn protocol_info_get(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> ProtocolInfo {
+    let app_ctx = context.app.lock().await;
+    ProtocolInfo {
+        protocol_version: PROTOCOL_VERSION,
+        schema_hash: dc_mini_icd::schema_hash(),
+        hardware_revision: app_ctx.device_info.hardware_revision.clone(),
+        features: FirmwareFeatures {
+            usb: true,
+            ble: cfg!(feature = "trouble"),
+            pmic_bus: cfg!(not(feature = "sr6")),
+        },
+        capabilities: app_ctx.capabilities(),
+        max_frame_size: super::MAX_FRAME_SIZE as u16,
+        max_ads_channels: ADS_MAX_CHANNELS as u8,
+        fs_chunk_size: FS_CHUNK_SIZE as u16,
+    }
+}
+
+pub async fn device_identity_get(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> DeviceIdentity {
+    let mut app_ctx = context.app.lock().await;
+    DeviceIdentity {
+        serial_number: crate::identity::serial_number(),
+        nickname: app_ctx
+            .profile_manager
+            .get_nickname()
+            .await
+            .cloned()
+            .unwrap_or_default(),
+    }
+}
+
+/// Stores a new nickname. It is advertised from the next BLE advertising
+/// cycle and shows up in the USB descriptors after the next reset.
+pub async fn device_set_nickname(
+    context: &mut super::Context,
+    _header: VarHeader,
+    req: Nickname,
+) -> bool {
+    if req.chars().any(|c| c.is_control()) {
+        return false;
+    }
+    let mut app_ctx = context.app.lock().await;
+    match app_ctx.profile_manager.set_nickname(req).await {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Failed to save nickname: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Forgets the bonded BLE centrals. A central that is connected keeps its
+/// link, but loses access to the protected characteristics.
+pub async fn ble_clear_bonds(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> bool {
+    let mut app_ctx = context.app.lock().await;
+    match app_ctx.profile_manager.clear_ble_bonds().await {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Failed to clear BLE bonds: {:?}", e);
+            false
+        }
+    }
+}
@@ -144,8 +144,8 @@ pub async fn dfu_finish(
         };
     }
 
-    info!("[usb-dfu] Finish: marking updated");
-    match context.dfu.mark_updated() {
+    info!("[usb-dfu] Finish: verifying signature");
+    match context.dfu.verify_and_mark_updated().await {
         Ok(()) => {
             context.dfu.finish();
             {
@@ -163,17 +163,16 @@ pub async fn dfu_finish(
                     .unwrap(),
             }
         }
-        Err(_e) => {
+        Err(e) => {
             context.dfu.finish();
             {
                 let app_ctx = context.app.lock().await;
                 app_ctx.event_sender.send(DfuEvent::Failed.into()).await;
             }
-            warn!("[usb-dfu] mark_updated failed");
+            warn!("[usb-dfu] Update rejected: {:?}", e);
             DfuResult {
                 success: false,
-                message: heapless::String::try_from("mark_updated failed")
-                    .unwrap(),
+                message: heapless::String::try_from(e.as_str()).unwrap(),
             }
         }
     }
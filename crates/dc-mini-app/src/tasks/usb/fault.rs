@@ -0,0 +1,17 @@
+use crate::faults::FAULT_CH;
+use crate::prelude::*;
+use postcard_rpc::server::Sender;
+
+/// Forwards runtime faults to the host for as long as USB is up.
+pub async fn fault_publisher(sender: Sender<super::AppTx>) {
+    let mut sub =
+        FAULT_CH.dyn_subscriber().expect("Failed to create fault subscriber");
+    let mut seq: u8 = 0;
+    loop {
+        let fault = sub.next_message_pure().await;
+        if sender.publish::<FaultTopic>(seq.into(), &fault).await.is_err() {
+            warn!("[usb] Failed to publish fault {:?}", fault.kind);
+        }
+        seq = seq.wrapping_add(1);
+    }
+}
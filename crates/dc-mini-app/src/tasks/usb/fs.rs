@@ -0,0 +1,149 @@
+use crate::prelude::*;
+use dc_mini_icd::{
+    FsChunkData, FsDelete, FsReadBegin, FsReadChunk, FsResult, StorageStatus,
+    StorageStatusTopic,
+};
+use heapless::String;
+use postcard_rpc::{header::VarHeader, server::Sender};
+
+fn fs_result(success: bool, size: u32, message: &str) -> FsResult {
+    FsResult {
+        success,
+        size,
+        message: String::try_from(message).unwrap_or_default(),
+    }
+}
+
+pub async fn fs_read_begin(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    req: FsReadBegin,
+) -> FsResult {
+    match open_transfer(TransferOwner::Usb, req.name).await {
+        Ok(size) => fs_result(true, size, "File opened"),
+        Err(msg) => fs_result(false, 0, msg),
+    }
+}
+
+pub async fn fs_read_chunk(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    req: FsReadChunk,
+) -> Option<FsChunkData> {
+    match read_transfer(TransferOwner::Usb, req.offset, req.length as usize)
+        .await
+    {
+        Ok((data, eof)) => Some(FsChunkData { offset: req.offset, data, eof }),
+        Err(_msg) => {
+            warn!("[usb-fs] Read failed at offset {}: {}", req.offset, _msg);
+            None
+        }
+    }
+}
+
+pub async fn fs_read_finish(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> FsResult {
+    match close_transfer(TransferOwner::Usb).await {
+        Ok(size) => fs_result(true, size, "File closed"),
+        Err(msg) => fs_result(false, 0, msg),
+    }
+}
+
+pub async fn fs_delete(
+    context: &mut super::Context,
+    _header: VarHeader,
+    req: FsDelete,
+) -> FsResult {
+    {
+        let app_ctx = context.app.lock().await;
+        if app_ctx.state.recording_status {
+            return fs_result(false, 0, "Recording active");
+        }
+    }
+    // An open download holds the card.
+    let _ = close_transfer(TransferOwner::Usb).await;
+    let Ok(mut sd) = context.sd.try_lock() else {
+        return fs_result(false, 0, "SD card busy");
+    };
+
+    match delete_file(&mut sd, req.name.as_str()) {
+        Ok(()) => {
+            info!("[usb-fs] Deleted {}", req.name.as_str());
+            fs_result(true, 0, "File deleted")
+        }
+        Err(msg) => fs_result(false, 0, msg),
+    }
+}
+
+pub async fn storage_get_status(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> Option<StorageStatus> {
+    let Ok(mut sd) = context.sd.try_lock() else {
+        warn!("[usb-fs] SD card busy");
+        return None;
+    };
+    match storage_status(&mut sd) {
+        Ok(status) => {
+            STORAGE_STATUS_WATCH.sender().send(status.clone());
+            Some(status)
+        }
+        Err(_msg) => {
+            warn!("[usb-fs] Storage status failed: {}", _msg);
+            None
+        }
+    }
+}
+
+pub async fn storage_clear(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> FsResult {
+    {
+        let app_ctx = context.app.lock().await;
+        if app_ctx.state.recording_status {
+            return fs_result(false, 0, "Recording active");
+        }
+    }
+    let _ = close_transfer(TransferOwner::Usb).await;
+    let Ok(mut sd) = context.sd.try_lock() else {
+        return fs_result(false, 0, "SD card busy");
+    };
+
+    match clear_card(&mut sd) {
+        Ok(_deleted) => {
+            info!("[usb-fs] Cleared card, {} deleted", _deleted);
+            if let Ok(status) = storage_status(&mut sd) {
+                STORAGE_STATUS_WATCH.sender().send(status);
+            }
+            fs_result(true, 0, "Card cleared")
+        }
+        Err(msg) => fs_result(false, 0, msg),
+    }
+}
+
+/// Publishes a [`StorageStatusTopic`] warning whenever the SD card is
+/// reported low on space.
+pub async fn storage_status_publisher(sender: Sender<super::AppTx>) {
+    let mut receiver = unwrap!(STORAGE_STATUS_WATCH.receiver());
+    let mut seq: u8 = 0;
+    loop {
+        let status = receiver.changed().await;
+        if !status.low {
+            continue;
+        }
+        if sender
+            .publish::<StorageStatusTopic>(seq.into(), &status)
+            .await
+            .is_err()
+        {
+            warn!("[usb-fs] Failed to publish storage warning");
+        }
+        seq = seq.wrapping_add(1);
+    }
+}
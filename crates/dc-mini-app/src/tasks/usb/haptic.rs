@@ -0,0 +1,60 @@
+use crate::prelude::*;
+use dc_mini_icd::{HapticConfig, HapticCue, HapticPattern, MAX_HAPTIC_EFFECT};
+use postcard_rpc::header::VarHeader;
+
+pub async fn haptic_play(
+    context: &mut super::Context,
+    _header: VarHeader,
+    rqst: HapticPattern,
+) -> bool {
+    if rqst.effect > MAX_HAPTIC_EFFECT {
+        warn!("Rejecting unknown haptic effect {}", rqst.effect);
+        return false;
+    }
+    let ctx = context.app.lock().await;
+    ctx.event_sender
+        .send(HapticEvent::Play(HapticCommand::PlayPattern(rqst)).into())
+        .await;
+    true
+}
+
+pub async fn haptic_stop(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> () {
+    let ctx = context.app.lock().await;
+    ctx.event_sender.send(HapticEvent::Stop.into()).await;
+}
+
+pub async fn haptic_play_cue(
+    context: &mut super::Context,
+    _header: VarHeader,
+    rqst: HapticCue,
+) -> bool {
+    let ctx = context.app.lock().await;
+    ctx.event_sender.send(HapticEvent::Cue(rqst).into()).await;
+    true
+}
+
+pub async fn haptic_get_config(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> HapticConfig {
+    let mut ctx = context.app.lock().await;
+    ctx.profile_manager.get_haptic_config().await.cloned().unwrap_or_default()
+}
+
+pub async fn haptic_set_config(
+    context: &mut super::Context,
+    _header: VarHeader,
+    rqst: HapticConfig,
+) -> bool {
+    if !rqst.is_valid() {
+        warn!("Rejecting haptic config with unknown effects");
+        return false;
+    }
+    let mut ctx = context.app.lock().await;
+    ctx.profile_manager.set_haptic_config(rqst).await.is_ok()
+}
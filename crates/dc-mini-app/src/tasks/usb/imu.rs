@@ -0,0 +1,92 @@
+use crate::prelude::*;
+use crate::tasks::ads::ADS_WATCH;
+use crate::tasks::imu::QUATERNION_WATCH;
+use embassy_futures::select::select;
+use embassy_sync::signal::Signal;
+use postcard_rpc::{header::VarHeader, server::Sender};
+
+static QUATERNION_USB_STREAM: Signal<CriticalSectionRawMutex, ()> =
+    Signal::new();
+
+#[embassy_executor::task]
+pub async fn quaternion_start_handler(
+    context: SpawnCtx,
+    header: VarHeader,
+    rqst: u8,
+    sender: Sender<super::AppTx>,
+) {
+    let started = {
+        let mut ctx = context.app.lock().await;
+        if ctx.capabilities().imu_present {
+            let mut config = ctx
+                .profile_manager
+                .get_imu_config()
+                .await
+                .cloned()
+                .unwrap_or_default();
+            config.quaternion_enabled = true;
+            if rqst != 0 {
+                config.quaternion_rate = rqst;
+            }
+            ctx.save_imu_config(config).await;
+            ctx.event_sender.send(ImuEvent::StartStream.into()).await;
+            true
+        } else {
+            false
+        }
+    };
+
+    if sender
+        .reply::<QuaternionStartEndpoint>(header.seq_no, &started)
+        .await
+        .is_err()
+    {
+        error!("Failed to reply, stopping quaternion stream");
+        return;
+    }
+    if !started {
+        return;
+    }
+
+    select(quaternion_stream_usb(sender), QUATERNION_USB_STREAM.wait()).await;
+    QUATERNION_USB_STREAM.reset();
+}
+
+pub async fn quaternion_stop_handler(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> () {
+    let mut ctx = context.app.lock().await;
+    if let Some(mut config) =
+        ctx.profile_manager.get_imu_config().await.cloned()
+    {
+        config.quaternion_enabled = false;
+        ctx.save_imu_config(config).await;
+    }
+    // The ADS stream carries IMU readings; leave the IMU running for it.
+    if ADS_WATCH.try_get() != Some(true) {
+        ctx.event_sender.send(ImuEvent::StopStream.into()).await;
+    }
+    QUATERNION_USB_STREAM.signal(());
+}
+
+async fn quaternion_stream_usb(sender: Sender<super::AppTx>) {
+    let mut receiver = QUATERNION_WATCH
+        .dyn_receiver()
+        .expect("Failed to create quaternion receiver");
+
+    loop {
+        let quat = receiver.changed().await;
+        let seq: u8 = (quat.seq & 0xFF) as u8;
+        if let Err(_e) =
+            sender.publish::<QuaternionTopic>(seq.into(), &quat).await
+        {
+            #[cfg(feature = "defmt")]
+            warn!(
+                "Failed to publish quaternion: {:?}",
+                defmt::Debug2Format(&_e)
+            );
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::prelude::*;
+use dc_mini_icd::{LedEffect, LedOverride, NeopixelConfig};
+use postcard_rpc::header::VarHeader;
+
+fn valid_effect(effect: &LedEffect) -> bool {
+    match *effect {
+        LedEffect::Flash { interval_ms, duty_cycle } => {
+            interval_ms != 0 && duty_cycle <= 100
+        }
+        LedEffect::Breathe { period_ms } => period_ms != 0,
+        LedEffect::Off | LedEffect::Solid => true,
+    }
+}
+
+pub async fn led_set(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    rqst: Option<LedOverride>,
+) -> bool {
+    if let Some(cfg) = rqst.as_ref() {
+        if !valid_effect(&cfg.effect) {
+            warn!("Rejecting invalid LED effect parameters");
+            return false;
+        }
+    }
+    NEOPIX_CHAN.send(NeopixEvent::Override(rqst)).await;
+    true
+}
+
+pub async fn led_get_config(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> NeopixelConfig {
+    let mut ctx = context.app.lock().await;
+    ctx.profile_manager
+        .get_neopixel_config()
+        .await
+        .cloned()
+        .unwrap_or_default()
+}
+
+pub async fn led_set_config(
+    context: &mut super::Context,
+    _header: VarHeader,
+    rqst: NeopixelConfig,
+) -> bool {
+    let patterns = [
+        &rqst.idle,
+        &rqst.advertising,
+        &rqst.connected,
+        &rqst.streaming,
+        &rqst.recording,
+        &rqst.error,
+        &rqst.charging,
+        &rqst.charged,
+        &rqst.charger_fault,
+        &rqst.low_battery,
+    ];
+    if !patterns.iter().all(|p| valid_effect(&p.effect)) {
+        warn!("Rejecting invalid LED effect parameters");
+        return false;
+    }
+    let saved = {
+        let mut ctx = context.app.lock().await;
+        ctx.profile_manager.set_neopixel_config(rqst).await.is_ok()
+    };
+    if saved {
+        NEOPIX_CHAN.send(NeopixEvent::ConfigChanged).await;
+    }
+    saved
+}
@@ -0,0 +1,80 @@
+use crate::logging::{self, LOG_CH};
+use crate::prelude::*;
+use embassy_futures::select::select;
+use embassy_sync::signal::Signal;
+use postcard_rpc::{header::VarHeader, server::Sender};
+
+static LOG_USB_STREAM: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+#[embassy_executor::task]
+pub async fn log_start_handler(
+    _context: SpawnCtx,
+    header: VarHeader,
+    rqst: LogLevel,
+    sender: Sender<super::AppTx>,
+) {
+    logging::set_level(rqst);
+
+    if sender.reply::<LogStartEndpoint>(header.seq_no, &()).await.is_err() {
+        error!("Failed to reply, stopping log stream");
+        return;
+    }
+
+    select(log_stream_usb(sender), LOG_USB_STREAM.wait()).await;
+    LOG_USB_STREAM.reset();
+}
+
+pub async fn log_stop_handler(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> () {
+    LOG_USB_STREAM.signal(());
+}
+
+pub async fn log_get_level(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> LogLevel {
+    logging::level()
+}
+
+pub async fn log_set_level(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    rqst: LogLevel,
+) -> bool {
+    logging::set_level(rqst);
+    true
+}
+
+pub async fn log_dump(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    rqst: u32,
+) -> LogDump {
+    logging::dump(rqst)
+}
+
+pub async fn event_log_dump(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    rqst: u32,
+) -> EventLogDump {
+    crate::event_log::dump(rqst).await
+}
+
+async fn log_stream_usb(sender: Sender<super::AppTx>) {
+    let mut sub =
+        LOG_CH.dyn_subscriber().expect("Failed to create log subscriber");
+    let mut seq: u8 = 0;
+
+    loop {
+        let record = sub.next_message_pure().await;
+        // Don't log failures here: the error would be fed straight back
+        // into this stream.
+        let _ = sender.publish::<LogTopic>(seq.into(), &record).await;
+        seq = seq.wrapping_add(1);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::clock::now_micros;
+use crate::prelude::*;
+use dc_mini_icd::{EventMarker, MarkerRecord};
+use postcard_rpc::{header::VarHeader, server::Sender};
+
+#[embassy_executor::task(pool_size = 2)]
+pub async fn event_marker_handler(
+    _context: SpawnCtx,
+    header: VarHeader,
+    rqst: EventMarker,
+    sender: Sender<super::AppTx>,
+) {
+    let mut marker = MarkerRecord {
+        ts: now_micros(),
+        host_epoch_us: rqst.host_epoch_us,
+        label: rqst.label,
+        recorded: false,
+    };
+    marker.recorded = record_marker(&marker);
+
+    if sender
+        .reply::<EventMarkerEndpoint>(header.seq_no, &marker)
+        .await
+        .is_err()
+    {
+        error!("Failed to reply to event marker");
+        return;
+    }
+
+    if let Err(_e) =
+        sender.publish::<EventMarkerTopic>(header.seq_no, &marker).await
+    {
+        warn!("Failed to publish event marker");
+    }
+}
@@ -1,10 +1,11 @@
 use crate::prelude::*;
 use crate::tasks::mic::adpcm::AdpcmEncoder;
-use crate::tasks::mic::{MIC_BUF_SAMPLES, MIC_STREAM_CH, MIC_WATCH};
-use dc_mini_icd::MicConfig;
+use crate::tasks::mic::{
+    MIC_BUF_SAMPLES, MIC_CONFIG_WATCH, MIC_STREAM_CH, MIC_WATCH,
+};
+use dc_mini_icd::{MicCodec, MicConfig};
 use embassy_futures::select::{select, Either};
 use embassy_sync::signal::Signal;
-use embassy_time::Instant;
 use postcard_rpc::{header::VarHeader, server::Sender};
 
 static MIC_USB_STREAM: Signal<CriticalSectionRawMutex, ()> = Signal::new();
@@ -68,7 +69,6 @@ async fn mic_stream_usb(sender: Sender<super::AppTx>, config: &MicConfig) {
     let mut mic_watcher =
         MIC_WATCH.dyn_receiver().expect("Failed to create mic watcher");
 
-    let sample_rate = config.sample_rate.as_hz();
     let mut encoder = AdpcmEncoder::new();
     let mut packet_counter: u64 = 0;
     let mut adpcm_buf = [0u8; MIC_BUF_SAMPLES / 2];
@@ -76,17 +76,36 @@ async fn mic_stream_usb(sender: Sender<super::AppTx>, config: &MicConfig) {
     loop {
         match select(sub.next_message_pure(), mic_watcher.changed()).await {
             Either::First(pcm_buf) => {
-                let (predictor, step_index) = encoder.decoder_state();
-                encoder.encode_block(&pcm_buf, &mut adpcm_buf);
-
-                let frame = dc_mini_icd::MicDataFrame {
-                    ts: Instant::now().as_micros(),
+                // Follows reconfigurations made while streaming.
+                let config = MIC_CONFIG_WATCH
+                    .try_get()
+                    .unwrap_or_else(|| config.clone());
+                let mut frame = dc_mini_icd::MicDataFrame {
+                    ts: pcm_buf.ts,
                     packet_counter,
-                    sample_rate,
-                    predictor,
-                    step_index,
-                    adpcm_data: adpcm_buf.to_vec(),
+                    sample_rate: config.sample_rate.as_hz(),
+                    predictor: 0,
+                    step_index: 0,
+                    adpcm_data: alloc::vec::Vec::new(),
+                    seq: packet_counter as u32,
+                    codec: config.stream_codec,
+                    pcm_data: alloc::vec::Vec::new(),
                 };
+                match config.stream_codec {
+                    MicCodec::Adpcm => {
+                        (frame.predictor, frame.step_index) =
+                            encoder.decoder_state();
+                        encoder.encode_block(&pcm_buf.data, &mut adpcm_buf);
+                        frame.adpcm_data = adpcm_buf.to_vec();
+                    }
+                    MicCodec::Pcm => {
+                        frame.pcm_data = pcm_buf
+                            .data
+                            .iter()
+                            .flat_map(|sample| sample.to_le_bytes())
+                            .collect();
+                    }
+                }
 
                 let seq: u8 = (packet_counter & 0xFF) as u8;
                 if let Err(_e) = sender
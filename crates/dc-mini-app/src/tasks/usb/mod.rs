@@ -1,9 +1,9 @@
 use crate::prelude::*;
 use dc_mini_bsp::usb::UsbDriverBuilder;
-use embassy_futures::join::join;
+use embassy_futures::join::{join, join4};
 use embassy_nrf::usb::Driver;
 use embassy_usb::Config;
-use static_cell::ConstStaticCell;
+use static_cell::{ConstStaticCell, StaticCell};
 
 // Re-exports
 use postcard_rpc::{
@@ -21,20 +21,46 @@ use postcard_rpc::{
 };
 
 mod ads;
+mod apds;
+#[cfg(feature = "usb-audio")]
+mod audio;
 mod battery;
 mod device_info;
 mod dfu;
+mod fault;
+mod fs;
+mod haptic;
+mod imu;
+mod led;
+mod log;
+mod marker;
 mod mic;
 mod profile;
+mod selftest;
 mod session;
+#[cfg(feature = "usb-shell")]
+mod shell;
+mod stream;
+mod time_sync;
 
 use ads::*;
+use apds::*;
 use battery::*;
 use device_info::*;
 use dfu::*;
+use fault::*;
+use fs::*;
+use haptic::*;
+use imu::*;
+use led::*;
+use log::*;
+use marker::*;
 use mic::*;
 use profile::*;
+use selftest::*;
 use session::*;
+use stream::*;
+use time_sync::*;
 
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 
@@ -46,17 +72,24 @@ type AppServer = Server<AppTx, AppRx, WireRxBuf, DcMiniUsbApp>;
 
 type AppDriver =
     Driver<'static, embassy_nrf::usb::vbus_detect::HardwareVbusDetect>;
-type AppStorage = WireStorage<MutexType, AppDriver, 256, 256, 64, 256>;
-type BufStorage = PacketBuffers<1024, 1024>;
+// The configuration descriptor leaves room for the optional shell and audio
+// interfaces.
+type AppStorage = WireStorage<MutexType, AppDriver, 512, 256, 64, 256>;
+/// Largest postcard-rpc frame in either direction.
+const MAX_FRAME_SIZE: usize = 1024;
+type BufStorage = PacketBuffers<MAX_FRAME_SIZE, MAX_FRAME_SIZE>;
 
 // Statics
 static PBUFS: ConstStaticCell<BufStorage> =
     ConstStaticCell::new(BufStorage::new());
 static STORAGE: AppStorage = AppStorage::new();
+static PRODUCT: StaticCell<Nickname> = StaticCell::new();
+static SERIAL_NUMBER: StaticCell<heapless::String<16>> = StaticCell::new();
 
 pub struct Context {
     pub app: &'static Mutex<MutexType, AppContext>,
     pub dfu: &'static crate::tasks::dfu::DfuResources,
+    pub sd: &'static Mutex<MutexType, SdCardResources>,
 }
 
 define_dispatch! {
@@ -76,25 +109,88 @@ define_dispatch! {
         | AdsResetConfigEndpoint    | async     | ads_reset_config              |
         | AdsGetConfigEndpoint      | async     | ads_get_config                |
         | AdsSetConfigEndpoint      | async     | ads_set_config                |
+        | AdsGetMontageEndpoint     | async     | ads_get_montage               |
+        | AdsSetMontageEndpoint     | async     | ads_set_montage               |
+        | LeadOffStartEndpoint      | spawn     | lead_off_start_handler        |
+        | LeadOffStopEndpoint       | async     | lead_off_stop_handler         |
+        | AdsImpedanceEndpoint      | async     | ads_impedance_check           |
+        | QuaternionStartEndpoint   | spawn     | quaternion_start_handler      |
+        | QuaternionStopEndpoint    | async     | quaternion_stop_handler       |
         | MicStartEndpoint          | spawn     | mic_start_handler             |
         | MicStopEndpoint           | async     | mic_stop_handler              |
         | MicGetConfigEndpoint      | async     | mic_get_config                |
         | MicSetConfigEndpoint      | async     | mic_set_config                |
+        | ApdsStartEndpoint         | spawn     | apds_start_handler            |
+        | ApdsStopEndpoint          | async     | apds_stop_handler             |
+        | ApdsResetConfigEndpoint   | async     | apds_reset_config             |
+        | ApdsGetConfigEndpoint     | async     | apds_get_config               |
+        | ApdsSetConfigEndpoint     | async     | apds_set_config               |
+        | ApdsGetGesturesEndpoint   | async     | apds_get_gestures             |
+        | ApdsSetGesturesEndpoint   | async     | apds_set_gestures             |
         | BatteryGetLevelEndpoint   | async     | battery_get_level             |
+        | BatteryGetStatusEndpoint  | async     | battery_get_status            |
+        | BatteryGetShutdownEndpoint | async    | battery_get_shutdown          |
+        | BatterySetShutdownEndpoint | async    | battery_set_shutdown          |
+        | PowerProfileStartEndpoint | async     | power_profile_start           |
+        | PowerProfileStopEndpoint  | async     | power_profile_stop            |
+        | PowerProfileGetEndpoint   | async     | power_profile_get             |
         | DeviceInfoGetEndpoint     | async     | device_info_get               |
+        | DeviceStatsEndpoint       | async     | device_stats_get              |
+        | CrashReportEndpoint       | async     | crash_report_get              |
+        | CrashClearEndpoint        | async     | crash_clear                   |
+        | SelfTestEndpoint          | async     | self_test_run                 |
+        | ProtocolInfoEndpoint      | async     | protocol_info_get             |
+        | DeviceIdentityEndpoint    | async     | device_identity_get           |
+        | DeviceSetNicknameEndpoint | async     | device_set_nickname           |
+        | BleClearBondsEndpoint     | async     | ble_clear_bonds               |
         | ProfileGetEndpoint        | async     | profile_get                   |
         | ProfileSetEndpoint        | async     | profile_set                   |
         | ProfileCommandEndpoint    | async     | profile_command               |
+        | ProfileGetInfoEndpoint    | async     | profile_get_info              |
+        | ProfileSetInfoEndpoint    | async     | profile_set_info              |
         | SessionGetStatusEndpoint  | async     | session_get_status            |
         | SessionGetIdEndpoint      | async     | session_get_id                |
         | SessionSetIdEndpoint      | async     | session_set_id                |
+        | SessionGetMetadataEndpoint | async    | session_get_metadata          |
+        | SessionSetMetadataEndpoint | async    | session_set_metadata          |
         | SessionStartEndpoint      | async     | session_start                 |
         | SessionStopEndpoint       | async     | session_stop                  |
+        | SessionGetMotionEndpoint  | async     | session_get_motion            |
+        | SessionSetMotionEndpoint  | async     | session_set_motion            |
         | DfuBeginEndpoint          | async     | dfu_begin                     |
         | DfuWriteEndpoint          | async     | dfu_write                     |
         | DfuFinishEndpoint         | async     | dfu_finish                    |
         | DfuAbortEndpoint          | async     | dfu_abort                     |
         | DfuStatusEndpoint         | async     | dfu_status                    |
+        | TimeGetEndpoint           | async     | time_get                      |
+        | TimeSetEndpoint           | async     | time_set                      |
+        | TimeExchangeEndpoint      | async     | time_exchange                 |
+        | TimeSampleEndpoint        | async     | time_sample                   |
+        | StreamConfigEndpoint      | async     | stream_set_config             |
+        | StreamGetConfigEndpoint   | async     | stream_get_config             |
+        | StreamGetCodecEndpoint    | async     | stream_get_codec              |
+        | StreamSetCodecEndpoint    | async     | stream_set_codec              |
+        | FsReadBeginEndpoint       | async     | fs_read_begin                 |
+        | FsReadChunkEndpoint       | async     | fs_read_chunk                 |
+        | FsReadFinishEndpoint      | async     | fs_read_finish                |
+        | FsDeleteEndpoint          | async     | fs_delete                     |
+        | StorageStatusEndpoint     | async     | storage_get_status            |
+        | StorageClearEndpoint      | async     | storage_clear                 |
+        | HapticPlayEndpoint        | async     | haptic_play                   |
+        | HapticStopEndpoint        | async     | haptic_stop                   |
+        | HapticPlayCueEndpoint     | async     | haptic_play_cue               |
+        | HapticGetConfigEndpoint   | async     | haptic_get_config             |
+        | HapticSetConfigEndpoint   | async     | haptic_set_config             |
+        | LedSetEndpoint            | async     | led_set                       |
+        | LedGetConfigEndpoint      | async     | led_get_config                |
+        | LedSetConfigEndpoint      | async     | led_set_config                |
+        | EventMarkerEndpoint       | spawn     | event_marker_handler          |
+        | LogStartEndpoint          | spawn     | log_start_handler             |
+        | LogStopEndpoint           | async     | log_stop_handler              |
+        | LogGetLevelEndpoint       | async     | log_get_level                 |
+        | LogSetLevelEndpoint       | async     | log_set_level                 |
+        | LogDumpEndpoint           | async     | log_dump                      |
+        | EventLogDumpEndpoint      | async     | event_log_dump                |
     };
     topics_in: {
         list: TOPICS_IN_LIST;
@@ -121,11 +217,14 @@ impl SpawnContext for Context {
 }
 
 // USB configuration
-fn usb_config() -> Config<'static> {
+fn usb_config(
+    product: &'static str,
+    serial_number: &'static str,
+) -> Config<'static> {
     let mut config = Config::new(0x16c0, 0x27DD);
     config.manufacturer = Some("JHUAPL");
-    config.product = Some("dc-mini");
-    config.serial_number = Some("12345678");
+    config.product = Some(product);
+    config.serial_number = Some(serial_number);
 
     // Required for windows compatibility.
     // https://developer.nordicsemi.com/nRF_Connect_SDK/doc/1.9.1/kconfig/CONFIG_CDC_ACM_IAD.html#help
@@ -143,17 +242,43 @@ pub async fn usb_task(
     usbd: UsbDriverBuilder,
     app_context: &'static Mutex<CriticalSectionRawMutex, AppContext>,
     dfu_resources: &'static crate::tasks::dfu::DfuResources,
+    sd: &'static Mutex<CriticalSectionRawMutex, SdCardResources>,
 ) {
-    let context = Context { app: app_context, dfu: dfu_resources };
+    let context = Context { app: app_context, dfu: dfu_resources, sd };
     let dispatcher = DcMiniUsbApp::new(context, spawner.into());
     let vkk = dispatcher.min_key_len();
 
     let driver = usbd.init();
     let pbufs = PBUFS.take();
-    let config = usb_config();
+    // Descriptors are built once, so a new nickname or mic rate shows up
+    // after a reset.
+    let product = {
+        let mut app_ctx = app_context.lock().await;
+        PRODUCT.init(crate::identity::display_name(
+            app_ctx.profile_manager.get_nickname().await,
+        ))
+    };
+    #[cfg(feature = "usb-audio")]
+    let mic_rate = {
+        let mut app_ctx = app_context.lock().await;
+        let mic_config = app_ctx.profile_manager.get_mic_config().await;
+        mic_config.cloned().unwrap_or_default().sample_rate
+    };
+    let serial_number = SERIAL_NUMBER.init(crate::identity::serial_number());
+    let config = usb_config(product.as_str(), serial_number.as_str());
 
-    let (mut device, tx_impl, rx_impl) =
-        STORAGE.init(driver, config, pbufs.tx_buf.as_mut_slice(), 64);
+    let (mut builder, tx_impl, rx_impl) = STORAGE.init_without_build(
+        driver,
+        config,
+        pbufs.tx_buf.as_mut_slice(),
+        64,
+    );
+    #[cfg(feature = "usb-shell")]
+    let mut shell = shell::Shell::new(&mut builder, app_context);
+    #[cfg(feature = "usb-audio")]
+    let mut audio =
+        audio::UsbMicrophone::new(&mut builder, mic_rate, app_context);
+    let mut device = builder.build();
 
     let mut server: AppServer = Server::new(
         tx_impl,
@@ -163,6 +288,20 @@ pub async fn usb_task(
         vkk,
     );
 
+    let storage_fut = storage_status_publisher(server.sender());
+    let fault_fut = fault_publisher(server.sender());
+    let battery_fut = battery_publisher(server.sender());
+    let self_test_fut = self_test_publisher(server.sender());
+    // Both idle unless built in.
+    let shell_fut = async {
+        #[cfg(feature = "usb-shell")]
+        shell.run().await;
+    };
+    let audio_fut = async {
+        #[cfg(feature = "usb-audio")]
+        audio.run().await;
+    };
+
     let server_fut = async {
         // Need to allow time for the USB driver to intialize prior to running the postcard server.
         Timer::after(Duration::from_secs(2)).await;
@@ -170,6 +309,8 @@ pub async fn usb_task(
         server.run().await;
     };
 
-    let _ = join(server_fut, device.run()).await;
+    let publishers = join4(storage_fut, fault_fut, battery_fut, self_test_fut);
+    let classes = join(shell_fut, audio_fut);
+    let _ = join4(server_fut, device.run(), publishers, classes).await;
     warn!("Exiting usb_task!!");
 }
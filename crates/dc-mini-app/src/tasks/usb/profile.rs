@@ -1,5 +1,7 @@
 use crate::prelude::*;
-use dc_mini_icd::{ProfileCommand, MAX_PROFILES};
+use dc_mini_icd::{
+    ProfileCommand, ProfileInfo, ProfileInfoUpdate, MAX_PROFILES,
+};
 use postcard_rpc::header::VarHeader;
 
 pub async fn profile_get(
@@ -21,8 +23,7 @@ pub async fn profile_set(
         return false;
     }
     let mut app_ctx = context.app.lock().await;
-    unwrap!(app_ctx.profile_manager.set_current_profile(req).await);
-    true
+    app_ctx.switch_profile(req).await
 }
 
 pub async fn profile_command(
@@ -30,32 +31,37 @@ pub async fn profile_command(
     _header: VarHeader,
     req: ProfileCommand,
 ) -> bool {
+    let mut app_ctx = context.app.lock().await;
+    app_ctx.apply_profile_command(req).await;
+    true
+}
+
+pub async fn profile_get_info(
+    context: &mut super::Context,
+    _header: VarHeader,
+    req: u8,
+) -> ProfileInfo {
+    let mut app_ctx = context.app.lock().await;
+    app_ctx.profile_manager.get_profile_info(req).await
+}
+
+pub async fn profile_set_info(
+    context: &mut super::Context,
+    _header: VarHeader,
+    req: ProfileInfoUpdate,
+) -> bool {
+    if req.profile > MAX_PROFILES
+        || req.info.name.chars().any(|c| c.is_control())
+    {
+        return false;
+    }
+    let mut app_ctx = context.app.lock().await;
+    match app_ctx.profile_manager.set_profile_info(req.profile, req.info).await
     {
-        let mut app_ctx = context.app.lock().await;
-        match req {
-            ProfileCommand::Reset => {
-                unwrap!(app_ctx.profile_manager.set_current_profile(0).await);
-            }
-            ProfileCommand::Next => {
-                let current =
-                    app_ctx.profile_manager.get_current_profile().await;
-                let next =
-                    if current >= MAX_PROFILES { 0 } else { current + 1 };
-                unwrap!(
-                    app_ctx.profile_manager.set_current_profile(next).await
-                );
-            }
-            ProfileCommand::Previous => {
-                let current =
-                    app_ctx.profile_manager.get_current_profile().await;
-                let prev =
-                    if current == 0 { MAX_PROFILES } else { current - 1 };
-                unwrap!(
-                    app_ctx.profile_manager.set_current_profile(prev).await
-                );
-            }
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Failed to save profile info: {:?}", e);
+            false
         }
     }
-
-    true
 }
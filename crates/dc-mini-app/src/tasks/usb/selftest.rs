@@ -0,0 +1,28 @@
+use crate::prelude::*;
+use crate::selftest::SELF_TEST_SIG;
+use postcard_rpc::{header::VarHeader, server::Sender};
+
+/// Queues a self-test and returns at once; the report follows on
+/// [`SelfTestTopic`] when the orchestrator has run every check.
+pub async fn self_test_run(
+    context: &mut super::Context,
+    _header: VarHeader,
+    _rqst: (),
+) {
+    let ctx = context.app.lock().await;
+    ctx.event_sender.send(Event::SelfTest).await;
+}
+
+/// Publishes each self-test report, including the one from boot, for as
+/// long as USB is up.
+pub async fn self_test_publisher(sender: Sender<super::AppTx>) {
+    let mut seq: u8 = 0;
+    loop {
+        let report = SELF_TEST_SIG.wait().await;
+        if sender.publish::<SelfTestTopic>(seq.into(), &report).await.is_err()
+        {
+            warn!("[usb] Failed to publish self-test report");
+        }
+        seq = seq.wrapping_add(1);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::prelude::*;
-use dc_mini_icd::SessionId;
+use dc_mini_icd::{MotionTriggerConfig, SessionId, SessionMetadata};
 use heapless::String;
 use postcard_rpc::header::VarHeader;
 
@@ -36,6 +36,29 @@ pub async fn session_set_id(
     true
 }
 
+pub async fn session_get_metadata(
+    context: &mut Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> SessionMetadata {
+    let mut app_ctx = context.app.lock().await;
+    app_ctx
+        .profile_manager
+        .get_session_metadata()
+        .await
+        .cloned()
+        .unwrap_or_default()
+}
+
+pub async fn session_set_metadata(
+    context: &mut Context,
+    _header: VarHeader,
+    rqst: SessionMetadata,
+) -> bool {
+    let mut app_ctx = context.app.lock().await;
+    app_ctx.profile_manager.set_session_metadata(rqst).await.is_ok()
+}
+
 pub async fn session_start(
     context: &mut Context,
     _header: VarHeader,
@@ -55,3 +78,32 @@ pub async fn session_stop(
     app_ctx.event_sender.send(SessionEvent::StopRecording.into()).await;
     true
 }
+
+pub async fn session_get_motion(
+    context: &mut Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> MotionTriggerConfig {
+    let mut app_ctx = context.app.lock().await;
+    app_ctx
+        .profile_manager
+        .get_motion_trigger()
+        .await
+        .copied()
+        .unwrap_or_default()
+}
+
+pub async fn session_set_motion(
+    context: &mut Context,
+    _header: VarHeader,
+    rqst: MotionTriggerConfig,
+) -> bool {
+    let saved = {
+        let mut app_ctx = context.app.lock().await;
+        app_ctx.profile_manager.set_motion_trigger(rqst).await.is_ok()
+    };
+    if saved {
+        motion_trigger_changed();
+    }
+    saved
+}
@@ -0,0 +1,303 @@
+//! Text shell on a CDC-ACM interface next to the postcard-rpc one, for
+//! driving the device from a plain terminal where there are no host
+//! bindings.
+//!
+//! Input is echoed and run a line at a time; `help` lists the commands.
+
+use super::{AppDriver, MutexType};
+use crate::logging::{self, LOG_CH};
+use crate::prelude::*;
+use crate::status::SystemState;
+use crate::tasks::ads::{ADS_MEAS_CH, ADS_WATCH};
+use crate::tasks::apds::APDS_DATA_WATCH;
+use crate::tasks::imu::IMU_DATA_WATCH;
+use core::fmt::Write;
+use dc_mini_icd::LogRecord;
+use embassy_futures::select::{select, Either};
+use embassy_time::{with_timeout, Instant};
+use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
+use embassy_usb::driver::EndpointError;
+use embassy_usb::Builder;
+use static_cell::StaticCell;
+
+const MAX_PACKET: u16 = 64;
+const MAX_LINE: usize = 64;
+const OUTPUT_LEN: usize = 512;
+/// How long `peek ads` waits for a sample.
+const ADS_PEEK_TIMEOUT: Duration = Duration::from_secs(1);
+
+const PROMPT: &str = "dc-mini> ";
+const HELP: &str = "\
+help                       this text\r\n\
+status                     system state, battery and memory\r\n\
+log                        recent log records\r\n\
+log follow                 new log records, until a key is pressed\r\n\
+peek ads|imu|apds|battery  latest sensor reading\r\n\
+session start|stop|status  SD recording\r\n";
+
+type Output = heapless::String<OUTPUT_LEN>;
+
+static STATE: StaticCell<State<'static>> = StaticCell::new();
+
+pub struct Shell {
+    class: CdcAcmClass<'static, AppDriver>,
+    app: &'static Mutex<MutexType, AppContext>,
+}
+
+impl Shell {
+    /// Adds the shell's interface to the USB device being built.
+    pub fn new(
+        builder: &mut Builder<'static, AppDriver>,
+        app: &'static Mutex<MutexType, AppContext>,
+    ) -> Self {
+        let state = STATE.init(State::new());
+        Self { class: CdcAcmClass::new(builder, state, MAX_PACKET), app }
+    }
+
+    /// Serves one terminal after another.
+    pub async fn run(&mut self) -> ! {
+        loop {
+            self.class.wait_connection().await;
+            info!("[shell] terminal connected");
+            // Only fails once the terminal has gone away.
+            let _ = self.serve().await;
+        }
+    }
+
+    async fn serve(&mut self) -> Result<(), EndpointError> {
+        self.write(PROMPT).await?;
+        let mut line = heapless::String::<MAX_LINE>::new();
+        let mut packet = [0u8; MAX_PACKET as usize];
+        let mut last = 0u8;
+        loop {
+            let n = self.class.read_packet(&mut packet).await?;
+            for &byte in &packet[..n] {
+                match byte {
+                    // The LF of a CRLF line end.
+                    b'\n' if last == b'\r' => {}
+                    b'\r' | b'\n' => {
+                        self.write("\r\n").await?;
+                        if !line.trim().is_empty() {
+                            self.execute(line.trim()).await?;
+                        }
+                        line.clear();
+                        self.write(PROMPT).await?;
+                    }
+                    0x08 | 0x7f => {
+                        if line.pop().is_some() {
+                            self.write("\x08 \x08").await?;
+                        }
+                    }
+                    b' '..=b'~' => {
+                        if line.push(byte as char).is_ok() {
+                            self.class.write_packet(&[byte]).await?;
+                        }
+                    }
+                    _ => {}
+                }
+                last = byte;
+            }
+        }
+    }
+
+    /// Sends `text` in packets, ending with a short one so the host does
+    /// not wait for more.
+    async fn write(&mut self, text: &str) -> Result<(), EndpointError> {
+        let mut last_len = 0;
+        for chunk in text.as_bytes().chunks(MAX_PACKET as usize) {
+            self.class.write_packet(chunk).await?;
+            last_len = chunk.len();
+        }
+        if last_len == MAX_PACKET as usize {
+            self.class.write_packet(&[]).await?;
+        }
+        Ok(())
+    }
+
+    async fn execute(&mut self, line: &str) -> Result<(), EndpointError> {
+        let mut words = line.split_whitespace();
+        let mut out = Output::new();
+        match (words.next(), words.next()) {
+            (Some("help"), None) => {
+                let _ = out.push_str(HELP);
+            }
+            (Some("status"), None) => self.status(&mut out).await,
+            (Some("log"), None) => return self.log_history().await,
+            (Some("log"), Some("follow")) => return self.log_follow().await,
+            (Some("peek"), Some(sensor)) => peek(sensor, &mut out).await,
+            (Some("session"), Some(command)) => {
+                self.session(command, &mut out).await
+            }
+            _ => {
+                let _ = write!(out, "unknown command, try `help`\r\n");
+            }
+        }
+        self.write(&out).await
+    }
+
+    async fn status(&self, out: &mut Output) {
+        let uptime = Instant::now();
+        let _ = write!(
+            out,
+            "state    {:?}\r\n\
+             uptime   {} s\r\n",
+            SystemState::current(),
+            uptime.as_secs(),
+        );
+        let _ = match latest_battery_status().await {
+            Some(battery) => write!(
+                out,
+                "battery  {} mV, {} %, {:?}\r\n",
+                battery.voltage_mv, battery.state_of_charge, battery.charging,
+            ),
+            None => write!(out, "battery  PMIC not responding\r\n"),
+        };
+        let _ = write!(
+            out,
+            "session  {}\r\n\
+             clock    {}\r\n\
+             heap     {} of {} bytes\r\n",
+            if session_active() { "recording" } else { "idle" },
+            match CLOCK.epoch_micros(uptime.as_micros()) {
+                Some(_) => "set",
+                None => "not set",
+            },
+            crate::ALLOCATOR.usage(),
+            crate::HEAP_SIZE,
+        );
+    }
+
+    async fn log_history(&mut self) -> Result<(), EndpointError> {
+        let mut from = 0;
+        loop {
+            let dump = logging::dump(from);
+            if dump.records.is_empty() {
+                return Ok(());
+            }
+            for record in dump.records.iter() {
+                self.write(&format_record(record)).await?;
+            }
+            from = dump.first + dump.records.len() as u32;
+        }
+    }
+
+    async fn log_follow(&mut self) -> Result<(), EndpointError> {
+        let Ok(mut sub) = LOG_CH.dyn_subscriber() else {
+            return self.write("log stream busy\r\n").await;
+        };
+        let mut packet = [0u8; MAX_PACKET as usize];
+        loop {
+            let next = select(
+                sub.next_message_pure(),
+                self.class.read_packet(&mut packet),
+            )
+            .await;
+            match next {
+                Either::First(record) => {
+                    self.write(&format_record(&record)).await?
+                }
+                Either::Second(read) => return read.map(|_| ()),
+            }
+        }
+    }
+
+    async fn session(&self, command: &str, out: &mut Output) {
+        let event = match command {
+            "start" => SessionEvent::StartRecording,
+            "stop" => SessionEvent::StopRecording,
+            "status" => {
+                let status =
+                    if session_active() { "recording" } else { "idle" };
+                let _ = write!(out, "{}\r\n", status);
+                return;
+            }
+            _ => {
+                let _ = write!(out, "usage: session start|stop|status\r\n");
+                return;
+            }
+        };
+        let ctx = self.app.lock().await;
+        ctx.event_sender.send(event.into()).await;
+        let _ = write!(out, "ok\r\n");
+    }
+}
+
+fn format_record(record: &LogRecord) -> Output {
+    let mut out = Output::new();
+    let _ = write!(
+        out,
+        "[{}.{:06}] {:?} {}\r\n",
+        record.ts / 1_000_000,
+        record.ts % 1_000_000,
+        record.level,
+        record.message
+    );
+    out
+}
+
+async fn peek(sensor: &str, out: &mut Output) {
+    match sensor {
+        "ads" => {
+            if ADS_WATCH.try_get() != Some(true) {
+                let _ = write!(out, "ADS not streaming\r\n");
+                return;
+            }
+            let Ok(mut sub) = ADS_MEAS_CH.dyn_subscriber() else {
+                let _ = write!(out, "ADS stream busy\r\n");
+                return;
+            };
+            let Ok(sample) =
+                with_timeout(ADS_PEEK_TIMEOUT, sub.next_message_pure()).await
+            else {
+                let _ = write!(out, "no ADS sample\r\n");
+                return;
+            };
+            for (i, ads) in sample.iter().enumerate() {
+                let _ = write!(out, "ads{} {:?}\r\n", i, ads.data.as_slice());
+            }
+        }
+        "imu" => match IMU_DATA_WATCH.try_get() {
+            Some(imu) => {
+                let _ = write!(
+                    out,
+                    "accel  {:.3} {:.3} {:.3} g\r\n\
+                     gyro   {:.2} {:.2} {:.2} dps\r\n\
+                     temp   {:.1} C\r\n",
+                    imu.accel_x,
+                    imu.accel_y,
+                    imu.accel_z,
+                    imu.gyro_x,
+                    imu.gyro_y,
+                    imu.gyro_z,
+                    imu.temp,
+                );
+            }
+            None => {
+                let _ = write!(out, "no IMU reading\r\n");
+            }
+        },
+        "apds" => match APDS_DATA_WATCH.try_get() {
+            Some(apds) => {
+                let _ = write!(
+                    out,
+                    "lux {:.1}, cct {} K, rgb+ir {} {} {} {}\r\n",
+                    apds.lux,
+                    apds.cct,
+                    apds.red,
+                    apds.green,
+                    apds.blue,
+                    apds.ir,
+                );
+            }
+            None => {
+                let _ = write!(out, "no light sensor reading\r\n");
+            }
+        },
+        "battery" => {
+            let _ = write!(out, "{:?}\r\n", latest_battery_status().await);
+        }
+        _ => {
+            let _ = write!(out, "usage: peek ads|imu|apds|battery\r\n");
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use crate::prelude::*;
+use crate::{codec, decimation};
+use dc_mini_icd::{AdsCodec, StreamConfig, StreamKind};
+use postcard_rpc::header::VarHeader;
+
+pub async fn stream_set_config(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    rqst: StreamConfig,
+) -> bool {
+    if !decimation::set_config(&rqst) {
+        warn!("Rejecting invalid stream decimation config");
+        return false;
+    }
+    info!("Stream {:?} decimated by {}", rqst.stream, rqst.factor);
+    true
+}
+
+pub async fn stream_get_config(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    rqst: StreamKind,
+) -> StreamConfig {
+    decimation::config(rqst)
+}
+
+pub async fn stream_get_codec(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    _rqst: (),
+) -> AdsCodec {
+    codec::ads_codec()
+}
+
+pub async fn stream_set_codec(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    rqst: AdsCodec,
+) {
+    info!("ADS stream codec {:?}", rqst);
+    codec::set_ads_codec(rqst);
+}
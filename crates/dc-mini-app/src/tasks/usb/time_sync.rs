@@ -0,0 +1,66 @@
+use crate::prelude::*;
+use dc_mini_icd::{TimeExchange, TimeSample, TimeStatus, TimeSync};
+use embassy_time::Instant;
+use postcard_rpc::header::VarHeader;
+
+fn time_status() -> TimeStatus {
+    let uptime_us = Instant::now().as_micros();
+    TimeStatus {
+        uptime_us,
+        epoch_us: CLOCK.epoch_micros(uptime_us).map(|us| us as u64),
+        drift_ppb: CLOCK.drift_ppb(),
+    }
+}
+
+pub async fn time_get(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    _req: (),
+) -> TimeStatus {
+    time_status()
+}
+
+pub async fn time_set(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    req: TimeSync,
+) -> TimeStatus {
+    // Assume a symmetric link: the request took half the round trip.
+    let now_epoch_us = req.host_epoch_us + req.round_trip_us as u64 / 2;
+    let boot_epoch_us =
+        now_epoch_us as i64 - Instant::now().as_micros() as i64;
+    CLOCK.set_boot_epoch_micros(boot_epoch_us);
+    info!(
+        "[usb-time] Clock set, boot epoch {}us (rtt {}us)",
+        boot_epoch_us, req.round_trip_us
+    );
+    time_status()
+}
+
+pub async fn time_exchange(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    host_tx_us: u64,
+) -> TimeExchange {
+    let device_rx_us = Instant::now().as_micros();
+    TimeExchange {
+        host_tx_us,
+        device_rx_us,
+        device_tx_us: Instant::now().as_micros(),
+    }
+}
+
+pub async fn time_sample(
+    _context: &mut super::Context,
+    _header: VarHeader,
+    req: TimeSample,
+) -> TimeStatus {
+    let error_us = CLOCK.apply_exchange(&req);
+    info!(
+        "[usb-time] Clock synced, delay {}us, error {}us, drift {}ppb",
+        req.delay_us(),
+        error_us,
+        CLOCK.drift_ppb()
+    );
+    time_status()
+}
@@ -124,6 +124,7 @@ macro_rules! println {
 macro_rules! trace {
     ($s:literal $(, $x:expr)* $(,)?) => {
         {
+            $crate::logging::record($crate::logging::LogLevel::Trace, $s);
             #[cfg(feature = "defmt")]
             ::defmt::trace!($s $(, $x)*);
             #[cfg(not(feature="defmt"))]
@@ -136,6 +137,7 @@ macro_rules! trace {
 macro_rules! debug {
     ($s:literal $(, $x:expr)* $(,)?) => {
         {
+            $crate::logging::record($crate::logging::LogLevel::Debug, $s);
             #[cfg(feature = "defmt")]
             ::defmt::debug!($s $(, $x)*);
             #[cfg(not(feature="defmt"))]
@@ -148,6 +150,7 @@ macro_rules! debug {
 macro_rules! info {
     ($s:literal $(, $x:expr)* $(,)?) => {
         {
+            $crate::logging::record($crate::logging::LogLevel::Info, $s);
             #[cfg(feature = "defmt")]
             ::defmt::info!($s $(, $x)*);
             #[cfg(not(feature="defmt"))]
@@ -160,6 +163,7 @@ macro_rules! info {
 macro_rules! warn {
     ($s:literal $(, $x:expr)* $(,)?) => {
         {
+            $crate::logging::record($crate::logging::LogLevel::Warn, $s);
             #[cfg(feature = "defmt")]
             ::defmt::warn!($s $(, $x)*);
             #[cfg(not(feature="defmt"))]
@@ -172,6 +176,7 @@ macro_rules! warn {
 macro_rules! error {
     ($s:literal $(, $x:expr)* $(,)?) => {
         {
+            $crate::logging::record($crate::logging::LogLevel::Error, $s);
             #[cfg(feature = "defmt")]
             ::defmt::error!($s $(, $x)*);
             #[cfg(not(feature="defmt"))]
@@ -29,9 +29,40 @@ fn main() {
     // `memory.x` is changed.
     println!("cargo:rerun-if-changed=memory.x");
 
+    embed_dfu_key(out);
+
     println!("cargo:rustc-link-arg-bins=--nmagic");
     println!("cargo:rustc-link-arg-bins=-Tlink.x");
     if env::var("CARGO_FEATURE_DEFMT").is_ok() {
         println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
     }
 }
+
+/// Stages the public key DFU images must be signed with. The key is the
+/// raw 32-byte Ed25519 public key at `DC_MINI_DFU_PUBLIC_KEY` (see the
+/// `dfu-sign` host tool). Without one the key slot stays erased and the
+/// application refuses every update.
+fn embed_dfu_key(out: &PathBuf) {
+    println!("cargo:rerun-if-env-changed=DC_MINI_DFU_PUBLIC_KEY");
+    let key = match env::var_os("DC_MINI_DFU_PUBLIC_KEY") {
+        Some(path) => {
+            println!("cargo:rerun-if-changed={}", path.to_string_lossy());
+            let key = std::fs::read(&path).unwrap_or_else(|e| {
+                panic!("Failed to read DFU public key {:?}: {}", path, e)
+            });
+            assert_eq!(key.len(), 32, "DFU public key must be 32 bytes");
+            key
+        }
+        None => {
+            println!(
+                "cargo:warning=DC_MINI_DFU_PUBLIC_KEY not set, firmware \
+                 updates will be refused"
+            );
+            vec![0xFF; 32]
+        }
+    };
+    File::create(out.join("dfu_public_key.bin"))
+        .unwrap()
+        .write_all(&key)
+        .unwrap();
+}
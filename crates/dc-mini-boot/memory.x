@@ -1,17 +1,31 @@
 MEMORY
 {
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
-  FLASH                             : ORIGIN = 0x00000000, LENGTH = 24K
-  BOOTLOADER_STATE                  : ORIGIN = 0x00006000, LENGTH = 4K
-  ACTIVE                            : ORIGIN = 0x00007000, LENGTH = 988K
+  /* Sized for recovery mode (USB serial, SD card, LED) and the Ed25519
+     check. The link fails if the bootloader outgrows it. Keep BOOTLOADER,
+     BOOTLOADER_STATE and the application's FLASH in sync between both
+     images. */
+  FLASH                             : ORIGIN = 0x00000000, LENGTH = 96K - 256
+  DFU_KEY                           : ORIGIN = 0x00017F00, LENGTH = 256
+  BOOTLOADER_STATE                  : ORIGIN = 0x00018000, LENGTH = 4K
+  ACTIVE                            : ORIGIN = 0x00019000, LENGTH = 908K
+  /* The application's crash page. Outside ACTIVE so a swap or a rollback
+     cannot move or discard the report. Keep in sync between both images. */
+  CRASH                             : ORIGIN = 0x000fc000, LENGTH = 4K
+  /* Outside ACTIVE so swaps leave it alone, see `dc_mini_bsp::BootMetrics`.
+     Keep in sync between both images. */
+  BOOT_METRICS                      : ORIGIN = 0x000fd000, LENGTH = 4K
   STORAGE                           : ORIGIN = 0x000fe000, LENGTH = 8K
   RAM                         (rwx) : ORIGIN = 0x20000000, LENGTH = 256K
 
-  /* DFU is stored in external flash */
+  /* DFU is stored in external flash, followed by the data partition.
+     Keep in sync with the layout in `dc_mini_bsp::ExternalFlashResources`. */
   DFU                               : ORIGIN = 0x00000000, LENGTH = 992K
   EXTERNAL_STORAGE                  : ORIGIN = 0x000f8000, LENGTH = 1056K
 }
 
+__boot_metrics_start = ORIGIN(BOOT_METRICS);
+
 __bootloader_state_start = ORIGIN(BOOTLOADER_STATE);
 __bootloader_state_end = ORIGIN(BOOTLOADER_STATE) + LENGTH(BOOTLOADER_STATE);
 
@@ -20,3 +34,13 @@ __bootloader_active_end = ORIGIN(ACTIVE) + LENGTH(ACTIVE);
 
 __bootloader_dfu_start = ORIGIN(DFU);
 __bootloader_dfu_end = ORIGIN(DFU) + LENGTH(DFU);
+
+/* Public key DFU images must be signed with, read by the application */
+SECTIONS
+{
+  .dfu_key :
+  {
+    KEEP(*(.dfu_key));
+  } > DFU_KEY
+}
+INSERT AFTER .rodata;
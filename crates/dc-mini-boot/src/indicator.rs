@@ -0,0 +1,23 @@
+//! LED colors for bootloader work that takes long enough to pass for a
+//! dead device. A swap rewrites both partitions and can run for tens of
+//! seconds.
+
+use dc_mini_bsp::StatusLed;
+use embassy_futures::block_on;
+use smart_leds_trait::{RGB8, SmartLedsWriteAsync};
+
+/// Checking the signature of a staged update.
+pub const VERIFYING: RGB8 = RGB8 { r: 0x00, g: 0x20, b: 0x20 };
+/// Swapping a verified update in.
+pub const SWAPPING: RGB8 = RGB8 { r: 0x20, g: 0x00, b: 0x20 };
+/// Swapping the previous image back after an update failed to confirm.
+pub const ROLLING_BACK: RGB8 = RGB8 { r: 0x30, g: 0x00, b: 0x00 };
+/// Waiting for an update in recovery mode.
+pub const RECOVERY: RGB8 = RGB8 { r: 0x30, g: 0x10, b: 0x00 };
+pub const OFF: RGB8 = RGB8 { r: 0, g: 0, b: 0 };
+
+/// Shows `color` until the next call. The LED latches it, so it stays lit
+/// while the CPU is busy with flash.
+pub fn show(led: &mut StatusLed<'_>, color: RGB8) {
+    let _ = block_on(led.write([color]));
+}
@@ -1,16 +1,38 @@
 #![no_std]
 #![no_main]
 
+mod indicator;
+mod recovery;
+mod sd_update;
+mod usb_dfu;
+mod verify;
+
 use core::cell::RefCell;
 
 use cortex_m_rt::{entry, exception};
 use dc_mini_bsp::*;
 #[cfg(feature = "defmt")]
 use defmt_rtt as _;
+use embassy_boot::{AlignedBuffer, BlockingFirmwareState, State};
 use embassy_boot_nrf::*;
+use embassy_futures::block_on;
+use embassy_nrf::gpio::{Level, Output, OutputDrive};
 use embassy_nrf::nvmc::Nvmc;
 use embassy_nrf::wdt::{self, HaltConfig, SleepConfig};
 use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+/// Value of erased state flash, as embassy-boot expects it.
+const STATE_ERASE_VALUE: u8 = 0xFF;
+
+/// Public key DFU images must be signed with. The application reads it
+/// from here before marking an update, so replacing the application
+/// cannot change it.
+#[used]
+#[unsafe(link_section = ".dfu_key")]
+static DFU_PUBLIC_KEY: [u8; 32] =
+    *include_bytes!(concat!(env!("OUT_DIR"), "/dfu_public_key.bin"));
 
 #[entry]
 fn main() -> ! {
@@ -22,6 +44,27 @@ fn main() -> ! {
     //     cortex_m::asm::nop();
     // }
 
+    let mut led = board.status_led.into_led();
+
+    // Before the watchdog starts: recovery waits for the user, and erasing
+    // the DFU partition takes longer than the timeout.
+    if recovery::requested(board.pwrbtn.reborrow()) {
+        let nvmc = Mutex::new(RefCell::new(Nvmc::new(board.nvmc.reborrow())));
+        let qspi = Mutex::new(RefCell::new(board.external_flash.configure()));
+        let _usbsel =
+            Output::new(board.usbsel, Level::High, OutputDrive::Standard);
+        block_on(recovery::run(
+            &mut led,
+            board.usb,
+            &mut board.sd_card_resources,
+            &qspi,
+            &nvmc,
+        ));
+        // USB is still running; the reset hands the staged update to a
+        // clean boot.
+        cortex_m::peripheral::SCB::sys_reset();
+    }
+
     let mut wdt_config = wdt::Config::default();
     wdt_config.timeout_ticks = 32768 * 5; // timeout seconds
     wdt_config.action_during_sleep = SleepConfig::RUN;
@@ -34,6 +77,22 @@ fn main() -> ! {
     let external_flash = board.external_flash.configure();
     let external_flash = Mutex::new(RefCell::new(external_flash));
 
+    let status = check_update(&mut led, &external_flash, &flash);
+    #[cfg(feature = "defmt")]
+    defmt::info!("Boot status: {:?}", status);
+    let _metrics = flash.lock(|flash| {
+        BootMetrics::record_boot(&mut *flash.borrow_mut(), status)
+    });
+    #[cfg(feature = "defmt")]
+    defmt::info!("Boot metrics: {:?}", _metrics);
+    match status {
+        BootStatus::Updated => indicator::show(&mut led, indicator::SWAPPING),
+        BootStatus::RolledBack => {
+            indicator::show(&mut led, indicator::ROLLING_BACK)
+        }
+        _ => {}
+    }
+
     let config = BootLoaderConfig::from_linkerfile_blocking(
         &flash,
         &external_flash,
@@ -41,6 +100,11 @@ fn main() -> ! {
     );
     let active_offset = config.active.offset();
     let bl: BootLoader = BootLoader::prepare(config);
+    if status != BootStatus::Booted {
+        indicator::show(&mut led, indicator::OFF);
+    }
+    // `load` does not return, so nothing would drop the LED's PWM.
+    drop(led);
 
     #[cfg(feature = "defmt")]
     defmt::info!("Loading Application!");
@@ -48,6 +112,98 @@ fn main() -> ! {
     unsafe { bl.load(active_offset) }
 }
 
+/// Verifies a staged update before `BootLoader::prepare` swaps it in.
+/// An update that fails is marked booted, which drops the swap request
+/// and keeps the current image.
+///
+/// Once embassy-boot has written any swap progress, the DFU partition no
+/// longer holds the staged image as it was downloaded, so nothing is
+/// verified or marked and `prepare` carries on from the progress: it
+/// finishes a swap cut short by a power loss, and swaps the old image back
+/// after an update that never confirmed itself.
+fn check_update<DFU: NorFlash, STATE: NorFlash>(
+    led: &mut StatusLed<'_>,
+    dfu_flash: &Mutex<NoopRawMutex, RefCell<DFU>>,
+    state_flash: &Mutex<NoopRawMutex, RefCell<STATE>>,
+) -> BootStatus {
+    // The active partition shares the internal flash with the state.
+    let BootLoaderConfig { active, mut dfu, mut state } =
+        BootLoaderConfig::from_linkerfile_blocking(
+            state_flash,
+            dfu_flash,
+            state_flash,
+        );
+    // As in `BootLoader`, a page is the larger of the erase sizes.
+    let page_count =
+        active.capacity() / STATE::ERASE_SIZE.max(DFU::ERASE_SIZE);
+    // Internal flash reads do not fail; if one did, `prepare` would stop
+    // on it too, and nothing here may mark the image booted.
+    let progress =
+        swap_progress(&mut state, page_count).unwrap_or(SwapProgress::Swapped);
+
+    let mut aligned = [0u8; 4];
+    let mut state = BlockingFirmwareState::new(state, &mut aligned);
+    if !matches!(state.get_state(), Ok(State::Swap)) {
+        return BootStatus::Booted;
+    }
+    match progress {
+        SwapProgress::NotStarted => {}
+        SwapProgress::Partial => return BootStatus::Updated,
+        SwapProgress::Swapped => return BootStatus::RolledBack,
+    }
+    // Only ever verify a DFU partition no page has been swapped out of:
+    // a partly swapped one fails the check, and marking it booted would
+    // leave the active partition half copied.
+    indicator::show(led, indicator::VERIFYING);
+    match verify::verify_image(&mut dfu, &DFU_PUBLIC_KEY) {
+        Ok(()) => BootStatus::Updated,
+        Err(reason) => {
+            #[cfg(feature = "defmt")]
+            defmt::warn!("Refusing update: {:?}", reason);
+            let _ = state.mark_booted();
+            BootStatus::UpdateRejected(reason)
+        }
+    }
+}
+
+/// How far embassy-boot got swapping the staged update in.
+enum SwapProgress {
+    /// No page step written; the DFU partition is as downloaded.
+    NotStarted,
+    /// Cut short, e.g. by a power loss; `prepare` resumes the swap.
+    Partial,
+    /// Swapped in, which `prepare` answers with a revert.
+    Swapped,
+}
+
+/// Reads embassy-boot's persisted swap progress. Mirrors
+/// `BootLoader::is_swapped`: after the magic, the state partition holds a
+/// progress validity word, then one word per page step, and a swap takes
+/// two steps per page of the active partition.
+fn swap_progress<STATE: NorFlash>(
+    state: &mut STATE,
+    page_count: usize,
+) -> Result<SwapProgress, STATE::Error> {
+    let mut buf = AlignedBuffer([0u8; 32]);
+    let word = &mut buf.0[..STATE::WRITE_SIZE];
+    state.read(STATE::WRITE_SIZE as u32, word)?;
+    if word.iter().any(|&b| b != STATE_ERASE_VALUE) {
+        // Only a finished swap or revert invalidates the progress.
+        return Ok(SwapProgress::Swapped);
+    }
+    for step in 0..page_count * 2 {
+        state.read(((2 + step) * STATE::WRITE_SIZE) as u32, word)?;
+        if word.iter().any(|&b| b == STATE_ERASE_VALUE) {
+            return Ok(if step == 0 {
+                SwapProgress::NotStarted
+            } else {
+                SwapProgress::Partial
+            });
+        }
+    }
+    Ok(SwapProgress::Swapped)
+}
+
 #[unsafe(no_mangle)]
 #[cfg_attr(target_os = "none", unsafe(link_section = ".HardFault.user"))]
 unsafe extern "C" fn HardFault() {
@@ -0,0 +1,99 @@
+//! Recovery mode, for units whose application crashes before it can take
+//! an update.
+//!
+//! Holding the button through power-on keeps the application from
+//! starting. The LED blinks amber while the bootloader waits for an
+//! update from the SD card or over USB; once one is staged, the device
+//! resets into the usual signature check and swap. Anything else needs a
+//! reset to leave.
+
+use core::cell::RefCell;
+
+use crate::indicator::{OFF, RECOVERY};
+use crate::sd_update::{self, SdUpdateError};
+use crate::usb_dfu;
+use dc_mini_bsp::usb::UsbDriverBuilder;
+use dc_mini_bsp::{SdCardResources, StatusLed};
+use embassy_futures::select::select;
+use embassy_nrf::Peri;
+use embassy_nrf::gpio::{Input, Pin, Pull};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_time::{Duration, Instant, Timer, block_for};
+use embedded_storage::nor_flash::NorFlash;
+use smart_leds_trait::SmartLedsWriteAsync;
+
+/// How long the button must be held at reset to enter recovery. The
+/// button also wakes the device from System OFF, so a press that only
+/// powers it on must fall well short of this.
+const HOLD_TIME: Duration = Duration::from_secs(3);
+/// Half period of the recovery blink.
+const BLINK: Duration = Duration::from_millis(500);
+
+/// Whether the button (active low) is held for [`HOLD_TIME`] from now.
+pub fn requested(button: Peri<'_, impl Pin>) -> bool {
+    let button = Input::new(button, Pull::Up);
+    let start = Instant::now();
+    while start.elapsed() < HOLD_TIME {
+        if button.is_high() {
+            return false;
+        }
+        block_for(Duration::from_millis(10));
+    }
+    true
+}
+
+/// Blinks the recovery pattern and serves the USB DFU port until an
+/// update is staged. A card is tried once when it is inserted, so a bad
+/// image is not rewritten over and over.
+pub async fn run<DFU: NorFlash, STATE: NorFlash>(
+    led: &mut StatusLed<'_>,
+    usbd: UsbDriverBuilder,
+    sd: &mut SdCardResources,
+    dfu_flash: &Mutex<NoopRawMutex, RefCell<DFU>>,
+    state_flash: &Mutex<NoopRawMutex, RefCell<STATE>>,
+) {
+    #[cfg(feature = "defmt")]
+    defmt::warn!("Recovery mode, waiting for an update");
+    let usb = async {
+        let _size = usb_dfu::run(usbd, dfu_flash, state_flash).await;
+        #[cfg(feature = "defmt")]
+        defmt::info!("Staged {} bytes over USB", _size);
+    };
+    select(usb, sd_card(led, sd, dfu_flash, state_flash)).await;
+    let _ = led.write([OFF]).await;
+}
+
+/// Blinks until an update is staged from the SD card. Copying the image
+/// blocks, so USB stalls while a card is read.
+async fn sd_card<DFU: NorFlash, STATE: NorFlash>(
+    led: &mut StatusLed<'_>,
+    sd: &mut SdCardResources,
+    dfu_flash: &Mutex<NoopRawMutex, RefCell<DFU>>,
+    state_flash: &Mutex<NoopRawMutex, RefCell<STATE>>,
+) {
+    let mut card_tried = false;
+    loop {
+        if !sd.card_present() {
+            card_tried = false;
+        } else if !card_tried {
+            card_tried = true;
+            match sd_update::stage(sd, dfu_flash, state_flash) {
+                Ok(_size) => {
+                    #[cfg(feature = "defmt")]
+                    defmt::info!("Staged {} bytes from the SD card", _size);
+                    return;
+                }
+                Err(SdUpdateError::NoCard) => card_tried = false,
+                Err(_e) => {
+                    #[cfg(feature = "defmt")]
+                    defmt::warn!("SD update failed: {:?}", _e);
+                }
+            }
+        }
+        let _ = led.write([RECOVERY]).await;
+        Timer::after(BLINK).await;
+        let _ = led.write([OFF]).await;
+        Timer::after(BLINK).await;
+    }
+}
@@ -0,0 +1,99 @@
+//! Field update from the SD card.
+//!
+//! In recovery mode, a signed image named [`SD_UPDATE_FILE`] in the card's
+//! root directory is copied into the DFU partition and marked updated. The
+//! usual signature check and swap follow, so this needs neither a probe
+//! nor a working application.
+
+use core::cell::RefCell;
+
+use dc_mini_bsp::SdCardResources;
+use dc_mini_icd::SD_UPDATE_FILE;
+use embassy_boot::{BlockingFirmwareUpdater, FirmwareUpdaterConfig};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embedded_sdmmc::{Mode, TimeSource, Timestamp, VolumeIdx, VolumeManager};
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+/// Bytes written to the DFU partition at a time, one erase sector.
+const CHUNK: usize = 4096;
+
+/// Why an SD update was not staged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SdUpdateError {
+    NoCard,
+    /// The card could not be mounted or has no update file.
+    NoFile,
+    /// The file does not fit in the DFU partition.
+    TooLarge,
+    Read,
+    Flash,
+}
+
+/// Files are only read, so timestamps are never written.
+struct NoClock;
+
+impl TimeSource for NoClock {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 0,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+/// Copies [`SD_UPDATE_FILE`] from the card into the DFU partition and
+/// marks it updated. Returns the image size.
+pub fn stage<DFU: NorFlash, STATE: NorFlash>(
+    sd: &mut SdCardResources,
+    dfu_flash: &Mutex<NoopRawMutex, RefCell<DFU>>,
+    state_flash: &Mutex<NoopRawMutex, RefCell<STATE>>,
+) -> Result<u32, SdUpdateError> {
+    if !sd.card_present() {
+        return Err(SdUpdateError::NoCard);
+    }
+    let volume_mgr = VolumeManager::new(sd.get_card(), NoClock);
+    let volume = volume_mgr
+        .open_volume(VolumeIdx(0))
+        .map_err(|_| SdUpdateError::NoFile)?;
+    let root_dir =
+        volume.open_root_dir().map_err(|_| SdUpdateError::NoFile)?;
+    let file = root_dir
+        .open_file_in_dir(SD_UPDATE_FILE, Mode::ReadOnly)
+        .map_err(|_| SdUpdateError::NoFile)?;
+
+    let config = FirmwareUpdaterConfig::from_linkerfile_blocking(
+        dfu_flash,
+        state_flash,
+    );
+    if file.length() as usize > config.dfu.capacity() {
+        return Err(SdUpdateError::TooLarge);
+    }
+    let mut aligned = [0u8; 4];
+    let mut updater = BlockingFirmwareUpdater::new(config, &mut aligned);
+    // Nothing of an earlier image may stay behind this one.
+    updater.prepare_update().map_err(|_| SdUpdateError::Flash)?;
+
+    let mut buf = [0u8; CHUNK];
+    let mut offset = 0;
+    while !file.is_eof() {
+        let mut len = 0;
+        while len < CHUNK && !file.is_eof() {
+            len +=
+                file.read(&mut buf[len..]).map_err(|_| SdUpdateError::Read)?;
+        }
+        // The sector is written whole; the tail stays erased.
+        buf[len..].fill(0xFF);
+        updater
+            .write_firmware(offset, &buf)
+            .map_err(|_| SdUpdateError::Flash)?;
+        offset += len;
+    }
+    updater.mark_updated().map_err(|_| SdUpdateError::Flash)?;
+    Ok(offset as u32)
+}
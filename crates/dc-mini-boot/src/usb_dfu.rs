@@ -0,0 +1,173 @@
+//! USB serial DFU for recovery mode, see [`BOOT_DFU_BEGIN`] for the
+//! protocol.
+//!
+//! Only the image is transferred; the usual signature check and swap
+//! follow once it is marked updated.
+
+use core::cell::RefCell;
+
+use dc_mini_bsp::usb::UsbDriverBuilder;
+use dc_mini_icd::{
+    BOOT_DFU_BAD_REQUEST, BOOT_DFU_BEGIN, BOOT_DFU_FLASH_ERROR, BOOT_DFU_OK,
+    BOOT_DFU_PRODUCT, BOOT_DFU_SECTOR, BOOT_DFU_TOO_LARGE,
+};
+use embassy_boot::{BlockingFirmwareUpdater, FirmwareUpdaterConfig};
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
+use embassy_usb::driver::{Driver, EndpointError};
+use embassy_usb::{Builder, Config};
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+const MAX_PACKET_SIZE: u16 = 64;
+
+/// Why a transfer ended without staging an image.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Error {
+    /// The host went away mid-transfer.
+    Disconnected,
+    /// Answered with the status byte, which ends the transfer.
+    Refused(u8),
+}
+
+impl From<EndpointError> for Error {
+    fn from(_: EndpointError) -> Self {
+        Error::Disconnected
+    }
+}
+
+/// Serves the DFU port until an image is staged. Returns its size.
+pub async fn run<DFU: NorFlash, STATE: NorFlash>(
+    usbd: UsbDriverBuilder,
+    dfu_flash: &Mutex<NoopRawMutex, RefCell<DFU>>,
+    state_flash: &Mutex<NoopRawMutex, RefCell<STATE>>,
+) -> u32 {
+    let mut config = Config::new(0x16c0, 0x27DD);
+    config.manufacturer = Some("JHUAPL");
+    config.product = Some(BOOT_DFU_PRODUCT);
+    config.max_packet_size_0 = MAX_PACKET_SIZE as u8;
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut state = State::new();
+    let mut builder = Builder::new(
+        usbd.init(),
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut [],
+        &mut control_buf,
+    );
+    let mut class =
+        CdcAcmClass::new(&mut builder, &mut state, MAX_PACKET_SIZE);
+    let mut device = builder.build();
+
+    let transfers = async {
+        loop {
+            class.wait_connection().await;
+            match transfer(&mut class, dfu_flash, state_flash).await {
+                Ok(size) => return size,
+                Err(_e) => {
+                    #[cfg(feature = "defmt")]
+                    defmt::warn!("USB DFU failed: {:?}", _e);
+                }
+            }
+        }
+    };
+    match select(device.run(), transfers).await {
+        Either::First(never) => never,
+        Either::Second(size) => size,
+    }
+}
+
+/// Receives one image into the DFU partition and marks it updated.
+async fn transfer<'d, D: Driver<'d>, DFU: NorFlash, STATE: NorFlash>(
+    class: &mut CdcAcmClass<'d, D>,
+    dfu_flash: &Mutex<NoopRawMutex, RefCell<DFU>>,
+    state_flash: &Mutex<NoopRawMutex, RefCell<STATE>>,
+) -> Result<u32, Error> {
+    let mut rx = Reader::new(class);
+    let mut header = [0u8; 5];
+    rx.read_exact(&mut header).await?;
+    if header[0] != BOOT_DFU_BEGIN {
+        return rx.refuse(BOOT_DFU_BAD_REQUEST).await;
+    }
+    let size =
+        u32::from_le_bytes([header[1], header[2], header[3], header[4]]);
+
+    let config = FirmwareUpdaterConfig::from_linkerfile_blocking(
+        dfu_flash,
+        state_flash,
+    );
+    if size as usize > config.dfu.capacity() {
+        return rx.refuse(BOOT_DFU_TOO_LARGE).await;
+    }
+    let mut aligned = [0u8; 4];
+    let mut updater = BlockingFirmwareUpdater::new(config, &mut aligned);
+    // Nothing of an earlier image may stay behind this one.
+    if updater.prepare_update().is_err() {
+        return rx.refuse(BOOT_DFU_FLASH_ERROR).await;
+    }
+
+    let mut sector = [0u8; BOOT_DFU_SECTOR];
+    let mut offset = 0;
+    while offset < size as usize {
+        let len = (size as usize - offset).min(BOOT_DFU_SECTOR);
+        rx.read_exact(&mut sector[..len]).await?;
+        // The sector is written whole; the tail stays erased.
+        sector[len..].fill(0xFF);
+        if updater.write_firmware(offset, &sector).is_err() {
+            return rx.refuse(BOOT_DFU_FLASH_ERROR).await;
+        }
+        rx.reply(BOOT_DFU_OK).await?;
+        offset += len;
+    }
+    if updater.mark_updated().is_err() {
+        return rx.refuse(BOOT_DFU_FLASH_ERROR).await;
+    }
+    rx.reply(BOOT_DFU_OK).await?;
+    Ok(size)
+}
+
+/// Byte stream over the CDC class's packets.
+struct Reader<'c, 'd, D: Driver<'d>> {
+    class: &'c mut CdcAcmClass<'d, D>,
+    buf: [u8; MAX_PACKET_SIZE as usize],
+    pos: usize,
+    len: usize,
+}
+
+impl<'c, 'd, D: Driver<'d>> Reader<'c, 'd, D> {
+    fn new(class: &'c mut CdcAcmClass<'d, D>) -> Self {
+        Self { class, buf: [0; MAX_PACKET_SIZE as usize], pos: 0, len: 0 }
+    }
+
+    async fn read_exact(&mut self, out: &mut [u8]) -> Result<(), Error> {
+        let mut filled = 0;
+        while filled < out.len() {
+            if self.pos == self.len {
+                self.len = self.class.read_packet(&mut self.buf).await?;
+                self.pos = 0;
+            }
+            let n = (self.len - self.pos).min(out.len() - filled);
+            out[filled..filled + n]
+                .copy_from_slice(&self.buf[self.pos..self.pos + n]);
+            self.pos += n;
+            filled += n;
+        }
+        Ok(())
+    }
+
+    async fn reply(&mut self, status: u8) -> Result<(), Error> {
+        self.class.write_packet(&[status]).await?;
+        Ok(())
+    }
+
+    async fn refuse<T>(&mut self, status: u8) -> Result<T, Error> {
+        self.reply(status).await?;
+        Err(Error::Refused(status))
+    }
+}
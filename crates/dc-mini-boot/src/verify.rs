@@ -0,0 +1,112 @@
+//! Signature check of a staged update, on top of embassy-boot's swap.
+//!
+//! The DFU partition holds a signed image as described on
+//! [`DfuSignature`]. The trailer is found by scanning forward for one that
+//! sits where its own firmware length puts it. The swap installs the
+//! whole partition, so everything after the trailer must be erased; the
+//! writers see to that before marking an update.
+
+use dc_mini_bsp::UpdateRejection;
+use dc_mini_icd::{DFU_SIGNATURE_LEN, DFU_SIGNATURE_MAGIC, DfuSignature};
+use embedded_storage::nor_flash::ReadNorFlash;
+
+/// Bytes read from the DFU partition at a time.
+const CHUNK: usize = 256;
+/// Overlap between scanned chunks, so a magic in a chunk's last word is
+/// seen along with the length after it.
+const OVERLAP: usize = 8;
+
+/// Checks the image staged in `dfu` against `public_key`, an erased key
+/// meaning none.
+pub fn verify_image<F: ReadNorFlash>(
+    dfu: &mut F,
+    public_key: &[u8; 32],
+) -> Result<(), UpdateRejection> {
+    if *public_key == [0xFF; 32] {
+        return Err(UpdateRejection::NoKey);
+    }
+    let public_key = salty::PublicKey::try_from(public_key)
+        .map_err(|_| UpdateRejection::NoKey)?;
+    let trailer = find_signature(dfu)?;
+    let image_end =
+        DfuSignature::offset(trailer.firmware_len) + DFU_SIGNATURE_LEN as u32;
+    if !is_erased_from(dfu, image_end)? {
+        return Err(UpdateRejection::TrailingData);
+    }
+
+    let mut hasher = salty::Sha512::new();
+    let mut buf = [0u8; CHUNK];
+    let mut offset = 0;
+    while offset < trailer.firmware_len {
+        let len = (trailer.firmware_len - offset).min(CHUNK as u32);
+        // Reads stay word aligned; the padding is not hashed.
+        let aligned = (len as usize + 3) & !3;
+        dfu.read(offset, &mut buf[..aligned])
+            .map_err(|_| UpdateRejection::Flash)?;
+        hasher.update(&buf[..len as usize]);
+        offset += len;
+    }
+    let digest = hasher.finalize();
+
+    let signature = salty::Signature::from(&trailer.signature);
+    public_key
+        .verify(&digest, &signature)
+        .map_err(|_| UpdateRejection::BadSignature)
+}
+
+/// Whether `dfu` reads `0xFF` from `offset` to its end.
+fn is_erased_from<F: ReadNorFlash>(
+    dfu: &mut F,
+    mut offset: u32,
+) -> Result<bool, UpdateRejection> {
+    let capacity = dfu.capacity() as u32;
+    let mut buf = [0u8; CHUNK];
+    while offset < capacity {
+        let len = (capacity - offset).min(CHUNK as u32) as usize;
+        dfu.read(offset, &mut buf[..len])
+            .map_err(|_| UpdateRejection::Flash)?;
+        if buf[..len].iter().any(|&b| b != 0xFF) {
+            return Ok(false);
+        }
+        offset += len as u32;
+    }
+    Ok(true)
+}
+
+/// Returns the first trailer in `dfu` whose firmware length places it
+/// where it was found.
+fn find_signature<F: ReadNorFlash>(
+    dfu: &mut F,
+) -> Result<DfuSignature, UpdateRejection> {
+    let capacity = dfu.capacity() as u32;
+    let mut buf = [0u8; CHUNK];
+    let mut base = 0;
+    while base + DFU_SIGNATURE_LEN as u32 <= capacity {
+        let len = (capacity - base).min(CHUNK as u32) as usize;
+        dfu.read(base, &mut buf[..len]).map_err(|_| UpdateRejection::Flash)?;
+        for i in (0..len.saturating_sub(OVERLAP - 1)).step_by(4) {
+            if buf[i..i + 4] != DFU_SIGNATURE_MAGIC {
+                continue;
+            }
+            let firmware_len = u32::from_le_bytes([
+                buf[i + 4],
+                buf[i + 5],
+                buf[i + 6],
+                buf[i + 7],
+            ]);
+            let pos = base + i as u32;
+            if DfuSignature::offset(firmware_len) != pos
+                || pos + DFU_SIGNATURE_LEN as u32 > capacity
+            {
+                continue;
+            }
+            let mut bytes = [0u8; DFU_SIGNATURE_LEN];
+            dfu.read(pos, &mut bytes).map_err(|_| UpdateRejection::Flash)?;
+            if let Some(trailer) = DfuSignature::from_bytes(&bytes) {
+                return Ok(trailer);
+            }
+        }
+        base += (CHUNK - OVERLAP) as u32;
+    }
+    Err(UpdateRejection::Unsigned)
+}
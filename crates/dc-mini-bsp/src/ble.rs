@@ -25,9 +25,12 @@ const L2CAP_RXQ: u8 = 4;
 /// trouble-host handles fragmentation/reassembly of larger L2CAP PDUs above this.
 const L2CAP_MTU: u16 = 251;
 
+/// Number of centrals that can be connected at once.
+pub const PERIPHERAL_COUNT: u8 = 2;
+
 /// Memory allocation for SDC BLE controller in bytes.
 /// Must be large enough to accommodate the configured buffer sizes and connection count.
-const SDC_MEMORY_SIZE: usize = 3336;
+const SDC_MEMORY_SIZE: usize = 3336 * PERIPHERAL_COUNT as usize;
 
 /// SDC BLE Controller Builder.
 pub struct BleControllerBuilder<'d> {
@@ -107,12 +110,19 @@ where
         }
     }
 
+    /// Starts the MPSL and builds the controller. Also returns a seed from
+    /// the hardware RNG for the host's pairing key generator, as the RNG
+    /// itself is owned by the controller afterwards.
     pub fn init(
         self,
         timer0: Peri<'d, peripherals::TIMER0>,
         rng: Peri<'d, peripherals::RNG>,
     ) -> Result<
-        (SoftdeviceController<'d>, &'static MultiprotocolServiceLayer<'d>),
+        (
+            SoftdeviceController<'d>,
+            &'static MultiprotocolServiceLayer<'d>,
+            [u8; 32],
+        ),
         SoftdeviceError,
     > {
         let mpsl = {
@@ -135,6 +145,8 @@ where
                 StaticCell::new();
             SDC_RNG.init(rng::Rng::new(rng, BleIrqs))
         };
+        let mut seed = [0u8; 32];
+        sdc_rng.blocking_fill_bytes(&mut seed);
         let mem = {
             static SDC_MEM: StaticCell<sdc::Mem<SDC_MEMORY_SIZE>> =
                 StaticCell::new();
@@ -146,7 +158,7 @@ where
             MPSL.init(mpsl)
         };
         let sdc = build_sdc(self.sdc_peripherals, sdc_rng, mpsl, mem)?;
-        Ok((sdc, mpsl))
+        Ok((sdc, mpsl, seed))
     }
 }
 
@@ -162,7 +174,7 @@ fn build_sdc<'d, const N: usize>(
         .support_peripheral()
         .support_dle_peripheral()
         .support_le_2m_phy()
-        .peripheral_count(1)?
+        .peripheral_count(PERIPHERAL_COUNT)?
         .buffer_cfg(L2CAP_MTU, L2CAP_MTU, L2CAP_TXQ, L2CAP_RXQ)?
         .build(p, rng, mpsl, mem)
 }
@@ -0,0 +1,428 @@
+//! Board resources shared by every revision, generated from a pin table.
+//!
+//! Each board module invokes [`dc_mini_board!`] with its own table, so a
+//! respin that moves a net is a one-line change in a copy of that table
+//! rather than a new board module. Peripherals are moved out of
+//! `embassy_nrf::init` by name, so a pin listed twice fails to compile.
+
+/// Defines the resource structs and [`DCMini`](crate::DCMini) for a board
+/// from its pin table; see `sr7.rs` for the layout. `status_led` names a
+/// [`StatusLed`](crate::StatusLed) constructor and its peripherals,
+/// `sleep` lists the pins that need a [`SleepState`](crate::SleepState)
+/// other than disconnected across System OFF, `pmic_bus` gives the PMIC its own TWISPI0 bus, and `spare` exposes
+/// peripherals that are not otherwise assigned.
+macro_rules! dc_mini_board {
+    (
+        vbus_src: $vbus_src:ident,
+        pwrbtn: $pwrbtn:ident,
+        status_led: $led_kind:ident {
+            $($led_name:ident: $led_periph:ident),* $(,)?
+        },
+        apds_irq: $apds_irq:ident,
+        en5v: $en5v:ident,
+        usbsel: $usbsel:ident,
+        npm_gpio: $npm_gpio:ident,
+        nrf_gpio: [
+            $gpio1:ident,
+            $gpio2:ident,
+            $gpio3:ident,
+            $gpio4:ident,
+            $gpio5:ident,
+            $gpio6:ident,
+            $gpio7:ident $(,)?
+        ],
+        mic: { clk: $mic_clk:ident, din: $mic_din:ident $(,)? },
+        haptic: { trig: $haptic_trig:ident $(,)? },
+        imu: { irq: $imu_irq:ident, sync: $imu_sync:ident $(,)? },
+        twim1: { sda: $twim1_sda:ident, scl: $twim1_scl:ident $(,)? },
+        ads: {
+            pwdn: $ads_pwdn:ident,
+            reset: $ads_reset:ident,
+            start: $ads_start:ident,
+            cs1: $ads_cs1:ident,
+            cs2: $ads_cs2:ident,
+            drdy: $ads_drdy:ident $(,)?
+        },
+        spi3: {
+            sclk: $spi3_sclk:ident,
+            mosi: $spi3_mosi:ident,
+            miso: $spi3_miso:ident $(,)?
+        },
+        sd_card: {
+            sclk: $sd_sclk:ident,
+            mosi: $sd_mosi:ident,
+            miso: $sd_miso:ident,
+            cs: $sd_cs:ident,
+            detect: $sd_detect:ident $(,)?
+        },
+        vsys_sense: { ain: $vsys_ain:ident $(,)? },
+        external_flash: {
+            sck: $flash_sck:ident,
+            csn: $flash_csn:ident,
+            io0: $flash_io0:ident,
+            io1: $flash_io1:ident,
+            io2: $flash_io2:ident,
+            io3: $flash_io3:ident $(,)?
+        },
+        sleep: { $($sleep_pin:ident: $sleep_state:ident),* $(,)? }
+        $(, pmic_bus: { sda: $pmic_sda:ident, scl: $pmic_scl:ident $(,)? })?
+        $(, spare: { $($spare:ident: $spare_periph:ident),* $(,)? })?
+        $(,)?
+    ) => {
+        use embassy_nrf::interrupt::Priority;
+        use embassy_nrf::peripherals;
+        use embassy_nrf::Peri;
+
+        #[cfg(feature = "trouble")]
+        use crate::ble;
+        #[cfg(feature = "usb")]
+        use crate::usb;
+        use crate::BoardRev;
+
+        // Need 3.3V rail for following:
+        // - inidicator LED neopixel
+        // - haptic driver
+        // - SD card
+        // - AFE of ADS1299
+
+        pub struct ImuResources {
+            pub irq: Peri<'static, peripherals::$imu_irq>,
+            pub sync: Peri<'static, peripherals::$imu_sync>,
+        }
+
+        pub struct Twim1BusResources {
+            pub twim: Peri<'static, peripherals::TWISPI1>,
+            pub sda: Peri<'static, peripherals::$twim1_sda>,
+            pub scl: Peri<'static, peripherals::$twim1_scl>,
+        }
+
+        $(
+            pub struct PmicBusResources {
+                pub twim: Peri<'static, peripherals::TWISPI0>,
+                pub sda: Peri<'static, peripherals::$pmic_sda>,
+                pub scl: Peri<'static, peripherals::$pmic_scl>,
+            }
+        )?
+
+        pub struct AdsResources {
+            pub pwdn: Peri<'static, peripherals::$ads_pwdn>,
+            pub reset: Peri<'static, peripherals::$ads_reset>,
+            pub start: Peri<'static, peripherals::$ads_start>,
+            pub cs1: Peri<'static, peripherals::$ads_cs1>,
+            pub cs2: Peri<'static, peripherals::$ads_cs2>,
+            pub drdy: Peri<'static, peripherals::$ads_drdy>,
+        }
+
+        pub struct Spi3BusResources {
+            pub sclk: Peri<'static, peripherals::$spi3_sclk>,
+            pub mosi: Peri<'static, peripherals::$spi3_mosi>,
+            pub miso: Peri<'static, peripherals::$spi3_miso>,
+            pub spim: Peri<'static, peripherals::SPI3>,
+        }
+
+        pub struct SdCardResources {
+            pub sclk: Peri<'static, peripherals::$sd_sclk>,
+            pub mosi: Peri<'static, peripherals::$sd_mosi>,
+            pub miso: Peri<'static, peripherals::$sd_miso>,
+            pub cs: Peri<'static, peripherals::$sd_cs>,
+            /// Card-detect switch of the socket, closed to ground while a
+            /// card is inserted.
+            pub detect: Peri<'static, peripherals::$sd_detect>,
+            pub spim: Peri<'static, peripherals::SPI2>,
+        }
+
+        /// Peripherals of the indicator LED.
+        pub struct StatusLedResources {
+            $(pub $led_name: Peri<'static, peripherals::$led_periph>,)*
+        }
+
+        impl StatusLedResources {
+            #[doc = concat!(
+                "The LED, with the ", stringify!($led_kind), " backend."
+            )]
+            pub fn into_led(self) -> crate::StatusLed<'static> {
+                crate::StatusLed::$led_kind($(self.$led_name),*)
+            }
+        }
+
+        pub struct HapticResources {
+            pub trig: Peri<'static, peripherals::$haptic_trig>,
+        }
+
+        pub struct MicResources {
+            pub pdm: Peri<'static, peripherals::PDM>,
+            pub clk: Peri<'static, peripherals::$mic_clk>,
+            pub din: Peri<'static, peripherals::$mic_din>,
+        }
+
+        /// VSYS sense divider, read by the SAADC.
+        pub struct VsysSenseResources {
+            pub saadc: Peri<'static, peripherals::SAADC>,
+            /// Divider output, on an analog input.
+            pub ain: Peri<'static, peripherals::$vsys_ain>,
+        }
+
+        /// Pins for External QSPI flash
+        pub struct ExternalFlashResources {
+            /// The QSPI instance.
+            pub qspi: Peri<'static, peripherals::QSPI>,
+            /// The Serial Clock Line (SCLK) pin.
+            pub sck: Peri<'static, peripherals::$flash_sck>,
+            /// The Chip Select (CSN) pin.
+            pub csn: Peri<'static, peripherals::$flash_csn>,
+            /// Input/Output pin 0.
+            pub io0: Peri<'static, peripherals::$flash_io0>,
+            /// Input/Output pin 1.
+            pub io1: Peri<'static, peripherals::$flash_io1>,
+            /// Input/Output pin 2.
+            pub io2: Peri<'static, peripherals::$flash_io2>,
+            /// Input/Output pin 3.
+            pub io3: Peri<'static, peripherals::$flash_io3>,
+        }
+
+        /// Represents all the peripherals and pins available for the DCMini device.
+        pub struct DCMini {
+            /// Board revision, read from the ID strap at init and otherwise
+            /// the one the firmware is built for.
+            pub rev: BoardRev,
+            /// Pulled low means ext vbus
+            /// Pulled high means through usb isolator
+            /// Needs internal pull up
+            /// If vbus connected through EXT, don't allow EEG
+            pub vbus_src: Peri<'static, peripherals::$vbus_src>,
+            /// Pin for the user/power button.
+            pub pwrbtn: Peri<'static, peripherals::$pwrbtn>,
+            /// Indicator LED.
+            pub status_led: StatusLedResources,
+            /// PDM microphone resources (SPK0838HT4H).
+            pub mic: MicResources,
+            /// Interrupt pin for the ambient light sensor.
+            pub apds_irq: Peri<'static, peripherals::$apds_irq>,
+            /// Power enable for 5V rail
+            /// pull low to turn on 5V rail.
+            pub en5v: Peri<'static, peripherals::$en5v>,
+            /// Haptic driver resources
+            pub haptic_resources: HapticResources,
+
+            // USB Select, set default pull-up,
+            // down when we want to use usb that
+            // is connected on board to board connector
+            pub usbsel: Peri<'static, peripherals::$usbsel>,
+
+            // General purpose nRF gpio that connects to b2b connector.
+            pub nrf_gpio1: Peri<'static, peripherals::$gpio1>,
+            pub nrf_gpio2: Peri<'static, peripherals::$gpio2>,
+            /// Also the board-ID strap, read once at init.
+            pub nrf_gpio3: Peri<'static, peripherals::$gpio3>,
+            pub nrf_gpio4: Peri<'static, peripherals::$gpio4>,
+            pub nrf_gpio5: Peri<'static, peripherals::$gpio5>,
+            pub nrf_gpio6: Peri<'static, peripherals::$gpio6>,
+            pub nrf_gpio7: Peri<'static, peripherals::$gpio7>,
+
+            // Power Chip Interrupt (useful for power low interrupt)
+            pub npm_gpio: Peri<'static, peripherals::$npm_gpio>,
+
+            /// Configuration pins for external flash memory.
+            pub external_flash: ExternalFlashResources,
+            /// Peripherals for ADS1299.
+            pub ads_resources: AdsResources,
+            /// Peripherals for SPI 3 bus.
+            pub spi3_bus_resources: Spi3BusResources,
+            /// Peripherals for SD Card.
+            pub sd_card_resources: SdCardResources,
+            /// Peripherals for I2C bus.
+            pub twim1_bus_resources: Twim1BusResources,
+            $(
+                #[doc = concat!(
+                    "Dedicated low-speed I2C bus for the PMIC, on ",
+                    stringify!($pmic_sda), "/", stringify!($pmic_scl), "."
+                )]
+                pub pmic_bus_resources: PmicBusResources,
+            )?
+            /// Peripherals for the Imu.
+            pub imu_resources: ImuResources,
+            /// Real-Time Clock 2.
+            pub rtc2: Peri<'static, peripherals::RTC2>,
+            /// Watchdog Timer.
+            pub wdt: Peri<'static, peripherals::WDT>,
+            /// Non-Volatile Memory Controller.
+            pub nvmc: Peri<'static, peripherals::NVMC>,
+            /// Random Number Generator.
+            pub rng: Peri<'static, peripherals::RNG>,
+            /// Quadrature Decoder.
+            pub qdec: Peri<'static, peripherals::QDEC>,
+            /// UART (Universal Asynchronous Receiver-Transmitter) 0.
+            pub uarte0: Peri<'static, peripherals::UARTE0>,
+            /// UART (Universal Asynchronous Receiver-Transmitter) 1.
+            pub uarte1: Peri<'static, peripherals::UARTE1>,
+            /// SAADC and the VSYS divider it measures. The divider output
+            /// is also routed to the board-to-board connector as
+            /// `nrf_gpio8`.
+            pub vsys_sense: VsysSenseResources,
+            /// Pulse-Width Modulation 1.
+            pub pwm1: Peri<'static, peripherals::PWM1>,
+            /// Pulse-Width Modulation 2.
+            pub pwm2: Peri<'static, peripherals::PWM2>,
+            /// Pulse-Width Modulation 3.
+            pub pwm3: Peri<'static, peripherals::PWM3>,
+            /// Timer 0.
+            pub timer0: Peri<'static, peripherals::TIMER0>,
+            /// Timer 1.
+            pub timer1: Peri<'static, peripherals::TIMER1>,
+            /// Timer 2.
+            pub timer2: Peri<'static, peripherals::TIMER2>,
+            /// Timer 3.
+            pub timer3: Peri<'static, peripherals::TIMER3>,
+            /// Timer 4.
+            pub timer4: Peri<'static, peripherals::TIMER4>,
+            /// Inter-IC Sound.
+            pub i2s: Peri<'static, peripherals::I2S>,
+            $($(
+                #[doc = concat!(
+                    "Unassigned ", stringify!($spare_periph), "."
+                )]
+                pub $spare: Peri<'static, peripherals::$spare_periph>,
+            )*)?
+            #[cfg(feature = "trouble")]
+            /// Bluetooth Low Energy peripheral
+            pub ble: ble::BleControllerBuilder<'static>,
+            #[cfg(feature = "usb")]
+            /// USB driver builder
+            pub usb: usb::UsbDriverBuilder,
+        }
+
+        impl Default for DCMini {
+            fn default() -> Self {
+                let mut config = embassy_nrf::config::Config::default();
+                config.gpiote_interrupt_priority = Priority::P2;
+                config.time_interrupt_priority = Priority::P2;
+                Self::new(config)
+            }
+        }
+
+        impl DCMini {
+            /// Create a new instance based on HAL configuration
+            pub fn new(config: embassy_nrf::config::Config) -> Self {
+                let mut p = embassy_nrf::init(config);
+                let rev = BoardRev::detect(p.$gpio3.reborrow())
+                    .unwrap_or(BoardRev::COMPILED);
+
+                Self {
+                    rev,
+                    vbus_src: p.$vbus_src,
+                    pwrbtn: p.$pwrbtn,
+                    status_led: StatusLedResources {
+                        $($led_name: p.$led_periph,)*
+                    },
+                    mic: MicResources {
+                        pdm: p.PDM,
+                        clk: p.$mic_clk,
+                        din: p.$mic_din,
+                    },
+                    apds_irq: p.$apds_irq,
+                    en5v: p.$en5v,
+                    haptic_resources: HapticResources { trig: p.$haptic_trig },
+                    usbsel: p.$usbsel,
+                    nrf_gpio1: p.$gpio1,
+                    nrf_gpio2: p.$gpio2,
+                    nrf_gpio3: p.$gpio3,
+                    nrf_gpio4: p.$gpio4,
+                    nrf_gpio5: p.$gpio5,
+                    nrf_gpio6: p.$gpio6,
+                    nrf_gpio7: p.$gpio7,
+                    npm_gpio: p.$npm_gpio,
+                    rtc2: p.RTC2,
+                    wdt: p.WDT,
+                    nvmc: p.NVMC,
+                    rng: p.RNG,
+                    qdec: p.QDEC,
+                    uarte0: p.UARTE0,
+                    uarte1: p.UARTE1,
+                    vsys_sense: VsysSenseResources {
+                        saadc: p.SAADC,
+                        ain: p.$vsys_ain,
+                    },
+                    pwm1: p.PWM1,
+                    pwm2: p.PWM2,
+                    pwm3: p.PWM3,
+                    timer0: p.TIMER0,
+                    timer1: p.TIMER1,
+                    timer2: p.TIMER2,
+                    timer3: p.TIMER3,
+                    timer4: p.TIMER4,
+                    i2s: p.I2S,
+                    external_flash: ExternalFlashResources {
+                        qspi: p.QSPI,
+                        sck: p.$flash_sck,
+                        csn: p.$flash_csn,
+                        io0: p.$flash_io0,
+                        io1: p.$flash_io1,
+                        io2: p.$flash_io2,
+                        io3: p.$flash_io3,
+                    },
+                    ads_resources: AdsResources {
+                        pwdn: p.$ads_pwdn,
+                        reset: p.$ads_reset,
+                        start: p.$ads_start,
+                        cs1: p.$ads_cs1,
+                        cs2: p.$ads_cs2,
+                        drdy: p.$ads_drdy,
+                    },
+                    spi3_bus_resources: Spi3BusResources {
+                        sclk: p.$spi3_sclk,
+                        mosi: p.$spi3_mosi,
+                        miso: p.$spi3_miso,
+                        spim: p.SPI3,
+                    },
+                    sd_card_resources: SdCardResources {
+                        sclk: p.$sd_sclk,
+                        mosi: p.$sd_mosi,
+                        miso: p.$sd_miso,
+                        cs: p.$sd_cs,
+                        detect: p.$sd_detect,
+                        spim: p.SPI2,
+                    },
+                    twim1_bus_resources: Twim1BusResources {
+                        twim: p.TWISPI1,
+                        sda: p.$twim1_sda,
+                        scl: p.$twim1_scl,
+                    },
+                    $(
+                        pmic_bus_resources: PmicBusResources {
+                            twim: p.TWISPI0,
+                            sda: p.$pmic_sda,
+                            scl: p.$pmic_scl,
+                        },
+                    )?
+                    imu_resources: ImuResources {
+                        irq: p.$imu_irq,
+                        sync: p.$imu_sync,
+                    },
+                    $($($spare: p.$spare_periph,)*)?
+                    #[cfg(feature = "trouble")]
+                    ble: ble::BleControllerBuilder::new(
+                        p.RTC0, p.TEMP, p.PPI_CH17, p.PPI_CH18, p.PPI_CH19,
+                        p.PPI_CH20, p.PPI_CH21, p.PPI_CH22, p.PPI_CH23,
+                        p.PPI_CH24, p.PPI_CH25, p.PPI_CH26, p.PPI_CH27,
+                        p.PPI_CH28, p.PPI_CH29, p.PPI_CH30, p.PPI_CH31,
+                    ),
+                    #[cfg(feature = "usb")]
+                    usb: usb::UsbDriverBuilder::new(p.USBD),
+                }
+            }
+
+            /// Leaves every pin in its lowest-leakage state for System OFF:
+            /// disconnected, except the pins in this board's `sleep` table.
+            /// Call it last before arming the wake pins.
+            pub fn park_pins() {
+                crate::park_pins(&[$(
+                    crate::SleepPin::new(
+                        // SAFETY: only the pin number is read.
+                        &*unsafe { peripherals::$sleep_pin::steal() },
+                        crate::SleepState::$sleep_state,
+                    ),
+                )*]);
+            }
+        }
+    };
+}
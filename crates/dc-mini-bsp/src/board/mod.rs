@@ -1,3 +1,6 @@
+#[macro_use]
+mod layout;
+
 const _ENABLED_FEATURES: u32 = 0
     + if cfg!(feature = "sr6") { 1 } else { 0 }
     + if cfg!(feature = "sr7") { 1 } else { 0 };
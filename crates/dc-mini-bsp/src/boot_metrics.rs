@@ -0,0 +1,159 @@
+use core::ptr::{addr_of, read_volatile};
+
+use embassy_nrf::nvmc::PAGE_SIZE;
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::BootStatus;
+
+/// Words of a record: the counters, the last swap, the status of the boot
+/// that wrote it and the application's acknowledgement of that status.
+const RECORD_WORDS: usize = 6;
+/// Written last by the bootloader; a record without it is incomplete.
+const STATUS_WORD: usize = 4;
+const ACK_WORD: usize = 5;
+const RECORD_LEN: usize = RECORD_WORDS * 4;
+const RECORDS: usize = PAGE_SIZE / RECORD_LEN;
+const ERASED: u32 = 0xFFFF_FFFF;
+/// Last-swap word before any update was staged. Status words always carry
+/// their magic, so this is never one.
+const NO_SWAP: u32 = 0;
+
+/// `RESETREAS.DOG`.
+const RESETREAS_DOG: u32 = 1 << 1;
+
+extern "C" {
+    /// Flash page outside both images and the bootloader's partitions, so
+    /// swaps leave it alone. Defined by both `memory.x` files.
+    static __boot_metrics_start: u32;
+}
+
+/// Counters the bootloader keeps across updates and power loss.
+///
+/// Each boot appends a record to a flash page of its own, so the page is
+/// only erased once every hundred and seventy boots. The bootloader
+/// writes a record's status word last; one cut short by a reset is
+/// skipped. The application writes the word after it, see
+/// [`BootStatus::acknowledge`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BootMetrics {
+    /// Boots through the bootloader since the page was first written.
+    pub boot_count: u32,
+    /// Boots after a watchdog reset.
+    pub watchdog_resets: u32,
+    /// Updates that never confirmed themselves and were swapped back out.
+    pub rollbacks: u32,
+    /// What the last boot that found an update staged did with it.
+    pub last_swap: Option<BootStatus>,
+}
+
+fn page_start() -> u32 {
+    unsafe { addr_of!(__boot_metrics_start) as u32 }
+}
+
+fn word(slot: usize, i: usize) -> u32 {
+    let addr = page_start() as usize + slot * RECORD_LEN + i * 4;
+    unsafe { read_volatile(addr as *const u32) }
+}
+
+/// Index of the first unwritten slot, `RECORDS` when the page is full.
+fn next_slot() -> usize {
+    (0..RECORDS).find(|&slot| word(slot, 0) == ERASED).unwrap_or(RECORDS)
+}
+
+/// Slot of the latest complete record.
+fn latest_slot() -> Option<usize> {
+    (0..next_slot()).rev().find(|&slot| word(slot, STATUS_WORD) != ERASED)
+}
+
+impl BootMetrics {
+    /// The latest record, `None` if the bootloader never wrote one.
+    pub fn read() -> Option<Self> {
+        latest_slot().map(|slot| Self {
+            boot_count: word(slot, 0),
+            watchdog_resets: word(slot, 1),
+            rollbacks: word(slot, 2),
+            last_swap: BootStatus::from_word(word(slot, 3)),
+        })
+    }
+
+    /// Counts this boot, which ended in `status`, and appends the result.
+    /// Called by the bootloader once per boot. `flash` addresses the
+    /// internal flash from its start.
+    ///
+    /// Watchdog resets are read from `RESETREAS`, which the application
+    /// clears once it has read it.
+    pub fn record_boot<F: NorFlash>(
+        flash: &mut F,
+        status: BootStatus,
+    ) -> Result<Self, F::Error> {
+        let mut metrics = Self::read().unwrap_or_default();
+        metrics.boot_count += 1;
+        let resetreas = embassy_nrf::pac::POWER.resetreas().read().0;
+        if resetreas & RESETREAS_DOG != 0 {
+            metrics.watchdog_resets += 1;
+        }
+        match status {
+            BootStatus::Booted => {}
+            BootStatus::RolledBack => {
+                metrics.rollbacks += 1;
+                metrics.last_swap = Some(status);
+            }
+            _ => metrics.last_swap = Some(status),
+        }
+
+        // A status the application never saw, say for a power loss before
+        // it started, is handed on until it is acknowledged.
+        let status = match (status, BootStatus::pending()) {
+            (BootStatus::Booted, Some(pending)) => pending,
+            _ => status,
+        };
+
+        let mut slot = next_slot();
+        if slot == RECORDS {
+            flash.erase(page_start(), page_start() + PAGE_SIZE as u32)?;
+            slot = 0;
+        }
+        let words = [
+            metrics.boot_count,
+            metrics.watchdog_resets,
+            metrics.rollbacks,
+            metrics.last_swap.map_or(NO_SWAP, BootStatus::to_word),
+            status.to_word(),
+        ];
+        let mut record = [0u8; STATUS_WORD * 4 + 4];
+        for (chunk, word) in record.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        let offset = page_start() + (slot * RECORD_LEN) as u32;
+        flash.write(offset, &record)?;
+        Ok(metrics)
+    }
+}
+
+impl BootStatus {
+    /// What the bootloader did before this boot, until the application
+    /// acknowledges it. `None` with a bootloader that does not report one.
+    pub fn pending() -> Option<Self> {
+        let slot = latest_slot()?;
+        if word(slot, ACK_WORD) != ERASED {
+            return None;
+        }
+        Self::from_word(word(slot, STATUS_WORD))
+    }
+
+    /// Marks the pending status as seen. The application calls this once
+    /// it has confirmed its boot, so the status outlives a crash or power
+    /// loss before that. `flash` addresses the internal flash from its
+    /// start.
+    pub fn acknowledge<F: NorFlash>(flash: &mut F) -> Result<(), F::Error> {
+        let Some(slot) = latest_slot() else {
+            return Ok(());
+        };
+        if word(slot, ACK_WORD) != ERASED {
+            return Ok(());
+        }
+        let offset = page_start() + (slot * RECORD_LEN + ACK_WORD * 4) as u32;
+        flash.write(offset, &0u32.to_le_bytes())
+    }
+}
@@ -0,0 +1,77 @@
+/// Upper half of a valid status word, so erased or zeroed flash is not
+/// taken for one.
+const MAGIC: u32 = 0xB005_0000;
+const MAGIC_MASK: u32 = 0xFFFF_0000;
+
+const BOOTED: u32 = 0x0000;
+const UPDATED: u32 = 0x0100;
+const UPDATE_REJECTED: u32 = 0x0200;
+const ROLLED_BACK: u32 = 0x0300;
+
+/// Why the bootloader refused a staged update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum UpdateRejection {
+    /// The bootloader carries no signing key.
+    NoKey = 1,
+    /// No signature trailer was found in the DFU partition.
+    Unsigned = 2,
+    /// The signature does not match the image.
+    BadSignature = 3,
+    /// Reading the DFU partition failed.
+    Flash = 4,
+    /// The partition holds something other than erased flash after the
+    /// signature trailer, which the swap would install unsigned.
+    TrailingData = 5,
+}
+
+/// What the bootloader did before starting the application, handed over
+/// in its boot record in flash, see [`BootStatus::pending`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BootStatus {
+    /// Nothing was staged.
+    Booted,
+    /// A verified update was swapped in.
+    Updated,
+    /// The staged update was refused and the current image kept.
+    UpdateRejected(UpdateRejection),
+    /// An update swapped in on the previous boot never confirmed itself,
+    /// so the image before it was swapped back.
+    RolledBack,
+}
+
+impl BootStatus {
+    pub(crate) fn to_word(self) -> u32 {
+        MAGIC
+            | match self {
+                Self::Booted => BOOTED,
+                Self::Updated => UPDATED,
+                Self::UpdateRejected(reason) => {
+                    UPDATE_REJECTED | reason as u32
+                }
+                Self::RolledBack => ROLLED_BACK,
+            }
+    }
+
+    pub(crate) fn from_word(word: u32) -> Option<Self> {
+        if word & MAGIC_MASK != MAGIC {
+            return None;
+        }
+        let reason = match word & 0xff {
+            1 => UpdateRejection::NoKey,
+            2 => UpdateRejection::Unsigned,
+            3 => UpdateRejection::BadSignature,
+            5 => UpdateRejection::TrailingData,
+            _ => UpdateRejection::Flash,
+        };
+        match word & 0xff00 {
+            BOOTED => Some(Self::Booted),
+            UPDATED => Some(Self::Updated),
+            UPDATE_REJECTED => Some(Self::UpdateRejected(reason)),
+            ROLLED_BACK => Some(Self::RolledBack),
+            _ => None,
+        }
+    }
+}
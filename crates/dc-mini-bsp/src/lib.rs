@@ -4,11 +4,23 @@
 
 // Modules
 mod board;
+mod boot_metrics;
+mod boot_status;
+mod low_power;
 mod resources;
+mod rev;
+mod status_led;
+mod usb_c;
 
 // Flatten
 pub use board::*;
+pub use boot_metrics::*;
+pub use boot_status::*;
+pub use low_power::*;
 pub use resources::*;
+pub use rev::*;
+pub use status_led::*;
+pub use usb_c::*;
 
 #[cfg(feature = "trouble")]
 pub mod ble;
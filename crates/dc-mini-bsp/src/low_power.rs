@@ -0,0 +1,81 @@
+use embassy_nrf::gpio::Pin;
+use embassy_nrf::pac;
+use embassy_nrf::pac::gpio::vals::{Dir, Input, Pull, Sense};
+
+/// GPIOs on the nRF52840: all of port 0 and P1.00 to P1.15.
+const PIN_COUNT: u8 = 48;
+
+/// State a pin is left in across System OFF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SleepState {
+    /// Input buffer disconnected and no pull, the reset state. Nothing
+    /// leaks, so this is the default for every pin.
+    Disconnected,
+    /// Disconnected input held up by the internal pull-up.
+    PullUp,
+    /// Disconnected input held down by the internal pull-down.
+    PullDown,
+    /// Driven low.
+    OutputLow,
+    /// Driven high.
+    OutputHigh,
+}
+
+/// A pin that needs something other than [`SleepState::Disconnected`]
+/// across System OFF, e.g. an active-low enable that must keep its rail
+/// off.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SleepPin {
+    pin_port: u8,
+    state: SleepState,
+}
+
+impl SleepPin {
+    pub fn new<P: Pin>(pin: &P, state: SleepState) -> Self {
+        Self { pin_port: pin.port() as u8 * 32 + pin.pin(), state }
+    }
+}
+
+/// Returns every GPIO to [`SleepState::Disconnected`] and then applies
+/// `keep`. Pin configuration outlives the peripherals, so this works
+/// whichever driver last owned a pin, but any driver still running may
+/// take its pins back; call it only on the way into System OFF. Wake
+/// sources are armed afterwards and override their pin's state.
+pub fn park_pins(keep: &[SleepPin]) {
+    for pin_port in 0..PIN_COUNT {
+        configure(pin_port, SleepState::Disconnected);
+    }
+    for pin in keep {
+        configure(pin.pin_port, pin.state);
+    }
+}
+
+fn configure(pin_port: u8, state: SleepState) {
+    let port = if pin_port < 32 { pac::P0 } else { pac::P1 };
+    let pin = pin_port as usize % 32;
+    let (dir, pull) = match state {
+        SleepState::Disconnected => (Dir::INPUT, Pull::DISABLED),
+        SleepState::PullUp => (Dir::INPUT, Pull::PULLUP),
+        SleepState::PullDown => (Dir::INPUT, Pull::PULLDOWN),
+        SleepState::OutputLow | SleepState::OutputHigh => {
+            (Dir::OUTPUT, Pull::DISABLED)
+        }
+    };
+    // Set the level before the driver is enabled so the pin does not
+    // glitch.
+    match state {
+        SleepState::OutputLow => port.outclr().write(|w| w.set_pin(pin, true)),
+        SleepState::OutputHigh => {
+            port.outset().write(|w| w.set_pin(pin, true))
+        }
+        _ => {}
+    }
+    port.pin_cnf(pin).write(|w| {
+        w.set_dir(dir);
+        w.set_input(Input::DISCONNECT);
+        w.set_pull(pull);
+        w.set_sense(Sense::DISABLED);
+    });
+}
@@ -3,20 +3,22 @@ use crate::board::PmicBusResources;
 use crate::board::{
     AdsResources, ExternalFlashResources, HapticResources, ImuResources,
     MicResources, SdCardResources, Spi3BusResources, Twim1BusResources,
+    VsysSenseResources,
 };
 use ads1299::{Ads1299, AdsFrontend};
 use bus_manager::BusFactory;
+use embassy_embedded_hal::flash::partition::Partition;
 use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
 use embassy_embedded_hal::shared_bus::asynch::spi::SpiDevice;
 use embassy_nrf::{
     bind_interrupts,
     gpio::{Input, Level, Output, OutputDrive, Pull},
     interrupt::{self, InterruptExt},
-    pdm, peripherals, qspi, spim, twim,
+    pdm, peripherals, qspi, saadc, spim, twim, Peri,
 };
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::{blocking_mutex::raw::RawMutex, mutex::Mutex};
-use embassy_time::Timer;
+use embassy_time::{block_for, Duration, Timer};
 use embedded_hal_bus::spi::ExclusiveDevice;
 use embedded_sdmmc::SdCard;
 use grounded::uninit::GroundedArrayCell;
@@ -97,6 +99,10 @@ pub type Haptic<'a, 'b, MutexType> =
 /// Represents a structure for an external flash configuration using the QSPI protocol.
 pub type ExternalFlash<'d> = qspi::Qspi<'d>;
 
+/// `NorFlash` view of a region of the external flash's data partition,
+/// sharing the flash with other users through `M`.
+pub type DataFlash<'a, M> = Partition<'a, M, ExternalFlash<'static>>;
+
 bind_interrupts!(struct SpiIrq {
     SPIM3 => spim::InterruptHandler<peripherals::SPI3>;
     SPI2 => spim::InterruptHandler<peripherals::SPI2>;
@@ -111,6 +117,10 @@ bind_interrupts!(struct PmicTwimIrqs {
     TWISPI0 => twim::InterruptHandler<peripherals::TWISPI0>;
 });
 
+bind_interrupts!(struct SaadcIrqs {
+    SAADC => saadc::InterruptHandler;
+});
+
 bind_interrupts!(struct PdmIrqs {
     PDM => pdm::InterruptHandler<peripherals::PDM>;
 });
@@ -196,9 +206,10 @@ impl ImuResources {
         Icm45605::new(I2cDevice::new(bus), embassy_time::Delay)
     }
 
-    /// Configure IMU with an existing I2cDevice (for use with bus manager)
+    /// Configure IMU with an existing I2cDevice (for use with bus manager).
+    /// The driver does not borrow the resources, so `irq` stays available.
     pub async fn configure_with_device<'a, 'b, MutexType: RawMutex>(
-        &'a mut self,
+        &mut self,
         device: I2cDevice<'a, MutexType, twim::Twim<'b>>,
     ) -> Icm45605<I2cDevice<'a, MutexType, twim::Twim<'b>>, embassy_time::Delay>
     {
@@ -230,6 +241,44 @@ impl MicResources {
     }
 }
 
+/// VSYS over the voltage at the divider output.
+pub const VSYS_DIVIDER_RATIO: f32 = 2.0;
+/// SAADC full scale: the 0.6 V internal reference at a gain of 1/6.
+const SAADC_FULL_SCALE_V: f32 = 3.6;
+/// Samples averaged in hardware per reading.
+const SAADC_OVERSAMPLE: saadc::Oversample = saadc::Oversample::OVER8X;
+
+impl VsysSenseResources {
+    /// Measures VSYS, in volts, independently of the PMIC. The SAADC is
+    /// offset-calibrated before each reading and released afterwards.
+    pub async fn read_voltage(&mut self) -> f32 {
+        let mut config = saadc::Config::default();
+        config.resolution = saadc::Resolution::_12BIT;
+        config.oversample = SAADC_OVERSAMPLE;
+        let mut channel =
+            saadc::ChannelConfig::single_ended(self.ain.reborrow());
+        channel.gain = saadc::Gain::GAIN1_6;
+        channel.reference = saadc::Reference::INTERNAL;
+        // The divider has a high source impedance.
+        channel.time = saadc::Time::_40US;
+        interrupt::SAADC.set_priority(interrupt::Priority::P3);
+
+        let mut adc = saadc::Saadc::new(
+            self.saadc.reborrow(),
+            SaadcIrqs,
+            config,
+            [channel],
+        );
+        adc.calibrate().await;
+        let mut buf = [0i16; 1];
+        adc.sample(&mut buf).await;
+
+        // Noise around 0 V reads slightly negative in single-ended mode.
+        let raw = buf[0].max(0) as f32;
+        raw * SAADC_FULL_SCALE_V / 4096.0 * VSYS_DIVIDER_RATIO
+    }
+}
+
 impl Twim1BusResources {
     pub fn get_bus<'a, MutexType: RawMutex>(
         &'a mut self,
@@ -298,47 +347,171 @@ impl Spi3BusResources {
     }
 }
 
-impl SdCardResources {
-    pub fn get_card<'a>(
-        &'a mut self,
-    ) -> SdCard<
-        ExclusiveDevice<spim::Spim<'a>, Output<'a>, embassy_time::Delay>,
-        embassy_time::Delay,
-    > {
-        let mut config = spim::Config::default();
-        config.mode = spim::MODE_0;
-        config.frequency = spim::Frequency::K250;
-        interrupt::SPI2.set_priority(interrupt::Priority::P3);
+/// SD card on SPI2, as returned by [`SdCardResources::get_card`].
+pub type SdCardDevice<'a> = SdCard<
+    ExclusiveDevice<spim::Spim<'a>, Output<'a>, embassy_time::Delay>,
+    embassy_time::Delay,
+>;
+
+/// Time the card-detect switch is given to stop bouncing.
+const CARD_DETECT_DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Card-detect switch of the SD socket.
+pub struct CardDetect<'a> {
+    pin: Input<'a>,
+}
+
+impl<'a> CardDetect<'a> {
+    fn new(pin: Peri<'a, peripherals::P0_29>) -> Self {
+        let pin = Input::new(pin, Pull::Up);
+        // Let the pull-up charge the line before the first read.
+        block_for(Duration::from_micros(10));
+        Self { pin }
+    }
+
+    /// Whether a card is in the socket.
+    pub fn is_present(&self) -> bool {
+        self.pin.is_low()
+    }
+
+    /// Returns once a card is inserted and the switch has settled.
+    pub async fn wait_for_insert(&mut self) {
+        loop {
+            self.pin.wait_for_low().await;
+            Timer::after(CARD_DETECT_DEBOUNCE).await;
+            if self.is_present() {
+                return;
+            }
+        }
+    }
+
+    /// Returns once the card is removed and the switch has settled.
+    pub async fn wait_for_remove(&mut self) {
+        loop {
+            self.pin.wait_for_high().await;
+            Timer::after(CARD_DETECT_DEBOUNCE).await;
+            if !self.is_present() {
+                return;
+            }
+        }
+    }
+}
 
-        // We first need to create the spi driver with a low frequency clock to correctly
-        // initialize the SD card.
-        let mut cs_pin = Output::new(
+impl SdCardResources {
+    pub fn get_card<'a>(&'a mut self) -> SdCardDevice<'a> {
+        open_card(
+            self.spim.reborrow(),
+            self.sclk.reborrow(),
+            self.miso.reborrow(),
+            self.mosi.reborrow(),
             self.cs.reborrow(),
-            Level::High,
-            OutputDrive::Standard,
-        );
+        )
+    }
 
-        // Create SPI with final configuration directly
-        config.frequency = spim::Frequency::M16;
-        let spi = spim::Spim::new(
+    /// The card together with its detect switch, so a card in use can be
+    /// watched for removal.
+    pub fn get_card_with_detect<'a>(
+        &'a mut self,
+    ) -> (SdCardDevice<'a>, CardDetect<'a>) {
+        let card = open_card(
             self.spim.reborrow(),
-            SpiIrq,
             self.sclk.reborrow(),
             self.miso.reborrow(),
             self.mosi.reborrow(),
-            config.clone(),
+            self.cs.reborrow(),
         );
-        // Initialize SD card with dummy bytes
-        cs_pin.set_high();
-        // Note: SD card initialization is now handled by the SdCard driver itself
+        (card, CardDetect::new(self.detect.reborrow()))
+    }
 
-        let spi = ExclusiveDevice::new(spi, cs_pin, embassy_time::Delay)
-            .expect("Failed to create SD card spi device.");
-        SdCard::new(spi, embassy_time::Delay)
+    /// Whether a card is in the socket.
+    pub fn card_present(&mut self) -> bool {
+        CardDetect::new(self.detect.reborrow()).is_present()
     }
+
+    /// Returns once a card is inserted; immediately if one already is.
+    pub async fn wait_for_insert(&mut self) {
+        let mut detect = CardDetect::new(self.detect.reborrow());
+        if !detect.is_present() {
+            detect.wait_for_insert().await;
+        }
+    }
+
+    /// Returns once the card is removed; immediately if there is none.
+    pub async fn wait_for_remove(&mut self) {
+        let mut detect = CardDetect::new(self.detect.reborrow());
+        if detect.is_present() {
+            detect.wait_for_remove().await;
+        }
+    }
+}
+
+fn open_card<'a>(
+    spim: Peri<'a, peripherals::SPI2>,
+    sclk: Peri<'a, peripherals::P0_05>,
+    miso: Peri<'a, peripherals::P0_26>,
+    mosi: Peri<'a, peripherals::P0_07>,
+    cs: Peri<'a, peripherals::P1_08>,
+) -> SdCardDevice<'a> {
+    let mut config = spim::Config::default();
+    config.mode = spim::MODE_0;
+    config.frequency = spim::Frequency::K250;
+    interrupt::SPI2.set_priority(interrupt::Priority::P3);
+
+    // We first need to create the spi driver with a low frequency clock to correctly
+    // initialize the SD card.
+    let mut cs_pin = Output::new(cs, Level::High, OutputDrive::Standard);
+
+    // Create SPI with final configuration directly
+    config.frequency = spim::Frequency::M16;
+    let spi = spim::Spim::new(spim, SpiIrq, sclk, miso, mosi, config.clone());
+    // Initialize SD card with dummy bytes
+    cs_pin.set_high();
+    // Note: SD card initialization is now handled by the SdCard driver itself
+
+    let spi = ExclusiveDevice::new(spi, cs_pin, embassy_time::Delay)
+        .expect("Failed to create SD card spi device.");
+    SdCard::new(spi, embassy_time::Delay)
 }
 
+/// External flash layout. Must match the `DFU` and `EXTERNAL_STORAGE`
+/// regions in the `memory.x` of the app and the bootloader.
 impl ExternalFlashResources {
+    /// Size of the flash, in bytes.
+    pub const CAPACITY: u32 = 2048 * 1024;
+    /// Start of the DFU partition, where updates are staged.
+    pub const DFU_OFFSET: u32 = 0;
+    /// Size of the DFU partition.
+    pub const DFU_SIZE: u32 = 992 * 1024;
+    /// Start of the data partition. Firmware updates leave it alone and
+    /// it stays with the device when the SD card is swapped.
+    pub const DATA_OFFSET: u32 = 0xf8000;
+    /// Size of the data partition, the rest of the flash.
+    pub const DATA_SIZE: u32 = Self::CAPACITY - Self::DATA_OFFSET;
+
+    /// The whole data partition of `flash`.
+    pub fn data_partition<M: RawMutex>(
+        flash: &Mutex<M, ExternalFlash<'static>>,
+    ) -> DataFlash<'_, M> {
+        Self::data_region(flash, 0, Self::DATA_SIZE)
+    }
+
+    /// `size` bytes of the data partition of `flash`, starting `offset`
+    /// bytes in.
+    ///
+    /// # Panics
+    /// If the region does not fit in the data partition.
+    pub fn data_region<M: RawMutex>(
+        flash: &Mutex<M, ExternalFlash<'static>>,
+        offset: u32,
+        size: u32,
+    ) -> DataFlash<'_, M> {
+        assert!(
+            offset.checked_add(size).is_some_and(|end| end <= Self::DATA_SIZE),
+            "Region outside the data partition"
+        );
+        Partition::new(flash, Self::DATA_OFFSET + offset, size)
+    }
+
     /// Configures an external flash instance based on the defined pins.
     ///
     /// # Returns
@@ -349,7 +522,7 @@ impl ExternalFlashResources {
         });
 
         let mut config = qspi::Config::default();
-        config.capacity = 2048 * 1024; // 2048K-Byte
+        config.capacity = Self::CAPACITY;
         config.frequency = qspi::Frequency::M16;
         config.read_opcode = qspi::ReadOpcode::READ4IO;
         config.write_opcode = qspi::WriteOpcode::PP4O;
@@ -0,0 +1,49 @@
+use embassy_nrf::gpio::{Flex, Pin, Pull};
+use embassy_nrf::Peri;
+use embassy_time::{block_for, Duration};
+
+/// Time for the pin to settle after its pull is switched.
+const STRAP_SETTLE: Duration = Duration::from_micros(20);
+
+/// Hardware revision of the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BoardRev {
+    Sr6,
+    Sr7,
+}
+
+impl BoardRev {
+    /// The revision selected by the hardware feature this firmware is
+    /// built for.
+    pub const COMPILED: Self =
+        if cfg!(feature = "sr6") { Self::Sr6 } else { Self::Sr7 };
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Sr6 => "sr6",
+            Self::Sr7 => "sr7",
+        }
+    }
+
+    /// Reads the board-ID strap on `strap`: tied low on SR6, tied high on
+    /// SR7. Boards built before the strap leave it floating and report
+    /// `None`. The pin is left disconnected afterwards.
+    pub fn detect(strap: Peri<'_, impl Pin>) -> Option<Self> {
+        let mut pin = Flex::new(strap);
+        pin.set_as_input(Pull::Up);
+        block_for(STRAP_SETTLE);
+        let pulled_up = pin.is_high();
+        pin.set_as_input(Pull::Down);
+        block_for(STRAP_SETTLE);
+        let pulled_down = pin.is_high();
+        pin.set_as_disconnected();
+
+        match (pulled_up, pulled_down) {
+            (true, true) => Some(Self::Sr7),
+            (false, false) => Some(Self::Sr6),
+            // Follows the pull, so nothing drives it.
+            _ => None,
+        }
+    }
+}
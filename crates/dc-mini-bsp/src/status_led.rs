@@ -0,0 +1,73 @@
+use embassy_nrf::gpio::{AnyPin, Level, Output, OutputDrive, Pin};
+use embassy_nrf::{pwm, Peri};
+use smart_leds_trait::{SmartLedsWriteAsync, RGB8};
+use ws2812_nrf_pwm::Ws2812;
+
+/// PWM words for one WS2812: 24 bits and the reset word.
+const WS2812_WORDS: usize = 25;
+
+/// The board's indicator LED. The board module picks the backend; tasks
+/// write colors to it through [`SmartLedsWriteAsync`], and only the first
+/// color is shown.
+pub enum StatusLed<'d> {
+    /// A WS2812 (neopixel) driven by a PWM sequence.
+    Ws2812(Ws2812<'d, WS2812_WORDS>),
+    /// Discrete LEDs, one per color channel, lit while the channel is
+    /// non-zero. Driven low to light.
+    Gpio { red: Output<'d>, green: Output<'d>, blue: Output<'d> },
+}
+
+impl<'d> StatusLed<'d> {
+    pub fn ws2812(
+        pwm: Peri<'d, impl pwm::Instance>,
+        pin: Peri<'d, impl Pin>,
+    ) -> Self {
+        Self::Ws2812(Ws2812::new(pwm, pin))
+    }
+
+    pub fn gpio(
+        red: Peri<'d, impl Pin>,
+        green: Peri<'d, impl Pin>,
+        blue: Peri<'d, impl Pin>,
+    ) -> Self {
+        let off = |pin: Peri<'d, AnyPin>| {
+            Output::new(pin, Level::High, OutputDrive::Standard)
+        };
+        Self::Gpio {
+            red: off(red.into()),
+            green: off(green.into()),
+            blue: off(blue.into()),
+        }
+    }
+}
+
+impl SmartLedsWriteAsync for StatusLed<'_> {
+    type Error = pwm::Error;
+    type Color = RGB8;
+
+    async fn write<C, I>(&mut self, iterator: C) -> Result<(), Self::Error>
+    where
+        C: IntoIterator<Item = I>,
+        I: Into<Self::Color>,
+    {
+        match self {
+            Self::Ws2812(ws) => ws.write(iterator).await,
+            Self::Gpio { red, green, blue } => {
+                let color = iterator
+                    .into_iter()
+                    .next()
+                    .map_or(RGB8::default(), Into::into);
+                for (pin, value) in
+                    [(red, color.r), (green, color.g), (blue, color.b)]
+                {
+                    pin.set_level(if value > 0 {
+                        Level::Low
+                    } else {
+                        Level::High
+                    });
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use embedded_hal_async::i2c::I2c;
+
+/// I2C address of the nPM1300.
+const NPM1300_ADDR: u8 = 0x6b;
+/// VBUSIN USBCDETECTSTATUS: the CC1 comparator in bits 1:0 and CC2 in bits
+/// 3:2.
+const USBCDETECTSTATUS: [u8; 2] = [0x02, 0x05];
+/// VBUSIN VBUSINSTATUS: VBUS present in bit 0.
+const VBUSINSTATUS: [u8; 2] = [0x02, 0x07];
+const VBUSIN_PRESENT: u8 = 1 << 0;
+
+/// Current a USB-C source advertises through its Rp pull-up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UsbCurrent {
+    /// No Rp seen, e.g. nothing attached or a legacy A-to-C cable.
+    None,
+    /// Default USB power: 100 mA until the host configures the device.
+    Default,
+    /// 1.5 A.
+    A1_5,
+    /// 3 A.
+    A3_0,
+}
+
+impl UsbCurrent {
+    fn from_cc(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => Self::None,
+            1 => Self::Default,
+            2 => Self::A1_5,
+            _ => Self::A3_0,
+        }
+    }
+
+    /// Current that may be drawn without USB enumeration.
+    pub const fn milliamps(self) -> u16 {
+        match self {
+            Self::None | Self::Default => 100,
+            Self::A1_5 => 1500,
+            Self::A3_0 => 3000,
+        }
+    }
+}
+
+/// Which CC line the source's Rp is on, i.e. how the plug is inserted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CcOrientation {
+    Cc1,
+    Cc2,
+}
+
+/// USB-C attachment as seen by the PMIC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UsbCStatus {
+    /// VBUS is present on the PMIC's VBUS input.
+    pub attached: bool,
+    /// `None` while detached, or when the source has no Rp.
+    pub orientation: Option<CcOrientation>,
+    pub current: UsbCurrent,
+}
+
+/// USB-C sense of the nPM1300: its VBUS input status and the comparators
+/// on both CC lines. It only reads registers, so it can share the PMIC's
+/// bus with the PMIC driver.
+pub struct UsbCSense<B> {
+    bus: B,
+}
+
+impl<B: I2c> UsbCSense<B> {
+    pub fn new(bus: B) -> Self {
+        Self { bus }
+    }
+
+    pub async fn read(&mut self) -> Result<UsbCStatus, B::Error> {
+        let mut vbus = [0u8];
+        self.bus.write_read(NPM1300_ADDR, &VBUSINSTATUS, &mut vbus).await?;
+        let mut cc = [0u8];
+        self.bus.write_read(NPM1300_ADDR, &USBCDETECTSTATUS, &mut cc).await?;
+
+        let attached = vbus[0] & VBUSIN_PRESENT != 0;
+        let cc1 = UsbCurrent::from_cc(cc[0]);
+        let cc2 = UsbCurrent::from_cc(cc[0] >> 2);
+        let (orientation, current) = if !attached {
+            (None, UsbCurrent::None)
+        } else if cc1 != UsbCurrent::None {
+            (Some(CcOrientation::Cc1), cc1)
+        } else if cc2 != UsbCurrent::None {
+            (Some(CcOrientation::Cc2), cc2)
+        } else {
+            (None, UsbCurrent::None)
+        };
+        Ok(UsbCStatus { attached, orientation, current })
+    }
+}
@@ -17,9 +17,12 @@ def main():
         # Get battery level
         battery = client.get_battery_level()
         print("Battery:")
-        print(f"  Percentage: {battery.percentage}%")
-        print(f"  Voltage: {battery.voltage_mv} mV")
-        print(f"  Charging: {battery.charging}")
+        if battery is None:
+            print("  PMIC not responding")
+        else:
+            print(f"  Percentage: {battery.percentage}%")
+            print(f"  Voltage: {battery.voltage_mv} mV")
+            print(f"  Charging: {battery.charging}")
 
         # Get current ADS configuration
         config = client.get_ads_config()
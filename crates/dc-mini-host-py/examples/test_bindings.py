@@ -48,6 +48,9 @@ def test_battery_level(client):
     print("\nTesting battery level retrieval...")
     try:
         battery = client.get_battery_level()
+        if battery is None:
+            print("❌ Battery level unavailable: PMIC not responding")
+            return False
         print("✅ Battery level retrieved successfully:")
         print(f"  • Percentage: {battery.percentage}%")
         print(f"  • Voltage: {battery.voltage_mv} mV")
@@ -1,7 +1,8 @@
 use dc_mini_host::clients::UsbClient;
 use dc_mini_host::icd::{
-    AdsConfig, AdsDataFrame, AdsSample, BatteryLevel, CalFreq, CompThreshPos,
-    DeviceInfo, FLeadOff, Gain, ILeadOff, Mux, ProfileCommand, SampleRate,
+    AdsConfig, AdsDataFrame, AdsSample, BatteryStatus, CalFreq, ChargingState,
+    CompThreshPos, DeviceInfo, FLeadOff, Gain, ILeadOff, Mux, ProfileCommand,
+    SampleRate,
 };
 use pyo3::create_exception;
 use pyo3::exceptions::PyException;
@@ -56,6 +57,14 @@ struct PyAdsSample {
     pub gyro_y: Option<f32>,
     #[pyo3(get)]
     pub gyro_z: Option<f32>,
+    #[pyo3(get)]
+    pub quat_w: Option<f32>,
+    #[pyo3(get)]
+    pub quat_x: Option<f32>,
+    #[pyo3(get)]
+    pub quat_y: Option<f32>,
+    #[pyo3(get)]
+    pub quat_z: Option<f32>,
 }
 
 impl From<AdsSample> for PyAdsSample {
@@ -71,6 +80,10 @@ impl From<AdsSample> for PyAdsSample {
             gyro_x: sample.gyro_x,
             gyro_y: sample.gyro_y,
             gyro_z: sample.gyro_z,
+            quat_w: sample.quat_w,
+            quat_x: sample.quat_x,
+            quat_y: sample.quat_y,
+            quat_z: sample.quat_z,
         }
     }
 }
@@ -82,6 +95,11 @@ struct PyAdsDataFrame {
     #[pyo3(get)]
     pub timestamp: u64,
     #[pyo3(get)]
+    pub seq: u32,
+    /// Factor the device decimated the samples by.
+    #[pyo3(get)]
+    pub decimation: u8,
+    #[pyo3(get)]
     pub samples: Vec<PyAdsSample>,
     #[pyo3(get)]
     pub channel_data: Vec<Vec<i32>>, // Reorganized data for easier Python use
@@ -97,7 +115,8 @@ impl PyAdsDataFrame {
 }
 
 impl From<AdsDataFrame> for PyAdsDataFrame {
-    fn from(frame: AdsDataFrame) -> Self {
+    fn from(mut frame: AdsDataFrame) -> Self {
+        frame.restore_samples();
         let py_samples = frame
             .samples
             .iter()
@@ -114,6 +133,10 @@ impl From<AdsDataFrame> for PyAdsDataFrame {
                     gyro_x: sample.gyro_x,
                     gyro_y: sample.gyro_y,
                     gyro_z: sample.gyro_z,
+                    quat_w: sample.quat_w,
+                    quat_x: sample.quat_x,
+                    quat_y: sample.quat_y,
+                    quat_z: sample.quat_z,
                 }
             })
             .collect();
@@ -140,7 +163,13 @@ impl From<AdsDataFrame> for PyAdsDataFrame {
             }
         }
 
-        Self { timestamp: frame.ts, samples: py_samples, channel_data }
+        Self {
+            timestamp: frame.ts,
+            seq: frame.seq,
+            decimation: frame.decimation,
+            samples: py_samples,
+            channel_data,
+        }
     }
 }
 
@@ -180,8 +209,10 @@ struct PyUsbClient {
 
 #[pymethods]
 impl PyUsbClient {
+    /// Opens the unit with the given serial number, or the first one found.
     #[new]
-    fn new() -> PyResult<Self> {
+    #[pyo3(signature = (serial=None))]
+    fn new(serial: Option<String>) -> PyResult<Self> {
         let runtime = Runtime::new().map_err(|e| {
             PyException::new_err(format!(
                 "Failed to create Tokio runtime: {}",
@@ -190,7 +221,11 @@ impl PyUsbClient {
         })?;
 
         let client = runtime.block_on(async {
-            UsbClient::try_new().map_err(|e| {
+            match &serial {
+                Some(serial) => UsbClient::connect_serial(serial),
+                None => UsbClient::try_new(),
+            }
+            .map_err(|e| {
                 UsbConnectionError::new_err(format!(
                     "Failed to create USB client: {}",
                     e
@@ -273,12 +308,13 @@ impl PyUsbClient {
     }
 
     // Battery Service Methods
-    fn get_battery_level(&self) -> PyResult<PyBatteryLevel> {
+    /// Returns `None` if the device cannot read its PMIC.
+    fn get_battery_level(&self) -> PyResult<Option<PyBatteryLevel>> {
         let client = self.client.clone();
-        let level = self.runtime.block_on(async move {
-            client.get_battery_level().await.map_err(convert_error)
+        let status = self.runtime.block_on(async move {
+            client.get_battery_status().await.map_err(convert_error)
         })?;
-        Ok(PyBatteryLevel::from(level))
+        Ok(status.map(PyBatteryLevel::from))
     }
 
     // Device Info Service Methods
@@ -757,13 +793,12 @@ struct PyBatteryLevel {
     pub charging: bool,
 }
 
-impl From<BatteryLevel> for PyBatteryLevel {
-    fn from(_level: BatteryLevel) -> Self {
-        // Adjust based on your actual BatteryLevel structure
+impl From<BatteryStatus> for PyBatteryLevel {
+    fn from(status: BatteryStatus) -> Self {
         Self {
-            percentage: 100,  // Default value
-            voltage_mv: 4200, // Default value
-            charging: false,  // Default value
+            percentage: status.state_of_charge,
+            voltage_mv: status.voltage_mv,
+            charging: status.charging != ChargingState::NotCharging,
         }
     }
 }
@@ -0,0 +1,22 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    compile_control_proto();
+
+    println!("cargo:rerun-if-changed=protos/");
+    println!("cargo:rerun-if-changed=../dc-mini-icd/protos/");
+}
+
+#[cfg(feature = "grpc")]
+fn compile_control_proto() {
+    // The streamed frames are the ICD's own protobuf messages
+    tonic_prost_build::configure()
+        .build_client(false)
+        .extern_path(".ads", "::dc_mini_icd::proto")
+        .extern_path(".mic", "::dc_mini_icd::mic_proto")
+        .extern_path(".imu", "::dc_mini_icd::imu_proto")
+        .compile_protos(
+            &["protos/control.proto"],
+            &["protos", "../dc-mini-icd/protos"],
+        )
+        .unwrap();
+}
@@ -1,6 +1,7 @@
 use byteorder::{LittleEndian, WriteBytesExt};
 use chrono::DateTime;
 use clap::Parser;
+use dc_mini_host::fileio::dat::read_session_metadata;
 use dc_mini_icd::proto::AdsDataFrame;
 use prost::Message;
 use std::fs::File;
@@ -195,19 +196,39 @@ fn process_dat_file(
     };
     println!("Detected {} channels at {} Hz", num_channels, SAMPLE_RATE);
 
+    // Session metadata recorded by the device, used where no argument is given
+    let session = read_session_metadata(input_path)
+        .map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+        })?
+        .unwrap_or_default();
+
     // Create and initialize EDF header
     let mut header = EdfHeader::new(num_channels as u16);
-    header.patient_id = args.patient_id.clone().unwrap_or_default();
+    header.patient_id = args
+        .patient_id
+        .clone()
+        .unwrap_or_else(|| session.subject_code.to_string());
     header.recording_id = args.recording_id.clone().unwrap_or_else(|| {
-        input_path
+        let mut id = input_path
             .file_stem()
             .and_then(|s| s.to_str())
             .unwrap_or("UNKNOWN")
-            .to_string()
+            .to_string();
+        for field in
+            [&session.operator[..], &session.montage[..], &session.notes[..]]
+        {
+            if !field.is_empty() {
+                id.push(' ');
+                id.push_str(field);
+            }
+        }
+        id
     });
 
     // Convert timestamp to date/time strings
-    let start_time = DateTime::from_timestamp_micros(first_frame.ts as i64)
+    let start_us = session.start_epoch_us.unwrap_or(first_frame.ts);
+    let start_time = DateTime::from_timestamp_micros(start_us as i64)
         .expect("Invalid timestamp");
     header.start_date = start_time.format("%d.%m.%y").to_string();
     header.start_time = start_time.format("%H.%M.%S").to_string();
@@ -5,8 +5,11 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 
+use dc_mini_host::fileio::csv::CsvConfig;
 use dc_mini_host::fileio::edf::EdfConfig;
+use dc_mini_host::fileio::timebase::{self, TimebaseConfig};
 use dc_mini_host::fileio::{self, ConversionConfig, Error, Result};
+use dc_mini_host::integrity::IntegrityReport;
 
 #[derive(Default, Serialize, Deserialize)]
 struct SavedMetadata {
@@ -44,6 +47,8 @@ struct ConverterApp {
     output_path: Option<PathBuf>,
     selected_format: String,
     metadata: SavedMetadata,
+    csv_config: CsvConfig,
+    timebase: TimebaseConfig,
     error_message: String,
     success_message: String,
     num_channels: Option<usize>,
@@ -60,7 +65,7 @@ impl ConverterApp {
 
     fn handle_input_file_selected(&mut self, path: PathBuf) -> Result<()> {
         self.input_path = Some(path.clone());
-        self.output_path = Some(path.with_extension("edf"));
+        self.output_path = Some(path.with_extension(&self.selected_format));
         let mut reader = fileio::create_reader(&path)?;
         let metadata = reader.read_header()?;
         self.num_channels = Some(metadata.num_channels);
@@ -80,9 +85,36 @@ impl ConverterApp {
         }
     }
 
-    fn process_file(&self) -> Result<()> {
+    fn convert(config: &ConversionConfig) -> Result<()> {
+        let mut reader = fileio::create_reader(config.input_path())?;
+        let mut metadata = reader.read_header()?;
+        let annotations = reader.read_annotations()?;
+        let records = reader.read_data()?;
+        let records =
+            timebase::apply(config.timebase(), &mut metadata, records)?;
+
+        let mut writer = fileio::create_writer(config)?;
+        writer.set_metadata(metadata);
+        writer.write_header()?;
+
+        writer.set_annotations(annotations);
+        writer.write_data(records)?;
+
+        writer.finalize()
+    }
+
+    /// Converts the input and checks it for lost data, which the export
+    /// marks where the format allows.
+    fn process_file(&self) -> Result<IntegrityReport> {
+        self.export()?;
+        let mut reader =
+            fileio::create_reader(self.input_path.as_ref().unwrap())?;
+        reader.integrity_report()
+    }
+
+    fn export(&self) -> Result<()> {
         match self.selected_format.as_str() {
-            "edf" => {
+            format @ ("edf" | "bdf") => {
                 if self.metadata.hospital_code.is_empty() {
                     return Err(Error::InvalidInput(
                         "Hospital code is required".to_string(),
@@ -149,27 +181,38 @@ impl ConverterApp {
                     electrode_labels,
                 )?;
 
-                let config = ConversionConfig::Edf {
-                    input_path: self.input_path.clone().unwrap(),
-                    output_path: self.output_path.clone().unwrap(),
-                    config: edf_config,
+                let input_path = self.input_path.clone().unwrap();
+                let output_path = self.output_path.clone().unwrap();
+                let timebase = self.timebase.clone();
+                let config = if format == "bdf" {
+                    ConversionConfig::Bdf {
+                        input_path,
+                        output_path,
+                        config: edf_config,
+                        timebase,
+                    }
+                } else {
+                    ConversionConfig::Edf {
+                        input_path,
+                        output_path,
+                        config: edf_config,
+                        timebase,
+                    }
                 };
 
-                let mut reader =
-                    fileio::create_reader(&self.input_path.clone().unwrap())?;
-                let metadata = reader.read_header()?;
-
-                let mut writer = fileio::create_writer(&config)?;
-                writer.set_metadata(metadata);
-                writer.write_header()?;
-
-                let records = reader.read_data()?;
-                writer.write_data(records)?;
-
-                writer.finalize()?;
-
-                Ok(())
+                Self::convert(&config)
             }
+            "csv" => Self::convert(&ConversionConfig::Csv {
+                input_path: self.input_path.clone().unwrap(),
+                output_path: self.output_path.clone().unwrap(),
+                config: self.csv_config.clone(),
+                timebase: self.timebase.clone(),
+            }),
+            "xdf" => fileio::xdf::convert(
+                self.input_path.as_ref().unwrap(),
+                self.output_path.as_ref().unwrap(),
+                &self.timebase,
+            ),
             _ => Err(Error::InvalidInput(format!(
                 "Unsupported output format: {}",
                 self.selected_format
@@ -194,15 +237,37 @@ impl eframe::App for ConverterApp {
                             "edf".to_string(),
                             "EDF+",
                         );
+                        ui.selectable_value(
+                            &mut self.selected_format,
+                            "bdf".to_string(),
+                            "BDF+ (24-bit)",
+                        );
+                        ui.selectable_value(
+                            &mut self.selected_format,
+                            "csv".to_string(),
+                            "CSV",
+                        );
+                        ui.selectable_value(
+                            &mut self.selected_format,
+                            "xdf".to_string(),
+                            "XDF (EEG, IMU, audio, markers)",
+                        );
                     });
             });
+            // Keep the output extension in step with the format
+            if let Some(path) = &mut self.output_path {
+                path.set_extension(&self.selected_format);
+            }
 
             ui.add_space(10.0);
 
             ui.horizontal(|ui| {
                 if ui.button("Select Input File").clicked() {
                     if let Some(path) = FileDialog::new()
-                        .add_filter("DC Mini Recording", &["dat", "DAT", ""])
+                        .add_filter(
+                            "DC Mini Recording",
+                            &["dat", "DAT", "dcs", "DCS", ""],
+                        )
                         .pick_file()
                     {
                         if let Err(e) = self.handle_input_file_selected(path) {
@@ -219,7 +284,10 @@ impl eframe::App for ConverterApp {
             ui.horizontal(|ui| {
                 if ui.button("Select Output Location").clicked() {
                     if let Some(path) = FileDialog::new()
-                        .add_filter("EDF", &["edf"])
+                        .add_filter(
+                            self.selected_format.to_uppercase(),
+                            &[self.selected_format.as_str()],
+                        )
                         .save_file()
                     {
                         self.output_path = Some(path);
@@ -232,7 +300,51 @@ impl eframe::App for ConverterApp {
 
             ui.add_space(20.0);
 
-            if self.selected_format == "edf" {
+            ui.group(|ui| {
+                ui.heading("Timebase");
+                ui.checkbox(
+                    &mut self.timebase.smooth,
+                    "Smooth timestamps (removes BLE jitter)",
+                );
+                ui.horizontal(|ui| {
+                    let mut resample = self.timebase.resample_rate.is_some();
+                    ui.checkbox(&mut resample, "Resample to");
+                    let mut rate =
+                        self.timebase.resample_rate.unwrap_or(250.0);
+                    ui.add_enabled(
+                        resample,
+                        egui::DragValue::new(&mut rate)
+                            .range(1.0..=16000.0)
+                            .suffix(" Hz"),
+                    );
+                    self.timebase.resample_rate = resample.then_some(rate);
+                });
+            });
+
+            ui.add_space(10.0);
+
+            if self.selected_format == "csv" {
+                ui.group(|ui| {
+                    ui.heading("CSV Options");
+                    ui.horizontal(|ui| {
+                        ui.label("Delimiter:");
+                        let delimiter = &mut self.csv_config.delimiter;
+                        ui.radio_value(delimiter, ',', "Comma");
+                        ui.radio_value(delimiter, ';', "Semicolon");
+                        ui.radio_value(delimiter, '\t', "Tab");
+                    });
+                    ui.checkbox(
+                        &mut self.csv_config.include_timestamps,
+                        "Timestamp column (seconds)",
+                    );
+                    ui.checkbox(
+                        &mut self.csv_config.physical_units,
+                        "Values in microvolts instead of raw ADC codes",
+                    );
+                });
+            }
+
+            if matches!(self.selected_format.as_str(), "edf" | "bdf") {
                 ui.group(|ui| {
                     ui.heading("Hospital Information");
                     ui.horizontal(|ui| {
@@ -338,11 +450,18 @@ impl eframe::App for ConverterApp {
                 .clicked()
             {
                 match self.process_file() {
-                    Ok(_) => {
+                    Ok(report) if report.is_complete() => {
                         self.error_message.clear();
                         self.success_message =
                             "File converted successfully!".to_string();
                     }
+                    Ok(report) => {
+                        self.error_message.clear();
+                        self.success_message = format!(
+                            "File converted successfully! The recording has \
+                             {report}."
+                        );
+                    }
                     Err(e) => {
                         self.success_message.clear();
                         self.error_message = format!("Error: {}", e);
@@ -0,0 +1,80 @@
+use clap::{Parser, Subcommand};
+use dc_mini_icd::DfuSignature;
+use ed25519_dalek::{Signer, SigningKey};
+use sha2::{Digest, Sha512};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "dfu-sign", about = "Sign DC-Mini firmware for DFU")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a signing key pair. Build dc-mini-boot with
+    /// DC_MINI_DFU_PUBLIC_KEY pointing at the public key.
+    Keygen {
+        /// Secret key output; the public key is written next to it with a
+        /// `.pub` extension
+        key: PathBuf,
+    },
+    /// Append a signature trailer to a firmware binary
+    Sign {
+        /// Secret key from `keygen`
+        #[arg(long)]
+        key: PathBuf,
+        /// Firmware binary to sign
+        firmware: PathBuf,
+        /// Signed image output
+        output: PathBuf,
+    },
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match Args::parse().command {
+        Command::Keygen { key } => {
+            let signing_key = SigningKey::generate(&mut rand::rngs::OsRng);
+            let public = key.with_extension("pub");
+            std::fs::write(&key, signing_key.to_bytes())?;
+            std::fs::write(&public, signing_key.verifying_key().to_bytes())?;
+            println!(
+                "Wrote secret key {} and public key {}",
+                key.display(),
+                public.display()
+            );
+        }
+        Command::Sign { key, firmware, output } => {
+            let secret: [u8; 32] = std::fs::read(&key)?
+                .try_into()
+                .map_err(|_| "Secret key must be 32 bytes")?;
+            let signing_key = SigningKey::from_bytes(&secret);
+
+            let mut image = std::fs::read(&firmware)?;
+            if DfuSignature::from_image(&image).is_some() {
+                return Err("Firmware is already signed".into());
+            }
+
+            let digest = Sha512::digest(&image);
+            let trailer = DfuSignature {
+                firmware_len: image.len() as u32,
+                signature: signing_key.sign(&digest).to_bytes(),
+            };
+            image.resize(
+                DfuSignature::offset(trailer.firmware_len) as usize,
+                0xFF,
+            );
+            image.extend_from_slice(&trailer.to_bytes());
+            std::fs::write(&output, &image)?;
+            println!(
+                "Signed {} ({} bytes) -> {} ({} bytes)",
+                firmware.display(),
+                trailer.firmware_len,
+                output.display(),
+                image.len()
+            );
+        }
+    }
+    Ok(())
+}
@@ -1,12 +1,16 @@
 use clap::Parser;
-use dc_mini_host::clients::usb::UsbClient;
+use dc_mini_host::UsbClient;
+use dc_mini_icd::DfuSignature;
 use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "dfu", about = "DC-Mini USB DFU firmware updater")]
 struct Args {
-    /// Path to the firmware binary file
+    /// Path to the firmware binary file, signed with `dfu-sign`
     firmware: PathBuf,
+    /// Serial number of the unit to update, when several are plugged in
+    #[arg(long)]
+    serial: Option<String>,
 }
 
 #[tokio::main]
@@ -24,6 +28,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         return Err("Firmware file is empty".into());
     }
 
+    // The device rejects unsigned images only after the whole transfer.
+    if DfuSignature::from_image(&firmware).is_none() {
+        return Err("Firmware is not signed, sign it with dfu-sign".into());
+    }
+
     if firmware.len() > 992 * 1024 {
         return Err(format!(
             "Firmware too large: {} bytes (max {} bytes)",
@@ -34,7 +43,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     }
 
     println!("Connecting to DC-Mini via USB...");
-    let client = UsbClient::try_new()?;
+    let client = match &args.serial {
+        Some(serial) => UsbClient::connect_serial(serial)?,
+        None => UsbClient::try_new()?,
+    };
     println!("Connected.");
 
     client.dfu_upload(&firmware).await?;
@@ -0,0 +1,60 @@
+//! Serves a DC-Mini over gRPC. Built with the `grpc` feature:
+//!
+//! ```text
+//! cargo run -p dc-mini-host --features grpc --bin grpc-server
+//! ```
+//!
+//! Clients are generated from `protos/control.proto`, which imports the
+//! frame messages of the ICD, e.g. for Python:
+//!
+//! ```text
+//! python -m grpc_tools.protoc -I crates/dc-mini-host/protos \
+//!     -I crates/dc-mini-icd/protos --python_out=. --grpc_python_out=. \
+//!     control.proto ads.proto mic.proto imu.proto
+//! ```
+
+use clap::Parser;
+use dc_mini_host::grpc::DcMiniService;
+use dc_mini_host::{DeviceConnection, DeviceFilter};
+use std::net::SocketAddr;
+
+#[derive(Parser)]
+#[command(
+    name = "grpc-server",
+    about = "Control and stream a DC-Mini over gRPC from any language"
+)]
+struct Args {
+    /// Address to listen on; use 0.0.0.0 to accept other machines
+    #[arg(long, default_value = "127.0.0.1:50051")]
+    addr: SocketAddr,
+    /// Serial number of the unit to serve, when several are plugged in
+    #[arg(long)]
+    serial: Option<String>,
+    /// Nickname of the unit to serve
+    #[arg(long)]
+    name: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let args = Args::parse();
+
+    let filter = DeviceFilter {
+        serial: args.serial,
+        name: args.name,
+        ..DeviceFilter::default()
+    };
+    println!("Connecting to DC-Mini...");
+    let connection = DeviceConnection::connect(&filter).await?;
+    println!("Connected over {}.", connection.transport());
+    connection.sync_time().await?;
+
+    let service =
+        DcMiniService::new(&tokio::runtime::Handle::current(), connection);
+    println!("Serving gRPC on {}", args.addr);
+    tonic::transport::Server::builder()
+        .add_service(service.into_server())
+        .serve(args.addr)
+        .await?;
+    Ok(())
+}
@@ -1,13 +1,36 @@
+use clap::Parser;
+use dc_mini_host::icd::{imu_proto, mic_proto, proto, SampleRate};
 use dc_mini_host::ui::DevicePanel;
+use dc_mini_host::{
+    AdsDataFrames, MicDataFrames, QuaternionFrames, ReplaySource,
+};
+use futures::StreamExt;
+use prost::Message;
 use rerun::blueprint::{
-    Blueprint, BlueprintPanel, ContainerLike, SelectionPanel, TimePanel,
-    TimeSeriesView, Vertical,
+    Blueprint, BlueprintPanel, ContainerLike, Horizontal, SelectionPanel,
+    Spatial3DView, TimePanel, TimeSeriesView, Vertical,
 };
 use rerun::external::{
     eframe, egui, re_crash_handler, re_grpc_server, re_log, re_memory,
     re_sdk_types::blueprint::components::PanelState, re_viewer,
 };
 use rerun::SeriesLines;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(
+    name = "gui-rr",
+    about = "DC-Mini control panel with a Rerun viewer"
+)]
+struct Args {
+    /// Play a .dat or .dcs recording into the viewer instead of the
+    /// streams of a connected device
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// Playback speed of --replay, relative to real time
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
+}
 
 // Use memory allocator for Rerun
 #[global_allocator]
@@ -53,29 +76,43 @@ fn create_blueprint() -> Blueprint {
                 .with_origin("/ads")
                 .with_defaults(&line_defaults),
         ),
+        ContainerLike::from(Horizontal::new(vec![
+            ContainerLike::from(Vertical::new(vec![
+                ContainerLike::from(
+                    TimeSeriesView::new("Accelerometer")
+                        .with_origin("/imu")
+                        .with_contents([
+                            "$origin/accel_x",
+                            "$origin/accel_y",
+                            "$origin/accel_z",
+                        ])
+                        .with_defaults(&line_defaults),
+                ),
+                ContainerLike::from(
+                    TimeSeriesView::new("Gyroscope")
+                        .with_origin("/imu")
+                        .with_contents([
+                            "$origin/gyro_x",
+                            "$origin/gyro_y",
+                            "$origin/gyro_z",
+                        ])
+                        .with_defaults(&line_defaults),
+                ),
+            ])),
+            ContainerLike::from(
+                Spatial3DView::new("Orientation").with_origin("/imu/3d"),
+            ),
+        ])),
         ContainerLike::from(
-            TimeSeriesView::new("Accelerometer")
-                .with_origin("/imu")
-                .with_contents([
-                    "$origin/accel_x",
-                    "$origin/accel_y",
-                    "$origin/accel_z",
-                ])
-                .with_defaults(&line_defaults),
-        ),
-        ContainerLike::from(
-            TimeSeriesView::new("Gyroscope")
-                .with_origin("/imu")
-                .with_contents([
-                    "$origin/gyro_x",
-                    "$origin/gyro_y",
-                    "$origin/gyro_z",
-                ])
+            TimeSeriesView::new("Microphone")
+                .with_origin("/mic")
+                .with_contents(["$origin/audio"])
                 .with_defaults(&line_defaults),
         ),
         ContainerLike::from(
-            TimeSeriesView::new("Microphone")
+            TimeSeriesView::new("Mic level (dBFS)")
                 .with_origin("/mic")
+                .with_contents(["$origin/level_dbfs"])
                 .with_defaults(&line_defaults),
         ),
     ]))
@@ -85,8 +122,64 @@ fn create_blueprint() -> Blueprint {
     .with_time_panel(TimePanel::new().with_state(PanelState::Collapsed))
 }
 
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Feeds a recording to the same logging callbacks the device panel uses
+/// for live streams, decoding the frames as they come from a BLE device.
+async fn replay(
+    source: ReplaySource,
+    on_ads: Box<dyn Fn(SampleRate, AdsDataFrames) + Send>,
+    on_mic: Box<dyn Fn(MicDataFrames) + Send>,
+    on_quaternion: Box<dyn Fn(QuaternionFrames) + Send>,
+) -> Result<(), BoxError> {
+    let sample_rate = source.sample_rate();
+    // Subscribe before starting, so no early frames are missed
+    let ads = source.notify_ads_stream().await?.for_each(move |data| {
+        if let Ok(Ok(mut frame)) =
+            data.map(|data| proto::AdsDataFrame::decode(&data[..]))
+        {
+            frame.restore_samples();
+            on_ads(sample_rate, AdsDataFrames::Proto(frame));
+        }
+        async {}
+    });
+    let mic = source.notify_mic_stream().await?.for_each(move |data| {
+        if let Ok(Ok(frame)) =
+            data.map(|data| mic_proto::MicDataFrame::decode(&data[..]))
+        {
+            on_mic(MicDataFrames::Proto(frame));
+        }
+        async {}
+    });
+    let quaternion =
+        source.notify_quaternion_stream().await?.for_each(move |data| {
+            if let Ok(Ok(q)) =
+                data.map(|data| imu_proto::ImuQuaternion::decode(&data[..]))
+            {
+                on_quaternion(QuaternionFrames::Proto(q));
+            }
+            async {}
+        });
+
+    source.start_streaming().await?;
+    source.start_mic_streaming().await?;
+    source.start_quaternion_streaming(0).await?;
+    println!("Replaying {}", source.path().display());
+    futures::join!(ads, mic, quaternion);
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    if args.speed <= 0.0 {
+        return Err("--speed must be positive".into());
+    }
+    let replay_source = match &args.replay {
+        Some(path) => Some(ReplaySource::open(path)?.with_speed(args.speed)),
+        None => None,
+    };
+
     let main_thread_token =
         re_viewer::MainThreadToken::i_promise_i_am_on_the_main_thread();
 
@@ -140,14 +233,31 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             let handle = tokio::runtime::Handle::current();
 
-            Ok(Box::new(DcMiniApp {
-                rerun_app,
-                device_panel: DevicePanel::new(
+            let device_panel = match replay_source {
+                Some(source) => {
+                    handle.spawn(async move {
+                        let played = replay(
+                            source,
+                            dc_mini_host::log_ads_frame(recording.clone()),
+                            dc_mini_host::log_mic_frame(recording.clone()),
+                            dc_mini_host::log_quaternion_frame(recording),
+                        );
+                        if let Err(e) = played.await {
+                            println!("Replay failed: {e}");
+                        }
+                    });
+                    // Devices can still be configured, but their streams
+                    // are not logged over the recording
+                    DevicePanel::new(handle, None, None)
+                }
+                None => DevicePanel::new(
                     handle,
                     Some(dc_mini_host::log_ads_frame(recording.clone())),
                     Some(dc_mini_host::log_mic_frame(recording)),
                 ),
-            }))
+            };
+
+            Ok(Box::new(DcMiniApp { rerun_app, device_panel }))
         }),
     )?;
 
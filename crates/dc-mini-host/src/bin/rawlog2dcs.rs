@@ -0,0 +1,33 @@
+use clap::Parser;
+use dc_mini_host::fileio::rawlog::RawLogReader;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(
+    author,
+    version,
+    about = "Extract DC-Mini sessions from a raw-log SD card or image"
+)]
+struct Args {
+    /// SD card image or raw block device, e.g. /dev/sdb
+    input: PathBuf,
+
+    /// Directory the .dcs session files are written to
+    #[arg(short, long, default_value = ".")]
+    output: PathBuf,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let mut reader = RawLogReader::new(&args.input)?;
+    println!("Raw log generation {}", reader.superblock().generation);
+
+    let paths = reader.extract(&args.output)?;
+    for path in &paths {
+        let size = std::fs::metadata(path)?.len();
+        println!("{} ({} bytes)", path.display(), size);
+    }
+    println!("Extracted {} session(s)", paths.len());
+    Ok(())
+}
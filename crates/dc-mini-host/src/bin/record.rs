@@ -0,0 +1,131 @@
+use clap::Parser;
+use dc_mini_host::{DeviceConnection, DeviceFilter, Recorder};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(
+    name = "record",
+    about = "Stream from a DC-Mini and record to a .dcs file on this computer"
+)]
+struct Args {
+    /// Output .dcs file, convertible with dc-convert-gui
+    output: PathBuf,
+    /// Serial number of the unit to record, when several are plugged in
+    #[arg(long)]
+    serial: Option<String>,
+    /// Nickname of the unit to record
+    #[arg(long)]
+    name: Option<String>,
+    /// Stop after this many seconds instead of waiting for Enter
+    #[arg(long)]
+    duration: Option<u64>,
+    /// Also stream and record the microphone
+    #[arg(long)]
+    mic: bool,
+    /// Also stream and record the IMU orientation
+    #[arg(long)]
+    imu: bool,
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+async fn start_streams(
+    connection: &DeviceConnection,
+    args: &Args,
+) -> Result<(), BoxError> {
+    match connection {
+        DeviceConnection::Usb(client) => {
+            client.start_streaming().await?;
+            if args.mic {
+                client.start_mic_streaming().await?;
+            }
+            if args.imu && !client.start_quaternion_streaming(0).await? {
+                println!("Device has no IMU, not recording orientation");
+            }
+        }
+        DeviceConnection::Ble(client) => {
+            client.start_streaming().await?;
+            if args.mic {
+                client.start_mic_streaming().await?;
+            }
+            if args.imu {
+                client.start_quaternion_streaming(0).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn stop_streams(
+    connection: &DeviceConnection,
+    args: &Args,
+) -> Result<(), BoxError> {
+    match connection {
+        DeviceConnection::Usb(client) => {
+            client.stop_streaming().await?;
+            if args.mic {
+                client.stop_mic_streaming().await?;
+            }
+            if args.imu {
+                client.stop_quaternion_streaming().await?;
+            }
+        }
+        DeviceConnection::Ble(client) => {
+            client.stop_streaming().await?;
+            if args.mic {
+                client.stop_mic_streaming().await?;
+            }
+            if args.imu {
+                client.stop_quaternion_streaming().await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), BoxError> {
+    let args = Args::parse();
+
+    let filter = DeviceFilter {
+        serial: args.serial.clone(),
+        name: args.name.clone(),
+        ..DeviceFilter::default()
+    };
+    println!("Connecting to DC-Mini...");
+    let connection = DeviceConnection::connect(&filter).await?;
+    println!("Connected over {}.", connection.transport());
+    connection.sync_time().await?;
+
+    let recorder = Recorder::start(
+        &tokio::runtime::Handle::current(),
+        connection.clone(),
+        &args.output,
+    )?;
+    start_streams(&connection, &args).await?;
+    println!("Recording to {}", args.output.display());
+
+    match args.duration {
+        Some(secs) => tokio::time::sleep(Duration::from_secs(secs)).await,
+        None => {
+            println!("Press Enter to stop.");
+            dc_mini_host::read_line().await;
+        }
+    }
+
+    if let Err(e) = stop_streams(&connection, &args).await {
+        println!("Failed to stop streaming: {e}");
+    }
+    let records = recorder.records();
+    let elapsed = recorder.elapsed();
+    let report =
+        tokio::task::spawn_blocking(move || recorder.stop()).await??;
+    println!(
+        "Saved {records} records ({:.1} s) to {}",
+        elapsed.as_secs_f64(),
+        args.output.display()
+    );
+    println!("{report:#}");
+    Ok(())
+}
@@ -1,5 +1,6 @@
 use dc_mini_icd::{
-    self as icd, CalFreq, CompThreshPos, FLeadOff, ILeadOff, SampleRate,
+    self as icd, AdsCodec, CalFreq, CompThreshPos, FLeadOff, ILeadOff,
+    SampleRate,
 };
 use futures::Stream;
 use futures_lite::StreamExt;
@@ -22,6 +23,12 @@ mod uuids {
         bluest::Uuid::from_u128(0x32200000_af46_43af_a0ba_4dbeb457f51c);
     pub const MIC_SERVICE_UUID: bluest::Uuid =
         bluest::Uuid::from_u128(0x33100000_af46_43af_a0ba_4dbeb457f51c);
+    pub const IMU_SERVICE_UUID: bluest::Uuid =
+        bluest::Uuid::from_u128(0x34100000_af46_43af_a0ba_4dbeb457f51c);
+    pub const TIME_SERVICE_UUID: bluest::Uuid =
+        bluest::Uuid::from_u128(0x32400000_af46_43af_a0ba_4dbeb457f51c);
+    pub const FILE_SERVICE_UUID: bluest::Uuid =
+        bluest::Uuid::from_u128(0x32500000_af46_43af_a0ba_4dbeb457f51c);
 
     // Battery Service Characteristics
     pub const BATTERY_LEVEL_UUID: bluest::Uuid =
@@ -49,6 +56,20 @@ mod uuids {
     pub const SESSION_CMD_UUID: bluest::Uuid =
         bluest::Uuid::from_u128(0x32200004_af46_43af_a0ba_4dbeb457f51c);
 
+    // Time Service Characteristics
+    pub const TIME_EXCHANGE_UUID: bluest::Uuid =
+        bluest::Uuid::from_u128(0x32400001_af46_43af_a0ba_4dbeb457f51c);
+    pub const TIME_SAMPLE_UUID: bluest::Uuid =
+        bluest::Uuid::from_u128(0x32400002_af46_43af_a0ba_4dbeb457f51c);
+
+    // File Service Characteristics
+    pub mod file {
+        pub const CONTROL_UUID: bluest::Uuid =
+            bluest::Uuid::from_u128(0x32500001_af46_43af_a0ba_4dbeb457f51c);
+        pub const DATA_UUID: bluest::Uuid =
+            bluest::Uuid::from_u128(0x32500002_af46_43af_a0ba_4dbeb457f51c);
+    }
+
     // Mic Service Characteristics
     pub mod mic {
         pub const GAIN_DB_UUID: bluest::Uuid =
@@ -61,6 +82,16 @@ mod uuids {
             bluest::Uuid::from_u128(0x33000300_af46_43af_a0ba_4dbeb457f51c);
     }
 
+    // IMU Service Characteristics
+    pub mod imu {
+        pub const QUATERNION_RATE_UUID: bluest::Uuid =
+            bluest::Uuid::from_u128(0x34000000_af46_43af_a0ba_4dbeb457f51c);
+        pub const QUATERNION_STREAM_UUID: bluest::Uuid =
+            bluest::Uuid::from_u128(0x34000200_af46_43af_a0ba_4dbeb457f51c);
+        pub const COMMAND_UUID: bluest::Uuid =
+            bluest::Uuid::from_u128(0x34000300_af46_43af_a0ba_4dbeb457f51c);
+    }
+
     // ADS Service Characteristics
     pub mod ads {
         // Characteristic UUIDs
@@ -125,11 +156,98 @@ mod uuids {
             bluest::Uuid::from_u128(0x32000200_af46_43af_a0ba_4dbeb457f51c);
         pub const COMMAND_UUID: bluest::Uuid =
             bluest::Uuid::from_u128(0x32000300_af46_43af_a0ba_4dbeb457f51c);
+        pub const STREAM_CODEC_UUID: bluest::Uuid =
+            bluest::Uuid::from_u128(0x32000302_af46_43af_a0ba_4dbeb457f51c);
     }
 }
 
 use uuids::ads::*;
 
+/// File service requests, answered on the control characteristic as
+/// `[op, ok, size (u32 LE), message…]`.
+mod file_op {
+    pub const OPEN: u8 = 0x01;
+    pub const READ: u8 = 0x02;
+    pub const CLOSE: u8 = 0x03;
+}
+
+/// Most file bytes the device sends in one data notification.
+const FILE_CHUNK_LEN: u16 = 239;
+
+/// Waits for the answer to file request `op`. Returns the file size.
+async fn file_reply(
+    replies: &mut (impl Stream<Item = bluest::Result<Vec<u8>>> + Unpin),
+    op: u8,
+) -> Result<u32, Box<dyn Error + Send + Sync>> {
+    loop {
+        let reply = replies
+            .next()
+            .await
+            .ok_or("File control notifications ended")??;
+        let [reply_op, ok, s0, s1, s2, s3, message @ ..] = reply.as_slice()
+        else {
+            return Err("Malformed file reply".into());
+        };
+        if *reply_op != op {
+            continue;
+        }
+        if *ok == 0 {
+            return Err(String::from_utf8_lossy(message).into_owned().into());
+        }
+        return Ok(u32::from_le_bytes([*s0, *s1, *s2, *s3]));
+    }
+}
+
+use super::discovery::{DeviceFilter, DiscoveredDevice, Transport};
+
+/// Devices connected to this host, then those advertising within
+/// `scan_time`. Each device is listed once, with its latest RSSI.
+pub(crate) async fn scan(
+    scan_time: std::time::Duration,
+) -> Result<Vec<DiscoveredDevice>, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(adapter) = bluest::Adapter::default().await else {
+        return Ok(Vec::new());
+    };
+    adapter.wait_available().await?;
+
+    let mut found: Vec<DiscoveredDevice> = adapter
+        .connected_devices_with_services(&[uuids::ADS_SERVICE_UUID])
+        .await?
+        .into_iter()
+        .map(|device| DiscoveredDevice {
+            transport: Transport::Ble,
+            serial: None,
+            ble_id: Some(device.id()),
+            rssi: None,
+            name: device.name().ok(),
+        })
+        .collect();
+
+    let mut scan = adapter.scan(&[uuids::ADS_SERVICE_UUID]).await?;
+    let _ = tokio::time::timeout(scan_time, async {
+        while let Some(adv) = scan.next().await {
+            let id = adv.device.id();
+            let name =
+                adv.adv_data.local_name.or_else(|| adv.device.name().ok());
+            match found.iter_mut().find(|d| d.ble_id.as_ref() == Some(&id)) {
+                Some(device) => {
+                    device.rssi = adv.rssi.or(device.rssi);
+                    device.name = name.or(device.name.take());
+                }
+                None => found.push(DiscoveredDevice {
+                    transport: Transport::Ble,
+                    serial: None,
+                    ble_id: Some(id),
+                    rssi: adv.rssi,
+                    name,
+                }),
+            }
+        }
+    })
+    .await;
+    Ok(found)
+}
+
 /// BLE client for communicating with the device
 pub struct BleClient {
     pub device: bluest::Device,
@@ -139,31 +257,75 @@ pub struct BleClient {
 }
 
 impl BleClient {
+    /// Connects to the first device found.
     pub async fn try_new(
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::connect(&DeviceFilter::default()).await
+    }
+
+    /// Connects to the first device advertising `name`.
+    pub async fn connect_name(
+        name: &str,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::connect(&DeviceFilter::name(name)).await
+    }
+
+    /// Connects to the device with the id [`discover`] reported.
+    ///
+    /// [`discover`]: super::discover
+    pub async fn connect_address(
+        id: &bluest::DeviceId,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::connect(&DeviceFilter::ble_id(id.clone())).await
+    }
+
+    /// Connects to the first device `filter` matches, scanning until one
+    /// shows up.
+    pub async fn connect(
+        filter: &DeviceFilter,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if !filter.allows(Transport::Ble) {
+            return Err("Filter only matches USB devices".into());
+        }
         let adapter = bluest::Adapter::default()
             .await
             .ok_or("Bluetooth adapter not found")?;
         println!("Waiting for adapter!");
         adapter.wait_available().await?;
 
-        println!("Discovering devices!");
-        let mut devices = adapter
-            .discover_devices(&[
-                uuids::ADS_SERVICE_UUID,
-                uuids::PROFILE_SERVICE_UUID,
-                uuids::SESSION_SERVICE_UUID,
-                uuids::MIC_SERVICE_UUID,
-                // uuids::BATTERY_SERVICE_UUID,
-                // uuids::DEVICE_INFO_SERVICE_UUID,
-            ])
-            .await?;
-
-        let device = devices
-            .next()
-            .await
-            .ok_or("No devices found")?
-            .map_err(|e| format!("Device error: {:?}", e))?;
+        let device = match &filter.ble_id {
+            Some(id) => adapter.open_device(id).await?,
+            None => {
+                println!("Discovering devices!");
+                let mut devices = adapter
+                    .discover_devices(&[
+                        uuids::ADS_SERVICE_UUID,
+                        uuids::PROFILE_SERVICE_UUID,
+                        uuids::SESSION_SERVICE_UUID,
+                        uuids::MIC_SERVICE_UUID,
+                        // uuids::BATTERY_SERVICE_UUID,
+                        // uuids::DEVICE_INFO_SERVICE_UUID,
+                    ])
+                    .await?;
+                loop {
+                    let device = devices
+                        .next()
+                        .await
+                        .ok_or("No devices found")?
+                        .map_err(|e| format!("Device error: {:?}", e))?;
+                    let found = DiscoveredDevice {
+                        transport: Transport::Ble,
+                        serial: None,
+                        ble_id: Some(device.id()),
+                        rssi: None,
+                        name: device.name().ok(),
+                    };
+                    if filter.matches(&found) {
+                        break device;
+                    }
+                }
+            }
+        };
 
         println!(
             "Found device: {} ({:?})",
@@ -184,6 +346,9 @@ impl BleClient {
             uuids::PROFILE_SERVICE_UUID,
             uuids::SESSION_SERVICE_UUID,
             uuids::MIC_SERVICE_UUID,
+            uuids::IMU_SERVICE_UUID,
+            uuids::TIME_SERVICE_UUID,
+            uuids::FILE_SERVICE_UUID,
         ] {
             if let Ok(service) =
                 device.discover_services_with_uuid(service_uuid).await
@@ -213,14 +378,14 @@ impl BleClient {
 
     pub async fn notify_ads_stream(
         &self,
-    ) -> impl Stream<Item = bluest::Result<Vec<u8>>> + Send + Unpin + use<'_>
-    {
+    ) -> Result<
+        impl Stream<Item = bluest::Result<Vec<u8>>> + Send + Unpin + use<'_>,
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
         let characteristic = self
             .get_characteristic(DATA_STREAM_UUID)
-            .ok_or("Data stream characteristic not found")
-            .unwrap();
-        let stream = characteristic.notify().await.unwrap();
-        stream
+            .ok_or("Data stream characteristic not found")?;
+        Ok(characteristic.notify().await?)
     }
 
     fn get_characteristic(
@@ -365,7 +530,40 @@ impl BleClient {
         self.write_characteristic(uuids::SESSION_CMD_UUID, &[cmd]).await
     }
 
+    // Time Service Methods
+    /// Set the device clock to the host clock with a few two-way
+    /// exchanges, applying the least delayed one. See
+    /// [`dc_mini_icd::TimeSample`].
+    pub async fn sync_time(
+        &self,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let sample = super::best_time_sample(|host_tx_us| async move {
+            self.write_characteristic(
+                uuids::TIME_EXCHANGE_UUID,
+                &host_tx_us.to_le_bytes(),
+            )
+            .await?;
+            let value =
+                self.read_characteristic(uuids::TIME_EXCHANGE_UUID).await?;
+            let exchange = icd::TimeExchange::from_bytes(&value)
+                .ok_or("Malformed clock exchange")?;
+            Ok::<_, Box<dyn Error + Send + Sync>>(exchange)
+        })
+        .await?;
+        self.write_characteristic(uuids::TIME_SAMPLE_UUID, &sample.to_bytes())
+            .await
+    }
+
     // ADS Service Methods
+    /// Select how ADS frames are encoded; decode received frames with
+    /// [`crate::icd::proto::AdsDataFrame::restore_samples`].
+    pub async fn set_stream_codec(
+        &self,
+        codec: AdsCodec,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.write_characteristic(STREAM_CODEC_UUID, &[codec as u8]).await
+    }
+
     pub async fn get_ads_config(
         &self,
     ) -> Result<icd::AdsConfig, Box<dyn std::error::Error + Send + Sync>> {
@@ -827,14 +1025,14 @@ impl BleClient {
     // Mic Service Methods
     pub async fn notify_mic_stream(
         &self,
-    ) -> impl Stream<Item = bluest::Result<Vec<u8>>> + Send + Unpin + use<'_>
-    {
+    ) -> Result<
+        impl Stream<Item = bluest::Result<Vec<u8>>> + Send + Unpin + use<'_>,
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
         let characteristic = self
             .get_characteristic(uuids::mic::DATA_STREAM_UUID)
-            .ok_or("Mic data stream characteristic not found")
-            .unwrap();
-        let stream = characteristic.notify().await.unwrap();
-        stream
+            .ok_or("Mic data stream characteristic not found")?;
+        Ok(characteristic.notify().await?)
     }
 
     pub async fn get_mic_config(
@@ -845,7 +1043,8 @@ impl BleClient {
         let sample_rate = icd::MicSampleRate::from(
             self.read_characteristic(uuids::mic::SAMPLE_RATE_UUID).await?[0],
         );
-        Ok(icd::MicConfig { gain_db, sample_rate })
+        // Recording options are not exposed over GATT.
+        Ok(icd::MicConfig { gain_db, sample_rate, ..Default::default() })
     }
 
     pub async fn set_mic_config(
@@ -877,6 +1076,126 @@ impl BleClient {
         self.write_characteristic(uuids::mic::COMMAND_UUID, &[1]).await
     }
 
+    // IMU Service Methods
+    /// Orientation notifications, each an encoded
+    /// [`icd::imu_proto::ImuQuaternion`].
+    pub async fn notify_quaternion_stream(
+        &self,
+    ) -> Result<
+        impl Stream<Item = bluest::Result<Vec<u8>>> + Send + Unpin + use<'_>,
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
+        let characteristic = self
+            .get_characteristic(uuids::imu::QUATERNION_STREAM_UUID)
+            .ok_or("Quaternion stream characteristic not found")?;
+        Ok(characteristic.notify().await?)
+    }
+
+    /// Starts the orientation stream; a `rate` of 0 keeps the configured
+    /// rate.
+    pub async fn start_quaternion_streaming(
+        &self,
+        rate: u8,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if rate != 0 {
+            self.write_characteristic(
+                uuids::imu::QUATERNION_RATE_UUID,
+                &[rate],
+            )
+            .await?;
+        }
+        self.write_characteristic(uuids::imu::COMMAND_UUID, &[0]).await
+    }
+
+    pub async fn stop_quaternion_streaming(
+        &self,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.write_characteristic(uuids::imu::COMMAND_UUID, &[1]).await
+    }
+
+    // File Service Methods
+    /// Download a file from the SD card, resuming after `resume_from` bytes.
+    pub async fn fs_download(
+        &self,
+        name: &str,
+        resume_from: u32,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+        if name.len() > icd::MAX_FS_NAME_LEN {
+            return Err(format!(
+                "File name longer than {} bytes",
+                icd::MAX_FS_NAME_LEN
+            )
+            .into());
+        }
+        let mut replies = self
+            .get_characteristic(uuids::file::CONTROL_UUID)
+            .ok_or("File control characteristic not found")?
+            .notify()
+            .await?;
+        let mut chunks = self
+            .get_characteristic(uuids::file::DATA_UUID)
+            .ok_or("File data characteristic not found")?
+            .notify()
+            .await?;
+
+        let mut open = vec![file_op::OPEN];
+        open.extend_from_slice(name.as_bytes());
+        self.write_characteristic(uuids::file::CONTROL_UUID, &open).await?;
+        let size = file_reply(&mut replies, file_op::OPEN)
+            .await
+            .map_err(|e| format!("Open {name} failed: {e}"))?;
+
+        let mut data =
+            Vec::with_capacity(size.saturating_sub(resume_from) as usize);
+        let mut offset = resume_from;
+        while offset < size {
+            let mut read = vec![file_op::READ];
+            read.extend_from_slice(&offset.to_le_bytes());
+            read.extend_from_slice(&FILE_CHUNK_LEN.to_le_bytes());
+            self.write_characteristic(uuids::file::CONTROL_UUID, &read)
+                .await?;
+            // A failed read is answered on the control characteristic.
+            let chunk = tokio::select! {
+                chunk = chunks.next() => {
+                    chunk.ok_or("File data notifications ended")??
+                }
+                reply = file_reply(&mut replies, file_op::READ) => {
+                    let _ = self
+                        .write_characteristic(
+                            uuids::file::CONTROL_UUID,
+                            &[file_op::CLOSE],
+                        )
+                        .await;
+                    let reason =
+                        reply.err().map(|e| e.to_string()).unwrap_or_default();
+                    return Err(format!(
+                        "Read failed at offset {offset}: {reason}"
+                    )
+                    .into());
+                }
+            };
+            let [o0, o1, o2, o3, eof, bytes @ ..] = chunk.as_slice() else {
+                return Err("Malformed file data".into());
+            };
+            if u32::from_le_bytes([*o0, *o1, *o2, *o3]) != offset {
+                return Err(format!("No file data for offset {offset}").into());
+            }
+            offset += bytes.len() as u32;
+            data.extend_from_slice(bytes);
+            if *eof != 0 || bytes.is_empty() {
+                break;
+            }
+        }
+
+        self.write_characteristic(
+            uuids::file::CONTROL_UUID,
+            &[file_op::CLOSE],
+        )
+        .await?;
+        file_reply(&mut replies, file_op::CLOSE).await?;
+        Ok(data)
+    }
+
     pub async fn is_connected(&self) -> bool {
         self.device.is_connected().await
     }
@@ -0,0 +1,127 @@
+//! Listing every reachable device, so a lab with several units can pick
+//! one deliberately instead of taking whichever answers first.
+
+use std::fmt;
+use std::time::Duration;
+
+use super::{ble, usb};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Usb,
+    Ble,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Usb => f.write_str("USB"),
+            Self::Ble => f.write_str("BLE"),
+        }
+    }
+}
+
+/// A device found by [`discover`]. A unit plugged in while advertising
+/// is listed once per transport.
+#[derive(Debug, Clone)]
+pub struct DiscoveredDevice {
+    pub transport: Transport,
+    /// Factory serial number. Only USB descriptors carry it.
+    pub serial: Option<String>,
+    /// BLE device id: the address on Linux and Windows, an OS-assigned
+    /// UUID on macOS.
+    pub ble_id: Option<bluest::DeviceId>,
+    /// Signal strength of the last advertisement; `None` over USB and for
+    /// devices already connected to this host.
+    pub rssi: Option<i16>,
+    /// Nickname, or the default name if none is set.
+    pub name: Option<String>,
+}
+
+impl fmt::Display for DiscoveredDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.transport,
+            self.name.as_deref().unwrap_or("(unnamed)")
+        )?;
+        if let Some(serial) = &self.serial {
+            write!(f, " serial {serial}")?;
+        }
+        if let Some(id) = &self.ble_id {
+            write!(f, " id {id:?}")?;
+        }
+        if let Some(rssi) = self.rssi {
+            write!(f, " {rssi} dBm")?;
+        }
+        Ok(())
+    }
+}
+
+/// Lists the devices on USB, then those seen over BLE within `scan_time`.
+/// A host without a Bluetooth adapter only lists USB devices.
+pub async fn discover(
+    scan_time: Duration,
+) -> Result<Vec<DiscoveredDevice>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut devices = usb::enumerate()?;
+    devices.extend(ble::scan(scan_time).await?);
+    Ok(devices)
+}
+
+/// Selects one unit among several. Unset fields match anything, so the
+/// default filter takes whichever device is found first.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceFilter {
+    pub transport: Option<Transport>,
+    /// Factory serial number. Only visible over USB.
+    pub serial: Option<String>,
+    pub name: Option<String>,
+    pub ble_id: Option<bluest::DeviceId>,
+}
+
+impl DeviceFilter {
+    pub fn serial(serial: impl Into<String>) -> Self {
+        Self { serial: Some(serial.into()), ..Self::default() }
+    }
+
+    pub fn name(name: impl Into<String>) -> Self {
+        Self { name: Some(name.into()), ..Self::default() }
+    }
+
+    pub fn ble_id(id: bluest::DeviceId) -> Self {
+        Self {
+            transport: Some(Transport::Ble),
+            ble_id: Some(id),
+            ..Self::default()
+        }
+    }
+
+    pub fn matches(&self, device: &DiscoveredDevice) -> bool {
+        self.transport.is_none_or(|transport| transport == device.transport)
+            && self
+                .serial
+                .as_ref()
+                .is_none_or(|serial| device.serial.as_ref() == Some(serial))
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|name| device.name.as_ref() == Some(name))
+            && self
+                .ble_id
+                .as_ref()
+                .is_none_or(|id| device.ble_id.as_ref() == Some(id))
+    }
+
+    /// Whether a device on `transport` could match.
+    pub fn allows(&self, transport: Transport) -> bool {
+        match transport {
+            Transport::Usb => {
+                self.transport != Some(Transport::Ble) && self.ble_id.is_none()
+            }
+            Transport::Ble => {
+                self.transport != Some(Transport::Usb) && self.serial.is_none()
+            }
+        }
+    }
+}
@@ -1,9 +1,20 @@
+use dc_mini_icd::{TimeExchange, TimeSample};
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 mod ble;
+mod discovery;
+mod multi;
+mod reconnect;
+mod replay;
 mod usb;
 
 pub use ble::BleClient;
+pub use discovery::{discover, DeviceFilter, DiscoveredDevice, Transport};
+pub use multi::{MultiDeviceSession, TaggedFrame};
+pub use reconnect::{ConnectionState, ReconnectingConnection};
+pub use replay::ReplaySource;
 pub use usb::{UsbClient, UsbError};
 
 #[derive(Clone)]
@@ -11,3 +22,100 @@ pub enum DeviceConnection {
     Usb(Arc<UsbClient>),
     Ble(Arc<BleClient>),
 }
+
+/// Clock exchanges per sync; only the least delayed one is applied.
+const TIME_EXCHANGES: usize = 8;
+
+/// Host Unix time in microseconds.
+fn host_epoch_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+/// Runs [`TIME_EXCHANGES`] clock exchanges through `exchange`, which sends
+/// the host time to the device and returns its answer, and completes the
+/// least delayed one.
+async fn best_time_sample<F, Fut, E>(mut exchange: F) -> Result<TimeSample, E>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = Result<TimeExchange, E>>,
+{
+    let mut best: Option<TimeSample> = None;
+    for _ in 0..TIME_EXCHANGES {
+        let exchange = exchange(host_epoch_us()).await?;
+        let sample = TimeSample { exchange, host_rx_us: host_epoch_us() };
+        if best.is_none_or(|best| sample.delay_us() < best.delay_us()) {
+            best = Some(sample);
+        }
+    }
+    Ok(best.expect("TIME_EXCHANGES is not zero"))
+}
+
+impl DeviceConnection {
+    /// Opens the first device `filter` matches, trying USB before BLE
+    /// when it allows both.
+    pub async fn connect(
+        filter: &DeviceFilter,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if filter.allows(Transport::Usb) {
+            match UsbClient::connect(filter) {
+                Ok(client) => return Ok(Self::Usb(Arc::new(client))),
+                Err(e) if !filter.allows(Transport::Ble) => return Err(e),
+                Err(_) => {}
+            }
+        }
+        Ok(Self::Ble(Arc::new(BleClient::connect(filter).await?)))
+    }
+
+    /// A filter that matches only the unit behind this connection.
+    pub fn filter(&self) -> DeviceFilter {
+        match self {
+            Self::Usb(client) => DeviceFilter {
+                transport: Some(Transport::Usb),
+                serial: client.serial().map(str::to_owned),
+                ..DeviceFilter::default()
+            },
+            Self::Ble(client) => DeviceFilter::ble_id(client.device.id()),
+        }
+    }
+
+    pub fn transport(&self) -> Transport {
+        match self {
+            Self::Usb(_) => Transport::Usb,
+            Self::Ble(_) => Transport::Ble,
+        }
+    }
+
+    pub async fn is_connected(&self) -> bool {
+        match self {
+            Self::Usb(client) => client.is_connected(),
+            Self::Ble(client) => client.is_connected().await,
+        }
+    }
+
+    /// Releases the device. Errors are ignored, as the device may be gone.
+    pub async fn close(&self) {
+        match self {
+            Self::Usb(client) => client.client.close(),
+            Self::Ble(client) => {
+                let _ = client.close().await;
+            }
+        }
+    }
+
+    /// Syncs the device clock to the host's. Syncing again every few
+    /// minutes lets the device estimate and correct its drift.
+    pub async fn sync_time(
+        &self,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match self {
+            Self::Usb(client) => {
+                client.sync_time().await?;
+            }
+            Self::Ble(client) => client.sync_time().await?,
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+//! Streaming from several devices at once, e.g. one per wearer in a
+//! hyperscanning experiment.
+//!
+//! Every device clock is synced to the host's, so the `ts` of frames from
+//! different devices share a timeline, and frames arrive on one channel
+//! tagged with the index of the device they came from.
+
+use std::error::Error;
+
+use dc_mini_icd::{self as icd, SampleRate};
+use futures::future::{join_all, try_join_all};
+use futures::StreamExt;
+use prost::Message as ProtoMessage;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use super::{DeviceConnection, DeviceFilter};
+use crate::AdsDataFrames;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// A frame from one device of a [`MultiDeviceSession`].
+pub struct TaggedFrame {
+    /// Index of the device in the session.
+    pub device: usize,
+    pub sample_rate: SampleRate,
+    pub frame: AdsDataFrames,
+}
+
+struct Member {
+    label: String,
+    connection: DeviceConnection,
+}
+
+/// Devices that are synced, started and stopped together.
+pub struct MultiDeviceSession {
+    members: Vec<Member>,
+    forwarders: Vec<JoinHandle<()>>,
+}
+
+impl MultiDeviceSession {
+    /// Connects to one device per filter, in order. Fails if any of them
+    /// cannot be reached, so no wearer is silently left out.
+    pub async fn connect(filters: &[DeviceFilter]) -> Result<Self, BoxError> {
+        let mut connections = Vec::with_capacity(filters.len());
+        for filter in filters {
+            let connection = DeviceConnection::connect(filter)
+                .await
+                .map_err(|e| format!("No device for {filter:?}: {e}"))?;
+            connections.push(connection);
+        }
+        Ok(Self::new(connections))
+    }
+
+    pub fn new(connections: Vec<DeviceConnection>) -> Self {
+        let members = connections
+            .into_iter()
+            .enumerate()
+            .map(|(i, connection)| Member {
+                label: label(i, &connection),
+                connection,
+            })
+            .collect();
+        Self { members, forwarders: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Serial number or BLE id of device `device`, for file names and
+    /// plots.
+    pub fn label(&self, device: usize) -> &str {
+        &self.members[device].label
+    }
+
+    pub fn connection(&self, device: usize) -> &DeviceConnection {
+        &self.members[device].connection
+    }
+
+    /// Syncs every device clock to the host's. Repeat every few minutes
+    /// during long recordings so the devices can correct their drift.
+    pub async fn sync_time(&self) -> Result<(), BoxError> {
+        try_join_all(self.members.iter().map(|m| m.connection.sync_time()))
+            .await?;
+        Ok(())
+    }
+
+    /// Syncs the clocks and starts acquisition on every device. Streams are
+    /// subscribed before any device starts, so no early frames are lost.
+    /// If a device fails to start, those already started are stopped.
+    pub async fn start(
+        &mut self,
+    ) -> Result<mpsc::UnboundedReceiver<TaggedFrame>, BoxError> {
+        self.stop_forwarding();
+        self.sync_time().await?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let rates = try_join_all(self.members.iter().map(sample_rate)).await?;
+        for (device, (member, sample_rate)) in
+            self.members.iter().zip(rates).enumerate()
+        {
+            let forwarder = forward(
+                member.connection.clone(),
+                device,
+                sample_rate,
+                tx.clone(),
+            )
+            .await;
+            match forwarder {
+                Ok(forwarder) => self.forwarders.push(forwarder),
+                Err(e) => {
+                    self.stop_forwarding();
+                    return Err(e);
+                }
+            }
+        }
+
+        let started =
+            join_all(self.members.iter().map(|m| start(&m.connection))).await;
+        if let Some(Err(e)) = started.into_iter().find(Result::is_err) {
+            let _ = self.stop().await;
+            return Err(e);
+        }
+        Ok(rx)
+    }
+
+    /// Stops acquisition on every device, and the frame channel with it.
+    /// Every device is asked to stop even if one fails.
+    pub async fn stop(&mut self) -> Result<(), BoxError> {
+        let stopped =
+            join_all(self.members.iter().map(|m| stop(&m.connection))).await;
+        self.stop_forwarding();
+        stopped.into_iter().collect()
+    }
+
+    fn stop_forwarding(&mut self) {
+        for forwarder in self.forwarders.drain(..) {
+            forwarder.abort();
+        }
+    }
+}
+
+impl Drop for MultiDeviceSession {
+    fn drop(&mut self) {
+        self.stop_forwarding();
+    }
+}
+
+fn label(index: usize, connection: &DeviceConnection) -> String {
+    match connection {
+        DeviceConnection::Usb(client) => client
+            .serial()
+            .map_or_else(|| format!("usb-{index}"), str::to_owned),
+        DeviceConnection::Ble(client) => format!("{:?}", client.device.id()),
+    }
+}
+
+async fn sample_rate(member: &Member) -> Result<SampleRate, BoxError> {
+    Ok(match &member.connection {
+        DeviceConnection::Usb(client) => {
+            client.get_ads_config().await?.sample_rate
+        }
+        DeviceConnection::Ble(client) => {
+            client.get_ads_config().await?.sample_rate
+        }
+    })
+}
+
+async fn start(connection: &DeviceConnection) -> Result<(), BoxError> {
+    match connection {
+        DeviceConnection::Usb(client) => {
+            client.start_streaming().await?;
+        }
+        DeviceConnection::Ble(client) => client.start_streaming().await?,
+    }
+    Ok(())
+}
+
+async fn stop(connection: &DeviceConnection) -> Result<(), BoxError> {
+    match connection {
+        DeviceConnection::Usb(client) => client.stop_streaming().await?,
+        DeviceConnection::Ble(client) => client.stop_streaming().await?,
+    }
+    Ok(())
+}
+
+/// Subscribes to the ADS stream of `connection` and spawns a task that
+/// tags its frames and sends them on `tx`.
+async fn forward(
+    connection: DeviceConnection,
+    device: usize,
+    sample_rate: SampleRate,
+    tx: mpsc::UnboundedSender<TaggedFrame>,
+) -> Result<JoinHandle<()>, BoxError> {
+    let send = move |frame| {
+        tx.send(TaggedFrame { device, sample_rate, frame }).is_ok()
+    };
+    Ok(match connection {
+        DeviceConnection::Usb(client) => {
+            let mut sub = client
+                .client
+                .subscribe_multi::<icd::AdsTopic>(8)
+                .await
+                .map_err(|e| format!("ADS subscription failed: {e:?}"))?;
+            tokio::spawn(async move {
+                while let Ok(mut frame) = sub.recv().await {
+                    frame.restore_samples();
+                    if !send(AdsDataFrames::Icd(frame)) {
+                        break;
+                    }
+                }
+            })
+        }
+        DeviceConnection::Ble(client) => {
+            // The notification stream borrows the client, so it is opened
+            // inside the task, which reports back once it is subscribed.
+            let (subscribed_tx, subscribed) = oneshot::channel();
+            let forwarder = tokio::spawn(async move {
+                let mut stream = match client.notify_ads_stream().await {
+                    Ok(stream) => {
+                        let _ = subscribed_tx.send(Ok(()));
+                        stream
+                    }
+                    Err(e) => {
+                        let _ = subscribed_tx.send(Err(e));
+                        return;
+                    }
+                };
+                while let Some(data) = stream.next().await {
+                    let data = match data {
+                        Ok(data) => data,
+                        Err(e) => {
+                            println!("Device {device} stream error: {e:?}");
+                            if e.kind()
+                                == bluest::error::ErrorKind::NotConnected
+                            {
+                                break;
+                            }
+                            continue;
+                        }
+                    };
+                    let Ok(mut frame) =
+                        icd::proto::AdsDataFrame::decode(&data[..])
+                    else {
+                        continue;
+                    };
+                    frame.restore_samples();
+                    if !send(AdsDataFrames::Proto(frame)) {
+                        break;
+                    }
+                }
+            });
+            subscribed.await.map_err(|_| "ADS stream task ended")??;
+            forwarder
+        }
+    })
+}
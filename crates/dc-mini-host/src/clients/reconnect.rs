@@ -0,0 +1,131 @@
+//! Keeping a connection alive across device resets and BLE drops.
+//!
+//! [`ReconnectingConnection`] watches a [`DeviceConnection`] and, once it
+//! is lost, opens a new one to the same unit, backing off between
+//! attempts. Each new connection is published as a [`ConnectionState`];
+//! stream tasks pick it up and subscribe again, since topic subscriptions
+//! do not carry over from the old client.
+
+use std::time::Duration;
+
+use tokio::runtime::Handle;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+
+use super::DeviceConnection;
+
+/// How often the connection is checked.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// Wait before the first attempt, doubled after every failed one.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+/// A BLE connect scans until the device shows up, so it is bounded.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub enum ConnectionState {
+    Connected(DeviceConnection),
+    /// The connection was lost; attempt `attempt` starts after
+    /// `retry_in`.
+    Reconnecting {
+        attempt: u32,
+        retry_in: Duration,
+    },
+}
+
+impl ConnectionState {
+    pub fn connection(&self) -> Option<&DeviceConnection> {
+        match self {
+            Self::Connected(connection) => Some(connection),
+            Self::Reconnecting { .. } => None,
+        }
+    }
+}
+
+/// A connection that is reopened whenever it is lost, until dropped.
+pub struct ReconnectingConnection {
+    state: watch::Receiver<ConnectionState>,
+    task: JoinHandle<()>,
+}
+
+impl ReconnectingConnection {
+    /// Starts watching `connection` on `rt`.
+    pub fn spawn(rt: &Handle, connection: DeviceConnection) -> Self {
+        let (tx, state) =
+            watch::channel(ConnectionState::Connected(connection.clone()));
+        let task = rt.spawn(supervise(connection, tx));
+        Self { state, task }
+    }
+
+    /// Receives every state change; the current state is marked seen.
+    pub fn subscribe(&self) -> watch::Receiver<ConnectionState> {
+        let mut state = self.state.clone();
+        state.mark_unchanged();
+        state
+    }
+
+    /// The live connection, `None` while reconnecting.
+    pub fn current(&self) -> Option<DeviceConnection> {
+        self.state.borrow().connection().cloned()
+    }
+
+    /// Waits for a live connection.
+    pub async fn connected(&self) -> DeviceConnection {
+        let mut state = self.state.clone();
+        let state = state
+            .wait_for(|state| state.connection().is_some())
+            .await
+            .expect("the supervisor outlives its receivers");
+        state.connection().cloned().expect("checked by wait_for")
+    }
+}
+
+impl Drop for ReconnectingConnection {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn supervise(
+    mut connection: DeviceConnection,
+    state: watch::Sender<ConnectionState>,
+) {
+    let transport = connection.transport();
+    let unit = connection.filter();
+    loop {
+        while connection.is_connected().await {
+            sleep(HEALTH_CHECK_INTERVAL).await;
+        }
+        println!("{transport} connection lost, reconnecting");
+        connection.close().await;
+
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+        connection = loop {
+            state.send_replace(ConnectionState::Reconnecting {
+                attempt,
+                retry_in: backoff,
+            });
+            sleep(backoff).await;
+            match tokio::time::timeout(
+                CONNECT_TIMEOUT,
+                DeviceConnection::connect(&unit),
+            )
+            .await
+            {
+                Ok(Ok(connection)) => break connection,
+                Ok(Err(e)) => {
+                    println!("Reconnect attempt {attempt} failed: {e}")
+                }
+                Err(_) => {
+                    println!("Reconnect attempt {attempt} timed out")
+                }
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            attempt += 1;
+        };
+        println!("{transport} reconnected after {attempt} attempt(s)");
+        state.send_replace(ConnectionState::Connected(connection.clone()));
+    }
+}
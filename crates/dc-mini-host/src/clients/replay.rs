@@ -0,0 +1,284 @@
+//! Playing a recording back as if a device were streaming it, to exercise
+//! the viewer and the stream consumers without hardware.
+//!
+//! A [`ReplaySource`] offers the streaming half of a [`super::BleClient`]:
+//! the same start and stop calls, and notification streams carrying the
+//! same protobuf frames, paced by the timestamps they were recorded with.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::{stream, Stream, StreamExt};
+use prost::Message;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, Duration, Instant};
+
+use crate::fileio;
+use crate::fileio::dat::DatReader;
+use crate::fileio::dcs::{Record, SessionReader};
+use crate::icd::{imu_proto, mic_proto, proto, SampleRate};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Frames a notification stream may fall behind by; a reader slower than
+/// that misses frames, as it would on a congested link.
+const STREAM_CAPACITY: usize = 256;
+
+/// Pauses in the recording, such as gaps or the device clock being set,
+/// are played back no longer than this.
+const MAX_PAUSE_US: u64 = 1_000_000;
+
+enum Frame {
+    Ads(proto::AdsDataFrame),
+    Mic(mic_proto::MicDataFrame),
+    Quaternion(imu_proto::ImuQuaternion),
+}
+
+impl Frame {
+    fn ts(&self) -> u64 {
+        match self {
+            Frame::Ads(frame) => frame.ts,
+            Frame::Mic(frame) => frame.ts,
+            Frame::Quaternion(q) => q.ts,
+        }
+    }
+}
+
+/// One notification stream, sent only while started.
+struct Channel {
+    tx: broadcast::Sender<Vec<u8>>,
+    enabled: AtomicBool,
+}
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            tx: broadcast::channel(STREAM_CAPACITY).0,
+            enabled: AtomicBool::new(false),
+        }
+    }
+
+    fn subscribe(
+        &self,
+    ) -> impl Stream<Item = bluest::Result<Vec<u8>>> + Send + Unpin {
+        stream::unfold(self.tx.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(data) => return Some((Ok(data), rx)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+/// A `.dat` or `.dcs` recording played back in real time, or faster.
+///
+/// Playback starts with the first stream started and runs through the
+/// recording once; starting a stream after the end plays it again.
+pub struct ReplaySource {
+    path: PathBuf,
+    frames: Arc<Vec<Frame>>,
+    speed: f64,
+    ads: Arc<Channel>,
+    mic: Arc<Channel>,
+    quaternion: Arc<Channel>,
+    player: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl ReplaySource {
+    /// Loads the frames of a recording.
+    pub fn open(path: impl AsRef<Path>) -> fileio::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        // As for conversions, files without an extension are .dat files
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("dat");
+        let frames = match ext.to_lowercase().as_str() {
+            "dat" => read_dat(&path)?,
+            "dcs" => read_session(&path)?,
+            _ => {
+                return Err(fileio::Error::InvalidInput(format!(
+                    "Cannot replay {ext} files. Expected DAT or DCS."
+                )))
+            }
+        };
+        if frames.is_empty() {
+            return Err(fileio::Error::InvalidData(
+                "Nothing to replay in the recording".to_string(),
+            ));
+        }
+        Ok(Self {
+            path,
+            frames: Arc::new(frames),
+            speed: 1.0,
+            ads: Arc::new(Channel::new()),
+            mic: Arc::new(Channel::new()),
+            quaternion: Arc::new(Channel::new()),
+            player: Mutex::new(None),
+        })
+    }
+
+    /// Plays `speed` times as fast as recorded.
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        assert!(speed > 0.0, "Replay speed must be positive");
+        self.speed = speed;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rate of the ADS frames. Recordings do not store the ADS
+    /// configuration and are taken at the default rate.
+    pub fn sample_rate(&self) -> SampleRate {
+        SampleRate::Sps250
+    }
+
+    /// Whether the recording is still being played.
+    pub fn is_playing(&self) -> bool {
+        let player = self.player.lock().unwrap();
+        player.as_ref().is_some_and(|player| !player.is_finished())
+    }
+
+    pub async fn notify_ads_stream(
+        &self,
+    ) -> Result<
+        impl Stream<Item = bluest::Result<Vec<u8>>> + Send + Unpin + use<'_>,
+        BoxError,
+    > {
+        Ok(self.ads.subscribe())
+    }
+
+    pub async fn notify_mic_stream(
+        &self,
+    ) -> Result<
+        impl Stream<Item = bluest::Result<Vec<u8>>> + Send + Unpin + use<'_>,
+        BoxError,
+    > {
+        Ok(self.mic.subscribe())
+    }
+
+    pub async fn notify_quaternion_stream(
+        &self,
+    ) -> Result<
+        impl Stream<Item = bluest::Result<Vec<u8>>> + Send + Unpin + use<'_>,
+        BoxError,
+    > {
+        Ok(self.quaternion.subscribe())
+    }
+
+    pub async fn start_streaming(&self) -> Result<(), BoxError> {
+        self.start(&self.ads);
+        Ok(())
+    }
+
+    pub async fn stop_streaming(&self) -> Result<(), BoxError> {
+        self.ads.enabled.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub async fn start_mic_streaming(&self) -> Result<(), BoxError> {
+        self.start(&self.mic);
+        Ok(())
+    }
+
+    pub async fn stop_mic_streaming(&self) -> Result<(), BoxError> {
+        self.mic.enabled.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Orientation plays at the rate it was recorded at; `_rate` is taken
+    /// for parity with the device clients.
+    pub async fn start_quaternion_streaming(
+        &self,
+        _rate: u8,
+    ) -> Result<(), BoxError> {
+        self.start(&self.quaternion);
+        Ok(())
+    }
+
+    pub async fn stop_quaternion_streaming(&self) -> Result<(), BoxError> {
+        self.quaternion.enabled.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn start(&self, channel: &Channel) {
+        channel.enabled.store(true, Ordering::Relaxed);
+        let mut player = self.player.lock().unwrap();
+        if player.as_ref().is_none_or(|player| player.is_finished()) {
+            *player = Some(tokio::spawn(play(
+                self.frames.clone(),
+                self.speed,
+                [self.ads.clone(), self.mic.clone(), self.quaternion.clone()],
+            )));
+        }
+    }
+}
+
+impl Drop for ReplaySource {
+    fn drop(&mut self) {
+        if let Some(player) = self.player.get_mut().unwrap().take() {
+            player.abort();
+        }
+    }
+}
+
+/// Sends every frame on its channel once its time has come.
+async fn play(
+    frames: Arc<Vec<Frame>>,
+    speed: f64,
+    channels: [Arc<Channel>; 3],
+) {
+    let [ads, mic, quaternion] = channels;
+    let start = Instant::now();
+    // Recording time played so far. Streams are interleaved in the order
+    // they were received, so timestamps need not increase.
+    let mut elapsed_us = 0;
+    let mut latest_ts = frames[0].ts();
+    for frame in frames.iter() {
+        if frame.ts() > latest_ts {
+            elapsed_us += (frame.ts() - latest_ts).min(MAX_PAUSE_US);
+            latest_ts = frame.ts();
+        }
+        let offset = Duration::from_secs_f64(elapsed_us as f64 / 1e6 / speed);
+        sleep_until(start + offset).await;
+
+        let (channel, data) = match frame {
+            Frame::Ads(frame) => (&ads, frame.encode_to_vec()),
+            Frame::Mic(frame) => (&mic, frame.encode_to_vec()),
+            Frame::Quaternion(q) => (&quaternion, q.encode_to_vec()),
+        };
+        if channel.enabled.load(Ordering::Relaxed) {
+            // As with a device, frames nobody listens to are dropped
+            let _ = channel.tx.send(data);
+        }
+    }
+}
+
+fn read_dat(path: &PathBuf) -> fileio::Result<Vec<Frame>> {
+    let mut reader = DatReader::new(path)?;
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.read_frame()? {
+        frames.push(Frame::Ads(frame));
+    }
+    Ok(frames)
+}
+
+fn read_session(path: &PathBuf) -> fileio::Result<Vec<Frame>> {
+    let mut reader = SessionReader::new(path)?;
+    let mut frames = Vec::new();
+    while let Some(record) = reader.next_record()? {
+        match record {
+            Record::Ads(frame) => frames.push(Frame::Ads(frame)),
+            Record::Mic(frame) => frames.push(Frame::Mic(frame)),
+            Record::Quaternion(q) => frames.push(Frame::Quaternion(q)),
+            _ => {}
+        }
+    }
+    Ok(frames)
+}
@@ -1,25 +1,59 @@
 use dc_mini_icd::{
-    AdsConfig, AdsGetConfigEndpoint, AdsResetConfigEndpoint,
-    AdsSetConfigEndpoint, AdsStartEndpoint, AdsStopEndpoint,
-    BatteryGetLevelEndpoint, BatteryLevel, DeviceInfo, DeviceInfoGetEndpoint,
-    DfuAbortEndpoint, DfuBegin, DfuBeginEndpoint, DfuFinishEndpoint,
-    DfuProgress, DfuResult, DfuStatusEndpoint, DfuWriteChunk,
-    DfuWriteEndpoint, MicConfig, MicGetConfigEndpoint, MicSetConfigEndpoint,
-    MicStartEndpoint, MicStopEndpoint, ProfileCommand, ProfileCommandEndpoint,
-    ProfileGetEndpoint, ProfileSetEndpoint, SessionGetIdEndpoint,
-    SessionGetStatusEndpoint, SessionId, SessionSetIdEndpoint,
-    SessionStartEndpoint, SessionStopEndpoint,
+    AdsCodec, AdsConfig, AdsGetConfigEndpoint, AdsGetMontageEndpoint,
+    AdsImpedanceEndpoint, AdsResetConfigEndpoint, AdsSetConfigEndpoint,
+    AdsSetMontageEndpoint, AdsStartEndpoint, AdsStopEndpoint, ApdsConfig,
+    ApdsGetConfigEndpoint, ApdsGetGesturesEndpoint, ApdsResetConfigEndpoint,
+    ApdsSetConfigEndpoint, ApdsSetGesturesEndpoint, ApdsStartEndpoint,
+    ApdsStopEndpoint, BatteryGetLevelEndpoint, BatteryGetShutdownEndpoint,
+    BatteryGetStatusEndpoint, BatteryLevel, BatterySetShutdownEndpoint,
+    BatteryStatus, BleClearBondsEndpoint, CrashClearEndpoint, CrashReport,
+    CrashReportEndpoint, DeviceIdentity, DeviceIdentityEndpoint, DeviceInfo,
+    DeviceInfoGetEndpoint, DeviceSetNicknameEndpoint, DeviceStats,
+    DeviceStatsEndpoint, DfuAbortEndpoint, DfuBegin, DfuBeginEndpoint,
+    DfuFinishEndpoint, DfuProgress, DfuResult, DfuStatusEndpoint,
+    DfuWriteChunk, DfuWriteEndpoint, EventLogDumpEndpoint, EventMarker,
+    EventMarkerEndpoint, FsChunkData, FsDelete, FsDeleteEndpoint, FsReadBegin,
+    FsReadBeginEndpoint, FsReadChunk, FsReadChunkEndpoint,
+    FsReadFinishEndpoint, FsResult, GestureConfig, HapticConfig, HapticCue,
+    HapticGetConfigEndpoint, HapticPattern, HapticPlayCueEndpoint,
+    HapticPlayEndpoint, HapticSetConfigEndpoint, HapticStopEndpoint,
+    ImpedanceReport, LeadOffStartEndpoint, LeadOffStopEndpoint,
+    LedGetConfigEndpoint, LedOverride, LedSetConfigEndpoint, LedSetEndpoint,
+    LogDumpEndpoint, LogGetLevelEndpoint, LogLevel, LogRecord,
+    LogSetLevelEndpoint, LogStartEndpoint, LogStopEndpoint, LowBatteryConfig,
+    MarkerRecord, MicConfig, MicGetConfigEndpoint, MicSetConfigEndpoint,
+    MicStartEndpoint, MicStopEndpoint, Montage, MotionTriggerConfig,
+    NeopixelConfig, Nickname, PowerProfile, PowerProfileGetEndpoint,
+    PowerProfileStartEndpoint, PowerProfileStopEndpoint, ProfileCommand,
+    ProfileCommandEndpoint, ProfileGetEndpoint, ProfileGetInfoEndpoint,
+    ProfileInfo, ProfileInfoUpdate, ProfileSetEndpoint,
+    ProfileSetInfoEndpoint, ProtocolInfo, ProtocolInfoEndpoint,
+    QuaternionStartEndpoint, QuaternionStopEndpoint, SelfTestEndpoint,
+    SelfTestReport, SelfTestTopic, SessionGetIdEndpoint,
+    SessionGetMetadataEndpoint, SessionGetMotionEndpoint,
+    SessionGetStatusEndpoint, SessionId, SessionMetadata,
+    SessionSetIdEndpoint, SessionSetMetadataEndpoint,
+    SessionSetMotionEndpoint, SessionStartEndpoint, SessionStopEndpoint,
+    StorageClearEndpoint, StorageStatus, StorageStatusEndpoint, StreamConfig,
+    StreamConfigEndpoint, StreamGetCodecEndpoint, StreamGetConfigEndpoint,
+    StreamKind, StreamSetCodecEndpoint, SystemEvent, TimeExchangeEndpoint,
+    TimeGetEndpoint, TimeSampleEndpoint, TimeSetEndpoint, TimeStatus,
+    TimeSync, BOOT_DFU_PRODUCT, FS_CHUNK_SIZE, MAX_FS_NAME_LEN,
 };
 use postcard_rpc::{
     header::VarSeqKind,
-    host_client::{HostClient, HostErr},
+    host_client::{HostClient, HostErr, MultiSubRxError},
     standard_icd::{WireError, ERROR_PATH},
 };
 use std::convert::Infallible;
 use std::fmt;
+use std::time::Duration;
+
+use super::discovery::{DeviceFilter, DiscoveredDevice, Transport};
 
 pub struct UsbClient {
     pub client: HostClient<WireError>,
+    serial: Option<String>,
 }
 
 #[derive(Debug)]
@@ -45,16 +79,109 @@ impl<E> From<HostErr<WireError>> for UsbError<E> {
     }
 }
 
+/// A file name longer than [`MAX_FS_NAME_LEN`] bytes, which no file on the
+/// card can have.
+#[derive(Debug)]
+pub struct FileNameTooLong;
+
+impl fmt::Display for FileNameTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "file name longer than {MAX_FS_NAME_LEN} bytes")
+    }
+}
+
+/// How long [`UsbClient::run_self_test`] waits for the report. The device
+/// bounds each check, so this only trips if the report is lost.
+const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// No self-test report arrived within [`SELF_TEST_TIMEOUT`].
+#[derive(Debug)]
+pub struct SelfTestTimedOut;
+
+impl fmt::Display for SelfTestTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no self-test report within {SELF_TEST_TIMEOUT:?}")
+    }
+}
+
+fn fs_name(
+    name: &str,
+) -> Result<heapless::String<MAX_FS_NAME_LEN>, UsbError<FileNameTooLong>> {
+    heapless::String::try_from(name)
+        .map_err(|_| UsbError::Endpoint(FileNameTooLong))
+}
+
+/// VID/PID pair the firmware enumerates with.
+const VID: u16 = 0x16c0;
+const PID: u16 = 0x27DD;
+
+/// Whether `info` is a device running the application. The product string
+/// is the nickname, so only the bootloader's recovery mode, which shares
+/// the IDs, is told apart by it.
+fn is_dc_mini(info: &nusb::DeviceInfo) -> bool {
+    info.vendor_id() == VID
+        && info.product_id() == PID
+        && info.product_string() != Some(BOOT_DFU_PRODUCT)
+}
+
+fn discovered(info: &nusb::DeviceInfo) -> DiscoveredDevice {
+    DiscoveredDevice {
+        transport: Transport::Usb,
+        serial: info.serial_number().map(str::to_owned),
+        ble_id: None,
+        rssi: None,
+        name: info.product_string().map(str::to_owned),
+    }
+}
+
+/// Devices on USB, without opening them.
+pub(crate) fn enumerate() -> Result<Vec<DiscoveredDevice>, nusb::Error> {
+    Ok(nusb::list_devices()?
+        .filter(is_dc_mini)
+        .map(|info| discovered(&info))
+        .collect())
+}
+
 impl UsbClient {
+    /// Opens the first device found.
     pub fn try_new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>>
     {
+        Self::connect(&DeviceFilter::default())
+    }
+
+    /// Opens the unit with the factory serial number `serial`.
+    pub fn connect_serial(
+        serial: &str,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::connect(&DeviceFilter::serial(serial))
+    }
+
+    /// Opens the first device `filter` matches.
+    pub fn connect(
+        filter: &DeviceFilter,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if !filter.allows(Transport::Usb) {
+            return Err("Filter only matches BLE devices".into());
+        }
+        let mut serial = None;
         let client = HostClient::try_new_raw_nusb(
-            |d| d.product_string() == Some("dc-mini"),
+            |d| {
+                let found = is_dc_mini(d) && filter.matches(&discovered(d));
+                if found {
+                    serial = d.serial_number().map(str::to_owned);
+                }
+                found
+            },
             ERROR_PATH,
             8,
             VarSeqKind::Seq2,
         )?;
-        Ok(Self { client })
+        Ok(Self { client, serial })
+    }
+
+    /// Factory serial number of the open device.
+    pub fn serial(&self) -> Option<&str> {
+        self.serial.as_deref()
     }
 
     pub fn new() -> Self {
@@ -103,7 +230,36 @@ impl UsbClient {
         Ok(result)
     }
 
+    pub async fn get_montage(&self) -> Result<Montage, UsbError<Infallible>> {
+        let montage =
+            self.client.send_resp::<AdsGetMontageEndpoint>(&()).await?;
+        Ok(montage)
+    }
+
+    pub async fn set_montage(
+        &self,
+        montage: Montage,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let result =
+            self.client.send_resp::<AdsSetMontageEndpoint>(&montage).await?;
+        Ok(result)
+    }
+
     // Battery Service Methods
+    pub async fn start_lead_off_streaming(
+        &self,
+    ) -> Result<(), UsbError<Infallible>> {
+        self.client.send_resp::<LeadOffStartEndpoint>(&()).await?;
+        Ok(())
+    }
+
+    pub async fn stop_lead_off_streaming(
+        &self,
+    ) -> Result<(), UsbError<Infallible>> {
+        self.client.send_resp::<LeadOffStopEndpoint>(&()).await?;
+        Ok(())
+    }
+
     pub async fn get_battery_level(
         &self,
     ) -> Result<BatteryLevel, UsbError<Infallible>> {
@@ -112,6 +268,60 @@ impl UsbClient {
         Ok(level)
     }
 
+    /// Battery and charger state, or `None` if the device cannot read its
+    /// PMIC.
+    pub async fn get_battery_status(
+        &self,
+    ) -> Result<Option<BatteryStatus>, UsbError<Infallible>> {
+        let status =
+            self.client.send_resp::<BatteryGetStatusEndpoint>(&()).await?;
+        Ok(status)
+    }
+
+    pub async fn get_low_battery_config(
+        &self,
+    ) -> Result<LowBatteryConfig, UsbError<Infallible>> {
+        let config =
+            self.client.send_resp::<BatteryGetShutdownEndpoint>(&()).await?;
+        Ok(config)
+    }
+
+    pub async fn set_low_battery_config(
+        &self,
+        config: LowBatteryConfig,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let result = self
+            .client
+            .send_resp::<BatterySetShutdownEndpoint>(&config)
+            .await?;
+        Ok(result)
+    }
+
+    /// Clears the power profile and starts collecting it. Returns false if
+    /// the board cannot measure its battery power.
+    pub async fn start_power_profile(
+        &self,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let started =
+            self.client.send_resp::<PowerProfileStartEndpoint>(&()).await?;
+        Ok(started)
+    }
+
+    pub async fn stop_power_profile(
+        &self,
+    ) -> Result<(), UsbError<Infallible>> {
+        self.client.send_resp::<PowerProfileStopEndpoint>(&()).await?;
+        Ok(())
+    }
+
+    pub async fn get_power_profile(
+        &self,
+    ) -> Result<PowerProfile, UsbError<Infallible>> {
+        let profile =
+            self.client.send_resp::<PowerProfileGetEndpoint>(&()).await?;
+        Ok(profile)
+    }
+
     // Device Info Service Methods
     pub async fn get_device_info(
         &self,
@@ -120,7 +330,125 @@ impl UsbClient {
         Ok(info)
     }
 
+    pub async fn get_device_stats(
+        &self,
+    ) -> Result<DeviceStats, UsbError<Infallible>> {
+        let stats = self.client.send_resp::<DeviceStatsEndpoint>(&()).await?;
+        Ok(stats)
+    }
+
+    /// Fetches the report of the last firmware crash, if one is stored.
+    pub async fn get_crash_report(
+        &self,
+    ) -> Result<Option<CrashReport>, UsbError<Infallible>> {
+        let report = self.client.send_resp::<CrashReportEndpoint>(&()).await?;
+        Ok(report)
+    }
+
+    /// Erases the stored crash report.
+    pub async fn clear_crash_report(
+        &self,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let cleared = self.client.send_resp::<CrashClearEndpoint>(&()).await?;
+        Ok(cleared)
+    }
+
+    /// Measures electrode impedance. A running stream pauses for about
+    /// two seconds while the check runs.
+    pub async fn check_impedance(
+        &self,
+    ) -> Result<ImpedanceReport, UsbError<Infallible>> {
+        let report =
+            self.client.send_resp::<AdsImpedanceEndpoint>(&()).await?;
+        Ok(report)
+    }
+
+    /// Runs the firmware self-test and waits for its report on
+    /// [`SelfTestTopic`]. This can take several seconds.
+    pub async fn run_self_test(
+        &self,
+    ) -> Result<SelfTestReport, UsbError<SelfTestTimedOut>> {
+        // Subscribe first so a quick report is not missed.
+        let mut reports = self
+            .client
+            .subscribe_multi::<SelfTestTopic>(1)
+            .await
+            .map_err(|_| HostErr::<WireError>::Closed)?;
+        self.client.send_resp::<SelfTestEndpoint>(&()).await?;
+        let report = async {
+            loop {
+                match reports.recv().await {
+                    Ok(report) => return Ok(report),
+                    Err(MultiSubRxError::Lagged(_)) => continue,
+                    Err(MultiSubRxError::IoClosed) => {
+                        return Err(HostErr::<WireError>::Closed.into())
+                    }
+                }
+            }
+        };
+        match tokio::time::timeout(SELF_TEST_TIMEOUT, report).await {
+            Ok(result) => result,
+            Err(_) => Err(UsbError::Endpoint(SelfTestTimedOut)),
+        }
+    }
+
+    pub async fn get_protocol_info(
+        &self,
+    ) -> Result<ProtocolInfo, UsbError<Infallible>> {
+        let info = self.client.send_resp::<ProtocolInfoEndpoint>(&()).await?;
+        Ok(info)
+    }
+
+    pub async fn get_device_identity(
+        &self,
+    ) -> Result<DeviceIdentity, UsbError<Infallible>> {
+        let identity =
+            self.client.send_resp::<DeviceIdentityEndpoint>(&()).await?;
+        Ok(identity)
+    }
+
+    /// Stores a persistent nickname; an empty name restores the default.
+    pub async fn set_device_nickname(
+        &self,
+        nickname: Nickname,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let result = self
+            .client
+            .send_resp::<DeviceSetNicknameEndpoint>(&nickname)
+            .await?;
+        Ok(result)
+    }
+
+    /// Forgets the bonded BLE centrals so new ones can pair.
+    pub async fn clear_ble_bonds(&self) -> Result<bool, UsbError<Infallible>> {
+        let cleared =
+            self.client.send_resp::<BleClearBondsEndpoint>(&()).await?;
+        Ok(cleared)
+    }
+
+    /// Fetches the device's protocol info and checks that it was built with
+    /// the same ICD as this host.
+    pub async fn check_protocol(
+        &self,
+    ) -> Result<ProtocolInfo, Box<dyn std::error::Error + Send + Sync>> {
+        let info = self.get_protocol_info().await?;
+        if info.protocol_version != dc_mini_icd::PROTOCOL_VERSION
+            || info.schema_hash != dc_mini_icd::schema_hash()
+        {
+            return Err(format!(
+                "Protocol mismatch: device v{} ({:016x}), host v{} ({:016x})",
+                info.protocol_version,
+                info.schema_hash,
+                dc_mini_icd::PROTOCOL_VERSION,
+                dc_mini_icd::schema_hash()
+            )
+            .into());
+        }
+        Ok(info)
+    }
+
     // Profile Service Methods
+
     pub async fn get_profile(&self) -> Result<u8, UsbError<Infallible>> {
         let profile = self.client.send_resp::<ProfileGetEndpoint>(&()).await?;
         Ok(profile)
@@ -144,6 +472,30 @@ impl UsbClient {
         Ok(result)
     }
 
+    pub async fn get_profile_info(
+        &self,
+        profile: u8,
+    ) -> Result<ProfileInfo, UsbError<Infallible>> {
+        let info =
+            self.client.send_resp::<ProfileGetInfoEndpoint>(&profile).await?;
+        Ok(info)
+    }
+
+    pub async fn set_profile_info(
+        &self,
+        profile: u8,
+        info: ProfileInfo,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let result = self
+            .client
+            .send_resp::<ProfileSetInfoEndpoint>(&ProfileInfoUpdate {
+                profile,
+                info,
+            })
+            .await?;
+        Ok(result)
+    }
+
     // Session Service Methods
     pub async fn get_session_status(
         &self,
@@ -181,6 +533,25 @@ impl UsbClient {
         Ok(result)
     }
 
+    pub async fn get_session_metadata(
+        &self,
+    ) -> Result<SessionMetadata, UsbError<Infallible>> {
+        let metadata =
+            self.client.send_resp::<SessionGetMetadataEndpoint>(&()).await?;
+        Ok(metadata)
+    }
+
+    pub async fn set_session_metadata(
+        &self,
+        metadata: SessionMetadata,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let result = self
+            .client
+            .send_resp::<SessionSetMetadataEndpoint>(&metadata)
+            .await?;
+        Ok(result)
+    }
+
     pub async fn start_session(&self) -> Result<bool, UsbError<Infallible>> {
         let result =
             self.client.send_resp::<SessionStartEndpoint>(&()).await?;
@@ -192,6 +563,44 @@ impl UsbClient {
         Ok(result)
     }
 
+    pub async fn get_motion_trigger(
+        &self,
+    ) -> Result<MotionTriggerConfig, UsbError<Infallible>> {
+        let config =
+            self.client.send_resp::<SessionGetMotionEndpoint>(&()).await?;
+        Ok(config)
+    }
+
+    /// Lets motion start a session and stillness stop it, for the active
+    /// profile.
+    pub async fn set_motion_trigger(
+        &self,
+        config: MotionTriggerConfig,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let result =
+            self.client.send_resp::<SessionSetMotionEndpoint>(&config).await?;
+        Ok(result)
+    }
+
+    // IMU Service Methods
+    /// Starts the fused orientation stream; `rate` in Hz, 0 keeps the
+    /// configured rate. Returns `false` if the device has no IMU.
+    pub async fn start_quaternion_streaming(
+        &self,
+        rate: u8,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let started =
+            self.client.send_resp::<QuaternionStartEndpoint>(&rate).await?;
+        Ok(started)
+    }
+
+    pub async fn stop_quaternion_streaming(
+        &self,
+    ) -> Result<(), UsbError<Infallible>> {
+        let res = self.client.send_resp::<QuaternionStopEndpoint>(&()).await?;
+        Ok(res)
+    }
+
     // Mic Service Methods
     pub async fn start_mic_streaming(
         &self,
@@ -224,6 +633,65 @@ impl UsbClient {
         Ok(result)
     }
 
+    // APDS Service Methods
+    pub async fn start_apds_streaming(
+        &self,
+    ) -> Result<ApdsConfig, UsbError<Infallible>> {
+        let config = self.client.send_resp::<ApdsStartEndpoint>(&()).await?;
+        Ok(config)
+    }
+
+    pub async fn stop_apds_streaming(
+        &self,
+    ) -> Result<(), UsbError<Infallible>> {
+        let res = self.client.send_resp::<ApdsStopEndpoint>(&()).await?;
+        Ok(res)
+    }
+
+    pub async fn reset_apds_config(
+        &self,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let result =
+            self.client.send_resp::<ApdsResetConfigEndpoint>(&()).await?;
+        Ok(result)
+    }
+
+    pub async fn get_apds_config(
+        &self,
+    ) -> Result<ApdsConfig, UsbError<Infallible>> {
+        let config =
+            self.client.send_resp::<ApdsGetConfigEndpoint>(&()).await?;
+        Ok(config)
+    }
+
+    pub async fn set_apds_config(
+        &self,
+        config: ApdsConfig,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let result =
+            self.client.send_resp::<ApdsSetConfigEndpoint>(&config).await?;
+        Ok(result)
+    }
+
+    pub async fn get_gesture_config(
+        &self,
+    ) -> Result<GestureConfig, UsbError<Infallible>> {
+        let config =
+            self.client.send_resp::<ApdsGetGesturesEndpoint>(&()).await?;
+        Ok(config)
+    }
+
+    /// Binds light sensor gestures for the active profile. Binding any
+    /// gesture starts the sensor.
+    pub async fn set_gesture_config(
+        &self,
+        config: GestureConfig,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let result =
+            self.client.send_resp::<ApdsSetGesturesEndpoint>(&config).await?;
+        Ok(result)
+    }
+
     pub fn is_connected(&self) -> bool {
         !self.client.is_closed()
     }
@@ -270,6 +738,322 @@ impl UsbClient {
         Ok(status)
     }
 
+    // Haptic Service Methods
+    pub async fn play_haptic(
+        &self,
+        pattern: HapticPattern,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let res =
+            self.client.send_resp::<HapticPlayEndpoint>(&pattern).await?;
+        Ok(res)
+    }
+
+    pub async fn stop_haptic(&self) -> Result<(), UsbError<Infallible>> {
+        self.client.send_resp::<HapticStopEndpoint>(&()).await?;
+        Ok(())
+    }
+
+    /// Plays one of the named patterns of the active profile.
+    pub async fn play_haptic_cue(
+        &self,
+        cue: HapticCue,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let res = self.client.send_resp::<HapticPlayCueEndpoint>(&cue).await?;
+        Ok(res)
+    }
+
+    pub async fn get_haptic_config(
+        &self,
+    ) -> Result<HapticConfig, UsbError<Infallible>> {
+        let config =
+            self.client.send_resp::<HapticGetConfigEndpoint>(&()).await?;
+        Ok(config)
+    }
+
+    /// Sets the haptic patterns and event cues for the active profile.
+    pub async fn set_haptic_config(
+        &self,
+        config: HapticConfig,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let res =
+            self.client.send_resp::<HapticSetConfigEndpoint>(&config).await?;
+        Ok(res)
+    }
+
+    // LED Service Methods
+    /// Overrides the status LED, or restores it when `led` is `None`.
+    pub async fn set_led(
+        &self,
+        led: Option<LedOverride>,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let res = self.client.send_resp::<LedSetEndpoint>(&led).await?;
+        Ok(res)
+    }
+
+    pub async fn get_led_config(
+        &self,
+    ) -> Result<NeopixelConfig, UsbError<Infallible>> {
+        let config =
+            self.client.send_resp::<LedGetConfigEndpoint>(&()).await?;
+        Ok(config)
+    }
+
+    /// Sets the charger and battery indications for the active profile.
+    pub async fn set_led_config(
+        &self,
+        config: NeopixelConfig,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let res =
+            self.client.send_resp::<LedSetConfigEndpoint>(&config).await?;
+        Ok(res)
+    }
+
+    // Event Marker Methods
+    /// Sends a marker stamped with the current host time.
+    pub async fn add_event_marker(
+        &self,
+        label: &str,
+    ) -> Result<MarkerRecord, UsbError<Infallible>> {
+        let host_epoch_us = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        // Labels longer than `MAX_MARKER_LABEL_LEN` are truncated.
+        let mut marker =
+            EventMarker { label: heapless::String::new(), host_epoch_us };
+        for c in label.chars() {
+            if marker.label.push(c).is_err() {
+                break;
+            }
+        }
+        let res =
+            self.client.send_resp::<EventMarkerEndpoint>(&marker).await?;
+        Ok(res)
+    }
+
+    // Log Service Methods
+    pub async fn start_log_streaming(
+        &self,
+        level: LogLevel,
+    ) -> Result<(), UsbError<Infallible>> {
+        self.client.send_resp::<LogStartEndpoint>(&level).await?;
+        Ok(())
+    }
+
+    pub async fn stop_log_streaming(
+        &self,
+    ) -> Result<(), UsbError<Infallible>> {
+        self.client.send_resp::<LogStopEndpoint>(&()).await?;
+        Ok(())
+    }
+
+    pub async fn get_log_level(
+        &self,
+    ) -> Result<LogLevel, UsbError<Infallible>> {
+        let level = self.client.send_resp::<LogGetLevelEndpoint>(&()).await?;
+        Ok(level)
+    }
+
+    pub async fn set_log_level(
+        &self,
+        level: LogLevel,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let res = self.client.send_resp::<LogSetLevelEndpoint>(&level).await?;
+        Ok(res)
+    }
+
+    /// Reads back the log history the device keeps in RAM, oldest first.
+    pub async fn dump_logs(
+        &self,
+    ) -> Result<Vec<LogRecord>, UsbError<Infallible>> {
+        let mut records = Vec::new();
+        let mut next = 0;
+        loop {
+            let dump = self.client.send_resp::<LogDumpEndpoint>(&next).await?;
+            let empty = dump.records.is_empty();
+            next = dump.first + dump.records.len() as u32;
+            records.extend(dump.records);
+            if empty || next >= dump.end {
+                return Ok(records);
+            }
+        }
+    }
+
+    /// Reads back the persistent system event log, oldest first.
+    pub async fn dump_event_log(
+        &self,
+    ) -> Result<Vec<SystemEvent>, UsbError<Infallible>> {
+        let mut events = Vec::new();
+        let mut next = 0;
+        loop {
+            let dump =
+                self.client.send_resp::<EventLogDumpEndpoint>(&next).await?;
+            let empty = dump.events.is_empty();
+            next = dump.first + dump.events.len() as u32;
+            events.extend(dump.events);
+            if empty || next >= dump.end {
+                return Ok(events);
+            }
+        }
+    }
+
+    // Time Service Methods
+    pub async fn get_time(&self) -> Result<TimeStatus, UsbError<Infallible>> {
+        let status = self.client.send_resp::<TimeGetEndpoint>(&()).await?;
+        Ok(status)
+    }
+
+    pub async fn set_time(
+        &self,
+        sync: TimeSync,
+    ) -> Result<TimeStatus, UsbError<Infallible>> {
+        let status = self.client.send_resp::<TimeSetEndpoint>(&sync).await?;
+        Ok(status)
+    }
+
+    /// Set the device clock to the host clock with a few two-way
+    /// exchanges, applying the least delayed one. See
+    /// [`dc_mini_icd::TimeSample`].
+    pub async fn sync_time(&self) -> Result<TimeStatus, UsbError<Infallible>> {
+        let sample = super::best_time_sample(|host_tx_us| async move {
+            self.client.send_resp::<TimeExchangeEndpoint>(&host_tx_us).await
+        })
+        .await?;
+        let status =
+            self.client.send_resp::<TimeSampleEndpoint>(&sample).await?;
+        Ok(status)
+    }
+
+    // Stream Service Methods
+    /// Reduce the rate of a live stream without changing acquisition.
+    pub async fn set_stream_config(
+        &self,
+        config: StreamConfig,
+    ) -> Result<bool, UsbError<Infallible>> {
+        let result =
+            self.client.send_resp::<StreamConfigEndpoint>(&config).await?;
+        Ok(result)
+    }
+
+    pub async fn get_stream_config(
+        &self,
+        stream: StreamKind,
+    ) -> Result<StreamConfig, UsbError<Infallible>> {
+        let config =
+            self.client.send_resp::<StreamGetConfigEndpoint>(&stream).await?;
+        Ok(config)
+    }
+
+    /// Select how ADS frames are encoded; decode received frames with
+    /// [`dc_mini_icd::AdsDataFrame::restore_samples`].
+    pub async fn set_stream_codec(
+        &self,
+        codec: AdsCodec,
+    ) -> Result<(), UsbError<Infallible>> {
+        self.client.send_resp::<StreamSetCodecEndpoint>(&codec).await?;
+        Ok(())
+    }
+
+    pub async fn get_stream_codec(
+        &self,
+    ) -> Result<AdsCodec, UsbError<Infallible>> {
+        let codec =
+            self.client.send_resp::<StreamGetCodecEndpoint>(&()).await?;
+        Ok(codec)
+    }
+
+    // File transfer Service Methods
+    pub async fn fs_read_begin(
+        &self,
+        name: &str,
+    ) -> Result<FsResult, UsbError<FileNameTooLong>> {
+        let req = FsReadBegin { name: fs_name(name)? };
+        let result =
+            self.client.send_resp::<FsReadBeginEndpoint>(&req).await?;
+        Ok(result)
+    }
+
+    pub async fn fs_read_chunk(
+        &self,
+        offset: u32,
+        length: u16,
+    ) -> Result<Option<FsChunkData>, UsbError<Infallible>> {
+        let chunk = self
+            .client
+            .send_resp::<FsReadChunkEndpoint>(&FsReadChunk { offset, length })
+            .await?;
+        Ok(chunk)
+    }
+
+    pub async fn fs_read_finish(
+        &self,
+    ) -> Result<FsResult, UsbError<Infallible>> {
+        let result =
+            self.client.send_resp::<FsReadFinishEndpoint>(&()).await?;
+        Ok(result)
+    }
+
+    pub async fn fs_delete(
+        &self,
+        name: &str,
+    ) -> Result<FsResult, UsbError<FileNameTooLong>> {
+        let req = FsDelete { name: fs_name(name)? };
+        let result = self.client.send_resp::<FsDeleteEndpoint>(&req).await?;
+        Ok(result)
+    }
+
+    // Storage Service Methods
+    pub async fn get_storage_status(
+        &self,
+    ) -> Result<Option<StorageStatus>, UsbError<Infallible>> {
+        let status =
+            self.client.send_resp::<StorageStatusEndpoint>(&()).await?;
+        Ok(status)
+    }
+
+    /// Delete every recording on the SD card: the files in its root
+    /// directory, or every session in the raw log. The filesystem is kept.
+    pub async fn clear_storage(
+        &self,
+    ) -> Result<FsResult, UsbError<Infallible>> {
+        let result =
+            self.client.send_resp::<StorageClearEndpoint>(&()).await?;
+        Ok(result)
+    }
+
+    /// Download a file from the SD card, resuming after `resume_from` bytes.
+    pub async fn fs_download(
+        &self,
+        name: &str,
+        resume_from: u32,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+        let begin = self.fs_read_begin(name).await?;
+        if !begin.success {
+            return Err(
+                format!("Open {name} failed: {}", begin.message).into()
+            );
+        }
+
+        let mut data = Vec::with_capacity(begin.size as usize);
+        let mut offset = resume_from;
+        while offset < begin.size {
+            let Some(chunk) =
+                self.fs_read_chunk(offset, FS_CHUNK_SIZE as u16).await?
+            else {
+                let _ = self.fs_read_finish().await;
+                return Err(format!("Read failed at offset {offset}").into());
+            };
+            offset += chunk.data.len() as u32;
+            data.extend_from_slice(&chunk.data);
+            if chunk.eof || chunk.data.is_empty() {
+                break;
+            }
+        }
+
+        self.fs_read_finish().await?;
+        Ok(data)
+    }
+
     /// Perform a full DFU transfer of the given firmware binary.
     /// Sends the firmware in chunks and prints progress.
     pub async fn dfu_upload(
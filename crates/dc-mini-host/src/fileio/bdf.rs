@@ -0,0 +1,53 @@
+use super::edf::{EdfWriter, SampleFormat};
+use super::{
+    ConversionConfig, EegAnnotation, EegDataRecord, EegMetadata, EegWriter,
+    Error, Result,
+};
+
+/// Writer for BioSemi BDF+ files.
+///
+/// BDF is EDF with 24-bit samples, so the ADS1299 readings are stored as
+/// they are instead of being quantized to 16 bits. Header and annotations
+/// are the same as in [`EdfWriter`], which does the writing.
+pub struct BdfWriter {
+    inner: EdfWriter,
+}
+
+impl BdfWriter {
+    pub fn new(config: &ConversionConfig) -> Result<Self> {
+        match config {
+            ConversionConfig::Bdf { output_path, config, .. } => Ok(Self {
+                inner: EdfWriter::create(
+                    output_path,
+                    config,
+                    SampleFormat::Bdf,
+                )?,
+            }),
+            _ => Err(Error::InvalidInput(
+                "Expected BDF configuration".to_string(),
+            )),
+        }
+    }
+}
+
+impl EegWriter for BdfWriter {
+    fn set_metadata(&mut self, metadata: EegMetadata) {
+        self.inner.set_metadata(metadata);
+    }
+
+    fn set_annotations(&mut self, annotations: Vec<EegAnnotation>) {
+        self.inner.set_annotations(annotations);
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        self.inner.write_header()
+    }
+
+    fn write_data(&mut self, records: Vec<EegDataRecord>) -> Result<()> {
+        self.inner.write_data(records)
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        self.inner.finalize()
+    }
+}
@@ -0,0 +1,163 @@
+use super::{
+    ConversionConfig, EegDataRecord, EegMetadata, EegWriter, Error,
+    PhysicalUnitConversion, Result,
+};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Configuration specific to CSV export
+#[derive(Debug, Clone)]
+pub struct CsvConfig {
+    /// Field separator, e.g. ',' or ';' for spreadsheets in locales that
+    /// use a decimal comma, or '\t'
+    pub delimiter: char,
+    /// Prepend a column with the time of each sample in seconds
+    pub include_timestamps: bool,
+    /// Write microvolts instead of the raw ADC codes
+    pub physical_units: bool,
+}
+
+impl Default for CsvConfig {
+    fn default() -> Self {
+        Self { delimiter: ',', include_timestamps: true, physical_units: true }
+    }
+}
+
+/// Writer for comma-separated values, one row per sample.
+///
+/// The first row holds the column names: `timestamp`, if enabled, then the
+/// channel labels. Annotations are not written.
+pub struct CsvWriter {
+    writer: BufWriter<File>,
+    config: CsvConfig,
+    metadata: Option<EegMetadata>,
+    // Timestamp of the previous record, and how many samples have shared it
+    last_timestamp: Option<f64>,
+    samples_since_timestamp: usize,
+}
+
+impl CsvWriter {
+    pub fn new(config: &ConversionConfig) -> Result<Self> {
+        match config {
+            ConversionConfig::Csv { output_path, config, .. } => {
+                Self::create(output_path, config)
+            }
+            _ => Err(Error::InvalidInput(
+                "Expected CSV configuration".to_string(),
+            )),
+        }
+    }
+
+    fn create(output_path: &Path, config: &CsvConfig) -> Result<Self> {
+        if matches!(config.delimiter, '"' | '\n' | '\r') {
+            return Err(Error::InvalidInput(format!(
+                "Invalid CSV delimiter {:?}",
+                config.delimiter
+            )));
+        }
+        Ok(Self {
+            writer: BufWriter::new(File::create(output_path)?),
+            config: config.clone(),
+            metadata: None,
+            last_timestamp: None,
+            samples_since_timestamp: 0,
+        })
+    }
+
+    /// Quotes `field` if it contains the delimiter, a quote or a line break
+    fn escape(&self, field: &str) -> String {
+        if field.contains([self.config.delimiter, '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    fn write_row(&mut self, fields: &[String]) -> Result<()> {
+        let mut buf = [0; 4];
+        let delimiter: &str = self.config.delimiter.encode_utf8(&mut buf);
+        writeln!(self.writer, "{}", fields.join(delimiter))?;
+        Ok(())
+    }
+}
+
+impl EegWriter for CsvWriter {
+    fn set_metadata(&mut self, metadata: EegMetadata) {
+        self.metadata = Some(metadata);
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        let metadata = self.metadata.as_ref().ok_or(Error::NoMetadataSet)?;
+
+        let mut fields = Vec::with_capacity(metadata.num_channels + 1);
+        if self.config.include_timestamps {
+            fields.push("timestamp".to_string());
+        }
+        for i in 0..metadata.num_channels {
+            let label = match metadata.channel_labels.get(i) {
+                Some(label) if !label.is_empty() => self.escape(label),
+                _ => format!("ch{}", i + 1),
+            };
+            fields.push(label);
+        }
+        self.write_row(&fields)
+    }
+
+    fn write_data(&mut self, records: Vec<EegDataRecord>) -> Result<()> {
+        let metadata =
+            self.metadata.clone().ok_or_else(|| Error::NoMetadataSet)?;
+
+        let mut fields = Vec::with_capacity(metadata.num_channels + 1);
+        for record in records {
+            if record.samples.len() != metadata.num_channels {
+                return Err(Error::InvalidData(format!(
+                    "Record has {} channels, expected {}",
+                    record.samples.len(),
+                    metadata.num_channels
+                )));
+            }
+            let num_samples =
+                record.samples.iter().map(Vec::len).min().unwrap_or(0);
+
+            // A frame's samples all carry the timestamp of its first one,
+            // so the following samples are spaced by the sample period.
+            if record.timestamp != self.last_timestamp {
+                self.last_timestamp = record.timestamp;
+                self.samples_since_timestamp = 0;
+            }
+
+            for i in 0..num_samples {
+                fields.clear();
+                if self.config.include_timestamps {
+                    fields.push(match record.timestamp {
+                        Some(ts) => {
+                            let offset = (self.samples_since_timestamp + i)
+                                as f64
+                                / metadata.sample_rate;
+                            format!("{:.6}", ts + offset)
+                        }
+                        None => String::new(),
+                    });
+                }
+                for channel in &record.samples {
+                    fields.push(if self.config.physical_units {
+                        format!(
+                            "{:.3}",
+                            metadata.to_physical_units(channel[i])
+                        )
+                    } else {
+                        channel[i].to_string()
+                    });
+                }
+                self.write_row(&fields)?;
+            }
+            self.samples_since_timestamp += num_samples;
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
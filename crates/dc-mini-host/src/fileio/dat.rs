@@ -1,22 +1,47 @@
-use super::{EegDataRecord, EegMetadata, EegReader, Error, Result};
-use crate::icd::proto::AdsDataFrame;
+use super::{
+    EegAnnotation, EegDataRecord, EegMetadata, EegReader, Error, Result,
+};
+use crate::icd::proto::{AdsDataFrame, ImuRecord};
+use crate::icd::SessionMetadata;
+use crate::integrity::{IntegrityChecker, IntegrityReport};
 use chrono::DateTime;
 use prost::Message;
 use std::fs::File;
 use std::io::{self, BufReader, Read, Seek, SeekFrom};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 // Eventually, this metadata will be contained in the files we write out.
-const SAMPLE_RATE: f64 = 250.0; // ADS1299 sample rate
-const BIT_DEPTH: u8 = 24; // ADS1299 bit depth
+pub(crate) const SAMPLE_RATE: f64 = 250.0; // ADS1299 sample rate
+pub(super) const BIT_DEPTH: u8 = 24; // ADS1299 bit depth
 const VREF: f64 = 4.5; // Reference voltage in volts
 const GAIN: f64 = 24.0; // PGA gain
 
 // Conversion factor from digital values to microvolts
-const CONVERSION_FACTOR: f64 = (VREF / GAIN)
+pub(super) const CONVERSION_FACTOR: f64 = (VREF / GAIN)
     / (i32::pow(2, BIT_DEPTH as u32 - 1) as f64 - 1.0)
     * 1_000_000.0;
 
+/// Reads the session metadata stored next to a `.dat` recording (same stem,
+/// `.met` extension). Returns `None` if the recording has no metadata file.
+pub fn read_session_metadata(path: &Path) -> Result<Option<SessionMetadata>> {
+    match std::fs::read(path.with_extension("met")) {
+        Ok(bytes) => Ok(Some(postcard::from_bytes(&bytes)?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads the IMU records interleaved with the ADS frames of a recording,
+/// in recording order.
+pub fn read_imu_records(path: &Path) -> Result<Vec<ImuRecord>> {
+    let mut reader = DatReader::new(&path.to_path_buf())?;
+    let mut records = Vec::new();
+    while let Some(frame) = reader.read_frame()? {
+        records.extend(frame.imu);
+    }
+    Ok(records)
+}
+
 pub struct DatReader {
     reader: BufReader<File>,
     path: PathBuf,
@@ -34,7 +59,7 @@ impl DatReader {
         })
     }
 
-    fn read_frame(&mut self) -> Result<Option<AdsDataFrame>> {
+    pub(crate) fn read_frame(&mut self) -> Result<Option<AdsDataFrame>> {
         let mut size_buf = [0u8; 4];
         match self.reader.read_exact(&mut size_buf) {
             Ok(()) => {
@@ -114,6 +139,18 @@ impl EegReader for DatReader {
                     Error::InvalidData("Invalid timestamp".to_string())
                 })?;
 
+        let session = read_session_metadata(&self.path)?;
+        // Prefer the wall-clock start recorded by the device.
+        let start_time = session
+            .as_ref()
+            .and_then(|s| s.start_epoch_us)
+            .and_then(|us| DateTime::from_timestamp_micros(us as i64))
+            .unwrap_or(start_time);
+        let patient_id = session
+            .as_ref()
+            .filter(|s| !s.subject_code.is_empty())
+            .map(|s| s.subject_code.to_string());
+
         // Find actual physical min/max values from the data
         let (physical_min, physical_max) = self.find_physical_range()?;
 
@@ -124,7 +161,7 @@ impl EegReader for DatReader {
                 .map(|i| format!("EEG-{}", i))
                 .collect(),
             start_time: Some(start_time),
-            patient_id: None,
+            patient_id,
             recording_id: self
                 .path
                 .file_stem()
@@ -134,6 +171,7 @@ impl EegReader for DatReader {
             physical_min,
             physical_max,
             conversion_factor: CONVERSION_FACTOR,
+            session,
         };
 
         self.metadata = Some(metadata.clone());
@@ -168,4 +206,38 @@ impl EegReader for DatReader {
 
         Ok(records)
     }
+
+    fn read_annotations(&mut self) -> Result<Vec<EegAnnotation>> {
+        let mut annotations = Vec::new();
+        if let Some(session) = read_session_metadata(&self.path)? {
+            annotations.extend(EegAnnotation::session_start(&session));
+        }
+
+        // Markers ride along in the frames
+        let current_pos = self.reader.stream_position()?;
+        self.reader.seek(SeekFrom::Start(0))?;
+        while let Some(frame) = self.read_frame()? {
+            for marker in frame.markers {
+                annotations.push(EegAnnotation::new(marker.ts, marker.label));
+            }
+        }
+        self.reader.seek(SeekFrom::Start(current_pos))?;
+
+        let report = self.integrity_report()?;
+        annotations.extend(report.gaps.iter().map(EegAnnotation::gap));
+        annotations.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+        Ok(annotations)
+    }
+
+    fn integrity_report(&mut self) -> Result<IntegrityReport> {
+        let current_pos = self.reader.stream_position()?;
+        self.reader.seek(SeekFrom::Start(0))?;
+        let mut checker = IntegrityChecker::new(SAMPLE_RATE);
+        while let Some(frame) = self.read_frame()? {
+            checker.check_ads(&frame);
+        }
+        self.reader.seek(SeekFrom::Start(current_pos))?;
+        Ok(checker.into_report())
+    }
 }
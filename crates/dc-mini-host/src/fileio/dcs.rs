@@ -0,0 +1,365 @@
+use super::dat::{BIT_DEPTH, CONVERSION_FACTOR, SAMPLE_RATE};
+use super::{
+    EegAnnotation, EegDataRecord, EegMetadata, EegReader, Error, Result,
+};
+use crate::icd::container::{
+    FileHeader, RecordHeader, RecordKind, CONTAINER_VERSION,
+};
+use crate::icd::imu_proto::ImuQuaternion;
+use crate::icd::mic_proto::MicDataFrame;
+use crate::icd::proto::{AdsDataFrame, EventMarker, ImuRecord};
+use crate::icd::{ApdsDataFrame, LeadOffStatus, SessionMetadata};
+use crate::integrity::{IntegrityChecker, IntegrityReport};
+use chrono::DateTime;
+use prost::Message;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Largest record payload accepted, far above anything the device or the
+/// host writes. A longer length means the record header is corrupt.
+const MAX_RECORD_LEN: u32 = 1 << 20;
+
+/// A decoded session container record.
+#[derive(Debug, Clone)]
+pub enum Record {
+    Metadata(SessionMetadata),
+    Ads(AdsDataFrame),
+    Imu(ImuRecord),
+    Mic(MicDataFrame),
+    Apds(ApdsDataFrame),
+    Marker(EventMarker),
+    LeadOff(LeadOffStatus),
+    Quaternion(ImuQuaternion),
+    /// A record type added after this reader was built.
+    Unknown {
+        kind: u8,
+        ts: u64,
+    },
+}
+
+/// Reader for `.dcs` session containers written by the device.
+pub struct SessionReader {
+    reader: BufReader<File>,
+    /// File size in bytes, bounding the record lengths read.
+    len: u64,
+    path: PathBuf,
+    version: u16,
+    metadata: Option<EegMetadata>,
+}
+
+impl SessionReader {
+    pub fn new(path: &PathBuf) -> Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut header_buf = [0u8; FileHeader::SIZE];
+        reader.read_exact(&mut header_buf)?;
+        let header = FileHeader::from_bytes(&header_buf).ok_or_else(|| {
+            Error::InvalidData("Not a DC Mini session file".to_string())
+        })?;
+        if header.version > CONTAINER_VERSION {
+            return Err(Error::InvalidData(format!(
+                "Unsupported session file version {}",
+                header.version
+            )));
+        }
+        Ok(Self {
+            reader,
+            len,
+            path: path.clone(),
+            version: header.version,
+            metadata: None,
+        })
+    }
+
+    /// Container version the file was written with.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Reads the next record, or `None` at the end of the file. A record
+    /// truncated by a power loss is treated as the end of the file; one
+    /// longer than [`MAX_RECORD_LEN`] is an error.
+    pub fn next_record(&mut self) -> Result<Option<Record>> {
+        let mut header_buf = [0u8; RecordHeader::SIZE];
+        match self.reader.read_exact(&mut header_buf) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let header = RecordHeader::from_bytes(&header_buf);
+        if header.len > MAX_RECORD_LEN {
+            return Err(Error::InvalidData(format!(
+                "Record length {} exceeds {MAX_RECORD_LEN} bytes",
+                header.len
+            )));
+        }
+        let remaining =
+            self.len.saturating_sub(self.reader.stream_position()?);
+        if u64::from(header.len) > remaining {
+            // Cut short by a power loss.
+            return Ok(None);
+        }
+        let mut payload = vec![0u8; header.len as usize];
+        match self.reader.read_exact(&mut payload) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        let record = match header.kind() {
+            Ok(RecordKind::Metadata) => {
+                Record::Metadata(postcard::from_bytes(&payload)?)
+            }
+            Ok(RecordKind::Ads) => {
+                Record::Ads(AdsDataFrame::decode(&payload[..])?)
+            }
+            Ok(RecordKind::Imu) => {
+                Record::Imu(ImuRecord::decode(&payload[..])?)
+            }
+            Ok(RecordKind::Mic) => {
+                Record::Mic(MicDataFrame::decode(&payload[..])?)
+            }
+            Ok(RecordKind::Apds) => {
+                Record::Apds(postcard::from_bytes(&payload)?)
+            }
+            Ok(RecordKind::Marker) => {
+                Record::Marker(EventMarker::decode(&payload[..])?)
+            }
+            Ok(RecordKind::LeadOff) => {
+                Record::LeadOff(postcard::from_bytes(&payload)?)
+            }
+            Ok(RecordKind::Quaternion) => {
+                Record::Quaternion(ImuQuaternion::decode(&payload[..])?)
+            }
+            Err(kind) => Record::Unknown { kind, ts: header.ts },
+        };
+        Ok(Some(record))
+    }
+
+    /// Seeks back to the first record.
+    pub fn rewind(&mut self) -> Result<()> {
+        self.reader.seek(SeekFrom::Start(FileHeader::SIZE as u64))?;
+        Ok(())
+    }
+}
+
+/// Writer for `.dcs` session containers, for recordings made on the host.
+pub struct SessionWriter {
+    writer: BufWriter<File>,
+}
+
+impl SessionWriter {
+    /// Creates `path` and writes the file header.
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&FileHeader::current().to_bytes())?;
+        Ok(Self { writer })
+    }
+
+    pub fn push_proto(
+        &mut self,
+        kind: RecordKind,
+        ts: u64,
+        message: &impl Message,
+    ) -> Result<()> {
+        self.push(kind, ts, &message.encode_to_vec())
+    }
+
+    pub fn push_postcard(
+        &mut self,
+        kind: RecordKind,
+        ts: u64,
+        value: &impl Serialize,
+    ) -> Result<()> {
+        self.push(kind, ts, &postcard::to_stdvec(value)?)
+    }
+
+    fn push(
+        &mut self,
+        kind: RecordKind,
+        ts: u64,
+        payload: &[u8],
+    ) -> Result<()> {
+        let header = RecordHeader::new(kind, payload.len() as u32, ts);
+        self.writer.write_all(&header.to_bytes())?;
+        self.writer.write_all(payload)?;
+        Ok(())
+    }
+
+    /// Writes out buffered records, so a crash loses at most what was
+    /// received since.
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl EegReader for SessionReader {
+    fn read_header(&mut self) -> Result<EegMetadata> {
+        self.rewind()?;
+
+        let mut session = None;
+        let mut first_frame: Option<AdsDataFrame> = None;
+        let mut min_value = f64::MAX;
+        let mut max_value = f64::MIN;
+        while let Some(record) = self.next_record()? {
+            match record {
+                Record::Metadata(metadata) if session.is_none() => {
+                    session = Some(metadata);
+                }
+                Record::Ads(frame) => {
+                    for value in frame.samples.iter().flat_map(|s| &s.data) {
+                        let physical_value = *value as f64 * CONVERSION_FACTOR;
+                        min_value = min_value.min(physical_value);
+                        max_value = max_value.max(physical_value);
+                    }
+                    if first_frame.is_none() {
+                        first_frame = Some(frame);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.rewind()?;
+
+        let first_frame = first_frame.ok_or_else(|| {
+            Error::InvalidData("No ADS data in session file".to_string())
+        })?;
+        let num_channels = first_frame
+            .samples
+            .first()
+            .map(|sample| sample.data.len())
+            .ok_or_else(|| {
+                Error::InvalidData("No samples in first frame".to_string())
+            })?;
+
+        let start_time =
+            DateTime::from_timestamp_micros(first_frame.ts as i64)
+                .ok_or_else(|| {
+                    Error::InvalidData("Invalid timestamp".to_string())
+                })?;
+        // Prefer the wall-clock start recorded by the device.
+        let start_time = session
+            .as_ref()
+            .and_then(|s| s.start_epoch_us)
+            .and_then(|us| DateTime::from_timestamp_micros(us as i64))
+            .unwrap_or(start_time);
+        let patient_id = session
+            .as_ref()
+            .filter(|s| !s.subject_code.is_empty())
+            .map(|s| s.subject_code.to_string());
+
+        if min_value == f64::MAX || max_value == f64::MIN {
+            let max_digital = (1i32 << (BIT_DEPTH - 1)) - 1;
+            let min_digital = -(1i32 << (BIT_DEPTH - 1));
+            min_value = min_digital as f64 * CONVERSION_FACTOR;
+            max_value = max_digital as f64 * CONVERSION_FACTOR;
+        }
+
+        let metadata = EegMetadata {
+            num_channels,
+            sample_rate: SAMPLE_RATE,
+            channel_labels: (1..=num_channels)
+                .map(|i| format!("EEG-{}", i))
+                .collect(),
+            start_time: Some(start_time),
+            patient_id,
+            recording_id: self
+                .path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .map(String::from),
+            bit_depth: BIT_DEPTH,
+            physical_min: min_value,
+            physical_max: max_value,
+            conversion_factor: CONVERSION_FACTOR,
+            session,
+        };
+
+        self.metadata = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    fn read_data(&mut self) -> Result<Vec<EegDataRecord>> {
+        let num_channels =
+            self.metadata.as_ref().ok_or(Error::NoMetadataSet)?.num_channels;
+        self.rewind()?;
+
+        let mut records = Vec::new();
+        while let Some(record) = self.next_record()? {
+            let Record::Ads(frame) = record else {
+                continue;
+            };
+            for sample in frame.samples {
+                let mut channel_samples = vec![Vec::new(); num_channels];
+                for (ch_idx, &value) in
+                    sample.data.iter().enumerate().take(num_channels)
+                {
+                    channel_samples[ch_idx].push(value);
+                }
+                records.push(EegDataRecord {
+                    timestamp: Some(frame.ts as f64 / 1_000_000.0),
+                    samples: channel_samples,
+                });
+            }
+        }
+        Ok(records)
+    }
+
+    fn read_annotations(&mut self) -> Result<Vec<EegAnnotation>> {
+        self.rewind()?;
+
+        let mut annotations = Vec::new();
+        let mut session_start = false;
+        while let Some(record) = self.next_record()? {
+            match record {
+                Record::Metadata(metadata) if !session_start => {
+                    session_start = true;
+                    annotations
+                        .extend(EegAnnotation::session_start(&metadata));
+                }
+                Record::Marker(marker) => {
+                    annotations
+                        .push(EegAnnotation::new(marker.ts, marker.label));
+                }
+                Record::LeadOff(status) => {
+                    annotations.push(EegAnnotation::lead_off(&status));
+                }
+                _ => {}
+            }
+        }
+
+        let report = self.integrity_report()?;
+        annotations.extend(report.gaps.iter().map(EegAnnotation::gap));
+        annotations.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        Ok(annotations)
+    }
+
+    fn integrity_report(&mut self) -> Result<IntegrityReport> {
+        self.rewind()?;
+
+        let mut checker = IntegrityChecker::new(SAMPLE_RATE);
+        while let Some(record) = self.next_record()? {
+            match record {
+                Record::Ads(frame) => {
+                    checker.check_ads(&frame);
+                }
+                Record::Mic(frame) => {
+                    checker.check_mic(&frame);
+                }
+                Record::Quaternion(q) => {
+                    checker.check_orientation(&q);
+                }
+                _ => {}
+            }
+        }
+        self.rewind()?;
+        Ok(checker.into_report())
+    }
+}
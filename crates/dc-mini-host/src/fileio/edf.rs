@@ -1,11 +1,12 @@
 use super::{
-    ConversionConfig, EegDataRecord, EegMetadata, EegWriter, Error,
-    PhysicalUnitConversion, Result,
+    ConversionConfig, EegAnnotation, EegDataRecord, EegMetadata, EegWriter,
+    Error, PhysicalUnitConversion, Result,
 };
 use byteorder::{LittleEndian, WriteBytesExt};
 use chrono::{Datelike, NaiveDate, Timelike};
 use std::fs::File;
 use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
 
 // EDF constants
 const EDF_VERSION: &str = "0"; // 8 chars with spaces
@@ -15,8 +16,14 @@ const DURATION_OF_RECORD: f32 = 1.0; // 1 second per record
 const EDF_DIGITAL_MIN: i16 = -32768;
 const EDF_DIGITAL_MAX: i16 = 32767;
 
+// BDF: BioSemi's 24-bit variant of EDF
+const BDF_VERSION: [u8; 8] = *b"\xFFBIOSEMI";
+const BDF_DIGITAL_MIN: i32 = -8_388_608;
+const BDF_DIGITAL_MAX: i32 = 8_388_607;
+
 // EDF+ annotation-related constants
-const TAL_DURATION_CHAR: u8 = 0x14; // ASCII DC4 (20 decimal) - Annotation separator
+const TAL_SEPARATOR: u8 = 0x14; // ASCII DC4 (20 decimal) - Annotation separator
+const TAL_DURATION_CHAR: u8 = 0x15; // ASCII NAK (21 decimal) - Precedes the duration
 const TAL_END_CHAR: u8 = 0x00; // NULL terminator (0 decimal)
 
 // Standard EEG electrode positions according to 10/20 and 10/10% system
@@ -91,20 +98,21 @@ impl EdfAnnotation {
 
     /// Format a number for EDF+ annotations, removing unnecessary trailing zeros
     fn format_number(value: f64, include_plus: bool) -> String {
+        // Onsets before the first record are allowed and carry a '-'
+        let sign = if value < 0.0 {
+            "-"
+        } else if include_plus {
+            "+"
+        } else {
+            ""
+        };
+        let value = value.abs();
         if value.fract() == 0.0 {
             // For integer values, use simple format
-            if include_plus {
-                format!("+{}", value as i64)
-            } else {
-                format!("{}", value as i64)
-            }
+            format!("{}{}", sign, value as i64)
         } else {
             // For fractional values, format with up to 6 decimal places, no trailing zeros
-            let mut s = if include_plus {
-                format!("+{:.6}", value)
-            } else {
-                format!("{:.6}", value)
-            };
+            let mut s = format!("{}{:.6}", sign, value);
 
             // Remove trailing zeros
             while s.ends_with('0') && s.contains('.') {
@@ -129,10 +137,18 @@ impl EdfAnnotation {
             Self::format_number(self.onset, true).as_bytes(),
         );
 
-        // Add annotation text
-        bytes.push(TAL_DURATION_CHAR);
-        bytes.extend_from_slice(self.text.as_bytes());
-        bytes.push(TAL_DURATION_CHAR);
+        if let Some(duration) = self.duration {
+            bytes.push(TAL_DURATION_CHAR);
+            bytes.extend_from_slice(
+                Self::format_number(duration, false).as_bytes(),
+            );
+        }
+
+        // Add annotation text, without the bytes that delimit TALs
+        bytes.push(TAL_SEPARATOR);
+        let text = self.text.replace(['\x14', '\x15', '\0'], " ");
+        bytes.extend_from_slice(text.as_bytes());
+        bytes.push(TAL_SEPARATOR);
         bytes.push(TAL_END_CHAR);
 
         bytes
@@ -192,12 +208,53 @@ impl EdfConfig {
     }
 }
 
+/// Formats `value` as a single EDF+ header subfield ('X' if unknown).
+fn edf_subfield(value: &str) -> String {
+    if value.is_empty() {
+        "X".to_string()
+    } else {
+        value.replace(' ', "_")
+    }
+}
+
+/// Sample encoding, which is what sets EDF and BDF apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SampleFormat {
+    /// EDF: 16-bit samples, scaled from the recording's physical range
+    Edf,
+    /// BDF: 24-bit samples, the ADS1299 readings as they are
+    Bdf,
+}
+
+impl SampleFormat {
+    fn digital_range(self) -> (i32, i32) {
+        match self {
+            SampleFormat::Edf => {
+                (EDF_DIGITAL_MIN as i32, EDF_DIGITAL_MAX as i32)
+            }
+            SampleFormat::Bdf => (BDF_DIGITAL_MIN, BDF_DIGITAL_MAX),
+        }
+    }
+
+    fn bytes_per_sample(self) -> usize {
+        match self {
+            SampleFormat::Edf => 2,
+            SampleFormat::Bdf => 3,
+        }
+    }
+}
+
 pub struct EdfWriter {
     writer: BufWriter<File>,
+    format: SampleFormat,
     config: EdfConfig,
     metadata: Option<EegMetadata>,
     record_count: i64,
-    annotations: Vec<EdfAnnotation>, // Add this field to store annotations
+    annotations: Vec<EdfAnnotation>,
+    // Index of the first annotation not yet written to a data record
+    next_annotation: usize,
+    // Events from the reader, placed once the first data timestamp is known
+    events: Vec<EegAnnotation>,
 }
 
 impl EdfWriter {
@@ -205,19 +262,30 @@ impl EdfWriter {
         match config {
             ConversionConfig::Edf {
                 output_path, config: edf_config, ..
-            } => Ok(Self {
-                writer: BufWriter::new(File::create(output_path)?),
-                config: edf_config.clone(),
-                metadata: None,
-                record_count: -1,
-                annotations: Vec::new(),
-            }),
-            // _ => Err(Error::InvalidInput(
-            //     "Expected EDF configuration".to_string(),
-            // )),
+            } => Self::create(output_path, edf_config, SampleFormat::Edf),
+            _ => Err(Error::InvalidInput(
+                "Expected EDF configuration".to_string(),
+            )),
         }
     }
 
+    pub(super) fn create(
+        output_path: &Path,
+        config: &EdfConfig,
+        format: SampleFormat,
+    ) -> Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(output_path)?),
+            format,
+            config: config.clone(),
+            metadata: None,
+            record_count: -1,
+            annotations: Vec::new(),
+            next_annotation: 0,
+            events: Vec::new(),
+        })
+    }
+
     fn write_str(&mut self, s: &str, width: usize) -> Result<()> {
         // Ensure we write exactly width bytes, space-padded on the left
         let bytes = format!("{:<width$}", s, width = width).into_bytes();
@@ -267,6 +335,48 @@ impl EdfWriter {
             as i16
     }
 
+    /// Write one sample. EDF scales it into 16 bits; BDF keeps the 24-bit
+    /// reading, with the physical range in the header set to match.
+    fn write_sample(
+        &mut self,
+        raw_value: i32,
+        metadata: &EegMetadata,
+    ) -> Result<()> {
+        match self.format {
+            SampleFormat::Edf => {
+                let edf_value = self.scale_to_edf_digital(raw_value, metadata);
+                self.writer.write_i16::<LittleEndian>(edf_value)?;
+            }
+            SampleFormat::Bdf => {
+                let bdf_value =
+                    raw_value.clamp(BDF_DIGITAL_MIN, BDF_DIGITAL_MAX);
+                self.writer.write_i24::<LittleEndian>(bdf_value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Physical range of the data signals, in mV
+    fn physical_range(&self, metadata: &EegMetadata) -> (f64, f64) {
+        match self.format {
+            SampleFormat::Edf => (
+                metadata.physical_min / 1000.0,
+                metadata.physical_max / 1000.0,
+            ),
+            SampleFormat::Bdf => (
+                metadata.to_physical_units(BDF_DIGITAL_MIN) / 1000.0,
+                metadata.to_physical_units(BDF_DIGITAL_MAX) / 1000.0,
+            ),
+        }
+    }
+
+    fn annotations_label(&self) -> &'static str {
+        match self.format {
+            SampleFormat::Edf => "EDF Annotations",
+            SampleFormat::Bdf => "BDF Annotations",
+        }
+    }
+
     /// Add an annotation to the EDF+ file
     pub fn add_annotation(&mut self, annotation: EdfAnnotation) {
         self.annotations.push(annotation);
@@ -283,19 +393,37 @@ impl EdfWriter {
         bytes.extend_from_slice(
             EdfAnnotation::format_number(record_time, true).as_bytes(),
         );
-        bytes.push(TAL_DURATION_CHAR);
-        bytes.push(TAL_DURATION_CHAR);
+        bytes.push(TAL_SEPARATOR);
+        bytes.push(TAL_SEPARATOR);
         bytes.push(TAL_END_CHAR);
 
         bytes
     }
 
-    /// Format all annotations for a specific data record
-    fn format_annotations(&self, record_index: usize) -> Vec<u8> {
-        let samples_per_record =
-            self.config.annotations_samples_per_record * 2;
+    /// Turns the reader's events into annotations relative to `start`, the
+    /// timestamp of the first sample
+    fn place_events(&mut self, start: f64) {
+        for event in self.events.drain(..) {
+            self.annotations.push(EdfAnnotation::new(
+                event.timestamp - start,
+                event.duration,
+                event.text,
+            ));
+        }
+    }
+
+    /// Format the annotations signal of a data record: the timekeeping TAL,
+    /// then the annotations due by the end of the record. Annotations that
+    /// do not fit are carried over to the next record; the last record
+    /// takes everything left that fits.
+    fn format_annotations(
+        &mut self,
+        record_index: usize,
+        last: bool,
+    ) -> Vec<u8> {
+        let samples_per_record = self.config.annotations_samples_per_record
+            * self.format.bytes_per_sample();
         let mut buffer = vec![0u8; samples_per_record];
-        let mut position = 0;
 
         // Start with the required timekeeping TAL
         let timekeeping_tal = Self::create_timekeeping_tal(record_index);
@@ -303,45 +431,40 @@ impl EdfWriter {
         // Add timekeeping TAL to buffer
         let copy_len = timekeeping_tal.len().min(buffer.len());
         buffer[..copy_len].copy_from_slice(&timekeeping_tal[..copy_len]);
-        position += copy_len;
-
-        // Calculate time range for this record
-        let record_time = record_index as f64 * DURATION_OF_RECORD as f64;
-        let record_end_time = record_time + DURATION_OF_RECORD as f64;
+        let mut position = copy_len;
 
-        // Add any annotations that fall within this record's time range
-        for annotation in &self.annotations {
-            if annotation.onset >= record_time
-                && annotation.onset < record_end_time
-            {
-                let annotation_bytes = annotation.to_bytes();
-                let remaining_space = buffer.len() - position;
-
-                if annotation_bytes.len() <= remaining_space {
-                    // Copy the entire annotation
-                    buffer[position..position + annotation_bytes.len()]
-                        .copy_from_slice(&annotation_bytes);
-                    position += annotation_bytes.len();
-                } else {
-                    // Copy as much as we can
-                    if remaining_space > 0 {
-                        buffer[position..].copy_from_slice(
-                            &annotation_bytes[..remaining_space],
-                        );
-                        // position = buffer.len(); // buffer is now full
-                    }
-
-                    break;
-                }
+        let record_end_time =
+            (record_index + 1) as f64 * DURATION_OF_RECORD as f64;
+        while let Some(annotation) = self.annotations.get(self.next_annotation)
+        {
+            if annotation.onset >= record_end_time && !last {
+                break;
+            }
+            let annotation_bytes = annotation.to_bytes();
+            if annotation_bytes.len() > buffer.len() - copy_len {
+                // Longer than any record can hold
+                self.next_annotation += 1;
+                continue;
             }
+            if annotation_bytes.len() > buffer.len() - position {
+                break;
+            }
+            buffer[position..position + annotation_bytes.len()]
+                .copy_from_slice(&annotation_bytes);
+            position += annotation_bytes.len();
+            self.next_annotation += 1;
         }
 
         buffer
     }
 
     /// Write the EDF Annotations signal to the file
-    fn write_annotations_signal(&mut self, record_index: usize) -> Result<()> {
-        let buffer = self.format_annotations(record_index);
+    fn write_annotations_signal(
+        &mut self,
+        record_index: usize,
+        last: bool,
+    ) -> Result<()> {
+        let buffer = self.format_annotations(record_index, last);
         self.writer.write_all(&buffer)?;
         Ok(())
     }
@@ -349,18 +472,29 @@ impl EdfWriter {
 
 impl EegWriter for EdfWriter {
     fn set_metadata(&mut self, mut metadata: EegMetadata) {
-        // Override the start time with the one from our config
-        if let Ok(dt) = self
-            .config
-            .recording_start_date
-            .and_hms_opt(0, 0, 0)
-            .ok_or_else(|| Error::InvalidInput("invalid date".to_string()))
-        {
-            metadata.start_time = Some(dt.and_utc());
+        // Override the start time with the one from our config, unless the
+        // device recorded the wall-clock start of the session.
+        let device_start = metadata
+            .session
+            .as_ref()
+            .is_some_and(|s| s.start_epoch_us.is_some());
+        if !device_start {
+            if let Ok(dt) = self
+                .config
+                .recording_start_date
+                .and_hms_opt(0, 0, 0)
+                .ok_or_else(|| Error::InvalidInput("invalid date".to_string()))
+            {
+                metadata.start_time = Some(dt.and_utc());
+            }
         }
         self.metadata = Some(metadata);
     }
 
+    fn set_annotations(&mut self, annotations: Vec<EegAnnotation>) {
+        self.events = annotations;
+    }
+
     fn write_header(&mut self) -> Result<()> {
         // Extract all metadata first to avoid borrow conflicts
         let metadata =
@@ -387,10 +521,36 @@ impl EegWriter for EdfWriter {
         let _channel_labels = metadata.channel_labels.clone();
         let start_time = metadata.start_time;
 
+        // Session metadata from the device fills in what the config leaves
+        // out. EDF+ subfields are space separated, so spaces become '_'.
+        let session = metadata.session.clone().unwrap_or_default();
+        let patient_code = metadata
+            .patient_id
+            .as_deref()
+            .map(edf_subfield)
+            .unwrap_or_else(|| self.config.hospital_code.clone());
+        let technician = if self.config.recording_technician.is_empty() {
+            edf_subfield(&session.operator)
+        } else {
+            self.config.recording_technician.clone()
+        };
+        let mut recording_extra = String::new();
+        for (key, value) in
+            [("Montage", &session.montage[..]), ("Notes", &session.notes[..])]
+        {
+            if !value.is_empty() {
+                recording_extra.push_str(&format!(
+                    " {}:{}",
+                    key,
+                    edf_subfield(value)
+                ));
+            }
+        }
+
         // Format patient identification according to EDF+ spec
         let patient_id = format!(
             "{} {} {} {}",
-            self.config.hospital_code,
+            patient_code,
             self.config.patient_sex,
             self.config
                 .patient_birthdate
@@ -402,15 +562,16 @@ impl EegWriter for EdfWriter {
 
         // Format recording identification according to EDF+ spec
         let recording_id = format!(
-            "Startdate {} {} {} {}",
+            "Startdate {} {} {} {}{}",
             self.config
                 .recording_start_date
                 .format("%d-%b-%Y")
                 .to_string()
                 .to_uppercase(),
             self.config.hospital_code,
-            self.config.recording_technician,
-            self.config.recording_equipment
+            technician,
+            self.config.recording_equipment,
+            recording_extra
         );
 
         let header_bytes = 256 + (total_channels * 256);
@@ -418,7 +579,10 @@ impl EegWriter for EdfWriter {
             (metadata.sample_rate * DURATION_OF_RECORD as f64) as u32;
 
         // Write version
-        self.write_str(EDF_VERSION, 8)?;
+        match self.format {
+            SampleFormat::Edf => self.write_str(EDF_VERSION, 8)?,
+            SampleFormat::Bdf => self.writer.write_all(&BDF_VERSION)?,
+        }
 
         // Write patient and recording IDs
         self.write_str(&patient_id, 80)?;
@@ -452,7 +616,15 @@ impl EegWriter for EdfWriter {
         self.write_str(&date_str, 8)?;
         self.write_str(&time_str, 8)?;
         self.write_num(header_bytes, 8)?;
-        self.write_str("EDF+C", 44)?; // Changed from EDF to EDF+C to indicate continuous recording
+        // EDF+C marks a continuous EDF+ recording, which requires the
+        // annotations signal; without it the file is plain EDF.
+        let reserved = match (self.format, self.config.include_annotations) {
+            (SampleFormat::Edf, true) => "EDF+C",
+            (SampleFormat::Edf, false) => "",
+            (SampleFormat::Bdf, true) => "BDF+C",
+            (SampleFormat::Bdf, false) => "24BIT",
+        };
+        self.write_str(reserved, 44)?;
         self.write_num(self.record_count, 8)?;
         self.write_float(DURATION_OF_RECORD as f64, 8)?;
         self.write_num(total_channels, 4)?;
@@ -465,7 +637,7 @@ impl EegWriter for EdfWriter {
 
         // Write EDF Annotations label if enabled
         if self.config.include_annotations {
-            self.write_str("EDF Annotations", 16)?;
+            self.write_str(self.annotations_label(), 16)?;
         }
 
         // Write transducer type (80 chars each)
@@ -489,8 +661,9 @@ impl EegWriter for EdfWriter {
         }
 
         // Write physical min values
+        let (physical_min, physical_max) = self.physical_range(&metadata);
         for _ in 0..num_channels {
-            self.write_float(metadata.physical_min / 1000.0, 8)?;
+            self.write_float(physical_min, 8)?;
         }
 
         // Write physical min for annotations (just needs to differ from max)
@@ -500,7 +673,7 @@ impl EegWriter for EdfWriter {
 
         // Write physical max values
         for _ in 0..num_channels {
-            self.write_float(metadata.physical_max / 1000.0, 8)?;
+            self.write_float(physical_max, 8)?;
         }
 
         // Write physical max for annotations (just needs to differ from min)
@@ -509,23 +682,24 @@ impl EegWriter for EdfWriter {
         }
 
         // Write digital min values
+        let (digital_min, digital_max) = self.format.digital_range();
         for _ in 0..num_channels {
-            self.write_num(EDF_DIGITAL_MIN, 8)?;
+            self.write_num(digital_min, 8)?;
         }
 
         // Write digital min for annotations
         if self.config.include_annotations {
-            self.write_num(EDF_DIGITAL_MIN, 8)?;
+            self.write_num(digital_min, 8)?;
         }
 
         // Write digital max values
         for _ in 0..num_channels {
-            self.write_num(EDF_DIGITAL_MAX, 8)?;
+            self.write_num(digital_max, 8)?;
         }
 
         // Write digital max for annotations
         if self.config.include_annotations {
-            self.write_num(EDF_DIGITAL_MAX, 8)?;
+            self.write_num(digital_max, 8)?;
         }
 
         // Write prefiltering fields
@@ -572,6 +746,16 @@ impl EegWriter for EdfWriter {
             vec![Vec::new(); num_channels];
         let mut total_samples = 0;
 
+        // Annotation onsets count from the first sample
+        if self.record_count < 0 {
+            match records.iter().find_map(|r| r.timestamp) {
+                Some(start) => self.place_events(start),
+                // Without timestamps events cannot be placed
+                None => self.events.clear(),
+            }
+            self.annotations.sort_by(|a, b| a.onset.total_cmp(&b.onset));
+        }
+
         // First, reorganize samples by channel
         for record in records.iter() {
             for (ch_idx, channel_samples) in record.samples.iter().enumerate()
@@ -583,6 +767,9 @@ impl EegWriter for EdfWriter {
 
         // Now write complete records
         let num_complete_records = total_samples / samples_per_record;
+        let remaining_samples = total_samples % samples_per_record;
+        let num_records =
+            num_complete_records + (remaining_samples > 0) as usize;
         for record_idx in 0..num_complete_records {
             // Write all channels for this record
             for ch_buffer in &channel_buffers {
@@ -590,41 +777,39 @@ impl EegWriter for EdfWriter {
                 let end = start + samples_per_record;
                 // Write samples for this channel
                 for &value in &ch_buffer[start..end] {
-                    let edf_value =
-                        self.scale_to_edf_digital(value, &metadata);
-                    self.writer.write_i16::<LittleEndian>(edf_value)?;
+                    self.write_sample(value, &metadata)?;
                 }
             }
 
             // Write annotations channel if enabled
             if self.config.include_annotations {
-                self.write_annotations_signal(record_idx)?;
+                let index = (self.record_count + 1) as usize;
+                let last = record_idx + 1 == num_records;
+                self.write_annotations_signal(index, last)?;
             }
 
             self.record_count += 1;
         }
 
         // Handle any remaining samples
-        let remaining_samples = total_samples % samples_per_record;
         if remaining_samples > 0 {
             // Write remaining samples for each channel
             for ch_buffer in &channel_buffers {
                 let start = num_complete_records * samples_per_record;
                 // Write remaining samples
                 for &value in &ch_buffer[start..start + remaining_samples] {
-                    let edf_value =
-                        self.scale_to_edf_digital(value, &metadata);
-                    self.writer.write_i16::<LittleEndian>(edf_value)?;
+                    self.write_sample(value, &metadata)?;
                 }
                 // Pad with zeros to complete the record
-                for _ in 0..(samples_per_record - remaining_samples) {
-                    self.writer.write_i16::<LittleEndian>(0)?;
-                }
+                let padding = (samples_per_record - remaining_samples)
+                    * self.format.bytes_per_sample();
+                self.writer.write_all(&vec![0u8; padding])?;
             }
 
             // Write annotations for the last partial record
             if self.config.include_annotations {
-                self.write_annotations_signal(num_complete_records)?;
+                let index = (self.record_count + 1) as usize;
+                self.write_annotations_signal(index, true)?;
             }
 
             self.record_count += 1;
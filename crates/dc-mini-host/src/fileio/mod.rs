@@ -2,10 +2,19 @@ use derive_more::{Display, From};
 use std::io;
 use std::path::PathBuf;
 
+pub mod bdf;
+pub mod csv;
 pub mod dat;
+pub mod dcs;
 pub mod edf;
+pub mod rawlog;
+pub mod timebase;
+pub mod xdf;
 
+use crate::integrity::{Gap, IntegrityReport};
+use csv::CsvConfig;
 use edf::EdfConfig;
+use timebase::TimebaseConfig;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -21,20 +30,43 @@ pub enum Error {
     // External
     IoError(io::Error),
     ProstError(prost::DecodeError),
+    Postcard(postcard::Error),
     Egui(eframe::Error),
     SerdeJson(serde_json::Error),
 }
 
+impl std::error::Error for Error {}
+
 /// Configuration for file conversion
 #[derive(Debug, Clone)]
 pub enum ConversionConfig {
-    Edf { input_path: PathBuf, output_path: PathBuf, config: EdfConfig },
+    Edf {
+        input_path: PathBuf,
+        output_path: PathBuf,
+        config: EdfConfig,
+        timebase: TimebaseConfig,
+    },
+    /// EDF+ with 24-bit samples; takes the same configuration
+    Bdf {
+        input_path: PathBuf,
+        output_path: PathBuf,
+        config: EdfConfig,
+        timebase: TimebaseConfig,
+    },
+    Csv {
+        input_path: PathBuf,
+        output_path: PathBuf,
+        config: CsvConfig,
+        timebase: TimebaseConfig,
+    },
 }
 
 impl ConversionConfig {
     pub fn input_path(&self) -> &PathBuf {
         match self {
             ConversionConfig::Edf { input_path, .. } => input_path,
+            ConversionConfig::Bdf { input_path, .. } => input_path,
+            ConversionConfig::Csv { input_path, .. } => input_path,
             // Add arms for other formats
         }
     }
@@ -42,14 +74,28 @@ impl ConversionConfig {
     pub fn output_path(&self) -> &PathBuf {
         match self {
             ConversionConfig::Edf { output_path, .. } => output_path,
+            ConversionConfig::Bdf { output_path, .. } => output_path,
+            ConversionConfig::Csv { output_path, .. } => output_path,
             // Add arms for other formats
         }
     }
+
+    /// Correction applied to the timestamps before writing
+    pub fn timebase(&self) -> &TimebaseConfig {
+        match self {
+            ConversionConfig::Edf { timebase, .. } => timebase,
+            ConversionConfig::Bdf { timebase, .. } => timebase,
+            ConversionConfig::Csv { timebase, .. } => timebase,
+        }
+    }
 }
 
 /// Common trait for all file writers that can write EEG data
 pub trait EegWriter {
     fn set_metadata(&mut self, metadata: EegMetadata);
+    /// Events to store with the data; call before [`Self::write_data`].
+    /// Formats without an annotation channel ignore them.
+    fn set_annotations(&mut self, _annotations: Vec<EegAnnotation>) {}
     fn write_header(&mut self) -> Result<()>;
     fn write_data(&mut self, records: Vec<EegDataRecord>) -> Result<()>;
     fn finalize(&mut self) -> Result<()>;
@@ -59,6 +105,15 @@ pub trait EegWriter {
 pub trait EegReader {
     fn read_header(&mut self) -> Result<EegMetadata>;
     fn read_data(&mut self) -> Result<Vec<EegDataRecord>>;
+    /// Events recorded alongside the data, in recording order.
+    fn read_annotations(&mut self) -> Result<Vec<EegAnnotation>> {
+        Ok(Vec::new())
+    }
+    /// Data the recording is missing. Formats that do not number their
+    /// frames report none.
+    fn integrity_report(&mut self) -> Result<IntegrityReport> {
+        Ok(IntegrityReport::default())
+    }
 }
 
 /// Metadata common to all EEG file formats
@@ -74,6 +129,7 @@ pub struct EegMetadata {
     pub physical_min: f64, // Physical minimum value in microvolts
     pub physical_max: f64, // Physical maximum value in microvolts
     pub conversion_factor: f64, // Factor to convert from digital to physical units (microvolts)
+    pub session: Option<crate::icd::SessionMetadata>, // Metadata recorded alongside the session, if any
 }
 
 /// Single data record containing samples for all channels
@@ -83,6 +139,72 @@ pub struct EegDataRecord {
     pub samples: Vec<Vec<i32>>, // Raw digital samples for each channel (signed)
 }
 
+/// An event recorded alongside the data, such as an event marker
+#[derive(Debug, Clone)]
+pub struct EegAnnotation {
+    /// Seconds, on the same clock as [`EegDataRecord::timestamp`]
+    pub timestamp: f64,
+    pub duration: Option<f64>,
+    pub text: String,
+}
+
+impl EegAnnotation {
+    pub fn new(timestamp_us: u64, text: impl Into<String>) -> Self {
+        Self {
+            timestamp: timestamp_us as f64 / 1_000_000.0,
+            duration: None,
+            text: text.into(),
+        }
+    }
+
+    /// The wall-clock start of a session, if the device clock was set.
+    pub fn session_start(
+        session: &crate::icd::SessionMetadata,
+    ) -> Option<Self> {
+        let start_us = session.start_epoch_us?;
+        let start = chrono::DateTime::from_timestamp_micros(start_us as i64)?;
+        Some(Self::new(
+            start_us,
+            format!(
+                "Session start {}",
+                start.to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+            ),
+        ))
+    }
+
+    /// Describes the electrodes without contact, or that all have it.
+    pub fn lead_off(status: &crate::icd::LeadOffStatus) -> Self {
+        let off: Vec<String> = status
+            .channels
+            .iter()
+            .enumerate()
+            .filter_map(|(i, ch)| {
+                let side = match (ch.positive, ch.negative) {
+                    (true, true) => "P/N",
+                    (true, false) => "P",
+                    (false, true) => "N",
+                    (false, false) => return None,
+                };
+                Some(format!("ch{} {}", i + 1, side))
+            })
+            .collect();
+        let text = if off.is_empty() {
+            "All leads on".to_string()
+        } else {
+            format!("Lead off {}", off.join(", "))
+        };
+        Self::new(status.ts, text)
+    }
+
+    /// Marks the time a stream was without data.
+    pub fn gap(gap: &Gap) -> Self {
+        let mut annotation = Self::new(gap.start_us(), format!("Gap: {gap}"));
+        annotation.duration = (gap.duration_us > 0)
+            .then(|| gap.duration_us as f64 / 1_000_000.0);
+        annotation
+    }
+}
+
 /// Trait for converting between digital and physical units
 pub trait PhysicalUnitConversion {
     fn to_physical_units(&self, digital_value: i32) -> f64;
@@ -104,6 +226,12 @@ pub fn create_writer(config: &ConversionConfig) -> Result<Box<dyn EegWriter>> {
     match config {
         ConversionConfig::Edf { .. } => {
             Ok(Box::new(edf::EdfWriter::new(config)?))
+        }
+        ConversionConfig::Bdf { .. } => {
+            Ok(Box::new(bdf::BdfWriter::new(config)?))
+        }
+        ConversionConfig::Csv { .. } => {
+            Ok(Box::new(csv::CsvWriter::new(config)?))
         } // Add arms for other formats
     }
 }
@@ -115,8 +243,9 @@ pub fn create_reader(path: &PathBuf) -> Result<Box<dyn EegReader>> {
 
     match ext.to_lowercase().as_str() {
         "dat" => Ok(Box::new(dat::DatReader::new(path)?)),
+        "dcs" => Ok(Box::new(dcs::SessionReader::new(path)?)),
         _ => Err(Error::InvalidInput(format!(
-            "Unsupported input format: {}. Only DAT format is supported.",
+            "Unsupported input format: {}. Expected DAT or DCS.",
             ext
         ))),
     }
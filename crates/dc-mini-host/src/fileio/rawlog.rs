@@ -0,0 +1,80 @@
+use super::{Error, Result};
+use crate::icd::rawlog::{
+    BlockHeader, Superblock, BLOCK_SIZE, FIRST_DATA_BLOCK,
+};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Reader for SD cards written with the firmware's `raw-log` feature.
+///
+/// Takes a card image or a raw block device and yields the sessions stored
+/// in it as `.dcs` container bytes.
+pub struct RawLogReader {
+    reader: BufReader<File>,
+    superblock: Superblock,
+}
+
+impl RawLogReader {
+    pub fn new(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut block = [0u8; BLOCK_SIZE];
+        reader.read_exact(&mut block)?;
+        let superblock = Superblock::from_bytes(&block).ok_or_else(|| {
+            Error::InvalidData("Not a DC Mini raw log".to_string())
+        })?;
+        Ok(Self { reader, superblock })
+    }
+
+    pub fn superblock(&self) -> Superblock {
+        self.superblock
+    }
+
+    /// Reads the next data block, or `None` at the end of the log. The log
+    /// ends at the first block that does not verify, which is where the
+    /// device stopped writing.
+    pub fn next_block(&mut self) -> Result<Option<(u32, Vec<u8>)>> {
+        let mut block = [0u8; BLOCK_SIZE];
+        match self.reader.read_exact(&mut block) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(BlockHeader::verify(&block, self.superblock.generation)
+            .map(|header| (header.session, header.payload(&block).to_vec())))
+    }
+
+    /// Writes each session to `dir` as `NNNN.dcs` and returns the paths.
+    /// Sessions are streamed, so this works on whole cards.
+    pub fn extract(&mut self, dir: &Path) -> Result<Vec<PathBuf>> {
+        std::fs::create_dir_all(dir)?;
+        self.reader.seek(SeekFrom::Start(
+            FIRST_DATA_BLOCK as u64 * BLOCK_SIZE as u64,
+        ))?;
+        let mut paths = Vec::new();
+        let mut current: Option<(u32, BufWriter<File>)> = None;
+        while let Some((session, payload)) = self.next_block()? {
+            match current.as_mut() {
+                Some((open, writer)) if *open == session => {
+                    writer.write_all(&payload)?;
+                }
+                _ => {
+                    if let Some((_, mut writer)) = current.take() {
+                        writer.flush()?;
+                    }
+                    let path = dir.join(format!("{:04}.dcs", session));
+                    let mut writer = BufWriter::new(File::create(&path)?);
+                    writer.write_all(&payload)?;
+                    paths.push(path);
+                    current = Some((session, writer));
+                }
+            }
+        }
+        if let Some((_, mut writer)) = current {
+            writer.flush()?;
+        }
+        Ok(paths)
+    }
+}
@@ -0,0 +1,239 @@
+//! Correcting the timebase of a recording before export.
+//!
+//! Frames are stamped on the device clock, and over BLE the stamps jitter
+//! with the connection schedule. Smoothing fits a line through the frame
+//! timestamps against the number of samples before each frame, and gives
+//! every sample its time on that line: a uniform timebase at the rate the
+//! samples actually arrived, free of jitter and of the drift between the
+//! ADS and the device clock. A jump that the sample count does not explain
+//! is a gap, and starts a new line.
+//!
+//! Resampling interpolates the smoothed samples onto a grid at another
+//! rate, for analysis tools that expect a particular one.
+
+use super::{EegDataRecord, EegMetadata, Error, Result};
+
+/// A frame arriving this much earlier than the samples before it account
+/// for means the device clock was set back.
+const MAX_CLOCK_STEP: f64 = 1.0;
+
+/// Per-conversion timebase correction; the default leaves the timestamps
+/// as recorded.
+#[derive(Debug, Clone, Default)]
+pub struct TimebaseConfig {
+    /// Replace the frame timestamps by a uniform timebase fitted to them
+    pub smooth: bool,
+    /// Resample to this rate in Hz; implies `smooth`. Whole numbers keep
+    /// EDF records exactly one second long.
+    pub resample_rate: Option<f64>,
+}
+
+/// A frame timestamp and the number of samples before the frame
+#[derive(Debug, Clone, Copy)]
+struct Anchor {
+    record: usize,
+    sample: usize,
+    ts: f64,
+}
+
+/// Records sharing one fitted line, `ts = offset + period * sample`
+#[derive(Debug, Clone)]
+struct Segment {
+    records: std::ops::Range<usize>,
+    offset: f64,
+    period: f64,
+}
+
+impl Segment {
+    fn time(&self, sample: usize) -> f64 {
+        self.offset + self.period * sample as f64
+    }
+}
+
+/// Applies `config` to the records of a recording, updating the sample
+/// rate in `metadata` if they are resampled.
+pub fn apply(
+    config: &TimebaseConfig,
+    metadata: &mut EegMetadata,
+    mut records: Vec<EegDataRecord>,
+) -> Result<Vec<EegDataRecord>> {
+    if let Some(rate) = config.resample_rate {
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(Error::InvalidInput(format!(
+                "Invalid resampling rate {rate}"
+            )));
+        }
+    }
+    if !config.smooth && config.resample_rate.is_none() {
+        return Ok(records);
+    }
+
+    let starts = sample_starts(&records);
+    let segments = fit_segments(&records, &starts, metadata.sample_rate);
+    if segments.is_empty() && !records.is_empty() {
+        return Err(Error::InvalidData(
+            "Recording has no timestamps to correct".to_string(),
+        ));
+    }
+    for segment in &segments {
+        for i in segment.records.clone() {
+            records[i].timestamp = Some(segment.time(starts[i]));
+        }
+    }
+
+    match config.resample_rate {
+        Some(rate) => {
+            metadata.sample_rate = rate;
+            Ok(segments
+                .iter()
+                .flat_map(|segment| {
+                    resample(&records, &starts, segment, metadata, rate)
+                })
+                .collect())
+        }
+        None => Ok(records),
+    }
+}
+
+fn num_samples(record: &EegDataRecord) -> usize {
+    record.samples.iter().map(Vec::len).min().unwrap_or(0)
+}
+
+/// Number of samples before each record
+fn sample_starts(records: &[EegDataRecord]) -> Vec<usize> {
+    records
+        .iter()
+        .scan(0, |total, record| {
+            let start = *total;
+            *total += num_samples(record);
+            Some(start)
+        })
+        .collect()
+}
+
+/// Splits the records at gaps and fits a line through the frame
+/// timestamps of each part.
+fn fit_segments(
+    records: &[EegDataRecord],
+    starts: &[usize],
+    sample_rate: f64,
+) -> Vec<Segment> {
+    // A frame's samples share its timestamp; the first one carries it
+    let mut anchors = Vec::new();
+    let mut last_ts = None;
+    for (i, record) in records.iter().enumerate() {
+        if record.timestamp != last_ts {
+            last_ts = record.timestamp;
+            if let Some(ts) = record.timestamp {
+                anchors.push(Anchor { record: i, sample: starts[i], ts });
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<Anchor>> = Vec::new();
+    for anchor in anchors {
+        let gap = match groups.last().map(Vec::as_slice) {
+            Some([first, .., prev]) if prev.sample > first.sample => {
+                // The rate seen so far, which includes any decimation
+                let period =
+                    (prev.ts - first.ts) / (prev.sample - first.sample) as f64;
+                is_gap(prev, &anchor, period)
+            }
+            Some([.., prev]) => is_gap(prev, &anchor, 1.0 / sample_rate),
+            _ => true,
+        };
+        match groups.last_mut() {
+            Some(group) if !gap => group.push(anchor),
+            _ => groups.push(vec![anchor]),
+        }
+    }
+
+    let mut segments: Vec<Segment> = Vec::with_capacity(groups.len());
+    for (i, group) in groups.iter().enumerate() {
+        // Records before the first timestamp join the first segment
+        let first = if i == 0 { 0 } else { group[0].record };
+        let end = groups.get(i + 1).map_or(records.len(), |g| g[0].record);
+        let (offset, period) = fit_line(group, 1.0 / sample_rate);
+        segments.push(Segment { records: first..end, offset, period });
+    }
+    segments
+}
+
+/// Whether samples are missing between `prev` and `next`, given the time
+/// per sample. As with lost frames on the device, a hole of more than half
+/// the previous frame counts.
+fn is_gap(prev: &Anchor, next: &Anchor, period: f64) -> bool {
+    let frame_len = (next.sample - prev.sample) as f64 * period;
+    let late = next.ts - prev.ts - frame_len;
+    late > frame_len / 2.0 || late < -MAX_CLOCK_STEP
+}
+
+/// Least-squares fit of `ts = offset + period * sample`. A single frame
+/// leaves the slope open, so the nominal period is used, as it is when
+/// the stamps are too erratic to give a positive one.
+fn fit_line(anchors: &[Anchor], nominal_period: f64) -> (f64, f64) {
+    let n = anchors.len() as f64;
+    let mean_sample = anchors.iter().map(|a| a.sample as f64).sum::<f64>() / n;
+    let mean_ts = anchors.iter().map(|a| a.ts).sum::<f64>() / n;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for anchor in anchors {
+        let ds = anchor.sample as f64 - mean_sample;
+        covariance += ds * (anchor.ts - mean_ts);
+        variance += ds * ds;
+    }
+    let period = if variance > 0.0 && covariance > 0.0 {
+        covariance / variance
+    } else {
+        nominal_period
+    };
+    (mean_ts - period * mean_sample, period)
+}
+
+/// Interpolates the samples of `segment` linearly onto a grid at `rate`,
+/// starting at its first sample. There is no anti-aliasing filter, so
+/// reduce the bandwidth first when resampling far below the recorded rate.
+fn resample(
+    records: &[EegDataRecord],
+    starts: &[usize],
+    segment: &Segment,
+    metadata: &EegMetadata,
+    rate: f64,
+) -> Vec<EegDataRecord> {
+    let mut channels = vec![Vec::new(); metadata.num_channels];
+    for record in &records[segment.records.clone()] {
+        let n = num_samples(record);
+        for (channel, samples) in channels.iter_mut().zip(&record.samples) {
+            channel.extend_from_slice(&samples[..n]);
+        }
+    }
+    let len = channels.first().map_or(0, Vec::len);
+    if len == 0 {
+        return Vec::new();
+    }
+
+    let first_sample = starts[segment.records.start];
+    let start = segment.time(first_sample);
+    // Input samples advanced per output sample
+    let step = 1.0 / (rate * segment.period);
+    let out_len = ((len - 1) as f64 / step).floor() as usize + 1;
+
+    (0..out_len)
+        .map(|k| {
+            let position = k as f64 * step;
+            let j = (position as usize).min(len - 1);
+            let fraction = position - j as f64;
+            EegDataRecord {
+                timestamp: Some(start + k as f64 / rate),
+                samples: channels
+                    .iter()
+                    .map(|channel| {
+                        let a = channel[j] as f64;
+                        let b =
+                            *channel.get(j + 1).unwrap_or(&channel[j]) as f64;
+                        vec![(a + (b - a) * fraction).round() as i32]
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,531 @@
+use super::dcs::{Record, SessionReader};
+use super::timebase::TimebaseConfig;
+use super::{
+    EegAnnotation, EegMetadata, Error, PhysicalUnitConversion, Result,
+};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// XDF constants
+const XDF_MAGIC: &[u8; 4] = b"XDF:";
+// Fixed UUID of boundary chunks, which let readers resync in damaged files
+const BOUNDARY_UUID: [u8; 16] = [
+    0x43, 0xA5, 0x46, 0xDC, 0xCB, 0xF5, 0x41, 0x0F, 0xB3, 0x0E, 0xD5, 0x46,
+    0x73, 0x83, 0xCB, 0xE4,
+];
+const SAMPLES_PER_CHUNK: usize = 1024;
+
+// Chunk tags
+const TAG_FILE_HEADER: u16 = 1;
+const TAG_STREAM_HEADER: u16 = 2;
+const TAG_SAMPLES: u16 = 3;
+const TAG_BOUNDARY: u16 = 5;
+const TAG_STREAM_FOOTER: u16 = 6;
+
+/// Value type of the channels of a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelFormat {
+    Float32,
+    Int16,
+    String,
+}
+
+impl ChannelFormat {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Float32 => "float32",
+            Self::Int16 => "int16",
+            Self::String => "string",
+        }
+    }
+}
+
+/// A channel value as stored in a samples chunk
+pub trait XdfValue {
+    const FORMAT: ChannelFormat;
+    fn write_to(&self, buf: &mut Vec<u8>);
+}
+
+impl XdfValue for f32 {
+    const FORMAT: ChannelFormat = ChannelFormat::Float32;
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl XdfValue for i16 {
+    const FORMAT: ChannelFormat = ChannelFormat::Int16;
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl XdfValue for &str {
+    const FORMAT: ChannelFormat = ChannelFormat::String;
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        write_varlen(buf, self.len() as u64);
+        buf.extend_from_slice(self.as_bytes());
+    }
+}
+
+/// One channel of a stream
+#[derive(Debug, Clone)]
+pub struct ChannelInfo {
+    pub label: String,
+    pub unit: String,
+    /// Content of the channel, e.g. "EEG" or "AccelerationX"
+    pub kind: String,
+}
+
+impl ChannelInfo {
+    pub fn new(label: impl Into<String>, unit: &str, kind: &str) -> Self {
+        Self { label: label.into(), unit: unit.into(), kind: kind.into() }
+    }
+}
+
+/// Description of a stream, written to its header
+#[derive(Debug, Clone)]
+pub struct StreamInfo {
+    pub name: String,
+    /// Content type, e.g. "EEG" or "Markers"
+    pub kind: String,
+    pub source_id: String,
+    pub channel_format: ChannelFormat,
+    /// Sample rate in Hz, 0 for irregular streams such as markers
+    pub nominal_srate: f64,
+    pub channels: Vec<ChannelInfo>,
+}
+
+struct StreamState {
+    format: ChannelFormat,
+    channel_count: usize,
+    nominal_srate: f64,
+    first_timestamp: Option<f64>,
+    last_timestamp: Option<f64>,
+    sample_count: u64,
+    // Encoded samples not yet written as a chunk
+    pending: Vec<u8>,
+    pending_samples: usize,
+}
+
+/// Writer for Extensible Data Format (XDF) files.
+///
+/// XDF holds any number of streams, each with its own channels, rate and
+/// timestamps, so EEG, IMU, audio and markers recorded at different rates
+/// are stored side by side, as LSL recorders do. Samples are buffered per
+/// stream and written in chunks; call [`Self::finish`] to write the rest
+/// and the stream footers.
+pub struct XdfWriter {
+    writer: BufWriter<File>,
+    streams: Vec<StreamState>,
+}
+
+impl XdfWriter {
+    /// Creates `path` and writes the file header.
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(XDF_MAGIC)?;
+        let mut xdf = Self { writer, streams: Vec::new() };
+        xdf.write_chunk(
+            TAG_FILE_HEADER,
+            b"<?xml version=\"1.0\"?><info><version>1.0</version></info>",
+        )?;
+        Ok(xdf)
+    }
+
+    /// Writes the header of a new stream and returns its id.
+    pub fn add_stream(&mut self, info: &StreamInfo) -> Result<u32> {
+        let id = self.streams.len() as u32 + 1;
+
+        let mut xml = String::from("<?xml version=\"1.0\"?><info>");
+        xml += &format!("<name>{}</name>", escape_xml(&info.name));
+        xml += &format!("<type>{}</type>", escape_xml(&info.kind));
+        xml +=
+            &format!("<channel_count>{}</channel_count>", info.channels.len());
+        xml +=
+            &format!("<nominal_srate>{}</nominal_srate>", info.nominal_srate);
+        xml += &format!(
+            "<channel_format>{}</channel_format>",
+            info.channel_format.as_str()
+        );
+        xml +=
+            &format!("<source_id>{}</source_id>", escape_xml(&info.source_id));
+        xml += "<desc><channels>";
+        for channel in &info.channels {
+            xml += &format!(
+                "<channel><label>{}</label><unit>{}</unit><type>{}</type></channel>",
+                escape_xml(&channel.label),
+                escape_xml(&channel.unit),
+                escape_xml(&channel.kind)
+            );
+        }
+        xml += "</channels><acquisition><manufacturer>DC-Mini</manufacturer>";
+        xml += "</acquisition></desc></info>";
+
+        let mut content = Vec::with_capacity(4 + xml.len());
+        content.write_u32::<LittleEndian>(id)?;
+        content.extend_from_slice(xml.as_bytes());
+        self.write_chunk(TAG_STREAM_HEADER, &content)?;
+
+        self.streams.push(StreamState {
+            format: info.channel_format,
+            channel_count: info.channels.len(),
+            nominal_srate: info.nominal_srate,
+            first_timestamp: None,
+            last_timestamp: None,
+            sample_count: 0,
+            pending: Vec::new(),
+            pending_samples: 0,
+        });
+        Ok(id)
+    }
+
+    /// Adds one sample of `stream`, one value per channel. Leave out the
+    /// timestamp of samples that follow the previous one by exactly the
+    /// sample period; readers fill it in.
+    pub fn push_sample<T: XdfValue>(
+        &mut self,
+        stream: u32,
+        timestamp: Option<f64>,
+        values: &[T],
+    ) -> Result<()> {
+        let state = self.stream_mut(stream)?;
+        if state.format != T::FORMAT {
+            return Err(Error::InvalidInput(format!(
+                "Stream {} holds {} values",
+                stream,
+                state.format.as_str()
+            )));
+        }
+        if values.len() != state.channel_count {
+            return Err(Error::InvalidInput(format!(
+                "Stream {} has {} channels, got {} values",
+                stream,
+                state.channel_count,
+                values.len()
+            )));
+        }
+
+        match timestamp {
+            Some(ts) => {
+                state.pending.push(8);
+                state.pending.extend_from_slice(&ts.to_le_bytes());
+                state.first_timestamp.get_or_insert(ts);
+                state.last_timestamp = Some(ts);
+            }
+            None => {
+                state.pending.push(0);
+                if state.nominal_srate > 0.0 {
+                    state.last_timestamp = state
+                        .last_timestamp
+                        .map(|ts| ts + 1.0 / state.nominal_srate);
+                }
+            }
+        }
+        for value in values {
+            value.write_to(&mut state.pending);
+        }
+        state.pending_samples += 1;
+        state.sample_count += 1;
+
+        if state.pending_samples >= SAMPLES_PER_CHUNK {
+            self.flush_stream(stream)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered samples and the stream footers.
+    pub fn finish(mut self) -> Result<()> {
+        for stream in 1..=self.streams.len() as u32 {
+            self.flush_stream(stream)?;
+        }
+        self.write_chunk(TAG_BOUNDARY, &BOUNDARY_UUID)?;
+
+        for (i, state) in self.streams.iter().enumerate() {
+            let mut xml = String::from("<?xml version=\"1.0\"?><info>");
+            if let (Some(first), Some(last)) =
+                (state.first_timestamp, state.last_timestamp)
+            {
+                xml +=
+                    &format!("<first_timestamp>{}</first_timestamp>", first);
+                xml += &format!("<last_timestamp>{}</last_timestamp>", last);
+            }
+            xml += &format!(
+                "<sample_count>{}</sample_count></info>",
+                state.sample_count
+            );
+
+            let mut content = Vec::with_capacity(4 + xml.len());
+            content.write_u32::<LittleEndian>(i as u32 + 1)?;
+            content.extend_from_slice(xml.as_bytes());
+            write_chunk(&mut self.writer, TAG_STREAM_FOOTER, &content)?;
+        }
+        Ok(self.writer.flush()?)
+    }
+
+    fn stream_mut(&mut self, stream: u32) -> Result<&mut StreamState> {
+        (stream as usize)
+            .checked_sub(1)
+            .and_then(|i| self.streams.get_mut(i))
+            .ok_or_else(|| {
+                Error::InvalidInput(format!("No stream with id {}", stream))
+            })
+    }
+
+    fn flush_stream(&mut self, stream: u32) -> Result<()> {
+        let state = self.stream_mut(stream)?;
+        if state.pending_samples == 0 {
+            return Ok(());
+        }
+        let samples = std::mem::take(&mut state.pending);
+        let count = std::mem::take(&mut state.pending_samples);
+
+        let mut content = Vec::with_capacity(13 + samples.len());
+        content.write_u32::<LittleEndian>(stream)?;
+        write_varlen(&mut content, count as u64);
+        content.extend_from_slice(&samples);
+        self.write_chunk(TAG_SAMPLES, &content)
+    }
+
+    fn write_chunk(&mut self, tag: u16, content: &[u8]) -> Result<()> {
+        write_chunk(&mut self.writer, tag, content)
+    }
+}
+
+fn write_chunk(
+    writer: &mut impl Write,
+    tag: u16,
+    content: &[u8],
+) -> Result<()> {
+    // The length counts the tag too
+    let mut header = Vec::with_capacity(11);
+    write_varlen(&mut header, content.len() as u64 + 2);
+    header.write_u16::<LittleEndian>(tag)?;
+    writer.write_all(&header)?;
+    writer.write_all(content)?;
+    Ok(())
+}
+
+/// Writes `value` prefixed by its size in bytes: 1, 4 or 8
+fn write_varlen(buf: &mut Vec<u8>, value: u64) {
+    if value <= u8::MAX as u64 {
+        buf.push(1);
+        buf.push(value as u8);
+    } else if value <= u32::MAX as u64 {
+        buf.push(4);
+        buf.extend_from_slice(&(value as u32).to_le_bytes());
+    } else {
+        buf.push(8);
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn eeg_stream(metadata: &EegMetadata, source_id: &str) -> StreamInfo {
+    StreamInfo {
+        name: "DC-Mini EEG".to_string(),
+        kind: "EEG".to_string(),
+        source_id: source_id.to_string(),
+        channel_format: ChannelFormat::Float32,
+        nominal_srate: metadata.sample_rate,
+        channels: metadata
+            .channel_labels
+            .iter()
+            .map(|label| ChannelInfo::new(label, "microvolts", "EEG"))
+            .collect(),
+    }
+}
+
+fn markers_stream(source_id: &str) -> StreamInfo {
+    StreamInfo {
+        name: "DC-Mini Markers".to_string(),
+        kind: "Markers".to_string(),
+        source_id: source_id.to_string(),
+        channel_format: ChannelFormat::String,
+        nominal_srate: 0.0,
+        channels: vec![ChannelInfo::new("Marker", "", "Marker")],
+    }
+}
+
+fn imu_stream(source_id: &str) -> StreamInfo {
+    StreamInfo {
+        name: "DC-Mini IMU".to_string(),
+        kind: "IMU".to_string(),
+        source_id: source_id.to_string(),
+        channel_format: ChannelFormat::Float32,
+        nominal_srate: 0.0,
+        channels: vec![
+            ChannelInfo::new("AccelX", "g", "AccelerationX"),
+            ChannelInfo::new("AccelY", "g", "AccelerationY"),
+            ChannelInfo::new("AccelZ", "g", "AccelerationZ"),
+            ChannelInfo::new("GyroX", "dps", "AngularVelocityX"),
+            ChannelInfo::new("GyroY", "dps", "AngularVelocityY"),
+            ChannelInfo::new("GyroZ", "dps", "AngularVelocityZ"),
+            ChannelInfo::new("Temp", "celsius", "Temperature"),
+        ],
+    }
+}
+
+fn orientation_stream(source_id: &str) -> StreamInfo {
+    StreamInfo {
+        name: "DC-Mini Orientation".to_string(),
+        kind: "Orientation".to_string(),
+        source_id: source_id.to_string(),
+        channel_format: ChannelFormat::Float32,
+        nominal_srate: 0.0,
+        channels: ["W", "X", "Y", "Z"]
+            .iter()
+            .map(|axis| {
+                ChannelInfo::new(format!("Quat{}", axis), "", "Quaternion")
+            })
+            .collect(),
+    }
+}
+
+fn audio_stream(source_id: &str, sample_rate: u32) -> StreamInfo {
+    StreamInfo {
+        name: "DC-Mini Audio".to_string(),
+        kind: "Audio".to_string(),
+        source_id: source_id.to_string(),
+        channel_format: ChannelFormat::Int16,
+        nominal_srate: sample_rate as f64,
+        channels: vec![ChannelInfo::new("Mic", "", "Audio")],
+    }
+}
+
+/// Converts a recording to XDF, with timestamps in seconds on the device
+/// clock, which is Unix time once the host has synced it.
+///
+/// EEG and markers (event markers, session start, lead-off changes) are
+/// exported from any input. Session containers also contribute the IMU,
+/// orientation and audio streams they hold. `timebase` applies to the EEG.
+pub fn convert(
+    input_path: &PathBuf,
+    output_path: &Path,
+    timebase: &TimebaseConfig,
+) -> Result<()> {
+    let mut reader = super::create_reader(input_path)?;
+    let mut metadata = reader.read_header()?;
+    let annotations = reader.read_annotations()?;
+    let records = reader.read_data()?;
+    drop(reader);
+    let records = super::timebase::apply(timebase, &mut metadata, records)?;
+
+    let source_id = format!(
+        "dc-mini-{}",
+        metadata.recording_id.as_deref().unwrap_or("recording")
+    );
+    let mut xdf = XdfWriter::create(output_path)?;
+
+    // Samples after the first of a frame carry the frame's timestamp; leave
+    // theirs out so readers space them by the sample period.
+    let eeg = xdf.add_stream(&eeg_stream(&metadata, &source_id))?;
+    let mut last_timestamp = None;
+    let mut values = Vec::with_capacity(metadata.num_channels);
+    for record in &records {
+        let num_samples =
+            record.samples.iter().map(Vec::len).min().unwrap_or(0);
+        for i in 0..num_samples {
+            values.clear();
+            values.extend(
+                record.samples.iter().map(|channel| {
+                    metadata.to_physical_units(channel[i]) as f32
+                }),
+            );
+            let timestamp =
+                record.timestamp.filter(|ts| last_timestamp != Some(*ts));
+            last_timestamp = record.timestamp;
+            xdf.push_sample(eeg, timestamp, &values)?;
+        }
+    }
+    drop(records);
+
+    let markers = xdf.add_stream(&markers_stream(&source_id))?;
+    for EegAnnotation { timestamp, text, .. } in &annotations {
+        xdf.push_sample(markers, Some(*timestamp), &[text.as_str()])?;
+    }
+
+    let is_session = input_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("dcs"));
+    if is_session {
+        write_session_streams(&mut xdf, input_path, &source_id)?;
+    }
+
+    xdf.finish()
+}
+
+/// Adds the IMU, orientation and audio records of a session container,
+/// each stream created when its first record is found.
+fn write_session_streams(
+    xdf: &mut XdfWriter,
+    input_path: &PathBuf,
+    source_id: &str,
+) -> Result<()> {
+    let mut session = SessionReader::new(input_path)?;
+    let mut imu = None;
+    let mut orientation = None;
+    let mut audio = None;
+
+    while let Some(record) = session.next_record()? {
+        match record {
+            Record::Imu(r) => {
+                let stream = match imu {
+                    Some(stream) => stream,
+                    None => {
+                        *imu.insert(xdf.add_stream(&imu_stream(source_id))?)
+                    }
+                };
+                xdf.push_sample(
+                    stream,
+                    Some(r.ts as f64 / 1_000_000.0),
+                    &[
+                        r.accel_x, r.accel_y, r.accel_z, r.gyro_x, r.gyro_y,
+                        r.gyro_z, r.temp,
+                    ],
+                )?;
+            }
+            Record::Quaternion(q) => {
+                let stream = match orientation {
+                    Some(stream) => stream,
+                    None => *orientation.insert(
+                        xdf.add_stream(&orientation_stream(source_id))?,
+                    ),
+                };
+                xdf.push_sample(
+                    stream,
+                    Some(q.ts as f64 / 1_000_000.0),
+                    &[q.w, q.x, q.y, q.z],
+                )?;
+            }
+            Record::Mic(frame) => {
+                let stream = match audio {
+                    Some(stream) => stream,
+                    None => *audio.insert(xdf.add_stream(&audio_stream(
+                        source_id,
+                        frame.sample_rate,
+                    ))?),
+                };
+                // Frames are stamped with the capture time of their first
+                // sample; the rest follow at the nominal rate.
+                let pcm = crate::decode_mic_proto(&frame);
+                for (i, sample) in pcm.iter().enumerate() {
+                    let timestamp =
+                        (i == 0).then(|| frame.ts as f64 / 1_000_000.0);
+                    xdf.push_sample(stream, timestamp, &[*sample])?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,652 @@
+//! gRPC service bridging lab tooling to a device.
+//!
+//! [`DcMiniService`] implements the `control.DcMini` service of
+//! `protos/control.proto` on top of a [`ReconnectingConnection`], so clients
+//! in any language control the device and receive its streams without
+//! knowing whether it is on USB or BLE. Configurations are passed as JSON
+//! of the ICD types; frames are the protobuf messages of the ICD, as in
+//! session containers.
+
+use std::fmt;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bluest::error::ErrorKind;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use postcard_rpc::Topic;
+use prost::Message;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+use crate::icd::{self, imu_proto, mic_proto, proto};
+use crate::{BleClient, DeviceConnection, ReconnectingConnection, UsbClient};
+
+pub mod pb {
+    tonic::include_proto!("control");
+}
+
+use pb::dc_mini_server::{DcMini, DcMiniServer};
+
+/// Frames buffered per call before the device side waits for the client.
+const STREAM_BUFFER: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+enum DataStream {
+    Ads = 0,
+    Mic = 1,
+    Quaternion = 2,
+}
+
+/// Serves one device, reconnecting to it whenever it is lost.
+pub struct DcMiniService {
+    link: ReconnectingConnection,
+    /// Open streaming calls per [`DataStream`]; the device streams while
+    /// any is open.
+    users: Arc<[AtomicUsize; 3]>,
+}
+
+impl DcMiniService {
+    pub fn new(rt: &Handle, connection: DeviceConnection) -> Self {
+        Self {
+            link: ReconnectingConnection::spawn(rt, connection),
+            users: Arc::default(),
+        }
+    }
+
+    pub fn into_server(self) -> DcMiniServer<Self> {
+        DcMiniServer::new(self)
+    }
+
+    fn connection(&self) -> Result<DeviceConnection, Status> {
+        self.link
+            .current()
+            .ok_or_else(|| Status::unavailable("Device is reconnecting"))
+    }
+
+    /// Registers a streaming call, starting the device stream for the
+    /// first one.
+    async fn acquire(
+        &self,
+        connection: &DeviceConnection,
+        kind: DataStream,
+        rate: u8,
+    ) -> Result<StreamGuard, Status> {
+        let first =
+            self.users[kind as usize].fetch_add(1, Ordering::SeqCst) == 0;
+        let guard = StreamGuard {
+            connection: connection.clone(),
+            kind,
+            users: self.users.clone(),
+        };
+        if first {
+            start(connection, kind, rate).await?;
+        }
+        Ok(guard)
+    }
+}
+
+/// Stops the device stream once the last call using it ends.
+struct StreamGuard {
+    connection: DeviceConnection,
+    kind: DataStream,
+    users: Arc<[AtomicUsize; 3]>,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        if self.users[self.kind as usize].fetch_sub(1, Ordering::SeqCst) == 1 {
+            let (connection, kind) = (self.connection.clone(), self.kind);
+            tokio::spawn(async move {
+                if let Err(e) = stop(&connection, kind).await {
+                    println!("Failed to stop {kind:?} stream: {e}");
+                }
+            });
+        }
+    }
+}
+
+/// Frames of one streaming call; dropped by tonic when the call ends.
+pub struct FrameStream<M> {
+    frames: ReceiverStream<Result<M, Status>>,
+    _guard: StreamGuard,
+}
+
+impl<M> Stream for FrameStream<M> {
+    type Item = Result<M, Status>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.frames).poll_next(cx)
+    }
+}
+
+fn internal(e: impl fmt::Display) -> Status {
+    Status::internal(e.to_string())
+}
+
+fn disconnected() -> Status {
+    Status::unavailable("Device disconnected")
+}
+
+fn usb_only(what: &str) -> Status {
+    Status::unimplemented(format!("{what} is only available over USB"))
+}
+
+fn rejected(accepted: bool, what: &str) -> Result<(), Status> {
+    if accepted {
+        Ok(())
+    } else {
+        Err(Status::failed_precondition(format!("Device rejected {what}")))
+    }
+}
+
+fn to_config(value: &impl Serialize) -> Result<pb::Config, Status> {
+    Ok(pb::Config { json: serde_json::to_string(value).map_err(internal)? })
+}
+
+fn from_config<T: DeserializeOwned>(config: &pb::Config) -> Result<T, Status> {
+    serde_json::from_str(&config.json)
+        .map_err(|e| Status::invalid_argument(e.to_string()))
+}
+
+async fn start(
+    connection: &DeviceConnection,
+    kind: DataStream,
+    rate: u8,
+) -> Result<(), Status> {
+    match (connection, kind) {
+        (DeviceConnection::Usb(client), DataStream::Ads) => {
+            client.start_streaming().await.map_err(internal)?;
+        }
+        (DeviceConnection::Usb(client), DataStream::Mic) => {
+            client.start_mic_streaming().await.map_err(internal)?;
+        }
+        (DeviceConnection::Usb(client), DataStream::Quaternion) => {
+            let started = client
+                .start_quaternion_streaming(rate)
+                .await
+                .map_err(internal)?;
+            if !started {
+                return Err(Status::failed_precondition("Device has no IMU"));
+            }
+        }
+        (DeviceConnection::Ble(client), DataStream::Ads) => {
+            client.start_streaming().await.map_err(internal)?
+        }
+        (DeviceConnection::Ble(client), DataStream::Mic) => {
+            client.start_mic_streaming().await.map_err(internal)?
+        }
+        (DeviceConnection::Ble(client), DataStream::Quaternion) => {
+            client.start_quaternion_streaming(rate).await.map_err(internal)?
+        }
+    }
+    Ok(())
+}
+
+async fn stop(
+    connection: &DeviceConnection,
+    kind: DataStream,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match (connection, kind) {
+        (DeviceConnection::Usb(client), DataStream::Ads) => {
+            client.stop_streaming().await?
+        }
+        (DeviceConnection::Usb(client), DataStream::Mic) => {
+            client.stop_mic_streaming().await?
+        }
+        (DeviceConnection::Usb(client), DataStream::Quaternion) => {
+            client.stop_quaternion_streaming().await?
+        }
+        (DeviceConnection::Ble(client), DataStream::Ads) => {
+            client.stop_streaming().await?
+        }
+        (DeviceConnection::Ble(client), DataStream::Mic) => {
+            client.stop_mic_streaming().await?
+        }
+        (DeviceConnection::Ble(client), DataStream::Quaternion) => {
+            client.stop_quaternion_streaming().await?
+        }
+    }
+    Ok(())
+}
+
+/// Subscribes to topic `T` and forwards its messages, converted, until
+/// the call ends.
+async fn forward_usb<T, M>(
+    client: &UsbClient,
+    convert: fn(T::Message) -> M,
+) -> Result<mpsc::Receiver<Result<M, Status>>, Status>
+where
+    T: Topic,
+    T::Message: DeserializeOwned + Send + 'static,
+    M: Send + 'static,
+{
+    let mut sub =
+        client.client.subscribe_multi::<T>(8).await.map_err(|e| {
+            Status::internal(format!(
+                "Subscribing to {} failed: {e:?}",
+                T::PATH
+            ))
+        })?;
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tx.closed() => break,
+                message = sub.recv() => {
+                    let Ok(message) = message else {
+                        let _ = tx.send(Err(disconnected())).await;
+                        break;
+                    };
+                    if tx.send(Ok(convert(message))).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    Ok(rx)
+}
+
+fn boxed<'a>(
+    stream: impl Stream<Item = bluest::Result<Vec<u8>>> + Send + 'a,
+) -> BoxStream<'a, bluest::Result<Vec<u8>>> {
+    stream.boxed()
+}
+
+/// Subscribes to the notifications of `kind` and forwards them, decoded,
+/// until the call ends.
+async fn forward_ble<M>(
+    client: Arc<BleClient>,
+    kind: DataStream,
+    fixup: fn(&mut M),
+) -> Result<mpsc::Receiver<Result<M, Status>>, Status>
+where
+    M: Message + Default + 'static,
+{
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+    // The notification stream borrows the client, so it is opened inside
+    // the task, which reports back once it is subscribed.
+    let (subscribed_tx, subscribed) = oneshot::channel();
+    tokio::spawn(async move {
+        let stream = match kind {
+            DataStream::Ads => client.notify_ads_stream().await.map(boxed),
+            DataStream::Mic => client.notify_mic_stream().await.map(boxed),
+            DataStream::Quaternion => {
+                client.notify_quaternion_stream().await.map(boxed)
+            }
+        };
+        let mut stream = match stream {
+            Ok(stream) => {
+                let _ = subscribed_tx.send(Ok(()));
+                stream
+            }
+            Err(e) => {
+                let _ = subscribed_tx.send(Err(e.to_string()));
+                return;
+            }
+        };
+        loop {
+            tokio::select! {
+                _ = tx.closed() => break,
+                data = stream.next() => match data {
+                    Some(Ok(data)) => match M::decode(&data[..]) {
+                        Ok(mut message) => {
+                            fixup(&mut message);
+                            if tx.send(Ok(message)).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            println!("Dropping undecodable notification: {e}")
+                        }
+                    },
+                    Some(Err(e)) if e.kind() != ErrorKind::NotConnected => {
+                        println!("{kind:?} stream error: {e:?}");
+                    }
+                    _ => {
+                        let _ = tx.send(Err(disconnected())).await;
+                        break;
+                    }
+                },
+            }
+        }
+    });
+    subscribed
+        .await
+        .map_err(|_| Status::internal("Stream task ended"))?
+        .map_err(Status::internal)?;
+    Ok(rx)
+}
+
+#[tonic::async_trait]
+impl DcMini for DcMiniService {
+    type StreamAdsStream = FrameStream<proto::AdsDataFrame>;
+    type StreamMicStream = FrameStream<mic_proto::MicDataFrame>;
+    type StreamQuaternionStream = FrameStream<imu_proto::ImuQuaternion>;
+
+    async fn get_device_info(
+        &self,
+        _request: Request<pb::Empty>,
+    ) -> Result<Response<pb::DeviceInfo>, Status> {
+        let connection = self.connection()?;
+        let (info, serial) = match &connection {
+            DeviceConnection::Usb(client) => (
+                client.get_device_info().await.map_err(internal)?,
+                client.serial().map(str::to_owned),
+            ),
+            DeviceConnection::Ble(client) => {
+                (client.get_device_info().await.map_err(internal)?, None)
+            }
+        };
+        Ok(Response::new(pb::DeviceInfo {
+            transport: connection.transport().to_string(),
+            hardware_revision: info.hardware_revision.to_string(),
+            software_revision: info.software_revision.to_string(),
+            manufacturer_name: info.manufacturer_name.to_string(),
+            serial,
+        }))
+    }
+
+    async fn get_battery_level(
+        &self,
+        _request: Request<pb::Empty>,
+    ) -> Result<Response<pb::BatteryLevel>, Status> {
+        let level = match self.connection()? {
+            DeviceConnection::Usb(client) => {
+                client.get_battery_level().await.map_err(internal)?
+            }
+            DeviceConnection::Ble(client) => {
+                client.get_battery_level().await.map_err(internal)?
+            }
+        };
+        Ok(Response::new(pb::BatteryLevel { percent: level.0 as u32 }))
+    }
+
+    async fn sync_time(
+        &self,
+        _request: Request<pb::Empty>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        self.connection()?.sync_time().await.map_err(internal)?;
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    async fn get_ads_config(
+        &self,
+        _request: Request<pb::Empty>,
+    ) -> Result<Response<pb::Config>, Status> {
+        let config = match self.connection()? {
+            DeviceConnection::Usb(client) => {
+                client.get_ads_config().await.map_err(internal)?
+            }
+            DeviceConnection::Ble(client) => {
+                client.get_ads_config().await.map_err(internal)?
+            }
+        };
+        Ok(Response::new(to_config(&config)?))
+    }
+
+    async fn set_ads_config(
+        &self,
+        request: Request<pb::Config>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        let config: icd::AdsConfig = from_config(request.get_ref())?;
+        match self.connection()? {
+            DeviceConnection::Usb(client) => rejected(
+                client.set_ads_config(config).await.map_err(internal)?,
+                "the ADS configuration",
+            )?,
+            DeviceConnection::Ble(client) => {
+                client.set_ads_config(&config).await.map_err(internal)?
+            }
+        }
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    async fn get_mic_config(
+        &self,
+        _request: Request<pb::Empty>,
+    ) -> Result<Response<pb::Config>, Status> {
+        let config = match self.connection()? {
+            DeviceConnection::Usb(client) => {
+                client.get_mic_config().await.map_err(internal)?
+            }
+            DeviceConnection::Ble(client) => {
+                client.get_mic_config().await.map_err(internal)?
+            }
+        };
+        Ok(Response::new(to_config(&config)?))
+    }
+
+    async fn set_mic_config(
+        &self,
+        request: Request<pb::Config>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        let config: icd::MicConfig = from_config(request.get_ref())?;
+        match self.connection()? {
+            DeviceConnection::Usb(client) => rejected(
+                client.set_mic_config(config).await.map_err(internal)?,
+                "the mic configuration",
+            )?,
+            DeviceConnection::Ble(client) => {
+                client.set_mic_config(&config).await.map_err(internal)?
+            }
+        }
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    async fn get_session(
+        &self,
+        _request: Request<pb::Empty>,
+    ) -> Result<Response<pb::SessionStatus>, Status> {
+        let (recording, id) = match self.connection()? {
+            DeviceConnection::Usb(client) => (
+                client.get_session_status().await.map_err(internal)?,
+                client.get_session_id().await.map_err(internal)?,
+            ),
+            DeviceConnection::Ble(client) => (
+                client.get_session_status().await.map_err(internal)?,
+                client.get_session_id().await.map_err(internal)?,
+            ),
+        };
+        Ok(Response::new(pb::SessionStatus { recording, id }))
+    }
+
+    async fn set_session_id(
+        &self,
+        request: Request<pb::SessionId>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        let id = request.into_inner().id;
+        match self.connection()? {
+            DeviceConnection::Usb(client) => rejected(
+                client.set_session_id(id).await.map_err(internal)?,
+                "the session id",
+            )?,
+            DeviceConnection::Ble(client) => {
+                client.set_session_id(&id).await.map_err(internal)?
+            }
+        }
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    async fn get_session_metadata(
+        &self,
+        _request: Request<pb::Empty>,
+    ) -> Result<Response<pb::Config>, Status> {
+        match self.connection()? {
+            DeviceConnection::Usb(client) => {
+                let metadata =
+                    client.get_session_metadata().await.map_err(internal)?;
+                Ok(Response::new(to_config(&metadata)?))
+            }
+            DeviceConnection::Ble(_) => Err(usb_only("Session metadata")),
+        }
+    }
+
+    async fn set_session_metadata(
+        &self,
+        request: Request<pb::Config>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        let metadata: icd::SessionMetadata = from_config(request.get_ref())?;
+        match self.connection()? {
+            DeviceConnection::Usb(client) => rejected(
+                client
+                    .set_session_metadata(metadata)
+                    .await
+                    .map_err(internal)?,
+                "the session metadata",
+            )?,
+            DeviceConnection::Ble(_) => {
+                return Err(usb_only("Session metadata"))
+            }
+        }
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    async fn start_session(
+        &self,
+        _request: Request<pb::Empty>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        match self.connection()? {
+            DeviceConnection::Usb(client) => rejected(
+                client.start_session().await.map_err(internal)?,
+                "starting a session",
+            )?,
+            DeviceConnection::Ble(client) => {
+                client.send_session_command(0).await.map_err(internal)?
+            }
+        }
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    async fn stop_session(
+        &self,
+        _request: Request<pb::Empty>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        match self.connection()? {
+            DeviceConnection::Usb(client) => rejected(
+                client.stop_session().await.map_err(internal)?,
+                "stopping the session",
+            )?,
+            DeviceConnection::Ble(client) => {
+                client.send_session_command(1).await.map_err(internal)?
+            }
+        }
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    async fn add_event_marker(
+        &self,
+        request: Request<pb::EventMarker>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        match self.connection()? {
+            DeviceConnection::Usb(client) => {
+                client
+                    .add_event_marker(&request.get_ref().label)
+                    .await
+                    .map_err(internal)?;
+            }
+            DeviceConnection::Ble(_) => return Err(usb_only("Event markers")),
+        }
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    async fn stream_ads(
+        &self,
+        _request: Request<pb::Empty>,
+    ) -> Result<Response<Self::StreamAdsStream>, Status> {
+        let connection = self.connection()?;
+        let rx = match &connection {
+            DeviceConnection::Usb(client) => {
+                forward_usb::<icd::AdsTopic, _>(client, |mut frame| {
+                    frame.restore_samples();
+                    proto::AdsDataFrame::from(&frame)
+                })
+                .await?
+            }
+            DeviceConnection::Ble(client) => {
+                forward_ble(
+                    client.clone(),
+                    DataStream::Ads,
+                    proto::AdsDataFrame::restore_samples,
+                )
+                .await?
+            }
+        };
+        let guard = self.acquire(&connection, DataStream::Ads, 0).await?;
+        Ok(Response::new(FrameStream {
+            frames: ReceiverStream::new(rx),
+            _guard: guard,
+        }))
+    }
+
+    async fn stream_mic(
+        &self,
+        _request: Request<pb::Empty>,
+    ) -> Result<Response<Self::StreamMicStream>, Status> {
+        let connection = self.connection()?;
+        let rx = match &connection {
+            DeviceConnection::Usb(client) => {
+                forward_usb::<icd::MicTopic, _>(client, |frame| {
+                    mic_proto::MicDataFrame::from(&frame)
+                })
+                .await?
+            }
+            DeviceConnection::Ble(client) => {
+                forward_ble::<mic_proto::MicDataFrame>(
+                    client.clone(),
+                    DataStream::Mic,
+                    |_| {},
+                )
+                .await?
+            }
+        };
+        let guard = self.acquire(&connection, DataStream::Mic, 0).await?;
+        Ok(Response::new(FrameStream {
+            frames: ReceiverStream::new(rx),
+            _guard: guard,
+        }))
+    }
+
+    /// The rate of the first open call applies to all of them.
+    async fn stream_quaternion(
+        &self,
+        request: Request<pb::QuaternionRequest>,
+    ) -> Result<Response<Self::StreamQuaternionStream>, Status> {
+        let rate = u8::try_from(request.get_ref().rate).map_err(|_| {
+            Status::invalid_argument("Orientation rate must be below 256 Hz")
+        })?;
+        let connection = self.connection()?;
+        let rx = match &connection {
+            DeviceConnection::Usb(client) => {
+                forward_usb::<icd::QuaternionTopic, _>(client, |q| {
+                    imu_proto::ImuQuaternion::from(&q)
+                })
+                .await?
+            }
+            DeviceConnection::Ble(client) => {
+                forward_ble::<imu_proto::ImuQuaternion>(
+                    client.clone(),
+                    DataStream::Quaternion,
+                    |_| {},
+                )
+                .await?
+            }
+        };
+        let guard =
+            self.acquire(&connection, DataStream::Quaternion, rate).await?;
+        Ok(Response::new(FrameStream {
+            frames: ReceiverStream::new(rx),
+            _guard: guard,
+        }))
+    }
+}
@@ -0,0 +1,284 @@
+//! Detecting data lost between the device and the host.
+//!
+//! Streams number their frames, so a jump in the sequence number tells how
+//! many were lost. Firmware from before the numbering leaves it at 0; for
+//! those streams a gap is inferred when a frame starts later than the
+//! samples of the previous one account for.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::icd::{imu_proto, mic_proto, proto};
+
+/// Timestamps jumping further than this are taken as the device clock being
+/// set, not as lost data.
+const MAX_GAP_US: u64 = 3_600_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    Ads,
+    Mic,
+    Orientation,
+}
+
+impl fmt::Display for StreamKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StreamKind::Ads => "ADS",
+            StreamKind::Mic => "mic",
+            StreamKind::Orientation => "orientation",
+        })
+    }
+}
+
+/// Data missing from a stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gap {
+    pub stream: StreamKind,
+    /// Device timestamp of the first frame after the gap, in microseconds
+    pub ts: u64,
+    /// Frames lost, if the stream numbers its frames
+    pub frames: Option<u32>,
+    /// How long the stream was without data, in microseconds; 0 if unknown
+    pub duration_us: u64,
+}
+
+impl Gap {
+    /// Device timestamp where the data stops, in microseconds
+    pub fn start_us(&self) -> u64 {
+        self.ts.saturating_sub(self.duration_us)
+    }
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.frames {
+            Some(1) => write!(f, "1 {} frame lost", self.stream)?,
+            Some(n) => write!(f, "{n} {} frames lost", self.stream)?,
+            None => write!(f, "{} data missing", self.stream)?,
+        }
+        if self.duration_us > 0 {
+            write!(f, " ({:.3} s)", self.duration_us as f64 / 1_000_000.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FrameInfo {
+    seq: u32,
+    ts: u64,
+    span_us: Option<u64>,
+}
+
+/// Follows one stream and reports the gaps in it.
+#[derive(Debug, Clone)]
+pub struct GapDetector {
+    stream: StreamKind,
+    /// Set once a frame carries a sequence number other than 0
+    sequenced: bool,
+    last: Option<FrameInfo>,
+    /// Time between the last two frames, for frames of unknown length
+    interval_us: Option<u64>,
+}
+
+impl GapDetector {
+    pub fn new(stream: StreamKind) -> Self {
+        Self { stream, sequenced: false, last: None, interval_us: None }
+    }
+
+    /// Checks the next frame of the stream. `span_us` is the time its
+    /// samples cover; if unknown, frames are assumed to be as far apart as
+    /// the previous two.
+    pub fn push(
+        &mut self,
+        seq: u32,
+        ts: u64,
+        span_us: Option<u64>,
+    ) -> Option<Gap> {
+        self.sequenced |= seq != 0;
+        let prev = self.last.replace(FrameInfo { seq, ts, span_us })?;
+
+        let span = prev.span_us.or(self.interval_us);
+        // Time between the end of the previous frame and the start of this
+        // one, unless the device clock was set in between
+        let elapsed =
+            ts.checked_sub(prev.ts).filter(|&elapsed| elapsed <= MAX_GAP_US);
+        if elapsed.is_some() {
+            self.interval_us = elapsed;
+        }
+        let missing = elapsed.zip(span).map(|(e, s)| e.saturating_sub(s));
+
+        if self.sequenced {
+            // A restarted stream counts from 0 again
+            if seq <= prev.seq {
+                return None;
+            }
+            let frames = seq - prev.seq - 1;
+            if frames == 0 {
+                return None;
+            }
+            let duration_us = missing
+                .or_else(|| span.map(|s| s * frames as u64))
+                .unwrap_or(0);
+            Some(Gap {
+                stream: self.stream,
+                ts,
+                frames: Some(frames),
+                duration_us,
+            })
+        } else {
+            // Frames are stamped with some jitter, but a lost frame leaves a
+            // hole about as long as itself
+            let (missing, span) = (missing?, span?);
+            (missing > span / 2).then_some(Gap {
+                stream: self.stream,
+                ts,
+                frames: None,
+                duration_us: missing,
+            })
+        }
+    }
+}
+
+/// Time covered by an ADS frame of `num_samples` samples, in microseconds.
+fn ads_span_us(num_samples: usize, decimation: u32, sample_rate: f64) -> u64 {
+    let samples = num_samples as f64 * decimation.max(1) as f64;
+    (samples * 1_000_000.0 / sample_rate) as u64
+}
+
+/// Time covered by a mic frame, in microseconds, without decoding it.
+fn mic_span_us(frame: &mic_proto::MicDataFrame) -> Option<u64> {
+    // Mirrors decode_mic_proto: 16-bit PCM, or two ADPCM samples per byte
+    let num_samples = if frame.codec() == mic_proto::MicCodec::Pcm
+        || !frame.pcm_data.is_empty()
+    {
+        frame.pcm_data.len() / 2
+    } else {
+        frame.adpcm_data.len() * 2
+    };
+    (frame.sample_rate > 0)
+        .then(|| num_samples as u64 * 1_000_000 / frame.sample_rate as u64)
+}
+
+/// Gaps found in the streams of a session.
+#[derive(Debug, Clone, Default)]
+pub struct IntegrityReport {
+    /// Device timestamp of the first frame checked, in microseconds
+    pub start_us: Option<u64>,
+    /// In the order they were found
+    pub gaps: Vec<Gap>,
+}
+
+impl IntegrityReport {
+    /// Whether no data was found missing.
+    pub fn is_complete(&self) -> bool {
+        self.gaps.is_empty()
+    }
+
+    /// Frames known to be lost; gaps in streams that do not number their
+    /// frames are not counted.
+    pub fn lost_frames(&self) -> u64 {
+        self.gaps.iter().filter_map(|gap| gap.frames).map(u64::from).sum()
+    }
+
+    /// Time without data, summed over all streams.
+    pub fn lost_duration(&self) -> Duration {
+        Duration::from_micros(
+            self.gaps.iter().map(|gap| gap.duration_us).sum(),
+        )
+    }
+
+    /// Seconds from the start of the session to where `gap` begins.
+    pub fn offset_secs(&self, gap: &Gap) -> f64 {
+        let start = self.start_us.unwrap_or(0);
+        gap.start_us().saturating_sub(start) as f64 / 1_000_000.0
+    }
+}
+
+/// A one-line summary; the alternate form (`{:#}`) lists every gap below.
+impl fmt::Display for IntegrityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_complete() {
+            return f.write_str("No gaps");
+        }
+        write!(
+            f,
+            "{} gap{}, {} frames and {:.3} s of data lost",
+            self.gaps.len(),
+            if self.gaps.len() == 1 { "" } else { "s" },
+            self.lost_frames(),
+            self.lost_duration().as_secs_f64()
+        )?;
+        if f.alternate() {
+            for gap in &self.gaps {
+                write!(f, "\n  at {:.3} s: {gap}", self.offset_secs(gap))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks the streams of a session as their frames arrive.
+#[derive(Debug, Clone)]
+pub struct IntegrityChecker {
+    ads: GapDetector,
+    mic: GapDetector,
+    orientation: GapDetector,
+    ads_sample_rate: f64,
+    report: IntegrityReport,
+}
+
+impl IntegrityChecker {
+    /// `ads_sample_rate` is the rate the ADS ran at, before any decimation
+    /// on the device.
+    pub fn new(ads_sample_rate: f64) -> Self {
+        Self {
+            ads: GapDetector::new(StreamKind::Ads),
+            mic: GapDetector::new(StreamKind::Mic),
+            orientation: GapDetector::new(StreamKind::Orientation),
+            ads_sample_rate,
+            report: IntegrityReport::default(),
+        }
+    }
+
+    pub fn check_ads(&mut self, frame: &proto::AdsDataFrame) -> Option<&Gap> {
+        let span = ads_span_us(
+            frame.samples.len(),
+            frame.decimation,
+            self.ads_sample_rate,
+        );
+        let gap = self.ads.push(frame.seq, frame.ts, Some(span));
+        self.record(frame.ts, gap)
+    }
+
+    pub fn check_mic(
+        &mut self,
+        frame: &mic_proto::MicDataFrame,
+    ) -> Option<&Gap> {
+        let gap = self.mic.push(frame.seq, frame.ts, mic_span_us(frame));
+        self.record(frame.ts, gap)
+    }
+
+    pub fn check_orientation(
+        &mut self,
+        q: &imu_proto::ImuQuaternion,
+    ) -> Option<&Gap> {
+        let gap = self.orientation.push(q.seq, q.ts, None);
+        self.record(q.ts, gap)
+    }
+
+    fn record(&mut self, ts: u64, gap: Option<Gap>) -> Option<&Gap> {
+        self.report.start_us.get_or_insert(ts);
+        self.report.gaps.push(gap?);
+        self.report.gaps.last()
+    }
+
+    pub fn report(&self) -> &IntegrityReport {
+        &self.report
+    }
+
+    pub fn into_report(self) -> IntegrityReport {
+        self.report
+    }
+}
@@ -5,6 +5,12 @@ pub use clients::DeviceConnection;
 pub use dc_mini_icd as icd;
 
 pub mod fileio;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod integrity;
+pub mod recorder;
+
+pub use recorder::Recorder;
 
 use audio_codec_algorithms::{decode_adpcm_ima, AdpcmImaState};
 
@@ -32,150 +38,102 @@ pub async fn read_line() -> String {
 pub use clients::*;
 pub use ui::*;
 
+/// Rerun timeline every stream is logged on, in seconds of device time.
+pub const TIMELINE: &str = "time";
+
+/// Logs a three-axis IMU reading as one scalar per axis, under
+/// `imu/{name}_x` and so on, and as an arrow under `imu/3d/{name}`.
+fn log_imu_vector(rec: &rerun::RecordingStream, name: &str, value: [f32; 3]) {
+    for (axis, v) in ["x", "y", "z"].iter().zip(value) {
+        rec.log(
+            format!("imu/{name}_{axis}"),
+            &rerun::Scalars::new([v as f64]),
+        )
+        .unwrap();
+    }
+    rec.log(format!("imu/3d/{name}"), &rerun::Arrows3D::from_vectors([value]))
+        .unwrap();
+}
+
+/// Logs a unit quaternion as the rotation of `imu/3d/orientation`, and its
+/// components as scalars.
+fn log_orientation(rec: &rerun::RecordingStream, [w, x, y, z]: [f32; 4]) {
+    rec.log(
+        "imu/3d/orientation",
+        &rerun::Transform3D::from_rotation(rerun::Quaternion::from_xyzw([
+            x, y, z, w,
+        ])),
+    )
+    .unwrap();
+    for (name, v) in [("w", w), ("x", x), ("y", y), ("z", z)] {
+        rec.log(format!("imu/quat_{name}"), &rerun::Scalars::new([v as f64]))
+            .unwrap();
+    }
+}
+
+fn log_ads_sample(
+    rec: &rerun::RecordingStream,
+    data: &[i32],
+    accel: [Option<f32>; 3],
+    gyro: [Option<f32>; 3],
+    quat: [Option<f32>; 4],
+) {
+    for (ch, &value) in data.iter().enumerate() {
+        rec.log(
+            format!("ads/channel_{}", ch),
+            &rerun::Scalars::new([value as f64]),
+        )
+        .unwrap();
+    }
+    // Present when the device interleaves IMU readings with the samples
+    if let [Some(x), Some(y), Some(z)] = accel {
+        log_imu_vector(rec, "accel", [x, y, z]);
+    }
+    if let [Some(x), Some(y), Some(z)] = gyro {
+        log_imu_vector(rec, "gyro", [x, y, z]);
+    }
+    if let [Some(w), Some(x), Some(y), Some(z)] = quat {
+        log_orientation(rec, [w, x, y, z]);
+    }
+}
+
 pub fn log_ads_frame(
     rec: rerun::RecordingStream,
 ) -> Box<dyn Fn(icd::SampleRate, AdsDataFrames) + Send> {
     let fp = move |sample_rate, data_frame| {
-        let sample_period_us = get_sample_period_us(sample_rate);
+        // Frames are stamped with the capture time of their first sample
+        let (ts, decimation) = match &data_frame {
+            AdsDataFrames::Icd(frame) => (frame.ts, frame.decimation as u32),
+            AdsDataFrames::Proto(frame) => (frame.ts, frame.decimation),
+        };
+        let sample_period_us =
+            get_sample_period_us(sample_rate) * decimation.max(1) as f64;
+        let timestamp =
+            |i: usize| (ts as f64 + i as f64 * sample_period_us) / 1_000_000.0;
+
         match data_frame {
             AdsDataFrames::Icd(frame) => {
-                let num_samples = frame.samples.len();
-                if num_samples == 0 {
-                    return;
-                }
-
-                // For each sample in the frame
-                for (i, sample) in frame.samples.iter().enumerate() {
-                    // Calculate timestamp for this sample
-                    let timestamp = (frame.ts as f64
-                        - ((num_samples - 1 - i) as f64 * sample_period_us))
-                        / 1_000_000.0;
-                    rec.set_duration_secs("time", timestamp);
-
-                    // Log each channel's data
-                    for (ch, &value) in sample.data.iter().enumerate() {
-                        rec.log(
-                            format!("ads/channel_{}", ch),
-                            &rerun::Scalars::new([value as f64]),
-                        )
-                        .unwrap();
-                    }
-
-                    // Log IMU accelerometer data if present
-                    if let Some(val) = sample.accel_x {
-                        rec.log(
-                            "imu/accel_x",
-                            &rerun::Scalars::new([val as f64]),
-                        )
-                        .unwrap();
-                    }
-                    if let Some(val) = sample.accel_y {
-                        rec.log(
-                            "imu/accel_y",
-                            &rerun::Scalars::new([val as f64]),
-                        )
-                        .unwrap();
-                    }
-                    if let Some(val) = sample.accel_z {
-                        rec.log(
-                            "imu/accel_z",
-                            &rerun::Scalars::new([val as f64]),
-                        )
-                        .unwrap();
-                    }
-
-                    // Log IMU gyroscope data if present
-                    if let Some(val) = sample.gyro_x {
-                        rec.log(
-                            "imu/gyro_x",
-                            &rerun::Scalars::new([val as f64]),
-                        )
-                        .unwrap();
-                    }
-                    if let Some(val) = sample.gyro_y {
-                        rec.log(
-                            "imu/gyro_y",
-                            &rerun::Scalars::new([val as f64]),
-                        )
-                        .unwrap();
-                    }
-                    if let Some(val) = sample.gyro_z {
-                        rec.log(
-                            "imu/gyro_z",
-                            &rerun::Scalars::new([val as f64]),
-                        )
-                        .unwrap();
-                    }
+                for (i, s) in frame.samples.iter().enumerate() {
+                    rec.set_duration_secs(TIMELINE, timestamp(i));
+                    log_ads_sample(
+                        &rec,
+                        &s.data,
+                        [s.accel_x, s.accel_y, s.accel_z],
+                        [s.gyro_x, s.gyro_y, s.gyro_z],
+                        [s.quat_w, s.quat_x, s.quat_y, s.quat_z],
+                    );
                 }
             }
             AdsDataFrames::Proto(frame) => {
-                let num_samples = frame.samples.len();
-                if num_samples == 0 {
-                    return;
-                }
-
-                // For each sample in the frame
-                for (i, sample) in frame.samples.iter().enumerate() {
-                    // Calculate timestamp for this sample
-                    let timestamp = (frame.ts as f64
-                        - ((num_samples - 1 - i) as f64 * sample_period_us))
-                        / 1_000_000.0;
-                    rec.set_duration_secs("time", timestamp);
-
-                    // Log each channel's data
-                    for (ch, &value) in sample.data.iter().enumerate() {
-                        rec.log(
-                            format!("ads/channel_{}", ch),
-                            &rerun::Scalars::new([value as f64]),
-                        )
-                        .unwrap();
-                    }
-
-                    // Log IMU accelerometer data if present
-                    if let Some(val) = sample.accel_x {
-                        rec.log(
-                            "imu/accel_x",
-                            &rerun::Scalars::new([val as f64]),
-                        )
-                        .unwrap();
-                    }
-                    if let Some(val) = sample.accel_y {
-                        rec.log(
-                            "imu/accel_y",
-                            &rerun::Scalars::new([val as f64]),
-                        )
-                        .unwrap();
-                    }
-                    if let Some(val) = sample.accel_z {
-                        rec.log(
-                            "imu/accel_z",
-                            &rerun::Scalars::new([val as f64]),
-                        )
-                        .unwrap();
-                    }
-
-                    // Log IMU gyroscope data if present
-                    if let Some(val) = sample.gyro_x {
-                        rec.log(
-                            "imu/gyro_x",
-                            &rerun::Scalars::new([val as f64]),
-                        )
-                        .unwrap();
-                    }
-                    if let Some(val) = sample.gyro_y {
-                        rec.log(
-                            "imu/gyro_y",
-                            &rerun::Scalars::new([val as f64]),
-                        )
-                        .unwrap();
-                    }
-                    if let Some(val) = sample.gyro_z {
-                        rec.log(
-                            "imu/gyro_z",
-                            &rerun::Scalars::new([val as f64]),
-                        )
-                        .unwrap();
-                    }
+                for (i, s) in frame.samples.iter().enumerate() {
+                    rec.set_duration_secs(TIMELINE, timestamp(i));
+                    log_ads_sample(
+                        &rec,
+                        &s.data,
+                        [s.accel_x, s.accel_y, s.accel_z],
+                        [s.gyro_x, s.gyro_y, s.gyro_z],
+                        [s.quat_w, s.quat_x, s.quat_y, s.quat_z],
+                    );
                 }
             }
         }
@@ -183,6 +141,47 @@ pub fn log_ads_frame(
     Box::new(fp)
 }
 
+/// Logs an IMU reading from a session recording: acceleration and angular
+/// rate as vectors, and the temperature.
+pub fn log_imu_record(
+    rec: &rerun::RecordingStream,
+    record: &icd::proto::ImuRecord,
+) {
+    rec.set_duration_secs(TIMELINE, record.ts as f64 / 1_000_000.0);
+    log_imu_vector(
+        rec,
+        "accel",
+        [record.accel_x, record.accel_y, record.accel_z],
+    );
+    log_imu_vector(rec, "gyro", [record.gyro_x, record.gyro_y, record.gyro_z]);
+    rec.log("imu/temp", &rerun::Scalars::new([record.temp as f64])).unwrap();
+}
+
+pub enum QuaternionFrames {
+    Proto(icd::imu_proto::ImuQuaternion),
+    Icd(icd::ImuQuaternion),
+}
+
+/// Logs the fused orientation stream, rotating a box standing in for the
+/// device in the 3D view.
+pub fn log_quaternion_frame(
+    rec: rerun::RecordingStream,
+) -> Box<dyn Fn(QuaternionFrames) + Send> {
+    rec.log_static(
+        "imu/3d/orientation/device",
+        &rerun::Boxes3D::from_half_sizes([[0.5, 0.3, 0.1]]),
+    )
+    .unwrap();
+    Box::new(move |frame: QuaternionFrames| {
+        let (ts, quat) = match frame {
+            QuaternionFrames::Icd(q) => (q.ts, [q.w, q.x, q.y, q.z]),
+            QuaternionFrames::Proto(q) => (q.ts, [q.w, q.x, q.y, q.z]),
+        };
+        rec.set_duration_secs(TIMELINE, ts as f64 / 1_000_000.0);
+        log_orientation(&rec, quat);
+    })
+}
+
 /// Calculate sample period in microseconds from sample rate
 pub fn get_sample_period_us(sample_rate: icd::SampleRate) -> f64 {
     let rate_hz = match sample_rate {
@@ -211,32 +210,67 @@ fn decode_adpcm_block(
     pcm
 }
 
+fn decode_pcm_block(pcm_data: &[u8]) -> Vec<i16> {
+    pcm_data
+        .chunks_exact(2)
+        .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+        .collect()
+}
+
+/// Decodes the audio of a recorded or BLE-streamed mic frame.
+pub(crate) fn decode_mic_proto(f: &icd::mic_proto::MicDataFrame) -> Vec<i16> {
+    // PCM recordings from older firmware leave the codec unset.
+    if f.codec() == icd::mic_proto::MicCodec::Pcm || !f.pcm_data.is_empty() {
+        decode_pcm_block(&f.pcm_data)
+    } else {
+        decode_adpcm_block(
+            &f.adpcm_data,
+            f.predictor as i16,
+            f.step_index as u8,
+        )
+    }
+}
+
 pub fn log_mic_frame(
     rec: rerun::RecordingStream,
 ) -> Box<dyn Fn(MicDataFrames) + Send> {
     Box::new(move |frame: MicDataFrames| {
-        let (ts, sample_rate, predictor, step_index, adpcm_data) = match &frame
-        {
+        let (ts, sample_rate, pcm) = match &frame {
             MicDataFrames::Icd(f) => {
-                (f.ts, f.sample_rate, f.predictor, f.step_index, &f.adpcm_data)
+                let pcm = match f.codec {
+                    icd::MicCodec::Adpcm => decode_adpcm_block(
+                        &f.adpcm_data,
+                        f.predictor as i16,
+                        f.step_index as u8,
+                    ),
+                    icd::MicCodec::Pcm => decode_pcm_block(&f.pcm_data),
+                };
+                (f.ts, f.sample_rate, pcm)
             }
             MicDataFrames::Proto(f) => {
-                (f.ts, f.sample_rate, f.predictor, f.step_index, &f.adpcm_data)
+                (f.ts, f.sample_rate, decode_mic_proto(f))
             }
         };
+        if pcm.is_empty() || sample_rate == 0 {
+            return;
+        }
 
-        let pcm =
-            decode_adpcm_block(adpcm_data, predictor as i16, step_index as u8);
-        let sample_period_us = 1_000_000.0 / sample_rate as f64;
-        let num_samples = pcm.len();
-
+        // Frames are stamped with the capture time of their first sample
+        let start = ts as f64 / 1_000_000.0;
+        let sample_period = 1.0 / sample_rate as f64;
         for (i, &sample) in pcm.iter().enumerate() {
-            let timestamp = (ts as f64
-                - ((num_samples - 1 - i) as f64 * sample_period_us))
-                / 1_000_000.0;
-            rec.set_duration_secs("time", timestamp);
+            rec.set_duration_secs(TIMELINE, start + i as f64 * sample_period);
             rec.log("mic/audio", &rerun::Scalars::new([sample as f64]))
                 .unwrap();
         }
+
+        // Loudness of the frame, readable at zoom levels where the
+        // waveform is not
+        let mean_square = pcm.iter().map(|&s| (s as f64).powi(2)).sum::<f64>()
+            / pcm.len() as f64;
+        let level_dbfs =
+            20.0 * (mean_square.sqrt() / i16::MAX as f64).max(1e-5).log10();
+        rec.set_duration_secs(TIMELINE, start);
+        rec.log("mic/level_dbfs", &rerun::Scalars::new([level_dbfs])).unwrap();
     })
 }
@@ -0,0 +1,327 @@
+//! Recording the device streams to a file on the host.
+//!
+//! A [`Recorder`] writes whatever the device streams while it runs into a
+//! `.dcs` session container, the format the device uses on its SD card, so
+//! recordings made either way are read and converted by the same tools.
+//! It does not start or stop the streams itself.
+//!
+//! Frames lost on the way are reported as they are noticed, and the
+//! recording keeps an [`IntegrityReport`] of them.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::{Stream, StreamExt};
+use postcard_rpc::Topic;
+use prost::Message;
+use serde::de::DeserializeOwned;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+use crate::fileio::dat::SAMPLE_RATE;
+use crate::fileio::dcs::SessionWriter;
+use crate::fileio::{Error, Result};
+use crate::icd::container::RecordKind;
+use crate::icd::{self, imu_proto, mic_proto, proto, SessionMetadata};
+use crate::integrity::{IntegrityChecker, IntegrityReport};
+use crate::{DeviceConnection, UsbClient};
+
+/// Buffered records are written out at least this often.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+enum Entry {
+    Metadata(u64, SessionMetadata),
+    Ads(proto::AdsDataFrame),
+    Mic(mic_proto::MicDataFrame),
+    Quaternion(imu_proto::ImuQuaternion),
+    /// Ends the recording; anything received after it is dropped.
+    Stop,
+}
+
+/// Records the ADS, mic and orientation streams of one device until
+/// stopped or dropped.
+pub struct Recorder {
+    path: PathBuf,
+    records: Arc<AtomicU64>,
+    integrity: Arc<Mutex<IntegrityChecker>>,
+    started: Instant,
+    tasks: Vec<JoinHandle<()>>,
+    tx: Option<mpsc::Sender<Entry>>,
+    writer: Option<thread::JoinHandle<Result<()>>>,
+}
+
+impl Recorder {
+    /// Creates `path` and starts recording the streams of `connection`.
+    /// Over USB the session metadata set on the device is stored first,
+    /// with the host time as start time.
+    pub fn start(
+        rt: &Handle,
+        connection: DeviceConnection,
+        path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = SessionWriter::create(&path)?;
+        let records = Arc::new(AtomicU64::new(0));
+        let integrity =
+            Arc::new(Mutex::new(IntegrityChecker::new(SAMPLE_RATE)));
+        let (tx, rx) = mpsc::channel();
+        let writer =
+            thread::Builder::new().name("dcs-recorder".into()).spawn({
+                let (records, integrity) =
+                    (records.clone(), integrity.clone());
+                move || write_records(file, rx, &records, &integrity)
+            })?;
+
+        let tasks = match connection {
+            DeviceConnection::Usb(client) => vec![
+                rt.spawn(record_metadata(client.clone(), tx.clone())),
+                rt.spawn(record_topic::<icd::AdsTopic>(
+                    client.clone(),
+                    tx.clone(),
+                    |mut frame| {
+                        frame.restore_samples();
+                        Entry::Ads(proto::AdsDataFrame::from(&frame))
+                    },
+                )),
+                rt.spawn(record_topic::<icd::MicTopic>(
+                    client.clone(),
+                    tx.clone(),
+                    |frame| Entry::Mic(mic_proto::MicDataFrame::from(&frame)),
+                )),
+                rt.spawn(record_topic::<icd::QuaternionTopic>(
+                    client,
+                    tx.clone(),
+                    |q| Entry::Quaternion(imu_proto::ImuQuaternion::from(&q)),
+                )),
+            ],
+            DeviceConnection::Ble(client) => vec![
+                rt.spawn({
+                    let (client, tx) = (client.clone(), tx.clone());
+                    async move {
+                        match client.notify_ads_stream().await {
+                            Ok(stream) => {
+                                record_notifications(stream, tx, |data| {
+                                    let mut frame =
+                                        proto::AdsDataFrame::decode(data)?;
+                                    frame.restore_samples();
+                                    Ok(Entry::Ads(frame))
+                                })
+                                .await
+                            }
+                            Err(e) => println!("Not recording ADS: {e}"),
+                        }
+                    }
+                }),
+                rt.spawn({
+                    let (client, tx) = (client.clone(), tx.clone());
+                    async move {
+                        match client.notify_mic_stream().await {
+                            Ok(stream) => {
+                                record_notifications(stream, tx, |data| {
+                                    mic_proto::MicDataFrame::decode(data)
+                                        .map(Entry::Mic)
+                                })
+                                .await
+                            }
+                            Err(e) => println!("Not recording mic: {e}"),
+                        }
+                    }
+                }),
+                rt.spawn({
+                    let tx = tx.clone();
+                    async move {
+                        match client.notify_quaternion_stream().await {
+                            Ok(stream) => {
+                                record_notifications(stream, tx, |data| {
+                                    imu_proto::ImuQuaternion::decode(data)
+                                        .map(Entry::Quaternion)
+                                })
+                                .await
+                            }
+                            Err(e) => {
+                                println!("Not recording orientation: {e}")
+                            }
+                        }
+                    }
+                }),
+            ],
+        };
+
+        Ok(Self {
+            path,
+            records,
+            integrity,
+            started: Instant::now(),
+            tasks,
+            tx: Some(tx),
+            writer: Some(writer),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records written so far.
+    pub fn records(&self) -> u64 {
+        self.records.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Gaps in the streams recorded so far.
+    pub fn integrity(&self) -> IntegrityReport {
+        self.integrity.lock().unwrap().report().clone()
+    }
+
+    /// Stops recording and waits for the file to be written out. Returns
+    /// the gaps in what was recorded, or the first write error, after
+    /// which nothing more was recorded.
+    pub fn stop(mut self) -> Result<IntegrityReport> {
+        self.finish()?;
+        Ok(self.integrity())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+        if let Some(tx) = self.tx.take() {
+            let _ = tx.send(Entry::Stop);
+        }
+        match self.writer.take() {
+            Some(writer) => writer.join().unwrap_or_else(|_| {
+                Err(Error::InvalidData("Recorder thread panicked".into()))
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            println!("Recording to {} failed: {e}", self.path.display());
+        }
+    }
+}
+
+fn write_records(
+    mut file: SessionWriter,
+    rx: mpsc::Receiver<Entry>,
+    records: &AtomicU64,
+    integrity: &Mutex<IntegrityChecker>,
+) -> Result<()> {
+    let mut last_flush = Instant::now();
+    loop {
+        match rx.recv_timeout(FLUSH_INTERVAL) {
+            Ok(entry) => {
+                check_entry(&mut integrity.lock().unwrap(), &entry);
+                match entry {
+                    Entry::Metadata(ts, metadata) => file.push_postcard(
+                        RecordKind::Metadata,
+                        ts,
+                        &metadata,
+                    )?,
+                    Entry::Ads(frame) => {
+                        file.push_proto(RecordKind::Ads, frame.ts, &frame)?
+                    }
+                    Entry::Mic(frame) => {
+                        file.push_proto(RecordKind::Mic, frame.ts, &frame)?
+                    }
+                    Entry::Quaternion(q) => {
+                        file.push_proto(RecordKind::Quaternion, q.ts, &q)?
+                    }
+                    Entry::Stop => break,
+                }
+                records.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        if last_flush.elapsed() >= FLUSH_INTERVAL {
+            file.flush()?;
+            last_flush = Instant::now();
+        }
+    }
+    file.flush()
+}
+
+fn check_entry(checker: &mut IntegrityChecker, entry: &Entry) {
+    let gap = match entry {
+        Entry::Ads(frame) => checker.check_ads(frame),
+        Entry::Mic(frame) => checker.check_mic(frame),
+        Entry::Quaternion(q) => checker.check_orientation(q),
+        Entry::Metadata(..) | Entry::Stop => None,
+    };
+    if let Some(gap) = gap {
+        println!("Recording gap: {gap}");
+    }
+}
+
+fn host_epoch_us() -> u64 {
+    chrono::Utc::now().timestamp_micros() as u64
+}
+
+async fn record_metadata(client: Arc<UsbClient>, tx: mpsc::Sender<Entry>) {
+    let now = host_epoch_us();
+    match client.get_session_metadata().await {
+        Ok(mut metadata) => {
+            metadata.start_epoch_us = Some(now);
+            let _ = tx.send(Entry::Metadata(now, metadata));
+        }
+        Err(e) => println!("Not recording session metadata: {e:?}"),
+    }
+}
+
+async fn record_topic<T>(
+    client: Arc<UsbClient>,
+    tx: mpsc::Sender<Entry>,
+    to_entry: fn(T::Message) -> Entry,
+) where
+    T: Topic,
+    T::Message: DeserializeOwned,
+{
+    let mut sub = match client.client.subscribe_multi::<T>(8).await {
+        Ok(sub) => sub,
+        Err(e) => {
+            println!("Not recording {}: {e:?}", T::PATH);
+            return;
+        }
+    };
+    while let Ok(message) = sub.recv().await {
+        if tx.send(to_entry(message)).is_err() {
+            break;
+        }
+    }
+}
+
+async fn record_notifications(
+    mut stream: impl Stream<Item = bluest::Result<Vec<u8>>> + Unpin,
+    tx: mpsc::Sender<Entry>,
+    decode: fn(&[u8]) -> std::result::Result<Entry, prost::DecodeError>,
+) {
+    while let Some(data) = stream.next().await {
+        match data {
+            Ok(data) => match decode(&data) {
+                Ok(entry) => {
+                    if tx.send(entry).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => println!("Dropping undecodable notification: {e}"),
+            },
+            Err(e) => {
+                println!("Recorded stream error: {e:?}");
+                if e.kind() == bluest::error::ErrorKind::NotConnected {
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -110,17 +110,27 @@ impl AcquisitionPanel {
             if let Some(conn) = connection {
                 match conn {
                     DeviceConnection::Ble(ble_client) => {
-                        let mut stream = ble_client.notify_ads_stream().await;
+                        // The connection may be gone until it is replaced.
+                        let Ok(mut stream) =
+                            ble_client.notify_ads_stream().await
+                        else {
+                            tokio::time::sleep(
+                                tokio::time::Duration::from_secs(1),
+                            )
+                            .await;
+                            continue;
+                        };
                         println!("Waiting for data stream updates");
 
                         while let Some(data) = stream.next().await {
                             match data {
                                 Ok(data) => {
-                                    if let Ok(frame) =
+                                    if let Ok(mut frame) =
                                         icd::proto::AdsDataFrame::decode(
                                             &data[..],
                                         )
                                     {
+                                        frame.restore_samples();
                                         let active_config =
                                             { config.borrow().clone() };
                                         if let Some(conf) = active_config {
@@ -149,7 +159,8 @@ impl AcquisitionPanel {
                             .await;
 
                         if let Ok(mut sub) = sub {
-                            while let Ok(frame) = sub.recv().await {
+                            while let Ok(mut frame) = sub.recv().await {
+                                frame.restore_samples();
                                 let active_config =
                                     { config.borrow().clone() };
                                 if let Some(conf) = active_config {
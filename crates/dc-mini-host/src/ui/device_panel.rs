@@ -3,17 +3,21 @@ use crate::ui::{
     ProfilePanel, SessionPanel,
 };
 use crate::{AdsDataFrames, DeviceConnection, MicDataFrames};
-use crate::{BleClient, UsbClient};
+use crate::{BleClient, ConnectionState, ReconnectingConnection, UsbClient};
 use dc_mini_icd::SampleRate;
 use egui::{Color32, RichText};
 use std::sync::{Arc, Mutex};
 use tokio::{
     runtime::Handle,
-    sync::mpsc,
+    sync::{mpsc, watch},
     task::JoinHandle,
     time::{sleep, Duration},
 };
 
+/// How often the device clock is synced while connected; repeated syncs
+/// let the device estimate its drift.
+const TIME_SYNC_INTERVAL: Duration = Duration::from_secs(300);
+
 #[derive(Debug, Clone)]
 enum DetectedDevice {
     Usb,
@@ -23,6 +27,10 @@ enum DetectedDevice {
 #[derive(Clone)]
 pub enum ConnectionEvent {
     Connected(DeviceConnection),
+    /// The connection was lost and is being reopened.
+    Reconnecting {
+        attempt: u32,
+    },
     Disconnected,
 }
 
@@ -37,7 +45,11 @@ pub struct DevicePanel {
     connection_event_sender: mpsc::UnboundedSender<ConnectionEvent>,
     rt: Handle,
     scan_task: Option<JoinHandle<()>>,
-    health_check_task: Option<JoinHandle<()>>,
+    time_sync_task: Option<JoinHandle<()>>,
+    /// Reopens the connection when it is lost; dropped on disconnect.
+    link: Option<ReconnectingConnection>,
+    link_state: Option<watch::Receiver<ConnectionState>>,
+    reconnect_attempt: Option<u32>,
     // Shared client for child panels
     client: Arc<Mutex<Option<DeviceConnection>>>,
     // Child panels
@@ -85,7 +97,10 @@ impl DevicePanel {
             connection_event_sender,
             rt,
             scan_task: None,
-            health_check_task: None,
+            time_sync_task: None,
+            link: None,
+            link_state: None,
+            reconnect_attempt: None,
             // Shared client
             client,
             // Child panels
@@ -111,7 +126,7 @@ impl DevicePanel {
         }
 
         // Detach current client if one exists
-        if self.connection.is_some() {
+        if self.link.is_some() {
             let connection_sender = self.connection_sender.clone();
             self.rt.spawn(async move {
                 let _ = connection_sender.send(None);
@@ -160,69 +175,101 @@ impl DevicePanel {
         }));
     }
 
-    fn start_health_check(&mut self) {
-        // Cancel any existing health check task
-        if let Some(task) = self.health_check_task.take() {
+    /// Keeps the device clock in sync while `connection` is up.
+    fn start_time_sync(&mut self, connection: DeviceConnection) {
+        self.stop_time_sync();
+        self.time_sync_task = Some(self.rt.spawn(async move {
+            loop {
+                if let Err(e) = connection.sync_time().await {
+                    println!("Clock sync failed: {e}");
+                }
+                sleep(TIME_SYNC_INTERVAL).await;
+            }
+        }));
+    }
+
+    fn stop_time_sync(&mut self) {
+        if let Some(task) = self.time_sync_task.take() {
             task.abort();
         }
+    }
 
-        let connection_sender = self.connection_sender.clone();
-        let client = self.client.clone();
-
-        // Start a new health check task
-        self.health_check_task = Some(self.rt.spawn(async move {
-            loop {
-                sleep(Duration::from_millis(500)).await;
+    fn refresh_panels(&mut self) {
+        self.ads_panel.refresh();
+        self.mic_panel.refresh();
+        self.battery_panel.refresh();
+        self.session_panel.refresh();
+        self.device_info_panel.refresh();
+        self.profile_panel.refresh();
+    }
 
-                let connection =
-                    client.lock().ok().and_then(|guard| guard.clone());
+    /// Hands a new or reopened connection to the child panels, whose
+    /// stream tasks subscribe again on it.
+    fn set_connected(&mut self, connection: DeviceConnection) {
+        self.connection = Some(connection.clone());
+        self.reconnect_attempt = None;
+        if let Ok(mut client) = self.client.lock() {
+            *client = Some(connection.clone());
+        }
+        self.start_time_sync(connection.clone());
+        let _ = self
+            .connection_event_sender
+            .send(ConnectionEvent::Connected(connection));
+        self.refresh_panels();
+    }
 
-                if let Some(connection) = connection {
-                    let is_alive = match connection {
-                        DeviceConnection::Ble(client) => {
-                            client.is_connected().await
-                        }
-                        DeviceConnection::Usb(client) => client.is_connected(),
-                    };
-                    if !is_alive {
-                        let _ = connection_sender.send(None);
-                        break;
+    /// Applies what the reconnect layer reported since the last frame.
+    fn poll_link(&mut self) {
+        let Some(state) = self.link_state.as_mut() else {
+            return;
+        };
+        if !state.has_changed().unwrap_or(false) {
+            return;
+        }
+        let state = state.borrow_and_update().clone();
+        match state {
+            ConnectionState::Connected(connection) => {
+                self.set_connected(connection)
+            }
+            ConnectionState::Reconnecting { attempt, .. } => {
+                self.reconnect_attempt = Some(attempt);
+                if self.connection.take().is_some() {
+                    if let Ok(mut client) = self.client.lock() {
+                        *client = None;
                     }
-                } else {
-                    break;
+                    self.stop_time_sync();
+                    self.refresh_panels();
                 }
+                let _ = self
+                    .connection_event_sender
+                    .send(ConnectionEvent::Reconnecting { attempt });
             }
-        }));
+        }
     }
 
     pub fn show(&mut self, ui: &mut egui::Ui) {
         // Handle connection events
         while let Ok(connection) = self.connection_receiver.try_recv() {
-            self.connection = connection.clone();
-            // Update the shared client that child panels use and get a handle to the previous
-            // connection.
-            let previous_connection = {
-                if let Ok(mut client) = self.client.lock() {
-                    let c = client.as_ref().cloned();
-                    *client = self.connection.clone();
-                    c
-                } else {
-                    None
-                }
-            };
             if let Some(connection) = connection {
-                self.start_health_check();
-                let _ = self
-                    .connection_event_sender
-                    .send(ConnectionEvent::Connected(connection));
-                // Refresh all panels on connection
-                self.ads_panel.refresh();
-                self.mic_panel.refresh();
-                self.battery_panel.refresh();
-                self.session_panel.refresh();
-                self.device_info_panel.refresh();
-                self.profile_panel.refresh();
+                let link = ReconnectingConnection::spawn(
+                    &self.rt,
+                    connection.clone(),
+                );
+                self.link_state = Some(link.subscribe());
+                self.link = Some(link);
+                self.set_connected(connection);
             } else {
+                // Stop reconnecting before the client goes away.
+                self.link = None;
+                self.link_state = None;
+                self.reconnect_attempt = None;
+                self.stop_time_sync();
+                self.connection = None;
+                let previous_connection = self
+                    .client
+                    .lock()
+                    .ok()
+                    .and_then(|mut client| client.take());
                 // Explicitly disconnect the client
                 println!("Refreshing panels and dropping connection!");
                 if let Some(c) = previous_connection {
@@ -235,13 +282,7 @@ impl DevicePanel {
                         }
                     }
                 }
-                // Refresh all panels on disconnection
-                self.ads_panel.refresh();
-                self.mic_panel.refresh();
-                self.battery_panel.refresh();
-                self.session_panel.refresh();
-                self.device_info_panel.refresh();
-                self.profile_panel.refresh();
+                self.refresh_panels();
 
                 let _ = self
                     .connection_event_sender
@@ -252,6 +293,7 @@ impl DevicePanel {
             // Reset connecting state
             self.is_connecting = false;
         }
+        self.poll_link();
 
         // Handle profile events
         while let Ok(event) = self.profile_event_receiver.try_recv() {
@@ -262,6 +304,7 @@ impl DevicePanel {
                     self.mic_panel.refresh();
                     self.session_panel.refresh();
                 }
+                ProfileEvent::Info(..) => {}
             }
         }
 
@@ -274,11 +317,23 @@ impl DevicePanel {
             ui.horizontal(|ui| {
                 ui.label("Status:");
                 match &self.connection {
-                    None => {
-                        ui.label(
-                            RichText::new("Disconnected").color(Color32::RED),
-                        );
-                    }
+                    None => match self.reconnect_attempt {
+                        Some(attempt) => {
+                            ui.spinner();
+                            ui.label(
+                                RichText::new(format!(
+                                    "Reconnecting (attempt {attempt})"
+                                ))
+                                .color(Color32::YELLOW),
+                            );
+                        }
+                        None => {
+                            ui.label(
+                                RichText::new("Disconnected")
+                                    .color(Color32::RED),
+                            );
+                        }
+                    },
                     Some(DeviceConnection::Usb(_)) => {
                         ui.label(
                             RichText::new("Connected (USB)")
@@ -393,8 +448,8 @@ impl DevicePanel {
                 });
             }
 
-            // Disconnect button
-            if self.connection.is_some() {
+            // Disconnect button, which also stops reconnecting
+            if self.link.is_some() {
                 if ui.button("Disconnect").clicked() {
                     let connection_sender = self.connection_sender.clone();
                     let rt = self.rt.clone();
@@ -434,8 +489,6 @@ impl Drop for DevicePanel {
         if let Some(task) = self.scan_task.take() {
             task.abort();
         }
-        if let Some(task) = self.health_check_task.take() {
-            task.abort();
-        }
+        self.stop_time_sync();
     }
 }
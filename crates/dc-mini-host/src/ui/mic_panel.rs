@@ -1,4 +1,4 @@
-use crate::icd::{self, MicConfig, MicSampleRate};
+use crate::icd::{self, MicCodec, MicConfig, MicSampleRate};
 use crate::{DeviceConnection, MicDataFrames};
 use egui::{Color32, RichText};
 use futures::StreamExt;
@@ -12,6 +12,7 @@ pub enum MicMessage {
     Refresh,
     GainDb(i8),
     SampleRate(MicSampleRate),
+    Codec(MicCodec),
     Command(u8), // 0=Start, 1=Stop
 }
 
@@ -66,7 +67,16 @@ impl MicPanel {
             if let Some(conn) = connection {
                 match conn {
                     DeviceConnection::Ble(ble_client) => {
-                        let mut stream = ble_client.notify_mic_stream().await;
+                        // The connection may be gone until it is replaced.
+                        let Ok(mut stream) =
+                            ble_client.notify_mic_stream().await
+                        else {
+                            tokio::time::sleep(
+                                tokio::time::Duration::from_secs(1),
+                            )
+                            .await;
+                            continue;
+                        };
                         println!("Waiting for mic data stream updates");
 
                         while let Some(data) = stream.next().await {
@@ -144,10 +154,8 @@ impl MicPanel {
                         MicMessage::GainDb(gain) => {
                             if let Ok(current) = client.get_mic_config().await
                             {
-                                let new_config = MicConfig {
-                                    gain_db: gain,
-                                    sample_rate: current.sample_rate,
-                                };
+                                let new_config =
+                                    MicConfig { gain_db: gain, ..current };
                                 if client
                                     .set_mic_config(&new_config)
                                     .await
@@ -160,10 +168,8 @@ impl MicPanel {
                         MicMessage::SampleRate(rate) => {
                             if let Ok(current) = client.get_mic_config().await
                             {
-                                let new_config = MicConfig {
-                                    gain_db: current.gain_db,
-                                    sample_rate: rate,
-                                };
+                                let new_config =
+                                    MicConfig { sample_rate: rate, ..current };
                                 if client
                                     .set_mic_config(&new_config)
                                     .await
@@ -173,6 +179,8 @@ impl MicPanel {
                                 }
                             }
                         }
+                        // BLE always streams ADPCM.
+                        MicMessage::Codec(_) => {}
                     },
                     DeviceConnection::Usb(client) => match update {
                         MicMessage::Refresh => {
@@ -192,10 +200,8 @@ impl MicPanel {
                         MicMessage::GainDb(gain) => {
                             if let Ok(current) = client.get_mic_config().await
                             {
-                                let new_config = MicConfig {
-                                    gain_db: gain,
-                                    sample_rate: current.sample_rate,
-                                };
+                                let new_config =
+                                    MicConfig { gain_db: gain, ..current };
                                 if let Ok(true) = client
                                     .set_mic_config(new_config.clone())
                                     .await
@@ -205,11 +211,24 @@ impl MicPanel {
                             }
                         }
                         MicMessage::SampleRate(rate) => {
+                            if let Ok(current) = client.get_mic_config().await
+                            {
+                                let new_config =
+                                    MicConfig { sample_rate: rate, ..current };
+                                if let Ok(true) = client
+                                    .set_mic_config(new_config.clone())
+                                    .await
+                                {
+                                    let _ = update_tx.send(new_config);
+                                }
+                            }
+                        }
+                        MicMessage::Codec(codec) => {
                             if let Ok(current) = client.get_mic_config().await
                             {
                                 let new_config = MicConfig {
-                                    gain_db: current.gain_db,
-                                    sample_rate: rate,
+                                    stream_codec: codec,
+                                    ..current
                                 };
                                 if let Ok(true) = client
                                     .set_mic_config(new_config.clone())
@@ -308,6 +327,35 @@ impl MicPanel {
                         });
                 });
 
+                // Stream codec dropdown
+                ui.horizontal(|ui| {
+                    ui.label("Codec:");
+                    egui::ComboBox::from_id_salt("mic_codec")
+                        .selected_text(match config.stream_codec {
+                            MicCodec::Adpcm => "ADPCM",
+                            MicCodec::Pcm => "PCM",
+                        })
+                        .show_ui(ui, |ui| {
+                            for (codec, label) in [
+                                (MicCodec::Adpcm, "ADPCM"),
+                                (MicCodec::Pcm, "PCM"),
+                            ] {
+                                if ui
+                                    .selectable_value(
+                                        &mut config.stream_codec,
+                                        codec,
+                                        label,
+                                    )
+                                    .clicked()
+                                {
+                                    self.send_message(MicMessage::Codec(
+                                        codec,
+                                    ));
+                                }
+                            }
+                        });
+                });
+
                 self.config = Some(config);
             } else {
                 ui.label(
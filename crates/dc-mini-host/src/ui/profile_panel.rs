@@ -1,5 +1,5 @@
 use crate::DeviceConnection;
-use dc_mini_icd::MAX_PROFILES;
+use dc_mini_icd::{ProfileInfo, MAX_PROFILES};
 use egui::{Color32, RichText};
 use std::sync::{Arc, Mutex};
 use tokio::{runtime::Handle, sync::mpsc};
@@ -8,15 +8,22 @@ use tokio::{runtime::Handle, sync::mpsc};
 pub enum ProfileCommand {
     GetProfile,
     SetProfile(u8),
+    SetInfo(u8, ProfileInfo),
 }
 
 #[derive(Debug, Clone)]
 pub enum ProfileEvent {
     Changed(u8),
+    /// Name and description of a profile; only sent within the panel.
+    Info(u8, ProfileInfo),
 }
 
 pub struct ProfilePanel {
     profile: Option<u8>,
+    /// Stored profile info by index; empty over BLE, which has no names.
+    infos: Vec<ProfileInfo>,
+    new_name: String,
+    new_description: String,
     client: Arc<Mutex<Option<DeviceConnection>>>,
     command_sender: mpsc::UnboundedSender<ProfileCommand>,
     event_receiver: mpsc::UnboundedReceiver<ProfileEvent>,
@@ -38,6 +45,9 @@ impl ProfilePanel {
 
         let mut panel = Self {
             profile: None,
+            infos: Vec::new(),
+            new_name: String::new(),
+            new_description: String::new(),
             client,
             command_sender,
             event_receiver,
@@ -80,6 +90,14 @@ impl ProfilePanel {
                             let _ = event_sender.send(event.clone());
                             let _ = ui_event_sender.send(event);
                         }
+                        for profile in 0..MAX_PROFILES {
+                            if let Ok(info) =
+                                client.get_profile_info(profile).await
+                            {
+                                let _ = event_sender
+                                    .send(ProfileEvent::Info(profile, info));
+                            }
+                        }
                     }
                     (
                         ProfileCommand::GetProfile,
@@ -119,6 +137,21 @@ impl ProfilePanel {
                             println!("Failed to set profile with Ble Client!");
                         }
                     }
+                    (
+                        ProfileCommand::SetInfo(profile, info),
+                        Some(DeviceConnection::Usb(client)),
+                    ) => {
+                        match client
+                            .set_profile_info(profile, info.clone())
+                            .await
+                        {
+                            Ok(true) => {
+                                let _ = event_sender
+                                    .send(ProfileEvent::Info(profile, info));
+                            }
+                            _ => println!("Failed to set profile info!"),
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -131,6 +164,17 @@ impl ProfilePanel {
             match event {
                 ProfileEvent::Changed(profile) => {
                     self.profile = Some(profile);
+                    self.load_info_fields(profile);
+                }
+                ProfileEvent::Info(profile, info) => {
+                    let index = profile as usize;
+                    if self.infos.len() <= index {
+                        self.infos.resize(index + 1, ProfileInfo::default());
+                    }
+                    self.infos[index] = info;
+                    if self.profile == Some(profile) {
+                        self.load_info_fields(profile);
+                    }
                 }
             }
         }
@@ -143,25 +187,54 @@ impl ProfilePanel {
                 ui.horizontal(|ui| {
                     ui.label("Current Profile:");
                     ui.label(
-                        RichText::new(format!("{}", current_profile))
-                            .monospace(),
+                        RichText::new(self.label(current_profile)).monospace(),
                     );
                 });
 
                 ui.horizontal(|ui| {
                     ui.label("Select Profile:");
                     for profile in 0..MAX_PROFILES {
-                        let text = format!("{}", profile);
-                        if ui
-                            .selectable_label(current_profile == profile, text)
-                            .clicked()
-                        {
+                        let mut response = ui.selectable_label(
+                            current_profile == profile,
+                            self.label(profile),
+                        );
+                        if let Some(info) = self.info(profile) {
+                            if !info.description.is_empty() {
+                                response = response
+                                    .on_hover_text(info.description.as_str());
+                            }
+                        }
+                        if response.clicked() {
                             let _ = self
                                 .command_sender
                                 .send(ProfileCommand::SetProfile(profile));
                         }
                     }
                 });
+
+                if !self.infos.is_empty() {
+                    ui.separator();
+                    egui::Grid::new("profile_info").num_columns(2).show(
+                        ui,
+                        |ui| {
+                            ui.label("Name:");
+                            ui.text_edit_singleline(&mut self.new_name);
+                            ui.end_row();
+                            ui.label("Description:");
+                            ui.text_edit_singleline(&mut self.new_description);
+                            ui.end_row();
+                        },
+                    );
+                    if ui.button("Save").clicked() {
+                        let info = ProfileInfo {
+                            name: truncated(&self.new_name),
+                            description: truncated(&self.new_description),
+                        };
+                        let _ = self.command_sender.send(
+                            ProfileCommand::SetInfo(current_profile, info),
+                        );
+                    }
+                }
             } else {
                 ui.label(
                     RichText::new("Profile information unavailable")
@@ -171,7 +244,28 @@ impl ProfilePanel {
         });
     }
 
+    fn info(&self, profile: u8) -> Option<&ProfileInfo> {
+        self.infos.get(profile as usize)
+    }
+
+    /// The profile index, followed by its name if it has one.
+    fn label(&self, profile: u8) -> String {
+        match self.info(profile) {
+            Some(info) if !info.name.is_empty() => {
+                format!("{}: {}", profile, info.name)
+            }
+            _ => format!("{}", profile),
+        }
+    }
+
+    fn load_info_fields(&mut self, profile: u8) {
+        let info = self.info(profile).cloned().unwrap_or_default();
+        self.new_name = info.name.to_string();
+        self.new_description = info.description.to_string();
+    }
+
     pub fn refresh(&mut self) {
+        self.infos.clear();
         // Send command to get latest profile
         let _ = self.command_sender.send(ProfileCommand::GetProfile);
     }
@@ -184,3 +278,14 @@ impl Drop for ProfilePanel {
         }
     }
 }
+
+/// Copies as much of `text` as fits, on a character boundary.
+fn truncated<const N: usize>(text: &str) -> heapless::String<N> {
+    let mut out = heapless::String::new();
+    for c in text.chars() {
+        if out.push(c).is_err() {
+            break;
+        }
+    }
+    out
+}
@@ -1,5 +1,6 @@
-use crate::DeviceConnection;
+use crate::{DeviceConnection, Recorder};
 use egui::{Color32, RichText};
+use rfd::FileDialog;
 use std::sync::{Arc, Mutex};
 use tokio::{runtime::Handle, sync::mpsc};
 
@@ -24,6 +25,9 @@ pub struct SessionPanel {
     event_receiver: mpsc::UnboundedReceiver<SessionEvent>,
     background_task: Option<tokio::task::JoinHandle<()>>,
     rt: Handle,
+    // Recording of the streams to a file on the host
+    recorder: Option<Recorder>,
+    recorder_status: Option<String>,
 }
 
 impl SessionPanel {
@@ -45,6 +49,8 @@ impl SessionPanel {
             background_task: None,
             rt,
             new_id: String::new(),
+            recorder: None,
+            recorder_status: None,
         };
 
         // Start background task that:
@@ -203,9 +209,87 @@ impl SessionPanel {
                         .color(Color32::GRAY),
                 );
             }
+
+            ui.separator();
+            self.show_host_recording(ui);
         });
     }
 
+    /// Records the streams into a `.dcs` file on this computer, for
+    /// sessions without an SD card.
+    fn show_host_recording(&mut self, ui: &mut egui::Ui) {
+        ui.label(RichText::new("Host Recording").strong());
+        ui.horizontal(|ui| {
+            if let Some(recorder) = &self.recorder {
+                if ui.button("Stop").clicked() {
+                    let recorder = self.recorder.take().unwrap();
+                    let path = recorder.path().display().to_string();
+                    self.recorder_status = Some(match recorder.stop() {
+                        Ok(report) if report.is_complete() => {
+                            format!("Saved {path}")
+                        }
+                        Ok(report) => format!("Saved {path}; {report}"),
+                        Err(e) => format!("Recording failed: {e}"),
+                    });
+                    return;
+                }
+                let elapsed = recorder.elapsed().as_secs();
+                ui.label(
+                    RichText::new(format!(
+                        "Recording {:02}:{:02}, {} records",
+                        elapsed / 60,
+                        elapsed % 60,
+                        recorder.records()
+                    ))
+                    .color(Color32::GREEN),
+                );
+                let report = recorder.integrity();
+                if !report.is_complete() {
+                    ui.label(
+                        RichText::new(report.to_string())
+                            .color(Color32::YELLOW),
+                    );
+                }
+                ui.ctx().request_repaint_after(
+                    std::time::Duration::from_millis(500),
+                );
+            } else {
+                let connection =
+                    self.client.lock().ok().and_then(|guard| guard.clone());
+                let clicked = ui
+                    .add_enabled(
+                        connection.is_some(),
+                        egui::Button::new("Record to File…"),
+                    )
+                    .clicked();
+                if let (true, Some(connection)) = (clicked, connection) {
+                    let name = chrono::Local::now()
+                        .format("recording-%Y%m%d-%H%M%S.dcs")
+                        .to_string();
+                    if let Some(path) = FileDialog::new()
+                        .add_filter("DC Mini session", &["dcs"])
+                        .set_file_name(name)
+                        .save_file()
+                    {
+                        match Recorder::start(&self.rt, connection, &path) {
+                            Ok(recorder) => {
+                                self.recorder = Some(recorder);
+                                self.recorder_status = None;
+                            }
+                            Err(e) => {
+                                self.recorder_status =
+                                    Some(format!("Cannot record: {e}"));
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        if let Some(status) = &self.recorder_status {
+            ui.label(RichText::new(status).color(Color32::GRAY));
+        }
+    }
+
     pub fn refresh(&mut self) {
         self.id = None;
         self.is_running = false; // Reset running state
@@ -12,7 +12,10 @@ fn main() {
 
     config.btree_map(&["."]);
     config
-        .compile_protos(&["protos/ads.proto", "protos/mic.proto"], &["protos"])
+        .compile_protos(
+            &["protos/ads.proto", "protos/mic.proto", "protos/imu.proto"],
+            &["protos"],
+        )
         .unwrap();
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
 
@@ -27,6 +30,7 @@ fn main() {
             "--pyi_out=protos/",
             "protos/ads.proto",
             "protos/mic.proto",
+            "protos/imu.proto",
         ])
         .status()
         .expect("Failed to run protoc for Python files");
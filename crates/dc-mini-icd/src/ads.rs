@@ -135,6 +135,31 @@ pub struct AdsConfig {
     pub channels: heapless::Vec<ChannelConfig, ADS_MAX_CHANNELS>,
 }
 
+/// One logical channel of a [`Montage`], derived from physical ADS
+/// channels. Physical channels are numbered across the devices in frame
+/// order, as in [`AdsConfig::channels`].
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MontageChannel {
+    /// A physical channel as read.
+    Physical(u8),
+    /// The first physical channel minus the second, e.g. a bipolar pair.
+    Bipolar(u8, u8),
+    /// A physical channel minus the average of all active channels.
+    AverageReference(u8),
+}
+
+/// Channel montage applied by the firmware before ADS samples are
+/// published and recorded, so every consumer sees the logical channels.
+/// Physical channels the montage does not use are dropped; an empty
+/// montage leaves the active channels as read.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Montage {
+    /// Logical channels, in output order.
+    pub channels: heapless::Vec<MontageChannel, ADS_MAX_CHANNELS>,
+}
+
 #[derive(Serialize, Deserialize, Schema, Debug, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct AdsSample {
@@ -148,15 +173,116 @@ pub struct AdsSample {
     pub gyro_x: Option<f32>,
     pub gyro_y: Option<f32>,
     pub gyro_z: Option<f32>,
+    /// Fused orientation, see [`crate::ImuConfig::ads_orientation`].
+    pub quat_w: Option<f32>,
+    pub quat_x: Option<f32>,
+    pub quat_y: Option<f32>,
+    pub quat_z: Option<f32>,
+}
+
+/// Encoding of the channel data in a streamed ADS frame.
+#[derive(
+    Serialize, Deserialize, Schema, Debug, PartialEq, Clone, Copy, Default,
+)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AdsCodec {
+    /// Every sample carries its raw readings.
+    #[default]
+    Raw,
+    /// Every sample carries the difference to the previous sample of the
+    /// same frame; the first sample is raw. Consecutive readings are close,
+    /// so the varint encoding of the differences is much shorter.
+    Delta,
 }
 
 #[derive(Serialize, Deserialize, Schema, Debug, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct AdsDataFrame {
+    /// Device timestamp, see [`crate::TimeStatus`].
     pub ts: u64,
+    /// Incremented for every frame sent on the stream; a jump means frames
+    /// were dropped.
+    pub seq: u32,
+    pub codec: AdsCodec,
+    /// Factor the samples were decimated by on the device, including any
+    /// increase while the link was congested.
+    pub decimation: u8,
     pub samples: Vec<AdsSample>,
 }
 
+impl AdsDataFrame {
+    /// Delta-encodes the channel data of a raw frame.
+    pub fn delta_encode(&mut self) {
+        if self.codec == AdsCodec::Raw {
+            delta_encode(self.samples.iter_mut().map(|s| &mut s.data));
+            self.codec = AdsCodec::Delta;
+        }
+    }
+
+    /// Turns the channel data back into raw readings.
+    pub fn restore_samples(&mut self) {
+        if self.codec == AdsCodec::Delta {
+            delta_decode(self.samples.iter_mut().map(|s| &mut s.data));
+            self.codec = AdsCodec::Raw;
+        }
+    }
+}
+
+/// Replaces each reading with its difference to the same channel of the
+/// previous sample.
+pub(crate) fn delta_encode<'a>(
+    samples: impl Iterator<Item = &'a mut Vec<i32>>,
+) {
+    let mut prev = [0i32; ADS_MAX_CHANNELS];
+    for data in samples {
+        for (x, p) in data.iter_mut().zip(prev.iter_mut()) {
+            let raw = *x;
+            *x = raw.wrapping_sub(*p);
+            *p = raw;
+        }
+    }
+}
+
+/// Inverse of [`delta_encode`].
+pub(crate) fn delta_decode<'a>(
+    samples: impl Iterator<Item = &'a mut Vec<i32>>,
+) {
+    let mut prev = [0i32; ADS_MAX_CHANNELS];
+    for data in samples {
+        for (x, p) in data.iter_mut().zip(prev.iter_mut()) {
+            *x = x.wrapping_add(*p);
+            *p = *x;
+        }
+    }
+}
+
+/// Electrode contact of one channel; `true` means the lead is off.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LeadOffChannel {
+    pub positive: bool,
+    pub negative: bool,
+}
+
+/// Lead-off state of every channel, from LOFF_STATP/LOFF_STATN.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LeadOffStatus {
+    /// Timestamp of the sample at which the state last changed.
+    pub ts: u64,
+    pub channels: heapless::Vec<LeadOffChannel, ADS_MAX_CHANNELS>,
+}
+
+/// Result of an electrode impedance check.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ImpedanceReport {
+    /// Impedance in ohms per channel, `None` for powered-down channels.
+    pub channels: heapless::Vec<Option<u32>, ADS_MAX_CHANNELS>,
+    /// Set if the check could not run; `channels` is then empty.
+    pub error: Option<heapless::String<32>>,
+}
+
 impl Default for AdsConfig {
     fn default() -> Self {
         Self {
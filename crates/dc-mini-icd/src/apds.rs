@@ -195,9 +195,61 @@ pub fn default_apds_settings() -> ApdsConfig {
     ApdsConfig::default()
 }
 
+/// Shadows over the light sensor recognized as user input.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Schema)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Gesture {
+    /// A hand swiped over the sensor.
+    Wave,
+    /// The sensor held covered for over a second.
+    Cover,
+}
+
+/// What a gesture does.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Schema)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GestureAction {
+    None,
+    /// Adds a "gesture" marker to the recording in progress.
+    EventMarker,
+    ToggleAdsStream,
+    /// Starts or stops a recording, like a double press of the button.
+    ToggleRecording,
+}
+
+/// Per-profile gesture bindings. The light sensor runs whenever any
+/// gesture is bound.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Schema)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GestureConfig {
+    pub wave: GestureAction,
+    pub cover: GestureAction,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self { wave: GestureAction::None, cover: GestureAction::None }
+    }
+}
+
+impl GestureConfig {
+    pub fn action(&self, gesture: Gesture) -> GestureAction {
+        match gesture {
+            Gesture::Wave => self.wave,
+            Gesture::Cover => self.cover,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.wave != GestureAction::None || self.cover != GestureAction::None
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Schema)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ApdsDataFrame {
+    /// Device timestamp, see [`crate::TimeStatus`].
+    pub ts: u64,
     pub red: u32,
     pub green: u32,
     pub blue: u32,
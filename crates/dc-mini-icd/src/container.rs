@@ -0,0 +1,136 @@
+//! On-disk session container.
+//!
+//! A session file starts with a [`FileHeader`] followed by a sequence of
+//! records, each a [`RecordHeader`] and `len` payload bytes:
+//!
+//! | Offset | Size | Field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 1    | [`RecordKind`]                          |
+//! | 1      | 4    | payload length, little-endian `u32`     |
+//! | 5      | 8    | device timestamp, little-endian `u64`   |
+//! | 13     | len  | payload                                 |
+//!
+//! Payload encodings per kind are listed on [`RecordKind`]. Readers skip
+//! records of unknown kinds, so new record types can be added without
+//! bumping [`CONTAINER_VERSION`].
+
+/// Magic at the start of every session file.
+pub const CONTAINER_MAGIC: [u8; 4] = *b"DCMS";
+/// Version of the layout described here; bumped on incompatible changes.
+pub const CONTAINER_VERSION: u16 = 1;
+/// File extension of session containers on the SD card.
+pub const CONTAINER_EXT: &str = "dcs";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FileHeader {
+    pub version: u16,
+}
+
+impl FileHeader {
+    pub const SIZE: usize = 8;
+
+    pub const fn current() -> Self {
+        Self { version: CONTAINER_VERSION }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        buf[..4].copy_from_slice(&CONTAINER_MAGIC);
+        buf[4..6].copy_from_slice(&self.version.to_le_bytes());
+        // Bytes 6..8 are reserved and written as zero.
+        buf
+    }
+
+    /// Returns `None` if `buf` does not start with [`CONTAINER_MAGIC`].
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Option<Self> {
+        if buf[..4] != CONTAINER_MAGIC {
+            return None;
+        }
+        Some(Self { version: u16::from_le_bytes([buf[4], buf[5]]) })
+    }
+}
+
+/// Type of a record and the encoding of its payload.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum RecordKind {
+    /// postcard [`SessionMetadata`](crate::SessionMetadata).
+    Metadata = 0,
+    /// protobuf [`proto::AdsDataFrame`](crate::proto::AdsDataFrame).
+    Ads = 1,
+    /// protobuf [`proto::ImuRecord`](crate::proto::ImuRecord).
+    Imu = 2,
+    /// protobuf [`mic_proto::MicDataFrame`](crate::mic_proto::MicDataFrame).
+    Mic = 3,
+    /// postcard [`ApdsDataFrame`](crate::ApdsDataFrame).
+    Apds = 4,
+    /// protobuf [`proto::EventMarker`](crate::proto::EventMarker).
+    Marker = 5,
+    /// postcard [`LeadOffStatus`](crate::LeadOffStatus), written when the
+    /// electrode contact changes.
+    LeadOff = 6,
+    /// protobuf [`imu_proto::ImuQuaternion`](crate::imu_proto::ImuQuaternion),
+    /// from the orientation stream of host recordings.
+    Quaternion = 7,
+}
+
+impl TryFrom<u8> for RecordKind {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(RecordKind::Metadata),
+            1 => Ok(RecordKind::Ads),
+            2 => Ok(RecordKind::Imu),
+            3 => Ok(RecordKind::Mic),
+            4 => Ok(RecordKind::Apds),
+            5 => Ok(RecordKind::Marker),
+            6 => Ok(RecordKind::LeadOff),
+            7 => Ok(RecordKind::Quaternion),
+            other => Err(other),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RecordHeader {
+    /// Raw [`RecordKind`], kept as a byte so unknown kinds can be skipped.
+    pub kind: u8,
+    pub len: u32,
+    /// Device timestamp, see [`crate::TimeStatus`].
+    pub ts: u64,
+}
+
+impl RecordHeader {
+    pub const SIZE: usize = 13;
+
+    pub const fn new(kind: RecordKind, len: u32, ts: u64) -> Self {
+        Self { kind: kind as u8, len, ts }
+    }
+
+    pub fn kind(&self) -> Result<RecordKind, u8> {
+        RecordKind::try_from(self.kind)
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        buf[0] = self.kind;
+        buf[1..5].copy_from_slice(&self.len.to_le_bytes());
+        buf[5..13].copy_from_slice(&self.ts.to_le_bytes());
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8; Self::SIZE]) -> Self {
+        Self {
+            kind: buf[0],
+            len: u32::from_le_bytes([buf[1], buf[2], buf[3], buf[4]]),
+            ts: u64::from_le_bytes([
+                buf[5], buf[6], buf[7], buf[8], buf[9], buf[10], buf[11],
+                buf[12],
+            ]),
+        }
+    }
+}
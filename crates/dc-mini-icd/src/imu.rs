@@ -129,6 +129,10 @@ pub struct ImuConfig {
     // Quaternion/orientation settings
     pub quaternion_enabled: bool,
     pub quaternion_rate: u8, // Update rate in Hz (typical values: 50, 100, 200)
+    /// Attach the fused orientation to ADS samples instead of the raw
+    /// accel/gyro reading. Runs the fusion even without
+    /// `quaternion_enabled`.
+    pub ads_orientation: bool,
 }
 
 impl Default for ImuConfig {
@@ -147,9 +151,9 @@ impl Default for ImuConfig {
             gyro_power_mode: true,
 
             // FIFO defaults - enabled, stream mode, 32 samples watermark
-            fifo_enabled: false,
+            fifo_enabled: true,
             fifo_mode: FifoMode::Stream,
-            fifo_watermark: 64,
+            fifo_watermark: 32,
             fifo_temp_en: false,
             fifo_hires_en: false,
 
@@ -163,6 +167,7 @@ impl Default for ImuConfig {
             // Quaternion disabled by default
             quaternion_enabled: false,
             quaternion_rate: 100, // 100Hz default when enabled
+            ads_orientation: false,
         }
     }
 }
@@ -170,3 +175,16 @@ impl Default for ImuConfig {
 pub fn default_imu_settings() -> ImuConfig {
     ImuConfig::default()
 }
+
+/// On-device fused orientation as a unit quaternion.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ImuQuaternion {
+    /// Device timestamp, see [`crate::TimeStatus`].
+    pub ts: u64,
+    pub seq: u32,
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
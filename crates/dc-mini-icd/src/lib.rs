@@ -49,12 +49,108 @@ macro_rules! define_config_enum {
 
 pub mod proto {
     include!(concat!(env!("OUT_DIR"), "/ads.rs"));
+
+    impl AdsDataFrame {
+        /// Delta-encodes the channel data of a raw frame, see
+        /// [`crate::AdsCodec::Delta`].
+        pub fn delta_encode(&mut self) {
+            if self.codec() == AdsCodec::Raw {
+                crate::ads::delta_encode(
+                    self.samples.iter_mut().map(|s| &mut s.data),
+                );
+                self.set_codec(AdsCodec::Delta);
+            }
+        }
+
+        /// Turns the channel data back into raw readings.
+        pub fn restore_samples(&mut self) {
+            if self.codec() == AdsCodec::Delta {
+                crate::ads::delta_decode(
+                    self.samples.iter_mut().map(|s| &mut s.data),
+                );
+                self.set_codec(AdsCodec::Raw);
+            }
+        }
+    }
+
+    impl From<&crate::AdsSample> for AdsSample {
+        fn from(s: &crate::AdsSample) -> Self {
+            Self {
+                lead_off_positive: s.lead_off_positive,
+                lead_off_negative: s.lead_off_negative,
+                gpio: s.gpio,
+                data: s.data.clone(),
+                accel_x: s.accel_x,
+                accel_y: s.accel_y,
+                accel_z: s.accel_z,
+                gyro_x: s.gyro_x,
+                gyro_y: s.gyro_y,
+                gyro_z: s.gyro_z,
+                quat_w: s.quat_w,
+                quat_x: s.quat_x,
+                quat_y: s.quat_y,
+                quat_z: s.quat_z,
+            }
+        }
+    }
+
+    impl From<&crate::AdsDataFrame> for AdsDataFrame {
+        fn from(f: &crate::AdsDataFrame) -> Self {
+            let mut frame = Self {
+                ts: f.ts,
+                packet_counter: f.seq as u64,
+                samples: f.samples.iter().map(AdsSample::from).collect(),
+                seq: f.seq,
+                decimation: f.decimation as u32,
+                ..Default::default()
+            };
+            frame.set_codec(match f.codec {
+                crate::AdsCodec::Raw => AdsCodec::Raw,
+                crate::AdsCodec::Delta => AdsCodec::Delta,
+            });
+            frame
+        }
+    }
 }
 
 pub mod mic_proto {
     include!(concat!(env!("OUT_DIR"), "/mic.rs"));
+
+    impl From<&crate::MicDataFrame> for MicDataFrame {
+        fn from(f: &crate::MicDataFrame) -> Self {
+            let mut frame = Self {
+                ts: f.ts,
+                packet_counter: f.packet_counter,
+                sample_rate: f.sample_rate,
+                predictor: f.predictor,
+                step_index: f.step_index,
+                adpcm_data: f.adpcm_data.clone(),
+                seq: f.seq,
+                pcm_data: f.pcm_data.clone(),
+                ..Default::default()
+            };
+            frame.set_codec(match f.codec {
+                crate::MicCodec::Adpcm => MicCodec::Adpcm,
+                crate::MicCodec::Pcm => MicCodec::Pcm,
+            });
+            frame
+        }
+    }
+}
+
+pub mod imu_proto {
+    include!(concat!(env!("OUT_DIR"), "/imu.rs"));
+
+    impl From<&crate::ImuQuaternion> for ImuQuaternion {
+        fn from(q: &crate::ImuQuaternion) -> Self {
+            Self { ts: q.ts, seq: q.seq, w: q.w, x: q.x, y: q.y, z: q.z }
+        }
+    }
 }
 
+pub mod container;
+pub mod rawlog;
+
 mod ads;
 pub use ads::*;
 
@@ -76,6 +172,111 @@ pub const MAX_ID_LEN: usize = 4;
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct BatteryLevel(pub u8);
 
+/// Charging phase reported by the PMIC.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChargingState {
+    NotCharging,
+    Trickle,
+    ConstantCurrent,
+    ConstantVoltage,
+    Complete,
+}
+
+/// Charger fault flags.
+#[derive(
+    Debug, Default, PartialEq, Serialize, Deserialize, Schema, Clone, Copy,
+)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChargerFaults {
+    /// No battery detected by the charger.
+    pub battery_missing: bool,
+    /// Charging paused because the PMIC die is too hot.
+    pub die_temp_high: bool,
+}
+
+/// Battery and charger state read from the nPM1300. Requests answer `None`
+/// and [`BatteryTopic`] stays silent while the PMIC cannot be read.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BatteryStatus {
+    pub voltage_mv: u16,
+    /// Battery current, positive while charging.
+    pub current_ma: i16,
+    /// Estimated state of charge in percent (0-100).
+    pub state_of_charge: u8,
+    pub charging: ChargingState,
+    pub faults: ChargerFaults,
+    /// PMIC die temperature in degrees Celsius, if it could be read.
+    pub die_temp_c: Option<i8>,
+}
+
+/// Answer to a battery status request, `None` while the PMIC cannot be
+/// read.
+pub type MaybeBatteryStatus = Option<BatteryStatus>;
+
+/// When the device shuts itself down on a low battery. Shutdown triggers
+/// when either threshold is reached while not charging.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LowBatteryConfig {
+    pub enabled: bool,
+    pub shutdown_mv: u16,
+    /// State of charge in percent (0-100).
+    pub shutdown_soc: u8,
+}
+
+impl Default for LowBatteryConfig {
+    fn default() -> Self {
+        Self { enabled: true, shutdown_mv: 3400, shutdown_soc: 2 }
+    }
+}
+
+/// What the device was doing while its power draw was profiled.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerState {
+    Idle,
+    Advertising,
+    /// A BLE central is connected, nothing streaming.
+    Connected,
+    /// ADS or mic data streaming, which on battery means over BLE.
+    Streaming,
+    /// A session recording to the SD card.
+    Recording,
+}
+
+pub const POWER_STATE_COUNT: usize = 5;
+
+/// Battery energy drawn in one [`PowerState`].
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerStateEnergy {
+    pub state: PowerState,
+    /// Time profiled in this state.
+    pub duration_ms: u64,
+    pub energy_uj: u64,
+}
+
+impl PowerStateEnergy {
+    /// Mean battery power in microwatts, `None` if never in this state.
+    pub fn average_power_uw(&self) -> Option<u64> {
+        (self.duration_ms > 0)
+            .then(|| self.energy_uj * 1000 / self.duration_ms)
+    }
+}
+
+/// Battery energy per system state, summed while profiling is running.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerProfile {
+    pub running: bool,
+    /// Time not profiled because USB was powering the device.
+    pub usb_powered_ms: u64,
+    /// States in the order of [`PowerState`].
+    pub states: heapless::Vec<PowerStateEnergy, POWER_STATE_COUNT>,
+}
+
 // Device Information types
 #[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -86,6 +287,21 @@ pub struct DeviceInfo {
     pub capabilities: Option<DeviceCapabilities>,
 }
 
+/// Maximum length of the device nickname, bounded by the space left in the
+/// BLE advertising packet.
+pub const MAX_NICKNAME_LEN: usize = 16;
+pub type Nickname = String<MAX_NICKNAME_LEN>;
+
+/// Identity of a unit, so several devices can be told apart.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceIdentity {
+    /// Factory serial number (nRF52840 DEVICEID as hex).
+    pub serial_number: String<16>,
+    /// User-assigned name; empty if unset.
+    pub nickname: Nickname,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct DeviceCapabilities {
@@ -95,6 +311,224 @@ pub struct DeviceCapabilities {
     pub ppg_present: bool,
 }
 
+// Protocol handshake types
+/// Version of the protocol described by this crate, bumped whenever the wire
+/// format changes in a way hosts must know about.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Firmware build options that change which interfaces are available.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FirmwareFeatures {
+    pub usb: bool,
+    pub ble: bool,
+    /// Board has a dedicated PMIC bus (everything after SR6).
+    pub pmic_bus: bool,
+}
+
+/// Reply of the protocol handshake.
+///
+/// Its layout must stay stable so that any host can decode it, even one
+/// built against a different ICD.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProtocolInfo {
+    pub protocol_version: u16,
+    /// [`schema_hash`] of the ICD the firmware was built with.
+    pub schema_hash: u64,
+    pub hardware_revision: heapless::String<32>,
+    pub features: FirmwareFeatures,
+    pub capabilities: DeviceCapabilities,
+    /// Largest message the device accepts or sends over USB, in bytes.
+    pub max_frame_size: u16,
+    pub max_ads_channels: u8,
+    pub fs_chunk_size: u16,
+}
+
+/// Hash over every endpoint and topic key of this ICD.
+///
+/// Keys are derived from paths and message schemas, so two builds agree on
+/// the hash exactly when all their endpoints and topics are compatible.
+pub fn schema_hash() -> u64 {
+    // FNV-1a
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut hash = OFFSET;
+    let mut feed = |bytes: &[u8]| {
+        for b in bytes {
+            hash ^= *b as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    };
+    for (_, req, resp) in ENDPOINT_LIST.endpoints {
+        feed(&req.to_bytes());
+        feed(&resp.to_bytes());
+    }
+    for (_, key) in TOPICS_IN_LIST.topics.iter().chain(TOPICS_OUT_LIST.topics)
+    {
+        feed(&key.to_bytes());
+    }
+    hash
+}
+
+// Device statistics types
+/// Cause of the last reset, decoded from the nRF52 `RESETREAS` register.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResetReason {
+    PowerOn,
+    Pin,
+    Watchdog,
+    SoftReset,
+    Lockup,
+    WakeFromOff,
+    Debug,
+    /// Any other combination of `RESETREAS` bits.
+    Other(u32),
+}
+
+/// Firmware tasks that report heartbeats.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MonitoredTask {
+    Orchestrator,
+    Ads,
+    Imu,
+    Apds,
+    Mic,
+    Session,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TaskHeartbeat {
+    pub task: MonitoredTask,
+    /// Time since the last heartbeat, `None` if the task never reported.
+    pub age_ms: Option<u32>,
+}
+
+pub const MAX_MONITORED_TASKS: usize = 8;
+
+/// Subscribers of the ADS sample stream whose dropped samples are counted.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AdsConsumer {
+    Usb,
+    Ble,
+    Session,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdsDropCount {
+    pub consumer: AdsConsumer,
+    /// Samples the consumer fell too far behind to receive, since boot.
+    pub dropped: u32,
+}
+
+pub const MAX_ADS_CONSUMERS: usize = 4;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceStats {
+    pub uptime_ms: u64,
+    pub heap_used: u32,
+    pub heap_size: u32,
+    /// Most heap in use at once since boot.
+    pub heap_peak: u32,
+    pub stack_size: u32,
+    /// Stack never touched since boot; how close the deepest call came to
+    /// overflowing into the statics.
+    pub stack_margin: u32,
+    /// Result of the last self-test, run at boot and on request; `None`
+    /// until the boot self-test has finished.
+    pub board_health: Option<BoardHealth>,
+    /// Deepest event queue backlog seen since boot.
+    pub event_queue_high_water: u8,
+    pub event_queue_capacity: u8,
+    pub reset_reason: ResetReason,
+    pub heartbeats: heapless::Vec<TaskHeartbeat, MAX_MONITORED_TASKS>,
+    pub ads_drops: heapless::Vec<AdsDropCount, MAX_ADS_CONSUMERS>,
+    /// `None` with a bootloader that keeps no metrics.
+    pub boot_metrics: Option<BootMetrics>,
+}
+
+/// What brought the firmware down.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CrashKind {
+    Panic,
+    HardFault,
+    /// A watched task stopped reporting heartbeats, so the watchdog was
+    /// left to reset the device.
+    TaskStalled,
+}
+
+pub const MAX_CRASH_MESSAGE_LEN: usize = 128;
+
+/// Last crash, kept in flash across the reset that followed it.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CrashReport {
+    pub kind: CrashKind,
+    /// Faulting instruction for a hard fault; inside the panic handler for
+    /// a panic, whose location is in `message`.
+    pub pc: u32,
+    pub lr: u32,
+    /// Active exception number: 0 is thread mode, where the main executor
+    /// runs; an interrupt executor shows its IRQ number plus 16.
+    pub exception: u16,
+    pub uptime_ms: u32,
+    /// Panic message and location, truncated.
+    pub message: String<MAX_CRASH_MESSAGE_LEN>,
+    /// The stalled task for [`CrashKind::TaskStalled`].
+    pub task: Option<MonitoredTask>,
+}
+
+/// Answer to a crash report request, `None` if no crash is stored.
+pub type MaybeCrashReport = Option<CrashReport>;
+
+/// Runtime error classes reported on `FaultTopic`.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FaultKind {
+    /// Writing a recording to the SD card failed; the session was stopped.
+    SdWriteFailed,
+    /// A sensor read failed on its bus; the sensor task was stopped.
+    SensorBusError,
+    /// A data queue was full and samples were dropped.
+    FifoOverflow,
+    /// The watchdog was fed late, close to resetting the device.
+    WatchdogNearMiss,
+    /// The battery reached the shutdown threshold; the device is powering
+    /// down.
+    BatteryLow,
+    /// The PMIC die is too hot; charging pauses until it cools down.
+    PmicOverheat,
+    /// The battery or board reached its critical temperature; the device
+    /// is powering down.
+    Overheat,
+    /// The SD card is nearly full; the session was stopped and its file
+    /// finalized.
+    SdFull,
+    /// The SD card was pulled while recording; the session was stopped.
+    SdRemoved,
+}
+
+/// A runtime fault, published as it happens.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Fault {
+    /// Device timestamp, see [`TimeStatus`].
+    pub ts: u64,
+    pub kind: FaultKind,
+    /// Task that observed the fault, `None` for system-level faults.
+    pub source: Option<MonitoredTask>,
+    /// Faults of this kind since boot, including this one. Gaps tell the
+    /// host that reports were dropped.
+    pub count: u32,
+}
+
 // Profile Service types
 #[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -117,10 +551,66 @@ impl TryFrom<u8> for ProfileCommand {
     }
 }
 
+pub const MAX_PROFILE_NAME_LEN: usize = 16;
+pub const MAX_PROFILE_DESCRIPTION_LEN: usize = 64;
+
+/// Name and description of a profile; both empty until the user sets them.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProfileInfo {
+    pub name: String<MAX_PROFILE_NAME_LEN>,
+    pub description: String<MAX_PROFILE_DESCRIPTION_LEN>,
+}
+
+/// Sets the info of any profile, not only the current one.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProfileInfoUpdate {
+    pub profile: u8,
+    pub info: ProfileInfo,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct SessionId(pub String<MAX_ID_LEN>);
 
+pub const MAX_SUBJECT_LEN: usize = 16;
+pub const MAX_OPERATOR_LEN: usize = 32;
+pub const MAX_MONTAGE_LEN: usize = 16;
+pub const MAX_NOTES_LEN: usize = 96;
+
+/// Descriptive information recorded alongside a session.
+///
+/// Written next to each recording on the SD card (same file stem, `.met`
+/// extension) and carried into EDF headers when converting on the host.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SessionMetadata {
+    pub subject_code: String<MAX_SUBJECT_LEN>,
+    pub operator: String<MAX_OPERATOR_LEN>,
+    pub notes: String<MAX_NOTES_LEN>,
+    pub montage: String<MAX_MONTAGE_LEN>,
+    /// Recording start as UNIX epoch microseconds. Filled in by the device
+    /// when the recording starts if its clock is set.
+    pub start_epoch_us: Option<u64>,
+}
+
+/// Per-profile hands-free recording: motion starts a session and the
+/// device lying still ends it.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MotionTriggerConfig {
+    pub enabled: bool,
+    /// Minutes without motion after which an auto-started session stops.
+    pub still_minutes: u8,
+}
+
+impl Default for MotionTriggerConfig {
+    fn default() -> Self {
+        Self { enabled: false, still_minutes: 5 }
+    }
+}
+
 // DFU types
 /// Begin a DFU transfer with the total firmware size.
 #[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
@@ -164,6 +654,869 @@ pub struct DfuProgress {
     pub total_size: u32,
 }
 
+/// Signed image the bootloader installs from the SD card's root directory
+/// when the button is held at reset.
+pub const SD_UPDATE_FILE: &str = "DCMINI.BIN";
+
+/// Starts a USB serial DFU in the bootloader's recovery mode.
+///
+/// The bootloader enumerates as a CDC ACM port with the product string
+/// [`BOOT_DFU_PRODUCT`]. The host sends this byte and the size of the
+/// signed image as a little-endian `u32`, then the image. The bootloader
+/// erases the DFU partition before it reads the image, which takes
+/// several seconds. Every
+/// [`BOOT_DFU_SECTOR`] bytes, and once more after the image is marked
+/// updated, the bootloader answers with one status byte, [`BOOT_DFU_OK`]
+/// or an error that ends the transfer.
+pub const BOOT_DFU_BEGIN: u8 = b'U';
+/// Product string of the bootloader in recovery mode.
+pub const BOOT_DFU_PRODUCT: &str = "DC Mini Recovery";
+/// Image bytes acknowledged at a time.
+pub const BOOT_DFU_SECTOR: usize = 4096;
+pub const BOOT_DFU_OK: u8 = 0;
+/// Something other than [`BOOT_DFU_BEGIN`] started the transfer.
+pub const BOOT_DFU_BAD_REQUEST: u8 = 1;
+/// The image does not fit in the DFU partition.
+pub const BOOT_DFU_TOO_LARGE: u8 = 2;
+/// Writing the DFU partition or the bootloader state failed.
+pub const BOOT_DFU_FLASH_ERROR: u8 = 3;
+
+/// Marks the signature trailer of a signed firmware image.
+pub const DFU_SIGNATURE_MAGIC: [u8; 4] = *b"DCSG";
+/// Length of an encoded [`DfuSignature`].
+pub const DFU_SIGNATURE_LEN: usize = 72;
+
+/// Trailer of a signed firmware image.
+///
+/// A signed image is the firmware padded with `0xFF` to a multiple of four
+/// bytes, followed by the magic, the unpadded firmware length (little
+/// endian) and an Ed25519 signature over the SHA-512 digest of the
+/// firmware. The rest of the DFU partition must be erased. The device only accepts an update whose signature checks out
+/// against the public key in the bootloader.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DfuSignature {
+    pub firmware_len: u32,
+    pub signature: [u8; 64],
+}
+
+impl DfuSignature {
+    /// Offset of the trailer in a signed image of `firmware_len` bytes.
+    pub const fn offset(firmware_len: u32) -> u32 {
+        (firmware_len + 3) & !3
+    }
+
+    pub fn to_bytes(&self) -> [u8; DFU_SIGNATURE_LEN] {
+        let mut out = [0u8; DFU_SIGNATURE_LEN];
+        out[..4].copy_from_slice(&DFU_SIGNATURE_MAGIC);
+        out[4..8].copy_from_slice(&self.firmware_len.to_le_bytes());
+        out[8..].copy_from_slice(&self.signature);
+        out
+    }
+
+    /// Decodes a trailer, `None` if `bytes` does not start with the magic.
+    pub fn from_bytes(bytes: &[u8; DFU_SIGNATURE_LEN]) -> Option<Self> {
+        if bytes[..4] != DFU_SIGNATURE_MAGIC {
+            return None;
+        }
+        let mut signature = [0u8; 64];
+        signature.copy_from_slice(&bytes[8..]);
+        Some(Self {
+            firmware_len: u32::from_le_bytes([
+                bytes[4], bytes[5], bytes[6], bytes[7],
+            ]),
+            signature,
+        })
+    }
+
+    /// Reads the trailer at the end of a signed image.
+    pub fn from_image(image: &[u8]) -> Option<Self> {
+        let start = image.len().checked_sub(DFU_SIGNATURE_LEN)?;
+        Self::from_bytes(image[start..].try_into().ok()?)
+    }
+}
+
+// Time types
+/// Device timestamps (`ts` on data frames, markers and faults) at or above
+/// this are Unix time in microseconds; below it they are microseconds since
+/// boot, stamped before the clock was set.
+pub const SYNCED_TS_MIN: u64 = 1_000_000_000_000_000;
+
+/// Device clock as seen by the firmware.
+///
+/// Device timestamps are `epoch_us` once the clock has been set, through a
+/// clock exchange ([`TimeSample`]), [`TimeSetEndpoint`] or the Current Time
+/// Service of a BLE central, and `uptime_us` before. See [`SYNCED_TS_MIN`].
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimeStatus {
+    /// Microseconds since boot.
+    pub uptime_us: u64,
+    /// Unix time in microseconds, `None` until the clock has been set.
+    pub epoch_us: Option<u64>,
+    /// Estimated rate error of the device clock against the host's, in
+    /// parts per billion; positive when the device runs slow. Needs two
+    /// clock exchanges some time apart, 0 until then.
+    pub drift_ppb: i32,
+}
+
+/// The device's half of a clock exchange, see [`TimeSample`].
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimeExchange {
+    /// Host Unix time in microseconds when the request was sent, echoed.
+    pub host_tx_us: u64,
+    /// Device uptime in microseconds when the request arrived.
+    pub device_rx_us: u64,
+    /// Device uptime in microseconds when the response was sent.
+    pub device_tx_us: u64,
+}
+
+impl TimeExchange {
+    /// Size of [`Self::to_bytes`], as sent over BLE.
+    pub const SIZE: usize = 24;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut out = [0; Self::SIZE];
+        out[0..8].copy_from_slice(&self.host_tx_us.to_le_bytes());
+        out[8..16].copy_from_slice(&self.device_rx_us.to_le_bytes());
+        out[16..24].copy_from_slice(&self.device_tx_us.to_le_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let word = |i: usize| {
+            Some(u64::from_le_bytes(
+                bytes.get(i * 8..i * 8 + 8)?.try_into().ok()?,
+            ))
+        };
+        Some(Self {
+            host_tx_us: word(0)?,
+            device_rx_us: word(1)?,
+            device_tx_us: word(2)?,
+        })
+    }
+}
+
+/// A completed two-way clock exchange, as in NTP: the host stamps a request
+/// when sending it and the response when receiving it, the device stamps
+/// the request on arrival and the response on departure.
+///
+/// The host runs a few exchanges, keeps the one with the least
+/// [`delay_us`](Self::delay_us) and sends it back with
+/// [`TimeSampleEndpoint`], from which the device sets its clock and
+/// estimates its drift.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimeSample {
+    pub exchange: TimeExchange,
+    /// Host Unix time in microseconds when the response arrived.
+    pub host_rx_us: u64,
+}
+
+impl TimeSample {
+    /// Size of [`Self::to_bytes`], as sent over BLE.
+    pub const SIZE: usize = TimeExchange::SIZE + 8;
+
+    /// Time spent on the link, without the device's turnaround.
+    pub fn delay_us(&self) -> u64 {
+        let TimeExchange { host_tx_us, device_rx_us, device_tx_us } =
+            self.exchange;
+        self.host_rx_us
+            .saturating_sub(host_tx_us)
+            .saturating_sub(device_tx_us.saturating_sub(device_rx_us))
+    }
+
+    /// Device uptime in microseconds that [`Self::boot_epoch_us`] was
+    /// measured at: midway through the device's turnaround.
+    pub fn device_us(&self) -> u64 {
+        let TimeExchange { device_rx_us, device_tx_us, .. } = self.exchange;
+        device_rx_us + device_tx_us.saturating_sub(device_rx_us) / 2
+    }
+
+    /// Host Unix time in microseconds at device boot, assuming the link is
+    /// as slow one way as the other.
+    pub fn boot_epoch_us(&self) -> i64 {
+        let TimeExchange { host_tx_us, device_rx_us, device_tx_us } =
+            self.exchange;
+        let outbound = host_tx_us as i64 - device_rx_us as i64;
+        let inbound = self.host_rx_us as i64 - device_tx_us as i64;
+        (outbound + inbound) / 2
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut out = [0; Self::SIZE];
+        out[..TimeExchange::SIZE].copy_from_slice(&self.exchange.to_bytes());
+        out[TimeExchange::SIZE..]
+            .copy_from_slice(&self.host_rx_us.to_le_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let exchange = TimeExchange::from_bytes(bytes)?;
+        let host_rx_us = bytes.get(TimeExchange::SIZE..Self::SIZE)?;
+        Some(Self {
+            exchange,
+            host_rx_us: u64::from_le_bytes(host_rx_us.try_into().ok()?),
+        })
+    }
+}
+
+/// Host clock sample used to set the device clock in one step.
+///
+/// The device assumes the request spent half of `round_trip_us` in flight,
+/// so `round_trip_us` should be measured with a preceding time request.
+/// Less precise than a clock exchange, see [`TimeSample`].
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimeSync {
+    /// Host Unix time in microseconds when the request was sent.
+    pub host_epoch_us: u64,
+    pub round_trip_us: u32,
+}
+
+// Self-test types
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SelfTestOutcome {
+    Pass,
+    Fail,
+    /// Not run, e.g. because the subsystem is streaming or absent.
+    Skipped,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SelfTestResult {
+    pub outcome: SelfTestOutcome,
+    pub message: String<64>,
+}
+
+/// Per-subsystem results of a device self-test, published on
+/// [`SelfTestTopic`] when a test started on [`SelfTestEndpoint`] finishes.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SelfTestReport {
+    /// Device ID check and internal test signal.
+    pub ads: SelfTestResult,
+    /// WHO_AM_I check and the sensor's built-in accel and gyro self-test.
+    pub imu: SelfTestResult,
+    /// Write/read-back of a scratch file.
+    pub sd: SelfTestResult,
+    /// PMIC communication and charger faults.
+    pub pmic: SelfTestResult,
+    /// Short capture checked for a stuck output.
+    pub mic: SelfTestResult,
+}
+
+/// Subsystems that failed the last self-test, one bit each. Skipped
+/// checks do not count as failures.
+#[derive(
+    Debug, Default, PartialEq, Serialize, Deserialize, Schema, Clone, Copy,
+)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BoardHealth(pub u8);
+
+impl BoardHealth {
+    pub const ADS: u8 = 1 << 0;
+    pub const IMU: u8 = 1 << 1;
+    pub const SD: u8 = 1 << 2;
+    pub const PMIC: u8 = 1 << 3;
+    pub const MIC: u8 = 1 << 4;
+
+    pub fn from_report(report: &SelfTestReport) -> Self {
+        let checks = [
+            (Self::ADS, &report.ads),
+            (Self::IMU, &report.imu),
+            (Self::SD, &report.sd),
+            (Self::PMIC, &report.pmic),
+            (Self::MIC, &report.mic),
+        ];
+        Self(checks.iter().fold(
+            0,
+            |mask, (bit, result)| match result.outcome {
+                SelfTestOutcome::Fail => mask | bit,
+                _ => mask,
+            },
+        ))
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn failed(&self, subsystem: u8) -> bool {
+        self.0 & subsystem != 0
+    }
+}
+
+// Haptic types
+/// Highest effect id of the DRV2605L ROM library.
+pub const MAX_HAPTIC_EFFECT: u8 = 123;
+
+/// Haptic cue played on the DRV2605L.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HapticPattern {
+    /// ROM library effect id (1..=123), or 0 for a plain buzz.
+    pub effect: u8,
+    /// Buzz amplitude; ROM effects have a fixed amplitude and ignore it.
+    pub strength: u8,
+    /// Length of one repetition. A buzz lasts the whole period, a ROM
+    /// effect is re-triggered once it has elapsed.
+    pub duration_ms: u16,
+    /// Number of additional repetitions.
+    pub repeat: u8,
+}
+
+pub const MAX_HAPTIC_STEPS: usize = 8;
+
+/// One step of a [`HapticSequence`].
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HapticStep {
+    /// ROM library effect id (1..=123), or 0 for a plain buzz.
+    pub effect: u8,
+    /// Buzz amplitude; ignored by ROM effects.
+    pub strength: u8,
+    pub duration_ms: u16,
+    /// Silence before the next step.
+    pub pause_ms: u16,
+}
+
+impl HapticStep {
+    pub const fn buzz(strength: u8, duration_ms: u16, pause_ms: u16) -> Self {
+        Self { effect: 0, strength, duration_ms, pause_ms }
+    }
+}
+
+/// Steps played in order, `repeat` more times after the first.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HapticSequence {
+    pub steps: heapless::Vec<HapticStep, MAX_HAPTIC_STEPS>,
+    pub repeat: u8,
+}
+
+impl HapticSequence {
+    fn from_steps(steps: &[HapticStep], repeat: u8) -> Self {
+        Self {
+            steps: heapless::Vec::from_slice(steps).unwrap_or_default(),
+            repeat,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.steps.iter().all(|step| step.effect <= MAX_HAPTIC_EFFECT)
+    }
+}
+
+impl From<&HapticPattern> for HapticSequence {
+    fn from(pattern: &HapticPattern) -> Self {
+        let step = HapticStep {
+            effect: pattern.effect,
+            strength: pattern.strength,
+            duration_ms: pattern.duration_ms,
+            pause_ms: 0,
+        };
+        Self::from_steps(&[step], pattern.repeat)
+    }
+}
+
+/// Named haptic patterns, defined per profile in [`HapticConfig`].
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HapticCue {
+    DoubleBuzz,
+    Ramp,
+    Heartbeat,
+}
+
+/// Per-profile haptic patterns and the cue, if any, played on each
+/// device event.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HapticConfig {
+    pub double_buzz: HapticSequence,
+    pub ramp: HapticSequence,
+    pub heartbeat: HapticSequence,
+    pub on_session_start: Option<HapticCue>,
+    pub on_session_stop: Option<HapticCue>,
+    pub on_low_battery: Option<HapticCue>,
+    /// An electrode came off while recording.
+    pub on_electrode_off: Option<HapticCue>,
+}
+
+impl Default for HapticConfig {
+    fn default() -> Self {
+        Self {
+            double_buzz: HapticSequence::from_steps(
+                &[
+                    HapticStep::buzz(127, 80, 100),
+                    HapticStep::buzz(127, 80, 0),
+                ],
+                0,
+            ),
+            ramp: HapticSequence::from_steps(
+                &[
+                    HapticStep::buzz(32, 100, 0),
+                    HapticStep::buzz(64, 100, 0),
+                    HapticStep::buzz(96, 100, 0),
+                    HapticStep::buzz(127, 100, 0),
+                ],
+                0,
+            ),
+            heartbeat: HapticSequence::from_steps(
+                &[
+                    HapticStep::buzz(110, 60, 120),
+                    HapticStep::buzz(70, 60, 600),
+                ],
+                2,
+            ),
+            on_session_start: None,
+            on_session_stop: None,
+            on_low_battery: Some(HapticCue::Ramp),
+            on_electrode_off: Some(HapticCue::DoubleBuzz),
+        }
+    }
+}
+
+impl HapticConfig {
+    pub fn sequence(&self, cue: HapticCue) -> &HapticSequence {
+        match cue {
+            HapticCue::DoubleBuzz => &self.double_buzz,
+            HapticCue::Ramp => &self.ramp,
+            HapticCue::Heartbeat => &self.heartbeat,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        [&self.double_buzz, &self.ramp, &self.heartbeat]
+            .iter()
+            .all(|sequence| sequence.is_valid())
+    }
+}
+
+// LED types
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LedEffect {
+    Off,
+    Solid,
+    /// Blink with `duty_cycle` percent of each `interval_ms` on.
+    Flash {
+        interval_ms: u16,
+        duty_cycle: u8,
+    },
+    /// Fade in and out once every `period_ms`.
+    Breathe {
+        period_ms: u16,
+    },
+}
+
+/// Host override of the status neopixel.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LedOverride {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub effect: LedEffect,
+    pub brightness: u8,
+}
+
+/// LED override request; `None` hands the neopixel back to the firmware.
+pub type MaybeLedOverride = Option<LedOverride>;
+
+/// Colour and effect used for one status indication.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LedPattern {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub effect: LedEffect,
+}
+
+/// Per-profile mapping of system, charger and battery states to the
+/// status neopixel. Charger and battery indications take precedence over
+/// the system state but not over alerts or a host override.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NeopixelConfig {
+    pub brightness: u8,
+    /// Nothing else to show.
+    pub idle: LedPattern,
+    /// Advertising over BLE with no central connected.
+    pub advertising: LedPattern,
+    /// A BLE central is connected.
+    pub connected: LedPattern,
+    /// ADS or mic data is streaming.
+    pub streaming: LedPattern,
+    pub recording: LedPattern,
+    /// The last self-test found a failed subsystem.
+    pub error: LedPattern,
+    pub charging: LedPattern,
+    pub charged: LedPattern,
+    pub charger_fault: LedPattern,
+    pub low_battery: LedPattern,
+    /// State of charge (percent) at or below which `low_battery` is shown
+    /// while not charging.
+    pub low_battery_soc: u8,
+}
+
+impl Default for NeopixelConfig {
+    fn default() -> Self {
+        Self {
+            brightness: 10,
+            idle: LedPattern {
+                r: 240,
+                g: 248,
+                b: 255,
+                effect: LedEffect::Flash { interval_ms: 3000, duty_cycle: 5 },
+            },
+            advertising: LedPattern {
+                r: 0,
+                g: 0,
+                b: 255,
+                effect: LedEffect::Breathe { period_ms: 2000 },
+            },
+            connected: LedPattern {
+                r: 0,
+                g: 0,
+                b: 255,
+                effect: LedEffect::Flash { interval_ms: 3000, duty_cycle: 5 },
+            },
+            streaming: LedPattern {
+                r: 0,
+                g: 200,
+                b: 255,
+                effect: LedEffect::Flash { interval_ms: 1000, duty_cycle: 10 },
+            },
+            recording: LedPattern {
+                r: 199,
+                g: 21,
+                b: 133,
+                effect: LedEffect::Flash { interval_ms: 2000, duty_cycle: 25 },
+            },
+            error: LedPattern {
+                r: 255,
+                g: 0,
+                b: 0,
+                effect: LedEffect::Flash { interval_ms: 500, duty_cycle: 50 },
+            },
+            charging: LedPattern {
+                r: 255,
+                g: 120,
+                b: 0,
+                effect: LedEffect::Breathe { period_ms: 3000 },
+            },
+            charged: LedPattern {
+                r: 0,
+                g: 255,
+                b: 0,
+                effect: LedEffect::Solid,
+            },
+            charger_fault: LedPattern {
+                r: 255,
+                g: 0,
+                b: 0,
+                effect: LedEffect::Flash { interval_ms: 500, duty_cycle: 50 },
+            },
+            low_battery: LedPattern {
+                r: 255,
+                g: 0,
+                b: 0,
+                effect: LedEffect::Breathe { period_ms: 1000 },
+            },
+            low_battery_soc: 10,
+        }
+    }
+}
+
+// Event marker types
+pub const MAX_MARKER_LABEL_LEN: usize = 32;
+
+/// Stimulus/event label sent by the host.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EventMarker {
+    pub label: String<MAX_MARKER_LABEL_LEN>,
+    /// Host Unix time in microseconds when the event occurred.
+    pub host_epoch_us: u64,
+}
+
+/// An event marker stamped against the device clock.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MarkerRecord {
+    /// Device timestamp, see [`TimeStatus`].
+    pub ts: u64,
+    pub host_epoch_us: u64,
+    pub label: String<MAX_MARKER_LABEL_LEN>,
+    /// Whether the marker was written to an active session file.
+    pub recorded: bool,
+}
+
+// Log types
+/// Severity of a firmware log record, ordered from most to least verbose.
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    Schema,
+    Clone,
+    Copy,
+)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    /// Filter only: disables log streaming.
+    Off,
+}
+
+pub const MAX_LOG_LEN: usize = 96;
+
+/// A firmware log line.
+///
+/// `message` is the format string of the log call; arguments are only
+/// available in the deferred-format RTT log.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LogRecord {
+    pub ts: u64,
+    pub level: LogLevel,
+    pub message: String<MAX_LOG_LEN>,
+}
+
+/// Most records returned by one `LogDumpEndpoint` request.
+pub const LOG_DUMP_MAX: usize = 8;
+
+/// Page of the device's log history, oldest first.
+///
+/// Records are numbered in the order they were logged. A request names
+/// the first index it wants; records that were already overwritten are
+/// skipped, so `first` can be larger than requested.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LogDump {
+    /// Index of the first record in `records`.
+    pub first: u32,
+    pub records: heapless::Vec<LogRecord, LOG_DUMP_MAX>,
+    /// Index the next record logged will get.
+    pub end: u32,
+}
+
+// System event log types
+/// Why the device powered itself down.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerOffReason {
+    /// Nothing happened for the auto-sleep timeout.
+    Idle,
+    /// The battery reached the shutdown threshold.
+    LowBattery,
+    /// The battery or board reached its critical temperature.
+    Overheat,
+}
+
+/// Why the bootloader refused a staged update.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DfuRejectReason {
+    /// The bootloader carries no signing key.
+    NoKey,
+    /// The image has no signature trailer.
+    Unsigned,
+    /// The signature does not match the image.
+    BadSignature,
+    /// Reading the staged image failed.
+    Flash,
+    /// Something other than erased flash follows the signature trailer.
+    TrailingData,
+}
+
+/// What the bootloader did with the last update it found staged.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SwapResult {
+    /// The update was verified and swapped in.
+    Updated,
+    /// The update was refused and the running firmware kept.
+    Rejected(DfuRejectReason),
+    /// The update never confirmed itself and was swapped back out.
+    RolledBack,
+}
+
+/// Counters the bootloader keeps in internal flash, across updates and
+/// power loss.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BootMetrics {
+    pub boot_count: u32,
+    pub watchdog_resets: u32,
+    pub rollbacks: u32,
+    /// `None` until the bootloader has found an update staged.
+    pub last_swap: Option<SwapResult>,
+}
+
+/// Something that happened to the unit, kept in the device's persistent
+/// event log.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SystemEventKind {
+    /// The firmware started.
+    PowerOn(ResetReason),
+    PowerOff(PowerOffReason),
+    SessionStarted,
+    SessionStopped,
+    Fault(FaultKind),
+    DfuStarted,
+    DfuCompleted,
+    DfuFailed,
+    DfuAborted,
+    ChargerAttached,
+    ChargerDetached,
+    /// The bootloader refused the staged update and kept the running
+    /// firmware.
+    DfuRejected(DfuRejectReason),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SystemEvent {
+    /// Events are numbered in the order they were logged, across resets.
+    pub index: u32,
+    /// Boot the event happened in, counted by the log.
+    pub boot: u32,
+    /// Device timestamp, see [`TimeStatus`]; uptime restarts every boot.
+    pub ts: u64,
+    pub kind: SystemEventKind,
+}
+
+/// Most events returned by one `EventLogDumpEndpoint` request.
+pub const EVENT_LOG_DUMP_MAX: usize = 16;
+
+/// Page of the persistent event log, oldest first.
+///
+/// Works like [`LogDump`]: a request names the first index it wants, and
+/// events the log has already overwritten are skipped.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EventLogDump {
+    /// Index of the first event in `events`.
+    pub first: u32,
+    pub events: heapless::Vec<SystemEvent, EVENT_LOG_DUMP_MAX>,
+    /// Index the next event logged will get.
+    pub end: u32,
+}
+
+// Stream decimation types
+/// Live data stream that can be decimated.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StreamKind {
+    Ads,
+    /// IMU readings attached to the ADS samples.
+    Imu,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecimationMode {
+    /// Keep every `factor`-th sample.
+    Decimate,
+    /// Send the mean of each `factor` samples. ADS only.
+    Average,
+}
+
+/// Rate reduction of a live stream, independent of the acquisition rate.
+///
+/// Only data sent to a host is affected; SD recordings keep every sample.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StreamConfig {
+    pub stream: StreamKind,
+    /// 1 sends every sample.
+    pub factor: u8,
+    pub mode: DecimationMode,
+}
+
+// File transfer types
+/// Maximum length of an SD card file name (8.3 format).
+pub const MAX_FS_NAME_LEN: usize = 12;
+/// Maximum payload of a single file chunk.
+pub const FS_CHUNK_SIZE: usize = 512;
+
+/// Open a file on the SD card for reading.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FsReadBegin {
+    pub name: String<MAX_FS_NAME_LEN>,
+}
+
+/// Read `length` bytes (at most [`FS_CHUNK_SIZE`]) starting at `offset`.
+///
+/// Chunks may be requested in any order, so an interrupted transfer can be
+/// resumed from the last received offset.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FsReadChunk {
+    pub offset: u32,
+    pub length: u16,
+}
+
+/// Data read from the open file.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FsChunkData {
+    pub offset: u32,
+    pub data: heapless::Vec<u8, FS_CHUNK_SIZE>,
+    /// True if the chunk reaches the end of the file.
+    pub eof: bool,
+}
+
+/// Answer to a chunk read, `None` if the read failed.
+pub type MaybeFsChunk = Option<FsChunkData>;
+
+/// Result of a file transfer operation.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FsResult {
+    pub success: bool,
+    /// Size of the open file in bytes, 0 if none is open.
+    pub size: u32,
+    pub message: String<64>,
+}
+
+/// Delete a file from the SD card.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FsDelete {
+    pub name: String<MAX_FS_NAME_LEN>,
+}
+
+/// SD card capacity and the free space left in its filesystem.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StorageStatus {
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+    /// True if free space is below the device's warning threshold.
+    pub low: bool,
+}
+
+/// Answer to a storage status request, `None` if the card is busy or
+/// could not be read.
+pub type MaybeStorageStatus = Option<StorageStatus>;
+
 endpoints! {
     list = ENDPOINT_LIST;
     omit_std = true;
@@ -175,14 +1528,47 @@ endpoints! {
     | AdsResetConfigEndpoint    | ()                | bool                  | "ads/reset"       |
     | AdsGetConfigEndpoint      | ()                | AdsConfig             | "ads/get_config"  |
     | AdsSetConfigEndpoint      | AdsConfig         | bool                  | "ads/set_config"  |
-    // Battery endpoint (read-only)
+    | LeadOffStartEndpoint      | ()                | ()                    | "ads/loff/start"  |
+    | LeadOffStopEndpoint       | ()                | ()                    | "ads/loff/stop"   |
+    | AdsImpedanceEndpoint      | ()                | ImpedanceReport       | "ads/impedance"   |
+    | AdsGetMontageEndpoint     | ()                | Montage               | "ads/get_montage" |
+    | AdsSetMontageEndpoint     | Montage           | bool                  | "ads/set_montage" |
+    // APDS endpoints
+    | ApdsStartEndpoint         | ()                | ApdsConfig            | "apds/start"      |
+    | ApdsStopEndpoint          | ()                | ()                    | "apds/stop"       |
+    | ApdsResetConfigEndpoint   | ()                | bool                  | "apds/reset"      |
+    | ApdsGetConfigEndpoint     | ()                | ApdsConfig            | "apds/get_config" |
+    | ApdsSetConfigEndpoint     | ApdsConfig        | bool                  | "apds/set_config" |
+    | ApdsGetGesturesEndpoint   | ()                | GestureConfig         | "apds/get_gestures" |
+    | ApdsSetGesturesEndpoint   | GestureConfig     | bool                  | "apds/set_gestures" |
+    // Battery endpoints
     | BatteryGetLevelEndpoint   | ()                | BatteryLevel          | "battery/level"   |
-    // Device Info endpoint (read-only)
+    | BatteryGetStatusEndpoint  | ()                | MaybeBatteryStatus    | "battery/status"  |
+    | BatteryGetShutdownEndpoint | ()               | LowBatteryConfig      | "battery/get_shutdown" |
+    | BatterySetShutdownEndpoint | LowBatteryConfig | bool                  | "battery/set_shutdown" |
+    // Power profiling; starting clears the totals
+    | PowerProfileStartEndpoint | ()                | bool                  | "power/profile/start" |
+    | PowerProfileStopEndpoint  | ()                | ()                    | "power/profile/stop" |
+    | PowerProfileGetEndpoint   | ()                | PowerProfile          | "power/profile"   |
+    // Device Info endpoints (read-only)
     | DeviceInfoGetEndpoint     | ()                | DeviceInfo            | "device/info"     |
+    | DeviceStatsEndpoint       | ()                | DeviceStats           | "device/stats"    |
+    | CrashReportEndpoint       | ()                | MaybeCrashReport      | "device/crash"    |
+    | CrashClearEndpoint        | ()                | bool                  | "device/crash/clear" |
+    | SelfTestEndpoint          | ()                | ()                    | "device/selftest" |
+    | ProtocolInfoEndpoint      | ()                | ProtocolInfo          | "device/protocol" |
+    | DeviceIdentityEndpoint    | ()                | DeviceIdentity        | "device/identity" |
+    | DeviceSetNicknameEndpoint | Nickname          | bool                  | "device/set_name" |
+    | BleClearBondsEndpoint     | ()                | bool                  | "ble/clear_bonds" |
     // Profile endpoints
     | ProfileGetEndpoint        | ()                | u8                    | "profile/get"     |
     | ProfileSetEndpoint        | u8                | bool                  | "profile/set"     |
     | ProfileCommandEndpoint    | ProfileCommand    | bool                  | "profile/command" |
+    | ProfileGetInfoEndpoint    | u8                | ProfileInfo           | "profile/get_info" |
+    | ProfileSetInfoEndpoint    | ProfileInfoUpdate | bool                  | "profile/set_info" |
+    // IMU endpoints; a quaternion rate of 0 keeps the configured rate
+    | QuaternionStartEndpoint   | u8                | bool                  | "imu/quat/start"  |
+    | QuaternionStopEndpoint    | ()                | ()                    | "imu/quat/stop"   |
     // Mic endpoints
     | MicStartEndpoint          | ()                | MicConfig             | "mic/start"       |
     | MicStopEndpoint           | ()                | ()                    | "mic/stop"        |
@@ -192,14 +1578,54 @@ endpoints! {
     | SessionGetStatusEndpoint  | ()                | bool                  | "session/status"  |
     | SessionGetIdEndpoint      | ()                | SessionId             | "session/id"      |
     | SessionSetIdEndpoint      | SessionId         | bool                  | "session/set_id"  |
+    | SessionGetMetadataEndpoint | ()               | SessionMetadata       | "session/get_meta" |
+    | SessionSetMetadataEndpoint | SessionMetadata  | bool                  | "session/set_meta" |
     | SessionStartEndpoint      | ()                | bool                  | "session/start"   |
     | SessionStopEndpoint       | ()                | bool                  | "session/stop"    |
+    | SessionGetMotionEndpoint  | ()                | MotionTriggerConfig   | "session/get_motion" |
+    | SessionSetMotionEndpoint  | MotionTriggerConfig | bool                | "session/set_motion" |
     // DFU endpoints
     | DfuBeginEndpoint          | DfuBegin          | DfuResult             | "dfu/begin"       |
     | DfuWriteEndpoint          | DfuWriteChunk     | DfuResult             | "dfu/write"       |
     | DfuFinishEndpoint         | ()                | DfuResult             | "dfu/finish"      |
     | DfuAbortEndpoint          | ()                | DfuResult             | "dfu/abort"       |
     | DfuStatusEndpoint         | ()                | DfuProgress           | "dfu/status"      |
+    // Time endpoints
+    | TimeGetEndpoint           | ()                | TimeStatus            | "time/get"        |
+    | TimeSetEndpoint           | TimeSync          | TimeStatus            | "time/set"        |
+    | TimeExchangeEndpoint      | u64               | TimeExchange          | "time/exchange"   |
+    | TimeSampleEndpoint        | TimeSample        | TimeStatus            | "time/sample"     |
+    // Haptic endpoints
+    | HapticPlayEndpoint        | HapticPattern     | bool                  | "haptic/play"     |
+    | HapticStopEndpoint        | ()                | ()                    | "haptic/stop"     |
+    | HapticPlayCueEndpoint     | HapticCue         | bool                  | "haptic/cue"      |
+    | HapticGetConfigEndpoint   | ()                | HapticConfig          | "haptic/get_config" |
+    | HapticSetConfigEndpoint   | HapticConfig      | bool                  | "haptic/set_config" |
+    // LED endpoints; `None` hands the LED back to the system status
+    | LedSetEndpoint            | MaybeLedOverride  | bool                  | "led/set"         |
+    | LedGetConfigEndpoint      | ()                | NeopixelConfig        | "led/get_config"  |
+    | LedSetConfigEndpoint      | NeopixelConfig    | bool                  | "led/set_config"  |
+    // Event marker endpoints
+    | EventMarkerEndpoint       | EventMarker       | MarkerRecord          | "marker/add"      |
+    // Log endpoints
+    | LogStartEndpoint          | LogLevel          | ()                    | "log/start"       |
+    | LogStopEndpoint           | ()                | ()                    | "log/stop"        |
+    | LogGetLevelEndpoint       | ()                | LogLevel              | "log/get_level"   |
+    | LogSetLevelEndpoint       | LogLevel          | bool                  | "log/set_level"   |
+    | LogDumpEndpoint           | u32               | LogDump               | "log/dump"        |
+    | EventLogDumpEndpoint      | u32               | EventLogDump          | "eventlog/dump"   |
+    // Stream endpoints
+    | StreamConfigEndpoint      | StreamConfig      | bool                  | "stream/config"   |
+    | StreamGetConfigEndpoint   | StreamKind        | StreamConfig          | "stream/get_config" |
+    | StreamGetCodecEndpoint    | ()                | AdsCodec              | "stream/get_codec" |
+    | StreamSetCodecEndpoint    | AdsCodec          | ()                    | "stream/set_codec" |
+    // File transfer endpoints
+    | FsReadBeginEndpoint       | FsReadBegin       | FsResult              | "fs/read/begin"   |
+    | FsReadChunkEndpoint       | FsReadChunk       | MaybeFsChunk          | "fs/read/chunk"   |
+    | FsReadFinishEndpoint      | ()                | FsResult              | "fs/read/finish"  |
+    | FsDeleteEndpoint          | FsDelete          | FsResult              | "fs/delete"       |
+    | StorageStatusEndpoint     | ()                | MaybeStorageStatus    | "storage/status"  |
+    | StorageClearEndpoint      | ()                | FsResult              | "storage/clear"   |
 }
 
 topics! {
@@ -215,5 +1641,14 @@ topics! {
     | TopicTy                   | MessageTy     | Path              | Cfg                           |
     | -------                   | ---------     | ----              | ---                           |
     | AdsTopic                  | AdsDataFrame  | "ads/data"        |                               |
+    | LeadOffTopic              | LeadOffStatus | "ads/loff"        |                               |
     | MicTopic                  | MicDataFrame  | "mic/data"        |                               |
+    | QuaternionTopic           | ImuQuaternion | "imu/quat"        |                               |
+    | ApdsTopic                 | ApdsDataFrame | "apds/data"       |                               |
+    | LogTopic                  | LogRecord     | "log/data"        |                               |
+    | EventMarkerTopic          | MarkerRecord  | "marker/data"     |                               |
+    | StorageStatusTopic        | StorageStatus | "storage/warning" |                               |
+    | FaultTopic                | Fault         | "device/fault"    |                               |
+    | BatteryTopic              | BatteryStatus | "battery/update"  |                               |
+    | SelfTestTopic             | SelfTestReport | "device/selftest/report" |                        |
 }
@@ -16,11 +16,69 @@ pub enum MicSampleRate {
 pub struct MicConfig {
     pub gain_db: i8,
     pub sample_rate: MicSampleRate,
+    /// Record audio next to the ADS data while a session is active.
+    pub record_to_sd: bool,
+    /// Store recorded audio as IMA-ADPCM (4:1) instead of raw PCM.
+    pub record_adpcm: bool,
+    pub vad: VadConfig,
+    /// Codec of the frames streamed to the host. Streams that cannot carry
+    /// PCM, such as BLE, fall back to ADPCM.
+    pub stream_codec: MicCodec,
+}
+
+/// Encoding of the audio in a [`MicDataFrame`].
+#[derive(
+    Serialize, Deserialize, Schema, Debug, PartialEq, Clone, Copy, Default,
+)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MicCodec {
+    /// IMA-ADPCM, 4 bits per sample, in `adpcm_data`.
+    #[default]
+    Adpcm,
+    /// Little-endian 16-bit PCM in `pcm_data`.
+    Pcm,
+}
+
+/// Voice activity detection. While enabled the mic only wakes for short
+/// bursts until it hears speech, and audio is streamed and recorded only
+/// while speech lasts.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct VadConfig {
+    pub enabled: bool,
+    /// Mean absolute amplitude a buffer needs to count as speech.
+    pub energy_threshold: u16,
+    /// Zero crossings per buffer above which a buffer is taken as noise
+    /// rather than speech.
+    pub max_zero_crossings: u16,
+    /// Silence that ends a speech segment.
+    pub hangover_ms: u16,
+    /// Time between listening bursts while no speech is heard.
+    pub burst_interval_ms: u16,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            energy_threshold: 300,
+            max_zero_crossings: 80,
+            hangover_ms: 800,
+            burst_interval_ms: 200,
+        }
+    }
 }
 
 impl Default for MicConfig {
     fn default() -> Self {
-        Self { gain_db: 0, sample_rate: MicSampleRate::Rate16000 }
+        Self {
+            gain_db: 0,
+            sample_rate: MicSampleRate::Rate16000,
+            record_to_sd: false,
+            record_adpcm: true,
+            vad: VadConfig::default(),
+            stream_codec: MicCodec::default(),
+        }
     }
 }
 
@@ -52,10 +110,16 @@ pub fn default_mic_settings() -> MicConfig {
 #[derive(Debug, Serialize, Deserialize, Schema, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct MicDataFrame {
+    /// Device timestamp, see [`crate::TimeStatus`].
     pub ts: u64,
     pub packet_counter: u64,
+    /// Incremented for every frame sent on the stream; a jump means frames
+    /// were dropped.
+    pub seq: u32,
     pub sample_rate: u32,
     pub predictor: i32,
     pub step_index: u32,
     pub adpcm_data: alloc::vec::Vec<u8>,
+    pub codec: MicCodec,
+    pub pcm_data: alloc::vec::Vec<u8>,
 }
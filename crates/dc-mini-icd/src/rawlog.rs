@@ -0,0 +1,156 @@
+//! Power-fail-safe session log written straight to SD card blocks.
+//!
+//! Instead of a FAT filesystem the card holds a [`Superblock`] at block 0
+//! followed by data blocks written strictly in order. Every data block
+//! carries its own header and CRC and no block is rewritten, so a power
+//! loss can at most lose the block being written. The log ends at the
+//! first block that does not verify or belongs to an older
+//! [`Superblock::generation`].
+//!
+//! Each session is a [container](crate::container) byte stream split over
+//! consecutive blocks that share a session number. Data block layout:
+//!
+//! | Offset | Size | Field                                         |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 4    | [`BLOCK_MAGIC`]                               |
+//! | 4      | 4    | generation, little-endian `u32`               |
+//! | 8      | 4    | session number, little-endian `u32`           |
+//! | 12     | 2    | payload length, little-endian `u16`           |
+//! | 14     | 2    | reserved, zero                                |
+//! | 16     | 4    | CRC-32 of bytes 0..16 and the payload         |
+//! | 20     | 492  | payload                                       |
+
+/// Magic at the start of the superblock.
+pub const LOG_MAGIC: [u8; 4] = *b"DCLG";
+/// Magic at the start of every data block.
+pub const BLOCK_MAGIC: [u8; 4] = *b"DCLB";
+/// Version of the layout described here.
+pub const LOG_VERSION: u16 = 1;
+
+pub const BLOCK_SIZE: usize = 512;
+pub const BLOCK_HEADER_SIZE: usize = 20;
+/// Container bytes carried by one data block.
+pub const BLOCK_PAYLOAD: usize = BLOCK_SIZE - BLOCK_HEADER_SIZE;
+/// Index of the first data block; block 0 is the superblock.
+pub const FIRST_DATA_BLOCK: u32 = 1;
+
+/// CRC-32 (IEEE 802.3) lookup table.
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc =
+                if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &b in data {
+        crc = CRC_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// CRC-32 (IEEE 802.3) of `parts` concatenated.
+pub fn crc32(parts: &[&[u8]]) -> u32 {
+    !parts.iter().fold(!0, |crc, part| crc32_update(crc, part))
+}
+
+/// Log header in block 0. Formatting the log writes a new generation, which
+/// invalidates every data block written before it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Superblock {
+    pub version: u16,
+    pub generation: u32,
+}
+
+impl Superblock {
+    pub const SIZE: usize = 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        buf[..4].copy_from_slice(&LOG_MAGIC);
+        buf[4..6].copy_from_slice(&self.version.to_le_bytes());
+        // Bytes 6..8 are reserved and written as zero.
+        buf[8..12].copy_from_slice(&self.generation.to_le_bytes());
+        let crc = crc32(&[&buf[..12]]);
+        buf[12..16].copy_from_slice(&crc.to_le_bytes());
+        buf
+    }
+
+    /// Returns `None` if `buf` is not a valid superblock.
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        let buf = buf.get(..Self::SIZE)?;
+        let crc = u32::from_le_bytes([buf[12], buf[13], buf[14], buf[15]]);
+        if buf[..4] != LOG_MAGIC || crc != crc32(&[&buf[..12]]) {
+            return None;
+        }
+        Some(Self {
+            version: u16::from_le_bytes([buf[4], buf[5]]),
+            generation: u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BlockHeader {
+    pub generation: u32,
+    pub session: u32,
+    pub len: u16,
+}
+
+impl BlockHeader {
+    /// Writes the header and CRC into `block`, whose payload must already
+    /// hold `len` bytes at [`BLOCK_HEADER_SIZE`].
+    pub fn seal(&self, block: &mut [u8; BLOCK_SIZE]) {
+        block[..4].copy_from_slice(&BLOCK_MAGIC);
+        block[4..8].copy_from_slice(&self.generation.to_le_bytes());
+        block[8..12].copy_from_slice(&self.session.to_le_bytes());
+        block[12..14].copy_from_slice(&self.len.to_le_bytes());
+        block[14..16].fill(0);
+        let payload = &block[BLOCK_HEADER_SIZE..][..self.len as usize];
+        let crc = crc32(&[&block[..16], payload]);
+        block[16..20].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    /// Returns the header if `block` is an intact data block of
+    /// `generation`.
+    pub fn verify(block: &[u8; BLOCK_SIZE], generation: u32) -> Option<Self> {
+        if block[..4] != BLOCK_MAGIC {
+            return None;
+        }
+        let header = Self {
+            generation: u32::from_le_bytes([
+                block[4], block[5], block[6], block[7],
+            ]),
+            session: u32::from_le_bytes([
+                block[8], block[9], block[10], block[11],
+            ]),
+            len: u16::from_le_bytes([block[12], block[13]]),
+        };
+        if header.generation != generation
+            || header.len as usize > BLOCK_PAYLOAD
+        {
+            return None;
+        }
+        let crc =
+            u32::from_le_bytes([block[16], block[17], block[18], block[19]]);
+        let payload = &block[BLOCK_HEADER_SIZE..][..header.len as usize];
+        (crc == crc32(&[&block[..16], payload])).then_some(header)
+    }
+
+    /// The payload of a verified block.
+    pub fn payload<'a>(&self, block: &'a [u8; BLOCK_SIZE]) -> &'a [u8] {
+        &block[BLOCK_HEADER_SIZE..][..self.len as usize]
+    }
+}
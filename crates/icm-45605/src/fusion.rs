@@ -0,0 +1,93 @@
+//! Orientation estimation from accelerometer and gyroscope readings.
+
+use crate::CalibSensorData;
+use micromath::F32Ext;
+
+const DEG_TO_RAD: f32 = core::f32::consts::PI / 180.0;
+
+/// Madgwick gradient-descent orientation filter (IMU variant, no
+/// magnetometer), so heading drifts slowly with gyro bias.
+#[derive(Debug, Clone)]
+pub struct Madgwick {
+    /// Orientation as `[w, x, y, z]`.
+    q: [f32; 4],
+    /// Gain of the accelerometer correction step.
+    beta: f32,
+}
+
+impl Default for Madgwick {
+    fn default() -> Self {
+        Self::new(0.1)
+    }
+}
+
+impl Madgwick {
+    pub const fn new(beta: f32) -> Self {
+        Self { q: [1.0, 0.0, 0.0, 0.0], beta }
+    }
+
+    /// Current orientation as a unit quaternion `[w, x, y, z]`.
+    pub fn quaternion(&self) -> [f32; 4] {
+        self.q
+    }
+
+    /// Resets the orientation to identity.
+    pub fn reset(&mut self) {
+        self.q = [1.0, 0.0, 0.0, 0.0];
+    }
+
+    /// Integrates one sample taken `dt` seconds after the previous one.
+    pub fn update(&mut self, data: &CalibSensorData, dt: f32) {
+        let [q0, q1, q2, q3] = self.q;
+        let gx = data.gyro_x * DEG_TO_RAD;
+        let gy = data.gyro_y * DEG_TO_RAD;
+        let gz = data.gyro_z * DEG_TO_RAD;
+
+        // Rate of change of the quaternion from the gyroscope.
+        let mut q_dot = [
+            0.5 * (-q1 * gx - q2 * gy - q3 * gz),
+            0.5 * (q0 * gx + q2 * gz - q3 * gy),
+            0.5 * (q0 * gy - q1 * gz + q3 * gx),
+            0.5 * (q0 * gz + q1 * gy - q2 * gx),
+        ];
+
+        let (ax, ay, az) = (data.accel_x, data.accel_y, data.accel_z);
+        let norm_sq = ax * ax + ay * ay + az * az;
+        // Skip the correction in free fall, where gravity is not observable.
+        if norm_sq > 0.0 {
+            let inv_norm = norm_sq.invsqrt();
+            let (ax, ay, az) = (ax * inv_norm, ay * inv_norm, az * inv_norm);
+
+            // Gradient of the error between measured and expected gravity.
+            let f1 = 2.0 * (q1 * q3 - q0 * q2) - ax;
+            let f2 = 2.0 * (q0 * q1 + q2 * q3) - ay;
+            let f3 = 1.0 - 2.0 * (q1 * q1 + q2 * q2) - az;
+            let step = [
+                -2.0 * q2 * f1 + 2.0 * q1 * f2,
+                2.0 * q3 * f1 + 2.0 * q0 * f2 - 4.0 * q1 * f3,
+                -2.0 * q0 * f1 + 2.0 * q3 * f2 - 4.0 * q2 * f3,
+                2.0 * q1 * f1 + 2.0 * q2 * f2,
+            ];
+            let step_norm_sq = step.iter().map(|s| s * s).sum::<f32>();
+            if step_norm_sq > 0.0 {
+                let inv_norm = step_norm_sq.invsqrt();
+                for (d, s) in q_dot.iter_mut().zip(step.iter()) {
+                    *d -= self.beta * s * inv_norm;
+                }
+            }
+        }
+
+        for (q, d) in self.q.iter_mut().zip(q_dot.iter()) {
+            *q += d * dt;
+        }
+        let q_norm_sq = self.q.iter().map(|q| q * q).sum::<f32>();
+        if q_norm_sq > 0.0 {
+            let inv_norm = q_norm_sq.invsqrt();
+            for q in self.q.iter_mut() {
+                *q *= inv_norm;
+            }
+        } else {
+            self.reset();
+        }
+    }
+}
@@ -8,7 +8,8 @@ pub use ll::{
     GyroOdr, Int1Drive, Int1Mode, Int1Polarity,
 };
 
-// VQF for quaternions
+pub mod fusion;
+pub use fusion::Madgwick;
 
 use embedded_hal_async::{delay, i2c};
 use heapless::Vec;
@@ -79,12 +80,27 @@ impl GyrUnit {
     }
 }
 
+/// Resolution of the FIFO timestamps, as set by [`Icm45605::configure_fifo`].
+pub const FIFO_TMST_RESOLUTION_US: u32 = 16;
+
+/// Interval and count of the completion polls in [`Icm45605::self_test`],
+/// about twice the time the test takes.
+const SELF_TEST_POLL_MS: u32 = 20;
+const SELF_TEST_POLLS: u32 = 100;
+
+/// `*_UI_LPFBW_SEL` value: 1 selects ODR/4, 0 bypasses the filter.
+fn lpf_bandwidth(enable: bool) -> u8 {
+    u8::from(enable)
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct FifoConfig {
     pub accel_en: bool,
     pub gyro_en: bool,
     pub temp_en: bool,
     pub hires_en: bool,
+    /// Store a timestamp with every frame, see [`FifoSample::timestamp`].
+    pub tmst_en: bool,
     pub watermark: u16,
     pub mode: FifoMode,
 }
@@ -96,12 +112,23 @@ impl Default for FifoConfig {
             gyro_en: true,
             temp_en: false,
             hires_en: false,
+            tmst_en: true,
             watermark: 32,
             mode: FifoMode::Stream,
         }
     }
 }
 
+/// A FIFO frame and the sensor time it was sampled at.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FifoSample<T> {
+    pub data: T,
+    /// Free-running sensor time in units of [`FIFO_TMST_RESOLUTION_US`];
+    /// 0 unless [`FifoConfig::tmst_en`] is set.
+    pub timestamp: u16,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum ApexFeature {
     Pedometer,
@@ -132,6 +159,21 @@ pub struct TapData {
     pub direction: u8,
 }
 
+/// Per-axis outcome of the on-chip self-test, X, Y, Z.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SelfTestAxes {
+    pub accel: [bool; 3],
+    pub gyro: [bool; 3],
+}
+
+impl SelfTestAxes {
+    /// Whether every axis of both sensors passed.
+    pub fn passed(&self) -> bool {
+        self.accel.iter().chain(&self.gyro).all(|&pass| pass)
+    }
+}
+
 #[derive(derive_more::From, Debug, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error<I2cError> {
@@ -142,6 +184,8 @@ pub enum Error<I2cError> {
     FifoError,
     ApexError,
     FailedToPushData,
+    /// The on-chip self-test did not report completion.
+    SelfTestTimeout,
 }
 
 bitflags! {
@@ -444,6 +488,58 @@ impl<
             .await?)
     }
 
+    /// Set the accelerometer power mode, e.g. after [`Self::start_accel`]
+    pub async fn set_accel_mode(
+        &mut self,
+        mode: AccelMode,
+    ) -> Result<(), Error<I2c::Error>> {
+        Ok(self
+            .device
+            .pwr_mgmt_0()
+            .modify_async(|w| w.set_accel_mode(mode))
+            .await?)
+    }
+
+    /// Set the gyroscope power mode, e.g. after [`Self::start_gyro`]
+    pub async fn set_gyro_mode(
+        &mut self,
+        mode: GyroMode,
+    ) -> Result<(), Error<I2c::Error>> {
+        Ok(self
+            .device
+            .pwr_mgmt_0()
+            .modify_async(|w| w.set_gyro_mode(mode))
+            .await?)
+    }
+
+    /// Enable or bypass the accelerometer UI low-pass filter. When enabled
+    /// the bandwidth is ODR/4.
+    pub async fn set_accel_lpf(
+        &mut self,
+        enable: bool,
+    ) -> Result<(), Error<I2c::Error>> {
+        Ok(self
+            .device
+            .ipreg_sys_2()
+            .ipreg_sys_2_reg_131()
+            .modify_async(|w| w.set_accel_ui_lpfbw_sel(lpf_bandwidth(enable)))
+            .await?)
+    }
+
+    /// Enable or bypass the gyroscope UI low-pass filter. When enabled the
+    /// bandwidth is ODR/4.
+    pub async fn set_gyro_lpf(
+        &mut self,
+        enable: bool,
+    ) -> Result<(), Error<I2c::Error>> {
+        Ok(self
+            .device
+            .ipreg_sys_1()
+            .ipreg_sys_1_reg_172()
+            .modify_async(|w| w.set_gyro_ui_lpfbw_sel(lpf_bandwidth(enable)))
+            .await?)
+    }
+
     /// Read raw sensor data from registers
     pub async fn read_raw_data(
         &mut self,
@@ -502,13 +598,24 @@ impl<
             })
             .await?;
 
+        // Configure frame timestamps
+        self.device
+            .fifo_config_4()
+            .modify_async(|w| w.set_fifo_tmst_fsync_en(config.tmst_en))
+            .await?;
+        // 16 µs resolution, see FIFO_TMST_RESOLUTION_US
+        self.device
+            .tmst_wom_config()
+            .modify_async(|w| w.set_tmst_resol(true))
+            .await?;
+
         Ok(())
     }
 
     /// Read raw data from FIFO
     pub async fn read_fifo_data(
         &mut self,
-    ) -> Result<Vec<SensorData, 32>, Error<I2c::Error>> {
+    ) -> Result<Vec<FifoSample<SensorData>, 32>, Error<I2c::Error>> {
         let mut data = Vec::new();
 
         // Read FIFO count
@@ -645,10 +752,15 @@ impl<
             }
 
             // Read timestamp/FSYNC if present
+            let mut timestamp = 0;
             if header.tmst_field_en() || header.fsync_tag_en() || frame_32bytes
             {
-                for _ in 0..2 {
-                    let _ = self.device.fifo_data().read_async().await?.data();
+                let mut field = [0u8; 2];
+                for byte in field.iter_mut() {
+                    *byte = self.device.fifo_data().read_async().await?.data();
+                }
+                if header.tmst_field_en() {
+                    timestamp = u16::from_be_bytes(field);
                 }
                 frame_idx += 2;
             }
@@ -694,7 +806,7 @@ impl<
             let valid_temp = sensor_data.temp as i8 != INVALID_VALUE_FIFO_1B;
 
             if valid_accel && valid_gyro && valid_temp {
-                data.push(sensor_data)
+                data.push(FifoSample { data: sensor_data, timestamp })
                     .map_err(|_| Error::<I2c::Error>::FailedToPushData)?;
             }
         }
@@ -705,11 +817,11 @@ impl<
     /// Read calibrated data from FIFO
     pub async fn read_fifo_data_calibrated(
         &mut self,
-    ) -> Result<Vec<CalibSensorData, 32>, Error<I2c::Error>> {
+    ) -> Result<Vec<FifoSample<CalibSensorData>, 32>, Error<I2c::Error>> {
         let raw_data = self.read_fifo_data().await?;
         let mut calib_data = Vec::new();
 
-        for raw in raw_data {
+        for FifoSample { data: raw, timestamp } in raw_data {
             let calib = CalibSensorData {
                 accel_x: f32::from(raw.accel_x) * self.acc_scalar(),
                 accel_y: f32::from(raw.accel_y) * self.acc_scalar(),
@@ -720,7 +832,7 @@ impl<
                 temp: self.scaled_tmp_from_bytes(raw.temp.to_be_bytes()), // Temperature not included in FIFO
             };
             calib_data
-                .push(calib)
+                .push(FifoSample { data: calib, timestamp })
                 .map_err(|_| Error::<I2c::Error>::FailedToPushData)?;
         }
 
@@ -742,10 +854,14 @@ impl<
             })
             .await?;
 
-        // Enable/disable FIFO watermark interrupt
+        // Enable/disable FIFO watermark interrupt. Data ready would pulse
+        // INT1 for every frame, so it is only left on without the FIFO.
         self.device
             .int_1_config_0()
-            .modify_async(|w| w.set_int_1_status_en_fifo_ths(enable))
+            .modify_async(|w| {
+                w.set_int_1_status_en_fifo_ths(enable);
+                w.set_int_1_status_en_drdy(!enable);
+            })
             .await?;
 
         Ok(())
@@ -867,6 +983,16 @@ impl<
             })
             .await?;
 
+        // Compare each sample to the previous one so a device at rest does
+        // not keep triggering.
+        self.device
+            .tmst_wom_config()
+            .modify_async(|w| {
+                w.set_wom_mode(true);
+                w.set_wom_en(true);
+            })
+            .await?;
+
         Ok(())
     }
 
@@ -955,6 +1081,10 @@ impl<
                     .await
             }
             ApexFeature::WakeOnMotion => {
+                self.device
+                    .tmst_wom_config()
+                    .modify_async(|w| w.set_wom_en(false))
+                    .await?;
                 self.device
                     .int_1_config_1()
                     .modify_async(|w| {
@@ -1037,6 +1167,90 @@ impl<
         })
     }
 
+    /// Runs the on-chip self-test of the accelerometer and gyroscope.
+    ///
+    /// The EDMP excites each axis and compares its response against the
+    /// factory trim, which takes about a second. Both sensors are left
+    /// off afterwards.
+    pub async fn self_test(
+        &mut self,
+    ) -> Result<SelfTestAxes, Error<I2c::Error>> {
+        // The test drives the sensors itself and needs them off to start.
+        self.device
+            .pwr_mgmt_0()
+            .modify_async(|w| {
+                w.set_accel_mode(AccelMode::Off);
+                w.set_gyro_mode(GyroMode::Off);
+            })
+            .await?;
+
+        // Average over 320 ms and accept a response within 50% of the
+        // factory trim.
+        self.device
+            .imem_sram()
+            .imem_sram_reg_5657()
+            .modify_async(|w| {
+                w.set_st_accel_en(true);
+                w.set_st_gyro_en(true);
+                w.set_st_avg_time(5);
+                w.set_st_avvel_limit(7);
+                w.set_st_gyro_limit(7);
+            })
+            .await?;
+
+        // Clear a stale completion status before starting.
+        self.device.int_apex_status_1().read_async().await?;
+        self.device
+            .edmp_apex_en_1()
+            .modify_async(|w| w.set_edmp_enable(true))
+            .await?;
+        self.device
+            .reg_host_msg()
+            .modify_async(|w| w.set_edmp_on_demand_en(true))
+            .await?;
+
+        let mut done = false;
+        for _ in 0..SELF_TEST_POLLS {
+            self.device.interface.delay.delay_ms(SELF_TEST_POLL_MS).await;
+            let status = self.device.int_apex_status_1().read_async().await?;
+            if status.int_status_selftest_done() {
+                done = true;
+                break;
+            }
+        }
+
+        self.device
+            .reg_host_msg()
+            .modify_async(|w| w.set_edmp_on_demand_en(false))
+            .await?;
+        self.device
+            .imem_sram()
+            .imem_sram_reg_5657()
+            .modify_async(|w| {
+                w.set_st_accel_en(false);
+                w.set_st_gyro_en(false);
+            })
+            .await?;
+        if !done {
+            return Err(Error::SelfTestTimeout);
+        }
+
+        let result =
+            self.device.imem_sram().imem_sram_reg_68().read_async().await?;
+        Ok(SelfTestAxes {
+            accel: [
+                result.ax_st_pass(),
+                result.ay_st_pass(),
+                result.az_st_pass(),
+            ],
+            gyro: [
+                result.gx_st_pass(),
+                result.gy_st_pass(),
+                result.gz_st_pass(),
+            ],
+        })
+    }
+
     /// Set accelerometer calibration offsets
     pub async fn set_acc_offsets(
         &mut self,